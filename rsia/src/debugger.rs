@@ -0,0 +1,83 @@
+use rusteria::{DebugAction, DebugFrame, DebugHook, NodeOp};
+use std::io::{self, BufRead, Write};
+
+/// Interactive terminal debugger for rusteria scripts.
+#[derive(Default)]
+pub struct ConsoleDebugger {
+    /// The function names and pcs to break at, pc 0 breaks when the function is entered.
+    breakpoints: Vec<(String, usize)>,
+}
+
+impl ConsoleDebugger {
+    /// Break when entering the given functions.
+    pub fn new(functions: Vec<String>) -> Self {
+        Self {
+            breakpoints: functions.into_iter().map(|name| (name, 0)).collect(),
+        }
+    }
+
+    fn print_help() {
+        println!("  s, step        execute the next op");
+        println!("  c, continue    run until the next breakpoint");
+        println!("  p [name]       print a variable or all variables");
+        println!("  stack          print the execution stack");
+        println!("  b <fn> [pc]    break when entering function <fn> or at its op <pc>");
+        println!("  q, quit        abort the execution");
+    }
+}
+
+impl DebugHook for ConsoleDebugger {
+    fn is_breakpoint(&self, frame: &DebugFrame, _op: &NodeOp) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|(name, pc)| *pc == frame.pc && name == frame.function_name())
+    }
+
+    fn on_break(&mut self, frame: &DebugFrame, op: &NodeOp) -> DebugAction {
+        println!(
+            "[{}:{} depth {}] {:?}",
+            frame.function_name(),
+            frame.pc,
+            frame.depth,
+            op
+        );
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            _ = io::stdout().flush();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                return DebugAction::Continue;
+            }
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (None, _) | (Some("s"), _) | (Some("step"), _) => return DebugAction::Step,
+                (Some("c"), _) | (Some("continue"), _) => return DebugAction::Continue,
+                (Some("q"), _) | (Some("quit"), _) => return DebugAction::Abort,
+                (Some("p"), Some(name)) => match frame.variable(name) {
+                    Some(v) => println!("{} = {}", name, v),
+                    None => println!("Unknown variable '{}'", name),
+                },
+                (Some("p"), None) => {
+                    for (name, v) in frame.variables() {
+                        println!("{} = {}", name, v);
+                    }
+                    println!("uv = {}, color = {}", frame.uv, frame.color);
+                }
+                (Some("stack"), _) => {
+                    for (i, v) in frame.stack.iter().enumerate().rev() {
+                        println!("{}: {}", i, v);
+                    }
+                }
+                (Some("b"), Some(name)) => {
+                    let pc = parts.next().and_then(|pc| pc.parse().ok()).unwrap_or(0);
+                    self.breakpoints.push((name.to_string(), pc));
+                    println!("Breakpoint set at '{}' pc {}", name, pc);
+                }
+                _ => Self::print_help(),
+            }
+        }
+    }
+}
@@ -1,7 +1,13 @@
+mod debugger;
+
 use clap::{Command, arg};
-use rusteria::{RenderBuffer, Rusteria};
+use debugger::ConsoleDebugger;
+use rusteria::{CancelToken, RenderBuffer, Repl, Rusteria};
+use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 fn cli() -> Command {
     Command::new("shpz")
@@ -13,6 +19,140 @@ fn cli() -> Command {
         .arg(
             arg!(-r --resolution <RES> "Output resolution (WIDTHxHEIGHT)").default_value("800x800"),
         )
+        .arg(arg!(-d --debug "Step through the main program in the interactive debugger"))
+        .arg(arg!(-c --compile "Write the compiled program as '.rstb' bytecode next to the input"))
+        .arg(arg!(-p --profile "Print per function and per node timings after execution"))
+        .arg(arg!(-i --interactive "Start an interactive prompt, preloading FILE if it exists"))
+        .arg(arg!(-w --watch "Re-render whenever FILE or its imports change"))
+}
+
+/// Read-eval-print loop: every line is compiled against the current context and executed.
+fn repl(path: PathBuf) {
+    let palette = Rusteria::default().create_default_palette();
+    let mut repl = Repl::new(path.clone());
+
+    if let Ok(source) = std::fs::read_to_string(&path) {
+        match repl.eval(&source, &palette) {
+            Ok(_) => println!("Loaded '{}'.", path.display()),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    println!("Type an expression or statement, ':vars' to list globals, ':quit' to exit.");
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        _ = io::stdout().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let line = line.trim();
+
+        match line {
+            "" => continue,
+            ":q" | ":quit" => break,
+            ":vars" => {
+                for (name, value) in repl.variables() {
+                    println!("{} = {}", name, value);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        // Allow omitting the trailing semicolon for single statements
+        let source = if line.ends_with(';') || line.ends_with('}') {
+            line.to_string()
+        } else {
+            format!("{};", line)
+        };
+
+        match repl.eval(&source, &palette) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+}
+
+/// Modification times of the given files, used to detect changes in watch mode.
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Render the shade function whenever the source changes. A render in progress is
+/// cancelled as soon as a change is detected and restarted with the new source.
+fn watch(path: PathBuf, width: usize, height: usize) {
+    let poll = Duration::from_millis(200);
+
+    loop {
+        let mut ds = Rusteria::default();
+        let compiled = match ds.parse(path.clone()) {
+            Ok(module) => ds.compile(&module).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        let mut paths = vec![path.clone()];
+        paths.extend(ds.imported_paths());
+        let stamps = modified(&paths);
+
+        match (compiled, ds.context.program.shade_index) {
+            (Err(e), _) => eprintln!("{e}"),
+            (Ok(()), None) => eprintln!("No shade function in '{}'.", path.display()),
+            (Ok(()), Some(shade_index)) => {
+                let palette = ds.create_default_palette();
+                let cancel = CancelToken::new();
+
+                let watcher = {
+                    let cancel = cancel.clone();
+                    let paths = paths.clone();
+                    let stamps = stamps.clone();
+                    thread::spawn(move || {
+                        while !cancel.is_cancelled() {
+                            if modified(&paths) != stamps {
+                                cancel.cancel();
+                            }
+                            thread::sleep(poll);
+                        }
+                    })
+                };
+
+                let buffer = Arc::new(Mutex::new(RenderBuffer::new(width, height)));
+                let t0 = ds.get_time();
+                let completed =
+                    ds.shade_tiles(&buffer, shade_index, &palette, &cancel, &|done, total| {
+                        print!("\rRendering {}%", done * 100 / total);
+                        _ = io::stdout().flush();
+                    });
+                let t1 = ds.get_time();
+
+                // Also stops the watcher thread
+                cancel.cancel();
+                _ = watcher.join();
+                println!();
+
+                if !completed {
+                    println!("Source changed, restarting.");
+                    continue;
+                }
+
+                let mut png_path = path.clone();
+                png_path.set_extension("png");
+                buffer.lock().unwrap().save(png_path);
+                println!("Rendered in {}ms, waiting for changes.", t1 - t0);
+            }
+        }
+
+        while modified(&paths) == stamps {
+            thread::sleep(poll);
+        }
+    }
 }
 
 fn main() {
@@ -28,26 +168,63 @@ fn main() {
 
     let path = PathBuf::from(matches.get_one::<String>("FILE").unwrap());
 
+    if matches.get_flag("interactive") {
+        repl(path);
+        return;
+    }
+
+    if matches.get_flag("watch") {
+        watch(path, width, height);
+        return;
+    }
+
     let mut ds = Rusteria::default();
 
-    let _module = match ds.parse(path.clone()) {
-        Ok(module) => match ds.compile(&module) {
-            Ok(()) => {
-                println!("Module '{}' compiled successfully.", module.name);
-            }
+    if path.extension().and_then(|e| e.to_str()) == Some("rstb") {
+        if let Err(e) = ds.load_compiled(path.clone()) {
+            eprintln!("Error loading bytecode: {e}");
+            return;
+        }
+    } else {
+        match ds.parse(path.clone()) {
+            Ok(module) => match ds.compile(&module) {
+                Ok(()) => {
+                    println!("Module '{}' compiled successfully.", module.name);
+                }
+                Err(e) => {
+                    eprintln!("Error compiling module: {e}");
+                    return;
+                }
+            },
             Err(e) => {
-                eprintln!("Error compiling module: {e}");
+                eprintln!("Error parsing module: {e}");
                 return;
             }
-        },
-        Err(e) => {
-            eprintln!("Error parsing module: {e}");
+        };
+
+        if matches.get_flag("compile") {
+            let mut rstb_path = path.clone();
+            rstb_path.set_extension("rstb");
+            match ds.save_compiled(&rstb_path) {
+                Ok(()) => println!("Bytecode written to {}.", rstb_path.display()),
+                Err(e) => eprintln!("Error writing bytecode: {e}"),
+            }
             return;
         }
-    };
+    }
 
     let palette = ds.create_default_palette();
 
+    if matches.get_flag("debug") {
+        let debugger = Arc::new(Mutex::new(ConsoleDebugger::new(vec![])));
+        if let Some(rc) = ds.execute_with_debugger(&palette, debugger, true) {
+            println!("Result: {}", rc.to_string());
+        }
+        return;
+    }
+
+    ds.set_profiling(matches.get_flag("profile"));
+
     if let Some(shade_index) = ds.context.program.shade_index {
         let mut buffer = Arc::new(Mutex::new(RenderBuffer::new(width, height)));
         let t0 = ds.get_time();
@@ -66,4 +243,8 @@ fn main() {
             println!("Result: {}", rc.to_string());
         }
     }
+
+    if let Some(profile) = ds.profile() {
+        print!("{}", profile.report(&ds.context.program));
+    }
 }
@@ -1,5 +1,5 @@
 use crate::objectd::FunctionD;
-use crate::{ASTValue, CompileVisitor, Context, Module, RuntimeError};
+use crate::{ASTValue, CompileVisitor, Context, MatchPattern, Module, RuntimeError};
 use std::path::PathBuf;
 use vek::Vec3;
 
@@ -55,6 +55,12 @@ macro_rules! expr_float {
 #[derive(Clone, Debug)]
 pub enum Stmt {
     If(Box<Expr>, Box<Stmt>, Option<Box<Stmt>>, Location),
+    Match(
+        Box<Expr>,
+        Vec<(MatchPattern, Box<Stmt>)>,
+        Option<Box<Stmt>>,
+        Location,
+    ),
     While(Box<Expr>, Box<Stmt>, Location),
     For(
         Vec<Box<Stmt>>,
@@ -69,6 +75,7 @@ pub enum Stmt {
     Block(Vec<Box<Stmt>>, Location),
     Expression(Box<Expr>, Location),
     VarDeclaration(String, ASTValue, Box<Expr>, Location),
+    TupleDeclaration(Vec<String>, Box<Expr>, Location),
     StructDeclaration(String, Vec<(String, ASTValue)>, Location),
     Return(Box<Expr>, Location),
     Break(Location),
@@ -85,6 +92,7 @@ pub enum Expr {
     Comparison(Box<Expr>, ComparisonOperator, Box<Expr>, Location),
     Binary(Box<Expr>, BinaryOperator, Box<Expr>, Location),
     Grouping(Box<Expr>, Location),
+    Tuple(Vec<Box<Expr>>, Location),
     Variable(String, Vec<u8>, Vec<String>, Location),
     VariableAssignment(
         String,
@@ -96,6 +104,8 @@ pub enum Expr {
     ),
     FunctionCall(Box<Expr>, Vec<u8>, Vec<String>, Vec<Box<Expr>>, Location),
     Ternary(Box<Expr>, Box<Expr>, Box<Expr>, Location),
+    Swizzle(Box<Expr>, Vec<u8>, Location),
+    Dict(Vec<(Box<Expr>, Box<Expr>)>, Location),
 }
 
 /// Assignment operators in the AST
@@ -251,6 +261,14 @@ pub trait Visitor {
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError>;
 
+    fn tuple_declaration(
+        &mut self,
+        names: &[String],
+        expression: &Expr,
+        loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError>;
+
     fn value(
         &mut self,
         value: ASTValue,
@@ -302,6 +320,13 @@ pub trait Visitor {
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError>;
 
+    fn tuple(
+        &mut self,
+        elements: &[Box<Expr>],
+        loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError>;
+
     fn variable(
         &mut self,
         name: String,
@@ -360,6 +385,15 @@ pub trait Visitor {
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError>;
 
+    fn match_stmt(
+        &mut self,
+        value: &Expr,
+        arms: &[(MatchPattern, Box<Stmt>)],
+        default: &Option<Box<Stmt>>,
+        loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError>;
+
     fn while_stmt(
         &mut self,
         cond: &Expr,
@@ -395,6 +429,21 @@ pub trait Visitor {
         loc: &Location,
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError>;
+
+    fn swizzle(
+        &mut self,
+        expr: &Expr,
+        swizzle: &[u8],
+        loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError>;
+
+    fn dict(
+        &mut self,
+        entries: &[(Box<Expr>, Box<Expr>)],
+        loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError>;
 }
 
 impl Stmt {
@@ -407,6 +456,9 @@ impl Stmt {
             Stmt::If(cond, then_stmt, else_stmt, loc) => {
                 visitor.if_stmt(cond, then_stmt, else_stmt, loc, ctx)
             }
+            Stmt::Match(value, arms, default, loc) => {
+                visitor.match_stmt(value, arms, default, loc, ctx)
+            }
             Stmt::While(cond, body, loc) => visitor.while_stmt(cond, body, loc, ctx),
             Stmt::For(init, cond, incr, body, loc) => {
                 visitor.for_stmt(init, cond, incr, body, loc, ctx)
@@ -421,6 +473,9 @@ impl Stmt {
             Stmt::VarDeclaration(name, static_type, initializer, loc) => {
                 visitor.var_declaration(name, static_type, initializer, loc, ctx)
             }
+            Stmt::TupleDeclaration(names, initializer, loc) => {
+                visitor.tuple_declaration(names, initializer, loc, ctx)
+            }
             Stmt::StructDeclaration(name, fields, loc) => {
                 visitor.struct_declaration(name, fields, loc, ctx)
             }
@@ -447,6 +502,7 @@ impl Expr {
             Expr::Comparison(left, op, right, loc) => visitor.comparison(left, op, right, loc, ctx),
             Expr::Binary(left, op, right, loc) => visitor.binary(left, op, right, loc, ctx),
             Expr::Grouping(expr, loc) => visitor.grouping(expr, loc, ctx),
+            Expr::Tuple(elements, loc) => visitor.tuple(elements, loc, ctx),
             Expr::Variable(name, swizzle, field_path, loc) => {
                 visitor.variable(name.clone(), swizzle, field_path, loc, ctx)
             }
@@ -459,6 +515,8 @@ impl Expr {
             Expr::Ternary(cond, then_expr, else_expr, loc) => {
                 visitor.ternary(cond, then_expr, else_expr, loc, ctx)
             }
+            Expr::Swizzle(expr, swizzle, loc) => visitor.swizzle(expr, swizzle, loc, ctx),
+            Expr::Dict(entries, loc) => visitor.dict(entries, loc, ctx),
         }
     }
 
@@ -1,8 +1,8 @@
 use crate::objectd::FunctionD;
 use crate::{
     ASTValue, AssignmentOperator, BinaryOperator, ComparisonOperator, Context, Environment,
-    EqualityOperator, Expr, Location, LogicalOperator, Module, NodeOp, PatternKind, RuntimeError,
-    Stmt, UnaryOperator, Value, Visitor, optimize,
+    EqualityOperator, Expr, Location, LogicalOperator, MatchPattern, Module, NodeOp, PatternKind,
+    RuntimeError, Stmt, UnaryOperator, Value, Visitor, optimize,
 };
 use indexmap::{IndexMap, IndexSet};
 use rustc_hash::FxHashMap;
@@ -25,6 +25,15 @@ pub struct CompileVisitor {
 
     /// List of local variables which are in scope (inside functions)
     locals: IndexSet<String>,
+
+    /// Number of values returned by user functions which return tuples.
+    tuple_sizes: FxHashMap<String, usize>,
+
+    /// Number of values returned by the function currently being compiled.
+    return_size: Option<usize>,
+
+    /// Set while compiling the initializer of a tuple declaration.
+    destructuring: bool,
 }
 
 impl Visitor for CompileVisitor {
@@ -370,12 +379,191 @@ impl Visitor for CompileVisitor {
                 op: NodeOp::Round,
             },
         );
+        functions.insert(
+            "exp".to_string(),
+            ASTFunction {
+                name: "exp".to_string(),
+                arguments: 1,
+                op: NodeOp::Exp,
+            },
+        );
+        functions.insert(
+            "sign".to_string(),
+            ASTFunction {
+                name: "sign".to_string(),
+                arguments: 1,
+                op: NodeOp::Sign,
+            },
+        );
+        functions.insert(
+            "hash".to_string(),
+            ASTFunction {
+                name: "hash".to_string(),
+                arguments: 1,
+                op: NodeOp::Hash,
+            },
+        );
+        functions.insert(
+            "hash3".to_string(),
+            ASTFunction {
+                name: "hash3".to_string(),
+                arguments: 1,
+                op: NodeOp::Hash3,
+            },
+        );
+        functions.insert(
+            "noise2".to_string(),
+            ASTFunction {
+                name: "noise2".to_string(),
+                arguments: 1,
+                op: NodeOp::Noise2,
+            },
+        );
+        functions.insert(
+            "noise3".to_string(),
+            ASTFunction {
+                name: "noise3".to_string(),
+                arguments: 1,
+                op: NodeOp::Noise3,
+            },
+        );
+        functions.insert(
+            "fbm".to_string(),
+            ASTFunction {
+                name: "fbm".to_string(),
+                arguments: 2,
+                op: NodeOp::Fbm,
+            },
+        );
+        functions.insert(
+            "voronoi".to_string(),
+            ASTFunction {
+                name: "voronoi".to_string(),
+                arguments: 1,
+                op: NodeOp::Voronoi,
+            },
+        );
+        functions.insert(
+            "rotate_x".to_string(),
+            ASTFunction {
+                name: "rotate_x".to_string(),
+                arguments: 2,
+                op: NodeOp::RotateX,
+            },
+        );
+        functions.insert(
+            "rotate_y".to_string(),
+            ASTFunction {
+                name: "rotate_y".to_string(),
+                arguments: 2,
+                op: NodeOp::RotateY,
+            },
+        );
+        functions.insert(
+            "rotate_z".to_string(),
+            ASTFunction {
+                name: "rotate_z".to_string(),
+                arguments: 2,
+                op: NodeOp::RotateZ,
+            },
+        );
+        functions.insert(
+            "sd_sphere".to_string(),
+            ASTFunction {
+                name: "sd_sphere".to_string(),
+                arguments: 2,
+                op: NodeOp::SdSphere,
+            },
+        );
+        functions.insert(
+            "sd_box".to_string(),
+            ASTFunction {
+                name: "sd_box".to_string(),
+                arguments: 2,
+                op: NodeOp::SdBox,
+            },
+        );
+        functions.insert(
+            "sd_torus".to_string(),
+            ASTFunction {
+                name: "sd_torus".to_string(),
+                arguments: 2,
+                op: NodeOp::SdTorus,
+            },
+        );
+        functions.insert(
+            "op_smooth_union".to_string(),
+            ASTFunction {
+                name: "op_smooth_union".to_string(),
+                arguments: 3,
+                op: NodeOp::SmoothUnion,
+            },
+        );
+        functions.insert(
+            "op_smooth_subtraction".to_string(),
+            ASTFunction {
+                name: "op_smooth_subtraction".to_string(),
+                arguments: 3,
+                op: NodeOp::SmoothSubtraction,
+            },
+        );
+        functions.insert(
+            "op_smooth_intersection".to_string(),
+            ASTFunction {
+                name: "op_smooth_intersection".to_string(),
+                arguments: 3,
+                op: NodeOp::SmoothIntersection,
+            },
+        );
+        functions.insert(
+            "raymarch".to_string(),
+            ASTFunction {
+                name: "raymarch".to_string(),
+                arguments: 3,
+                op: NodeOp::Raymarch,
+            },
+        );
+        functions.insert(
+            "sdf_normal".to_string(),
+            ASTFunction {
+                name: "sdf_normal".to_string(),
+                arguments: 2,
+                op: NodeOp::SdfNormal,
+            },
+        );
+        functions.insert(
+            "get".to_string(),
+            ASTFunction {
+                name: "get".to_string(),
+                arguments: 2,
+                op: NodeOp::DictGet,
+            },
+        );
+        functions.insert(
+            "set".to_string(),
+            ASTFunction {
+                name: "set".to_string(),
+                arguments: 3,
+                op: NodeOp::DictSet,
+            },
+        );
+        functions.insert(
+            "contains".to_string(),
+            ASTFunction {
+                name: "contains".to_string(),
+                arguments: 2,
+                op: NodeOp::DictContains,
+            },
+        );
 
         Self {
             environment: Environment::default(),
             functions,
             user_functions: IndexMap::default(),
             locals: IndexSet::default(),
+            tuple_sizes: FxHashMap::default(),
+            return_size: None,
+            destructuring: false,
         }
     }
 
@@ -445,14 +633,54 @@ impl Visitor for CompileVisitor {
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError> {
         _ = expression.accept(self, ctx)?;
+        self.store_variable(name, ctx);
 
-        if let Some(index) = self.locals.get_index_of(name) {
-            ctx.emit(NodeOp::StoreLocal(index));
-        } else if let Some(index) = ctx.globals.get(name) {
-            ctx.emit(NodeOp::StoreGlobal(*index as usize));
+        // self.environment.define(name.to_string(), v);
+
+        Ok(ASTValue::None)
+    }
+
+    fn tuple_declaration(
+        &mut self,
+        names: &[String],
+        expression: &Expr,
+        loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError> {
+        let count = match expression {
+            Expr::Tuple(elements, _) => {
+                for element in elements {
+                    _ = element.accept(self, ctx)?;
+                }
+                elements.len()
+            }
+            Expr::FunctionCall(callee, ..) => {
+                let count = match callee.as_ref() {
+                    Expr::Variable(name, ..) => self.tuple_sizes.get(name).copied().unwrap_or(1),
+                    _ => 1,
+                };
+                self.destructuring = true;
+                _ = expression.accept(self, ctx)?;
+                count
+            }
+            _ => 1,
+        };
+
+        if count != names.len() {
+            return Err(RuntimeError::new(
+                format!(
+                    "Cannot destructure {} values into {} variables",
+                    count,
+                    names.len()
+                ),
+                loc,
+            ));
         }
 
-        // self.environment.define(name.to_string(), v);
+        // The last value is on top of the stack
+        for name in names.iter().rev() {
+            self.store_variable(name, ctx);
+        }
 
         Ok(ASTValue::None)
     }
@@ -673,7 +901,7 @@ impl Visitor for CompileVisitor {
     fn value(
         &mut self,
         value: ASTValue,
-        _swizzle: &[u8],
+        swizzle: &[u8],
         _field_path: &[String],
         _loc: &Location,
         ctx: &mut Context,
@@ -704,6 +932,10 @@ impl Visitor for CompileVisitor {
             _ => {}
         };
 
+        if !swizzle.is_empty() {
+            ctx.emit(NodeOp::GetComponents(swizzle.to_vec()));
+        }
+
         Ok(ASTValue::None)
     }
 
@@ -805,6 +1037,18 @@ impl Visitor for CompileVisitor {
         expression.accept(self, ctx)
     }
 
+    fn tuple(
+        &mut self,
+        _elements: &[Box<Expr>],
+        loc: &Location,
+        _ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError> {
+        Err(RuntimeError::new(
+            "Tuples can only be returned from functions or destructured with 'let (..) = ..'",
+            loc,
+        ))
+    }
+
     fn func_call(
         &mut self,
         callee: &Expr,
@@ -814,6 +1058,8 @@ impl Visitor for CompileVisitor {
         loc: &Location,
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError> {
+        let destructuring = std::mem::take(&mut self.destructuring);
+
         let callee = callee.accept(self, ctx)?;
 
         if let ASTValue::Function(name, _func_args, _returns) = callee {
@@ -898,6 +1144,16 @@ impl Visitor for CompileVisitor {
                     ));
                 }
 
+                if self.tuple_sizes.contains_key(&name) && !destructuring {
+                    return Err(RuntimeError::new(
+                        format!(
+                            "'{}' returns a tuple, destructure it with 'let (..) = {}(..)'",
+                            name, name
+                        ),
+                        loc,
+                    ));
+                }
+
                 for arg in args {
                     _ = arg.accept(self, ctx)?;
                 }
@@ -954,6 +1210,7 @@ impl Visitor for CompileVisitor {
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError> {
         self.locals.clear();
+        self.return_size = None;
 
         // Compile locals (and their optional default values)
         let mut cp: IndexMap<String, Option<Vec<NodeOp>>> = IndexMap::default();
@@ -978,6 +1235,9 @@ impl Visitor for CompileVisitor {
             .insert(objectd.name.clone(), (objectd.arity, cp.clone(), index));
 
         objectd.block.accept(self, ctx)?;
+        if let Some(size) = self.return_size.filter(|size| *size > 1) {
+            self.tuple_sizes.insert(objectd.name.clone(), size);
+        }
         if let Some(mut codes) = ctx.take_last_custom_target() {
             optimize(&mut codes);
             ctx.program
@@ -986,6 +1246,9 @@ impl Visitor for CompileVisitor {
             ctx.program
                 .user_functions_name_map
                 .insert(objectd.name.clone(), index);
+            ctx.program
+                .user_functions_locals
+                .push(cp.keys().cloned().collect());
             if objectd.name == "shade" {
                 ctx.program.shade_index = Some(index);
                 ctx.program.shade_locals = cp.len();
@@ -1000,11 +1263,28 @@ impl Visitor for CompileVisitor {
     fn return_stmt(
         &mut self,
         expr: &Expr,
-        _loc: &Location,
+        loc: &Location,
         ctx: &mut Context,
     ) -> Result<ASTValue, RuntimeError> {
-        _ = expr.accept(self, ctx)?;
-        ctx.emit(NodeOp::Return);
+        let size = if let Expr::Tuple(elements, _) = expr {
+            for element in elements {
+                _ = element.accept(self, ctx)?;
+            }
+            ctx.emit(NodeOp::ReturnTuple(elements.len() as u8));
+            elements.len()
+        } else {
+            _ = expr.accept(self, ctx)?;
+            ctx.emit(NodeOp::Return);
+            1
+        };
+
+        if self.return_size.is_some_and(|previous| previous != size) {
+            return Err(RuntimeError::new(
+                "All return statements of a function must return the same number of values",
+                loc,
+            ));
+        }
+        self.return_size = Some(size);
 
         Ok(ASTValue::None)
     }
@@ -1040,6 +1320,34 @@ impl Visitor for CompileVisitor {
         Ok(ASTValue::None)
     }
 
+    fn match_stmt(
+        &mut self,
+        value: &Expr,
+        arms: &[(MatchPattern, Box<Stmt>)],
+        default: &Option<Box<Stmt>>,
+        _loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError> {
+        let mut arms_code = vec![];
+        for (pattern, stmt) in arms {
+            ctx.add_custom_target();
+            _ = stmt.accept(self, ctx)?;
+            arms_code.push((*pattern, ctx.take_last_custom_target().unwrap_or_default()));
+        }
+
+        let mut default_code = None;
+        if let Some(default) = default {
+            ctx.add_custom_target();
+            _ = default.accept(self, ctx)?;
+            default_code = ctx.take_last_custom_target();
+        }
+
+        _ = value.accept(self, ctx)?;
+        ctx.emit(NodeOp::Match(arms_code, default_code));
+
+        Ok(ASTValue::None)
+    }
+
     fn ternary(
         &mut self,
         _cond: &Expr,
@@ -1246,4 +1554,44 @@ impl Visitor for CompileVisitor {
 
         Ok(ASTValue::None)
     }
+
+    fn swizzle(
+        &mut self,
+        expr: &Expr,
+        swizzle: &[u8],
+        _loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError> {
+        _ = expr.accept(self, ctx)?;
+        ctx.emit(NodeOp::GetComponents(swizzle.to_vec()));
+
+        Ok(ASTValue::None)
+    }
+
+    fn dict(
+        &mut self,
+        entries: &[(Box<Expr>, Box<Expr>)],
+        _loc: &Location,
+        ctx: &mut Context,
+    ) -> Result<ASTValue, RuntimeError> {
+        ctx.emit(NodeOp::DictNew);
+        for (key, value) in entries {
+            _ = key.accept(self, ctx)?;
+            _ = value.accept(self, ctx)?;
+            ctx.emit(NodeOp::DictSet);
+        }
+
+        Ok(ASTValue::None)
+    }
+}
+
+impl CompileVisitor {
+    /// Store the value on top of the stack in the given local or global variable.
+    fn store_variable(&self, name: &str, ctx: &mut Context) {
+        if let Some(index) = self.locals.get_index_of(name) {
+            ctx.emit(NodeOp::StoreLocal(index));
+        } else if let Some(index) = ctx.globals.get(name) {
+            ctx.emit(NodeOp::StoreGlobal(*index as usize));
+        }
+    }
 }
@@ -36,6 +36,17 @@ impl Context {
         self.custom_targets.pop()
     }
 
+    /// Store the names of the global variables in the program, indexed like the globals array.
+    pub fn update_global_names(&mut self) {
+        let mut global_names = vec![String::new(); self.globals.len()];
+        for (name, index) in &self.globals {
+            if let Some(slot) = global_names.get_mut(*index as usize) {
+                *slot = name.clone();
+            }
+        }
+        self.program.global_names = global_names;
+    }
+
     pub fn emit(&mut self, op: NodeOp) {
         if let Some(custom) = self.custom_targets.last_mut() {
             custom.push(op.clone());
@@ -75,6 +75,26 @@ impl IdVerifier {
             "rotate2d",
             "palette",
             "round",
+            "hash",
+            "hash3",
+            "noise2",
+            "noise3",
+            "fbm",
+            "voronoi",
+            "rotate_x",
+            "rotate_y",
+            "rotate_z",
+            "sd_sphere",
+            "sd_box",
+            "sd_torus",
+            "op_smooth_union",
+            "op_smooth_subtraction",
+            "op_smooth_intersection",
+            "raymarch",
+            "sdf_normal",
+            "get",
+            "set",
+            "contains",
         ];
 
         for func in inbuilt_functions {
@@ -10,8 +10,11 @@ pub mod node;
 pub mod objectd;
 pub mod optimize;
 pub mod parser;
+pub mod procedural;
 pub mod renderbuffer;
+pub mod repl;
 pub mod scanner;
+pub mod sdf;
 pub mod textures;
 
 pub type Value = vek::Vec3<f32>;
@@ -34,11 +37,17 @@ pub use crate::{
     errors::{ParseError, RuntimeError},
     idverifier::IdVerifier,
     module::Module,
+    node::debughook::{DebugAction, DebugFrame, DebugHook},
     node::execution::Execution,
-    node::{nodeop::NodeOp, program::Program},
+    node::{
+        nodeop::{MatchPattern, NodeOp},
+        profiler::{ProfileEntry, Profiler},
+        program::Program,
+    },
     optimize::optimize,
     parser::Parser,
     renderbuffer::RenderBuffer,
+    repl::Repl,
     scanner::{Scanner, Token, TokenType},
     textures::{
         TexStorage,
@@ -49,6 +58,7 @@ pub use crate::{
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use theframework::theui::ThePalette;
 use vek::Vec3;
@@ -57,6 +67,9 @@ pub struct Rusteria {
     path: PathBuf,
     pub context: Context,
     defaults: Option<Module>,
+
+    /// Collected profile of execute() and shade() calls, if profiling is enabled.
+    profiler: Option<Arc<Mutex<Profiler>>>,
 }
 
 impl Default for Rusteria {
@@ -71,6 +84,7 @@ impl Rusteria {
             path: PathBuf::new(),
             context: Context::new(FxHashMap::default()),
             defaults: None,
+            profiler: None,
         }
     }
 
@@ -126,20 +140,97 @@ impl Rusteria {
 
         self.context.program.globals = self.context.globals.len();
         self.context.program.strings = module.strings.clone();
+        self.context.update_global_names();
+
+        Ok(())
+    }
+
+    /// Write the compiled program as bytecode to the given path.
+    pub fn save_compiled(&self, path: &PathBuf) -> std::io::Result<()> {
+        std::fs::write(path, self.context.program.pack())
+    }
 
+    /// Load a compiled program from bytecode, bypassing the parser and compiler.
+    pub fn load_compiled(&mut self, path: PathBuf) -> Result<(), ParseError> {
+        let data =
+            std::fs::read(&path).map_err(|_| ParseError::new("Could not read file", 0, &path))?;
+        if !self.load_compiled_bytes(&data) {
+            return Err(ParseError::new("Invalid or outdated bytecode", 0, &path));
+        }
+        self.path = path;
         Ok(())
     }
 
+    /// Install a program from bytecode created by `Program::pack`. Returns false if the data is invalid.
+    pub fn load_compiled_bytes(&mut self, data: &[u8]) -> bool {
+        let Some(program) = Program::unpack(data) else {
+            return false;
+        };
+
+        let mut globals = FxHashMap::default();
+        for (index, name) in program.global_names.iter().enumerate() {
+            globals.insert(name.clone(), index as u32);
+        }
+        self.context = Context::new(globals);
+        self.context.program = program;
+
+        true
+    }
+
+    /// Enable or disable profiling of execute() and shade(). Enabling resets the collected data.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = if enabled {
+            Some(Arc::new(Mutex::new(Profiler::default())))
+        } else {
+            None
+        };
+    }
+
+    /// The profile collected since profiling was enabled.
+    pub fn profile(&self) -> Option<Profiler> {
+        self.profiler.as_ref().map(|p| p.lock().unwrap().clone())
+    }
+
+    /// Merge the profile of the given execution into the collected profile.
+    fn collect_profile(&self, execution: &mut Execution) {
+        if let Some(profiler) = &self.profiler {
+            if let Some(profile) = execution.take_profile() {
+                profiler.lock().unwrap().merge(&profile);
+            }
+        }
+    }
+
     /// Compile the voxels into the VoxelGrid.
     pub fn execute(&mut self, palette: &ThePalette) -> Option<Value> {
         let mut execution = Execution::new(self.context.globals.len());
+        execution.set_profiling(self.profiler.is_some());
 
         // Execute the main program to compile all voxels.
         execution.execute(&&self.context.program.body, &self.context.program, palette);
+        self.collect_profile(&mut execution);
 
         execution.stack.pop()
     }
 
+    /// Execute the main program under the control of the given debug hook.
+    pub fn execute_with_debugger(
+        &mut self,
+        palette: &ThePalette,
+        hook: Arc<Mutex<dyn DebugHook>>,
+        step: bool,
+    ) -> Option<Value> {
+        let mut execution = Execution::new(self.context.globals.len());
+        execution.set_debug_hook(hook, step);
+
+        execution.execute(&self.context.program.body, &self.context.program, palette);
+
+        if execution.is_halted() {
+            None
+        } else {
+            execution.stack.pop()
+        }
+    }
+
     pub fn execute_string(&mut self, str: &str, palette: &ThePalette) -> Option<Value> {
         let result = self.parse_str(str);
         match result {
@@ -164,6 +255,26 @@ impl Rusteria {
         function_index: usize,
         palette: &ThePalette,
     ) {
+        self.shade_tiles(
+            buffer,
+            function_index,
+            palette,
+            &CancelToken::default(),
+            &|_, _| {},
+        );
+    }
+
+    /// Shade the buffer in parallel tiles. Every finished tile is written to the buffer right away and
+    /// reported to `on_progress` with the number of finished and total tiles, so callers can display
+    /// progressive results. Returns false if the token was cancelled before all tiles were shaded.
+    pub fn shade_tiles(
+        &self,
+        buffer: &Arc<Mutex<RenderBuffer>>,
+        function_index: usize,
+        palette: &ThePalette,
+        cancel: &CancelToken,
+        on_progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> bool {
         let tile_size = (80, 80);
 
         let width = buffer.lock().unwrap().width;
@@ -171,12 +282,23 @@ impl Rusteria {
 
         let tiles = self.create_tiles(width, height, tile_size.0, tile_size.1);
         let screen_size = vek::Vec2::new(width as f32, height as f32);
+        let finished = AtomicUsize::new(0);
 
         tiles.par_iter().for_each(|tile| {
+            if cancel.is_cancelled() {
+                return;
+            }
+
             let mut tile_buffer = RenderBuffer::new(tile.width, tile.height);
             let mut execution = Execution::new(self.context.program.globals);
+            execution.set_profiling(self.profiler.is_some());
 
             for h in 0..tile.height {
+                // Check once per row so cancelling does not wait for whole tiles
+                if cancel.is_cancelled() {
+                    return;
+                }
+
                 for w in 0..tile.width {
                     let x = tile.x + w;
                     let y = tile.y + h;
@@ -202,11 +324,18 @@ impl Rusteria {
                 }
             }
 
+            self.collect_profile(&mut execution);
+
             buffer
                 .lock()
                 .unwrap()
                 .accum_from(tile.x, tile.y, &tile_buffer);
+
+            let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+            on_progress(done, tiles.len());
         });
+
+        finished.load(Ordering::Relaxed) == tiles.len()
     }
 
     /// Imported paths
@@ -259,6 +388,25 @@ impl Rusteria {
     }
 }
 
+/// Shared flag to abort a running `Rusteria::shade_tiles` from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request all holders of this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Tile {
     pub x: usize,
@@ -294,4 +442,383 @@ mod tests {
         let result = script.execute_string(fib.into(), &ThePalette::default());
         assert_eq!(result.unwrap().x, 196418.0);
     }
+
+    #[test]
+    fn procedural() {
+        let mut script = Rusteria::default();
+        let palette = ThePalette::default();
+
+        let n = script
+            .execute_string("noise2(vec2(3.7, 1.2));", &palette)
+            .unwrap();
+        assert!(n.x >= 0.0 && n.x <= 1.0);
+
+        let h1 = script
+            .execute_string("hash(vec2(1.0, 2.0));", &palette)
+            .unwrap();
+        let h2 = script
+            .execute_string("hash(vec2(1.0, 2.0));", &palette)
+            .unwrap();
+        assert_eq!(h1.x, h2.x);
+
+        let v = script
+            .execute_string("voronoi(vec2(0.5, 0.5));", &palette)
+            .unwrap();
+        assert!(v.x <= v.y);
+
+        let r = script
+            .execute_string("rotate_z(vec3(1.0, 0.0, 0.0), 90.0);", &palette)
+            .unwrap();
+        assert!((r.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn swizzle() {
+        let mut script = Rusteria::default();
+        let palette = ThePalette::default();
+
+        let v = script
+            .execute_string(
+                "let a = vec3(1.0, 2.0, 3.0); let b = vec3(a.z, a.y, 1.0).zyx; b.xy = (a + a).yx; b.rgb.z + b.x;",
+                &palette,
+            )
+            .unwrap();
+        assert_eq!(v.x, 7.0);
+
+        assert!(
+            script
+                .parse_str("let a = vec3(1.0); a.xx = vec2(1.0);")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn dict() {
+        let mut script = Rusteria::default();
+        let v = script
+            .execute_string(
+                r#"let colors = { "grass": vec3(0.0, 1.0, 0.0), "water": vec3(0.0, 0.0, 1.0) };
+                set(colors, "sand", vec3(1.0, 1.0, 0.0));
+                get(colors, "water").z + get(colors, "sand").x + contains(colors, "lava");"#,
+                &ThePalette::default(),
+            )
+            .unwrap();
+        assert_eq!(v.x, 2.0);
+    }
+
+    #[test]
+    fn optimizer() {
+        let mut script = Rusteria::default();
+        let module = script
+            .parse_str(
+                "fn f(x) {
+                    let unused = sin(x);
+                    let c = (1.0 + 2.0) * 2.0;
+                    if 0 { c = 100.0; }
+                    return (x + c) * (x + c);
+                    return 5.0;
+                }
+                f(1.0);",
+            )
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+
+        let code = &script.context.program.user_functions[0];
+        assert!(code.contains(&NodeOp::Push(Value::broadcast(6.0))));
+        assert!(
+            !code
+                .iter()
+                .any(|op| matches!(op, NodeOp::Sin | NodeOp::If(..)))
+        );
+        assert!(code.contains(&NodeOp::Dup));
+        assert_eq!(code.iter().filter(|op| **op == NodeOp::Return).count(), 1);
+
+        let result = script.execute(&ThePalette::default());
+        assert_eq!(result.unwrap().x, 49.0);
+    }
+
+    #[test]
+    fn tuples() {
+        let mut script = Rusteria::default();
+        let v = script
+            .execute_string(
+                "fn material(p) { return (p * 2.0, p + 1.0); }
+                fn f(x) { let (c, r) = material(x); return c + r; }
+                let (a, b) = material(3.0);
+                let (u, v) = (1.0, 2.0);
+                a * 100.0 + b * 10.0 + f(1.0) + u + v;",
+                &ThePalette::default(),
+            )
+            .unwrap();
+        assert_eq!(v.x, 647.0);
+
+        let module = script
+            .parse_str("fn m() { return (1.0, 2.0); } m() + 1.0;")
+            .ok()
+            .unwrap();
+        assert!(script.compile(&module).is_err());
+    }
+
+    #[test]
+    fn shade_cancel() {
+        let mut script = Rusteria::default();
+        let module = script
+            .parse_str("fn shade() { color = vec3(1.0, 0.5, 0.0); }")
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+        let index = script.context.program.shade_index.unwrap();
+        let palette = ThePalette::default();
+
+        let buffer = Arc::new(Mutex::new(RenderBuffer::new(200, 100)));
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(!script.shade_tiles(&buffer, index, &palette, &cancel, &|_, _| {}));
+        assert_eq!(buffer.lock().unwrap().at(10, 10)[0], 0.0);
+
+        let progress = AtomicUsize::new(0);
+        let completed = script.shade_tiles(
+            &buffer,
+            index,
+            &palette,
+            &CancelToken::new(),
+            &|_, total| {
+                assert_eq!(total, 6);
+                progress.fetch_add(1, Ordering::Relaxed);
+            },
+        );
+        assert!(completed);
+        assert_eq!(progress.load(Ordering::Relaxed), 6);
+        assert_eq!(buffer.lock().unwrap().at(199, 99)[1], 0.5);
+    }
+
+    #[test]
+    fn sdf() {
+        let mut script = Rusteria::default();
+        let palette = ThePalette::default();
+
+        let d = script
+            .execute_string("sd_box(vec3(2.0, 0.0, 0.0), vec3(1.0));", &palette)
+            .unwrap();
+        assert!((d.x - 1.0).abs() < 1e-5);
+
+        let hit = script
+            .execute_string(
+                r#"fn scene(p) { return sd_sphere(p - vec3(0.0, 0.0, 5.0), 1.0); }
+                raymarch(vec3(0.0), vec3(0.0, 0.0, 1.0), "scene");"#,
+                &palette,
+            )
+            .unwrap();
+        assert_eq!(hit.z, 1.0);
+        assert!((hit.x - 4.0).abs() < 1e-2);
+
+        let n = script
+            .execute_string(
+                r#"fn scene(p) { return sd_sphere(p, 1.0); }
+                sdf_normal(vec3(0.0, 1.0, 0.0), "scene");"#,
+                &palette,
+            )
+            .unwrap();
+        assert!((n.y - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn match_stmt() {
+        let mut script = Rusteria::default();
+        let palette = ThePalette::default();
+
+        let v = script
+            .execute_string(
+                "fn biome(h) {
+                    let c = 0.0;
+                    match h {
+                        0 { c = 1.0; }
+                        0.0..0.5 { c = 2.0; }
+                        0.5..=1.0 { c = 3.0; }
+                        _ { c = 4.0; }
+                    }
+                    return c;
+                }
+                biome(0.0) + biome(0.25) * 10.0 + biome(1.0) * 100.0 + biome(-1.0) * 1000.0;",
+                &palette,
+            )
+            .unwrap();
+        assert_eq!(v.x, 4321.0);
+
+        let v = script
+            .execute_string(
+                r#"let k = "water"; let r = 0.0;
+                match k { "grass" { r = 1.0; } "water" { r = 2.0; } }
+                r;"#,
+                &palette,
+            )
+            .unwrap();
+        assert_eq!(v.x, 2.0);
+    }
+
+    #[test]
+    fn profiler() {
+        let mut script = Rusteria::default();
+        let module = script
+            .parse_str("fn inc(n) { return n + 1; } let a = 0; for (let i = 0; i < 10; i += 1) { a = inc(a); } a;")
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+        script.set_profiling(true);
+
+        let result = script.execute(&ThePalette::default());
+        assert_eq!(result.unwrap().x, 10.0);
+
+        let profile = script.profile().unwrap();
+        let functions = profile.functions_by_time(&script.context.program);
+        assert_eq!(functions[0].0, "inc");
+        assert_eq!(functions[0].1.calls, 10);
+        assert_eq!(profile.ops.get("FunctionCall").unwrap().calls, 10);
+
+        // The scene function of the raymarcher is profiled as a call of its own
+        let module = script
+            .parse_str(
+                r#"fn scene(p) { return sd_sphere(p - vec3(0.0, 0.0, 5.0), 1.0); }
+                raymarch(vec3(0.0), vec3(0.0, 0.0, 1.0), "scene");"#,
+            )
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+        script.set_profiling(true);
+        script.execute(&ThePalette::default());
+
+        let profile = script.profile().unwrap();
+        let functions = profile.functions_by_time(&script.context.program);
+        assert_eq!(functions[0].0, "scene");
+        assert!(functions[0].1.calls > 1);
+    }
+
+    #[test]
+    fn repl() {
+        let palette = ThePalette::default();
+        let mut repl = Repl::new(PathBuf::from("repl.rusteria"));
+        assert!(repl.eval("let a = 2;", &palette).unwrap().is_none());
+        assert!(repl.eval("fn sq(n) { return n * n; }", &palette).is_ok());
+        assert_eq!(repl.eval("sq(a) + 1;", &palette).unwrap().unwrap().x, 5.0);
+        assert!(repl.eval("b + 1;", &palette).is_err());
+    }
+
+    #[test]
+    fn bytecode_roundtrip() {
+        let mut script = Rusteria::default();
+        let module = script
+            .parse_str("let a = 3; fn triple(n) { return n * 3; } triple(a);")
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+        let data = script.context.program.pack();
+
+        let mut loaded = Rusteria::default();
+        assert!(loaded.load_compiled_bytes(&data));
+        let result = loaded.execute(&ThePalette::default());
+        assert_eq!(result.unwrap().x, 9.0);
+
+        assert!(Program::unpack(&data[4..]).is_none());
+    }
+
+    #[test]
+    fn debug_hook() {
+        struct Inspector {
+            calls: usize,
+            seen: Option<Value>,
+        }
+
+        impl DebugHook for Inspector {
+            fn is_breakpoint(&self, frame: &DebugFrame, _op: &NodeOp) -> bool {
+                frame.function_name() == "double" && frame.pc == 0
+            }
+
+            fn on_break(&mut self, frame: &DebugFrame, _op: &NodeOp) -> DebugAction {
+                self.seen = frame.variable("n");
+                DebugAction::Continue
+            }
+
+            fn on_enter_function(&mut self, _index: usize, _args: &[Value]) {
+                self.calls += 1;
+            }
+        }
+
+        let mut script = Rusteria::default();
+        let module = script
+            .parse_str("fn double(n) { return n * 2; } double(21);")
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+
+        let hook = Arc::new(Mutex::new(Inspector {
+            calls: 0,
+            seen: None,
+        }));
+        let result = script.execute_with_debugger(&ThePalette::default(), hook.clone(), false);
+        assert_eq!(result.unwrap().x, 42.0);
+        assert_eq!(hook.lock().unwrap().calls, 1);
+        assert_eq!(hook.lock().unwrap().seen.unwrap().x, 21.0);
+
+        // The scene function of sdf_normal is a frame of its own
+        let module = script
+            .parse_str(
+                r#"fn double(n) { return sd_sphere(n, 1.0); }
+                sdf_normal(vec3(0.0, 2.0, 0.0), "double");"#,
+            )
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+
+        let hook = Arc::new(Mutex::new(Inspector {
+            calls: 0,
+            seen: None,
+        }));
+        let result = script.execute_with_debugger(&ThePalette::default(), hook.clone(), false);
+        assert!((result.unwrap().y - 1.0).abs() < 1e-3);
+        assert!(hook.lock().unwrap().calls > 1);
+        assert!((hook.lock().unwrap().seen.unwrap().y - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn debug_pcs_of_nested_blocks() {
+        /// Stops when `clamp` is entered and steps through it.
+        struct Tracer {
+            pcs: Vec<usize>,
+        }
+
+        impl DebugHook for Tracer {
+            fn is_breakpoint(&self, frame: &DebugFrame, _op: &NodeOp) -> bool {
+                frame.function_name() == "clamp" && frame.pc == 0
+            }
+
+            fn on_break(&mut self, frame: &DebugFrame, _op: &NodeOp) -> DebugAction {
+                if frame.function_name() != "clamp" {
+                    return DebugAction::Continue;
+                }
+                self.pcs.push(frame.pc);
+                DebugAction::Step
+            }
+        }
+
+        let mut script = Rusteria::default();
+        let module = script
+            .parse_str(
+                "fn clamp(n) { if n > 1 { n = 1; } else { if n < 0 { n = 0; } } return n; } \
+                 clamp(-2);",
+            )
+            .ok()
+            .unwrap();
+        script.compile(&module).unwrap();
+
+        let hook = Arc::new(Mutex::new(Tracer { pcs: vec![] }));
+        let result = script.execute_with_debugger(&ThePalette::default(), hook.clone(), false);
+        assert_eq!(result.unwrap().x, 0.0);
+
+        // The ops of the nested blocks have their own pcs, only the entry is at pc 0
+        let pcs = hook.lock().unwrap().pcs.clone();
+        assert_eq!(pcs[0], 0);
+        assert!(pcs.len() > 4);
+        assert!(pcs.windows(2).all(|pair| pair[0] < pair[1]));
+    }
 }
@@ -0,0 +1,94 @@
+use crate::{NodeOp, Program, Value};
+
+/// What the execution should do after a debug hook was invoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Stop again before the next NodeOp.
+    Step,
+    /// Abort the execution, unwinding all function calls.
+    Abort,
+}
+
+/// A read-only view of the execution state handed to debug hooks.
+pub struct DebugFrame<'a> {
+    /// The index of the user function currently executing, None for the main body.
+    pub function: Option<usize>,
+    /// The position of the NodeOp in the code of the current function. The ops of nested blocks
+    /// (if, match, for) follow the op they belong to, so each op has its own pc.
+    pub pc: usize,
+    /// The function call depth.
+    pub depth: usize,
+    pub globals: &'a [Value],
+    pub locals: &'a [Value],
+    pub stack: &'a [Value],
+    pub uv: Value,
+    pub color: Value,
+    pub program: &'a Program,
+}
+
+impl DebugFrame<'_> {
+    /// The name of the current function, or "main".
+    pub fn function_name(&self) -> &str {
+        if let Some(index) = self.function {
+            for (name, i) in &self.program.user_functions_name_map {
+                if *i == index {
+                    return name;
+                }
+            }
+        }
+        "main"
+    }
+
+    /// Look up a local (inside functions) or global variable by name.
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        if let Some(index) = self.function {
+            if let Some(names) = self.program.user_functions_locals.get(index) {
+                if let Some(i) = names.iter().position(|n| n == name) {
+                    return self.locals.get(i).copied();
+                }
+            }
+        }
+        self.program
+            .global_names
+            .iter()
+            .position(|n| n == name)
+            .and_then(|i| self.globals.get(i).copied())
+    }
+
+    /// All variables visible in the current frame as (name, value) pairs.
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let mut vars = vec![];
+        if let Some(index) = self.function {
+            if let Some(names) = self.program.user_functions_locals.get(index) {
+                for (name, value) in names.iter().zip(self.locals.iter()) {
+                    vars.push((name.clone(), *value));
+                }
+            }
+        }
+        for (name, value) in self.program.global_names.iter().zip(self.globals.iter()) {
+            vars.push((name.clone(), *value));
+        }
+        vars
+    }
+}
+
+/// Debug hook invoked by the Execution. Install it with `Execution::set_debug_hook`.
+pub trait DebugHook: Send {
+    /// Returns true if the execution should stop before the given op.
+    fn is_breakpoint(&self, _frame: &DebugFrame, _op: &NodeOp) -> bool {
+        false
+    }
+
+    /// Called when the execution stops at a breakpoint or while single stepping.
+    fn on_break(&mut self, _frame: &DebugFrame, _op: &NodeOp) -> DebugAction {
+        DebugAction::Continue
+    }
+
+    /// Called when a user function is entered.
+    fn on_enter_function(&mut self, _index: usize, _args: &[Value]) {}
+
+    /// Called when a user function returns.
+    fn on_exit_function(&mut self, _index: usize, _ret: &Value) {}
+}
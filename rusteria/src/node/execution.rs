@@ -1,6 +1,16 @@
+use crate::node::profiler::profile_time;
+use crate::procedural::{
+    fbm2, hash21, hash33, noise2, noise3, rotate_x, rotate_y, rotate_z, voronoi2,
+};
+use crate::sdf::{
+    HIT_EPSILON, MAX_DISTANCE, MAX_STEPS, op_smooth_intersection, op_smooth_subtraction,
+    op_smooth_union, sd_box, sd_sphere, sd_torus,
+};
 use crate::textures::patterns::{pattern_normal_safe, pattern_safe};
-use crate::{NodeOp, Program, TexStorage, Value};
+use crate::{DebugAction, DebugFrame, DebugHook, NodeOp, Profiler, Program, TexStorage, Value};
+use rustc_hash::FxHashMap;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use theframework::thepalette::ThePalette;
 use vek::Vec3;
 
@@ -21,9 +31,15 @@ pub struct Execution {
     /// Function return value.
     return_value: Option<Value>,
 
+    /// All values of a function returning a tuple.
+    return_tuple: Vec<Value>,
+
     /// Allocated textures.
     textures: Vec<TexStorage>,
 
+    /// Allocated dictionaries, scripts reference them by index.
+    dicts: Vec<FxHashMap<String, Value>>,
+
     /// UV
     pub uv: Value,
 
@@ -53,6 +69,28 @@ pub struct Execution {
 
     /// Time
     pub time: Value,
+
+    /// The user functions currently being executed.
+    call_stack: Vec<usize>,
+
+    /// Optional debug hook, consulted before every NodeOp.
+    debug_hook: Option<Arc<Mutex<dyn DebugHook>>>,
+
+    /// Stop before the next NodeOp (single stepping).
+    stepping: bool,
+
+    /// Set when the debug hook aborted the execution.
+    halted: bool,
+
+    /// The pcs of the ops of each code block the debugger stopped in, by block address. See
+    /// `number_ops`.
+    debug_pcs: FxHashMap<usize, Vec<usize>>,
+
+    /// The address of the program the pcs belong to.
+    debug_program: usize,
+
+    /// Optional profiler, collecting per function and per node timings.
+    profiler: Option<Box<Profiler>>,
 }
 
 impl Execution {
@@ -63,7 +101,9 @@ impl Execution {
             locals_stack: vec![],
             stack: Vec::with_capacity(32),
             return_value: None,
+            return_tuple: vec![],
             textures: vec![],
+            dicts: vec![],
             uv: Vec3::zero(),
             color: Vec3::zero(),
             roughness: Vec3::broadcast(0.5),
@@ -74,6 +114,13 @@ impl Execution {
             normal: Vec3::zero(),
             hitpoint: Vec3::zero(),
             time: Vec3::zero(),
+            call_stack: vec![],
+            debug_hook: None,
+            stepping: false,
+            halted: false,
+            debug_pcs: FxHashMap::default(),
+            debug_program: 0,
+            profiler: None,
         }
     }
 
@@ -84,7 +131,9 @@ impl Execution {
             locals_stack: vec![],
             stack: Vec::with_capacity(32),
             return_value: None,
+            return_tuple: vec![],
             textures: vec![],
+            dicts: execution.dicts.clone(),
             uv: Vec3::zero(),
             color: Vec3::zero(),
             roughness: Vec3::broadcast(0.5),
@@ -95,6 +144,13 @@ impl Execution {
             normal: Vec3::zero(),
             hitpoint: Vec3::zero(),
             time: Vec3::zero(),
+            call_stack: vec![],
+            debug_hook: None,
+            stepping: false,
+            halted: false,
+            debug_pcs: FxHashMap::default(),
+            debug_program: 0,
+            profiler: None,
         }
     }
 
@@ -106,12 +162,56 @@ impl Execution {
         }
     }
 
+    /// Install a debug hook. If `step` is true the execution stops before the first NodeOp.
+    pub fn set_debug_hook(&mut self, hook: Arc<Mutex<dyn DebugHook>>, step: bool) {
+        self.debug_hook = Some(hook);
+        self.stepping = step;
+        self.halted = false;
+    }
+
+    /// Remove the debug hook.
+    pub fn clear_debug_hook(&mut self) {
+        self.debug_hook = None;
+        self.stepping = false;
+        self.halted = false;
+    }
+
+    /// Returns true if the debug hook aborted the last execution.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Enable or disable the profiler. Enabling resets previously collected data.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = if enabled { Some(Box::default()) } else { None };
+    }
+
+    /// The collected profile, if profiling is enabled.
+    pub fn profile(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    /// Take the collected profile, disabling the profiler.
+    pub fn take_profile(&mut self) -> Option<Profiler> {
+        self.profiler.take().map(|p| *p)
+    }
+
     pub fn execute(&mut self, code: &[NodeOp], program: &Program, palette: &ThePalette) {
-        for op in code {
-            // Unwind if return is set
-            if self.return_value.is_some() {
+        for (pc, op) in code.iter().enumerate() {
+            // Unwind if return is set or the debugger aborted
+            if self.return_value.is_some() || self.halted {
                 break;
             }
+            if let Some(hook) = self.debug_hook.clone() {
+                if !self.debug_break(&hook, code, pc, op, program) {
+                    break;
+                }
+            }
+            let op_start = if self.profiler.is_some() {
+                profile_time()
+            } else {
+                0.0
+            };
             match op {
                 NodeOp::LoadGlobal(index) => {
                     self.stack.push(self.globals[*index]);
@@ -196,11 +296,27 @@ impl Execution {
                     // Save the stack position
                     let stack_base = self.stack.len();
 
+                    self.call_stack.push(*index);
+                    if let Some(hook) = &self.debug_hook {
+                        hook.lock()
+                            .unwrap()
+                            .on_enter_function(*index, &self.locals[..*arity as usize]);
+                    }
+
                     // Execute the function body
                     let body = program.user_functions[*index].clone(); // Arc clone
+                    let fn_start = if self.profiler.is_some() {
+                        profile_time()
+                    } else {
+                        0.0
+                    };
                     self.execute(&body, program, palette);
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.record_function(*index, profile_time() - fn_start);
+                    }
 
-                    // Retrieve the return value. A function always returns exactly one value.
+                    // Retrieve the return value. A function returns exactly one value or a tuple.
+                    let tuple = std::mem::take(&mut self.return_tuple);
                     let ret = if self.return_value.is_some() {
                         self.return_value.take().unwrap_or(Value::zero())
                     } else if self.stack.len() > stack_base {
@@ -212,10 +328,19 @@ impl Execution {
                     // Clean up temporaries
                     self.stack.truncate(stack_base);
 
+                    self.call_stack.pop();
+                    if let Some(hook) = &self.debug_hook {
+                        hook.lock().unwrap().on_exit_function(*index, &ret);
+                    }
+
                     self.pop_locals_state();
 
                     // Push the return value
-                    self.stack.push(ret);
+                    if tuple.is_empty() {
+                        self.stack.push(ret);
+                    } else {
+                        self.stack.extend(tuple);
+                    }
                 }
                 NodeOp::Return => {
                     let v = if let Some(top) = self.stack.pop() {
@@ -228,6 +353,13 @@ impl Execution {
                     self.return_value = Some(v);
                     break;
                 }
+                NodeOp::ReturnTuple(count) => {
+                    let base = self.stack.len().saturating_sub(*count as usize);
+                    self.return_tuple = self.stack.split_off(base);
+                    self.return_value =
+                        Some(self.return_tuple.first().copied().unwrap_or(Value::zero()));
+                    break;
+                }
                 NodeOp::Pack2 => {
                     let y = self.stack.pop().unwrap();
                     let x = self.stack.pop().unwrap();
@@ -252,6 +384,9 @@ impl Execution {
 
                     loop {
                         self.execute(cond, program, palette);
+                        if self.halted {
+                            break;
+                        }
 
                         let z = self.stack.pop().unwrap();
                         if z.x == 0.0 {
@@ -279,6 +414,14 @@ impl Execution {
                         self.execute(else_code, program, palette);
                     }
                 }
+                NodeOp::Match(arms, default) => {
+                    let value = self.stack.pop().unwrap().x;
+                    if let Some((_, code)) = arms.iter().find(|(p, _)| p.matches(value)) {
+                        self.execute(code, program, palette);
+                    } else if let Some(default) = default {
+                        self.execute(default, program, palette);
+                    }
+                }
                 // Math
                 NodeOp::Add => {
                     let b = self.stack.pop().unwrap();
@@ -502,6 +645,158 @@ impl Execution {
                     let a = self.stack.pop().unwrap();
                     self.stack.push(a.map(|x| x.ln()));
                 }
+                NodeOp::Exp => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(a.map(|x| x.exp()));
+                }
+                NodeOp::Sign => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(a.map(|x| {
+                        if x > 0.0 {
+                            1.0
+                        } else if x < 0.0 {
+                            -1.0
+                        } else {
+                            0.0
+                        }
+                    }));
+                }
+                // Procedural
+                NodeOp::Hash => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(hash21(a.x, a.y)));
+                }
+                NodeOp::Hash3 => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(hash33(a));
+                }
+                NodeOp::Noise2 => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(noise2(a.x, a.y)));
+                }
+                NodeOp::Noise3 => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(noise3(a)));
+                }
+                NodeOp::Fbm => {
+                    let octaves = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(fbm2(
+                        a.x,
+                        a.y,
+                        octaves.x.max(1.0) as usize,
+                    )));
+                }
+                NodeOp::Voronoi => {
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(voronoi2(a.x, a.y));
+                }
+                NodeOp::RotateX => {
+                    let angle = self.stack.pop().unwrap();
+                    let v = self.stack.pop().unwrap();
+                    self.stack.push(rotate_x(v, angle.x));
+                }
+                NodeOp::RotateY => {
+                    let angle = self.stack.pop().unwrap();
+                    let v = self.stack.pop().unwrap();
+                    self.stack.push(rotate_y(v, angle.x));
+                }
+                NodeOp::RotateZ => {
+                    let angle = self.stack.pop().unwrap();
+                    let v = self.stack.pop().unwrap();
+                    self.stack.push(rotate_z(v, angle.x));
+                }
+                NodeOp::SdSphere => {
+                    let r = self.stack.pop().unwrap();
+                    let p = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(sd_sphere(p, r.x)));
+                }
+                NodeOp::SdBox => {
+                    let b = self.stack.pop().unwrap();
+                    let p = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(sd_box(p, b)));
+                }
+                NodeOp::SdTorus => {
+                    let t = self.stack.pop().unwrap();
+                    let p = self.stack.pop().unwrap();
+                    self.stack.push(Value::broadcast(sd_torus(p, t)));
+                }
+                NodeOp::SmoothUnion => {
+                    let k = self.stack.pop().unwrap();
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack
+                        .push(Value::broadcast(op_smooth_union(a.x, b.x, k.x)));
+                }
+                NodeOp::SmoothSubtraction => {
+                    let k = self.stack.pop().unwrap();
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack
+                        .push(Value::broadcast(op_smooth_subtraction(a.x, b.x, k.x)));
+                }
+                NodeOp::SmoothIntersection => {
+                    let k = self.stack.pop().unwrap();
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack
+                        .push(Value::broadcast(op_smooth_intersection(a.x, b.x, k.x)));
+                }
+                NodeOp::Raymarch => {
+                    let scene = self.stack.pop().unwrap(); // string index
+                    let rd = self.stack.pop().unwrap();
+                    let ro = self.stack.pop().unwrap();
+
+                    // (distance, steps, hit)
+                    let mut result = Value::new(MAX_DISTANCE, MAX_STEPS as f32, 0.0);
+                    if let Some(index) = Self::user_function_index(program, scene) {
+                        let rd = if rd.magnitude_squared() > 0.0 {
+                            rd.normalized()
+                        } else {
+                            rd
+                        };
+                        let mut t = 0.0;
+                        for step in 0..MAX_STEPS {
+                            let d = self
+                                .call_user_function(index, &[ro + rd * t], program, palette)
+                                .x;
+                            if d < HIT_EPSILON * t.max(1.0) {
+                                result = Value::new(t, step as f32, 1.0);
+                                break;
+                            }
+                            t += d;
+                            if t > MAX_DISTANCE {
+                                result = Value::new(MAX_DISTANCE, step as f32, 0.0);
+                                break;
+                            }
+                        }
+                    }
+                    self.stack.push(result);
+                }
+                NodeOp::SdfNormal => {
+                    let scene = self.stack.pop().unwrap(); // string index
+                    let p = self.stack.pop().unwrap();
+
+                    let mut n = Value::zero();
+                    if let Some(index) = Self::user_function_index(program, scene) {
+                        // Tetrahedron technique, four evaluations of the scene
+                        let e = 0.0005;
+                        for k in [
+                            Value::new(1.0, -1.0, -1.0),
+                            Value::new(-1.0, -1.0, 1.0),
+                            Value::new(-1.0, 1.0, -1.0),
+                            Value::new(1.0, 1.0, 1.0),
+                        ] {
+                            n += k * self
+                                .call_user_function(index, &[p + k * e], program, palette)
+                                .x;
+                        }
+                        if n.magnitude_squared() > 0.0 {
+                            n = n.normalized();
+                        }
+                    }
+                    self.stack.push(n);
+                }
                 NodeOp::Pow => {
                     let b: Value = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
@@ -653,6 +948,44 @@ impl Execution {
                         self.stack.push(Vec3::zero());
                     }
                 }
+                NodeOp::DictNew => {
+                    let index = self.dicts.len();
+                    self.dicts.push(FxHashMap::default());
+                    self.stack.push(Vec3::broadcast(index as f32));
+                }
+                NodeOp::DictSet => {
+                    let value = self.stack.pop().unwrap();
+                    let key = self.stack.pop().unwrap(); // string index
+                    let dict = self.stack.pop().unwrap(); // dict index
+                    if let Some(d) = self.dicts.get_mut(dict.x as usize) {
+                        if let Some(k) = program.strings.get(key.x as usize) {
+                            d.insert(k.clone(), value);
+                        }
+                    }
+                    self.stack.push(dict);
+                }
+                NodeOp::DictGet => {
+                    let key = self.stack.pop().unwrap();
+                    let dict = self.stack.pop().unwrap();
+                    let value = self
+                        .dicts
+                        .get(dict.x as usize)
+                        .zip(program.strings.get(key.x as usize))
+                        .and_then(|(d, k)| d.get(k).copied())
+                        .unwrap_or(Value::zero());
+                    self.stack.push(value);
+                }
+                NodeOp::DictContains => {
+                    let key = self.stack.pop().unwrap();
+                    let dict = self.stack.pop().unwrap();
+                    let found = self
+                        .dicts
+                        .get(dict.x as usize)
+                        .zip(program.strings.get(key.x as usize))
+                        .is_some_and(|(d, k)| d.contains_key(k));
+                    self.stack
+                        .push(Vec3::broadcast(if found { 1.0 } else { 0.0 }));
+                }
                 NodeOp::Alloc => {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
@@ -748,9 +1081,80 @@ impl Execution {
                     }
                 }
             }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record_op(op, profile_time() - op_start);
+            }
         }
     }
 
+    /// Consult the debug hook before executing the given op. Returns false if the execution was aborted.
+    fn debug_break(
+        &mut self,
+        hook: &Arc<Mutex<dyn DebugHook>>,
+        code: &[NodeOp],
+        pc: usize,
+        op: &NodeOp,
+        program: &Program,
+    ) -> bool {
+        let pc = self.function_pc(code, pc, program);
+        let action = {
+            let frame = DebugFrame {
+                function: self.call_stack.last().copied(),
+                pc,
+                depth: self.call_stack.len(),
+                globals: &self.globals,
+                locals: &self.locals,
+                stack: &self.stack,
+                uv: self.uv,
+                color: self.color,
+                program,
+            };
+            let mut hook = hook.lock().unwrap();
+            if self.stepping || hook.is_breakpoint(&frame, op) {
+                Some(hook.on_break(&frame, op))
+            } else {
+                None
+            }
+        };
+
+        match action {
+            Some(DebugAction::Continue) => self.stepping = false,
+            Some(DebugAction::Step) => self.stepping = true,
+            Some(DebugAction::Abort) => self.halted = true,
+            None => {}
+        }
+
+        !self.halted
+    }
+
+    /// The pc of the op at the index of the code block inside the code of the current function.
+    fn function_pc(&mut self, code: &[NodeOp], index: usize, program: &Program) -> usize {
+        let program_address = program as *const Program as usize;
+        if self.debug_program != program_address {
+            self.debug_pcs.clear();
+            self.debug_program = program_address;
+        }
+
+        let block = code.as_ptr() as usize;
+        if !self.debug_pcs.contains_key(&block) {
+            match self.call_stack.last() {
+                Some(function) => {
+                    number_ops(&program.user_functions[*function], 0, &mut self.debug_pcs)
+                }
+                None => number_ops(&program.body, 0, &mut self.debug_pcs),
+            };
+            // Code which is not part of the program, i.e. handed to `run`
+            if !self.debug_pcs.contains_key(&block) {
+                number_ops(code, 0, &mut self.debug_pcs);
+            }
+        }
+        self.debug_pcs
+            .get(&block)
+            .and_then(|pcs| pcs.get(index))
+            .copied()
+            .unwrap_or(index)
+    }
+
     // Push the current locals state when we enter a function.
     fn push_locals_state(&mut self) {
         self.locals_stack.push(self.locals.clone());
@@ -763,15 +1167,109 @@ impl Execution {
         }
     }
 
+    /// Look up a user function by a string index, i.e. the scene function of raymarch().
+    fn user_function_index(program: &Program, name: Value) -> Option<usize> {
+        program
+            .strings
+            .get(name.x as usize)
+            .and_then(|name| program.user_functions_name_map.get(name))
+            .copied()
+    }
+
+    /// Call a user function from inside a NodeOp, the state of the caller stays untouched.
+    fn call_user_function(
+        &mut self,
+        index: usize,
+        args: &[Value],
+        program: &Program,
+        palette: &ThePalette,
+    ) -> Value {
+        self.push_locals_state();
+        let count = program
+            .user_functions_locals
+            .get(index)
+            .map_or(args.len(), |locals| locals.len().max(args.len()));
+        self.locals = vec![Value::zero(); count];
+        self.locals[..args.len()].copy_from_slice(args);
+
+        let stack_base = self.stack.len();
+
+        self.call_stack.push(index);
+        if let Some(hook) = &self.debug_hook {
+            hook.lock()
+                .unwrap()
+                .on_enter_function(index, &self.locals[..args.len()]);
+        }
+
+        let body = program.user_functions[index].clone();
+        let fn_start = if self.profiler.is_some() {
+            profile_time()
+        } else {
+            0.0
+        };
+        self.execute(&body, program, palette);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_function(index, profile_time() - fn_start);
+        }
+
+        let ret = if let Some(ret) = self.return_value.take() {
+            ret
+        } else if self.stack.len() > stack_base {
+            self.stack.pop().unwrap()
+        } else {
+            Value::zero()
+        };
+        self.return_tuple.clear();
+        self.stack.truncate(stack_base);
+
+        self.call_stack.pop();
+        if let Some(hook) = &self.debug_hook {
+            hook.lock().unwrap().on_exit_function(index, &ret);
+        }
+
+        self.pop_locals_state();
+
+        ret
+    }
+
+    /// Execute a top level block of code and return the value it left on the stack.
+    pub fn run(
+        &mut self,
+        code: &[NodeOp],
+        program: &Program,
+        palette: &ThePalette,
+    ) -> Option<Value> {
+        self.stack.truncate(0);
+        self.return_value = None;
+        self.halted = false;
+
+        self.execute(code, program, palette);
+
+        if let Some(ret) = self.return_value.take() {
+            return Some(ret);
+        }
+        self.stack.pop()
+    }
+
     /// Execute the shading function
     #[inline]
     pub fn shade(&mut self, index: usize, program: &Program, palette: &ThePalette) {
         // Reset state for this call
         self.stack.truncate(0);
         self.return_value = None;
+        // Dictionaries created by the previous pixel are no longer reachable
+        self.dicts.clear();
 
         self.locals.resize(program.shade_locals, Value::zero());
-        self.execute(&program.user_functions[index], program, palette);
+        if self.profiler.is_some() {
+            let start = profile_time();
+            self.execute(&program.user_functions[index], program, palette);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record_function(index, profile_time() - start);
+            }
+        } else {
+            self.execute(&program.user_functions[index], program, palette);
+        }
     }
 
     /// Call a function with no arguments
@@ -834,3 +1332,38 @@ impl Execution {
         }
     }
 }
+
+/// Number the ops of the code in execution order starting at `pc`: each op is followed by the
+/// ops of its nested blocks, so every op of a function has its own pc. Stores the pcs of each
+/// block by its address and returns the pc after the code.
+fn number_ops(code: &[NodeOp], mut pc: usize, pcs: &mut FxHashMap<usize, Vec<usize>>) -> usize {
+    let mut block = Vec::with_capacity(code.len());
+    for op in code {
+        block.push(pc);
+        pc += 1;
+        match op {
+            NodeOp::If(then_code, else_code) => {
+                pc = number_ops(then_code, pc, pcs);
+                if let Some(else_code) = else_code {
+                    pc = number_ops(else_code, pc, pcs);
+                }
+            }
+            NodeOp::Match(arms, default) => {
+                for (_, code) in arms {
+                    pc = number_ops(code, pc, pcs);
+                }
+                if let Some(default) = default {
+                    pc = number_ops(default, pc, pcs);
+                }
+            }
+            NodeOp::For(init, cond, incr, body) => {
+                for code in [init, cond, incr, body] {
+                    pc = number_ops(code, pc, pcs);
+                }
+            }
+            _ => {}
+        }
+    }
+    pcs.insert(code.as_ptr() as usize, block);
+    pc
+}
@@ -1,3 +1,5 @@
+pub mod debughook;
 pub mod execution;
 pub mod nodeop;
+pub mod profiler;
 pub mod program;
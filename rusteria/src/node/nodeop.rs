@@ -1,4 +1,6 @@
 use crate::Value;
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
 
 #[derive(Clone, Copy, Debug)]
 pub enum Plane {
@@ -8,7 +10,28 @@ pub enum Plane {
     ZY, // Left, Right
 }
 
-#[derive(Debug, Clone)]
+/// A pattern of a match arm, tested against the x component of the matched value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MatchPattern {
+    /// A number or string literal.
+    Value(f32),
+    /// A range from start to end, the end is included if the flag is set.
+    Range(f32, f32, bool),
+}
+
+impl MatchPattern {
+    #[inline]
+    pub fn matches(&self, value: f32) -> bool {
+        match self {
+            MatchPattern::Value(v) => value == *v,
+            MatchPattern::Range(start, end, inclusive) => {
+                value >= *start && (value < *end || (*inclusive && value == *end))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, IntoStaticStr)]
 pub enum NodeOp {
     LoadGlobal(usize),
     StoreGlobal(usize),
@@ -18,10 +41,12 @@ pub enum NodeOp {
     GetComponents(Vec<u8>),
     SetComponents(Vec<u8>),
     If(Vec<NodeOp>, Option<Vec<NodeOp>>),
+    Match(Vec<(MatchPattern, Vec<NodeOp>)>, Option<Vec<NodeOp>>),
     For(Vec<NodeOp>, Vec<NodeOp>, Vec<NodeOp>, Vec<NodeOp>),
     Push(Value),
     FunctionCall(u8, u8, usize),
     Return,
+    ReturnTuple(u8),
     Dup,
     Clear,
     Pack2,
@@ -65,6 +90,25 @@ pub enum NodeOp {
     Sqrt,
     Pow,
     Log,
+    Exp,
+    Sign,
+    Hash,
+    Hash3,
+    Noise2,
+    Noise3,
+    Fbm,
+    Voronoi,
+    RotateX,
+    RotateY,
+    RotateZ,
+    SdSphere,
+    SdBox,
+    SdTorus,
+    SmoothUnion,
+    SmoothSubtraction,
+    SmoothIntersection,
+    Raymarch,
+    SdfNormal,
     Print,
     Eq,
     Ne,
@@ -100,4 +144,8 @@ pub enum NodeOp {
     Iterate,
     Save,
     PaletteIndex,
+    DictNew,
+    DictGet,
+    DictSet,
+    DictContains,
 }
@@ -0,0 +1,123 @@
+use crate::{NodeOp, Program};
+use rustc_hash::FxHashMap;
+
+/// Accumulated call count and time of a function or node type.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProfileEntry {
+    pub calls: u64,
+    /// Inclusive time in milliseconds.
+    pub time: f64,
+}
+
+impl ProfileEntry {
+    fn add(&mut self, other: &ProfileEntry) {
+        self.calls += other.calls;
+        self.time += other.time;
+    }
+}
+
+/// Opt-in profiler of the Execution. Times are inclusive, i.e. the time of a function
+/// call or a control flow node contains the time of all nodes executed inside it.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    /// Per user function index.
+    pub functions: FxHashMap<usize, ProfileEntry>,
+    /// Per NodeOp type.
+    pub ops: FxHashMap<&'static str, ProfileEntry>,
+}
+
+impl Profiler {
+    #[inline]
+    pub fn record_op(&mut self, op: &NodeOp, time: f64) {
+        let entry = self.ops.entry(op.into()).or_default();
+        entry.calls += 1;
+        entry.time += time;
+    }
+
+    #[inline]
+    pub fn record_function(&mut self, index: usize, time: f64) {
+        let entry = self.functions.entry(index).or_default();
+        entry.calls += 1;
+        entry.time += time;
+    }
+
+    /// Merge the results of another profiler (i.e. of another thread) into this one.
+    pub fn merge(&mut self, other: &Profiler) {
+        for (index, entry) in &other.functions {
+            self.functions.entry(*index).or_default().add(entry);
+        }
+        for (name, entry) in &other.ops {
+            self.ops.entry(name).or_default().add(entry);
+        }
+    }
+
+    /// The per function results as (name, entry), sorted by time.
+    pub fn functions_by_time(&self, program: &Program) -> Vec<(String, ProfileEntry)> {
+        let mut names: FxHashMap<usize, &str> = FxHashMap::default();
+        for (name, index) in &program.user_functions_name_map {
+            names.insert(*index, name);
+        }
+
+        let mut list: Vec<(String, ProfileEntry)> = self
+            .functions
+            .iter()
+            .map(|(index, entry)| {
+                let name = names
+                    .get(index)
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| format!("fn#{}", index));
+                (name, *entry)
+            })
+            .collect();
+        list.sort_by(|a, b| b.1.time.total_cmp(&a.1.time));
+        list
+    }
+
+    /// The per node type results, sorted by time.
+    pub fn ops_by_time(&self) -> Vec<(&'static str, ProfileEntry)> {
+        let mut list: Vec<(&'static str, ProfileEntry)> = self
+            .ops
+            .iter()
+            .map(|(name, entry)| (*name, *entry))
+            .collect();
+        list.sort_by(|a, b| b.1.time.total_cmp(&a.1.time));
+        list
+    }
+
+    /// A human readable report.
+    pub fn report(&self, program: &Program) -> String {
+        let mut out = String::new();
+        out += "Functions:\n";
+        for (name, entry) in self.functions_by_time(program) {
+            out += &format!(
+                "  {:<24} {:>10} calls {:>12.3}ms\n",
+                name, entry.calls, entry.time
+            );
+        }
+        out += "Nodes:\n";
+        for (name, entry) in self.ops_by_time() {
+            out += &format!(
+                "  {:<24} {:>10} calls {:>12.3}ms\n",
+                name, entry.calls, entry.time
+            );
+        }
+        out
+    }
+}
+
+/// High resolution time stamp in milliseconds.
+#[inline]
+pub fn profile_time() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::window().unwrap().performance().unwrap().now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::sync::OnceLock;
+        use std::time::Instant;
+
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+    }
+}
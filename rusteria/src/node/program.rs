@@ -1,9 +1,16 @@
 use rustc_hash::FxHashMap;
 
 use crate::NodeOp;
+use serde::{Deserialize, Serialize};
 use std::{ops::Deref, sync::Arc};
 
-#[derive(Clone)]
+/// Magic bytes at the start of serialized programs.
+const BYTECODE_MAGIC: &[u8; 4] = b"RSTB";
+
+/// Bump whenever NodeOp or Program change in a way which breaks stored bytecode.
+pub const BYTECODE_VERSION: u32 = 5;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Program {
     /// Number of global variables
     pub globals: usize,
@@ -25,6 +32,12 @@ pub struct Program {
 
     /// Strings,
     pub strings: Vec<String>,
+
+    /// Names of the global variables, indexed like the globals array.
+    pub global_names: Vec<String>,
+
+    /// Names of the local variables of each user function.
+    pub user_functions_locals: Vec<Vec<String>>,
 }
 
 impl Program {
@@ -37,7 +50,31 @@ impl Program {
             globals: 0,
             shade_locals: 0,
             strings: vec![],
+            global_names: vec![],
+            user_functions_locals: vec![],
+        }
+    }
+
+    /// Serialize the compiled program into bytecode which can be loaded without the parser.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = BYTECODE_MAGIC.to_vec();
+        data.extend_from_slice(&BYTECODE_VERSION.to_le_bytes());
+        if let Ok(body) = bincode::serialize(self) {
+            data.extend(body);
+        }
+        data
+    }
+
+    /// Deserialize bytecode created by `pack`. Returns None for data of a different version.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 || &data[0..4] != BYTECODE_MAGIC {
+            return None;
+        }
+        let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        if version != BYTECODE_VERSION {
+            return None;
         }
+        bincode::deserialize(&data[8..]).ok()
     }
 
     /// Returns true if the shader changes opacity
@@ -1,4 +1,6 @@
-use crate::NodeOp;
+use crate::{Execution, NodeOp, Program, Value};
+use rustc_hash::FxHashSet;
+use theframework::thepalette::ThePalette;
 
 fn _peephole_locals(ops: &mut Vec<NodeOp>) {
     let mut i = 0;
@@ -14,6 +16,251 @@ fn _peephole_locals(ops: &mut Vec<NodeOp>) {
     }
 }
 
-pub fn optimize(_ops: &mut Vec<NodeOp>) {
+/// The amount of stack values a side effect free op consumes. All of these ops push exactly one value.
+/// Returns None for ops which access the execution state or have other side effects.
+fn pure_arity(op: &NodeOp) -> Option<usize> {
+    use NodeOp::*;
+    match op {
+        Push(_) => Some(0),
+        GetComponents(_) | Length | Length2 | Length3 | Abs | Sin | Sin1 | Sin2 | Cos | Cos1
+        | Cos2 | Tan | Atan | Normalize | Floor | Ceil | Round | Fract | Degrees | Radians
+        | Sqrt | Log | Exp | Sign | Hash | Hash3 | Noise2 | Noise3 | Voronoi | Not | Neg => Some(1),
+        SetComponents(_) | Pack2 | Add | Sub | Mul | Div | Atan2 | Rotate2D | Dot | Dot2 | Dot3
+        | Cross | Mod | Min | Max | Step | Pow | Fbm | RotateX | RotateY | RotateZ | Eq | Ne
+        | Lt | Le | Gt | Ge | And | Or => Some(2),
+        SdSphere | SdBox | SdTorus => Some(2),
+        Pack3 | Mix | Smoothstep | Clamp | SmoothUnion | SmoothSubtraction | SmoothIntersection => {
+            Some(3)
+        }
+        _ => None,
+    }
+}
+
+/// Ops which push a variable or a shading input without modifying anything.
+fn is_read(op: &NodeOp) -> bool {
+    use NodeOp::*;
+    matches!(
+        op,
+        LoadGlobal(_)
+            | LoadLocal(_)
+            | UV
+            | Normal
+            | Hitpoint
+            | Time
+            | Color
+            | Roughness
+            | Metallic
+            | Emissive
+            | Opacity
+            | Bump
+    )
+}
+
+/// Returns the start index of the side effect free code ending before `end` which
+/// leaves exactly one value on the stack.
+fn producer_start(ops: &[NodeOp], end: usize) -> Option<usize> {
+    let mut needed = 1;
+    let mut i = end;
+    while i > 0 {
+        i -= 1;
+        let arity = pure_arity(&ops[i]).or_else(|| is_read(&ops[i]).then_some(0))?;
+        needed = needed - 1 + arity;
+        if needed == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Evaluate constant code with the VM itself so folding matches the runtime semantics.
+fn evaluate(code: &[NodeOp]) -> Option<Value> {
+    let mut execution = Execution::new(0);
+    execution.execute(code, &Program::new(), &ThePalette::default());
+    execution.stack.pop()
+}
+
+/// Apply the given pass to all nested code blocks.
+fn for_each_block(ops: &mut [NodeOp], pass: &mut dyn FnMut(&mut Vec<NodeOp>)) {
+    for op in ops.iter_mut() {
+        match op {
+            NodeOp::If(then_code, else_code) => {
+                pass(then_code);
+                if let Some(else_code) = else_code {
+                    pass(else_code);
+                }
+            }
+            NodeOp::For(init, cond, incr, body) => {
+                pass(init);
+                pass(cond);
+                pass(incr);
+                pass(body);
+            }
+            NodeOp::Match(arms, default) => {
+                for (_, code) in arms {
+                    pass(code);
+                }
+                if let Some(default) = default {
+                    pass(default);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replace pure ops whose arguments are all constants by their result and
+/// resolve branches on constant conditions.
+fn fold_constants(ops: &mut Vec<NodeOp>) {
+    for_each_block(ops, &mut fold_constants);
+
+    let mut i = 0;
+    while i < ops.len() {
+        let start = match &ops[i] {
+            NodeOp::If(..) | NodeOp::Match(..) if i > 0 => i - 1,
+            op => match pure_arity(op) {
+                Some(arity) if arity > 0 && i >= arity => i - arity,
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            },
+        };
+
+        if !ops[start..i].iter().all(|op| matches!(op, NodeOp::Push(_))) {
+            i += 1;
+            continue;
+        }
+
+        let replacement = match &ops[i] {
+            NodeOp::If(then_code, else_code) => {
+                if let NodeOp::Push(cond) = ops[start] {
+                    if cond.x != 0.0 {
+                        Some(then_code.clone())
+                    } else {
+                        Some(else_code.clone().unwrap_or_default())
+                    }
+                } else {
+                    None
+                }
+            }
+            NodeOp::Match(arms, default) => {
+                if let NodeOp::Push(value) = ops[start] {
+                    arms.iter()
+                        .find(|(p, _)| p.matches(value.x))
+                        .map(|(_, code)| code.clone())
+                        .or_else(|| default.clone())
+                        .or(Some(vec![]))
+                } else {
+                    None
+                }
+            }
+            _ => evaluate(&ops[start..=i]).map(|v| vec![NodeOp::Push(v)]),
+        };
+
+        if let Some(code) = replacement {
+            let len = code.len();
+            ops.splice(start..=i, code);
+            // Re-examine the inserted code, it may enable further folding
+            i = if len == 1 { start + 1 } else { start };
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Drop code following a return, it can never execute.
+fn remove_unreachable(ops: &mut Vec<NodeOp>) {
+    for_each_block(ops, &mut remove_unreachable);
+
+    if let Some(pos) = ops
+        .iter()
+        .position(|op| matches!(op, NodeOp::Return | NodeOp::ReturnTuple(_)))
+    {
+        ops.truncate(pos + 1);
+    }
+}
+
+fn collect_loaded_locals(ops: &[NodeOp], loaded: &mut FxHashSet<usize>) {
+    for op in ops {
+        match op {
+            NodeOp::LoadLocal(index) => {
+                loaded.insert(*index);
+            }
+            NodeOp::If(then_code, else_code) => {
+                collect_loaded_locals(then_code, loaded);
+                if let Some(else_code) = else_code {
+                    collect_loaded_locals(else_code, loaded);
+                }
+            }
+            NodeOp::For(init, cond, incr, body) => {
+                for code in [init, cond, incr, body] {
+                    collect_loaded_locals(code, loaded);
+                }
+            }
+            NodeOp::Match(arms, default) => {
+                for (_, code) in arms {
+                    collect_loaded_locals(code, loaded);
+                }
+                if let Some(default) = default {
+                    collect_loaded_locals(default, loaded);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Remove assignments to local variables which are never read. If the assigned
+/// value has side effects it is still computed and then discarded.
+fn remove_dead_stores(ops: &mut Vec<NodeOp>, loaded: &FxHashSet<usize>) {
+    for_each_block(ops, &mut |code: &mut Vec<NodeOp>| {
+        remove_dead_stores(code, loaded)
+    });
+
+    let mut i = 0;
+    while i < ops.len() {
+        if let NodeOp::StoreLocal(index) = ops[i] {
+            if !loaded.contains(&index) {
+                if let Some(start) = producer_start(ops, i) {
+                    ops.drain(start..=i);
+                    i = start;
+                } else {
+                    ops[i] = NodeOp::Clear;
+                    i += 1;
+                }
+                continue;
+            }
+        }
+        i += 1;
+    }
+}
+
+/// Replace a side effect free expression which is immediately recomputed with a Dup,
+/// i.e. `(p - c) * (p - c)` or `dot(n, n)` evaluate their argument only once.
+fn share_subexpressions(ops: &mut Vec<NodeOp>) {
+    for_each_block(ops, &mut share_subexpressions);
+
+    let mut end = 1;
+    while end < ops.len() {
+        if let Some(start) = producer_start(ops, end) {
+            let len = end - start;
+            if len > 1 && end + len <= ops.len() && ops[start..end] == ops[end..end + len] {
+                ops.splice(end..end + len, [NodeOp::Dup]);
+            }
+        }
+        end += 1;
+    }
+}
+
+/// Optimize the code of a function or of the program body.
+pub fn optimize(ops: &mut Vec<NodeOp>) {
     // peephole_locals(ops);
+    fold_constants(ops);
+    remove_unreachable(ops);
+
+    let mut loaded = FxHashSet::default();
+    collect_loaded_locals(ops, &mut loaded);
+    remove_dead_stores(ops, &loaded);
+
+    share_subexpressions(ops);
 }
@@ -1,8 +1,8 @@
 use crate::zero_expr_float;
 use crate::{
     ASTValue, AssignmentOperator, BinaryOperator, ComparisonOperator, EqualityOperator, Expr,
-    IdVerifier, Location, LogicalOperator, Module, ParseError, Scanner, Stmt, Token, TokenType,
-    UnaryOperator, objectd::FunctionD,
+    IdVerifier, Location, LogicalOperator, MatchPattern, Module, ParseError, Scanner, Stmt, Token,
+    TokenType, UnaryOperator, objectd::FunctionD,
 };
 use indexmap::IndexMap;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -92,6 +92,7 @@ impl Parser {
             tokens.push(token);
         }
         self.tokens = tokens;
+        self.current = 0;
         self.path = path.clone();
 
         // Collect statements
@@ -130,21 +131,14 @@ impl Parser {
 
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
         let line = self.current_line;
+        if self.match_token(vec![TokenType::LeftParen]) {
+            return self.tuple_declaration();
+        }
+
         let var_name = self
             .consume(TokenType::Identifier, "Expect variable name", line)?
             .lexeme;
-
-        if self.scope == VariableScope::Global {
-            _ = self.verifier.define_var(&var_name, false)?;
-            if !self.globals_map.contains_key(&var_name) {
-                self.globals_map
-                    .insert(var_name.clone(), self.globals_map.len() as u32);
-            }
-        } else {
-            if !self.locals_map.contains_key(&var_name) {
-                self.locals_map.insert(var_name.clone(), None);
-            }
-        }
+        self.declare_variable(&var_name)?;
 
         let initializer;
         if self.match_token(vec![TokenType::Equal]) {
@@ -200,6 +194,52 @@ impl Parser {
         ))
     }
 
+    /// Destructuring declaration: let (a, b) = f(x);
+    fn tuple_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.current_line;
+        let mut names = vec![];
+
+        loop {
+            let name = self
+                .consume(TokenType::Identifier, "Expect variable name", line)?
+                .lexeme;
+            self.declare_variable(&name)?;
+            names.push(name);
+
+            if !self.match_token(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after variable names",
+            line,
+        )?;
+        self.consume(TokenType::Equal, "Expected '=' after variable names", line)?;
+        let initializer = self.expression()?;
+
+        Ok(Stmt::TupleDeclaration(
+            names,
+            Box::new(initializer),
+            self.create_loc(line),
+        ))
+    }
+
+    /// Register a new global or local variable, depending on the current scope.
+    fn declare_variable(&mut self, name: &str) -> Result<(), ParseError> {
+        if self.scope == VariableScope::Global {
+            _ = self.verifier.define_var(name, false)?;
+            if !self.globals_map.contains_key(name) {
+                self.globals_map
+                    .insert(name.to_string(), self.globals_map.len() as u32);
+            }
+        } else if !self.locals_map.contains_key(name) {
+            self.locals_map.insert(name.to_string(), None);
+        }
+        Ok(())
+    }
+
     /// Import statement
     fn import_statement(&mut self) -> Result<Stmt, ParseError> {
         let line = self.current_line;
@@ -343,6 +383,8 @@ impl Parser {
     fn statement(&mut self) -> Result<Stmt, ParseError> {
         if self.match_token(vec![TokenType::If]) {
             self.if_statement()
+        } else if self.match_token(vec![TokenType::Match]) {
+            self.match_statement()
         } else if self.match_token(vec![TokenType::LeftBrace]) {
             self.block()
         } else if self.match_token(vec![TokenType::Return]) {
@@ -443,6 +485,93 @@ impl Parser {
         ))
     }
 
+    fn match_statement(&mut self) -> Result<Stmt, ParseError> {
+        let line = self.current_line;
+        let value = self.expression()?;
+        self.consume(
+            TokenType::LeftBrace,
+            "Expect '{' after match expression",
+            line,
+        )?;
+
+        let mut arms = vec![];
+        let mut default = None;
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let token = self.peek();
+            let pattern = if token.kind == TokenType::Identifier && token.lexeme == "_" {
+                self.advance();
+                None
+            } else {
+                let start = self.match_literal()?;
+                if self.match_token(vec![TokenType::DotDot]) {
+                    Some(MatchPattern::Range(start, self.match_literal()?, false))
+                } else if self.match_token(vec![TokenType::DotDotEqual]) {
+                    Some(MatchPattern::Range(start, self.match_literal()?, true))
+                } else {
+                    Some(MatchPattern::Value(start))
+                }
+            };
+
+            self.consume(
+                TokenType::LeftBrace,
+                "Expect '{' to start match arm block",
+                self.current_line,
+            )?;
+            let block = Box::new(self.block()?);
+
+            if let Some(pattern) = pattern {
+                arms.push((pattern, block));
+            } else {
+                default = Some(block);
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after match arms", line)?;
+
+        Ok(Stmt::Match(
+            Box::new(value),
+            arms,
+            default,
+            self.create_loc(line),
+        ))
+    }
+
+    /// Reads a number or string literal of a match arm. Strings evaluate to their string index.
+    fn match_literal(&mut self) -> Result<f32, ParseError> {
+        let negative = self.match_token(vec![TokenType::Minus]);
+        let token = self.peek();
+        let value = match token.kind {
+            TokenType::IntegerNumber | TokenType::FloatNumber => token.lexeme.parse::<f32>().ok(),
+            TokenType::String if !negative => {
+                Some(self.intern_string(token.lexeme.replace("\"", "")) as f32)
+            }
+            _ => None,
+        };
+
+        match value {
+            Some(value) => {
+                self.advance();
+                Ok(if negative { -value } else { value })
+            }
+            None => Err(ParseError::new(
+                "Expected number or string literal or _ in match arm",
+                token.line,
+                &self.path,
+            )),
+        }
+    }
+
+    /// Adds the string to the string table and returns its index. Equal strings share an index.
+    fn intern_string(&mut self, string: String) -> usize {
+        if let Some(index) = self.strings.iter().position(|s| *s == string) {
+            index
+        } else {
+            self.strings.push(string);
+            self.strings.len() - 1
+        }
+    }
+
     fn expression(&mut self) -> Result<Expr, ParseError> {
         self.assignment()
     }
@@ -458,6 +587,7 @@ impl Parser {
             let value = self.assignment()?;
 
             if let Expr::Variable(name, swizzle, field_path, _loc) = expr {
+                self.verify_swizzle_target(&swizzle, equals.line)?;
                 return Ok(Expr::VariableAssignment(
                     name,
                     AssignmentOperator::AddAssign,
@@ -481,6 +611,7 @@ impl Parser {
             let value = self.assignment()?;
 
             if let Expr::Variable(name, swizzle, field_path, _loc) = expr {
+                self.verify_swizzle_target(&swizzle, equals.line)?;
                 return Ok(Expr::VariableAssignment(
                     name,
                     AssignmentOperator::SubtractAssign,
@@ -504,6 +635,7 @@ impl Parser {
             let value = self.assignment()?;
 
             if let Expr::Variable(name, swizzle, field_path, _loc) = expr {
+                self.verify_swizzle_target(&swizzle, equals.line)?;
                 return Ok(Expr::VariableAssignment(
                     name,
                     AssignmentOperator::MultiplyAssign,
@@ -527,6 +659,7 @@ impl Parser {
             let value = self.assignment()?;
 
             if let Expr::Variable(name, swizzle, field_path, _loc) = expr {
+                self.verify_swizzle_target(&swizzle, equals.line)?;
                 return Ok(Expr::VariableAssignment(
                     name,
                     AssignmentOperator::DivideAssign,
@@ -547,6 +680,7 @@ impl Parser {
             let value = self.assignment()?;
 
             if let Expr::Variable(name, swizzle, field_path, _loc) = expr {
+                self.verify_swizzle_target(&swizzle, equals.line)?;
                 return Ok(Expr::VariableAssignment(
                     name,
                     AssignmentOperator::Assign,
@@ -737,6 +871,11 @@ impl Parser {
         loop {
             if self.match_token(vec![TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.is_swizzle_valid_at_current() {
+                // Swizzle of a grouping or a chained swizzle, i.e. (a + b).xy or a.xyz.zy
+                let line = self.current_line;
+                let swizzle = self.get_swizzle_at_current();
+                expr = Expr::Swizzle(Box::new(expr), swizzle, self.create_loc(line));
             } else {
                 break;
             }
@@ -798,8 +937,7 @@ impl Parser {
         match token.kind {
             TokenType::String => {
                 self.advance();
-                let index = self.strings.len() as f32;
-                self.strings.push(token.lexeme.clone().replace("\"", ""));
+                let index = self.intern_string(token.lexeme.replace("\"", "")) as f32;
                 Ok(Expr::Value(
                     ASTValue::Float(index),
                     vec![],
@@ -807,6 +945,40 @@ impl Parser {
                     self.create_loc(token.line),
                 ))
             }
+            TokenType::LeftBrace => {
+                // Dictionary literal: { "key": value, ... }
+                self.advance();
+                let mut entries = vec![];
+
+                while !self.check(TokenType::RightBrace) {
+                    if !self.check(TokenType::String) {
+                        return Err(ParseError::new(
+                            "Expect string key in dictionary",
+                            self.current_line,
+                            &self.path,
+                        ));
+                    }
+                    let key = self.primary()?;
+                    self.consume(
+                        TokenType::Colon,
+                        "Expect ':' after dictionary key",
+                        token.line,
+                    )?;
+                    let value = self.expression()?;
+                    entries.push((Box::new(key), Box::new(value)));
+
+                    if !self.match_token(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(
+                    TokenType::RightBrace,
+                    "Expect '}' after dictionary entries",
+                    token.line,
+                )?;
+                Ok(Expr::Dict(entries, self.create_loc(token.line)))
+            }
             TokenType::False => {
                 self.advance();
                 Ok(Expr::Value(
@@ -952,7 +1124,18 @@ impl Parser {
             TokenType::LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
-                if self.match_token(vec![TokenType::RightParen]) {
+                if self.check(TokenType::Comma) {
+                    let mut elements = vec![Box::new(expr)];
+                    while self.match_token(vec![TokenType::Comma]) {
+                        elements.push(Box::new(self.expression()?));
+                    }
+                    self.consume(
+                        TokenType::RightParen,
+                        "Expected ')' after tuple elements",
+                        token.line,
+                    )?;
+                    Ok(Expr::Tuple(elements, self.create_loc(token.line)))
+                } else if self.match_token(vec![TokenType::RightParen]) {
                     Ok(Expr::Grouping(Box::new(expr), self.create_loc(token.line)))
                 } else {
                     Err(ParseError::new(
@@ -1070,26 +1253,14 @@ impl Parser {
     pub fn get_swizzle_at_current(&mut self) -> Vec<u8> {
         let mut swizzle: Vec<u8> = vec![];
 
-        if self.current + 2 < self.tokens.len()
+        if self.current + 1 < self.tokens.len()
             && self.tokens[self.current].kind == TokenType::Dot
             && self.tokens[self.current + 1].kind == TokenType::Identifier
-            && self.tokens[self.current + 2].kind != TokenType::Dot
         {
-            let swizzle_token = self.tokens[self.current + 1].lexeme.clone();
-            if swizzle_token
-                .chars()
-                .all(|c| matches!(c, 'x' | 'y' | 'z' | 'w'))
+            if let Some(components) =
+                Self::swizzle_components(&self.tokens[self.current + 1].lexeme)
             {
-                swizzle = swizzle_token
-                    .chars()
-                    .map(|c| match c {
-                        'x' => 0,
-                        'y' => 1,
-                        'z' => 2,
-                        'w' => 3,
-                        _ => unreachable!(),
-                    })
-                    .collect();
+                swizzle = components;
                 self.current += 2;
             }
         }
@@ -1099,17 +1270,49 @@ impl Parser {
 
     /// Returns true if a swizzle is valid at the current token.
     pub fn is_swizzle_valid_at_current(&self) -> bool {
-        if self.current + 1 < self.tokens.len()
+        self.current + 1 < self.tokens.len()
             && self.tokens[self.current].kind == TokenType::Dot
             && self.tokens[self.current + 1].kind == TokenType::Identifier
-        {
-            let swizzle_token = &self.tokens[self.current + 1].lexeme;
-            swizzle_token
+            && Self::swizzle_components(&self.tokens[self.current + 1].lexeme).is_some()
+    }
+
+    /// Map a swizzle like "xzy" or "bgr" to its component indices.
+    /// Position (xyzw) and color (rgba) names cannot be mixed.
+    fn swizzle_components(swizzle: &str) -> Option<Vec<u8>> {
+        if swizzle.is_empty() || swizzle.len() > 4 {
+            return None;
+        }
+        let position = swizzle.chars().all(|c| matches!(c, 'x' | 'y' | 'z' | 'w'));
+        let color = swizzle.chars().all(|c| matches!(c, 'r' | 'g' | 'b' | 'a'));
+        if !position && !color {
+            return None;
+        }
+
+        Some(
+            swizzle
                 .chars()
-                .all(|c| matches!(c, 'x' | 'y' | 'z' | 'w'))
-        } else {
-            false
+                .map(|c| match c {
+                    'x' | 'r' => 0,
+                    'y' | 'g' => 1,
+                    'z' | 'b' => 2,
+                    _ => 3,
+                })
+                .collect(),
+        )
+    }
+
+    /// A swizzle used as an assignment target must not write the same component twice.
+    fn verify_swizzle_target(&self, swizzle: &[u8], line: usize) -> Result<(), ParseError> {
+        for (i, c) in swizzle.iter().enumerate() {
+            if swizzle[..i].contains(c) {
+                return Err(ParseError::new(
+                    "Swizzle assignment target writes the same component twice",
+                    line,
+                    &self.path,
+                ));
+            }
         }
+        Ok(())
     }
 
     /// Extract a potential swizzle from the variable name.
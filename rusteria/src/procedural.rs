@@ -0,0 +1,136 @@
+//! Hashing, noise and cellular functions used by the procedural built-ins of the VM.
+//! All functions are deterministic and stateless so they can be evaluated per pixel in parallel.
+
+use crate::Value;
+
+/// Hash a 2D position to a float in [0, 1).
+#[inline]
+pub fn hash21(x: f32, y: f32) -> f32 {
+    let mut p3 = Value::new(x, y, x) * 0.1031;
+    p3 = p3.map(|v| v - v.floor());
+    let d = p3.dot(Value::new(p3.y, p3.z, p3.x) + 33.33);
+    p3 += Value::broadcast(d);
+    let f = (p3.x + p3.y) * p3.z;
+    f - f.floor()
+}
+
+/// Hash a 3D position to a vector with components in [0, 1).
+#[inline]
+pub fn hash33(p: Value) -> Value {
+    let mut p3 = (p * Value::new(0.1031, 0.1030, 0.0973)).map(|v| v - v.floor());
+    let d = p3.dot(Value::new(p3.y, p3.x, p3.z) + 33.33);
+    p3 += Value::broadcast(d);
+    let r = Value::new(
+        (p3.x + p3.y) * p3.z,
+        (p3.x + p3.x) * p3.y,
+        (p3.y + p3.x) * p3.x,
+    );
+    r.map(|v| v - v.floor())
+}
+
+#[inline]
+fn smooth(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// 2D value noise in [0, 1].
+pub fn noise2(x: f32, y: f32) -> f32 {
+    let (ix, iy) = (x.floor(), y.floor());
+    let (fx, fy) = (smooth(x - ix), smooth(y - iy));
+
+    let a = hash21(ix, iy);
+    let b = hash21(ix + 1.0, iy);
+    let c = hash21(ix, iy + 1.0);
+    let d = hash21(ix + 1.0, iy + 1.0);
+
+    lerp(lerp(a, b, fx), lerp(c, d, fx), fy)
+}
+
+/// 3D value noise in [0, 1].
+pub fn noise3(p: Value) -> f32 {
+    let i = p.map(|v| v.floor());
+    let f = (p - i).map(smooth);
+
+    let h = |x: f32, y: f32, z: f32| hash33(i + Value::new(x, y, z)).x;
+
+    let x00 = lerp(h(0.0, 0.0, 0.0), h(1.0, 0.0, 0.0), f.x);
+    let x10 = lerp(h(0.0, 1.0, 0.0), h(1.0, 1.0, 0.0), f.x);
+    let x01 = lerp(h(0.0, 0.0, 1.0), h(1.0, 0.0, 1.0), f.x);
+    let x11 = lerp(h(0.0, 1.0, 1.0), h(1.0, 1.0, 1.0), f.x);
+
+    lerp(lerp(x00, x10, f.y), lerp(x01, x11, f.y), f.z)
+}
+
+/// Fractal brownian motion of 2D value noise, normalized to [0, 1].
+pub fn fbm2(x: f32, y: f32, octaves: usize) -> f32 {
+    let mut sum = 0.0;
+    let mut amp = 0.5;
+    let mut norm = 0.0;
+    let (mut px, mut py) = (x, y);
+
+    for _ in 0..octaves.clamp(1, 12) {
+        sum += noise2(px, py) * amp;
+        norm += amp;
+        // Rotate and scale to reduce axis aligned artifacts
+        let nx = 1.6 * px + 1.2 * py;
+        let ny = -1.2 * px + 1.6 * py;
+        px = nx;
+        py = ny;
+        amp *= 0.5;
+    }
+
+    sum / norm
+}
+
+/// 2D Voronoi. Returns (distance to the closest cell point, distance to the second closest, cell id in [0, 1)).
+pub fn voronoi2(x: f32, y: f32) -> Value {
+    let (ix, iy) = (x.floor(), y.floor());
+    let (fx, fy) = (x - ix, y - iy);
+
+    let mut f1 = f32::MAX;
+    let mut f2 = f32::MAX;
+    let mut id = 0.0;
+
+    for j in -1..=1 {
+        for i in -1..=1 {
+            let (cx, cy) = (ix + i as f32, iy + j as f32);
+            let point = hash33(Value::new(cx, cy, 0.0));
+            let dx = i as f32 + point.x - fx;
+            let dy = j as f32 + point.y - fy;
+            let d = (dx * dx + dy * dy).sqrt();
+
+            if d < f1 {
+                f2 = f1;
+                f1 = d;
+                id = point.z;
+            } else if d < f2 {
+                f2 = d;
+            }
+        }
+    }
+
+    Value::new(f1, f2, id)
+}
+
+/// Rotate a vector around the X axis by the given angle in degrees.
+pub fn rotate_x(v: Value, degrees: f32) -> Value {
+    let (s, c) = degrees.to_radians().sin_cos();
+    Value::new(v.x, v.y * c - v.z * s, v.y * s + v.z * c)
+}
+
+/// Rotate a vector around the Y axis by the given angle in degrees.
+pub fn rotate_y(v: Value, degrees: f32) -> Value {
+    let (s, c) = degrees.to_radians().sin_cos();
+    Value::new(v.x * c + v.z * s, v.y, -v.x * s + v.z * c)
+}
+
+/// Rotate a vector around the Z axis by the given angle in degrees.
+pub fn rotate_z(v: Value, degrees: f32) -> Value {
+    let (s, c) = degrees.to_radians().sin_cos();
+    Value::new(v.x * c - v.y * s, v.x * s + v.y * c, v.z)
+}
@@ -0,0 +1,65 @@
+use crate::{CompileVisitor, Context, Execution, Parser, Value, Visitor};
+use rustc_hash::FxHashMap;
+use std::path::PathBuf;
+use theframework::theui::ThePalette;
+
+/// Incrementally parses, compiles and executes source lines against a persistent context.
+pub struct Repl {
+    parser: Parser,
+    visitor: CompileVisitor,
+    pub context: Context,
+    execution: Execution,
+    path: PathBuf,
+}
+
+impl Repl {
+    /// Create a new REPL. Imports are resolved relative to the given path.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            parser: Parser::new(),
+            visitor: CompileVisitor::new(),
+            context: Context::new(FxHashMap::default()),
+            execution: Execution::new(0),
+            path,
+        }
+    }
+
+    /// Parse, compile and execute the given source. Returns the value of the last expression, if any.
+    /// Globals and functions defined by earlier calls stay available.
+    pub fn eval(&mut self, source: &str, palette: &ThePalette) -> Result<Option<Value>, String> {
+        let module = self
+            .parser
+            .compile_module("repl".into(), source.into(), self.path.clone())
+            .map_err(|err| err.to_string())?;
+
+        self.context.globals = module.globals.clone();
+        self.context.program.strings = module.strings.clone();
+
+        // Only the code emitted for this input gets executed
+        let start = self.context.program.body.len();
+        for stmt in &module.stmts {
+            if let Err(err) = stmt.accept(&mut self.visitor, &mut self.context) {
+                self.context.program.body.truncate(start);
+                return Err(err.to_string());
+            }
+        }
+
+        self.context.program.globals = self.context.globals.len();
+        self.context.update_global_names();
+        self.execution.reset(self.context.globals.len());
+
+        let code = self.context.program.body[start..].to_vec();
+        Ok(self.execution.run(&code, &self.context.program, palette))
+    }
+
+    /// The global variables and their current values.
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        self.context
+            .program
+            .global_names
+            .iter()
+            .cloned()
+            .zip(self.execution.globals.iter().copied())
+            .collect()
+    }
+}
@@ -9,6 +9,8 @@ pub enum TokenType {
     RightBrace,
     Comma,
     Dot,
+    DotDot,
+    DotDotEqual,
     Minus,
     Plus,
     Semicolon,
@@ -48,6 +50,7 @@ pub enum TokenType {
     For,
     Fn,
     If,
+    Match,
     Void,
     Or,
     // Print,
@@ -137,6 +140,7 @@ impl Scanner {
         keywords.insert("for", TokenType::For);
         keywords.insert("fn", TokenType::Fn);
         keywords.insert("if", TokenType::If);
+        keywords.insert("match", TokenType::Match);
         keywords.insert("void", TokenType::Void);
         // keywords.insert("print", TokenType::Print);
         keywords.insert("return", TokenType::Return);
@@ -233,6 +237,13 @@ impl Scanner {
             b'>' => self.make_token(TokenType::Greater),
             b'"' => self.string(),
             b'`' => self.string2(),
+            b'.' if self.matches(b'.') => {
+                if self.matches(b'=') {
+                    self.make_token(TokenType::DotDotEqual)
+                } else {
+                    self.make_token(TokenType::DotDot)
+                }
+            }
             b'.' if is_digit(self.peek()) => self.float_with_starting_dot(),
             c if is_digit(c) => self.number(),
             b'.' => self.make_token(TokenType::Dot),
@@ -395,7 +406,7 @@ impl Scanner {
             while is_digit(self.peek()) {
                 lexeme.push(self.advance());
             }
-        } else if self.peek() == b'.' && !is_digit(self.peek_next()) {
+        } else if self.peek() == b'.' && !is_digit(self.peek_next()) && self.peek_next() != b'.' {
             is_float = true;
             lexeme.push(self.advance());
             lexeme.push(b'0');
@@ -0,0 +1,55 @@
+//! 3D signed distance primitives and operators used by the SDF built-ins of the VM.
+//! Distances are returned as scalars, the VM broadcasts them into all components.
+
+use crate::Value;
+
+/// Maximum number of steps of the raymarcher.
+pub const MAX_STEPS: usize = 128;
+
+/// Maximum distance travelled along a ray before it counts as a miss.
+pub const MAX_DISTANCE: f32 = 100.0;
+
+/// Surface hit threshold, scaled by the distance travelled.
+pub const HIT_EPSILON: f32 = 0.0005;
+
+/// Sphere of radius r centered at the origin.
+#[inline]
+pub fn sd_sphere(p: Value, r: f32) -> f32 {
+    p.magnitude() - r
+}
+
+/// Box with the half extents b centered at the origin.
+#[inline]
+pub fn sd_box(p: Value, b: Value) -> f32 {
+    let q = p.map(|v| v.abs()) - b;
+    q.map(|v| v.max(0.0)).magnitude() + q.x.max(q.y.max(q.z)).min(0.0)
+}
+
+/// Torus in the XZ plane, t.x is the major and t.y the minor radius.
+#[inline]
+pub fn sd_torus(p: Value, t: Value) -> f32 {
+    let qx = (p.x * p.x + p.z * p.z).sqrt() - t.x;
+    (qx * qx + p.y * p.y).sqrt() - t.y
+}
+
+/// Polynomial smooth minimum of two distances, k is the blend radius.
+#[inline]
+pub fn op_smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+/// Smoothly subtract the shape b from the shape a.
+#[inline]
+pub fn op_smooth_subtraction(a: f32, b: f32, k: f32) -> f32 {
+    -op_smooth_union(-a, b, k)
+}
+
+/// Smooth intersection of two distances.
+#[inline]
+pub fn op_smooth_intersection(a: f32, b: f32, k: f32) -> f32 {
+    -op_smooth_union(-a, -b, k)
+}
@@ -0,0 +1,199 @@
+//! Render a map into a PNG without opening a window.
+//!
+//! rusterix-render <map.rxm|map.json> <out.png> [options]
+
+use rusterix::{Assets, Headless, HeadlessCamera};
+use std::path::Path;
+use std::process::ExitCode;
+use vek::{Vec2, Vec3};
+
+const USAGE: &str = "Usage: rusterix-render <map.rxm|map.json> <out.png> [options]
+
+Options:
+  --size WxH          Image size in pixels (default 512x512)
+  --camera NAME       2d, iso, firstp or orbit (default: the camera of the map)
+  --center X,Y,Z      Point the 3D camera looks at (default: the center of the map)
+  --position X,Y,Z    Position of the first person camera
+  --offset X,Y        Grid position at the top left corner of a 2D view
+  --grid-size N       Size of a grid cell of a 2D view in pixels
+  --scale N           Half the visible height of an iso view in world units
+  --distance N        Distance of the orbit camera to its center
+  --angles A,E        Azimuth and elevation of the orbit camera in degrees
+  --assets DIR        Directory to collect the textures and tiles from
+  --hour N            Hour of the day used for lighting (default 12)";
+
+#[derive(Default)]
+struct Options {
+    map: String,
+    output: String,
+    size: Option<(usize, usize)>,
+    camera: Option<String>,
+    center: Option<Vec3<f32>>,
+    position: Option<Vec3<f32>>,
+    offset: Option<Vec2<f32>>,
+    grid_size: Option<f32>,
+    scale: Option<f32>,
+    distance: Option<f32>,
+    angles: Option<Vec2<f32>>,
+    assets: Option<String>,
+    hour: Option<f32>,
+}
+
+fn floats(value: &str, count: usize) -> Result<Vec<f32>, String> {
+    let floats = value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Invalid number in '{}'.", value))?;
+    if floats.len() != count {
+        return Err(format!("Expected {} numbers in '{}'.", count, value));
+    }
+    Ok(floats)
+}
+
+fn vec2(value: &str) -> Result<Vec2<f32>, String> {
+    let v = floats(value, 2)?;
+    Ok(Vec2::new(v[0], v[1]))
+}
+
+fn vec3(value: &str) -> Result<Vec3<f32>, String> {
+    let v = floats(value, 3)?;
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut files = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            files.push(arg.clone());
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}.", arg))?;
+        match arg.as_str() {
+            "--size" => {
+                let (w, h) = value
+                    .split_once('x')
+                    .ok_or_else(|| format!("Invalid size '{}'.", value))?;
+                let w = w.parse::<usize>().map_err(|_| "Invalid width.")?;
+                let h = h.parse::<usize>().map_err(|_| "Invalid height.")?;
+                if w == 0 || h == 0 {
+                    return Err("The size must not be empty.".into());
+                }
+                options.size = Some((w, h));
+            }
+            "--camera" => options.camera = Some(value.to_lowercase()),
+            "--center" => options.center = Some(vec3(value)?),
+            "--position" => options.position = Some(vec3(value)?),
+            "--offset" => options.offset = Some(vec2(value)?),
+            "--grid-size" => options.grid_size = Some(floats(value, 1)?[0]),
+            "--scale" => options.scale = Some(floats(value, 1)?[0]),
+            "--distance" => options.distance = Some(floats(value, 1)?[0]),
+            "--angles" => options.angles = Some(vec2(value)?),
+            "--assets" => options.assets = Some(value.clone()),
+            "--hour" => options.hour = Some(floats(value, 1)?[0]),
+            _ => return Err(format!("Unknown option {}.", arg)),
+        }
+    }
+
+    let [map, output] = files.as_slice() else {
+        return Err("Expected a map and an output file.".into());
+    };
+    options.map = map.clone();
+    options.output = output.clone();
+    Ok(options)
+}
+
+/// The camera from the options, the values not given fit the map.
+fn camera(
+    options: &Options,
+    map: &rusterix::Map,
+    width: usize,
+    height: usize,
+) -> Result<HeadlessCamera, String> {
+    let fitted = match options.camera.as_deref() {
+        None => HeadlessCamera::fit(map, width, height),
+        Some("2d") => HeadlessCamera::fit_2d(map, width, height),
+        Some("iso") => HeadlessCamera::fit_iso(map),
+        Some("orbit") | Some("firstp") => HeadlessCamera::fit_orbit(map),
+        Some(name) => return Err(format!("Unknown camera '{}'.", name)),
+    };
+
+    Ok(match fitted {
+        HeadlessCamera::D2 { offset, grid_size } => HeadlessCamera::D2 {
+            offset: options.offset.unwrap_or(offset),
+            grid_size: options.grid_size.unwrap_or(grid_size),
+        },
+        HeadlessCamera::Iso { center, scale } => HeadlessCamera::Iso {
+            center: options.center.unwrap_or(center),
+            scale: options.scale.unwrap_or(scale),
+        },
+        HeadlessCamera::Orbit {
+            center,
+            distance,
+            azimuth,
+            elevation,
+        } => {
+            let center = options.center.unwrap_or(center);
+            if options.camera.as_deref() == Some("firstp") {
+                let position = options
+                    .position
+                    .ok_or("The first person camera needs a --position.")?;
+                HeadlessCamera::FirstP { position, center }
+            } else {
+                let angles = options
+                    .angles
+                    .map(|angles| angles.map(f32::to_radians))
+                    .unwrap_or(Vec2::new(azimuth, elevation));
+                HeadlessCamera::Orbit {
+                    center,
+                    distance: options.distance.unwrap_or(distance),
+                    azimuth: angles.x,
+                    elevation: angles.y,
+                }
+            }
+        }
+        camera => camera,
+    })
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut assets = Assets::default();
+    if let Some(dir) = &options.assets {
+        assets.collect_from_directory(dir.clone());
+    }
+
+    let mut headless = Headless::new(assets);
+    if let Some(hour) = options.hour {
+        headless.hour = hour;
+    }
+
+    let map = headless.load_map(Path::new(&options.map))?;
+    let (width, height) = options.size.unwrap_or((512, 512));
+    let camera = camera(options, &map, width, height)?;
+
+    let texture = headless.render_map(&map, &camera, width, height);
+    Headless::save_png(&texture, Path::new(&options.output))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let result = parse_args(&args).and_then(|options| run(&options));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use crate::collision_world::ChunkCollision;
-use crate::{Assets, BBox, Batch2D, Batch3D, BillboardAnimation, CompiledLight, Pixel, Texture};
-use rusteria::{Program, RenderBuffer, Rusteria};
+use crate::{
+    Assets, BBox, Batch2D, Batch3D, BillboardAnimation, CompiledLight, Map, Pixel, Sector,
+    ShaderMaterial, Texture,
+};
+use rusteria::Program;
 use scenevm::GeoId;
-use std::sync::{Arc, Mutex};
+use theframework::prelude::FxHashSet;
 use uuid::Uuid;
 use vek::{Vec2, Vec3};
 
@@ -19,11 +22,26 @@ pub struct BillboardMetadata {
     pub repeat_mode: scenevm::RepeatMode,
 }
 
+/// The geometry a chunk build produces. Dynamic geometry (sectors with the `dynamic`
+/// property, i.e. movers, and sectors using vertices of soft rigs) is built separately from
+/// the static geometry, so that animating it does not rebuild the whole chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkPart {
+    #[default]
+    All,
+    Static,
+    Dynamic,
+}
+
 /// A chunk of 2D and 3D batches which make up a Scene.
 pub struct Chunk {
     pub origin: Vec2<i32>,
     pub size: i32,
     pub bbox: BBox,
+    /// The level of detail to build, 0 is the full detail. See `ChunkLod`.
+    pub lod: usize,
+    /// The geometry to build.
+    pub part: ChunkPart,
 
     // Geometry
     pub batches2d: Vec<Batch2D>,
@@ -64,6 +82,8 @@ impl Chunk {
             origin,
             size,
             bbox,
+            lod: 0,
+            part: ChunkPart::All,
             batches2d: vec![],
             batches3d_opacity: vec![],
             batches3d: vec![],
@@ -86,49 +106,25 @@ impl Chunk {
             return None;
         };
 
-        let mut rs: Rusteria = Rusteria::default();
-        let _module = match rs.parse_str(code) {
-            Ok(module) => match rs.compile(&module) {
-                Ok(()) => module,
-                Err(e) => {
-                    eprintln!("Error compiling module: {e}");
-                    return None;
-                }
-            },
+        match ShaderMaterial::new(code) {
+            Ok(material) => Some(self.add_shader_material(&material, assets)),
             Err(e) => {
-                eprintln!("Error parsing module: {e}");
-                return None;
+                eprintln!("{e}");
+                None
             }
-        };
-
-        let width = 64;
-        let height = 64;
-
-        let mut texture = None;
-
-        if let Some(shade_index) = rs.context.program.shade_index {
-            let mut rbuffer = Arc::new(Mutex::new(RenderBuffer::new(width, height)));
-            // let t0 = rs.get_time();
-            rs.shade(&mut rbuffer, shade_index, &assets.palette);
-            // let t1 = rs.get_time();
-            // println!("Rendered in {}ms", t1 - t0);
-
-            let b = rbuffer.lock().unwrap().as_rgba_bytes();
-
-            let mut tex = Texture::new(b, width, height);
-            tex.generate_normals(true);
-
-            texture = Some(tex);
         }
+    }
 
+    /// Add the program of a shader material and bake it at the material resolution.
+    pub fn add_shader_material(&mut self, material: &ShaderMaterial, assets: &Assets) -> usize {
         let index = self.shaders.len();
 
         self.shaders_with_opacity
-            .push(rs.context.program.shader_supports_opacity());
-        self.shaders.push(rs.context.program.clone());
-        self.shader_textures.push(texture);
+            .push(material.program.shader_supports_opacity());
+        self.shaders.push(material.program.clone());
+        self.shader_textures.push(material.bake(&assets.palette));
 
-        Some(index)
+        index
     }
 
     /// Sample the baked terrain texture at the given world position
@@ -150,6 +146,16 @@ impl Chunk {
         [0, 0, 0, 0]
     }
 
+    /// True if the chunk builds the geometry of the sector, see `ChunkPart`. The rigged
+    /// vertices are the vertices animated by soft rigs, see `Map::rigged_vertices`.
+    pub fn builds_sector(&self, sector: &Sector, map: &Map, rigged: &FxHashSet<u32>) -> bool {
+        match self.part {
+            ChunkPart::All => true,
+            ChunkPart::Static => !map.is_dynamic_sector(sector, rigged),
+            ChunkPart::Dynamic => map.is_dynamic_sector(sector, rigged),
+        }
+    }
+
     /// Returns the sector occlusion at the given position.
     pub fn get_occlusion(&self, at: Vec2<f32>) -> f32 {
         for (bbox, occlusion) in &self.occluded_sectors {
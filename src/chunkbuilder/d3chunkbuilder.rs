@@ -4,7 +4,8 @@ use crate::chunkbuilder::surface_mesh_builder::{
 use crate::chunkbuilder::terrain_generator::{TerrainConfig, TerrainGenerator};
 use crate::collision_world::{BlockingVolume, DynamicOpening, OpeningType, WalkableFloor};
 use crate::{
-    Assets, Batch3D, Chunk, ChunkBuilder, Item, Map, PixelSource, Value, VertexBlendPreset,
+    Assets, Batch3D, Chunk, ChunkBuilder, ChunkPart, Item, Map, PixelSource, Value,
+    VertexBlendPreset,
 };
 use crate::{BillboardAnimation, GeometrySource, LoopOp, ProfileLoop, RepeatMode, Sector};
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -18,6 +19,10 @@ pub const DEFAULT_TILE_ID: &str = "27826750-a9e7-4346-994b-fb318b238452";
 
 pub struct D3ChunkBuilder {}
 
+/// Sectors smaller than this area (in square map units) are skipped in chunks with a level
+/// of detail of 2 or more.
+const LOD_MIN_SECTOR_AREA: f32 = 0.25;
+
 fn profile_sector_item(map: &Map, profile_id: Uuid, sector_id: u32) -> Option<&Item> {
     let profile_map = map.profiles.get(&profile_id)?;
     profile_map
@@ -386,11 +391,22 @@ impl ChunkBuilder for D3ChunkBuilder {
     ) {
         let mut hidden: FxHashSet<GeoId> = FxHashSet::default();
 
+        // Distant chunks: at level 1 the tile overrides and blends are merged into the sector
+        // material and the feature loops (recesses and reliefs) are dropped, from level 2 on
+        // the walls lose their side bands and small sectors are skipped.
+        let merge_surfaces = chunk.lod >= 1;
+        let simplify_walls = chunk.lod >= 2;
+
+        let rigged = map.rigged_vertices();
+
         // For each surface in the map
         for surface in map.surfaces.values() {
             let Some(sector) = map.find_sector(surface.sector_id) else {
                 continue;
             };
+            if !chunk.builds_sector(sector, map, &rigged) {
+                continue;
+            }
 
             // Check for invalid surface - this shouldn't happen after sanitization,
             // but acts as a safety net. We can't rebuild here since we only have a reference to map.
@@ -419,6 +435,9 @@ impl ChunkBuilder for D3ChunkBuilder {
             if !bbox.intersects(&chunk.bbox) || !chunk.bbox.contains(bbox.center()) {
                 continue;
             }
+            if simplify_walls && bbox.size().x * bbox.size().y < LOD_MIN_SECTOR_AREA {
+                continue;
+            }
 
             // Occlusion data
             let occlusion = sector.properties.get_float_default("occlusion", 1.0);
@@ -476,7 +495,7 @@ impl ChunkBuilder for D3ChunkBuilder {
 
                 // Cut holes in the FRONT cap for recesses/reliefs that extend beyond the front
                 // Note: We check depth, not enabled flag, because depth=0 still needs hole logic
-                if extrude_abs > 1e-6 {
+                if extrude_abs > 1e-6 && !merge_surfaces {
                     for h in &hole_loops {
                         let target = loop_profile_target(h);
                         match h.op {
@@ -524,21 +543,29 @@ impl ChunkBuilder for D3ChunkBuilder {
                     let desired_n = surface.plane.normal;
                     fix_winding(&world_vertices_for_fix, &mut indices, desired_n);
 
-                    let tile_overrides = sector.properties.get("tiles").and_then(|v| {
-                        if let Value::TileOverrides(map) = v {
-                            Some(map)
-                        } else {
-                            None
-                        }
-                    });
-
-                    let blend_overrides = sector.properties.get("blend_tiles").and_then(|v| {
-                        if let Value::BlendOverrides(map) = v {
-                            Some(map)
-                        } else {
-                            None
-                        }
-                    });
+                    let tile_overrides = sector
+                        .properties
+                        .get("tiles")
+                        .and_then(|v| {
+                            if let Value::TileOverrides(map) = v {
+                                Some(map)
+                            } else {
+                                None
+                            }
+                        })
+                        .filter(|_| !merge_surfaces);
+
+                    let blend_overrides = sector
+                        .properties
+                        .get("blend_tiles")
+                        .and_then(|v| {
+                            if let Value::BlendOverrides(map) = v {
+                                Some(map)
+                            } else {
+                                None
+                            }
+                        })
+                        .filter(|_| !merge_surfaces);
 
                     // Get default tile for blending
                     let default_tile_id =
@@ -861,13 +888,13 @@ impl ChunkBuilder for D3ChunkBuilder {
                                     }
                                     LoopOp::Recess { .. } => {
                                         // Always cut a hole for recesses that target the back side
-                                        if to_back {
+                                        if to_back && !merge_surfaces {
                                             back_holes_paths.push(h.path.clone());
                                         }
                                     }
                                     LoopOp::Relief { .. } => {
                                         // Cut a hole for reliefs that target the back side
-                                        if to_back {
+                                        if to_back && !merge_surfaces {
                                             back_holes_paths.push(h.path.clone());
                                         }
                                     }
@@ -900,13 +927,17 @@ impl ChunkBuilder for D3ChunkBuilder {
                                     -surface.plane.normal,
                                 );
 
-                                let tile_overrides = sector.properties.get("tiles").and_then(|v| {
-                                    if let Value::TileOverrides(map) = v {
-                                        Some(map)
-                                    } else {
-                                        None
-                                    }
-                                });
+                                let tile_overrides = sector
+                                    .properties
+                                    .get("tiles")
+                                    .and_then(|v| {
+                                        if let Value::TileOverrides(map) = v {
+                                            Some(map)
+                                        } else {
+                                            None
+                                        }
+                                    })
+                                    .filter(|_| !merge_surfaces);
 
                                 // Apply both blend and tile overrides to back cap in a single pass
                                 let (
@@ -1000,19 +1031,25 @@ impl ChunkBuilder for D3ChunkBuilder {
                             );
                         };
 
-                        // 2) Outer perimeter side band
-                        push_side_band(&outer_loop.path);
+                        if !simplify_walls {
+                            // 2) Outer perimeter side band
+                            push_side_band(&outer_loop.path);
 
-                        // 3) Through-hole tubes for base holes (cutouts + through-recesses)
-                        // Thin edge check automatically handles doors vs windows
-                        for h in &base_holes {
-                            push_side_band(&h.path);
+                            // 3) Through-hole tubes for base holes (cutouts + through-recesses)
+                            // Thin edge check automatically handles doors vs windows
+                            for h in &base_holes {
+                                push_side_band(&h.path);
+                            }
                         }
                     }
                 }
 
                 // 2) FEATURE LOOPS: build caps + jambs using trait-based system
                 for fl in &feature_loops {
+                    // Billboards stay at every level, they fill holes of the caps
+                    if merge_surfaces && !matches!(fl.op, LoopOp::Billboard { .. }) {
+                        continue;
+                    }
                     // Use the new trait-based system for processing feature loops
                     process_feature_loop_with_action(
                         surface, map, sector, chunk, vmchunk, assets, fl,
@@ -1026,21 +1063,29 @@ impl ChunkBuilder for D3ChunkBuilder {
                     let mut indices = indices;
                     fix_winding(&world_vertices_for_fix, &mut indices, surface.plane.normal);
 
-                    let tile_overrides = sector.properties.get("tiles").and_then(|v| {
-                        if let Value::TileOverrides(map) = v {
-                            Some(map)
-                        } else {
-                            None
-                        }
-                    });
-
-                    let blend_overrides = sector.properties.get("blend_tiles").and_then(|v| {
-                        if let Value::BlendOverrides(map) = v {
-                            Some(map)
-                        } else {
-                            None
-                        }
-                    });
+                    let tile_overrides = sector
+                        .properties
+                        .get("tiles")
+                        .and_then(|v| {
+                            if let Value::TileOverrides(map) = v {
+                                Some(map)
+                            } else {
+                                None
+                            }
+                        })
+                        .filter(|_| !merge_surfaces);
+
+                    let blend_overrides = sector
+                        .properties
+                        .get("blend_tiles")
+                        .and_then(|v| {
+                            if let Value::BlendOverrides(map) = v {
+                                Some(map)
+                            } else {
+                                None
+                            }
+                        })
+                        .filter(|_| !merge_surfaces);
 
                     // Get default tile for blending
                     let default_tile_id =
@@ -1216,9 +1261,11 @@ impl ChunkBuilder for D3ChunkBuilder {
             }
         }
 
-        // Generate terrain for this chunk
-        let terrain_counter = chunk.bbox.min.x as u32 * 10000 + chunk.bbox.min.y as u32;
-        generate_terrain(map, assets, chunk, vmchunk, terrain_counter);
+        // Generate terrain for this chunk, the terrain is static
+        if chunk.part != ChunkPart::Dynamic {
+            let terrain_counter = chunk.bbox.min.x as u32 * 10000 + chunk.bbox.min.y as u32;
+            generate_terrain(map, assets, chunk, vmchunk, terrain_counter);
+        }
     }
 
     fn build_collision(
@@ -0,0 +1,154 @@
+use crate::{Assets, Map};
+use rustc_hash::FxHasher;
+use scenevm::Chunk as VMChunk;
+use std::hash::Hasher;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use theframework::prelude::*;
+
+/// The version of the cached chunks, part of the content hash. Increase it when the chunk
+/// builders or the chunk format change so that older chunks are not loaded.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Prebuilt chunk geometry on disk, so that reopening a large region loads its chunks instead
+/// of building them from the map again. Every map has its own directory named by its id,
+/// the files inside are named by a hash of the `CACHE_VERSION`, the content of the map and
+/// the tile list, the chunk coordinate and the level of detail:
+///
+/// ```text
+/// <dir>/<map id>/<content hash>_<x>_<y>_<lod>.bin
+/// ```
+///
+/// Files of older versions of a map are removed once its new content is hashed. Chunks with
+/// billboards are not cached, they are always built.
+#[derive(Debug, Clone)]
+pub struct ChunkCache {
+    dir: PathBuf,
+    // The directory of the current map and the hash of its content
+    content: Option<(PathBuf, u64)>,
+}
+
+impl ChunkCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            content: None,
+        }
+    }
+
+    /// The directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// True if the content of the map is hashed, see `set_content`.
+    pub fn has_content(&self) -> bool {
+        self.content.is_some()
+    }
+
+    /// Forget the hashed content after the map or the assets changed.
+    pub fn invalidate(&mut self) {
+        self.content = None;
+    }
+
+    /// Hash the content of the map and the tile list the chunks are built from and remove
+    /// the files of other versions of the map. The hash is stable between runs and builds,
+    /// the map is hashed as JSON with sorted keys.
+    pub fn set_content(&mut self, map: &Map, assets: &Assets) {
+        let mut hasher = FxHasher::default();
+        hasher.write_u32(CACHE_VERSION);
+        let hashed = serde_json::to_value(map)
+            .and_then(|value| serde_json::to_writer(HashWriter(&mut hasher), &value));
+        if hashed.is_err() {
+            self.content = None;
+            return;
+        }
+        for tile in &assets.tile_list {
+            hasher.write(tile.id.as_bytes());
+        }
+        let hash = hasher.finish();
+        let map_dir = self.dir.join(map.id.to_string());
+
+        // Remove the chunks of the older versions of the map
+        let prefix = format!("{:016x}_", hash);
+        if let Ok(entries) = std::fs::read_dir(&map_dir) {
+            for entry in entries.flatten() {
+                if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        self.content = Some((map_dir, hash));
+    }
+
+    /// Load the chunk at the coordinate and level of detail, if it was stored.
+    pub fn load(&self, coord: (i32, i32), lod: usize) -> Option<VMChunk> {
+        let data = std::fs::read(self.path(coord, lod)?).ok()?;
+        bincode::deserialize(&data).ok()
+    }
+
+    /// Store the chunk at the coordinate and level of detail. Errors are ignored, the chunk
+    /// is built again next time.
+    pub fn store(&self, coord: (i32, i32), lod: usize, chunk: &VMChunk) {
+        let Some(path) = self.path(coord, lod) else {
+            return;
+        };
+        let Ok(data) = bincode::serialize(chunk) else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        // Write to a temporary file first so that readers never see a partial chunk
+        let tmp = path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+        if std::fs::write(&tmp, data).is_ok() && std::fs::rename(&tmp, &path).is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+
+    fn path(&self, coord: (i32, i32), lod: usize) -> Option<PathBuf> {
+        let (map_dir, hash) = self.content.as_ref()?;
+        Some(map_dir.join(format!("{:016x}_{}_{}_{}.bin", hash, coord.0, coord.1, lod)))
+    }
+}
+
+/// Feeds serialized data straight into a hasher.
+struct HashWriter<'a>(&'a mut FxHasher);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content_hash(map: &Map) -> u64 {
+        let dir = std::env::temp_dir().join(format!("chunkcache-{}", Uuid::new_v4()));
+        let mut cache = ChunkCache::new(&dir);
+        cache.set_content(map, &Assets::default());
+        let _ = std::fs::remove_dir_all(&dir);
+        cache.content.unwrap().1
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let mut map = Map::new();
+        let a = map.add_vertex_at(0.0, 0.0);
+        let b = map.add_vertex_at(4.0, 0.0);
+        map.create_linedef(a, b);
+
+        let hash = content_hash(&map);
+        assert_eq!(content_hash(&map.clone()), hash);
+
+        map.update_vertex(b, Vec2::new(8.0, 0.0));
+        assert_ne!(content_hash(&map), hash);
+    }
+}
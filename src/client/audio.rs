@@ -0,0 +1,79 @@
+use crate::{AudioEvent, Entity, SoundEvent};
+use vek::{Vec2, Vec3};
+
+/// Resolves the sounds of the server and the UI against the listener (the player) and
+/// hands them to the audio backend of the host: either through a callback or a queue which
+/// the host drains every frame.
+#[derive(Default)]
+pub struct SoundManager {
+    listener: Vec3<f32>,
+    /// The facing of the listener on the XZ plane, used for panning.
+    orientation: Vec2<f32>,
+    events: Vec<AudioEvent>,
+    callback: Option<Box<dyn FnMut(&AudioEvent) + Send>>,
+}
+
+impl SoundManager {
+    /// Update the listener from the player entity.
+    pub fn set_listener(&mut self, entity: &Entity) {
+        self.listener = entity.position;
+        self.orientation = entity.orientation;
+    }
+
+    /// The stereo pan of a position, -1 left to 1 right of the listener.
+    pub fn pan_at(&self, position: Vec3<f32>) -> f32 {
+        let delta = Vec2::new(position.x - self.listener.x, position.z - self.listener.z);
+        let length = delta.magnitude();
+        if length < f32::EPSILON || self.orientation.magnitude_squared() < f32::EPSILON {
+            return 0.0;
+        }
+        let right = Vec2::new(-self.orientation.y, self.orientation.x).normalized();
+        (delta / length).dot(right).clamp(-1.0, 1.0)
+    }
+
+    /// Attenuate and pan the positional sound, inaudible sounds are dropped.
+    pub fn play(&mut self, sound: SoundEvent) {
+        let volume = sound.volume_at(self.listener);
+        if volume <= 0.0 {
+            return;
+        }
+        let event = AudioEvent {
+            pan: self.pan_at(sound.position),
+            distance: sound.position.distance(self.listener),
+            name: sound.name,
+            position: sound.position,
+            volume,
+        };
+        self.emit(event);
+    }
+
+    /// Play a sound of the UI (i.e. a button click), centered and at the given volume.
+    pub fn play_ui(&mut self, name: &str, volume: f32) {
+        let event = AudioEvent {
+            name: name.to_string(),
+            position: self.listener,
+            volume,
+            distance: 0.0,
+            pan: 0.0,
+        };
+        self.emit(event);
+    }
+
+    fn emit(&mut self, event: AudioEvent) {
+        if let Some(callback) = &mut self.callback {
+            callback(&event);
+        } else {
+            self.events.push(event);
+        }
+    }
+
+    /// Set the callback which receives the sounds instead of the queue.
+    pub fn set_callback(&mut self, callback: impl FnMut(&AudioEvent) + Send + 'static) {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// Get the queued sounds and clear them.
+    pub fn drain(&mut self) -> Vec<AudioEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
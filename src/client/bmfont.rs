@@ -0,0 +1,168 @@
+use crate::Texture;
+use std::path::Path;
+use theframework::prelude::*;
+
+/// A glyph of a bitmap font, in pixels of its page.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BitmapGlyph {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub xoffset: i32,
+    pub yoffset: i32,
+    pub xadvance: i32,
+    pub page: usize,
+}
+
+/// A pre-rendered bitmap font in the text format of BMFont (.fnt plus page images). Small
+/// pixel fonts stay crisp as their glyphs are drawn at integer scales instead of being
+/// rasterized.
+#[derive(Debug, Clone, Default)]
+pub struct BitmapFont {
+    pub line_height: usize,
+    pub base: usize,
+    pub glyphs: FxHashMap<char, BitmapGlyph>,
+    pub kerning: FxHashMap<(char, char), i32>,
+    pub pages: Vec<Texture>,
+}
+
+/// Split a .fnt line into its tag and key=value pairs, values may be quoted.
+fn parse_line(line: &str) -> Option<(&str, FxHashMap<&str, &str>)> {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let mut values = FxHashMap::default();
+    loop {
+        rest = rest.trim_start();
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        values.insert(key.trim(), value);
+        rest = after;
+    }
+    (!tag.is_empty()).then_some((tag, values))
+}
+
+impl BitmapFont {
+    /// The page image files of the .fnt source, in page order.
+    pub fn page_files(fnt: &str) -> Vec<String> {
+        let mut pages: Vec<(usize, String)> = fnt
+            .lines()
+            .filter_map(parse_line)
+            .filter(|(tag, _)| *tag == "page")
+            .filter_map(|(_, values)| {
+                Some((
+                    values.get("id")?.parse().ok()?,
+                    values.get("file")?.to_string(),
+                ))
+            })
+            .collect();
+        pages.sort_by_key(|(id, _)| *id);
+        pages.into_iter().map(|(_, file)| file).collect()
+    }
+
+    /// Parse the .fnt source, the pages are the textures of `page_files`.
+    pub fn from_fnt(fnt: &str, pages: Vec<Texture>) -> Result<Self, String> {
+        let mut font = BitmapFont {
+            pages,
+            ..Default::default()
+        };
+
+        let get = |values: &FxHashMap<&str, &str>, key: &str| -> Result<i32, String> {
+            values
+                .get(key)
+                .ok_or_else(|| format!("Missing '{}'.", key))?
+                .parse::<i32>()
+                .map_err(|err| format!("Invalid '{}': {}", key, err))
+        };
+
+        for (tag, values) in fnt.lines().filter_map(parse_line) {
+            match tag {
+                "common" => {
+                    font.line_height = get(&values, "lineHeight")?.max(1) as usize;
+                    font.base = get(&values, "base")?.max(0) as usize;
+                }
+                "char" => {
+                    let Some(c) = char::from_u32(get(&values, "id")? as u32) else {
+                        continue;
+                    };
+                    let glyph = BitmapGlyph {
+                        x: get(&values, "x")?.max(0) as usize,
+                        y: get(&values, "y")?.max(0) as usize,
+                        width: get(&values, "width")?.max(0) as usize,
+                        height: get(&values, "height")?.max(0) as usize,
+                        xoffset: get(&values, "xoffset")?,
+                        yoffset: get(&values, "yoffset")?,
+                        xadvance: get(&values, "xadvance")?,
+                        page: get(&values, "page").unwrap_or(0).max(0) as usize,
+                    };
+                    if glyph.page >= font.pages.len() {
+                        return Err(format!("Glyph '{}' uses a missing page.", c));
+                    }
+                    font.glyphs.insert(c, glyph);
+                }
+                "kerning" => {
+                    let first = char::from_u32(get(&values, "first")? as u32);
+                    let second = char::from_u32(get(&values, "second")? as u32);
+                    if let (Some(first), Some(second)) = (first, second) {
+                        font.kerning
+                            .insert((first, second), get(&values, "amount")?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if font.line_height == 0 {
+            return Err("Missing the 'common' line.".into());
+        }
+        Ok(font)
+    }
+
+    /// Load the .fnt file and its pages, which are relative to the file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let fnt = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let pages = Self::page_files(&fnt)
+            .iter()
+            .map(|file| {
+                Texture::from_image_safe(dir.join(file).as_path())
+                    .ok_or_else(|| format!("Could not load page '{}'.", file))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_fnt(&fnt, pages)
+    }
+
+    /// The glyph of the character, unknown characters fall back to '?'.
+    pub fn glyph(&self, c: char) -> Option<&BitmapGlyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?'))
+    }
+
+    /// The integer scale which is closest to the given font size.
+    pub fn scale_for(&self, size: f32) -> usize {
+        ((size / self.line_height as f32).round() as usize).max(1)
+    }
+
+    /// The size of the text in pixels at the scale.
+    pub fn text_size(&self, text: &str, scale: usize) -> (usize, usize) {
+        let mut width = 0;
+        let mut prev = None;
+        for c in text.chars() {
+            if let Some(glyph) = self.glyph(c) {
+                if let Some(prev) = prev {
+                    width += self.kerning.get(&(prev, c)).copied().unwrap_or(0);
+                }
+                width += glyph.xadvance;
+            }
+            prev = Some(c);
+        }
+        (width.max(0) as usize * scale, self.line_height * scale)
+    }
+}
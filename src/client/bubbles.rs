@@ -0,0 +1,355 @@
+use crate::{
+    Assets, Map, Pixel, SceneHandler, Texture, Value,
+    client::draw2d::{Draw2D, TheHorizontalAlign, TheVerticalAlign},
+    client::richtext::{RichPart, layout_rich_text, named_color, parse_rich_text},
+    client::widget::FrameTile,
+};
+use fontdue::Font;
+use std::collections::VecDeque;
+use theframework::prelude::*;
+
+/// The look of the speech bubbles of a game widget, read from its `[bubbles]` table. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// The text supports the color tags of the rich text.
+#[derive(Clone)]
+pub struct BubbleStyle {
+    pub font: String,
+    pub font_size: f32,
+    pub max_width: f32,
+    pub padding: f32,
+    pub tail: f32,
+    pub portrait_size: f32,
+    pub height: f32,
+    pub color: Pixel,
+    pub background: Pixel,
+    pub frame: Option<FrameTile>,
+}
+
+impl Default for BubbleStyle {
+    fn default() -> Self {
+        Self {
+            font: String::new(),
+            font_size: 14.0,
+            max_width: 160.0,
+            padding: 6.0,
+            tail: 8.0,
+            portrait_size: 32.0,
+            height: 2.0,
+            color: [32, 32, 32, 255],
+            background: [240, 240, 240, 224],
+            frame: None,
+        }
+    }
+}
+
+impl BubbleStyle {
+    /// Read the style from the `[bubbles]` table of a widget, missing values use the defaults.
+    pub fn from_toml(toml: &str, assets: &Assets) -> Self {
+        let mut style = Self::default();
+        let Ok(table) = toml.parse::<toml::Table>() else {
+            return style;
+        };
+        let Some(bubbles) = table.get("bubbles").and_then(toml::Value::as_table) else {
+            return style;
+        };
+
+        if let Some(font) = bubbles.get("font").and_then(toml::Value::as_str) {
+            style.font = font.to_string();
+        }
+        for (key, value) in [
+            ("font_size", &mut style.font_size),
+            ("max_width", &mut style.max_width),
+            ("padding", &mut style.padding),
+            ("tail", &mut style.tail),
+            ("portrait_size", &mut style.portrait_size),
+            ("height", &mut style.height),
+        ] {
+            if let Some(v) = bubbles.get(key) {
+                if let Some(v) = v.as_float() {
+                    *value = (v as f32).max(0.0);
+                } else if let Some(v) = v.as_integer() {
+                    *value = (v as f32).max(0.0);
+                }
+            }
+        }
+        for (key, color) in [
+            ("color", &mut style.color),
+            ("background", &mut style.background),
+        ] {
+            if let Some(c) = bubbles
+                .get(key)
+                .and_then(toml::Value::as_str)
+                .and_then(named_color)
+            {
+                *color = c;
+            }
+        }
+        style.frame = FrameTile::from_ui(bubbles, assets);
+        style
+    }
+}
+
+/// Who a bubble belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BubbleSpeaker {
+    Entity(u32),
+    Item(u32),
+}
+
+impl BubbleSpeaker {
+    /// The world position of the speaker on the map.
+    pub fn position(&self, map: &Map, scene_handler: &SceneHandler) -> Option<Vec3<f32>> {
+        match self {
+            BubbleSpeaker::Entity(id) => map
+                .entities
+                .iter()
+                .find(|entity| entity.id == *id)
+                .map(|entity| scene_handler.entity_position(entity)),
+            BubbleSpeaker::Item(id) => map
+                .items
+                .iter()
+                .find(|item| item.id == *id)
+                .map(|item| item.position),
+        }
+    }
+
+    /// The portrait tile of an entity speaker, from its `portrait` attribute.
+    pub fn portrait<'a>(&self, map: &Map, assets: &'a Assets) -> Option<&'a Texture> {
+        let BubbleSpeaker::Entity(id) = self else {
+            return None;
+        };
+        let entity = map.entities.iter().find(|entity| entity.id == *id)?;
+        match entity.attributes.get("portrait") {
+            Some(Value::Source(source)) => source
+                .tile_from_tile_list(assets)
+                .and_then(|tile| tile.textures.first()),
+            _ => None,
+        }
+    }
+}
+
+/// A message waiting for or showing in its bubble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bubble {
+    pub text: String,
+    /// Seconds the bubble is shown, longer texts stay longer.
+    pub duration: f32,
+    pub age: f32,
+}
+
+/// The speech bubbles above the entities and items which send messages. Every speaker shows
+/// one bubble at a time, further messages queue up and follow when it expires.
+pub struct SpeechBubbles {
+    /// Seconds every bubble is shown at least.
+    pub duration: f32,
+    /// Additional seconds per character of the text.
+    pub per_char: f32,
+    /// Messages a speaker can queue, the oldest waiting ones are dropped.
+    pub max_queue: usize,
+    queues: FxHashMap<BubbleSpeaker, VecDeque<Bubble>>,
+}
+
+impl Default for SpeechBubbles {
+    fn default() -> Self {
+        Self {
+            duration: 2.0,
+            per_char: 0.05,
+            max_queue: 4,
+            queues: FxHashMap::default(),
+        }
+    }
+}
+
+impl SpeechBubbles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.queues.clear();
+    }
+
+    /// Queue a message of the speaker.
+    pub fn push(&mut self, speaker: BubbleSpeaker, text: String) {
+        let duration = self.duration + text.chars().count() as f32 * self.per_char;
+        let queue = self.queues.entry(speaker).or_default();
+        queue.push_back(Bubble {
+            text,
+            duration,
+            age: 0.0,
+        });
+        // Keep the visible bubble, drop the oldest waiting ones
+        while queue.len() > self.max_queue.max(1) {
+            queue.remove(1);
+        }
+    }
+
+    /// Queue the messages of the server which have an item or entity as sender, the item
+    /// takes precedence.
+    pub fn add_messages(&mut self, messages: &[crate::server::Message]) {
+        for (sender_entity, sender_item, _, message, _category) in messages {
+            let speaker = match (sender_item, sender_entity) {
+                (Some(item), _) => BubbleSpeaker::Item(*item),
+                (None, Some(entity)) => BubbleSpeaker::Entity(*entity),
+                (None, None) => continue,
+            };
+            self.push(speaker, message.clone());
+        }
+    }
+
+    /// Advance the visible bubbles by dt seconds, expired bubbles make room for the next
+    /// message of their speaker.
+    pub fn update(&mut self, dt: f32) {
+        for queue in self.queues.values_mut() {
+            if let Some(bubble) = queue.front_mut() {
+                bubble.age += dt;
+                if bubble.age >= bubble.duration {
+                    queue.pop_front();
+                }
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+    }
+
+    /// The visible bubble of every speaker.
+    pub fn visible(&self) -> impl Iterator<Item = (&BubbleSpeaker, &Bubble)> {
+        self.queues
+            .iter()
+            .filter_map(|(speaker, queue)| queue.front().map(|bubble| (speaker, bubble)))
+    }
+}
+
+/// Draw a speech bubble with its tail pointing at the anchor, the screen position above the
+/// speaker. The bubble is kept inside of the frame.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_bubble(
+    draw2d: &Draw2D,
+    pixels: &mut [u8],
+    width: usize,
+    height: usize,
+    style: &BubbleStyle,
+    font: &Font,
+    text: &str,
+    portrait: Option<&Texture>,
+    anchor: Vec2<f32>,
+) {
+    let size = style.font_size;
+    let parts = parse_rich_text(text, style.color);
+    let lines = layout_rich_text(&parts, style.max_width.max(size), size, |text| {
+        draw2d.get_text_size(font, size, text).0 as f32
+    });
+    let line_height = (size * 1.2).ceil();
+    let text_width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+    let text_height = lines.len() as f32 * line_height;
+
+    let portrait = portrait.filter(|_| style.portrait_size > 0.0);
+    let portrait_width = if portrait.is_some() {
+        style.portrait_size + style.padding
+    } else {
+        0.0
+    };
+
+    let pad = style.padding;
+    let w = (text_width + portrait_width + pad * 2.0).ceil();
+    let h = (text_height.max(portrait.map_or(0.0, |_| style.portrait_size)) + pad * 2.0).ceil();
+    if w > width as f32 || h + style.tail > height as f32 {
+        return;
+    }
+    let x = (anchor.x - w / 2.0).clamp(0.0, width as f32 - w).floor();
+    let y = (anchor.y - style.tail - h)
+        .clamp(0.0, height as f32 - h)
+        .floor();
+    let safe = (0, 0, width as isize, height as isize);
+
+    // The background, the tail takes the color of the center of the frame
+    let tail_color = match &style.frame {
+        Some(frame) => {
+            let texture = &frame.texture;
+            draw2d.blend_nine_slice(
+                pixels,
+                &(x as usize, y as usize, w as usize, h as usize),
+                width,
+                &texture.data,
+                &(texture.width, texture.height),
+                &frame.border,
+                frame.scale,
+            );
+            let i = (texture.height / 2 * texture.width + texture.width / 2) * 4;
+            texture
+                .data
+                .get(i..i + 4)
+                .map(|c| [c[0], c[1], c[2], c[3]])
+                .unwrap_or(style.background)
+        }
+        None => {
+            draw2d.blend_rect_safe(
+                pixels,
+                &(x as isize, y as isize, w as isize, h as isize),
+                width,
+                &style.background,
+                &safe,
+            );
+            style.background
+        }
+    };
+
+    // The tail narrows from the bottom of the bubble towards the speaker
+    let length = (anchor.y - (y + h)).min(style.tail);
+    if length >= 1.0 {
+        let half = style.tail * 0.6;
+        let base = anchor.x.clamp(x + half, x + w - half);
+        draw2d.blend_polygon_safe(
+            pixels,
+            &[
+                (base - half, y + h),
+                (base + half, y + h),
+                (anchor.x, y + h + length),
+            ],
+            width,
+            &tail_color,
+            &safe,
+        );
+    }
+
+    if let Some(texture) = portrait {
+        draw2d.blend_scale_chunk(
+            pixels,
+            &(
+                (x + pad) as usize,
+                (y + pad) as usize,
+                style.portrait_size as usize,
+                style.portrait_size as usize,
+            ),
+            width,
+            &texture.data,
+            &(texture.width, texture.height),
+        );
+    }
+
+    let text_x = x + pad + portrait_width;
+    for (i, line) in lines.iter().enumerate() {
+        let line_y = y + pad + i as f32 * line_height;
+        for run in &line.runs {
+            if let RichPart::Text(text, color) = &run.part {
+                draw2d.text_rect_blend_safe(
+                    pixels,
+                    &(
+                        (text_x + run.x) as isize,
+                        line_y as isize,
+                        run.width.ceil() as isize + 1,
+                        line_height as isize,
+                    ),
+                    width,
+                    font,
+                    size,
+                    text,
+                    color,
+                    TheHorizontalAlign::Left,
+                    TheVerticalAlign::Center,
+                    &safe,
+                );
+            }
+        }
+    }
+}
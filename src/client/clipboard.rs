@@ -0,0 +1,40 @@
+/// The clipboard of the text inputs. The host provides the system clipboard through
+/// callbacks, without them the text stays inside of the client.
+#[derive(Default)]
+pub struct Clipboard {
+    get: Option<Box<dyn FnMut() -> Option<String> + Send>>,
+    set: Option<Box<dyn FnMut(&str) + Send>>,
+    local: String,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the callbacks reading and writing the system clipboard.
+    pub fn set_callbacks(
+        &mut self,
+        get: impl FnMut() -> Option<String> + Send + 'static,
+        set: impl FnMut(&str) + Send + 'static,
+    ) {
+        self.get = Some(Box::new(get));
+        self.set = Some(Box::new(set));
+    }
+
+    /// Copy the text into the clipboard.
+    pub fn set(&mut self, text: &str) {
+        match &mut self.set {
+            Some(set) => set(text),
+            None => self.local = text.to_string(),
+        }
+    }
+
+    /// The text of the clipboard, empty if it has none.
+    pub fn get(&mut self) -> String {
+        match &mut self.get {
+            Some(get) => get().unwrap_or_default(),
+            None => self.local.clone(),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::WeatherState;
 use theframework::prelude::*;
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
@@ -59,6 +60,17 @@ impl Daylight {
         )
     }
 
+    /// Computes the current sky color tinted by the weather.
+    pub fn daylight_with_weather(
+        &self,
+        time: i32,
+        min_bright: f32,
+        max_bright: f32,
+        weather: &WeatherState,
+    ) -> Vec3<f32> {
+        self.daylight(time, min_bright, max_bright) * Vec3::from(weather.tint())
+    }
+
     /// Returns the intensity of daylight at a given time (0.0 = night, 1.0 = full daylight).
     pub fn daylight_intensity(&self, time: i32) -> f32 {
         let minutes = time;
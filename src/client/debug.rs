@@ -0,0 +1,220 @@
+use crate::{
+    Pixel,
+    client::draw2d::{Draw2D, TheHorizontalAlign, TheVerticalAlign},
+};
+use fontdue::Font;
+use instant::Instant;
+use std::collections::VecDeque;
+use theframework::prelude::*;
+
+/// Frames kept for the frame rate and the frame time graph.
+const HISTORY: usize = 120;
+
+/// The geometry of the game widgets, see `GameWidget::scene_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    /// Chunks uploaded to the scene.
+    pub chunks: usize,
+    /// Chunks still waiting to be built.
+    pub pending_chunks: usize,
+    /// Polygon batches of the 3D geometry of the chunks.
+    pub batches: usize,
+    pub triangles: usize,
+    pub billboards: usize,
+}
+
+impl std::ops::AddAssign for SceneStats {
+    fn add_assign(&mut self, other: Self) {
+        self.chunks += other.chunks;
+        self.pending_chunks += other.pending_chunks;
+        self.batches += other.batches;
+        self.triangles += other.triangles;
+        self.billboards += other.billboards;
+    }
+}
+
+/// The time of the phases of a frame in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameTimes {
+    /// Applying the entities and building the dynamic geometry.
+    pub build: f32,
+    /// Rendering the game widgets.
+    pub raster: f32,
+    /// Drawing the screen widgets on top.
+    pub ui: f32,
+}
+
+/// The performance overlay of the client, configured in the `[debug]` table of the game
+/// config. See `src/client/CONFIG.md` for an example.
+pub struct DebugOverlay {
+    pub visible: bool,
+    pub key: String,
+    pub font: Option<Font>,
+    pub font_size: f32,
+
+    pub times: FrameTimes,
+    pub scene: SceneStats,
+    pub entities: usize,
+    pub items: usize,
+
+    // Durations of the last frames in milliseconds
+    frames: VecDeque<f32>,
+    last_frame: Option<Instant>,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            key: "f3".into(),
+            font: None,
+            font_size: 12.0,
+            times: FrameTimes::default(),
+            scene: SceneStats::default(),
+            entities: 0,
+            items: 0,
+            frames: VecDeque::with_capacity(HISTORY),
+            last_frame: None,
+        }
+    }
+}
+
+impl DebugOverlay {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.frames.clear();
+        self.last_frame = None;
+    }
+
+    /// Start a frame, measures the time since the last one.
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            if self.frames.len() == HISTORY {
+                self.frames.pop_front();
+            }
+            self.frames
+                .push_back(now.duration_since(last).as_secs_f32() * 1000.0);
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Record the phase times of the frame, they are smoothed to stay readable.
+    pub fn record(&mut self, times: FrameTimes) {
+        let t = 0.1;
+        self.times.build += (times.build - self.times.build) * t;
+        self.times.raster += (times.raster - self.times.raster) * t;
+        self.times.ui += (times.ui - self.times.ui) * t;
+    }
+
+    /// The average frame rate of the last frames.
+    pub fn fps(&self) -> f32 {
+        let total: f32 = self.frames.iter().sum();
+        if total > 0.0 {
+            self.frames.len() as f32 * 1000.0 / total
+        } else {
+            0.0
+        }
+    }
+
+    /// The text lines of the overlay.
+    pub fn lines(&self) -> Vec<String> {
+        let frame = self.frames.back().copied().unwrap_or_default();
+        let worst = self.frames.iter().copied().fold(0.0, f32::max);
+        vec![
+            format!("FPS {:.1}  {:.2} ms (max {:.2})", self.fps(), frame, worst),
+            format!(
+                "Build {:.2}  Raster {:.2}  UI {:.2} ms",
+                self.times.build, self.times.raster, self.times.ui
+            ),
+            format!(
+                "Chunks {} ({} pending)",
+                self.scene.chunks, self.scene.pending_chunks
+            ),
+            format!(
+                "Batches {}  Triangles {}",
+                self.scene.batches, self.scene.triangles
+            ),
+            format!(
+                "Entities {}  Items {}  Billboards {}",
+                self.entities, self.items, self.scene.billboards
+            ),
+        ]
+    }
+
+    /// Draw the overlay into the top left corner of the buffer, with a graph of the last
+    /// frame times below the text. Frames over the 60 fps budget show in red.
+    pub fn draw(&self, buffer: &mut TheRGBABuffer, draw2d: &Draw2D) {
+        if !self.visible {
+            return;
+        }
+        let Some(font) = &self.font else {
+            return;
+        };
+        let width = buffer.dim().width as isize;
+        let height = buffer.dim().height as isize;
+        let stride = buffer.stride();
+        let safe = (0, 0, width, height);
+
+        let lines = self.lines();
+        let line_height = (self.font_size * 1.3).ceil() as isize;
+        let pad = 6;
+        let text_width = lines
+            .iter()
+            .map(|line| draw2d.get_text_size(font, self.font_size, line).0 as isize)
+            .max()
+            .unwrap_or(0);
+        let graph_height = 32;
+        let w = text_width.max(HISTORY as isize) + pad * 2;
+        let h = lines.len() as isize * line_height + graph_height + pad * 3;
+
+        let background: Pixel = [0, 0, 0, 176];
+        let color: Pixel = [230, 230, 230, 255];
+        draw2d.blend_rect_safe(
+            buffer.pixels_mut(),
+            &(4, 4, w, h),
+            stride,
+            &background,
+            &safe,
+        );
+
+        for (i, line) in lines.iter().enumerate() {
+            draw2d.text_rect_blend_safe(
+                buffer.pixels_mut(),
+                &(
+                    4 + pad,
+                    4 + pad + i as isize * line_height,
+                    text_width + 1,
+                    line_height,
+                ),
+                stride,
+                font,
+                self.font_size,
+                line,
+                &color,
+                TheHorizontalAlign::Left,
+                TheVerticalAlign::Center,
+                &safe,
+            );
+        }
+
+        // One bar per frame, the graph spans 0 to 33 ms
+        let budget = 1000.0 / 60.0;
+        let graph_y = 4 + pad * 2 + lines.len() as isize * line_height;
+        for (i, ms) in self.frames.iter().enumerate() {
+            let bar = ((ms / (budget * 2.0)).min(1.0) * graph_height as f32).ceil() as isize;
+            let color: Pixel = if *ms > budget {
+                [230, 80, 60, 255]
+            } else {
+                [90, 200, 90, 255]
+            };
+            draw2d.blend_rect_safe(
+                buffer.pixels_mut(),
+                &(4 + pad + i as isize, graph_y + graph_height - bar, 1, bar),
+                stride,
+                &color,
+                &safe,
+            );
+        }
+    }
+}
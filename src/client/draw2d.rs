@@ -1,3 +1,4 @@
+use crate::BitmapFont;
 use fontdue::Font;
 use fontdue::layout::{
     CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, TextStyle, VerticalAlign,
@@ -1318,6 +1319,166 @@ impl Draw2D {
         }
     }
 
+    /// Draws the text with a bitmap font at an integer scale and blends it onto the frame,
+    /// tinted by the color. Aligned like `text_rect_blend_safe`, only pixels inside the safe
+    /// rect are drawn.
+    #[allow(clippy::too_many_arguments)]
+    pub fn bitmap_text_rect_blend_safe(
+        &self,
+        frame: &mut [u8],
+        rect: &(isize, isize, isize, isize),
+        stride: usize,
+        font: &BitmapFont,
+        scale: usize,
+        text: &str,
+        color: &[u8; 4],
+        halign: TheHorizontalAlign,
+        valign: TheVerticalAlign,
+        safe_rect: &(isize, isize, isize, isize),
+    ) {
+        let scale = scale.max(1);
+        let (width, height) = font.text_size(text, scale);
+        let mut x = match halign {
+            TheHorizontalAlign::Left => rect.0,
+            TheHorizontalAlign::Center => rect.0 + (rect.2 - width as isize) / 2,
+            TheHorizontalAlign::Right => rect.0 + rect.2 - width as isize,
+        };
+        let y = match valign {
+            TheVerticalAlign::Top => rect.1,
+            TheVerticalAlign::Center => rect.1 + (rect.3 - height as isize) / 2,
+            TheVerticalAlign::Bottom => rect.1 + rect.3 - height as isize,
+        };
+
+        let mut prev = None;
+        for c in text.chars() {
+            let Some(glyph) = font.glyph(c) else {
+                prev = Some(c);
+                continue;
+            };
+            if let Some(prev) = prev {
+                x += font.kerning.get(&(prev, c)).copied().unwrap_or(0) as isize * scale as isize;
+            }
+            prev = Some(c);
+
+            let page = &font.pages[glyph.page];
+            let gx = x + glyph.xoffset as isize * scale as isize;
+            let gy = y + glyph.yoffset as isize * scale as isize;
+            for sy in 0..glyph.height {
+                for sx in 0..glyph.width {
+                    let (px, py) = (glyph.x + sx, glyph.y + sy);
+                    if px >= page.width || py >= page.height {
+                        continue;
+                    }
+                    let s = (px + py * page.width) * 4;
+                    let alpha = page.data[s + 3] as f32 / 255.0 * color[3] as f32 / 255.0;
+                    if alpha <= 0.0 {
+                        continue;
+                    }
+                    // Tint the glyph by the color
+                    let tinted = [
+                        (page.data[s] as u16 * color[0] as u16 / 255) as u8,
+                        (page.data[s + 1] as u16 * color[1] as u16 / 255) as u8,
+                        (page.data[s + 2] as u16 * color[2] as u16 / 255) as u8,
+                        255,
+                    ];
+                    for dy in 0..scale as isize {
+                        for dx in 0..scale as isize {
+                            let tx = gx + sx as isize * scale as isize + dx;
+                            let ty = gy + sy as isize * scale as isize + dy;
+                            if tx < safe_rect.0
+                                || ty < safe_rect.1
+                                || tx >= safe_rect.0 + safe_rect.2
+                                || ty >= safe_rect.1 + safe_rect.3
+                            {
+                                continue;
+                            }
+                            let d = (tx as usize + ty as usize * stride) * 4;
+                            if d + 4 > frame.len() {
+                                continue;
+                            }
+                            let background = &[frame[d], frame[d + 1], frame[d + 2], frame[d + 3]];
+                            frame[d..d + 4]
+                                .copy_from_slice(&self.mix_color(background, &tinted, alpha));
+                        }
+                    }
+                }
+            }
+            x += glyph.xadvance as isize * scale as isize;
+        }
+    }
+
+    /// Draw a 9-slice of the source into the rect and blend it onto the destination. The
+    /// corners of the given border size (left, top, right, bottom in source pixels) keep their
+    /// size scaled by the integer scale, the edges only stretch along their side and the center
+    /// fills the rest. This keeps pixel-art borders crisp at any panel size.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blend_nine_slice(
+        &self,
+        frame: &mut [u8],
+        rect: &(usize, usize, usize, usize),
+        stride: usize,
+        source_frame: &[u8],
+        source_size: &(usize, usize),
+        border: &(usize, usize, usize, usize),
+        scale: usize,
+    ) {
+        let scale = scale.max(1);
+        let (left, top, right, bottom) = *border;
+
+        // Map a destination offset to the source along one axis
+        fn map_axis(
+            d: usize,
+            dest: usize,
+            source: usize,
+            start: usize,
+            end: usize,
+            scale: usize,
+        ) -> usize {
+            let start_d = start * scale;
+            let end_d = end * scale;
+            let s = if d < start_d {
+                d / scale
+            } else if d + end_d >= dest {
+                source.saturating_sub(end) + (d + end_d - dest) / scale
+            } else {
+                let center_d = dest.saturating_sub(start_d + end_d).max(1);
+                let center_s = source.saturating_sub(start + end);
+                start + (d - start_d) * center_s / center_d
+            };
+            s.min(source.saturating_sub(1))
+        }
+
+        if source_size.0 == 0 || source_size.1 == 0 {
+            return;
+        }
+
+        for dy in 0..rect.3 {
+            let sy = map_axis(dy, rect.3, source_size.1, top, bottom, scale);
+            for dx in 0..rect.2 {
+                let sx = map_axis(dx, rect.2, source_size.0, left, right, scale);
+
+                let d = (rect.0 + dx) * 4 + (rect.1 + dy) * stride * 4;
+                let s = (sx + sy * source_size.0) * 4;
+                if d + 4 > frame.len() || s + 4 > source_frame.len() {
+                    continue;
+                }
+
+                let color = &[
+                    source_frame[s],
+                    source_frame[s + 1],
+                    source_frame[s + 2],
+                    source_frame[s + 3],
+                ];
+                let background = &[frame[d], frame[d + 1], frame[d + 2], frame[d + 3]];
+                frame[d..d + 4].copy_from_slice(&self.mix_color(
+                    background,
+                    color,
+                    (color[3] as f32) / 255.0,
+                ));
+            }
+        }
+    }
+
     /// Scale a chunk to the destination size with linear interpolation and blend onto destination
     pub fn blend_scale_chunk_linear(
         &self,
@@ -1365,6 +1526,161 @@ impl Draw2D {
         }
     }
 
+    /// Blends an anti-aliased filled circle, clipped to the safe rect
+    pub fn blend_circle_safe(
+        &self,
+        frame: &mut [u8],
+        center: &(f32, f32),
+        radius: f32,
+        stride: usize,
+        color: &[u8; 4],
+        safe_rect: &(isize, isize, isize, isize),
+    ) {
+        let bounds = (
+            center.0 - radius,
+            center.1 - radius,
+            center.0 + radius,
+            center.1 + radius,
+        );
+        self.blend_sdf_safe(frame, &bounds, stride, color, safe_rect, |x, y| {
+            self.length((x - center.0, y - center.1)) - radius
+        });
+    }
+
+    /// Blends an anti-aliased filled rect with rounded corners, clipped to the safe rect
+    pub fn blend_rounded_rect_safe(
+        &self,
+        frame: &mut [u8],
+        rect: &(f32, f32, f32, f32),
+        radius: f32,
+        stride: usize,
+        color: &[u8; 4],
+        safe_rect: &(isize, isize, isize, isize),
+    ) {
+        let half = (rect.2 / 2.0, rect.3 / 2.0);
+        let center = (rect.0 + half.0, rect.1 + half.1);
+        let radius = radius.clamp(0.0, half.0.min(half.1));
+        let bounds = (rect.0, rect.1, rect.0 + rect.2, rect.1 + rect.3);
+        self.blend_sdf_safe(frame, &bounds, stride, color, safe_rect, |x, y| {
+            let q = (
+                (x - center.0).abs() - half.0 + radius,
+                (y - center.1).abs() - half.1 + radius,
+            );
+            q.0.max(q.1).min(0.0) + self.length((q.0.max(0.0), q.1.max(0.0))) - radius
+        });
+    }
+
+    /// Blends an anti-aliased filled polygon, clipped to the safe rect. The points can be in
+    /// either winding order, self intersecting polygons are filled with the even-odd rule.
+    pub fn blend_polygon_safe(
+        &self,
+        frame: &mut [u8],
+        points: &[(f32, f32)],
+        stride: usize,
+        color: &[u8; 4],
+        safe_rect: &(isize, isize, isize, isize),
+    ) {
+        if points.len() < 3 {
+            return;
+        }
+        let mut bounds = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for p in points {
+            bounds = (
+                bounds.0.min(p.0),
+                bounds.1.min(p.1),
+                bounds.2.max(p.0),
+                bounds.3.max(p.1),
+            );
+        }
+        self.blend_sdf_safe(frame, &bounds, stride, color, safe_rect, |x, y| {
+            // The distance to the nearest edge, negative inside
+            let mut d = f32::MAX;
+            let mut inside = false;
+            let mut j = points.len() - 1;
+            for i in 0..points.len() {
+                let (a, b) = (points[j], points[i]);
+                d = d.min(self.segment_distance((x, y), a, b));
+                if (b.1 > y) != (a.1 > y) && x < (a.0 - b.0) * (y - b.1) / (a.1 - b.1) + b.0 {
+                    inside = !inside;
+                }
+                j = i;
+            }
+            if inside { -d } else { d }
+        });
+    }
+
+    /// Blends an anti-aliased line of the given width with round caps, clipped to the safe
+    /// rect
+    #[allow(clippy::too_many_arguments)]
+    pub fn blend_line_safe(
+        &self,
+        frame: &mut [u8],
+        from: &(f32, f32),
+        to: &(f32, f32),
+        width: f32,
+        stride: usize,
+        color: &[u8; 4],
+        safe_rect: &(isize, isize, isize, isize),
+    ) {
+        let half = width.max(1.0) / 2.0;
+        let bounds = (
+            from.0.min(to.0) - half,
+            from.1.min(to.1) - half,
+            from.0.max(to.0) + half,
+            from.1.max(to.1) + half,
+        );
+        self.blend_sdf_safe(frame, &bounds, stride, color, safe_rect, |x, y| {
+            self.segment_distance((x, y), *from, *to) - half
+        });
+    }
+
+    /// Blends the color into the pixels inside of the bounds (min x, min y, max x, max y)
+    /// by their coverage of the shape. The SDF is sampled at the pixel centers and is
+    /// negative inside of the shape.
+    fn blend_sdf_safe(
+        &self,
+        frame: &mut [u8],
+        bounds: &(f32, f32, f32, f32),
+        stride: usize,
+        color: &[u8; 4],
+        safe_rect: &(isize, isize, isize, isize),
+        sdf: impl Fn(f32, f32) -> f32,
+    ) {
+        let x0 = (bounds.0.floor() as isize - 1).max(safe_rect.0);
+        let y0 = (bounds.1.floor() as isize - 1).max(safe_rect.1);
+        let x1 = (bounds.2.ceil() as isize + 1).min(safe_rect.0 + safe_rect.2);
+        let y1 = (bounds.3.ceil() as isize + 1).min(safe_rect.1 + safe_rect.3);
+        let alpha = color[3] as f32 / 255.0;
+
+        for y in y0.max(0)..y1 {
+            for x in x0.max(0)..x1 {
+                let t = (0.5 - sdf(x as f32 + 0.5, y as f32 + 0.5)).clamp(0.0, 1.0);
+                if t <= 0.0 {
+                    continue;
+                }
+                let i = x as usize * 4 + y as usize * stride * 4;
+                if i + 4 > frame.len() {
+                    continue;
+                }
+                let background = &[frame[i], frame[i + 1], frame[i + 2], frame[i + 3]];
+                frame[i..i + 4].copy_from_slice(&self.mix_color(background, color, t * alpha));
+            }
+        }
+    }
+
+    /// The distance of p to the line segment from a to b
+    fn segment_distance(&self, p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+        let pa = (p.0 - a.0, p.1 - a.1);
+        let ba = (b.0 - a.0, b.1 - a.1);
+        let len = ba.0 * ba.0 + ba.1 * ba.1;
+        let h = if len > 0.0 {
+            ((pa.0 * ba.0 + pa.1 * ba.1) / len).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.length((pa.0 - ba.0 * h, pa.1 - ba.1 * h))
+    }
+
     /// The fill mask for an SDF distance
     fn fill_mask(&self, dist: f32) -> f32 {
         (-dist).clamp(0.0, 1.0)
@@ -0,0 +1,191 @@
+use crate::{
+    CombatEvent, CombatEventKind, FloatText, FloatTextStyle, Pixel, client::richtext::named_color,
+};
+use theframework::prelude::*;
+
+/// The look of the floating texts of a game widget, read from its `[float_text]` table. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// Critical hits are drawn larger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatTextSettings {
+    pub font: String,
+    pub font_size: f32,
+    pub rise: f32,
+    pub height: f32,
+    pub colors: FxHashMap<FloatTextStyle, Pixel>,
+}
+
+impl Default for FloatTextSettings {
+    fn default() -> Self {
+        let colors = [
+            (FloatTextStyle::Info, [255, 255, 255, 255]),
+            (FloatTextStyle::Damage, [255, 80, 80, 255]),
+            (FloatTextStyle::Critical, [255, 160, 32, 255]),
+            (FloatTextStyle::Heal, [80, 220, 80, 255]),
+            (FloatTextStyle::Miss, [170, 170, 170, 255]),
+            (FloatTextStyle::Status, [192, 128, 255, 255]),
+        ];
+        Self {
+            font: String::new(),
+            font_size: 16.0,
+            rise: 32.0,
+            height: 1.5,
+            colors: colors.into_iter().collect(),
+        }
+    }
+}
+
+impl FloatTextSettings {
+    /// Read the settings from the `[float_text]` table of a widget, missing values use the
+    /// defaults.
+    pub fn from_toml(toml: &str) -> Self {
+        let mut settings = Self::default();
+        let Ok(table) = toml.parse::<toml::Table>() else {
+            return settings;
+        };
+        let Some(float_text) = table.get("float_text").and_then(toml::Value::as_table) else {
+            return settings;
+        };
+
+        if let Some(font) = float_text.get("font").and_then(toml::Value::as_str) {
+            settings.font = font.to_string();
+        }
+        for (key, value) in [
+            ("font_size", &mut settings.font_size),
+            ("rise", &mut settings.rise),
+            ("height", &mut settings.height),
+        ] {
+            if let Some(v) = float_text.get(key).and_then(toml::Value::as_float) {
+                *value = v as f32;
+            }
+        }
+        for style in FloatTextStyle::ALL {
+            if let Some(color) = float_text
+                .get(style.name())
+                .and_then(toml::Value::as_str)
+                .and_then(named_color)
+            {
+                settings.colors.insert(style, color);
+            }
+        }
+        settings
+    }
+
+    /// The font size of the style, critical hits pop out.
+    pub fn size_of(&self, style: FloatTextStyle) -> f32 {
+        if style == FloatTextStyle::Critical {
+            self.font_size * 1.4
+        } else {
+            self.font_size
+        }
+    }
+}
+
+/// A floating text on its way up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveFloatText {
+    pub entity: u32,
+    pub text: String,
+    pub style: FloatTextStyle,
+    /// Seconds since the text appeared.
+    pub age: f32,
+    /// Texts which appear at the same time above an entity are stacked, in lines.
+    pub line: usize,
+}
+
+impl ActiveFloatText {
+    /// The progress of the text from 0.0 to 1.0.
+    pub fn progress(&self, duration: f32) -> f32 {
+        (self.age / duration.max(0.01)).clamp(0.0, 1.0)
+    }
+
+    /// The opacity of the text, it fades out during the last part of its lifetime.
+    pub fn alpha(&self, duration: f32) -> f32 {
+        ((1.0 - self.progress(duration)) / 0.4).min(1.0)
+    }
+}
+
+/// The floating damage and heal numbers and status popups above the entities, from the
+/// float texts and the combat events of the server.
+pub struct FloatTexts {
+    /// Seconds a text stays visible.
+    pub duration: f32,
+    texts: Vec<ActiveFloatText>,
+}
+
+impl Default for FloatTexts {
+    fn default() -> Self {
+        Self {
+            duration: 1.2,
+            texts: vec![],
+        }
+    }
+}
+
+impl FloatTexts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.texts.clear();
+    }
+
+    /// Add a text above the entity.
+    pub fn push(&mut self, entity: u32, text: String, style: FloatTextStyle) {
+        // Stack below the texts of the entity which just appeared
+        let line = self
+            .texts
+            .iter()
+            .filter(|t| t.entity == entity && t.age < self.duration * 0.25)
+            .count();
+        self.texts.push(ActiveFloatText {
+            entity,
+            text,
+            style,
+            age: 0.0,
+            line,
+        });
+    }
+
+    /// Add the floating texts of the server.
+    pub fn add(&mut self, texts: Vec<FloatText>) {
+        for text in texts {
+            self.push(text.entity, text.text, text.style);
+        }
+    }
+
+    /// Add the damage numbers and misses of the combat events.
+    pub fn add_combat(&mut self, events: &[CombatEvent]) {
+        for event in events {
+            match event.kind {
+                CombatEventKind::Hit => {
+                    let style = if event.critical {
+                        FloatTextStyle::Critical
+                    } else {
+                        FloatTextStyle::Damage
+                    };
+                    self.push(event.target, format!("-{}", event.damage), style);
+                }
+                CombatEventKind::Miss => {
+                    self.push(event.target, "Miss".into(), FloatTextStyle::Miss);
+                }
+                CombatEventKind::Kill => {}
+            }
+        }
+    }
+
+    /// Advance the texts by dt seconds and drop the expired ones.
+    pub fn update(&mut self, dt: f32) {
+        for text in &mut self.texts {
+            text.age += dt;
+        }
+        let duration = self.duration;
+        self.texts.retain(|text| text.age < duration);
+    }
+
+    pub fn texts(&self) -> &[ActiveFloatText] {
+        &self.texts
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{FogUpdate, Map};
+use theframework::prelude::*;
+
+/// The brightness of the tiles outside of the field of view, read from the `[fog_of_war]`
+/// table of the game and minimap widgets. See `src/client/CONFIG.md` for an example.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogStyle {
+    pub explored: f32,
+    pub hidden: f32,
+}
+
+impl Default for FogStyle {
+    fn default() -> Self {
+        Self {
+            explored: 0.45,
+            hidden: 0.0,
+        }
+    }
+}
+
+impl FogStyle {
+    /// Read the style from the `[fog_of_war]` table of a widget, missing values use the
+    /// defaults.
+    pub fn from_toml(toml: &str) -> Self {
+        let mut style = Self::default();
+        if let Ok(table) = toml.parse::<toml::Table>() {
+            if let Some(fog) = table.get("fog_of_war").and_then(toml::Value::as_table) {
+                for (key, value) in [
+                    ("explored", &mut style.explored),
+                    ("hidden", &mut style.hidden),
+                ] {
+                    if let Some(v) = fog.get(key).and_then(toml::Value::as_float) {
+                        *value = (v as f32).clamp(0.0, 1.0);
+                    }
+                }
+            }
+        }
+        style
+    }
+}
+
+/// The explored and visible tiles of the player on one map.
+#[derive(Debug, Clone, Default)]
+pub struct FogMap {
+    pub explored: FxHashSet<Vec2<i32>>,
+    pub visible: FxHashSet<Vec2<i32>>,
+}
+
+impl FogMap {
+    /// The brightness of the tile at the world position.
+    pub fn brightness(&self, at: Vec2<f32>, style: &FogStyle) -> f32 {
+        let cell = at.floor().as_::<i32>();
+        if self.visible.contains(&cell) {
+            1.0
+        } else if self.explored.contains(&cell) {
+            style.explored
+        } else {
+            style.hidden
+        }
+    }
+
+    /// Darken the tiles of a top-down view outside of the field of view. `top_left` is the
+    /// world position of the top left pixel, `scale` the pixels per world unit. The brightness
+    /// is interpolated between the tile centers to soften the edges.
+    pub fn draw_2d(
+        &self,
+        buffer: &mut TheRGBABuffer,
+        top_left: Vec2<f32>,
+        scale: f32,
+        style: &FogStyle,
+    ) {
+        if scale <= 0.0 {
+            return;
+        }
+        let width = buffer.dim().width as usize;
+        let height = buffer.dim().height as usize;
+
+        // The brightness of the tile centers around the visible area
+        let origin = top_left.floor().as_::<i32>() - Vec2::one();
+        let tiles_x = (width as f32 / scale).ceil() as i32 + 3;
+        let tiles_y = (height as f32 / scale).ceil() as i32 + 3;
+        let tiles: Vec<f32> = (0..tiles_x * tiles_y)
+            .map(|i| {
+                let cell = origin + Vec2::new(i % tiles_x, i / tiles_x);
+                self.brightness(cell.as_::<f32>() + 0.5, style)
+            })
+            .collect();
+        let tile = |x: i32, y: i32| {
+            tiles[(y.clamp(0, tiles_y - 1) * tiles_x + x.clamp(0, tiles_x - 1)) as usize]
+        };
+
+        let pixels = buffer.pixels_mut();
+        for y in 0..height {
+            let wy = top_left.y + (y as f32 + 0.5) / scale - origin.y as f32 - 0.5;
+            let ty = wy.floor();
+            let fy = wy - ty;
+            for x in 0..width {
+                let wx = top_left.x + (x as f32 + 0.5) / scale - origin.x as f32 - 0.5;
+                let tx = wx.floor();
+                let fx = wx - tx;
+                let (tx, ty) = (tx as i32, ty as i32);
+
+                let top = tile(tx, ty) + (tile(tx + 1, ty) - tile(tx, ty)) * fx;
+                let bottom = tile(tx, ty + 1) + (tile(tx + 1, ty + 1) - tile(tx, ty + 1)) * fx;
+                let brightness = top + (bottom - top) * fy;
+                if brightness >= 1.0 {
+                    continue;
+                }
+                let i = (y * width + x) * 4;
+                for c in 0..3 {
+                    pixels[i + c] = (pixels[i + c] as f32 * brightness) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// The fog of war of the player, by map.
+#[derive(Debug, Clone, Default)]
+pub struct FogOfWar {
+    pub maps: FxHashMap<Uuid, FogMap>,
+}
+
+impl FogOfWar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply the field of view updates of the server to the map.
+    pub fn apply(&mut self, map: &Map, updates: Vec<FogUpdate>) {
+        if updates.is_empty() {
+            return;
+        }
+        let fog = self.maps.entry(map.id).or_default();
+        for update in updates {
+            if update.reset {
+                fog.explored.clear();
+            }
+            fog.explored.extend(update.explored);
+            fog.visible = update.visible.into_iter().collect();
+            fog.explored.extend(fog.visible.iter().copied());
+        }
+    }
+
+    /// The fog of the map, None if the server sent none, i.e. if the fog of war is disabled.
+    pub fn get(&self, map_id: &Uuid) -> Option<&FogMap> {
+        self.maps.get(map_id)
+    }
+}
@@ -0,0 +1,313 @@
+use crate::EntityAction;
+use std::str::FromStr;
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// Navigation of the screen widgets without a mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiNav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Cancel,
+}
+
+impl FromStr for UiNav {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "up" => Ok(UiNav::Up),
+            "down" => Ok(UiNav::Down),
+            "left" => Ok(UiNav::Left),
+            "right" => Ok(UiNav::Right),
+            "confirm" => Ok(UiNav::Confirm),
+            "cancel" => Ok(UiNav::Cancel),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What an input is bound to: an action ("forward"), an intent ("intent:attack") or the
+/// navigation of the UI ("ui:confirm").
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputBinding {
+    Action(EntityAction),
+    Intent(String),
+    Ui(UiNav),
+}
+
+impl FromStr for InputBinding {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(intent) = value.strip_prefix("intent:") {
+            Ok(InputBinding::Intent(intent.to_string()))
+        } else if let Some(nav) = value.strip_prefix("ui:") {
+            UiNav::from_str(nav).map(InputBinding::Ui)
+        } else {
+            EntityAction::from_str(value).map(InputBinding::Action)
+        }
+    }
+}
+
+/// The gamepad mapping, read from the `[input.gamepad]` table of the game config. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// Axes are named `left_x`, `left_y`, `right_x` and `right_y` with positive y pointing down.
+/// Buttons are named `south`, `east`, `west`, `north`, `left_shoulder`, `right_shoulder`,
+/// `select`, `start` and `dpad_up` / `dpad_down` / `dpad_left` / `dpad_right`.
+#[derive(Debug, Clone)]
+pub struct GamepadMapping {
+    pub deadzone: f32,
+    pub stick: String,
+    pub buttons: FxHashMap<String, InputBinding>,
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        let mut buttons = FxHashMap::default();
+        for (button, binding) in [
+            ("dpad_up", InputBinding::Action(EntityAction::Forward)),
+            ("dpad_down", InputBinding::Action(EntityAction::Backward)),
+            ("dpad_left", InputBinding::Action(EntityAction::Left)),
+            ("dpad_right", InputBinding::Action(EntityAction::Right)),
+            ("south", InputBinding::Ui(UiNav::Confirm)),
+            ("east", InputBinding::Ui(UiNav::Cancel)),
+        ] {
+            buttons.insert(button.to_string(), binding);
+        }
+        Self {
+            deadzone: 0.2,
+            stick: "left".into(),
+            buttons,
+        }
+    }
+}
+
+impl GamepadMapping {
+    /// Read the mapping from the config, buttons in the config replace the default bindings.
+    /// Returns the invalid bindings as errors.
+    pub fn from_config(config: &toml::Table) -> (Self, Vec<String>) {
+        let mut mapping = Self::default();
+        let mut errors = vec![];
+
+        let Some(gamepad) = config
+            .get("input")
+            .and_then(|input| input.get("gamepad"))
+            .and_then(toml::Value::as_table)
+        else {
+            return (mapping, errors);
+        };
+
+        if let Some(deadzone) = gamepad.get("deadzone").and_then(toml::Value::as_float) {
+            mapping.deadzone = (deadzone as f32).clamp(0.0, 0.99);
+        }
+        if let Some(stick) = gamepad.get("stick").and_then(toml::Value::as_str) {
+            mapping.stick = stick.to_string();
+        }
+        if let Some(buttons) = gamepad.get("buttons").and_then(toml::Value::as_table) {
+            mapping.buttons.clear();
+            for (button, binding) in buttons {
+                match binding.as_str().map(InputBinding::from_str) {
+                    Some(Ok(binding)) => {
+                        mapping.buttons.insert(button.clone(), binding);
+                    }
+                    _ => errors.push(format!("Invalid gamepad binding for '{}'.", button)),
+                }
+            }
+        }
+        (mapping, errors)
+    }
+}
+
+/// The state of the gamepad, turns the stick into movement vectors.
+#[derive(Debug, Clone, Default)]
+pub struct Gamepad {
+    pub mapping: GamepadMapping,
+    axes: FxHashMap<String, f32>,
+    movement: Vec2<f32>,
+}
+
+impl Gamepad {
+    pub fn new(mapping: GamepadMapping) -> Self {
+        Self {
+            mapping,
+            ..Default::default()
+        }
+    }
+
+    /// The binding of the button.
+    pub fn binding(&self, button: &str) -> Option<&InputBinding> {
+        self.mapping.buttons.get(button)
+    }
+
+    /// Update an axis. Returns the new movement vector if the movement stick changed
+    /// noticeably, zero once it returned into the deadzone.
+    pub fn axis(&mut self, axis: &str, value: f32) -> Option<Vec2<f32>> {
+        self.axes.insert(axis.to_string(), value.clamp(-1.0, 1.0));
+
+        let stick = &self.mapping.stick;
+        if stick == "none" || !axis.starts_with(stick.as_str()) {
+            return None;
+        }
+        let get = |name: &str| {
+            self.axes
+                .get(&format!("{}_{}", stick, name))
+                .copied()
+                .unwrap_or(0.0)
+        };
+        let raw = Vec2::new(get("x"), get("y"));
+
+        // Radial deadzone, rescaled so that the movement starts at zero
+        let length = raw.magnitude();
+        let deadzone = self.mapping.deadzone;
+        let movement = if length <= deadzone {
+            Vec2::zero()
+        } else {
+            raw / length * ((length - deadzone) / (1.0 - deadzone)).min(1.0)
+        };
+
+        let changed = if movement == Vec2::zero() {
+            self.movement != Vec2::zero()
+        } else {
+            movement.distance(self.movement) > 0.05
+        };
+        if changed {
+            self.movement = movement;
+            Some(movement)
+        } else {
+            None
+        }
+    }
+}
+
+/// Normalize a key name: single characters are lowercased and " " is "space", named keys
+/// (i.e. "Space", "Escape") are lowercased.
+pub fn normalize_key(key: &str) -> String {
+    match key {
+        " " => "space".into(),
+        "\n" | "\r" => "enter".into(),
+        "\t" => "tab".into(),
+        _ => key.to_lowercase(),
+    }
+}
+
+/// The key bindings, read from the `[input.keys]` table of the game config. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// Names which are actions ("forward", "backward", "left", "right", "none") send the action
+/// while the key is down, all other names are intents. Bindings can be changed at runtime
+/// with `rebind` and persisted with `to_toml` / `load_toml`.
+#[derive(Debug, Clone, Default)]
+pub struct KeyMap {
+    /// The keys of each binding name, normalized.
+    keys: FxHashMap<String, Vec<String>>,
+}
+
+impl KeyMap {
+    /// Read the key bindings from the config. Returns the invalid bindings as errors.
+    pub fn from_config(config: &toml::Table) -> (Self, Vec<String>) {
+        match config
+            .get("input")
+            .and_then(|input| input.get("keys"))
+            .and_then(toml::Value::as_table)
+        {
+            Some(keys) => Self::from_table(keys),
+            None => (Self::default(), vec![]),
+        }
+    }
+
+    fn from_table(table: &toml::Table) -> (Self, Vec<String>) {
+        let mut map = Self::default();
+        let mut errors = vec![];
+        for (name, keys) in table {
+            let keys: Option<Vec<String>> = match keys {
+                toml::Value::String(key) => Some(vec![key.clone()]),
+                toml::Value::Array(keys) => keys
+                    .iter()
+                    .map(|key| key.as_str().map(str::to_string))
+                    .collect(),
+                _ => None,
+            };
+            match keys {
+                Some(keys) => map.rebind(name, keys),
+                None => errors.push(format!("Invalid key binding for '{}'.", name)),
+            }
+        }
+        (map, errors)
+    }
+
+    /// The binding of the key, if any.
+    pub fn binding(&self, key: &str) -> Option<InputBinding> {
+        let key = normalize_key(key);
+        let (name, _) = self.keys.iter().find(|(_, keys)| keys.contains(&key))?;
+        Some(match EntityAction::from_str(name) {
+            Ok(action) => InputBinding::Action(action),
+            Err(_) => InputBinding::Intent(name.clone()),
+        })
+    }
+
+    /// The keys bound to the name.
+    pub fn keys(&self, name: &str) -> &[String] {
+        self.keys.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Bind the keys to the name, replacing its previous keys. The keys are removed from all
+    /// other names. An empty list removes the binding.
+    pub fn rebind(&mut self, name: &str, keys: Vec<String>) {
+        let keys: Vec<String> = keys.iter().map(|key| normalize_key(key)).collect();
+        for (other, other_keys) in self.keys.iter_mut() {
+            if other != name {
+                other_keys.retain(|key| !keys.contains(key));
+            }
+        }
+        self.keys.retain(|_, keys| !keys.is_empty());
+        if keys.is_empty() {
+            self.keys.remove(name);
+        } else {
+            self.keys.insert(name.to_string(), keys);
+        }
+    }
+
+    /// Serialize the bindings as a `[keys]` TOML table, to persist user changes.
+    pub fn to_toml(&self) -> String {
+        let mut keys = toml::Table::new();
+        let mut names: Vec<&String> = self.keys.keys().collect();
+        names.sort();
+        for name in names {
+            keys.insert(
+                name.clone(),
+                toml::Value::Array(
+                    self.keys[name]
+                        .iter()
+                        .map(|key| toml::Value::String(key.clone()))
+                        .collect(),
+                ),
+            );
+        }
+        let mut table = toml::Table::new();
+        table.insert("keys".into(), toml::Value::Table(keys));
+        toml::to_string(&table).unwrap_or_default()
+    }
+
+    /// Apply bindings persisted by `to_toml` on top of the current ones.
+    pub fn load_toml(&mut self, toml: &str) -> Result<(), String> {
+        let table: toml::Table = toml
+            .parse()
+            .map_err(|err: toml::de::Error| err.to_string())?;
+        let Some(keys) = table.get("keys").and_then(toml::Value::as_table) else {
+            return Ok(());
+        };
+        let (map, errors) = Self::from_table(keys);
+        for (name, keys) in map.keys {
+            self.rebind(&name, keys);
+        }
+        match errors.first() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+}
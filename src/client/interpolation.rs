@@ -0,0 +1,158 @@
+use crate::Map;
+use instant::Instant;
+use std::collections::VecDeque;
+use theframework::prelude::*;
+
+/// The smoothing of the entity positions, read from the `[interpolation]` table of the game
+/// config. See `src/client/CONFIG.md` for an example.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterpolationSettings {
+    pub delay: f32,
+    pub extrapolate: f32,
+    pub snap_distance: f32,
+    pub buffer_size: usize,
+    /// The expected time between server updates.
+    pub tick: f32,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: 0.1,
+            extrapolate: 0.15,
+            snap_distance: 2.0,
+            buffer_size: 8,
+            tick: 0.25,
+        }
+    }
+}
+
+/// A position of the server and the time it arrived at, in seconds.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: f32,
+    position: Vec3<f32>,
+}
+
+/// Buffers the positions the server sends for each entity and renders them slightly in the
+/// past, so that motion stays smooth when updates arrive unevenly. Past the newest update the
+/// motion is extrapolated with the last velocity for a short while.
+pub struct EntityInterpolator {
+    pub settings: InterpolationSettings,
+    tracks: FxHashMap<u32, VecDeque<Sample>>,
+    positions: FxHashMap<u32, Vec3<f32>>,
+    clock: Instant,
+}
+
+impl EntityInterpolator {
+    pub fn new(settings: InterpolationSettings) -> Self {
+        Self {
+            settings,
+            tracks: FxHashMap::default(),
+            positions: FxHashMap::default(),
+            clock: Instant::now(),
+        }
+    }
+
+    /// Drop all buffered positions, i.e. after a region change.
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.positions.clear();
+    }
+
+    /// Record the current positions of the map and return the smoothed positions to render.
+    pub fn update(&mut self, map: &Map) -> &FxHashMap<u32, Vec3<f32>> {
+        let now = self.clock.elapsed().as_secs_f32();
+        self.update_at(map, now)
+    }
+
+    /// `update` at the given time in seconds.
+    pub fn update_at(&mut self, map: &Map, now: f32) -> &FxHashMap<u32, Vec3<f32>> {
+        let settings = self.settings;
+        self.tracks
+            .retain(|id, _| map.entities.iter().any(|entity| entity.id == *id));
+        self.positions.clear();
+
+        for entity in &map.entities {
+            let position = entity.position;
+            let track = self.tracks.entry(entity.id).or_default();
+
+            match track.back().copied() {
+                Some(last) if last.position == position => {}
+                Some(last) => {
+                    if last.position.distance(position) > settings.snap_distance {
+                        // Teleports are not interpolated
+                        track.clear();
+                    } else if now - last.time > settings.tick * 2.0 {
+                        // The entity was at rest, start the motion one tick ago
+                        track.push_back(Sample {
+                            time: now - settings.tick,
+                            position: last.position,
+                        });
+                    }
+                    track.push_back(Sample {
+                        time: now,
+                        position,
+                    });
+                }
+                None => track.push_back(Sample {
+                    time: now,
+                    position,
+                }),
+            }
+            while track.len() > settings.buffer_size.max(2) {
+                track.pop_front();
+            }
+
+            let mut smoothed = Self::sample(track, now - settings.delay, settings.extrapolate);
+            if smoothed.distance(position) > settings.snap_distance {
+                smoothed = position;
+            }
+            self.positions.insert(entity.id, smoothed);
+        }
+
+        &self.positions
+    }
+
+    /// The position of the track at the render time.
+    fn sample(track: &VecDeque<Sample>, time: f32, extrapolate: f32) -> Vec3<f32> {
+        let (Some(first), Some(last)) = (track.front(), track.back()) else {
+            return Vec3::zero();
+        };
+        if time <= first.time {
+            return first.position;
+        }
+
+        if time >= last.time {
+            // Past the newest update, continue with the last velocity
+            if track.len() < 2 {
+                return last.position;
+            }
+            let prev = track[track.len() - 2];
+            let duration = last.time - prev.time;
+            if duration <= f32::EPSILON {
+                return last.position;
+            }
+            // Glide back to the newest position when no update followed
+            let elapsed = time - last.time;
+            let ahead = if elapsed <= extrapolate {
+                elapsed
+            } else {
+                (extrapolate * 2.0 - elapsed).max(0.0)
+            };
+            let velocity = (last.position - prev.position) / duration;
+            return last.position + velocity * ahead;
+        }
+
+        for (a, b) in track.iter().zip(track.iter().skip(1)) {
+            if time >= a.time && time <= b.time {
+                let duration = b.time - a.time;
+                if duration <= f32::EPSILON {
+                    return b.position;
+                }
+                return Vec3::lerp(a.position, b.position, (time - a.time) / duration);
+            }
+        }
+        last.position
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{Assets, CompiledLight, Map, MapMini, SceneHandler, Value};
+use rayon::prelude::*;
+use theframework::prelude::*;
+
+/// The light map of a top-down view, read from the `[lighting]` table of the game widget. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// The light of the map, items and entities is accumulated per sample over the visible tiles
+/// and multiplied into the rendered frame, replacing the unshadowed lighting of the 2D shader.
+/// Samples which a light can not see receive none of it, which gives walls their shadows.
+pub struct TileLighting {
+    pub ambient: f32,
+    pub samples: usize,
+    pub shadows: bool,
+    pub max: f32,
+
+    mini: MapMini,
+
+    // World position of the first sample and the samples per axis
+    origin: Vec2<f32>,
+    width: usize,
+    height: usize,
+    light: Vec<Vec3<f32>>,
+}
+
+impl Default for TileLighting {
+    fn default() -> Self {
+        Self {
+            ambient: 1.0,
+            samples: 2,
+            shadows: true,
+            max: 2.0,
+            mini: MapMini::default(),
+            origin: Vec2::zero(),
+            width: 0,
+            height: 0,
+            light: vec![],
+        }
+    }
+}
+
+impl TileLighting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the occlusion geometry of the map.
+    pub fn set_map(&mut self, map: &Map, assets: &Assets) {
+        self.mini = map.as_mini(&assets.blocking_tiles());
+    }
+
+    /// The active lights of the map, its items and entities, including the lights carried in
+    /// the inventory of entities.
+    pub fn collect_lights(map: &Map, scene_handler: &SceneHandler) -> Vec<CompiledLight> {
+        let mut lights: Vec<CompiledLight> =
+            map.lights.iter().map(|light| light.compile()).collect();
+
+        let mut add = |value: Option<&Value>, position: Vec3<f32>| {
+            if let Some(Value::Light(light)) = value {
+                if light.active {
+                    let mut light = light.clone();
+                    light.set_position(position);
+                    lights.push(light.compile());
+                }
+            }
+        };
+
+        for item in &map.items {
+            add(item.attributes.get("light"), item.position);
+        }
+        for entity in &map.entities {
+            let position = scene_handler.entity_position(entity);
+            add(entity.attributes.get("light"), position);
+            for (_, item) in entity.iter_inventory() {
+                add(item.attributes.get("light"), position);
+            }
+        }
+        lights
+    }
+
+    /// Accumulate the light of the area starting at the world position `top_left` with the
+    /// given size in tiles.
+    pub fn compute(
+        &mut self,
+        lights: &[CompiledLight],
+        sky_color: [f32; 3],
+        top_left: Vec2<f32>,
+        size: Vec2<f32>,
+        hash: u32,
+    ) {
+        let samples = self.samples.max(1);
+        let step = 1.0 / samples as f32;
+
+        // Samples sit at the centers of their cells, one extra row and column covers the edges
+        self.origin = (top_left / step).floor() * step - step / 2.0;
+        self.width = (size.x * samples as f32).ceil() as usize + 3;
+        self.height = (size.y * samples as f32).ceil() as usize + 3;
+
+        let ambient = Vec3::from(sky_color) * self.ambient;
+
+        // Only lights which can reach the area matter
+        let reach = |light: &CompiledLight| {
+            let position = Vec2::new(light.position.x, light.position.z);
+            let end = top_left + size;
+            let nearest = position.clamped(top_left, end);
+            nearest.distance(position) <= light.end_distance
+        };
+        let lights: Vec<&CompiledLight> = lights.iter().filter(|light| reach(light)).collect();
+
+        let (width, origin, shadows, mini) = (self.width, self.origin, self.shadows, &self.mini);
+        self.light = (0..self.width * self.height)
+            .into_par_iter()
+            .map(|index| {
+                let p = origin + Vec2::new((index % width) as f32, (index / width) as f32) * step;
+                let mut color = ambient;
+                for light in &lights {
+                    let from = Vec2::new(light.position.x, light.position.z);
+                    if shadows && !mini.line_of_sight(from, p) {
+                        continue;
+                    }
+                    // Measure the distance on the ground plane
+                    let at = Vec3::new(p.x, light.position.y, p.y);
+                    if let Some(c) = light.color_at(at, &hash, true) {
+                        color += Vec3::from(c);
+                    }
+                }
+                color * mini.get_occlusion(p)
+            })
+            .collect();
+    }
+
+    /// Multiply the light into the buffer. `top_left` is the world position of the top left
+    /// pixel, `scale` the pixels per world unit.
+    pub fn apply(&self, buffer: &mut TheRGBABuffer, top_left: Vec2<f32>, scale: f32) {
+        if self.light.is_empty() || scale <= 0.0 {
+            return;
+        }
+        let width = buffer.dim().width as usize;
+        let samples = self.samples.max(1) as f32;
+        let max = self.max;
+
+        buffer
+            .pixels_mut()
+            .par_chunks_exact_mut(width * 4)
+            .enumerate()
+            .for_each(|(y, line)| {
+                let wy = top_left.y + (y as f32 + 0.5) / scale;
+                for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                    let wx = top_left.x + (x as f32 + 0.5) / scale;
+                    let light = self.sample(Vec2::new(wx, wy), samples);
+                    for c in 0..3 {
+                        pixel[c] = (pixel[c] as f32 * light[c].clamp(0.0, max)).min(255.0) as u8;
+                    }
+                }
+            });
+    }
+
+    /// The bilinear interpolated light at the world position.
+    fn sample(&self, at: Vec2<f32>, samples: f32) -> Vec3<f32> {
+        let s = (at - self.origin) * samples;
+        let x0 = (s.x.floor().max(0.0) as usize).min(self.width - 1);
+        let y0 = (s.y.floor().max(0.0) as usize).min(self.height - 1);
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = (s.x - x0 as f32).clamp(0.0, 1.0);
+        let fy = (s.y - y0 as f32).clamp(0.0, 1.0);
+
+        let at = |x: usize, y: usize| self.light[y * self.width + x];
+        let top = Vec3::lerp(at(x0, y0), at(x1, y0), fx);
+        let bottom = Vec3::lerp(at(x0, y1), at(x1, y1), fx);
+        Vec3::lerp(top, bottom, fy)
+    }
+}
@@ -1,9 +1,28 @@
 pub mod action;
+pub mod audio;
+pub mod bmfont;
+pub mod bubbles;
+pub mod clipboard;
 pub mod command;
 pub mod daylight;
+pub mod debug;
 pub mod draw2d;
+pub mod floattext;
+pub mod fog;
+pub mod input;
+pub mod interpolation;
+pub mod lighting;
 pub mod parser;
+pub mod particles;
+pub mod postfilter;
+pub mod prediction;
+pub mod recorder;
 pub mod resolver;
+pub mod richtext;
+pub mod scaling;
+pub mod theme;
+pub mod touch;
+pub mod tween;
 pub mod widget;
 
 use scenevm::{Atom, GeoId};
@@ -11,16 +30,35 @@ use std::str::FromStr;
 
 use crate::prelude::*;
 use crate::{
-    AccumBuffer, BrushPreview, Command, D2PreviewBuilder, EntityAction, Rect, SceneHandler,
-    ShapeFXGraph, Surface, Tracer, Value,
+    AccumBuffer, AudioEvent, BrushPreview, Command, D2PreviewBuilder, EntityAction, Rect,
+    SceneHandler, ShapeFXGraph, Surface, Tracer, Value,
     client::action::ClientAction,
+    client::audio::SoundManager,
+    client::bubbles::{BubbleStyle, SpeechBubbles, draw_bubble},
+    client::clipboard::Clipboard,
+    client::debug::{DebugOverlay, FrameTimes, SceneStats},
+    client::floattext::FloatTexts,
+    client::fog::FogOfWar,
+    client::input::{Gamepad, GamepadMapping, InputBinding, KeyMap, UiNav, normalize_key},
+    client::interpolation::{EntityInterpolator, InterpolationSettings},
+    client::particles::ParticleSystem,
+    client::postfilter::{PostFilter, PostFilterSettings},
+    client::prediction::{PlayerPrediction, PredictionSettings},
+    client::recorder::FrameRecorder,
+    client::richtext::named_color,
+    client::scaling::ViewportScaling,
+    client::theme::Themes,
+    client::touch::{TouchControls, TouchEvent, TouchSettings},
+    client::tween::{TweenState, WidgetTweens, draw_tweened},
     client::widget::{
-        Widget, deco::DecoWidget, game::GameWidget, messages::MessagesWidget, screen::ScreenWidget,
-        text::TextWidget,
+        ButtonState, ButtonStyle, FrameTile, Widget, bar::BarWidget, deco::DecoWidget,
+        dialogue::DialogueWidget, game::GameWidget, messages::MessagesWidget,
+        minimap::MinimapWidget, screen::ScreenWidget, text::TextWidget, textinput::TextInputWidget,
     },
 };
 use draw2d::Draw2D;
 use fontdue::*;
+use instant::Instant;
 use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
 use theframework::prelude::*;
@@ -41,6 +79,7 @@ pub struct Client {
 
     pub animation_frame: usize,
     pub server_time: TheTime,
+    pub weather: WeatherState,
 
     pub brush_preview: Option<BrushPreview>,
 
@@ -53,7 +92,8 @@ pub struct Client {
 
     pub draw2d: Draw2D,
 
-    pub messages_to_draw: FxHashMap<u32, (Vec2<f32>, String, usize, TheTime)>,
+    // The speech bubbles of the entities and items sending messages
+    pub bubbles: SpeechBubbles,
 
     // Name of player entity templates
     player_entities: Vec<String>,
@@ -82,9 +122,16 @@ pub struct Client {
     button_widgets: FxHashMap<u32, Widget>,
     text_widgets: FxHashMap<Uuid, TextWidget>,
     deco_widgets: FxHashMap<Uuid, DecoWidget>,
+    bar_widgets: FxHashMap<Uuid, BarWidget>,
+    input_widgets: FxHashMap<Uuid, TextInputWidget>,
+
+    // Copy and paste of the text inputs
+    clipboard: Clipboard,
+    minimap_widgets: FxHashMap<Uuid, MinimapWidget>,
     screen_widget: Option<ScreenWidget>,
 
     messages_widget: Option<MessagesWidget>,
+    dialogue_widget: Option<DialogueWidget>,
 
     // Button widgets which are active (clicked)
     activated_widgets: Vec<u32>,
@@ -101,19 +148,21 @@ pub struct Client {
     // Choice map
     choice_map: Option<FxHashMap<char, Choice>>,
 
+    // Dialogue prompts to show
+    dialogues: Vec<DialoguePrompt>,
+
     // Intent
     intent: String,
     key_down_intent: Option<String>,
 
     currencies: Currencies,
 
-    first_game_draw: bool,
+    // How the viewport is scaled into the window
+    scaling: ViewportScaling,
+    letterbox_color: Pixel,
 
-    // Upscale mode: "none" (default, centered), "aspect" (scale to aspect ratio)
-    upscale_mode: String,
-
-    // Current scale factor used for aspect mode (1.0 when no scaling)
-    upscale_factor: f32,
+    // Current scale factor of the viewport (1.0 when no scaling)
+    upscale_factor: Vec2<f32>,
 
     // Default mouse cursor
     default_cursor: Option<Uuid>,
@@ -138,6 +187,58 @@ pub struct Client {
 
     // Hover distance
     hover_distance: f32,
+
+    // Sounds heard by the player, for the audio stack of the host
+    sound_manager: SoundManager,
+
+    // Gamepad state and mapping
+    gamepad: Gamepad,
+
+    // Key bindings of the game config and the user
+    keymap: KeyMap,
+    // The on-screen joystick and buttons and the touches
+    touch: TouchControls,
+
+    // The tweens of the widgets by name and the layer tweened widgets are drawn into
+    tweens: FxHashMap<String, WidgetTweens>,
+    tween_layer: TheRGBABuffer,
+
+    // The themes of the screen widgets
+    themes: Themes,
+
+    // Button widget focused by the UI navigation
+    focused_widget: Option<u32>,
+
+    // Button widgets under the mouse and held down by it
+    hovered_button: Option<u32>,
+    pressed_button: Option<u32>,
+
+    // Language of the string tables
+    language: String,
+
+    // Ring buffer of the last frames for clips
+    recorder: Option<FrameRecorder>,
+
+    // Smoothing of the entity positions between server updates
+    interpolator: Option<EntityInterpolator>,
+
+    // Local movement of the player ahead of the server
+    prediction: Option<PlayerPrediction>,
+
+    // Frame and scene statistics
+    debug: DebugOverlay,
+
+    // Color blindness, contrast and gamma filters of the final frame
+    post_filter: PostFilter,
+
+    // Particles of the map and of server effects
+    particles: ParticleSystem,
+
+    // The explored and visible tiles of the player
+    fog: FogOfWar,
+
+    // Damage and heal numbers and status popups above the entities
+    float_texts: FloatTexts,
 }
 
 impl Default for Client {
@@ -163,6 +264,7 @@ impl Client {
 
             animation_frame: 0,
             server_time: TheTime::default(),
+            weather: WeatherState::default(),
 
             brush_preview: None,
 
@@ -174,7 +276,7 @@ impl Client {
             messages_font_size: 15.0,
             messages_font_color: [229, 229, 1, 255],
 
-            messages_to_draw: FxHashMap::default(),
+            bubbles: SpeechBubbles::default(),
 
             player_entities: Vec::new(),
 
@@ -195,9 +297,14 @@ impl Client {
             button_widgets: FxHashMap::default(),
             text_widgets: FxHashMap::default(),
             deco_widgets: FxHashMap::default(),
+            bar_widgets: FxHashMap::default(),
+            input_widgets: FxHashMap::default(),
+            clipboard: Clipboard::default(),
+            minimap_widgets: FxHashMap::default(),
             screen_widget: None,
 
             messages_widget: None,
+            dialogue_widget: None,
 
             activated_widgets: vec![],
             permanently_activated_widgets: vec![],
@@ -210,10 +317,11 @@ impl Client {
 
             choice_map: None,
 
-            first_game_draw: false,
+            dialogues: vec![],
 
-            upscale_mode: "none".to_string(),
-            upscale_factor: 1.0,
+            scaling: ViewportScaling::None,
+            letterbox_color: [30, 30, 30, 255],
+            upscale_factor: Vec2::one(),
 
             default_cursor: None,
             curr_cursor: None,
@@ -224,6 +332,29 @@ impl Client {
             hovered_item_id: None,
 
             hover_distance: f32::MAX,
+
+            sound_manager: SoundManager::default(),
+
+            gamepad: Gamepad::default(),
+            keymap: KeyMap::default(),
+            touch: TouchControls::default(),
+
+            tweens: FxHashMap::default(),
+            tween_layer: TheRGBABuffer::default(),
+
+            themes: Themes::default(),
+            focused_widget: None,
+            hovered_button: None,
+            pressed_button: None,
+            language: "en".into(),
+            recorder: None,
+            interpolator: None,
+            prediction: None,
+            debug: DebugOverlay::default(),
+            post_filter: PostFilter::default(),
+            particles: ParticleSystem::default(),
+            fog: FogOfWar::default(),
+            float_texts: FloatTexts::default(),
         }
     }
 
@@ -241,11 +372,37 @@ impl Client {
         self.server_time = time;
     }
 
+    /// Set the weather of the current region
+    pub fn set_weather(&mut self, weather: WeatherState) {
+        self.weather = weather;
+    }
+
     /// Set the current map id.
     pub fn set_curr_map_id(&mut self, id: Uuid) {
+        if id != self.curr_map_id {
+            if let Some(interpolator) = &mut self.interpolator {
+                interpolator.clear();
+            }
+            if let Some(prediction) = &mut self.prediction {
+                prediction.clear();
+            }
+            self.particles.clear();
+            self.float_texts.clear();
+            self.bubbles.clear();
+        }
         self.curr_map_id = id;
     }
 
+    /// Apply a new version of the current map, i.e. after its MapScript was recompiled by a
+    /// file watcher. The game widgets only rebuild the chunks covering the changes.
+    pub fn update_map(&mut self, map: &Map, assets: &Assets) {
+        for widget in self.game_widgets.values_mut() {
+            if widget.build_region_name == map.name {
+                widget.update_map(map, assets);
+            }
+        }
+    }
+
     /// Set the D3 Camera
     pub fn set_camera_d3(&mut self, camera: Box<dyn D3Camera>) {
         self.camera_d3 = camera;
@@ -329,65 +486,67 @@ impl Client {
         );
     }
 
-    /// Process messages from the server to be displayed after drawing.
-    pub fn process_messages(&mut self, map: &Map, messages: Vec<crate::server::Message>) {
-        // Remove expired messages
-        let expired_keys: Vec<_> = self
-            .messages_to_draw
-            .iter()
-            .filter(|(_, (_, _, _, expire_time))| *expire_time < self.server_time)
-            .map(|(id, _)| *id)
-            .collect();
+    /// Queue the messages from the server in the speech bubbles of their senders.
+    pub fn process_messages(&mut self, _map: &Map, messages: Vec<crate::server::Message>) {
+        self.bubbles.add_messages(&messages);
+    }
 
-        for id in expired_keys {
-            self.messages_to_draw.remove(&id);
+    /// Process the sounds from the server. The sounds are attenuated by their distance to the
+    /// player, panned by its facing and passed to the audio callback, or queued for
+    /// `get_audio_events` if none is set.
+    pub fn process_sounds(&mut self, map: &Map, sounds: Vec<SoundEvent>) {
+        let Some(player) = map.entities.iter().find(|entity| entity.is_player()) else {
+            return;
+        };
+        self.sound_manager.set_listener(player);
+
+        for sound in sounds {
+            self.sound_manager.play(sound);
         }
+    }
 
-        // Add new messages
-        for (sender_entity_id, sender_item_id, _, message, _category) in messages {
-            if let Some(sender_item_id) = sender_item_id {
-                for item in &map.items {
-                    if item.id == sender_item_id {
-                        if let Some(font) = &self.messages_font {
-                            let text_size =
-                                self.draw2d
-                                    .get_text_size(font, self.messages_font_size, &message);
+    /// Play a non positional UI sound, i.e. for a widget.
+    pub fn play_ui_sound(&mut self, name: &str, volume: f32) {
+        self.sound_manager.play_ui(name, volume);
+    }
 
-                            let ticks = self.server_time.to_ticks(4);
-                            let expire_time = TheTime::from_ticks(ticks + 4, 4);
+    /// Add the particle effects of the server, drawn by the 2D game widgets.
+    pub fn process_particles(&mut self, events: Vec<ParticleEvent>) {
+        self.particles.add_events(events);
+    }
 
-                            self.messages_to_draw.insert(
-                                sender_item_id,
-                                (item.get_pos_xz(), message.clone(), text_size.0, expire_time),
-                            );
-                        }
-                    }
-                }
-            } else if let Some(sender_entity_id) = sender_entity_id {
-                for entity in &map.entities {
-                    if entity.id == sender_entity_id {
-                        if let Some(font) = &self.messages_font {
-                            let text_size =
-                                self.draw2d
-                                    .get_text_size(font, self.messages_font_size, &message);
-
-                            let ticks = self.server_time.to_ticks(4);
-                            let expire_time = TheTime::from_ticks(ticks + 4, 4);
-
-                            self.messages_to_draw.insert(
-                                sender_entity_id,
-                                (
-                                    entity.get_pos_xz(),
-                                    message.clone(),
-                                    text_size.0,
-                                    expire_time,
-                                ),
-                            );
-                        }
-                    }
-                }
-            }
-        }
+    /// Add the floating texts of the server, drawn above their entities by the game widgets.
+    pub fn process_float_texts(&mut self, texts: Vec<FloatText>) {
+        self.float_texts.add(texts);
+    }
+
+    /// Show the damage numbers and misses of the combat events as floating texts.
+    pub fn process_combat_events(&mut self, events: &[CombatEvent]) {
+        self.float_texts.add_combat(events);
+    }
+
+    /// Apply the fog of war updates of the server to the map.
+    pub fn process_fog(&mut self, map: &Map, updates: Vec<FogUpdate>) {
+        self.fog.apply(map, updates);
+    }
+
+    /// Set the callback which receives the audible sounds, see `process_sounds`.
+    pub fn set_audio_callback(&mut self, callback: impl FnMut(&AudioEvent) + Send + 'static) {
+        self.sound_manager.set_callback(callback);
+    }
+
+    /// Set the callbacks reading and writing the system clipboard, used by the text inputs.
+    pub fn set_clipboard_callbacks(
+        &mut self,
+        get: impl FnMut() -> Option<String> + Send + 'static,
+        set: impl FnMut(&str) + Send + 'static,
+    ) {
+        self.clipboard.set_callbacks(get, set);
+    }
+
+    /// Get the queued audible sounds and clear them, call once per frame.
+    pub fn get_audio_events(&mut self) -> Vec<AudioEvent> {
+        self.sound_manager.drain()
     }
 
     /// Draw the 2D scene.
@@ -555,38 +714,32 @@ impl Client {
             .vm
             .render_frame(pixels, width as u32, height as u32);
 
-        // Draw Messages
-
+        // Draw the speech bubbles above their speakers
         if let Some(font) = &self.messages_font {
-            for (grid_pos, message, text_size, _) in self.messages_to_draw.values() {
-                let position = map_grid_to_local(screen_size, *grid_pos, map);
-
-                let tuple = (
-                    position.x as isize - *text_size as isize / 2 - 5,
-                    position.y as isize - self.messages_font_size as isize - map.grid_size as isize,
-                    *text_size as isize + 10,
-                    22,
-                );
-
-                self.draw2d.blend_rect_safe(
-                    pixels,
-                    &tuple,
-                    width,
-                    &[0, 0, 0, 128],
-                    &(0, 0, width as isize, height as isize),
-                );
-
-                self.draw2d.text_rect_blend_safe(
+            self.bubbles.update(1.0 / self.target_fps.max(1) as f32);
+            let style = BubbleStyle {
+                font_size: self.messages_font_size,
+                color: self.messages_font_color,
+                background: [0, 0, 0, 160],
+                portrait_size: 0.0,
+                ..Default::default()
+            };
+            for (speaker, bubble) in self.bubbles.visible() {
+                let Some(position) = speaker.position(map, scene_handler) else {
+                    continue;
+                };
+                let position =
+                    map_grid_to_local(screen_size, Vec2::new(position.x, position.z), map);
+                draw_bubble(
+                    &self.draw2d,
                     pixels,
-                    &tuple,
                     width,
+                    height,
+                    &style,
                     font,
-                    self.messages_font_size,
-                    message,
-                    &self.messages_font_color,
-                    draw2d::TheHorizontalAlign::Center,
-                    draw2d::TheVerticalAlign::Center,
-                    &(0, 0, width as isize, height as isize),
+                    &bubble.text,
+                    None,
+                    position - Vec2::new(0.0, map.grid_size / 2.0),
                 );
             }
         }
@@ -687,7 +840,7 @@ impl Client {
         default
     }
 
-    fn _get_config_f32_default(&self, table: &str, key: &str, default: f32) -> f32 {
+    fn get_config_f32_default(&self, table: &str, key: &str, default: f32) -> f32 {
         if let Some(game) = self.config.get(table).and_then(toml::Value::as_table) {
             if let Some(value) = game.get(key) {
                 if let Some(v) = value.as_float() {
@@ -729,7 +882,6 @@ impl Client {
     /// Setup the client with the given assets.
     pub fn setup(&mut self, assets: &mut Assets, scene_handler: &mut SceneHandler) -> Vec<Command> {
         let mut commands = vec![];
-        self.first_game_draw = true;
         self.intent = String::new();
 
         self.permanently_activated_widgets.clear();
@@ -745,6 +897,50 @@ impl Client {
             }
         }
 
+        let (mapping, errors) = GamepadMapping::from_config(&self.config);
+        for err in errors {
+            eprintln!("Client: {}", err);
+        }
+        self.gamepad = Gamepad::new(mapping);
+
+        let (keymap, errors) = KeyMap::from_config(&self.config);
+        for err in errors {
+            eprintln!("Client: {}", err);
+        }
+        self.keymap = keymap;
+
+        let (settings, errors) = TouchSettings::from_config(&self.config);
+        for err in errors {
+            eprintln!("Client: {}", err);
+        }
+        let font = assets.fonts.get(&settings.font).cloned();
+        self.touch = TouchControls::new(settings);
+        self.touch.font = font;
+
+        let (settings, errors) = PostFilterSettings::from_config(&self.config);
+        for err in errors {
+            eprintln!("Client: {}", err);
+        }
+        self.post_filter = PostFilter::new(settings);
+
+        let font = self.get_config_string_default("debug", "font", "");
+        self.debug = DebugOverlay {
+            visible: self.get_config_bool_default("debug", "overlay", false),
+            key: normalize_key(&self.get_config_string_default("debug", "key", "F3")),
+            font: assets
+                .fonts
+                .get(&font)
+                .or_else(|| assets.fonts.values().next())
+                .cloned(),
+            font_size: self
+                .get_config_f32_default("debug", "font_size", 12.0)
+                .max(1.0),
+            ..Default::default()
+        };
+
+        assets.read_locales();
+        self.language = self.get_config_string_default("game", "language", "en");
+
         let mut currencies = Currencies::default();
         _ = currencies.add_currency(Currency {
             name: "Gold".into(),
@@ -783,7 +979,19 @@ impl Client {
         self.target_fps = self.get_config_i32_default("game", "target_fps", 30);
         self.game_tick_ms = self.get_config_i32_default("game", "game_tick_ms", 250);
         self.grid_size = self.get_config_i32_default("viewport", "grid_size", 32) as f32;
-        self.upscale_mode = self.get_config_string_default("viewport", "upscale", "none");
+        self.scaling = match self
+            .get_config_string_default("viewport", "upscale", "none")
+            .parse()
+        {
+            Ok(scaling) => scaling,
+            Err(err) => {
+                eprintln!("Client: {}", err);
+                ViewportScaling::None
+            }
+        };
+        self.letterbox_color =
+            named_color(&self.get_config_string_default("viewport", "letterbox_color", ""))
+                .unwrap_or([30, 30, 30, 255]);
 
         self.default_cursor = None;
         let tile_id_str = self.get_config_string_default("viewport", "cursor_id", "");
@@ -798,9 +1006,90 @@ impl Client {
         // Create the overlay buffer
         self.overlay = TheRGBABuffer::new(TheDim::sized(self.viewport.x, self.viewport.y));
 
+        // Smooth the entity positions if configured
+        self.interpolator = None;
+        if self.get_config_bool_default("interpolation", "enabled", false) {
+            let defaults = InterpolationSettings::default();
+            let settings = InterpolationSettings {
+                delay: self
+                    .get_config_i32_default(
+                        "interpolation",
+                        "delay_ms",
+                        (defaults.delay * 1000.0) as i32,
+                    )
+                    .max(0) as f32
+                    / 1000.0,
+                extrapolate: self
+                    .get_config_i32_default(
+                        "interpolation",
+                        "extrapolate_ms",
+                        (defaults.extrapolate * 1000.0) as i32,
+                    )
+                    .max(0) as f32
+                    / 1000.0,
+                snap_distance: self.get_config_f32_default(
+                    "interpolation",
+                    "snap_distance",
+                    defaults.snap_distance,
+                ),
+                buffer_size: self
+                    .get_config_i32_default(
+                        "interpolation",
+                        "buffer_size",
+                        defaults.buffer_size as i32,
+                    )
+                    .max(2) as usize,
+                tick: self.game_tick_ms.max(1) as f32 / 1000.0,
+            };
+            self.interpolator = Some(EntityInterpolator::new(settings));
+        }
+
+        // Predict the movement of the player if configured
+        self.prediction = None;
+        if self.get_config_bool_default("prediction", "enabled", false) {
+            let defaults = PredictionSettings::default();
+            let settings = PredictionSettings {
+                speed: self
+                    .get_config_f32_default("prediction", "speed", defaults.speed)
+                    .max(0.0),
+                latency: self
+                    .get_config_i32_default(
+                        "prediction",
+                        "latency_ms",
+                        (defaults.latency * 1000.0) as i32,
+                    )
+                    .max(0) as f32
+                    / 1000.0,
+                correction: self
+                    .get_config_i32_default(
+                        "prediction",
+                        "correction_ms",
+                        (defaults.correction * 1000.0) as i32,
+                    )
+                    .max(0) as f32
+                    / 1000.0,
+                snap_distance: self.get_config_f32_default(
+                    "prediction",
+                    "snap_distance",
+                    defaults.snap_distance,
+                ),
+            };
+            self.prediction = Some(PlayerPrediction::new(settings));
+        }
+
+        // Keep the last seconds of frames if configured
+        let record_seconds = self.get_config_i32_default("game", "record_seconds", 0);
+        if record_seconds > 0 {
+            self.start_recording(record_seconds as f32);
+        } else {
+            self.recorder = None;
+        }
+
         // Find the start region
         self.current_map = self.get_config_string_default("game", "start_region", "");
 
+        self.themes = Themes::from_config(&self.config);
+
         // Find the start screen
         self.current_screen = self.get_config_string_default("game", "start_screen", "");
 
@@ -833,6 +1122,11 @@ impl Client {
         commands
     }
 
+    /// Add dialogue prompts received from the server, they are shown by the messages widget.
+    pub fn add_dialogues(&mut self, dialogues: Vec<DialoguePrompt>) {
+        self.dialogues.extend(dialogues);
+    }
+
     /// Draw the game into the internal buffer
     pub fn draw_game(
         &mut self,
@@ -843,6 +1137,7 @@ impl Client {
         scene_handler: &mut SceneHandler,
     ) {
         let mut player_entity = Entity::default();
+        self.debug.begin_frame();
 
         // Keep scene timing in sync with config
         scene_handler.set_timings(self.target_fps as f32, self.game_tick_ms);
@@ -856,9 +1151,46 @@ impl Client {
         }
 
         self.target.fill([0, 0, 0, 255]);
+        scene_handler.settings.weather = self.weather;
+
+        match &mut self.interpolator {
+            Some(interpolator) => {
+                scene_handler.entity_positions = interpolator.update(map).clone();
+            }
+            None => scene_handler.entity_positions.clear(),
+        }
+
+        let dt = 1.0 / self.target_fps.max(1) as f32;
+
+        // The predicted player overrides the position of the server
+        if let Some(prediction) = &mut self.prediction {
+            // In first person and with an intent the movement keys do not walk
+            let walking = self.intent.is_empty()
+                && !self
+                    .game_widgets
+                    .values()
+                    .any(|widget| widget.camera == crate::PlayerCamera::D3FirstP);
+            if let Some((id, position)) = prediction.update(map, assets, walking, dt) {
+                scene_handler.entity_positions.insert(id, position);
+            }
+        }
+        self.particles.update(map, dt);
+        self.float_texts.update(dt);
+        self.bubbles.add_messages(&messages);
+        self.bubbles.update(dt);
+
         // First process the game widgets
+        let mut times = FrameTimes::default();
+        let mut scene_stats = SceneStats {
+            billboards: scene_handler.billboards.len(),
+            ..Default::default()
+        };
         for widget in self.game_widgets.values_mut() {
+            let start = Instant::now();
             widget.apply_entities(map, assets, self.animation_frame, scene_handler);
+            times.build += start.elapsed().as_secs_f32() * 1000.0;
+
+            let start = Instant::now();
             widget.draw(
                 map,
                 &self.server_time,
@@ -866,10 +1198,18 @@ impl Client {
                 assets,
                 scene_handler,
             );
+            widget.draw_particles(&self.particles);
+            widget.draw_fog(self.fog.get(&map.id));
+            widget.draw_weather(&self.weather, self.animation_frame);
+            widget.draw_float_texts(&self.float_texts, map, scene_handler);
+            widget.draw_bubbles(&self.bubbles, map, assets, scene_handler);
 
             self.target
                 .copy_into(widget.rect.x as i32, widget.rect.y as i32, &widget.buffer);
+            times.raster += start.elapsed().as_secs_f32() * 1000.0;
+            scene_stats += widget.scene_stats();
         }
+        let ui_start = Instant::now();
 
         if let Some(screen) = assets.screens.get(&self.current_screen) {
             if let Some(screen_widget) = &mut self.screen_widget {
@@ -905,6 +1245,70 @@ impl Client {
                 .blend_into(widget.rect.x as i32, widget.rect.y as i32, &widget.buffer);
         }
 
+        // Draw the attribute bars
+        for widget in self.bar_widgets.values_mut() {
+            let hide = self.widgets_to_hide.iter().any(|pattern| {
+                if let Some(prefix) = pattern.strip_suffix('*') {
+                    widget.name.starts_with(prefix)
+                } else {
+                    widget.name == *pattern
+                }
+            });
+            let tween = match self.tweens.get_mut(&widget.name) {
+                Some(tweens) => tweens.update(!hide, false, dt),
+                None => (!hide).then_some(TweenState::REST),
+            };
+            if let Some(tween) = tween {
+                let rect = widget.rect;
+                draw_tweened(
+                    &mut self.target,
+                    &mut self.tween_layer,
+                    &rect,
+                    &tween,
+                    |target| widget.update_draw(target, map),
+                );
+            }
+        }
+
+        // Draw the text inputs
+        for widget in self.input_widgets.values_mut() {
+            let hide = self.widgets_to_hide.iter().any(|pattern| {
+                if let Some(prefix) = pattern.strip_suffix('*') {
+                    widget.name.starts_with(prefix)
+                } else {
+                    widget.name == *pattern
+                }
+            });
+            if hide {
+                widget.set_focus(false);
+            } else {
+                widget.update_draw(&mut self.target);
+            }
+        }
+
+        // Draw the minimaps
+        for widget in self.minimap_widgets.values_mut() {
+            let hide = self.widgets_to_hide.iter().any(|pattern| {
+                if let Some(prefix) = pattern.strip_suffix('*') {
+                    widget.name.starts_with(prefix)
+                } else {
+                    widget.name == *pattern
+                }
+            });
+            if !hide {
+                widget.update_draw(map, self.fog.get(&map.id));
+                self.target
+                    .blend_into(widget.rect.x as i32, widget.rect.y as i32, &widget.buffer);
+            }
+        }
+
+        // Dialogues go to the dialogue widget if there is one, the messages otherwise
+        if let Some(widget) = &mut self.dialogue_widget {
+            if let Some(prompt) = std::mem::take(&mut self.dialogues).pop() {
+                widget.set_prompt(prompt, map, assets);
+            }
+        }
+
         // Draw the messages on top
         if let Some(widget) = &mut self.messages_widget {
             let hide = self.widgets_to_hide.iter().any(|pattern| {
@@ -916,6 +1320,11 @@ impl Client {
                 }
             });
 
+            let dialogues = std::mem::take(&mut self.dialogues);
+            if !dialogues.is_empty() {
+                self.choice_map = widget.process_dialogues(assets, map, dialogues);
+            }
+
             if !hide {
                 let map = widget.update_draw(&mut self.target, assets, map, messages, choices);
                 if map.is_some() {
@@ -931,6 +1340,20 @@ impl Client {
             }
         }
 
+        // Draw the dialogue on top of the messages
+        if let Some(widget) = &mut self.dialogue_widget {
+            let hide = self.widgets_to_hide.iter().any(|pattern| {
+                if let Some(prefix) = pattern.strip_suffix('*') {
+                    widget.name.starts_with(prefix)
+                } else {
+                    widget.name == *pattern
+                }
+            });
+            if !hide {
+                widget.update_draw(&mut self.target, map, assets);
+            }
+        }
+
         // Draw the text widgets on top
         for widget in self.text_widgets.values_mut() {
             let hide = self.widgets_to_hide.iter().any(|pattern| {
@@ -942,10 +1365,26 @@ impl Client {
                 }
             });
 
-            if !hide {
-                widget.update_draw(&mut self.target, map, &self.currencies, assets);
-                self.target
-                    .blend_into(widget.rect.x as i32, widget.rect.y as i32, &widget.buffer);
+            let tween = match self.tweens.get_mut(&widget.name) {
+                Some(tweens) => tweens.update(!hide, false, dt),
+                None => (!hide).then_some(TweenState::REST),
+            };
+            if let Some(tween) = tween {
+                let rect = widget.rect;
+                draw_tweened(
+                    &mut self.target,
+                    &mut self.tween_layer,
+                    &rect,
+                    &tween,
+                    |target| {
+                        widget.update_draw(target, map, &self.currencies, assets);
+                        target.blend_into(
+                            widget.rect.x as i32,
+                            widget.rect.y as i32,
+                            &widget.buffer,
+                        );
+                    },
+                );
             }
         }
 
@@ -960,23 +1399,59 @@ impl Client {
                 }
             });
 
-            if !hide {
-                widget.update_draw(
+            let activated = self.activated_widgets.contains(&widget.id);
+            let tween = match self.tweens.get_mut(&widget.name) {
+                Some(tweens) => tweens.update(!hide, activated, dt),
+                None => (!hide).then_some(TweenState::REST),
+            };
+            if let Some(tween) = tween {
+                let state = if widget.disabled {
+                    ButtonState::Disabled
+                } else if self.pressed_button == Some(widget.id) {
+                    ButtonState::Pressed
+                } else if self.hovered_button == Some(widget.id)
+                    || self.focused_widget == Some(widget.id)
+                {
+                    ButtonState::Hover
+                } else {
+                    ButtonState::Normal
+                };
+                let highlight = if activated || self.focused_widget == Some(widget.id) {
+                    1
+                } else {
+                    0
+                };
+                let rect = widget.rect;
+                draw_tweened(
                     &mut self.target,
-                    map,
-                    assets,
-                    &player_entity,
-                    &self.draw2d,
-                    &self.animation_frame,
-                    if self.activated_widgets.contains(&widget.id) {
-                        1
-                    } else {
-                        0
+                    &mut self.tween_layer,
+                    &rect,
+                    &tween,
+                    |target| {
+                        widget.update_draw(
+                            target,
+                            map,
+                            assets,
+                            &player_entity,
+                            &self.draw2d,
+                            &self.animation_frame,
+                            highlight,
+                            state,
+                        )
                     },
                 );
             }
         }
 
+        self.touch.draw(&mut self.target, &self.draw2d);
+
+        times.ui = ui_start.elapsed().as_secs_f32() * 1000.0;
+        self.debug.record(times);
+        self.debug.scene = scene_stats;
+        self.debug.entities = map.entities.len();
+        self.debug.items = map.items.len();
+        self.debug.draw(&mut self.target, &self.draw2d);
+
         // Draw the cursor (centered on cursor_pos)
         if let Some(cursor) = self.curr_cursor {
             if let Some(tile) = assets.tiles.get(&cursor) {
@@ -1000,59 +1475,142 @@ impl Client {
                 }
             }
         }
+
+        self.post_filter.apply(&mut self.target);
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&self.target);
+        }
     }
 
-    /// Copy the game buffer into the external buffer
-    pub fn insert_game_buffer(&mut self, buffer: &mut TheRGBABuffer) {
-        let bg_color = [30, 30, 30, 255];
-
-        if self.upscale_mode == "aspect" {
-            // Scale to fit while maintaining aspect ratio, centered
-            let dim = buffer.dim();
-            let src_width = self.viewport.x as f32;
-            let src_height = self.viewport.y as f32;
-            let dst_width = dim.width as f32;
-            let dst_height = dim.height as f32;
-
-            let scale = (dst_width / src_width).min(dst_height / src_height);
-            let scaled_width = (src_width * scale) as i32;
-            let scaled_height = (src_height * scale) as i32;
-
-            let offset_x = (dim.width - scaled_width) / 2;
-            let offset_y = (dim.height - scaled_height) / 2;
-
-            self.target_offset = Vec2::new(offset_x, offset_y);
-            self.upscale_factor = scale;
-
-            // Only fill letterbox/pillarbox areas instead of entire buffer
-            Self::fill_borders(
-                buffer,
-                offset_x,
-                offset_y,
-                scaled_width,
-                scaled_height,
-                bg_color,
+    /// The settings of the accessibility filters, see `PostFilterSettings`.
+    pub fn post_filter_settings(&self) -> &PostFilterSettings {
+        &self.post_filter.settings
+    }
+
+    /// Switch the accessibility filters at runtime, i.e. from an options menu.
+    pub fn set_post_filter_settings(&mut self, settings: PostFilterSettings) {
+        self.post_filter = PostFilter::new(settings);
+    }
+
+    /// Start keeping the last seconds of frames, see `[game]` `record_every` and
+    /// `record_downscale` for the memory used.
+    pub fn start_recording(&mut self, seconds: f32) {
+        let every = self
+            .get_config_i32_default("game", "record_every", 2)
+            .max(1) as usize;
+        let mut recorder = FrameRecorder::new(seconds, self.target_fps.max(1) as usize, every);
+        recorder.downscale = self
+            .get_config_i32_default("game", "record_downscale", 1)
+            .max(1) as usize;
+        self.recorder = Some(recorder);
+    }
+
+    /// Stop recording and drop the kept frames.
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Export the kept frames as an animated GIF.
+    pub fn export_recording_gif(&self) -> Result<Vec<u8>, String> {
+        self.recorder.as_ref().ok_or("Not recording.")?.export_gif()
+    }
+
+    /// Export the kept frames as a sequence of PNGs.
+    pub fn export_recording_png_sequence(&self) -> Result<Vec<Vec<u8>>, String> {
+        self.recorder
+            .as_ref()
+            .ok_or("Not recording.")?
+            .export_png_sequence()
+    }
+
+    /// The last composited frame, the game widgets with the UI on top.
+    pub fn capture_screenshot(&self) -> Texture {
+        Texture::from_rgbabuffer(&self.target)
+    }
+
+    /// Re-render the game widgets at `factor` times the viewport resolution, i.e. for
+    /// marketing shots. The UI is not part of the capture as it only exists at the viewport
+    /// resolution.
+    pub fn capture_screenshot_hires(
+        &mut self,
+        factor: usize,
+        map: &Map,
+        assets: &Assets,
+        scene_handler: &mut SceneHandler,
+    ) -> Texture {
+        let factor = factor.max(1);
+        let mut frame = TheRGBABuffer::new(TheDim::sized(
+            self.viewport.x * factor as i32,
+            self.viewport.y * factor as i32,
+        ));
+        frame.fill([0, 0, 0, 255]);
+
+        for widget in self.game_widgets.values_mut() {
+            let mut buffer = widget.render_hires(
+                factor,
+                map,
+                &self.server_time,
+                self.animation_frame,
+                assets,
+                scene_handler,
+            );
+            std::mem::swap(&mut widget.buffer, &mut buffer);
+            widget.draw_weather(&self.weather, self.animation_frame);
+            std::mem::swap(&mut widget.buffer, &mut buffer);
+
+            frame.copy_into(
+                widget.rect.x as i32 * factor as i32,
+                widget.rect.y as i32 * factor as i32,
+                &buffer,
             );
+        }
 
-            Self::scale_buffer_into(&self.target, buffer, offset_x, offset_y, scale);
+        Texture::from_rgbabuffer(&frame)
+    }
+
+    /// Copy the game buffer into the external buffer, scaled by the viewport scaling.
+    pub fn insert_game_buffer(&mut self, buffer: &mut TheRGBABuffer) {
+        let dim = buffer.dim();
+        let (offset, scale) = self
+            .scaling
+            .layout(self.viewport, Vec2::new(dim.width, dim.height));
+        self.target_offset = offset;
+        self.upscale_factor = scale;
+
+        let size = (self.viewport.as_::<f32>() * scale).as_::<i32>();
+
+        // Only fill letterbox/pillarbox areas instead of entire buffer
+        Self::fill_borders(
+            buffer,
+            offset.x,
+            offset.y,
+            size.x,
+            size.y,
+            self.letterbox_color,
+        );
+
+        if scale == Vec2::one() {
+            buffer.copy_into(offset.x, offset.y, &self.target);
         } else {
-            self.upscale_factor = 1.0;
-            // "none" mode: center without scaling
-            if self.first_game_draw {
-                buffer.fill(bg_color);
-                let dim = buffer.dim();
-                if dim.width > self.viewport.x {
-                    self.target_offset.x = (dim.width - self.viewport.x) / 2;
-                }
-                if dim.height > self.viewport.y {
-                    self.target_offset.y = (dim.height - self.viewport.y) / 2;
-                }
-                self.first_game_draw = false;
-            }
-            buffer.copy_into(self.target_offset.x, self.target_offset.y, &self.target);
+            Self::scale_buffer_into(&self.target, buffer, offset.x, offset.y, scale);
         }
     }
 
+    /// The current viewport scaling.
+    pub fn viewport_scaling(&self) -> ViewportScaling {
+        self.scaling
+    }
+
+    /// Switch the viewport scaling at runtime, i.e. from an options menu.
+    pub fn set_viewport_scaling(&mut self, scaling: ViewportScaling) {
+        self.scaling = scaling;
+    }
+
     /// Fill only the border areas (letterbox/pillarbox) around the content area.
     fn fill_borders(
         buffer: &mut TheRGBABuffer,
@@ -1117,15 +1675,15 @@ impl Client {
         dst: &mut TheRGBABuffer,
         offset_x: i32,
         offset_y: i32,
-        scale: f32,
+        scale: Vec2<f32>,
     ) {
         let src_width = src.dim().width as usize;
         let src_height = src.dim().height as usize;
         let dst_width = dst.dim().width as usize;
         let dst_height = dst.dim().height as usize;
 
-        let scaled_width = (src_width as f32 * scale) as i32;
-        let scaled_height = (src_height as f32 * scale) as i32;
+        let scaled_width = (src_width as f32 * scale.x) as i32;
+        let scaled_height = (src_height as f32 * scale.y) as i32;
 
         // Pre-calculate valid render bounds
         let y_start = 0.max(-offset_y);
@@ -1140,11 +1698,9 @@ impl Client {
         let src_pixels = src.pixels();
         let dst_pixels = dst.pixels_mut();
 
-        let inv_scale = 1.0 / scale;
-
-        // Pre-compute source X indices for the row
+        // Nearest neighbor in integer math, whole scales repeat every pixel exactly
         let src_x_indices: Vec<usize> = (x_start..x_end)
-            .map(|dx| ((dx as f32 * inv_scale) as usize).min(src_width - 1))
+            .map(|dx| (dx as usize * src_width / scaled_width as usize).min(src_width - 1))
             .collect();
 
         let dst_x_offset = (offset_x + x_start) as usize * 4;
@@ -1157,7 +1713,7 @@ impl Client {
             .take((y_end - y_start) as usize)
             .for_each(|(dst_y, dst_row)| {
                 let dy = dst_y as i32 - offset_y;
-                let src_y = ((dy as f32 * inv_scale) as usize).min(src_height - 1);
+                let src_y = (dy as usize * src_height / scaled_height as usize).min(src_height - 1);
                 let src_row_start = src_y * src_width * 4;
 
                 for (i, &src_x) in src_x_indices.iter().enumerate() {
@@ -1171,8 +1727,8 @@ impl Client {
 
     /// Transform screen coordinates to viewport coordinates, accounting for offset and scale.
     fn screen_to_viewport(&self, coord: Vec2<i32>) -> Vec2<i32> {
-        let x = ((coord.x - self.target_offset.x) as f32 / self.upscale_factor) as i32;
-        let y = ((coord.y - self.target_offset.y) as f32 / self.upscale_factor) as i32;
+        let x = ((coord.x - self.target_offset.x) as f32 / self.upscale_factor.x) as i32;
+        let y = ((coord.y - self.target_offset.y) as f32 / self.upscale_factor.y) as i32;
         Vec2::new(x, y)
     }
 
@@ -1191,6 +1747,10 @@ impl Client {
     ) {
         let p = self.screen_to_viewport(coord);
         self.cursor_pos = p;
+
+        if let Some(widget) = self.input_widgets.values_mut().find(|w| w.focused) {
+            widget.touch_dragged(p.x as f32);
+        }
     }
 
     ///Hover event, used to adjust the screen cursor based on the widget or game object under the mouse
@@ -1198,6 +1758,20 @@ impl Client {
         let p = self.screen_to_viewport(coord);
         self.cursor_pos = p;
 
+        if let Some(widget) = &mut self.dialogue_widget {
+            widget.hover(p);
+        }
+
+        self.hovered_button = self
+            .button_widgets
+            .iter()
+            .find(|(_, widget)| {
+                !widget.disabled
+                    && !self.is_widget_hidden(&widget.name)
+                    && widget.rect.contains(Vec2::new(p.x as f32, p.y as f32))
+            })
+            .map(|(id, _)| *id);
+
         // Temporary, we have to make this widget dependent
         self.curr_cursor = self.default_cursor;
         self.hovered_entity_id = None;
@@ -1289,6 +1863,28 @@ impl Client {
             self.curr_cursor = self.default_cursor;
         }
 
+        // Clicks focus the text input under them and unfocus all others
+        let p = self.screen_to_viewport(coord);
+        let mut clicked_input = false;
+        for widget in self.input_widgets.values_mut() {
+            let inside = widget.rect.contains(Vec2::new(p.x as f32, p.y as f32));
+            widget.set_focus(inside);
+            if inside {
+                widget.touch_down(p.x as f32);
+            }
+            clicked_input |= inside;
+        }
+        if clicked_input {
+            return None;
+        }
+
+        // An open dialogue takes the clicks inside of it
+        if let Some(widget) = &mut self.dialogue_widget {
+            if let Some(choice) = widget.touch_down(self.screen_to_viewport(coord)) {
+                return choice.map(EntityAction::Choice);
+            }
+        }
+
         // If we hovered over an item in 3D, send an explicit ItemClicked intent
         if let Some(item_id) = self.hovered_item_id {
             return Some(EntityAction::ItemClicked(
@@ -1302,8 +1898,16 @@ impl Client {
         let p = self.screen_to_viewport(coord);
 
         for (id, widget) in self.button_widgets.iter() {
+            if widget.disabled {
+                continue;
+            }
             if widget.rect.contains(Vec2::new(p.x as f32, p.y as f32)) {
                 self.activated_widgets.push(*id);
+                self.pressed_button = Some(*id);
+
+                if let Some(sound) = &widget.sound {
+                    self.sound_manager.play_ui(sound, 1.0);
+                }
 
                 if self.game_widget_is_2d() {
                     if let Some(intent) = &widget.intent {
@@ -1426,6 +2030,7 @@ impl Client {
     /// Click / touch up event
     pub fn touch_up(&mut self, _coord: Vec2<i32>, _map: &Map) {
         self.activated_widgets = self.permanently_activated_widgets.clone();
+        self.pressed_button = None;
 
         // Adjust cursor
         if self.curr_intent_cursor.is_some() {
@@ -1437,15 +2042,67 @@ impl Client {
         for widget in self.messages_widget.iter_mut() {
             widget.touch_up();
         }
+        for widget in self.input_widgets.values_mut() {
+            widget.touch_up();
+        }
     }
 
     pub fn user_event(&mut self, event: String, value: Value) -> EntityAction {
+        let action = self.user_event_action(event, value);
+        self.predict(&action);
+        action
+    }
+
+    /// Move the predicted player with the action sent to the server.
+    fn predict(&mut self, action: &EntityAction) {
+        if let Some(prediction) = &mut self.prediction {
+            prediction.set_action(action);
+        }
+    }
+
+    fn user_event_action(&mut self, event: String, value: Value) -> EntityAction {
         // Make sure we do not send action events after a key down intent was handled
         // Otherwise the character would move a bit because "intent" is already cleared
         if event == "key_up" {
             self.key_down_intent = None;
         }
 
+        // --- A focused text input takes all key events
+
+        if let Value::Str(key) = &value {
+            if let Some(widget) = self.input_widgets.values_mut().find(|w| w.focused) {
+                match event.as_str() {
+                    "key_down" => {
+                        return widget
+                            .key_down(key, &mut self.clipboard)
+                            .unwrap_or(EntityAction::Off);
+                    }
+                    "key_up" => widget.key_up(key),
+                    "text" => widget.insert(key),
+                    _ => {}
+                }
+                return EntityAction::Off;
+            }
+            if event == "key_down" {
+                let key = normalize_key(key);
+                if let Some(widget) = self
+                    .input_widgets
+                    .values_mut()
+                    .find(|w| w.focus_key.as_ref() == Some(&key))
+                {
+                    widget.set_focus(true);
+                    return EntityAction::Off;
+                }
+            }
+        }
+
+        if let ("key_down", Value::Str(key)) = (&event[..], &value) {
+            if normalize_key(key) == self.debug.key {
+                self.toggle_debug_overlay();
+                return EntityAction::Off;
+            }
+        }
+
         if event == "key_down" {
             if let Some(key_down_intent) = &self.key_down_intent {
                 if !key_down_intent.is_empty() {
@@ -1458,6 +2115,35 @@ impl Client {
             self.key_down_intent = Some(self.intent.clone());
         }
 
+        // --- Check for an open dialogue
+
+        if let Some(widget) = &mut self.dialogue_widget {
+            if widget.is_active() && event == "key_down" {
+                if let Value::Str(key) = &value {
+                    let key = normalize_key(key);
+                    let choice = match key.as_str() {
+                        "up" | "arrowup" => {
+                            widget.select(-1);
+                            None
+                        }
+                        "down" | "arrowdown" => {
+                            widget.select(1);
+                            None
+                        }
+                        "enter" | "space" => widget.confirm(),
+                        "escape" | "0" => widget.cancel(),
+                        _ => match key.parse::<usize>() {
+                            Ok(number) => widget.pick(number),
+                            Err(_) => return EntityAction::Off,
+                        },
+                    };
+                    return choice
+                        .map(EntityAction::Choice)
+                        .unwrap_or(EntityAction::Off);
+                }
+            }
+        }
+
         // --- Check for multiple choice
 
         if let Some(choice_map) = &self.choice_map.clone() {
@@ -1466,7 +2152,7 @@ impl Client {
                     if let Some(c) = v.chars().next() {
                         if let Some(choice) = choice_map.get(&c) {
                             // println!("selected {:?}", choice);
-                            if matches!(choice, Choice::Cancel(_, _)) {
+                            if matches!(choice, Choice::Cancel(..) | Choice::Dialogue(..)) {
                                 self.choice_map = None;
                             }
                             return EntityAction::Choice(choice.clone());
@@ -1476,9 +2162,23 @@ impl Client {
             }
         }
 
-        // ---
+        // --- Check for key bindings, they take precedence over the user_event script
+
+        let binding = match (&event[..], &value) {
+            ("key_down" | "key_up", Value::Str(key)) => self.keymap.binding(key),
+            _ => None,
+        };
 
-        let action = self.client_action.lock().unwrap().user_event(event, value);
+        let action = match binding {
+            Some(InputBinding::Action(action)) if event == "key_down" => action,
+            Some(InputBinding::Action(_)) => EntityAction::Off,
+            Some(InputBinding::Intent(intent)) if event == "key_down" => {
+                self.intent = intent.clone();
+                return EntityAction::Intent(intent);
+            }
+            Some(_) => return EntityAction::Off,
+            None => self.client_action.lock().unwrap().user_event(event, value),
+        };
 
         let action_str: String = action.to_string();
         if action_str == "none" {
@@ -1494,6 +2194,317 @@ impl Client {
         action
     }
 
+    /// Show or hide the debug overlay, see `DebugOverlay`.
+    pub fn toggle_debug_overlay(&mut self) {
+        self.debug.toggle();
+    }
+
+    /// Is the debug overlay visible.
+    pub fn debug_overlay(&self) -> bool {
+        self.debug.visible
+    }
+
+    /// Committed text of the host (i.e. the result of an IME composition) for the focused
+    /// text input. Returns false if no text input has the focus.
+    pub fn text_input(&mut self, text: &str) -> bool {
+        match self.input_widgets.values_mut().find(|w| w.focused) {
+            Some(widget) => {
+                widget.insert(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The current language of the string tables.
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Switch the language at runtime, the widgets pick it up on their next draw.
+    pub fn set_language(&mut self, language: &str) {
+        self.language = language.to_string();
+        self.apply_language();
+    }
+
+    /// Translate the key into the current language, see `Assets::tr`.
+    pub fn tr(&self, assets: &Assets, key: &str, args: &[(&str, &str)]) -> String {
+        assets.tr(&self.language, key, args)
+    }
+
+    fn apply_language(&mut self) {
+        if let Some(widget) = &mut self.messages_widget {
+            widget.resolver.set_locale(&self.language);
+        }
+        if let Some(widget) = &mut self.dialogue_widget {
+            widget.resolver.set_locale(&self.language);
+        }
+        for widget in self.text_widgets.values_mut() {
+            widget.language = self.language.clone();
+        }
+    }
+
+    /// The current key bindings.
+    pub fn key_bindings(&self) -> &KeyMap {
+        &self.keymap
+    }
+
+    /// Bind the keys to an action or intent name at runtime, see `KeyMap`.
+    pub fn rebind_key(&mut self, name: &str, keys: Vec<String>) {
+        self.keymap.rebind(name, keys);
+    }
+
+    /// The key bindings as TOML, for the host to persist the user changes.
+    pub fn save_key_bindings(&self) -> String {
+        self.keymap.to_toml()
+    }
+
+    /// Apply key bindings persisted with `save_key_bindings`, call after `setup`.
+    pub fn load_key_bindings(&mut self, toml: &str) -> Result<(), String> {
+        self.keymap.load_toml(toml)
+    }
+
+    /// Gamepad axis event, see `GamepadMapping` for the axis names. Returns the analog
+    /// movement of the player if the movement stick changed.
+    pub fn gamepad_axis(&mut self, axis: &str, value: f32) -> Option<EntityAction> {
+        let movement = self.gamepad.axis(axis, value)?;
+        let action = if movement == Vec2::zero() {
+            EntityAction::Off
+        } else {
+            EntityAction::Analog(movement)
+        };
+        self.predict(&action);
+        Some(action)
+    }
+
+    /// Gamepad button event, see `GamepadMapping` for the button names. Returns the action of
+    /// the button binding, releasing a button bound to an action stops it.
+    pub fn gamepad_button(
+        &mut self,
+        button: &str,
+        pressed: bool,
+        map: &Map,
+    ) -> Option<EntityAction> {
+        let binding = self.gamepad.binding(button)?.clone();
+        self.apply_binding(binding, pressed, map)
+    }
+
+    /// Touch start of a multi touch device, `id` identifies the finger until `touch_end`.
+    /// Touches on the on-screen controls move the player or press their button, all others
+    /// hover and click the widgets below them.
+    pub fn touch_start(
+        &mut self,
+        id: u64,
+        coord: Vec2<i32>,
+        map: &Map,
+        scene_handler: &mut SceneHandler,
+    ) -> Option<EntityAction> {
+        let p = self.screen_to_viewport(coord).as_::<f32>();
+        match self.touch.start(id, p, self.viewport.as_::<f32>()) {
+            TouchEvent::Pointer => {
+                // Touches have no hover, pick what is below the finger first
+                self.touch_hover(coord, map, scene_handler);
+                self.touch_down(coord, map)
+            }
+            event => self.apply_touch_event(event, map),
+        }
+    }
+
+    /// Touch move of a multi touch device.
+    pub fn touch_move(
+        &mut self,
+        id: u64,
+        coord: Vec2<i32>,
+        map: &Map,
+        scene_handler: &mut SceneHandler,
+    ) -> Option<EntityAction> {
+        let p = self.screen_to_viewport(coord).as_::<f32>();
+        match self.touch.moved(id, p, self.viewport.as_::<f32>()) {
+            TouchEvent::Pointer => {
+                self.touch_dragged(coord, map, scene_handler);
+                None
+            }
+            event => self.apply_touch_event(event, map),
+        }
+    }
+
+    /// Touch end of a multi touch device.
+    pub fn touch_end(&mut self, id: u64, coord: Vec2<i32>, map: &Map) -> Option<EntityAction> {
+        match self.touch.end(id) {
+            TouchEvent::Pointer => {
+                self.touch_up(coord, map);
+                None
+            }
+            event => self.apply_touch_event(event, map),
+        }
+    }
+
+    /// The action of a joystick or button event of the touch controls.
+    fn apply_touch_event(&mut self, event: TouchEvent, map: &Map) -> Option<EntityAction> {
+        match event {
+            TouchEvent::Movement(movement) => {
+                let action = if movement == Vec2::zero() {
+                    EntityAction::Off
+                } else {
+                    EntityAction::Analog(movement)
+                };
+                self.predict(&action);
+                Some(action)
+            }
+            TouchEvent::Button(binding, pressed) => self.apply_binding(binding, pressed, map),
+            TouchEvent::None | TouchEvent::Pointer => None,
+        }
+    }
+
+    /// The action of a gamepad or touch button binding, releasing a button bound to an action
+    /// stops it.
+    fn apply_binding(
+        &mut self,
+        binding: InputBinding,
+        pressed: bool,
+        map: &Map,
+    ) -> Option<EntityAction> {
+        let action = if !pressed {
+            match binding {
+                InputBinding::Action(_) => Some(EntityAction::Off),
+                _ => None,
+            }
+        } else {
+            match binding {
+                InputBinding::Action(action) => Some(action),
+                InputBinding::Intent(intent) => {
+                    self.intent = intent.clone();
+                    Some(EntityAction::Intent(intent))
+                }
+                InputBinding::Ui(nav) => self.navigate(nav, map),
+            }
+        };
+        if let Some(action) = &action {
+            self.predict(action);
+        }
+        action
+    }
+
+    /// Navigate the button widgets: the directions move the focus to the nearest visible
+    /// button in that direction, confirm clicks the focused button and cancel closes an open
+    /// multiple choice.
+    pub fn navigate(&mut self, nav: UiNav, map: &Map) -> Option<EntityAction> {
+        let center = |rect: &Rect| Vec2::new(rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+
+        // An open dialogue takes the navigation
+        if let Some(widget) = &mut self.dialogue_widget {
+            if widget.is_active() {
+                let choice = match nav {
+                    UiNav::Up => {
+                        widget.select(-1);
+                        None
+                    }
+                    UiNav::Down => {
+                        widget.select(1);
+                        None
+                    }
+                    UiNav::Confirm => widget.confirm(),
+                    UiNav::Cancel => widget.cancel(),
+                    UiNav::Left | UiNav::Right => None,
+                };
+                return choice.map(EntityAction::Choice);
+            }
+        }
+
+        match nav {
+            UiNav::Confirm => {
+                let rect = self.button_widgets.get(&self.focused_widget?)?.rect;
+                let p = center(&rect);
+                let coord = Vec2::new(
+                    (p.x * self.upscale_factor.x) as i32 + self.target_offset.x,
+                    (p.y * self.upscale_factor.y) as i32 + self.target_offset.y,
+                );
+                let action = self.touch_down(coord, map);
+                self.touch_up(coord, map);
+                action
+            }
+            UiNav::Cancel => {
+                let cancel = self
+                    .choice_map
+                    .as_ref()?
+                    .values()
+                    .find(|choice| matches!(choice, Choice::Cancel(..)))?
+                    .clone();
+                self.choice_map = None;
+                Some(EntityAction::Choice(cancel))
+            }
+            _ => {
+                let direction = match nav {
+                    UiNav::Up => Vec2::new(0.0, -1.0),
+                    UiNav::Down => Vec2::new(0.0, 1.0),
+                    UiNav::Left => Vec2::new(-1.0, 0.0),
+                    _ => Vec2::new(1.0, 0.0),
+                };
+                let from = self
+                    .focused_widget
+                    .and_then(|id| self.button_widgets.get(&id))
+                    .map(|widget| center(&widget.rect));
+
+                // Without a focus we start at the top left button
+                let mut best: Option<(f32, u32)> = None;
+                for (id, widget) in &self.button_widgets {
+                    if widget.disabled || self.is_widget_hidden(&widget.name) {
+                        continue;
+                    }
+                    let to = center(&widget.rect);
+                    let score = match from {
+                        Some(from) => {
+                            let delta = to - from;
+                            let along = delta.dot(direction);
+                            if along <= 0.0 {
+                                continue;
+                            }
+                            // Prefer buttons in line with the direction
+                            along + (delta - direction * along).magnitude() * 2.0
+                        }
+                        None => to.x + to.y,
+                    };
+                    if best.is_none_or(|(best, _)| score < best) {
+                        best = Some((score, *id));
+                    }
+                }
+                if let Some((_, id)) = best {
+                    self.focused_widget = Some(id);
+                }
+                None
+            }
+        }
+    }
+
+    /// Enable or disable the button widgets of the given name, disabled buttons ignore clicks
+    /// and the UI navigation.
+    pub fn set_button_enabled(&mut self, name: &str, enabled: bool) {
+        for (id, widget) in self.button_widgets.iter_mut() {
+            if widget.name == name {
+                widget.disabled = !enabled;
+                if !enabled {
+                    if self.focused_widget == Some(*id) {
+                        self.focused_widget = None;
+                    }
+                    if self.hovered_button == Some(*id) {
+                        self.hovered_button = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if the widget of the given name is hidden by a button.
+    fn is_widget_hidden(&self, name: &str) -> bool {
+        self.widgets_to_hide.iter().any(|pattern| {
+            if let Some(prefix) = pattern.strip_suffix('*') {
+                name.starts_with(prefix)
+            } else {
+                name == pattern
+            }
+        })
+    }
+
     // Init the screen
     pub fn init_screen(
         &mut self,
@@ -1505,7 +2516,26 @@ impl Client {
         self.button_widgets.clear();
         self.text_widgets.clear();
         self.deco_widgets.clear();
+        self.bar_widgets.clear();
+        self.input_widgets.clear();
+        self.touch.clear();
+        self.tweens.clear();
+        // Keep the explored sectors of the minimaps across screens
+        let mut explored = FxHashMap::default();
+        for widget in self.minimap_widgets.values() {
+            for (map_id, sectors) in &widget.explored {
+                explored
+                    .entry(*map_id)
+                    .or_insert_with(FxHashSet::default)
+                    .extend(sectors.iter().copied());
+            }
+        }
+        self.minimap_widgets.clear();
         self.messages_widget = None;
+        self.dialogue_widget = None;
+        self.focused_widget = None;
+        self.hovered_button = None;
+        self.pressed_button = None;
 
         self.screen_widget = Some(ScreenWidget {
             buffer: TheRGBABuffer::new(TheDim::sized(self.viewport.x, self.viewport.y)),
@@ -1556,9 +2586,22 @@ impl Client {
                 // }
 
                 if let Some(crate::Value::Str(data)) = widget.properties.get("data") {
-                    if let Ok(table) = data.parse::<Table>() {
+                    if let Ok(mut table) = data.parse::<Table>() {
                         let grid_size = self.grid_size;
 
+                        // The style of the theme fills in the data of the widget
+                        let themed;
+                        let data = if self.themes.apply(&mut table) {
+                            themed = table.to_string();
+                            &themed
+                        } else {
+                            data
+                        };
+
+                        if let Some(tweens) = WidgetTweens::from_toml(&table) {
+                            self.tweens.insert(widget.name.clone(), tweens);
+                        }
+
                         let mut role = "none";
                         if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
                             if let Some(value) = ui.get("role") {
@@ -1592,6 +2635,10 @@ impl Client {
                             let mut hide: Option<Vec<String>> = None;
                             let mut deactivate: Vec<String> = vec![];
                             let mut inventory_index: Option<usize> = None;
+                            let mut sound = None;
+                            let mut frame = None;
+                            let mut styles = Default::default();
+                            let mut disabled = false;
 
                             let mut entity_cursor_id = None;
                             let mut entity_clicked_cursor_id = None;
@@ -1669,6 +2716,24 @@ impl Client {
                                     }
                                 }
 
+                                // Check for the click sound
+                                if let Some(value) = ui.get("sound") {
+                                    if let Some(v) = value.as_str() {
+                                        sound = Some(v.to_string());
+                                    }
+                                }
+
+                                // Check for the 9-slice frame
+                                frame = FrameTile::from_ui(ui, assets);
+
+                                // Check for the tiles and tints of the states
+                                styles = ButtonStyle::from_ui(ui, assets);
+                                if let Some(value) = ui.get("disabled") {
+                                    if let Some(v) = value.as_bool() {
+                                        disabled = v;
+                                    }
+                                }
+
                                 // Check for inventory
                                 if let Some(value) = ui.get("inventory_index") {
                                     if let Some(v) = value.as_integer() {
@@ -1695,6 +2760,10 @@ impl Client {
                                 hide,
                                 deactivate,
                                 inventory_index,
+                                sound,
+                                frame,
+                                styles,
+                                disabled,
                                 textures,
                                 entity_cursor_id,
                                 entity_clicked_cursor_id,
@@ -1741,11 +2810,82 @@ impl Client {
                             };
                             deco_widget.init(assets);
                             self.deco_widgets.insert(widget.creator_id, deco_widget);
+                        } else if role == "dialogue" {
+                            let mut widget = DialogueWidget {
+                                name: widget.name.clone(),
+                                rect: Rect::new(x, y, width, height),
+                                toml_str: data.clone(),
+                                ..Default::default()
+                            };
+                            widget.init(assets);
+                            self.dialogue_widget = Some(widget);
+                        } else if role == "input" {
+                            let mut input_widget = TextInputWidget {
+                                name: widget.name.clone(),
+                                rect: Rect::new(x, y, width, height),
+                                toml_str: data.clone(),
+                                ..Default::default()
+                            };
+                            input_widget.init(assets);
+                            self.input_widgets.insert(widget.creator_id, input_widget);
+                        } else if role == "bar" {
+                            let mut bar_widget = BarWidget {
+                                name: widget.name.clone(),
+                                rect: Rect::new(x, y, width, height),
+                                toml_str: data.clone(),
+                                ..Default::default()
+                            };
+                            bar_widget.init(assets);
+                            self.bar_widgets.insert(widget.creator_id, bar_widget);
+                        } else if role == "minimap" {
+                            let mut minimap_widget = MinimapWidget {
+                                name: widget.name.clone(),
+                                rect: Rect::new(x, y, width, height),
+                                toml_str: data.clone(),
+                                buffer: TheRGBABuffer::new(TheDim::sized(
+                                    width as i32,
+                                    height as i32,
+                                )),
+                                explored: explored.clone(),
+                                ..Default::default()
+                            };
+                            minimap_widget.init(assets);
+                            self.minimap_widgets
+                                .insert(widget.creator_id, minimap_widget);
                         }
                     }
                 }
             }
         }
+
+        self.apply_language();
+    }
+
+    /// The names of the themes of the game config.
+    pub fn themes(&self) -> Vec<String> {
+        self.themes.names()
+    }
+
+    /// The active theme.
+    pub fn theme(&self) -> &str {
+        &self.themes.active
+    }
+
+    /// Switch the theme and rebuild the widgets of the current screen with it. Returns false
+    /// if there is no theme of that name.
+    pub fn set_theme(
+        &mut self,
+        name: &str,
+        assets: &mut Assets,
+        scene_handler: &mut SceneHandler,
+    ) -> bool {
+        if !self.themes.set_active(name) {
+            return false;
+        }
+        if assets.screens.contains_key(&self.current_screen) {
+            self.init_screen(self.current_screen.clone(), assets, scene_handler);
+        }
+        true
     }
 
     /// Returns true if the game camera is 2D
@@ -0,0 +1,133 @@
+use crate::{Map, ParticleEmitter, ParticleEvent, Value};
+use theframework::prelude::*;
+
+/// The owner of an attached emitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum EmitterOwner {
+    Item(u32),
+    Entity(u32),
+}
+
+/// The particles of the client. Emitters come from the `particles` attribute of items and
+/// entities, which follow their owner, and from the particle events of the server, which emit
+/// for their duration.
+#[derive(Default)]
+pub struct ParticleSystem {
+    attached: FxHashMap<EmitterOwner, ParticleEmitter>,
+    events: Vec<(ParticleEmitter, f32)>,
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.attached.clear();
+        self.events.clear();
+    }
+
+    /// Add the particle effects of the server.
+    pub fn add_events(&mut self, events: Vec<ParticleEvent>) {
+        for event in events {
+            self.events.push((event.emitter, event.duration));
+        }
+    }
+
+    /// Sync the attached emitters with the map and advance all particles by dt seconds.
+    pub fn update(&mut self, map: &Map, dt: f32) {
+        let mut owners = FxHashSet::default();
+        let attached = map
+            .items
+            .iter()
+            .map(|item| (EmitterOwner::Item(item.id), &item.attributes, item.position))
+            .chain(map.entities.iter().map(|entity| {
+                (
+                    EmitterOwner::Entity(entity.id),
+                    &entity.attributes,
+                    entity.position,
+                )
+            }));
+        for (owner, attributes, position) in attached {
+            if let Some(Value::ParticleEmitter(emitter)) = attributes.get("particles") {
+                let emitter = self
+                    .attached
+                    .entry(owner)
+                    .or_insert_with(|| emitter.clone());
+                emitter.origin = position;
+                owners.insert(owner);
+            }
+        }
+        self.attached.retain(|owner, _| owners.contains(owner));
+
+        for emitter in self.attached.values_mut() {
+            emitter.update(dt);
+        }
+
+        // Events stop emitting after their duration and are dropped once their particles died
+        self.events.retain_mut(|(emitter, remaining)| {
+            *remaining -= dt;
+            if *remaining <= 0.0 {
+                emitter.rate = 0.0;
+            }
+            emitter.update(dt);
+            *remaining > 0.0 || !emitter.particles.is_empty()
+        });
+    }
+
+    /// All active emitters.
+    pub fn emitters(&self) -> impl Iterator<Item = &ParticleEmitter> {
+        self.attached
+            .values()
+            .chain(self.events.iter().map(|(emitter, _)| emitter))
+    }
+
+    /// Draw the particles of a top-down view into the buffer. `top_left` is the world position
+    /// of the top left pixel, `scale` the pixels per world unit. The height of a particle
+    /// raises it on the screen.
+    pub fn draw_2d(&self, buffer: &mut TheRGBABuffer, top_left: Vec2<f32>, scale: f32) {
+        let width = buffer.dim().width as isize;
+        let height = buffer.dim().height as isize;
+        let pixels = buffer.pixels_mut();
+
+        for emitter in self.emitters() {
+            for particle in &emitter.particles {
+                let cx = (particle.pos.x - top_left.x) * scale;
+                let cy = (particle.pos.z - particle.pos.y - top_left.y) * scale;
+                let radius = (particle.radius * scale).max(0.5);
+
+                // Fade out during the last moments of the particle
+                let fade = (particle.lifetime / 0.3).clamp(0.0, 1.0);
+                let alpha = particle.color[3] as f32 / 255.0 * fade;
+
+                let x0 = ((cx - radius).floor() as isize).max(0);
+                let x1 = ((cx + radius).ceil() as isize).min(width - 1);
+                let y0 = ((cy - radius).floor() as isize).max(0);
+                let y1 = ((cy + radius).ceil() as isize).min(height - 1);
+
+                for y in y0..=y1 {
+                    for x in x0..=x1 {
+                        let dx = x as f32 + 0.5 - cx;
+                        let dy = y as f32 + 0.5 - cy;
+                        let d = (dx * dx + dy * dy).sqrt();
+                        if d > radius {
+                            continue;
+                        }
+                        // Soft edge
+                        let a = alpha * (radius - d).min(1.0);
+                        let i = (y * width + x) as usize * 4;
+                        for c in 0..3 {
+                            let src = particle.color[c] as f32 * a;
+                            let dst = pixels[i + c] as f32;
+                            pixels[i + c] = if emitter.additive {
+                                (dst + src).min(255.0) as u8
+                            } else {
+                                (dst * (1.0 - a) + src) as u8
+                            };
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use rayon::prelude::*;
+use theframework::prelude::*;
+
+type Mat3 = [[f32; 3]; 3];
+
+const IDENTITY: Mat3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// A color vision deficiency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBlindness {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    /// The simulation of the deficiency in linear RGB (Machado et al. 2009, full severity).
+    fn simulation(&self) -> Mat3 {
+        match self {
+            ColorBlindness::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+
+    /// Where the correction moves the colors which are lost, into the channels which are
+    /// still seen.
+    fn shift(&self) -> Mat3 {
+        match self {
+            ColorBlindness::Protanopia | ColorBlindness::Deuteranopia => {
+                [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]]
+            }
+            ColorBlindness::Tritanopia => [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]],
+        }
+    }
+}
+
+/// The accessibility filters applied to the final frame, read from the `[accessibility]`
+/// table of the game config. See `src/client/CONFIG.md` for an example.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostFilterSettings {
+    pub color_blindness: Option<ColorBlindness>,
+    /// Simulate the deficiency instead of correcting for it.
+    pub simulate: bool,
+    pub strength: f32,
+    pub high_contrast: bool,
+    pub gamma: f32,
+}
+
+impl Default for PostFilterSettings {
+    fn default() -> Self {
+        Self {
+            color_blindness: None,
+            simulate: false,
+            strength: 1.0,
+            high_contrast: false,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl PostFilterSettings {
+    /// Read the settings from the config. Returns the invalid values as errors.
+    pub fn from_config(config: &toml::Table) -> (Self, Vec<String>) {
+        let mut settings = Self::default();
+        let mut errors = vec![];
+
+        let Some(table) = config.get("accessibility").and_then(toml::Value::as_table) else {
+            return (settings, errors);
+        };
+
+        let float = |key: &str| {
+            table
+                .get(key)
+                .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                .map(|v| v as f32)
+        };
+
+        if let Some(name) = table.get("color_blindness").and_then(toml::Value::as_str) {
+            match name.to_lowercase().as_str() {
+                "none" | "" => {}
+                "protanopia" => settings.color_blindness = Some(ColorBlindness::Protanopia),
+                "deuteranopia" => settings.color_blindness = Some(ColorBlindness::Deuteranopia),
+                "tritanopia" => settings.color_blindness = Some(ColorBlindness::Tritanopia),
+                _ => errors.push(format!("Unknown color blindness '{}'.", name)),
+            }
+        }
+        if let Some(mode) = table.get("color_blind_mode").and_then(toml::Value::as_str) {
+            match mode {
+                "correct" => settings.simulate = false,
+                "simulate" => settings.simulate = true,
+                _ => errors.push(format!("Unknown color blind mode '{}'.", mode)),
+            }
+        }
+        if let Some(strength) = float("strength") {
+            settings.strength = strength.clamp(0.0, 1.0);
+        }
+        if let Some(high_contrast) = table.get("high_contrast").and_then(toml::Value::as_bool) {
+            settings.high_contrast = high_contrast;
+        }
+        if let Some(gamma) = float("gamma") {
+            settings.gamma = gamma.clamp(0.1, 5.0);
+        }
+        (settings, errors)
+    }
+}
+
+/// The compiled post filters: the color blindness filter is a single matrix in linear RGB,
+/// contrast and gamma a lookup table per channel.
+#[derive(Debug, Clone)]
+pub struct PostFilter {
+    pub settings: PostFilterSettings,
+    matrix: Option<Mat3>,
+    tone: Option<[u8; 256]>,
+    to_linear: Vec<f32>,
+    // sRGB values of 4096 linear steps
+    from_linear: Vec<u8>,
+}
+
+impl Default for PostFilter {
+    fn default() -> Self {
+        Self::new(PostFilterSettings::default())
+    }
+}
+
+impl PostFilter {
+    pub fn new(settings: PostFilterSettings) -> Self {
+        let matrix = settings.color_blindness.and_then(|blindness| {
+            let simulation = blindness.simulation();
+            let target = if settings.simulate {
+                simulation
+            } else {
+                // Add the lost difference back into the seen channels:
+                // c + shift * (c - simulation * c)
+                let error = sub(&IDENTITY, &simulation);
+                add(&IDENTITY, &mul(&blindness.shift(), &error))
+            };
+            let matrix = lerp(&IDENTITY, &target, settings.strength);
+            (matrix != IDENTITY).then_some(matrix)
+        });
+
+        let contrast = if settings.high_contrast { 1.6 } else { 1.0 };
+        let tone = (contrast != 1.0 || settings.gamma != 1.0).then(|| {
+            let mut tone = [0; 256];
+            for (i, t) in tone.iter_mut().enumerate() {
+                let c = ((i as f32 / 255.0 - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+                *t = (c.powf(1.0 / settings.gamma) * 255.0).round() as u8;
+            }
+            tone
+        });
+
+        let (to_linear, from_linear) = if matrix.is_some() {
+            (
+                (0..256).map(|i| srgb_to_linear(i as f32 / 255.0)).collect(),
+                (0..4096)
+                    .map(|i| (linear_to_srgb(i as f32 / 4095.0) * 255.0).round() as u8)
+                    .collect(),
+            )
+        } else {
+            (vec![], vec![])
+        };
+
+        Self {
+            settings,
+            matrix,
+            tone,
+            to_linear,
+            from_linear,
+        }
+    }
+
+    /// True if the filter leaves the colors unchanged.
+    pub fn is_identity(&self) -> bool {
+        self.matrix.is_none() && self.tone.is_none()
+    }
+
+    /// Filter the buffer in place, alpha is kept.
+    pub fn apply(&self, buffer: &mut TheRGBABuffer) {
+        if self.is_identity() {
+            return;
+        }
+        let width = buffer.dim().width.max(1) as usize;
+        buffer
+            .pixels_mut()
+            .par_chunks_mut(width * 4)
+            .for_each(|row| {
+                for pixel in row.chunks_exact_mut(4) {
+                    if let Some(m) = &self.matrix {
+                        let c = [
+                            self.to_linear[pixel[0] as usize],
+                            self.to_linear[pixel[1] as usize],
+                            self.to_linear[pixel[2] as usize],
+                        ];
+                        for (i, w) in m.iter().enumerate() {
+                            let v = w[0] * c[0] + w[1] * c[1] + w[2] * c[2];
+                            pixel[i] = self.from_linear[(v.clamp(0.0, 1.0) * 4095.0) as usize];
+                        }
+                    }
+                    if let Some(tone) = &self.tone {
+                        for c in pixel.iter_mut().take(3) {
+                            *c = tone[*c as usize];
+                        }
+                    }
+                }
+            });
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn add(a: &Mat3, b: &Mat3) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| a[i][j] + b[i][j]))
+}
+
+fn sub(a: &Mat3, b: &Mat3) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| a[i][j] - b[i][j]))
+}
+
+fn lerp(a: &Mat3, b: &Mat3, t: f32) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| a[i][j] + (b[i][j] - a[i][j]) * t))
+}
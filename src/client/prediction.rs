@@ -0,0 +1,125 @@
+use crate::{Assets, EntityAction, Map, MapMini};
+use theframework::prelude::*;
+
+/// The client side prediction of the player movement, read from the `[prediction]` table of
+/// the game config. See `src/client/CONFIG.md` for an example.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictionSettings {
+    pub speed: f32,
+    pub latency: f32,
+    pub correction: f32,
+    pub snap_distance: f32,
+}
+
+impl Default for PredictionSettings {
+    fn default() -> Self {
+        Self {
+            speed: 4.0,
+            latency: 0.25,
+            correction: 0.15,
+            snap_distance: 2.0,
+        }
+    }
+}
+
+/// Moves the player at once on input, colliding with the walls of the map, instead of waiting
+/// for the server. The predicted position is reconciled with the position of the server: it
+/// is pulled towards it once the player stops, or while moving if it is off by more than the
+/// lag explains, and snaps to it after teleports.
+pub struct PlayerPrediction {
+    pub settings: PredictionSettings,
+    mini: MapMini,
+    map_id: Option<Uuid>,
+    movement: Vec2<f32>,
+    predicted: Option<(u32, Vec2<f32>)>,
+}
+
+impl PlayerPrediction {
+    pub fn new(settings: PredictionSettings) -> Self {
+        Self {
+            settings,
+            mini: MapMini::default(),
+            map_id: None,
+            movement: Vec2::zero(),
+            predicted: None,
+        }
+    }
+
+    /// Drop the prediction, i.e. after a region change.
+    pub fn clear(&mut self) {
+        self.movement = Vec2::zero();
+        self.predicted = None;
+    }
+
+    /// Record the action sent to the server, actions which do not walk stop the player.
+    pub fn set_action(&mut self, action: &EntityAction) {
+        self.movement = match action {
+            EntityAction::Forward => Vec2::new(0.0, -1.0),
+            EntityAction::Backward => Vec2::new(0.0, 1.0),
+            EntityAction::Left => Vec2::new(-1.0, 0.0),
+            EntityAction::Right => Vec2::new(1.0, 0.0),
+            EntityAction::Analog(direction) if direction.magnitude() > 1.0 => {
+                direction.normalized()
+            }
+            EntityAction::Analog(direction) => *direction,
+            _ => Vec2::zero(),
+        };
+    }
+
+    /// Advance the predicted player by dt seconds. Returns the player and the position to
+    /// render it at. `walking` is false while the movement keys do something else than
+    /// walking, i.e. while an intent is set or in the first person view.
+    pub fn update(
+        &mut self,
+        map: &Map,
+        assets: &Assets,
+        walking: bool,
+        dt: f32,
+    ) -> Option<(u32, Vec3<f32>)> {
+        if self.map_id != Some(map.id) {
+            self.mini = map.as_mini(&assets.blocking_tiles());
+            self.map_id = Some(map.id);
+            self.predicted = None;
+        }
+        let player = map.entities.iter().find(|entity| entity.is_player())?;
+        let server = player.get_pos_xz();
+
+        let mut position = match self.predicted {
+            Some((id, position))
+                if id == player.id && position.distance(server) <= self.settings.snap_distance =>
+            {
+                position
+            }
+            _ => server,
+        };
+
+        let moving = walking && self.movement != Vec2::zero();
+        if moving {
+            let radius = player.attributes.get_float_default("radius", 0.5) - 0.01;
+            let step = self.movement * self.settings.speed * dt;
+            position = self.mini.move_distance(position, step, radius).0;
+        }
+
+        // The server trails the prediction by its lag while moving
+        let error = server - position;
+        let tolerance = if moving {
+            self.settings.speed * self.settings.latency
+        } else {
+            0.0
+        };
+        if error.magnitude() > tolerance {
+            let blend = if self.settings.correction > 0.0 {
+                1.0 - (-dt / self.settings.correction).exp()
+            } else {
+                1.0
+            };
+            position += error * blend;
+        }
+
+        self.predicted = Some((player.id, position));
+        Some((
+            player.id,
+            Vec3::new(position.x, player.position.y, position.y),
+        ))
+    }
+}
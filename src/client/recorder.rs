@@ -0,0 +1,108 @@
+use crate::Texture;
+use image::{Delay, Frame, RgbaImage, codecs::gif::GifEncoder, codecs::gif::Repeat};
+use std::collections::VecDeque;
+use std::io::Cursor;
+use theframework::prelude::*;
+
+/// Keeps the last seconds of the composited frames in a ring buffer so that a clip of what
+/// just happened can be exported on demand, as an animated GIF or as a PNG sequence.
+pub struct FrameRecorder {
+    /// Frames per second of the game.
+    pub fps: usize,
+    /// Only every nth frame is kept, reduces memory and the size of the GIF.
+    pub every: usize,
+    /// Downscale factor of the kept frames.
+    pub downscale: usize,
+
+    capacity: usize,
+    frames: VecDeque<Texture>,
+    counter: usize,
+}
+
+impl FrameRecorder {
+    pub fn new(seconds: f32, fps: usize, every: usize) -> Self {
+        let fps = fps.max(1);
+        let every = every.max(1);
+        Self {
+            fps,
+            every,
+            downscale: 1,
+            capacity: ((seconds.max(0.0) * fps as f32) as usize / every).max(1),
+            frames: VecDeque::new(),
+            counter: 0,
+        }
+    }
+
+    /// The number of kept frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.counter = 0;
+    }
+
+    /// Record the frame, dropping the oldest one when the buffer is full.
+    pub fn record(&mut self, buffer: &TheRGBABuffer) {
+        self.counter = self.counter.wrapping_add(1);
+        if self.counter % self.every != 0 {
+            return;
+        }
+
+        let mut texture = Texture::from_rgbabuffer(buffer);
+        if self.downscale > 1 {
+            texture = texture.resized(
+                (texture.width / self.downscale).max(1),
+                (texture.height / self.downscale).max(1),
+            );
+        }
+
+        if self.frames.len() >= self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(texture);
+    }
+
+    /// The kept frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &Texture> {
+        self.frames.iter()
+    }
+
+    /// Encode the kept frames as a looping animated GIF.
+    pub fn export_gif(&self) -> Result<Vec<u8>, String> {
+        if self.frames.is_empty() {
+            return Err("No frames recorded.".into());
+        }
+
+        let delay = Delay::from_numer_denom_ms(1000 * self.every as u32, self.fps as u32);
+        let mut buf = Vec::new();
+        {
+            let mut encoder = GifEncoder::new_with_speed(Cursor::new(&mut buf), 10);
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(|err| err.to_string())?;
+            for texture in &self.frames {
+                let img = RgbaImage::from_raw(
+                    texture.width as u32,
+                    texture.height as u32,
+                    texture.data.clone(),
+                )
+                .ok_or("Frame data does not match its size.")?;
+                encoder
+                    .encode_frame(Frame::from_parts(img, 0, 0, delay))
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Encode the kept frames as PNGs, oldest first.
+    pub fn export_png_sequence(&self) -> Result<Vec<Vec<u8>>, String> {
+        self.frames.iter().map(Texture::to_png).collect()
+    }
+}
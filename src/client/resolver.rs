@@ -112,6 +112,16 @@ impl MsgResolver {
         }
     }
 
+    /// The language of the text keys.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Switch the language of the text keys.
+    pub fn set_locale(&mut self, locale: &str) {
+        self.locale = locale.to_string();
+    }
+
     fn adapter(&self) -> &dyn LocaleAdapter {
         if let Some(ad) = self.locales.get(&self.locale) {
             &**ad
@@ -130,11 +140,12 @@ impl MsgResolver {
             let rendered = match tok {
                 Tok::Plain(s) => s.clone(),
                 Tok::TextKey { key, opts } => {
-                    let base = if let Some(map) = assets.locales.get(&self.locale) {
-                        map.get(key).cloned().unwrap_or_else(|| key.clone())
-                    } else {
-                        key.clone()
-                    };
+                    // The options double as the arguments of the translation
+                    let args: Vec<(&str, &str)> = opts
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_str()))
+                        .collect();
+                    let base = assets.tr(&self.locale, key, &args);
                     Self::apply_case(&base, opts)
                 }
                 Tok::Num { val, opts } => Self::fmt_num(*val, opts),
@@ -0,0 +1,198 @@
+use crate::Pixel;
+use theframework::prelude::*;
+
+/// An inline icon of a rich text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RichIcon {
+    /// `[item:42]`, the tile of the item with the id on the map.
+    Item(u32),
+    /// `[tile:<uuid>]`, a tile of the assets.
+    Tile(Uuid),
+}
+
+/// A part of a rich text, text of one color or an icon.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RichPart {
+    Text(String, Pixel),
+    Icon(RichIcon),
+}
+
+/// A laid out part, `x` is the offset from the start of its line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RichRun {
+    pub x: f32,
+    pub width: f32,
+    pub part: RichPart,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RichLine {
+    pub runs: Vec<RichRun>,
+    pub width: f32,
+}
+
+/// The color of a name ("red") or a hex color ("#ff0000").
+pub fn named_color(name: &str) -> Option<Pixel> {
+    let color = match name {
+        "white" => [255, 255, 255, 255],
+        "black" => [0, 0, 0, 255],
+        "gray" | "grey" => [128, 128, 128, 255],
+        "red" => [220, 60, 60, 255],
+        "green" => [80, 200, 80, 255],
+        "blue" => [80, 120, 230, 255],
+        "yellow" => [230, 210, 70, 255],
+        "orange" => [240, 150, 50, 255],
+        "purple" => [170, 90, 210, 255],
+        "cyan" => [80, 210, 220, 255],
+        _ => {
+            let hex = name.strip_prefix('#')?;
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            match hex.len() {
+                6 => [channel(0)?, channel(2)?, channel(4)?, 255],
+                8 => [channel(0)?, channel(2)?, channel(4)?, channel(6)?],
+                _ => return None,
+            }
+        }
+    };
+    Some(color)
+}
+
+/// Parse the inline tags of a text: `[c=red]` / `[c=#ff0000]` switch the color until `[/c]`,
+/// `[item:id]` and `[tile:id]` insert icons. Unknown tags are kept as text.
+pub fn parse_rich_text(text: &str, default: Pixel) -> Vec<RichPart> {
+    let mut parts = vec![];
+    let mut color = default;
+    let mut current = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            break;
+        };
+        let tag = &rest[start + 1..end];
+
+        let action = if tag == "/c" {
+            Some((Some(default), None))
+        } else if let Some(name) = tag.strip_prefix("c=") {
+            named_color(name).map(|color| (Some(color), None))
+        } else if let Some(id) = tag.strip_prefix("item:") {
+            id.parse().ok().map(|id| (None, Some(RichIcon::Item(id))))
+        } else if let Some(id) = tag.strip_prefix("tile:") {
+            Uuid::parse_str(id)
+                .ok()
+                .map(|id| (None, Some(RichIcon::Tile(id))))
+        } else {
+            None
+        };
+
+        match action {
+            Some((new_color, icon)) => {
+                current.push_str(&rest[..start]);
+                if !current.is_empty() {
+                    parts.push(RichPart::Text(std::mem::take(&mut current), color));
+                }
+                if let Some(new_color) = new_color {
+                    color = new_color;
+                }
+                if let Some(icon) = icon {
+                    parts.push(RichPart::Icon(icon));
+                }
+            }
+            None => current.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    current.push_str(rest);
+    if !current.is_empty() {
+        parts.push(RichPart::Text(current, color));
+    }
+    parts
+}
+
+/// Word wrap the parts to the max width. Text is measured with the given function, icons are
+/// square with the icon size. Newlines break the line, words wider than a line get a line of
+/// their own.
+pub fn layout_rich_text(
+    parts: &[RichPart],
+    max_width: f32,
+    icon_size: f32,
+    measure: impl Fn(&str) -> f32,
+) -> Vec<RichLine> {
+    let mut lines = vec![];
+    let mut line = RichLine::default();
+
+    // Append to the line, text of the same color continues the last run
+    let push = |line: &mut RichLine, part: RichPart, width: f32| {
+        if let (Some(last), RichPart::Text(text, color)) = (line.runs.last_mut(), &part) {
+            if let RichPart::Text(last_text, last_color) = &mut last.part {
+                if last_color == color {
+                    last_text.push_str(text);
+                    last.width = measure(last_text);
+                    line.width = last.x + last.width;
+                    return;
+                }
+            }
+        }
+        line.runs.push(RichRun {
+            x: line.width,
+            width,
+            part,
+        });
+        line.width += width;
+    };
+
+    for part in parts {
+        match part {
+            RichPart::Icon(_) => {
+                if line.width + icon_size > max_width && !line.runs.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                push(&mut line, part.clone(), icon_size);
+            }
+            RichPart::Text(text, color) => {
+                for (index, paragraph) in text.split('\n').enumerate() {
+                    if index > 0 {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    // Words keep their leading space, which is dropped at the start of a line
+                    let mut word_start = 0;
+                    let bytes: Vec<(usize, char)> = paragraph.char_indices().collect();
+                    for i in 0..=bytes.len() {
+                        let at_end = i == bytes.len();
+                        if !at_end && (i == 0 || bytes[i].1 != ' ' || bytes[i - 1].1 == ' ') {
+                            continue;
+                        }
+                        let end = if at_end { paragraph.len() } else { bytes[i].0 };
+                        let word = &paragraph[word_start..end];
+                        word_start = end;
+                        if word.is_empty() {
+                            continue;
+                        }
+
+                        let width = measure(word);
+                        if line.width + width > max_width && !line.runs.is_empty() {
+                            lines.push(std::mem::take(&mut line));
+                        }
+                        let word = if line.runs.is_empty() {
+                            word.trim_start()
+                        } else {
+                            word
+                        };
+                        if !word.is_empty() {
+                            push(
+                                &mut line,
+                                RichPart::Text(word.to_string(), *color),
+                                measure(word),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if !line.runs.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
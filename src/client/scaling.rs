@@ -0,0 +1,54 @@
+use std::str::FromStr;
+use vek::Vec2;
+
+/// How the game viewport is scaled into the window, the `upscale` of the `[viewport]` table
+/// of the game config. See `src/client/CONFIG.md` for an example.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewportScaling {
+    /// No scaling, the viewport is centered.
+    #[default]
+    None,
+    /// The largest scale keeping the aspect ratio, centered with letterboxing.
+    Fit,
+    /// The largest whole scale so that every pixel stays square and crisp, centered with
+    /// letterboxing. Windows smaller than the viewport fall back to `Fit`.
+    Integer,
+    /// Fill the window, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl FromStr for ViewportScaling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(ViewportScaling::None),
+            "fit" | "aspect" => Ok(ViewportScaling::Fit),
+            "integer" | "pixel" => Ok(ViewportScaling::Integer),
+            "stretch" => Ok(ViewportScaling::Stretch),
+            _ => Err(format!("Unknown viewport scaling '{}'.", s)),
+        }
+    }
+}
+
+impl ViewportScaling {
+    /// The offset and the scale of the viewport inside of the window.
+    pub fn layout(&self, viewport: Vec2<i32>, window: Vec2<i32>) -> (Vec2<i32>, Vec2<f32>) {
+        let src = viewport.map(|v| v.max(1) as f32);
+        let dst = window.map(|v| v.max(0) as f32);
+        let fit = (dst.x / src.x).min(dst.y / src.y);
+
+        let scale = match self {
+            ViewportScaling::None => Vec2::one(),
+            ViewportScaling::Fit => Vec2::broadcast(fit),
+            ViewportScaling::Integer if fit >= 1.0 => Vec2::broadcast(fit.floor()),
+            ViewportScaling::Integer => Vec2::broadcast(fit),
+            ViewportScaling::Stretch => dst / src,
+        };
+
+        // Without scaling a larger viewport is cut off at the right and bottom
+        let size = (src * scale).map(|v| v as i32);
+        let offset = ((window - size) / 2).map(|v| v.max(0));
+        (offset, scale)
+    }
+}
@@ -0,0 +1,116 @@
+use theframework::prelude::*;
+
+/// The themes of the screen widgets, read from the `[themes]` table of the game config. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// A widget uses the style named by the `style` key of its `[ui]` table, or the style named
+/// like its role. The values of the style go into the `[ui]` table and its tables into the
+/// tables of the same name, values of the widget itself take precedence.
+#[derive(Debug, Clone, Default)]
+pub struct Themes {
+    themes: FxHashMap<String, toml::Table>,
+    pub active: String,
+}
+
+impl Themes {
+    /// Read the themes of the config, the active theme is the `theme` of the `[game]` table.
+    pub fn from_config(config: &toml::Table) -> Self {
+        let themes = config
+            .get("themes")
+            .and_then(toml::Value::as_table)
+            .map(|themes| {
+                themes
+                    .iter()
+                    .filter_map(|(name, theme)| Some((name.clone(), theme.as_table()?.clone())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let active = config
+            .get("game")
+            .and_then(|game| game.get("theme"))
+            .and_then(toml::Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        Self { themes, active }
+    }
+
+    /// The names of the themes, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.themes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Switch to the theme, returns false if there is no theme of that name.
+    pub fn set_active(&mut self, name: &str) -> bool {
+        if self.themes.contains_key(name) {
+            self.active = name.to_string();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Apply the style of the active theme to the widget data. Returns true if the style
+    /// changed the data.
+    pub fn apply(&self, data: &mut toml::Table) -> bool {
+        let Some(theme) = self.themes.get(&self.active) else {
+            return false;
+        };
+        let ui = data.get("ui").and_then(toml::Value::as_table);
+        let Some(name) = ui
+            .and_then(|ui| ui.get("style").or_else(|| ui.get("role")))
+            .and_then(toml::Value::as_str)
+            .map(str::to_string)
+        else {
+            return false;
+        };
+        let Some(style) = theme.get(&name).and_then(toml::Value::as_table) else {
+            return false;
+        };
+
+        let mut changed = false;
+        for (key, value) in style {
+            match value {
+                toml::Value::Table(table) => {
+                    let entry = data
+                        .entry(key.clone())
+                        .or_insert(toml::Value::Table(toml::Table::new()));
+                    if let Some(entry) = entry.as_table_mut() {
+                        changed |= merge_missing(entry, table);
+                    }
+                }
+                _ => {
+                    let ui = data
+                        .entry("ui")
+                        .or_insert(toml::Value::Table(toml::Table::new()));
+                    if let Some(ui) = ui.as_table_mut() {
+                        if !ui.contains_key(key) {
+                            ui.insert(key.clone(), value.clone());
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Insert the values of `from` which `into` lacks, tables are merged recursively.
+fn merge_missing(into: &mut toml::Table, from: &toml::Table) -> bool {
+    let mut changed = false;
+    for (key, value) in from {
+        match (into.get_mut(key), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => {
+                changed |= merge_missing(into, from);
+            }
+            (Some(_), _) => {}
+            (None, _) => {
+                into.insert(key.clone(), value.clone());
+                changed = true;
+            }
+        }
+    }
+    changed
+}
@@ -0,0 +1,371 @@
+use crate::{
+    Pixel,
+    client::draw2d::{Draw2D, TheHorizontalAlign, TheVerticalAlign},
+    client::input::InputBinding,
+    client::richtext::named_color,
+};
+use fontdue::Font;
+use std::str::FromStr;
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// An on-screen button of the touch controls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TouchButton {
+    pub label: String,
+    pub binding: InputBinding,
+    /// The center in pixels, negative values are measured from the right / bottom edge.
+    pub position: Vec2<f32>,
+    pub radius: f32,
+}
+
+/// The on-screen touch controls, read from the `[input.touch]` table of the game config. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// Button bindings use the syntax of the gamepad bindings. Touches outside of the controls
+/// are routed to the widgets like clicks.
+#[derive(Debug, Clone)]
+pub struct TouchSettings {
+    pub enabled: bool,
+    pub joystick: String,
+    pub radius: f32,
+    pub margin: f32,
+    pub deadzone: f32,
+    pub font: String,
+    pub font_size: f32,
+    pub color: Pixel,
+    pub knob_color: Pixel,
+    pub pressed_color: Pixel,
+    pub buttons: Vec<TouchButton>,
+}
+
+impl Default for TouchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            joystick: "left".into(),
+            radius: 56.0,
+            margin: 24.0,
+            deadzone: 0.2,
+            font: String::new(),
+            font_size: 16.0,
+            color: [255, 255, 255, 48],
+            knob_color: [255, 255, 255, 144],
+            pressed_color: [255, 255, 255, 112],
+            buttons: vec![],
+        }
+    }
+}
+
+impl TouchSettings {
+    /// Read the settings from the config. Returns the invalid buttons as errors.
+    pub fn from_config(config: &toml::Table) -> (Self, Vec<String>) {
+        let mut settings = Self::default();
+        let mut errors = vec![];
+
+        let Some(touch) = config
+            .get("input")
+            .and_then(|input| input.get("touch"))
+            .and_then(toml::Value::as_table)
+        else {
+            return (settings, errors);
+        };
+
+        let float = |table: &toml::Table, key: &str| {
+            table
+                .get(key)
+                .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                .map(|v| v as f32)
+        };
+
+        if let Some(enabled) = touch.get("enabled").and_then(toml::Value::as_bool) {
+            settings.enabled = enabled;
+        }
+        if let Some(joystick) = touch.get("joystick").and_then(toml::Value::as_str) {
+            settings.joystick = joystick.to_string();
+        }
+        if let Some(font) = touch.get("font").and_then(toml::Value::as_str) {
+            settings.font = font.to_string();
+        }
+        for (key, value) in [
+            ("radius", &mut settings.radius),
+            ("margin", &mut settings.margin),
+            ("font_size", &mut settings.font_size),
+        ] {
+            if let Some(v) = float(touch, key) {
+                *value = v.max(0.0);
+            }
+        }
+        if let Some(deadzone) = float(touch, "deadzone") {
+            settings.deadzone = deadzone.clamp(0.0, 0.99);
+        }
+        for (key, color) in [
+            ("color", &mut settings.color),
+            ("knob_color", &mut settings.knob_color),
+            ("pressed_color", &mut settings.pressed_color),
+        ] {
+            if let Some(c) = touch
+                .get(key)
+                .and_then(toml::Value::as_str)
+                .and_then(named_color)
+            {
+                *color = c;
+            }
+        }
+
+        if let Some(buttons) = touch.get("buttons").and_then(toml::Value::as_array) {
+            for (index, button) in buttons.iter().enumerate() {
+                let Some(button) = button.as_table() else {
+                    errors.push(format!("Invalid touch button {}.", index));
+                    continue;
+                };
+                let label = button
+                    .get("label")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                match button
+                    .get("binding")
+                    .and_then(toml::Value::as_str)
+                    .map(InputBinding::from_str)
+                {
+                    Some(Ok(binding)) => settings.buttons.push(TouchButton {
+                        label,
+                        binding,
+                        position: Vec2::new(
+                            float(button, "x").unwrap_or(0.0),
+                            float(button, "y").unwrap_or(0.0),
+                        ),
+                        radius: float(button, "radius").unwrap_or(32.0).max(1.0),
+                    }),
+                    _ => errors.push(format!("Invalid binding of touch button {}.", index)),
+                }
+            }
+        }
+        (settings, errors)
+    }
+}
+
+/// What a touch was captured by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TouchTarget {
+    Joystick,
+    Button(usize),
+    Pointer,
+}
+
+/// The result of a touch event of the touch controls.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TouchEvent {
+    /// The touch was consumed without a result.
+    None,
+    /// The joystick moved, zero once it was released.
+    Movement(Vec2<f32>),
+    /// A button was pressed or released.
+    Button(InputBinding, bool),
+    /// The touch is outside of the controls and goes to the widgets.
+    Pointer,
+}
+
+/// The state of the touch controls, tracks the touches by their id.
+#[derive(Clone, Default)]
+pub struct TouchControls {
+    pub settings: TouchSettings,
+    pub font: Option<Font>,
+    touches: FxHashMap<u64, TouchTarget>,
+    // Offset of the knob from the joystick center, in pixels
+    knob: Vec2<f32>,
+    movement: Vec2<f32>,
+}
+
+impl TouchControls {
+    pub fn new(settings: TouchSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    /// The center of the joystick in the viewport, None without a joystick.
+    pub fn joystick_center(&self, viewport: Vec2<f32>) -> Option<Vec2<f32>> {
+        let offset = self.settings.margin + self.settings.radius;
+        match self.settings.joystick.as_str() {
+            "left" => Some(Vec2::new(offset, viewport.y - offset)),
+            "right" => Some(Vec2::new(viewport.x - offset, viewport.y - offset)),
+            _ => None,
+        }
+    }
+
+    /// The center of the button in the viewport.
+    pub fn button_center(button: &TouchButton, viewport: Vec2<f32>) -> Vec2<f32> {
+        let mut center = button.position;
+        for i in 0..2 {
+            if center[i] < 0.0 {
+                center[i] += viewport[i];
+            }
+        }
+        center
+    }
+
+    /// A touch started at the viewport position.
+    pub fn start(&mut self, id: u64, p: Vec2<f32>, viewport: Vec2<f32>) -> TouchEvent {
+        if !self.settings.enabled {
+            self.touches.insert(id, TouchTarget::Pointer);
+            return TouchEvent::Pointer;
+        }
+
+        let pressed =
+            self.settings.buttons.iter().position(|button| {
+                Self::button_center(button, viewport).distance(p) <= button.radius
+            });
+        if let Some(index) = pressed {
+            self.touches.insert(id, TouchTarget::Button(index));
+            return TouchEvent::Button(self.settings.buttons[index].binding.clone(), true);
+        }
+
+        // The joystick reacts a bit outside of its base, it is hard to hit exactly
+        if let Some(center) = self.joystick_center(viewport) {
+            let joystick_taken = self.touches.values().any(|t| *t == TouchTarget::Joystick);
+            if !joystick_taken && center.distance(p) <= self.settings.radius * 1.5 {
+                self.touches.insert(id, TouchTarget::Joystick);
+                return self.move_knob(p - center);
+            }
+        }
+
+        self.touches.insert(id, TouchTarget::Pointer);
+        TouchEvent::Pointer
+    }
+
+    /// A touch moved to the viewport position.
+    pub fn moved(&mut self, id: u64, p: Vec2<f32>, viewport: Vec2<f32>) -> TouchEvent {
+        match self.touches.get(&id) {
+            Some(TouchTarget::Joystick) => match self.joystick_center(viewport) {
+                Some(center) => self.move_knob(p - center),
+                None => TouchEvent::None,
+            },
+            Some(TouchTarget::Pointer) => TouchEvent::Pointer,
+            _ => TouchEvent::None,
+        }
+    }
+
+    /// A touch ended.
+    pub fn end(&mut self, id: u64) -> TouchEvent {
+        match self.touches.remove(&id) {
+            Some(TouchTarget::Joystick) => {
+                self.knob = Vec2::zero();
+                self.movement = Vec2::zero();
+                TouchEvent::Movement(Vec2::zero())
+            }
+            Some(TouchTarget::Button(index)) => match self.settings.buttons.get(index) {
+                Some(button) => TouchEvent::Button(button.binding.clone(), false),
+                None => TouchEvent::None,
+            },
+            Some(TouchTarget::Pointer) => TouchEvent::Pointer,
+            None => TouchEvent::None,
+        }
+    }
+
+    /// Release all touches, i.e. when the screen changes.
+    pub fn clear(&mut self) {
+        self.touches.clear();
+        self.knob = Vec2::zero();
+        self.movement = Vec2::zero();
+    }
+
+    /// Move the knob by the offset from the joystick center. Returns the movement if it
+    /// changed noticeably, like the gamepad stick.
+    fn move_knob(&mut self, offset: Vec2<f32>) -> TouchEvent {
+        let radius = self.settings.radius.max(1.0);
+        let length = offset.magnitude();
+        self.knob = if length > radius {
+            offset / length * radius
+        } else {
+            offset
+        };
+
+        let raw = self.knob / radius;
+        let length = raw.magnitude();
+        let deadzone = self.settings.deadzone;
+        let movement = if length <= deadzone {
+            Vec2::zero()
+        } else {
+            raw / length * ((length - deadzone) / (1.0 - deadzone)).min(1.0)
+        };
+
+        let changed = if movement == Vec2::zero() {
+            self.movement != Vec2::zero()
+        } else {
+            movement.distance(self.movement) > 0.05
+        };
+        if changed {
+            self.movement = movement;
+            TouchEvent::Movement(movement)
+        } else {
+            TouchEvent::None
+        }
+    }
+
+    /// Draw the joystick and the buttons into the buffer covering the viewport.
+    pub fn draw(&self, buffer: &mut TheRGBABuffer, draw2d: &Draw2D) {
+        if !self.settings.enabled {
+            return;
+        }
+        let viewport = Vec2::new(buffer.dim().width as f32, buffer.dim().height as f32);
+        let stride = buffer.stride();
+        let safe = (0, 0, viewport.x as isize, viewport.y as isize);
+        let circle = |buffer: &mut TheRGBABuffer, center: Vec2<f32>, radius: f32, color: &Pixel| {
+            draw2d.blend_circle_safe(
+                buffer.pixels_mut(),
+                &(center.x, center.y),
+                radius,
+                stride,
+                color,
+                &safe,
+            );
+        };
+
+        if let Some(center) = self.joystick_center(viewport) {
+            circle(buffer, center, self.settings.radius, &self.settings.color);
+            circle(
+                buffer,
+                center + self.knob,
+                self.settings.radius * 0.45,
+                &self.settings.knob_color,
+            );
+        }
+
+        for (index, button) in self.settings.buttons.iter().enumerate() {
+            let pressed = self
+                .touches
+                .values()
+                .any(|t| *t == TouchTarget::Button(index));
+            let color = if pressed {
+                &self.settings.pressed_color
+            } else {
+                &self.settings.color
+            };
+            let center = Self::button_center(button, viewport);
+            circle(buffer, center, button.radius, color);
+
+            if let Some(font) = &self.font {
+                draw2d.text_rect_blend_safe(
+                    buffer.pixels_mut(),
+                    &(
+                        (center.x - button.radius) as isize,
+                        (center.y - button.radius) as isize,
+                        (button.radius * 2.0) as isize,
+                        (button.radius * 2.0) as isize,
+                    ),
+                    stride,
+                    font,
+                    self.settings.font_size,
+                    &button.label,
+                    &self.settings.knob_color,
+                    TheHorizontalAlign::Center,
+                    TheVerticalAlign::Center,
+                    &safe,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use crate::Rect;
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// The easing curve of a tween.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    #[default]
+    EaseOut,
+    EaseInOut,
+    /// Overshoots the target and settles back.
+    Back,
+    Bounce,
+    Elastic,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease_in" => Some(Easing::EaseIn),
+            "ease_out" => Some(Easing::EaseOut),
+            "ease_in_out" => Some(Easing::EaseInOut),
+            "back" => Some(Easing::Back),
+            "bounce" => Some(Easing::Bounce),
+            "elastic" => Some(Easing::Elastic),
+            _ => None,
+        }
+    }
+
+    /// Map the progress t from 0.0 to 1.0 onto the curve.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::Back => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+            Easing::Bounce => {
+                let (n1, d1) = (7.5625, 2.75);
+                if t < 1.0 / d1 {
+                    n1 * t * t
+                } else if t < 2.0 / d1 {
+                    let t = t - 1.5 / d1;
+                    n1 * t * t + 0.75
+                } else if t < 2.5 / d1 {
+                    let t = t - 2.25 / d1;
+                    n1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / d1;
+                    n1 * t * t + 0.984375
+                }
+            }
+            Easing::Elastic => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    let c4 = std::f32::consts::TAU / 3.0;
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0
+                }
+            }
+        }
+    }
+}
+
+/// The animated properties of a widget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TweenState {
+    /// Offset from the widget position in pixels.
+    pub offset: Vec2<f32>,
+    /// Scale around the center of the widget.
+    pub scale: f32,
+    pub opacity: f32,
+}
+
+impl TweenState {
+    /// The widget at its place, as laid out on the screen.
+    pub const REST: TweenState = TweenState {
+        offset: Vec2 { x: 0.0, y: 0.0 },
+        scale: 1.0,
+        opacity: 1.0,
+    };
+
+    pub fn lerp(&self, other: &TweenState, t: f32) -> TweenState {
+        TweenState {
+            offset: self.offset + (other.offset - self.offset) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+            opacity: (self.opacity + (other.opacity - self.opacity) * t).clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn is_rest(&self) -> bool {
+        *self == Self::REST
+    }
+}
+
+/// The events which start a tween.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TweenTrigger {
+    /// The widget appears, it animates from the tween state to its place.
+    Show,
+    /// The widget is hidden, it animates from its place to the tween state.
+    Hide,
+    /// The button is activated, it animates to the tween state and back.
+    Activate,
+    /// Loops to the tween state and back while the widget is visible, i.e. a pulse.
+    Idle,
+}
+
+impl TweenTrigger {
+    pub const ALL: [TweenTrigger; 4] = [
+        TweenTrigger::Show,
+        TweenTrigger::Hide,
+        TweenTrigger::Activate,
+        TweenTrigger::Idle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TweenTrigger::Show => "show",
+            TweenTrigger::Hide => "hide",
+            TweenTrigger::Activate => "activate",
+            TweenTrigger::Idle => "idle",
+        }
+    }
+}
+
+/// A tween between the rest state of a widget and `state`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tween {
+    pub state: TweenState,
+    /// Seconds of the animation, for the activate and idle tweens one way.
+    pub duration: f32,
+    pub delay: f32,
+    pub easing: Easing,
+}
+
+impl Tween {
+    /// Read a tween from its table, properties which are not given keep their rest value.
+    pub fn from_table(table: &toml::Table) -> Self {
+        let float = |key: &str| {
+            table
+                .get(key)
+                .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                .map(|v| v as f32)
+        };
+        let mut state = TweenState::REST;
+        if let Some(offset) = table.get("offset").and_then(toml::Value::as_array) {
+            let value = |i: usize| {
+                offset
+                    .get(i)
+                    .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                    .unwrap_or(0.0) as f32
+            };
+            state.offset = Vec2::new(value(0), value(1));
+        }
+        if let Some(scale) = float("scale") {
+            state.scale = scale.max(0.0);
+        }
+        if let Some(opacity) = float("opacity") {
+            state.opacity = opacity.clamp(0.0, 1.0);
+        }
+        Self {
+            state,
+            duration: float("duration").unwrap_or(0.25).max(0.01),
+            delay: float("delay").unwrap_or(0.0).max(0.0),
+            easing: table
+                .get("easing")
+                .and_then(toml::Value::as_str)
+                .and_then(Easing::from_name)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The state of the widget `age` seconds after the trigger.
+    pub fn state_at(&self, trigger: TweenTrigger, age: f32) -> TweenState {
+        let t = ((age - self.delay) / self.duration).max(0.0);
+        match trigger {
+            TweenTrigger::Show => self
+                .state
+                .lerp(&TweenState::REST, self.easing.apply(t.min(1.0))),
+            TweenTrigger::Hide => TweenState::REST.lerp(&self.state, self.easing.apply(t.min(1.0))),
+            TweenTrigger::Activate | TweenTrigger::Idle => {
+                // There and back, the activate tween once, the idle tween in a loop
+                let t = if trigger == TweenTrigger::Activate {
+                    t.min(2.0)
+                } else {
+                    t % 2.0
+                };
+                let t = if t <= 1.0 { t } else { 2.0 - t };
+                TweenState::REST.lerp(&self.state, self.easing.apply(t))
+            }
+        }
+    }
+
+    /// If the tween started by the trigger is done after `age` seconds.
+    pub fn finished(&self, trigger: TweenTrigger, age: f32) -> bool {
+        match trigger {
+            TweenTrigger::Show | TweenTrigger::Hide => age >= self.delay + self.duration,
+            TweenTrigger::Activate => age >= self.delay + self.duration * 2.0,
+            TweenTrigger::Idle => false,
+        }
+    }
+}
+
+/// The tweens of a widget, read from the `[tween]` table of its data. See `src/client/CONFIG.md`
+/// for an example.
+///
+/// Each tween accepts `duration`, `delay`, `easing`, `offset`, `scale` and `opacity`.
+#[derive(Debug, Clone, Default)]
+pub struct WidgetTweens {
+    pub tweens: FxHashMap<TweenTrigger, Tween>,
+    active: Option<(TweenTrigger, f32)>,
+    idle_age: f32,
+    visible: Option<bool>,
+    activated: bool,
+}
+
+impl WidgetTweens {
+    /// Read the tweens of the widget data, None if it has no `[tween]` table.
+    pub fn from_toml(table: &toml::Table) -> Option<Self> {
+        let tween = table.get("tween").and_then(toml::Value::as_table)?;
+        let tweens: FxHashMap<TweenTrigger, Tween> = TweenTrigger::ALL
+            .into_iter()
+            .filter_map(|trigger| {
+                let table = tween.get(trigger.name()).and_then(toml::Value::as_table)?;
+                Some((trigger, Tween::from_table(table)))
+            })
+            .collect();
+        if tweens.is_empty() {
+            return None;
+        }
+        Some(Self {
+            tweens,
+            ..Default::default()
+        })
+    }
+
+    /// Start the tween of the trigger, if the widget has one.
+    pub fn trigger(&mut self, trigger: TweenTrigger) {
+        if self.tweens.contains_key(&trigger) {
+            self.active = Some((trigger, 0.0));
+        } else if trigger == TweenTrigger::Hide {
+            // Without a hide tween the widget disappears at once
+            self.active = None;
+        }
+    }
+
+    /// Advance the tweens by dt seconds, starting the show, hide and activate tweens on the
+    /// changes of the visibility and activation. Returns the state to draw the widget with,
+    /// None if it is hidden and done animating.
+    pub fn update(&mut self, visible: bool, activated: bool, dt: f32) -> Option<TweenState> {
+        if self.visible != Some(visible) {
+            if visible {
+                self.trigger(TweenTrigger::Show);
+                self.idle_age = 0.0;
+            } else if self.visible.is_some() {
+                self.trigger(TweenTrigger::Hide);
+            }
+            self.visible = Some(visible);
+        }
+        if visible && activated && !self.activated {
+            self.trigger(TweenTrigger::Activate);
+        }
+        self.activated = activated;
+
+        if let Some((trigger, age)) = self.active {
+            let age = age + dt;
+            self.active = if self.tweens[&trigger].finished(trigger, age) {
+                None
+            } else {
+                Some((trigger, age))
+            };
+        }
+
+        match self.active {
+            Some((trigger, age)) => Some(self.tweens[&trigger].state_at(trigger, age)),
+            None if visible => {
+                self.idle_age += dt;
+                Some(match self.tweens.get(&TweenTrigger::Idle) {
+                    Some(idle) => idle.state_at(TweenTrigger::Idle, self.idle_age),
+                    None => TweenState::REST,
+                })
+            }
+            None => None,
+        }
+    }
+}
+
+/// Draw a widget with its tween state. At rest the widget draws directly into the target,
+/// otherwise into the transparent layer which is then moved, scaled around the center of
+/// `rect` and blended into the target with the opacity of the state.
+pub fn draw_tweened(
+    target: &mut TheRGBABuffer,
+    layer: &mut TheRGBABuffer,
+    rect: &Rect,
+    state: &TweenState,
+    draw: impl FnOnce(&mut TheRGBABuffer),
+) {
+    if state.is_rest() {
+        draw(target);
+        return;
+    }
+    if state.opacity <= 0.0 || state.scale <= 0.0 {
+        return;
+    }
+    let width = target.dim().width;
+    let height = target.dim().height;
+    if layer.dim().width != width || layer.dim().height != height {
+        *layer = TheRGBABuffer::new(TheDim::sized(width, height));
+    }
+    layer.fill([0, 0, 0, 0]);
+    draw(layer);
+
+    let center = Vec2::new(rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+    let to = center + state.offset;
+    let half = Vec2::new(rect.width, rect.height) / 2.0 * state.scale;
+
+    let x0 = ((to.x - half.x).floor() as i32).max(0);
+    let x1 = ((to.x + half.x).ceil() as i32).min(width);
+    let y0 = ((to.y - half.y).floor() as i32).max(0);
+    let y1 = ((to.y + half.y).ceil() as i32).min(height);
+
+    let source = layer.pixels();
+    let pixels = target.pixels_mut();
+    for y in y0..y1 {
+        for x in x0..x1 {
+            // The pixel of the layer which lands here
+            let p = center + (Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - to) / state.scale;
+            let (sx, sy) = (p.x.floor() as i32, p.y.floor() as i32);
+            if sx < rect.x as i32
+                || sy < rect.y as i32
+                || sx >= (rect.x + rect.width) as i32
+                || sy >= (rect.y + rect.height) as i32
+                || sx < 0
+                || sy < 0
+                || sx >= width
+                || sy >= height
+            {
+                continue;
+            }
+            let src = ((sy * width + sx) * 4) as usize;
+            let coverage = source[src + 3] as f32 / 255.0;
+            if coverage <= 0.0 {
+                continue;
+            }
+            // Blending into the transparent layer multiplied the colors with their coverage
+            let alpha = coverage * state.opacity;
+            let dst = ((y * width + x) * 4) as usize;
+            for c in 0..3 {
+                let color = (source[src + c] as f32 / coverage).min(255.0);
+                pixels[dst + c] = (pixels[dst + c] as f32 * (1.0 - alpha) + color * alpha) as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::client::widget::hex_to_rgba_u8;
+use crate::{Assets, BLACK, Entity, Map, Pixel, Rect, WHITE, client::draw2d};
+use draw2d::Draw2D;
+use theframework::prelude::*;
+
+/// The direction a bar fills up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarDirection {
+    Right,
+    Left,
+    Up,
+    Down,
+}
+
+/// A bar showing an attribute of an entity relative to its maximum, i.e. the health of the
+/// player. Configured in the `[ui]` table of the widget. See `src/client/CONFIG.md` for an example.
+pub struct BarWidget {
+    pub name: String,
+    pub rect: Rect,
+    pub toml_str: String,
+    pub draw2d: Draw2D,
+    pub entity: String,
+    pub attribute: String,
+    pub max_attribute: Option<String>,
+    pub max: f32,
+    pub direction: BarDirection,
+    pub color: Pixel,
+    pub background: Pixel,
+    pub border_color: Pixel,
+    pub border_size: i32,
+    pub segments: usize,
+    pub tick_color: Pixel,
+}
+
+impl Default for BarWidget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BarWidget {
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            rect: Rect::default(),
+            toml_str: String::new(),
+            draw2d: Draw2D::default(),
+            entity: "player".into(),
+            attribute: String::new(),
+            max_attribute: None,
+            max: 100.0,
+            direction: BarDirection::Right,
+            color: [192, 48, 48, 255],
+            background: BLACK,
+            border_color: WHITE,
+            border_size: 1,
+            segments: 0,
+            tick_color: BLACK,
+        }
+    }
+
+    pub fn init(&mut self, _assets: &Assets) {
+        if let Ok(table) = self.toml_str.parse::<toml::Table>() {
+            if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
+                if let Some(value) = ui.get("entity") {
+                    if let Some(v) = value.as_str() {
+                        self.entity = v.into();
+                    }
+                }
+                if let Some(value) = ui.get("attribute") {
+                    if let Some(v) = value.as_str() {
+                        self.attribute = v.into();
+                    }
+                }
+                if let Some(value) = ui.get("max_attribute") {
+                    if let Some(v) = value.as_str() {
+                        self.max_attribute = Some(v.into());
+                    }
+                }
+                if let Some(value) = ui.get("max") {
+                    if let Some(v) = value.as_float() {
+                        self.max = v as f32;
+                    } else if let Some(v) = value.as_integer() {
+                        self.max = v as f32;
+                    }
+                }
+                if let Some(value) = ui.get("direction") {
+                    if let Some(v) = value.as_str() {
+                        self.direction = match v {
+                            "left" => BarDirection::Left,
+                            "up" => BarDirection::Up,
+                            "down" => BarDirection::Down,
+                            _ => BarDirection::Right,
+                        };
+                    }
+                }
+                if let Some(value) = ui.get("border_size") {
+                    if let Some(v) = value.as_integer() {
+                        self.border_size = v as i32;
+                    }
+                }
+                if let Some(value) = ui.get("segments") {
+                    if let Some(v) = value.as_integer() {
+                        self.segments = v.max(0) as usize;
+                    }
+                }
+                for (key, color) in [
+                    ("color", &mut self.color),
+                    ("background", &mut self.background),
+                    ("border_color", &mut self.border_color),
+                    ("tick_color", &mut self.tick_color),
+                ] {
+                    if let Some(v) = ui.get(key).and_then(toml::Value::as_str) {
+                        *color = hex_to_rgba_u8(v);
+                    }
+                }
+            }
+        }
+    }
+
+    fn find_entity<'a>(&self, map: &'a Map) -> Option<&'a Entity> {
+        map.entities.iter().find(|entity| {
+            if self.entity == "player" {
+                entity.is_player()
+            } else {
+                entity.attributes.get_str("name") == Some(self.entity.as_str())
+            }
+        })
+    }
+
+    /// The fill of the bar from 0 to 1.
+    pub fn fill(&self, map: &Map) -> Option<f32> {
+        let entity = self.find_entity(map)?;
+        let value = entity.attributes.get(&self.attribute)?.to_f32()?;
+        let max = match &self.max_attribute {
+            Some(max) => entity.attributes.get(max).and_then(|v| v.to_f32())?,
+            None => self.max,
+        };
+        if max <= 0.0 {
+            return Some(0.0);
+        }
+        Some((value / max).clamp(0.0, 1.0))
+    }
+
+    pub fn update_draw(&mut self, buffer: &mut TheRGBABuffer, map: &Map) {
+        let Some(fill) = self.fill(map) else {
+            return;
+        };
+        let stride = buffer.stride();
+        let (x, y) = (self.rect.x as usize, self.rect.y as usize);
+        let (width, height) = (self.rect.width as usize, self.rect.height as usize);
+
+        self.draw2d.blend_rect(
+            buffer.pixels_mut(),
+            &(x, y, width, height),
+            stride,
+            &self.background,
+        );
+
+        let filled_width = (width as f32 * fill).round() as usize;
+        let filled_height = (height as f32 * fill).round() as usize;
+        let filled = match self.direction {
+            BarDirection::Right => (x, y, filled_width, height),
+            BarDirection::Left => (x + width - filled_width, y, filled_width, height),
+            BarDirection::Down => (x, y, width, filled_height),
+            BarDirection::Up => (x, y + height - filled_height, width, filled_height),
+        };
+        self.draw2d
+            .blend_rect(buffer.pixels_mut(), &filled, stride, &self.color);
+
+        // The segment ticks
+        for segment in 1..self.segments {
+            let tick = match self.direction {
+                BarDirection::Right | BarDirection::Left => {
+                    (x + width * segment / self.segments, y, 1, height)
+                }
+                BarDirection::Up | BarDirection::Down => {
+                    (x, y + height * segment / self.segments, width, 1)
+                }
+            };
+            self.draw2d
+                .blend_rect(buffer.pixels_mut(), &tick, stride, &self.tick_color);
+        }
+
+        if self.border_size > 0 {
+            self.draw2d.rect_outline_thickness(
+                buffer.pixels_mut(),
+                &(x, y, width, height),
+                stride,
+                &self.border_color,
+                self.border_size as usize,
+            );
+        }
+    }
+}
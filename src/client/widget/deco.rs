@@ -1,4 +1,7 @@
-use crate::{Assets, BLACK, Currencies, Map, Pixel, Rect, WHITE, client::draw2d};
+use crate::{
+    Assets, BLACK, Currencies, Map, Pixel, Rect, WHITE,
+    client::{draw2d, widget::FrameTile},
+};
 use draw2d::Draw2D;
 use theframework::prelude::*;
 
@@ -12,6 +15,7 @@ pub struct DecoWidget {
     pub color: Pixel,
     pub border_color: Pixel,
     pub border_size: i32,
+    pub frame: Option<FrameTile>,
 }
 
 impl Default for DecoWidget {
@@ -32,10 +36,11 @@ impl DecoWidget {
             color: BLACK,
             border_color: WHITE,
             border_size: 1,
+            frame: None,
         }
     }
 
-    pub fn init(&mut self, _assets: &Assets) {
+    pub fn init(&mut self, assets: &Assets) {
         if let Ok(table) = self.toml_str.parse::<toml::Table>() {
             if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
                 if let Some(value) = ui.get("border_size") {
@@ -53,6 +58,7 @@ impl DecoWidget {
                         self.border_color = self.hex_to_rgba_u8(v);
                     }
                 }
+                self.frame = FrameTile::from_ui(ui, assets);
             }
             self.table = table;
         }
@@ -65,6 +71,12 @@ impl DecoWidget {
         _currencies: &Currencies,
         _assets: &Assets,
     ) {
+        // A frame tile replaces the color and border
+        if let Some(frame) = &self.frame {
+            frame.draw(buffer, &self.rect, &self.draw2d);
+            return;
+        }
+
         let stride = buffer.stride();
 
         self.draw2d.blend_rect(
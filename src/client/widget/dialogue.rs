@@ -0,0 +1,392 @@
+use crate::client::widget::hex_to_rgba_u8;
+use crate::{
+    Assets, Choice, DialoguePrompt, Map, MsgParser, Pixel, Rect, Value,
+    client::{draw2d, resolver::MsgResolver},
+};
+use draw2d::Draw2D;
+use theframework::prelude::*;
+
+/// Shows the current dialogue node of an NPC: the portrait and name of the speaker, the text
+/// revealed like a typewriter and the choices of the player. Configured in the `[ui]` table
+/// of the widget. See `src/client/CONFIG.md` for an example.
+///
+/// Choices are selected with the up / down keys or the gamepad, confirmed with enter, space
+/// or a click and the number keys pick a choice directly. Confirming while the text is still
+/// revealed shows it completely.
+pub struct DialogueWidget {
+    pub name: String,
+    pub rect: Rect,
+    pub toml_str: String,
+    pub font: Option<fontdue::Font>,
+    pub font_size: f32,
+    pub spacing: f32,
+    pub draw2d: Draw2D,
+    pub reveal_speed: f32,
+    pub portrait_size: f32,
+    pub color: Pixel,
+    pub name_color: Pixel,
+    pub choice_color: Pixel,
+    pub selected_color: Pixel,
+    pub background: Pixel,
+    pub border_color: Pixel,
+    pub parser: MsgParser,
+    pub resolver: MsgResolver,
+
+    /// The current prompt with its resolved text and choices.
+    prompt: Option<DialoguePrompt>,
+    speaker: String,
+    text: String,
+    choices: Vec<String>,
+    revealed: f32,
+    pub selected: usize,
+    /// The rects of the choices of the last draw, for mouse selection.
+    choice_rects: Vec<Rect>,
+}
+
+impl Default for DialogueWidget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DialogueWidget {
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            rect: Rect::default(),
+            toml_str: String::new(),
+            font: None,
+            font_size: 16.0,
+            spacing: 2.0,
+            draw2d: Draw2D::default(),
+            reveal_speed: 1.0,
+            portrait_size: 64.0,
+            color: [208, 208, 208, 255],
+            name_color: [255, 255, 255, 255],
+            choice_color: [160, 160, 160, 255],
+            selected_color: [255, 208, 64, 255],
+            background: [0, 0, 0, 192],
+            border_color: [255, 255, 255, 255],
+            parser: MsgParser::new(),
+            resolver: MsgResolver::default(),
+
+            prompt: None,
+            speaker: String::new(),
+            text: String::new(),
+            choices: vec![],
+            revealed: 0.0,
+            selected: 0,
+            choice_rects: vec![],
+        }
+    }
+
+    pub fn init(&mut self, assets: &Assets) {
+        let mut font_name = String::new();
+        if let Ok(table) = self.toml_str.parse::<toml::Table>() {
+            if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
+                if let Some(value) = ui.get("font") {
+                    if let Some(v) = value.as_str() {
+                        font_name = v.into();
+                    }
+                }
+                for (key, target) in [
+                    ("font_size", &mut self.font_size),
+                    ("spacing", &mut self.spacing),
+                    ("reveal_speed", &mut self.reveal_speed),
+                    ("portrait_size", &mut self.portrait_size),
+                ] {
+                    if let Some(value) = ui.get(key) {
+                        if let Some(v) = value.as_float() {
+                            *target = v as f32;
+                        } else if let Some(v) = value.as_integer() {
+                            *target = v as f32;
+                        }
+                    }
+                }
+                for (key, color) in [
+                    ("color", &mut self.color),
+                    ("name_color", &mut self.name_color),
+                    ("choice_color", &mut self.choice_color),
+                    ("selected_color", &mut self.selected_color),
+                    ("background", &mut self.background),
+                    ("border_color", &mut self.border_color),
+                ] {
+                    if let Some(v) = ui.get(key).and_then(toml::Value::as_str) {
+                        *color = hex_to_rgba_u8(v);
+                    }
+                }
+            }
+        }
+
+        if let Some(font) = assets.fonts.get(&font_name) {
+            self.font = Some(font.clone());
+        }
+    }
+
+    /// True while a prompt is shown.
+    pub fn is_active(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    /// Show the prompt, replacing the current one.
+    pub fn set_prompt(&mut self, prompt: DialoguePrompt, map: &Map, assets: &Assets) {
+        self.speaker = map
+            .entities
+            .iter()
+            .find(|entity| entity.id == prompt.from)
+            .and_then(|entity| entity.attributes.get_str("name"))
+            .unwrap_or_default()
+            .to_string();
+        self.text = self.resolve_msg(&prompt.text, map, assets);
+        self.choices = prompt
+            .choices
+            .iter()
+            .map(|(_, text)| self.resolve_msg(text, map, assets))
+            .collect();
+        self.revealed = if self.reveal_speed > 0.0 {
+            0.0
+        } else {
+            f32::MAX
+        };
+        self.selected = 0;
+        self.choice_rects.clear();
+        self.prompt = Some(prompt);
+    }
+
+    /// Close the dialogue without a choice.
+    pub fn close(&mut self) {
+        self.prompt = None;
+        self.choice_rects.clear();
+    }
+
+    fn fully_revealed(&self) -> bool {
+        self.revealed as usize >= self.text.chars().count()
+    }
+
+    /// Move the selection up (-1) or down (1).
+    pub fn select(&mut self, delta: i32) {
+        if !self.choices.is_empty() {
+            let count = self.choices.len() as i32;
+            self.selected = (self.selected as i32 + delta).rem_euclid(count) as usize;
+        }
+    }
+
+    /// Confirm the selected choice. While the text is revealed this shows it completely
+    /// instead, a prompt without choices is closed.
+    pub fn confirm(&mut self) -> Option<Choice> {
+        let prompt = self.prompt.as_ref()?;
+        if !self.fully_revealed() {
+            self.revealed = f32::MAX;
+            return None;
+        }
+        let choice = prompt
+            .choices
+            .get(self.selected)
+            .map(|(index, _)| Choice::Dialogue(prompt.from, prompt.to, *index));
+        self.close();
+        choice
+    }
+
+    /// Pick the choice of the given number (starting at 1) directly.
+    pub fn pick(&mut self, number: usize) -> Option<Choice> {
+        if number == 0 || number > self.choices.len() {
+            return None;
+        }
+        self.selected = number - 1;
+        self.revealed = f32::MAX;
+        self.confirm()
+    }
+
+    /// End the dialogue.
+    pub fn cancel(&mut self) -> Option<Choice> {
+        let prompt = self.prompt.as_ref()?;
+        let choice = if prompt.choices.is_empty() {
+            None
+        } else {
+            Some(Choice::Cancel(prompt.from, prompt.to))
+        };
+        self.close();
+        choice
+    }
+
+    /// Select the choice under the mouse.
+    pub fn hover(&mut self, coord: Vec2<i32>) {
+        let p = Vec2::new(coord.x as f32, coord.y as f32);
+        if let Some(index) = self.choice_rects.iter().position(|rect| rect.contains(p)) {
+            self.selected = index;
+        }
+    }
+
+    /// A click on a choice confirms it, a click elsewhere in the widget acts like confirm
+    /// without choices. Returns None for clicks outside of the widget.
+    pub fn touch_down(&mut self, coord: Vec2<i32>) -> Option<Option<Choice>> {
+        let p = Vec2::new(coord.x as f32, coord.y as f32);
+        if !self.is_active() || !self.rect.contains(p) {
+            return None;
+        }
+        if let Some(index) = self.choice_rects.iter().position(|rect| rect.contains(p)) {
+            self.selected = index;
+            return Some(self.confirm());
+        }
+        if !self.fully_revealed() || self.choices.is_empty() {
+            return Some(self.confirm());
+        }
+        Some(None)
+    }
+
+    pub fn update_draw(&mut self, buffer: &mut TheRGBABuffer, map: &Map, assets: &Assets) {
+        let Some(prompt) = &self.prompt else {
+            return;
+        };
+        let stride = buffer.stride();
+        let safe = (
+            0,
+            0,
+            buffer.dim().width as isize,
+            buffer.dim().height as isize,
+        );
+
+        self.draw2d.blend_rect_safe(
+            buffer.pixels_mut(),
+            &(
+                self.rect.x as isize,
+                self.rect.y as isize,
+                self.rect.width as isize,
+                self.rect.height as isize,
+            ),
+            stride,
+            &self.background,
+            &safe,
+        );
+
+        let padding = 6.0;
+        let mut x = self.rect.x + padding;
+        let mut y = self.rect.y + padding;
+
+        // The portrait of the speaker
+        let portrait = map
+            .entities
+            .iter()
+            .find(|entity| entity.id == prompt.from)
+            .and_then(|entity| match entity.attributes.get("portrait") {
+                Some(Value::Source(source)) => source.tile_from_tile_list(assets),
+                _ => None,
+            });
+        if let Some(tile) = portrait {
+            if let Some(texture) = tile.textures.first() {
+                self.draw2d.blend_scale_chunk(
+                    buffer.pixels_mut(),
+                    &(
+                        x as usize,
+                        y as usize,
+                        self.portrait_size as usize,
+                        self.portrait_size as usize,
+                    ),
+                    stride,
+                    &texture.data,
+                    &(texture.width as usize, texture.height as usize),
+                );
+                x += self.portrait_size + padding;
+            }
+        }
+
+        let Some(font) = &self.font else {
+            return;
+        };
+        let text_width = (self.rect.x + self.rect.width - padding - x).max(0.0);
+        let line_height = self.font_size + self.spacing;
+        let draw_line = |buffer: &mut TheRGBABuffer, y: f32, text: &str, color: &Pixel| {
+            self.draw2d.text_rect_blend_safe(
+                buffer.pixels_mut(),
+                &(
+                    x as isize,
+                    y as isize,
+                    text_width as isize,
+                    self.font_size as isize,
+                ),
+                stride,
+                font,
+                self.font_size,
+                text,
+                color,
+                draw2d::TheHorizontalAlign::Left,
+                draw2d::TheVerticalAlign::Center,
+                &safe,
+            );
+        };
+
+        if !self.speaker.is_empty() {
+            draw_line(buffer, y, &self.speaker, &self.name_color);
+            y += line_height;
+        }
+
+        // Reveal the text character by character
+        let revealed: String = self.text.chars().take(self.revealed as usize).collect();
+        for line in self.wrap(font, &revealed, text_width) {
+            draw_line(buffer, y, &line, &self.color);
+            y += line_height;
+        }
+
+        let fully_revealed = self.fully_revealed();
+        if !fully_revealed {
+            self.revealed += self.reveal_speed;
+        }
+
+        // The choices are shown once the text is complete
+        self.choice_rects.clear();
+        if fully_revealed {
+            y += self.spacing;
+            for (index, choice) in self.choices.iter().enumerate() {
+                let (text, color) = if index == self.selected {
+                    (format!("> {}) {}", index + 1, choice), &self.selected_color)
+                } else {
+                    (format!("  {}) {}", index + 1, choice), &self.choice_color)
+                };
+                draw_line(buffer, y, &text, color);
+                self.choice_rects
+                    .push(Rect::new(x, y, text_width, self.font_size));
+                y += line_height;
+            }
+        }
+
+        self.draw2d.rect_outline(
+            buffer.pixels_mut(),
+            &(
+                self.rect.x as usize,
+                self.rect.y as usize,
+                self.rect.width as usize,
+                self.rect.height as usize,
+            ),
+            stride,
+            &self.border_color,
+        );
+    }
+
+    /// Break the text into lines fitting the width.
+    fn wrap(&self, font: &fontdue::Font, text: &str, width: f32) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in text.lines() {
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{} {}", line, word)
+                };
+                let (w, _) = self.draw2d.get_text_size(font, self.font_size, &candidate);
+                if w as f32 > width && !line.is_empty() {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Resolves a message
+    fn resolve_msg(&self, msg: &str, map: &Map, assets: &Assets) -> String {
+        self.resolver.resolve(self.parser.parse(msg), map, assets)
+    }
+}
@@ -1,9 +1,47 @@
 use crate::prelude::*;
-use crate::{PlayerCamera, Rect, SceneHandler};
+use crate::{
+    ChunkLod, PlayerCamera, Rect, SceneHandler, WeatherKind,
+    client::bubbles::{BubbleStyle, SpeechBubbles, draw_bubble},
+    client::debug::SceneStats,
+    client::draw2d::Draw2D,
+    client::floattext::{FloatTextSettings, FloatTexts},
+    client::fog::{FogMap, FogStyle},
+    client::lighting::TileLighting,
+    client::particles::ParticleSystem,
+    dynamic_chunk_id,
+    map::occlusion::SectorOcclusion,
+};
 use crate::{ValueGroups, ValueTomlLoader};
+use fontdue::Font;
+use instant::Instant;
 use theframework::prelude::*;
 use vek::Vec2;
 
+/// How the camera follows the player, read from the `[camera]` table of the widget. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// Applies to the 2D and the isometric camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraFollow {
+    pub deadzone: Vec2<f32>,
+    pub smoothing: f32,
+    pub look_ahead: f32,
+    pub clamp: bool,
+    pub snap_distance: f32,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        Self {
+            deadzone: Vec2::zero(),
+            smoothing: 0.0,
+            look_ahead: 0.0,
+            clamp: true,
+            snap_distance: 8.0,
+        }
+    }
+}
+
 pub struct GameWidget {
     pub scenemanager: SceneManager,
 
@@ -22,6 +60,22 @@ pub struct GameWidget {
 
     pub player_pos: Vec2<f32>,
 
+    pub follow: CameraFollow,
+    // The position the camera looks at, follows the player
+    pub camera_focus: Option<Vec2<f32>>,
+    camera_time: Option<Instant>,
+
+    // The light map of the 2D view, None if disabled
+    pub lighting: Option<TileLighting>,
+
+    pub fog_style: FogStyle,
+
+    pub float_text: FloatTextSettings,
+    float_font: Option<Font>,
+
+    pub bubble_style: BubbleStyle,
+    bubble_font: Option<Font>,
+
     pub toml_str: String,
     pub table: ValueGroups,
 
@@ -30,6 +84,19 @@ pub struct GameWidget {
     // Used to detect region changes (have to rebuild the geometry)
     pub build_region_name: String,
 
+    // Batches and triangles of the uploaded chunks, for the debug overlay
+    chunk_stats: FxHashMap<Vec2<i32>, (usize, usize)>,
+
+    // Portal depth of the sector occlusion of the 3D cameras (the `enabled` and `depth` of
+    // the `[occlusion]` table), None if disabled
+    pub occlusion_depth: Option<usize>,
+    occlusion: Option<SectorOcclusion>,
+    occlusion_sector: Option<u32>,
+    // The chunks visible from the sector of the player, None shows all chunks
+    visible_chunks: Option<FxHashSet<(i32, i32)>>,
+    // The chunks removed from the scene by the occlusion, rebuilt once visible again
+    culled_chunks: FxHashSet<Vec2<i32>>,
+
     // Upscale factor (1.0 = no upscaling, >1.0 = render at lower res and upscale)
     pub upscale: f32,
     // Secondary buffer for rendering at lower resolution when upscale > 1
@@ -62,6 +129,20 @@ impl GameWidget {
 
             player_pos: Vec2::zero(),
 
+            follow: CameraFollow::default(),
+            camera_focus: None,
+            camera_time: None,
+
+            lighting: None,
+
+            fog_style: FogStyle::default(),
+
+            float_text: FloatTextSettings::default(),
+            float_font: None,
+
+            bubble_style: BubbleStyle::default(),
+            bubble_font: None,
+
             toml_str: String::new(),
             table: ValueGroups::default(),
 
@@ -69,6 +150,14 @@ impl GameWidget {
 
             build_region_name: String::new(),
 
+            chunk_stats: FxHashMap::default(),
+
+            occlusion_depth: None,
+            occlusion: None,
+            occlusion_sector: None,
+            visible_chunks: None,
+            culled_chunks: FxHashSet::default(),
+
             upscale: 1.0,
             upscale_buffer: TheRGBABuffer::default(),
         }
@@ -80,6 +169,17 @@ impl GameWidget {
             if let Some(ui) = groups.get("ui") {
                 self.grid_size = ui.get_float_default("grid_size", self.grid_size);
                 self.upscale = ui.get_float_default("upscale", 1.0).max(1.0);
+                self.scenemanager
+                    .set_workers(ui.get_int_default("chunk_workers", 0).max(0) as usize);
+                self.scenemanager.budget = ui.get_int_default("chunk_budget", 4).max(1) as usize;
+                let memory = ui.get_float_default("chunk_memory_mb", 0.0);
+                self.scenemanager.memory_budget =
+                    (memory > 0.0).then(|| (memory * 1024.0 * 1024.0) as usize);
+                let radius = ui.get_float_default("chunk_radius", 0.0);
+                self.scenemanager.interest_radius = (radius > 0.0).then_some(radius);
+                let cache = ui.get_str_default("chunk_cache".into(), String::new());
+                self.scenemanager
+                    .set_cache_dir((!cache.is_empty()).then(|| cache.into()));
             }
             if let Some(camera) = groups.get("camera") {
                 let camera_type = camera.get_str_default("type".into(), "2d".into());
@@ -89,9 +189,58 @@ impl GameWidget {
                 } else if camera_type == "firstp" {
                     self.camera = PlayerCamera::D3FirstP;
                 }
+
+                let defaults = CameraFollow::default();
+                self.follow = CameraFollow {
+                    deadzone: Vec2::new(
+                        camera.get_float_default("deadzone_width", 0.0).max(0.0),
+                        camera.get_float_default("deadzone_height", 0.0).max(0.0),
+                    ),
+                    smoothing: camera
+                        .get_float_default("smoothing", defaults.smoothing)
+                        .max(0.0),
+                    look_ahead: camera.get_float_default("look_ahead", defaults.look_ahead),
+                    clamp: camera.get_bool_default("clamp", defaults.clamp),
+                    snap_distance: camera
+                        .get_float_default("snap_distance", defaults.snap_distance),
+                };
+            }
+            if let Some(lighting) = groups.get("lighting") {
+                if lighting.get_bool_default("enabled", true) {
+                    let defaults = TileLighting::default();
+                    let mut tile_lighting = TileLighting::new();
+                    tile_lighting.ambient = lighting
+                        .get_float_default("ambient", defaults.ambient)
+                        .max(0.0);
+                    tile_lighting.samples = lighting
+                        .get_float_default("samples", defaults.samples as f32)
+                        .max(1.0) as usize;
+                    tile_lighting.shadows = lighting.get_bool_default("shadows", defaults.shadows);
+                    tile_lighting.max = lighting.get_float_default("max", defaults.max).max(0.0);
+                    self.lighting = Some(tile_lighting);
+                }
+            }
+            // Distant chunks of the 3D cameras are built with less detail
+            if let Some(lod) = groups.get("lod") {
+                if lod.get_bool_default("enabled", true) && self.camera != PlayerCamera::D2 {
+                    let distance = lod.get_float_default("distance", 24.0).max(1.0);
+                    let levels = lod.get_int_default("levels", 2).max(0);
+                    self.scenemanager.set_lod(ChunkLod {
+                        distances: (1..=levels).map(|i| distance * i as f32).collect(),
+                        hysteresis: lod.get_float_default("hysteresis", 4.0).max(0.0),
+                    });
+                }
+            }
+            if let Some(occlusion) = groups.get("occlusion") {
+                if occlusion.get_bool_default("enabled", true) {
+                    self.occlusion_depth =
+                        Some(occlusion.get_int_default("depth", 3).max(0) as usize);
+                }
             }
             self.table = groups;
         }
+        self.fog_style = FogStyle::from_toml(&self.toml_str);
+        self.float_text = FloatTextSettings::from_toml(&self.toml_str);
     }
 
     pub fn build(&mut self, map: &Map, assets: &Assets, _scene_handler: &mut SceneHandler) {
@@ -104,6 +253,87 @@ impl GameWidget {
 
         self.scenemanager.send(SceneManagerCmd::SetMap(map.clone()));
         self.build_region_name = map.name.clone();
+        self.camera_focus = None;
+
+        if let Some(lighting) = &mut self.lighting {
+            lighting.set_map(map, assets);
+        }
+
+        self.occlusion = match self.occlusion_depth {
+            Some(depth) if self.camera != PlayerCamera::D2 => Some(SectorOcclusion::new(
+                map,
+                self.scenemanager.chunk_size(),
+                depth,
+            )),
+            _ => None,
+        };
+        self.occlusion_sector = None;
+        self.visible_chunks = None;
+        self.culled_chunks.clear();
+
+        self.float_font = assets.fonts.get(&self.float_text.font).cloned();
+        self.bubble_style = BubbleStyle::from_toml(&self.toml_str, assets);
+        self.bubble_font = assets.fonts.get(&self.bubble_style.font).cloned();
+    }
+
+    /// Apply a new version of the built map, i.e. after its MapScript was recompiled. Only the
+    /// chunks covering the changes are rebuilt, the camera and the selection are kept.
+    pub fn update_map(&mut self, map: &Map, assets: &Assets) {
+        if let Some(bbox) = map.bounding_box() {
+            self.map_bbox = bbox;
+        }
+
+        self.scenemanager.update_map(map.clone());
+
+        if let Some(lighting) = &mut self.lighting {
+            lighting.set_map(map, assets);
+        }
+
+        if let Some(occlusion) = &mut self.occlusion {
+            *occlusion = SectorOcclusion::new(map, self.scenemanager.chunk_size(), occlusion.depth);
+        }
+        self.occlusion_sector = None;
+    }
+
+    /// Move the camera focus towards the player, keeping the player inside of the deadzone.
+    fn update_camera_focus(&mut self, orientation: Vec2<f32>) {
+        let now = Instant::now();
+        let dt = self
+            .camera_time
+            .map(|time| now.duration_since(time).as_secs_f32().min(0.1))
+            .unwrap_or(0.0);
+        self.camera_time = Some(now);
+
+        let mut desired = self.player_pos;
+        if self.follow.look_ahead != 0.0 && orientation.magnitude_squared() > 0.0 {
+            desired += orientation.normalized() * self.follow.look_ahead;
+        }
+
+        let focus = match self.camera_focus {
+            Some(focus) if focus.distance(desired) <= self.follow.snap_distance => focus,
+            _ => {
+                self.camera_focus = Some(desired);
+                return;
+            }
+        };
+
+        // Only move by what the target left the deadzone
+        let half = self.follow.deadzone / 2.0;
+        let mut target = focus;
+        for i in 0..2 {
+            if desired[i] > focus[i] + half[i] {
+                target[i] = desired[i] - half[i];
+            } else if desired[i] < focus[i] - half[i] {
+                target[i] = desired[i] + half[i];
+            }
+        }
+
+        let t = if self.follow.smoothing > 0.0 {
+            1.0 - (-dt / self.follow.smoothing).exp()
+        } else {
+            1.0
+        };
+        self.camera_focus = Some(Vec2::lerp(focus, target, t));
     }
 
     pub fn apply_entities(
@@ -127,11 +357,37 @@ impl GameWidget {
                 //     }
                 // }
 
+                // Follow the smoothed position of the player
+                let position = scene_handler.entity_position(entity);
                 if self.camera != PlayerCamera::D2 {
-                    entity.apply_to_camera(&mut self.camera_d3);
+                    if position != entity.position {
+                        let mut entity = entity.clone();
+                        entity.position = position;
+                        entity.apply_to_camera(&mut self.camera_d3);
+                    } else {
+                        entity.apply_to_camera(&mut self.camera_d3);
+                    }
                 }
 
-                self.player_pos = entity.get_pos_xz();
+                self.player_pos = Vec2::new(position.x, position.z);
+                // Stream in the chunks in front of the camera first, the isometric camera
+                // looks in a fixed direction
+                self.scenemanager.set_view_direction(match self.camera {
+                    PlayerCamera::D3FirstP => Some(entity.orientation),
+                    PlayerCamera::D3Iso => Some(Vec2::new(1.0, -1.0)),
+                    _ => None,
+                });
+                if self.camera != PlayerCamera::D3FirstP {
+                    self.update_camera_focus(entity.orientation);
+                }
+                if self.camera == PlayerCamera::D3Iso {
+                    if let Some(focus) = self.camera_focus {
+                        let p = Vec3::new(focus.x, 0.0, focus.y);
+                        self.camera_d3.set_parameter_vec3("center", p);
+                        self.camera_d3
+                            .set_parameter_vec3("position", p + Vec3::new(-10.0, 10.0, 10.0));
+                    }
+                }
                 break;
             }
         }
@@ -154,12 +410,24 @@ impl GameWidget {
         if map.name != self.build_region_name {
             self.build(map, assets, scene_handler);
         }
+        self.scenemanager.set_focus(self.player_pos);
+        self.update_occlusion(map, scene_handler);
         self.scenemanager.tick();
 
         // Apply scene manager chunks
         while let Some(result) = self.scenemanager.receive() {
             match result {
                 SceneManagerResult::Chunk(chunk, _togo, _total, billboards) => {
+                    if !self.is_chunk_visible(chunk.origin) {
+                        self.cull_chunk(chunk.origin, scene_handler);
+                        continue;
+                    }
+
+                    let polys = chunk.polys3d_map.values().flatten();
+                    let batches = polys.clone().count();
+                    let triangles = polys.map(|poly| poly.indices.len()).sum();
+                    self.chunk_stats.insert(chunk.origin, (batches, triangles));
+
                     scene_handler.vm.execute(scenevm::Atom::RemoveChunkAt {
                         origin: chunk.origin,
                     });
@@ -174,7 +442,29 @@ impl GameWidget {
                         scene_handler.billboards.insert(billboard.geo_id, billboard);
                     }
                 }
+                SceneManagerResult::DynamicChunk(chunk, billboards) => {
+                    if !self.is_chunk_visible(chunk.origin) {
+                        continue;
+                    }
+                    scene_handler.vm.execute(scenevm::Atom::AddChunk {
+                        id: dynamic_chunk_id(chunk.origin),
+                        chunk,
+                    });
+                    for billboard in billboards {
+                        scene_handler.billboards.insert(billboard.geo_id, billboard);
+                    }
+                }
+                SceneManagerResult::Evicted((x, y)) => {
+                    let origin = Vec2::new(x, y);
+                    self.chunk_stats.remove(&origin);
+                    self.culled_chunks.remove(&origin);
+                    scene_handler
+                        .vm
+                        .execute(scenevm::Atom::RemoveChunkAt { origin });
+                }
                 SceneManagerResult::Clear => {
+                    self.chunk_stats.clear();
+                    self.culled_chunks.clear();
                     scene_handler.vm.execute(scenevm::Atom::ClearGeometry);
                     scene_handler.billboards.clear();
                     scene_handler.billboard_anim_states.clear();
@@ -200,10 +490,77 @@ impl GameWidget {
         }
     }
 
+    /// Update the visible chunks when the player enters another sector. Chunks which are
+    /// hidden now are removed from the scene, the ones visible again are rebuilt.
+    fn update_occlusion(&mut self, map: &Map, scene_handler: &mut SceneHandler) {
+        let Some(occlusion) = &mut self.occlusion else {
+            return;
+        };
+        let sector = occlusion.sector_at(map, self.player_pos);
+        if sector == self.occlusion_sector {
+            return;
+        }
+        self.occlusion_sector = sector;
+        self.visible_chunks = sector.map(|sector| occlusion.visible_chunks(sector).clone());
+
+        let hidden: Vec<Vec2<i32>> = self
+            .chunk_stats
+            .keys()
+            .filter(|origin| !self.is_chunk_visible(**origin))
+            .copied()
+            .collect();
+        for origin in hidden {
+            self.cull_chunk(origin, scene_handler);
+        }
+
+        let shown: Vec<Vec2<i32>> = self
+            .culled_chunks
+            .iter()
+            .filter(|origin| self.is_chunk_visible(**origin))
+            .copied()
+            .collect();
+        if !shown.is_empty() {
+            for origin in &shown {
+                self.culled_chunks.remove(origin);
+            }
+            self.scenemanager
+                .add_dirty(shown.iter().map(|origin| (origin.x, origin.y)).collect());
+        }
+    }
+
+    fn is_chunk_visible(&self, origin: Vec2<i32>) -> bool {
+        self.visible_chunks
+            .as_ref()
+            .is_none_or(|visible| visible.contains(&(origin.x, origin.y)))
+    }
+
+    fn cull_chunk(&mut self, origin: Vec2<i32>, scene_handler: &mut SceneHandler) {
+        self.chunk_stats.remove(&origin);
+        self.culled_chunks.insert(origin);
+        scene_handler
+            .vm
+            .execute(scenevm::Atom::RemoveChunkAt { origin });
+    }
+
+    /// The chunks and the geometry of the scene of the widget.
+    pub fn scene_stats(&self) -> SceneStats {
+        SceneStats {
+            chunks: self.chunk_stats.len(),
+            pending_chunks: self.scenemanager.remaining_chunks(),
+            batches: self.chunk_stats.values().map(|(batches, _)| batches).sum(),
+            triangles: self
+                .chunk_stats
+                .values()
+                .map(|(_, triangles)| triangles)
+                .sum(),
+            billboards: 0,
+        }
+    }
+
     /// Draw the 2D scene.
     pub fn draw_d2(
         &mut self,
-        _map: &Map,
+        map: &Map,
         time: &TheTime,
         animation_frame: usize,
         _assets: &Assets,
@@ -256,27 +613,30 @@ impl GameWidget {
         let half_screen = screen_size / 2.0;
 
         // Compute unclamped camera center in world space
-        let mut camera_pos = self.player_pos * self.grid_size;
+        let mut camera_pos = self.camera_focus.unwrap_or(self.player_pos) * self.grid_size;
 
         let map_width_px = max_world.x - min_world.x;
         let map_height_px = max_world.y - min_world.y;
 
-        if map_width_px > screen_size.x {
-            camera_pos.x = camera_pos
-                .x
-                .clamp(min_world.x + half_screen.x, max_world.x - half_screen.x);
-        } else {
-            // Center map horizontally
-            camera_pos.x = (min_world.x + max_world.x) / 2.0;
-        }
+        // Keep the camera inside of the map bounds
+        if self.follow.clamp {
+            if map_width_px > screen_size.x {
+                camera_pos.x = camera_pos
+                    .x
+                    .clamp(min_world.x + half_screen.x, max_world.x - half_screen.x);
+            } else {
+                // Center map horizontally
+                camera_pos.x = (min_world.x + max_world.x) / 2.0;
+            }
 
-        if map_height_px > screen_size.y {
-            camera_pos.y = camera_pos
-                .y
-                .clamp(min_world.y + half_screen.y, max_world.y - half_screen.y);
-        } else {
-            // Center map vertically
-            camera_pos.y = (min_world.y + max_world.y) / 2.0;
+            if map_height_px > screen_size.y {
+                camera_pos.y = camera_pos
+                    .y
+                    .clamp(min_world.y + half_screen.y, max_world.y - half_screen.y);
+            } else {
+                // Center map vertically
+                camera_pos.y = (min_world.y + max_world.y) / 2.0;
+            }
         }
 
         let translation_matrix =
@@ -309,6 +669,12 @@ impl GameWidget {
 
         scene_handler.settings.apply_hour(hour);
         scene_handler.settings.apply_2d(&mut scene_handler.vm);
+        if self.lighting.is_some() {
+            // The light map does the lighting, the shader renders unlit
+            scene_handler
+                .vm
+                .execute(scenevm::Atom::SetGP1(Vec4::new(1.0, 1.0, 1.0, 0.0)));
+        }
 
         scene_handler
             .vm
@@ -340,42 +706,22 @@ impl GameWidget {
                 .render_frame(self.buffer.pixels_mut(), width as u32, height as u32);
         }
 
-        // Draw Messages
-
-        /*
-        if let Some(font) = &self.messages_font {
-            for (grid_pos, message, text_size, _) in self.messages_to_draw.values() {
-                let position = map_grid_to_local(screen_size, *grid_pos, map);
-
-                let tuple = (
-                    position.x as isize - *text_size as isize / 2 - 5,
-                    position.y as isize - self.messages_font_size as isize - map.grid_size as isize,
-                    *text_size as isize + 10,
-                    22,
-                );
-
-                self.draw2d.blend_rect_safe(
-                    pixels,
-                    &tuple,
-                    width,
-                    &[0, 0, 0, 128],
-                    &(0, 0, width as isize, height as isize),
-                );
-
-                self.draw2d.text_rect_blend_safe(
-                    pixels,
-                    &tuple,
-                    width,
-                    font,
-                    self.messages_font_size,
-                    message,
-                    &self.messages_font_color,
-                    draw2d::TheHorizontalAlign::Center,
-                    draw2d::TheVerticalAlign::Center,
-                    &(0, 0, width as isize, height as isize),
-                );
-            }
-        }*/
+        // Light pools and wall shadows
+        if let Some(lighting) = &mut self.lighting {
+            let lights = TileLighting::collect_lights(map, scene_handler);
+            lighting.compute(
+                &lights,
+                scene_handler.settings.sky_color,
+                self.top_left,
+                screen_size / self.grid_size,
+                animation_frame as u32,
+            );
+            lighting.apply(
+                &mut self.buffer,
+                self.top_left,
+                self.grid_size * self.upscale,
+            );
+        }
     }
 
     pub fn draw_d3(
@@ -452,6 +798,205 @@ impl GameWidget {
         }
     }
 
+    /// Render the scene at `factor` times the resolution of the widget into a new buffer,
+    /// i.e. for screenshots. The view is the same as the one of the widget.
+    pub fn render_hires(
+        &mut self,
+        factor: usize,
+        map: &Map,
+        time: &TheTime,
+        animation_frame: usize,
+        assets: &Assets,
+        scene_handler: &mut SceneHandler,
+    ) -> TheRGBABuffer {
+        let factor = factor.max(1);
+        let dim = self.buffer.dim();
+        let hires = TheRGBABuffer::new(TheDim::sized(
+            dim.width * factor as i32,
+            dim.height * factor as i32,
+        ));
+
+        let buffer = std::mem::replace(&mut self.buffer, hires);
+        let upscale = std::mem::replace(&mut self.upscale, 1.0);
+        let grid_size = self.grid_size;
+        let top_left = self.top_left;
+        self.grid_size *= factor as f32;
+
+        self.draw(map, time, animation_frame, assets, scene_handler);
+
+        self.grid_size = grid_size;
+        self.top_left = top_left;
+        self.upscale = upscale;
+        std::mem::replace(&mut self.buffer, buffer)
+    }
+
+    /// Draw the particles on top of the 2D scene.
+    pub fn draw_particles(&mut self, particles: &ParticleSystem) {
+        if self.camera == PlayerCamera::D2 {
+            particles.draw_2d(
+                &mut self.buffer,
+                self.top_left,
+                self.grid_size * self.upscale,
+            );
+        }
+    }
+
+    /// Darken the tiles outside of the field of view of the player in the 2D scene.
+    pub fn draw_fog(&mut self, fog: Option<&FogMap>) {
+        if let Some(fog) = fog {
+            if self.camera == PlayerCamera::D2 {
+                fog.draw_2d(
+                    &mut self.buffer,
+                    self.top_left,
+                    self.grid_size * self.upscale,
+                    &self.fog_style,
+                );
+            }
+        }
+    }
+
+    /// Draw the floating texts above their entities, rising and fading out.
+    pub fn draw_float_texts(
+        &mut self,
+        texts: &FloatTexts,
+        map: &Map,
+        scene_handler: &SceneHandler,
+    ) {
+        let Some(font) = &self.float_font else {
+            return;
+        };
+        let draw2d = Draw2D::default();
+        for text in texts.texts() {
+            let Some(entity) = map.entities.iter().find(|e| e.id == text.entity) else {
+                continue;
+            };
+            let position = scene_handler.entity_position(entity);
+            let Some(anchor) = self.screen_anchor(position, self.float_text.height) else {
+                continue;
+            };
+
+            let size = self.float_text.size_of(text.style);
+            let color = self
+                .float_text
+                .colors
+                .get(&text.style)
+                .copied()
+                .unwrap_or([255, 255, 255, 255]);
+            let rise =
+                self.float_text.rise * text.progress(texts.duration) - text.line as f32 * size;
+            draw_faded_text(
+                &draw2d,
+                &mut self.buffer,
+                font,
+                size,
+                &text.text,
+                &color,
+                anchor - Vec2::new(0.0, rise),
+                text.alpha(texts.duration),
+            );
+        }
+    }
+
+    /// Draw the speech bubbles above the entities and items sending messages.
+    pub fn draw_bubbles(
+        &mut self,
+        bubbles: &SpeechBubbles,
+        map: &Map,
+        assets: &Assets,
+        scene_handler: &SceneHandler,
+    ) {
+        let Some(font) = &self.bubble_font else {
+            return;
+        };
+        let width = self.buffer.dim().width as usize;
+        let height = self.buffer.dim().height as usize;
+
+        let draw2d = Draw2D::default();
+        for (speaker, bubble) in bubbles.visible() {
+            let Some(position) = speaker.position(map, scene_handler) else {
+                continue;
+            };
+            let Some(anchor) = self.screen_anchor(position, self.bubble_style.height) else {
+                continue;
+            };
+            draw_bubble(
+                &draw2d,
+                self.buffer.pixels_mut(),
+                width,
+                height,
+                &self.bubble_style,
+                font,
+                &bubble.text,
+                speaker.portrait(map, assets),
+                anchor,
+            );
+        }
+    }
+
+    /// The screen position above the world position, at the given height in the 3D views.
+    /// None if it is behind the camera.
+    fn screen_anchor(&self, position: Vec3<f32>, height: f32) -> Option<Vec2<f32>> {
+        if self.camera == PlayerCamera::D2 {
+            let scale = self.grid_size * self.upscale;
+            let p = Vec2::new(position.x, position.z - 0.5);
+            return Some((p - self.top_left) * scale);
+        }
+
+        let width = self.buffer.dim().width as f32;
+        let buffer_height = self.buffer.dim().height as f32;
+        let view_projection =
+            self.camera_d3.projection_matrix(width, buffer_height) * self.camera_d3.view_matrix();
+        let p = position + Vec3::unit_y() * height;
+        let clip = view_projection * Vec4::new(p.x, p.y, p.z, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some(Vec2::new(
+            (clip.x / clip.w + 1.0) / 2.0 * width,
+            (1.0 - clip.y / clip.w) / 2.0 * buffer_height,
+        ))
+    }
+
+    /// Draw the fog, rain and snow overlay of the weather into the buffer.
+    pub fn draw_weather(&mut self, weather: &WeatherState, animation_frame: usize) {
+        let width = self.buffer.dim().width as usize;
+        let height = self.buffer.dim().height as usize;
+        if width == 0 || height == 0 {
+            return;
+        }
+        let pixels = self.buffer.pixels_mut();
+
+        let fog = weather.amount(WeatherKind::Fog);
+        if fog > 0.0 {
+            for pixel in pixels.chunks_exact_mut(4) {
+                blend_pixel(pixel, [200, 200, 205], 0.35 * fog);
+            }
+        }
+
+        // Rain falls fast in short streaks, snow drifts slowly in small flakes
+        let area = (width * height) as f32;
+        let rain = (area / 600.0 * weather.amount(WeatherKind::Rain)) as usize;
+        for i in 0..rain {
+            let x = weather_hash(i) % width;
+            let y = (weather_hash(i ^ 0x5bd1) + animation_frame * 12) % height;
+            for l in 0..6 {
+                let index = ((y + l) % height * width + (x + l / 3) % width) * 4;
+                blend_pixel(&mut pixels[index..index + 4], [170, 180, 200], 0.5);
+            }
+        }
+
+        let snow = (area / 900.0 * weather.amount(WeatherKind::Snow)) as usize;
+        for i in 0..snow {
+            let sway = ((animation_frame + i) as f32 * 0.1).sin() * 2.0;
+            let x = (weather_hash(i) as f32 + sway) as usize % width;
+            let y = (weather_hash(i ^ 0x5bd1) + animation_frame * 2) % height;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let index = ((y + dy) % height * width + (x + dx) % width) * 4;
+                blend_pixel(&mut pixels[index..index + 4], [250, 250, 255], 0.8);
+            }
+        }
+    }
+
     /// Upscale the source buffer into the destination buffer using nearest-neighbor sampling.
     fn upscale_buffer_into(
         src: &TheRGBABuffer,
@@ -487,3 +1032,73 @@ impl GameWidget {
         }
     }
 }
+
+/// Draw the text centered above the position with a drop shadow. The text is rendered into a
+/// transparent buffer first so that it can be blended with the given opacity.
+#[allow(clippy::too_many_arguments)]
+fn draw_faded_text(
+    draw2d: &Draw2D,
+    buffer: &mut TheRGBABuffer,
+    font: &Font,
+    size: f32,
+    text: &str,
+    color: &Pixel,
+    position: Vec2<f32>,
+    alpha: f32,
+) {
+    let (text_width, text_height) = draw2d.get_text_size(font, size, text);
+    if text_width == 0 || alpha <= 0.0 {
+        return;
+    }
+    let (w, h) = (text_width + 2, text_height + 2);
+    let mut layer = vec![0; w * h * 4];
+    draw2d.text_blend(&mut layer, &(1, 1), w, font, size, text, &[0, 0, 0, 255]);
+    draw2d.text_blend(&mut layer, &(0, 0), w, font, size, text, color);
+
+    let width = buffer.dim().width as isize;
+    let height = buffer.dim().height as isize;
+    let x0 = position.x as isize - w as isize / 2;
+    let y0 = position.y as isize - h as isize;
+    let pixels = buffer.pixels_mut();
+    for y in 0..h as isize {
+        for x in 0..w as isize {
+            let (bx, by) = (x0 + x, y0 + y);
+            if bx < 0 || by < 0 || bx >= width || by >= height {
+                continue;
+            }
+            let src = ((y * w as isize + x) * 4) as usize;
+            let coverage = layer[src + 3] as f32 / 255.0;
+            if coverage <= 0.0 {
+                continue;
+            }
+            // The layer holds the colors multiplied with their coverage
+            let unmultiply = |c: u8| (c as f32 / coverage).min(255.0) as u8;
+            let dst = ((by * width + bx) * 4) as usize;
+            blend_pixel(
+                &mut pixels[dst..dst + 3],
+                [
+                    unmultiply(layer[src]),
+                    unmultiply(layer[src + 1]),
+                    unmultiply(layer[src + 2]),
+                ],
+                coverage * alpha,
+            );
+        }
+    }
+}
+
+/// Blend the color into the RGBA pixel.
+fn blend_pixel(pixel: &mut [u8], color: [u8; 3], alpha: f32) {
+    for (p, c) in pixel.iter_mut().zip(color) {
+        *p = (*p as f32 * (1.0 - alpha) + c as f32 * alpha) as u8;
+    }
+}
+
+/// A cheap integer hash to scatter the weather particles.
+fn weather_hash(n: usize) -> usize {
+    let mut x = (n as u32).wrapping_mul(0x9e37_79b1);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x85eb_ca77);
+    x ^= x >> 13;
+    x as usize
+}
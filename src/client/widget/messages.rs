@@ -1,6 +1,10 @@
 use crate::{
-    Assets, Choice, EntityAction, Map, MsgParser, Pixel, Rect,
-    client::{draw2d, resolver::MsgResolver},
+    Assets, BitmapFont, Choice, DialoguePrompt, EntityAction, Map, MsgParser, Pixel, Rect, Value,
+    client::{
+        draw2d,
+        resolver::MsgResolver,
+        richtext::{RichIcon, RichPart, layout_rich_text, parse_rich_text},
+    },
 };
 use draw2d::Draw2D;
 use theframework::prelude::*;
@@ -11,6 +15,7 @@ pub struct MessagesWidget {
     pub toml_str: String,
     pub buffer: TheRGBABuffer,
     pub font: Option<fontdue::Font>,
+    pub bitmap_font: Option<BitmapFont>,
     pub font_size: f32,
     pub messages: Vec<(Uuid, String, Rect, Option<Choice>, Pixel)>,
     pub draw2d: Draw2D,
@@ -18,6 +23,9 @@ pub struct MessagesWidget {
     pub column_width: f32,
     pub table: toml::Table,
     pub top_down: bool,
+    /// Word wrap the messages to the width of the widget.
+    pub wrap: bool,
+    pub align: draw2d::TheHorizontalAlign,
     pub default_color: Pixel,
     pub clicked: Uuid,
     pub parser: MsgParser,
@@ -38,6 +46,7 @@ impl MessagesWidget {
             toml_str: String::new(),
             buffer: TheRGBABuffer::default(),
             font: None,
+            bitmap_font: None,
             font_size: 20.0,
             messages: vec![],
             draw2d: Draw2D::default(),
@@ -45,6 +54,8 @@ impl MessagesWidget {
             column_width: 20.0,
             table: toml::Table::default(),
             top_down: false,
+            wrap: true,
+            align: draw2d::TheHorizontalAlign::Left,
             default_color: [170, 170, 170, 255],
             clicked: Uuid::nil(),
             parser: MsgParser::new(),
@@ -87,6 +98,20 @@ impl MessagesWidget {
                         self.top_down = v;
                     }
                 }
+                if let Some(value) = ui.get("wrap") {
+                    if let Some(v) = value.as_bool() {
+                        self.wrap = v;
+                    }
+                }
+                if let Some(value) = ui.get("align") {
+                    if let Some(v) = value.as_str() {
+                        self.align = match v {
+                            "center" => draw2d::TheHorizontalAlign::Center,
+                            "right" => draw2d::TheHorizontalAlign::Right,
+                            _ => draw2d::TheHorizontalAlign::Left,
+                        };
+                    }
+                }
                 if let Some(value) = ui.get("default") {
                     if let Some(v) = value.as_str() {
                         self.default_color = self.hex_to_rgba_u8(v);
@@ -96,7 +121,10 @@ impl MessagesWidget {
             self.table = table;
         }
 
-        if let Some(font) = assets.fonts.get(&font_name) {
+        // Bitmap fonts take precedence over a font of the same name
+        if let Some(font) = assets.bitmap_fonts.get(&font_name) {
+            self.bitmap_font = Some(font.clone());
+        } else if let Some(font) = assets.fonts.get(&font_name) {
             self.font = Some(font.clone());
         }
     }
@@ -162,8 +190,11 @@ impl MessagesWidget {
                                             item_name = item
                                                 .get_attr_string("name")
                                                 .unwrap_or("".to_string());
-                                            item_price =
-                                                item.attributes.get_int_default("worth", 0) as i64;
+                                            // Vendors store their asked price
+                                            item_price = item.attributes.get_int("price").unwrap_or(
+                                                item.attributes.get_int_default("worth", 0),
+                                            )
+                                                as i64;
                                             break;
                                         }
                                     }
@@ -209,6 +240,67 @@ impl MessagesWidget {
         }
     }
 
+    /// Process the incoming dialogue prompts, the choices are numbered like multiple choices.
+    pub fn process_dialogues(
+        &mut self,
+        assets: &Assets,
+        map: &Map,
+        dialogues: Vec<DialoguePrompt>,
+    ) -> Option<FxHashMap<char, Choice>> {
+        let mut choice_map = FxHashMap::default();
+
+        let mut text_color = self.default_color;
+        let mut choice_color = self.default_color;
+        if let Some(ui) = self.table.get("ui").and_then(toml::Value::as_table) {
+            if let Some(v) = ui.get("dialogue").and_then(toml::Value::as_str) {
+                text_color = self.hex_to_rgba_u8(v);
+            }
+            if let Some(v) = ui.get("multiple_choice").and_then(toml::Value::as_str) {
+                choice_color = self.hex_to_rgba_u8(v);
+            }
+        }
+
+        for prompt in dialogues {
+            choice_map.clear();
+
+            let text = self.resolve_msg(&prompt.text, map, assets);
+            self.messages
+                .push((Uuid::new_v4(), text, Rect::default(), None, text_color));
+
+            if prompt.choices.is_empty() {
+                continue;
+            }
+
+            choice_map.insert('0', Choice::Cancel(prompt.from, prompt.to));
+            for (number, (index, text)) in prompt.choices.iter().enumerate() {
+                let choice = Choice::Dialogue(prompt.from, prompt.to, *index);
+                choice_map.insert((b'1' + number as u8) as char, choice.clone());
+
+                let text = format!("{}) {}", number + 1, self.resolve_msg(text, map, assets));
+                self.messages.push((
+                    Uuid::new_v4(),
+                    text,
+                    Rect::default(),
+                    Some(choice),
+                    choice_color,
+                ));
+            }
+            self.messages.push((
+                Uuid::new_v4(),
+                self.resolve_msg("0) {exit_menu}", map, assets),
+                Rect::default(),
+                Some(Choice::Cancel(prompt.from, prompt.to)),
+                choice_color,
+            ));
+        }
+
+        if choice_map.is_empty() {
+            None
+        } else {
+            Some(choice_map)
+        }
+    }
+
     pub fn update_draw(
         &mut self,
         buffer: &mut TheRGBABuffer,
@@ -232,51 +324,142 @@ impl MessagesWidget {
         let choice_map = self.process_messages(assets, map, messages, choices);
 
         // Draw bottom up
-        if let Some(font) = &self.font {
+        if self.font.is_some() || self.bitmap_font.is_some() {
             let stride = buffer.stride();
+            let safe = (0, 0, width as isize, height as isize);
+            let line_height = self.font_size + self.spacing;
+            let max_width = if self.wrap { self.rect.width } else { f32::MAX };
             let mut y = if self.top_down {
                 self.rect.y
             } else {
-                self.rect.y + self.rect.height - self.font_size.ceil()
+                self.rect.y + self.rect.height + self.spacing
             };
 
             for (id, message, rect, _choice, color) in self.messages.iter_mut().rev() {
-                if y + self.font_size < self.rect.y {
+                if (self.top_down && y > self.rect.y + self.rect.height)
+                    || (!self.top_down && y < self.rect.y)
+                {
                     break;
                 }
 
-                let tuple = (
-                    self.rect.x as isize,
-                    y.floor() as isize,
-                    self.rect.width as isize,
-                    self.font_size as isize,
-                );
-
                 let color = if *id == self.clicked {
                     darken(*color, 100)
                 } else {
                     *color
                 };
 
-                *rect = Rect::new(self.rect.x, y, self.rect.width, self.font_size);
-
-                self.draw2d.text_rect_blend_safe(
-                    buffer.pixels_mut(),
-                    &tuple,
-                    stride,
-                    font,
-                    self.font_size,
-                    message,
-                    &color,
-                    draw2d::TheHorizontalAlign::Left,
-                    draw2d::TheVerticalAlign::Center,
-                    &(0, 0, width as isize, height as isize),
-                );
+                let parts = parse_rich_text(message, color);
+                let lines = layout_rich_text(&parts, max_width, self.font_size, |text| {
+                    if let Some(font) = &self.bitmap_font {
+                        font.text_size(text, font.scale_for(self.font_size)).0 as f32
+                    } else if let Some(font) = &self.font {
+                        self.draw2d.get_text_size(font, self.font_size, text).0 as f32
+                    } else {
+                        0.0
+                    }
+                });
+
+                // The message block, bottom up messages grow upwards
+                let block_height = lines.len() as f32 * line_height - self.spacing;
+                let top = if self.top_down {
+                    y
+                } else {
+                    y - self.spacing - block_height
+                };
+                *rect = Rect::new(self.rect.x, top, self.rect.width, block_height);
+
+                for (index, line) in lines.iter().enumerate() {
+                    let line_y = top + index as f32 * line_height;
+                    if line_y + self.font_size < self.rect.y
+                        || line_y > self.rect.y + self.rect.height
+                    {
+                        continue;
+                    }
+                    let offset = match self.align {
+                        draw2d::TheHorizontalAlign::Left => 0.0,
+                        draw2d::TheHorizontalAlign::Center => (self.rect.width - line.width) / 2.0,
+                        draw2d::TheHorizontalAlign::Right => self.rect.width - line.width,
+                    };
+
+                    for run in &line.runs {
+                        let x = self.rect.x + offset + run.x;
+                        match &run.part {
+                            RichPart::Text(text, color) => {
+                                let tuple = (
+                                    x.floor() as isize,
+                                    line_y.floor() as isize,
+                                    run.width.ceil() as isize + 1,
+                                    self.font_size as isize,
+                                );
+                                if let Some(font) = &self.bitmap_font {
+                                    self.draw2d.bitmap_text_rect_blend_safe(
+                                        buffer.pixels_mut(),
+                                        &tuple,
+                                        stride,
+                                        font,
+                                        font.scale_for(self.font_size),
+                                        text,
+                                        color,
+                                        draw2d::TheHorizontalAlign::Left,
+                                        draw2d::TheVerticalAlign::Center,
+                                        &safe,
+                                    );
+                                } else if let Some(font) = &self.font {
+                                    self.draw2d.text_rect_blend_safe(
+                                        buffer.pixels_mut(),
+                                        &tuple,
+                                        stride,
+                                        font,
+                                        self.font_size,
+                                        text,
+                                        color,
+                                        draw2d::TheHorizontalAlign::Left,
+                                        draw2d::TheVerticalAlign::Center,
+                                        &safe,
+                                    );
+                                }
+                            }
+                            RichPart::Icon(icon) => {
+                                let tile = match icon {
+                                    RichIcon::Item(item_id) => {
+                                        map.items.iter().find(|item| item.id == *item_id).and_then(
+                                            |item| match item.attributes.get("source") {
+                                                Some(Value::Source(source)) => {
+                                                    source.tile_from_tile_list(assets)
+                                                }
+                                                _ => None,
+                                            },
+                                        )
+                                    }
+                                    RichIcon::Tile(tile_id) => assets.tiles.get(tile_id).cloned(),
+                                };
+                                let size = self.font_size as usize;
+                                let inside = x >= 0.0
+                                    && line_y >= 0.0
+                                    && x as usize + size <= width as usize
+                                    && line_y as usize + size <= height as usize;
+                                if let Some(texture) =
+                                    tile.as_ref().and_then(|t| t.textures.first())
+                                {
+                                    if inside {
+                                        self.draw2d.blend_scale_chunk(
+                                            buffer.pixels_mut(),
+                                            &(x as usize, line_y as usize, size, size),
+                                            stride,
+                                            &texture.data,
+                                            &(texture.width, texture.height),
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
 
                 if self.top_down {
-                    y += self.font_size + self.spacing;
+                    y += block_height + self.spacing;
                 } else {
-                    y -= self.font_size + self.spacing;
+                    y = top;
                 }
             }
         }
@@ -0,0 +1,356 @@
+use crate::client::fog::{FogMap, FogStyle};
+use crate::client::widget::hex_to_rgba_u8;
+use crate::{Assets, BBox, Map, Pixel, Rect};
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// A sector outline of the minimap.
+struct MinimapSector {
+    id: u32,
+    bbox: BBox,
+    polygon: Vec<Vec2<f32>>,
+}
+
+/// Draws the surroundings of the player from above: the explored sectors, entity and item
+/// dots and the player marker in the center. Configured in the `[ui]` table of the widget. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// With the fog of war of the server only the explored tiles are drawn, tiles outside of the
+/// field of view are darkened by the `[fog_of_war]` style and hide their entities.
+pub struct MinimapWidget {
+    pub name: String,
+    pub rect: Rect,
+    pub toml_str: String,
+    pub buffer: TheRGBABuffer,
+
+    pub scale: f32,
+    pub fog: bool,
+    pub background: Pixel,
+    pub floor_color: Pixel,
+    pub wall_color: Pixel,
+    pub entity_color: Pixel,
+    pub item_color: Pixel,
+    pub player_color: Pixel,
+    pub fog_style: FogStyle,
+
+    /// The sectors the player has been in, by map.
+    pub explored: FxHashMap<Uuid, FxHashSet<u32>>,
+
+    map_id: Uuid,
+    sectors: Vec<MinimapSector>,
+    walls: Vec<(Vec2<f32>, Vec2<f32>, u32)>,
+
+    /// The floor layer and the player pixel / explored counts it was drawn for.
+    floor: Vec<u8>,
+    floor_key: Option<(Vec2<i32>, usize, usize, usize)>,
+}
+
+impl Default for MinimapWidget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MinimapWidget {
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            rect: Rect::default(),
+            toml_str: String::new(),
+            buffer: TheRGBABuffer::default(),
+
+            scale: 4.0,
+            fog: true,
+            background: [0, 0, 0, 128],
+            floor_color: [80, 80, 80, 255],
+            wall_color: [192, 192, 192, 255],
+            entity_color: [208, 64, 64, 255],
+            item_color: [208, 208, 64, 255],
+            player_color: [255, 255, 255, 255],
+            fog_style: FogStyle::default(),
+
+            explored: FxHashMap::default(),
+
+            map_id: Uuid::nil(),
+            sectors: vec![],
+            walls: vec![],
+
+            floor: vec![],
+            floor_key: None,
+        }
+    }
+
+    pub fn init(&mut self, _assets: &Assets) {
+        if let Ok(table) = self.toml_str.parse::<toml::Table>() {
+            if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
+                if let Some(value) = ui.get("scale") {
+                    if let Some(v) = value.as_float() {
+                        self.scale = (v as f32).max(0.1);
+                    }
+                }
+                if let Some(value) = ui.get("fog") {
+                    if let Some(v) = value.as_bool() {
+                        self.fog = v;
+                    }
+                }
+                for (key, color) in [
+                    ("background", &mut self.background),
+                    ("floor_color", &mut self.floor_color),
+                    ("wall_color", &mut self.wall_color),
+                    ("entity_color", &mut self.entity_color),
+                    ("item_color", &mut self.item_color),
+                    ("player_color", &mut self.player_color),
+                ] {
+                    if let Some(v) = ui.get(key).and_then(toml::Value::as_str) {
+                        *color = hex_to_rgba_u8(v);
+                    }
+                }
+            }
+        }
+        self.fog_style = FogStyle::from_toml(&self.toml_str);
+    }
+
+    /// Collect the sector polygons and walls of the map.
+    fn build(&mut self, map: &Map) {
+        self.map_id = map.id;
+        self.sectors.clear();
+        self.walls.clear();
+        self.floor_key = None;
+
+        for sector in &map.sectors {
+            if sector.layer.is_some() {
+                continue;
+            }
+            let polygon: Vec<Vec2<f32>> = sector
+                .linedefs
+                .iter()
+                .filter_map(|id| map.find_linedef(*id))
+                .filter_map(|linedef| map.get_vertex(linedef.start_vertex))
+                .collect();
+            if polygon.len() < 3 {
+                continue;
+            }
+            self.sectors.push(MinimapSector {
+                id: sector.id,
+                bbox: sector.bounding_box(map),
+                polygon,
+            });
+        }
+
+        // Walls are the sector edges with only one side in a sector, remember it for the fog
+        for linedef in &map.linedefs {
+            let ([sector], Some(start), Some(end)) = (
+                linedef.sector_ids.as_slice(),
+                map.get_vertex(linedef.start_vertex),
+                map.get_vertex(linedef.end_vertex),
+            ) else {
+                continue;
+            };
+            self.walls.push((start, end, *sector));
+        }
+    }
+
+    fn sector_at(&self, position: Vec2<f32>) -> Option<u32> {
+        self.sectors
+            .iter()
+            .find(|sector| {
+                sector.bbox.contains(position) && point_in_polygon(&sector.polygon, position)
+            })
+            .map(|sector| sector.id)
+    }
+
+    pub fn update_draw(&mut self, map: &Map, fog: Option<&FogMap>) {
+        if self.map_id != map.id || self.sectors.is_empty() {
+            self.build(map);
+        }
+
+        let Some(player) = map.entities.iter().find(|entity| entity.is_player()) else {
+            return;
+        };
+        let center = player.get_pos_xz();
+
+        // Explore the sector of the player
+        if let Some(sector) = self.sector_at(center) {
+            self.explored.entry(map.id).or_default().insert(sector);
+        }
+        let explored = self.explored.get(&map.id).cloned().unwrap_or_default();
+        // The tiles of the fog of war replace the explored sectors
+        let visible = |sector: u32| !self.fog || fog.is_some() || explored.contains(&sector);
+
+        let width = self.buffer.dim().width as usize;
+        let height = self.buffer.dim().height as usize;
+        let half = Vec2::new(width as f32, height as f32) / 2.0;
+        let scale = self.scale;
+        let to_world = |x: usize, y: usize| center + (Vec2::new(x as f32, y as f32) - half) / scale;
+        let to_screen = |p: Vec2<f32>| (p - center) * scale + half;
+
+        // The floor only needs to be redrawn if the player moved a pixel or explored a sector
+        // or tile
+        let key = (
+            (center * scale).floor().as_::<i32>(),
+            explored.len(),
+            fog.map(|fog| fog.explored.len()).unwrap_or(0),
+            fog.map(|fog| fog.visible.len()).unwrap_or(0),
+        );
+        if self.floor_key != Some(key) || self.floor.len() != width * height * 4 {
+            let mut floor = vec![0; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let color = match self.sector_at(to_world(x, y)) {
+                        Some(sector) if visible(sector) => self.floor_color,
+                        _ => self.background,
+                    };
+                    let i = (y * width + x) * 4;
+                    floor[i..i + 4].copy_from_slice(&color);
+                }
+            }
+            for (start, end, sector) in &self.walls {
+                if visible(*sector) {
+                    draw_line(
+                        &mut floor,
+                        width,
+                        height,
+                        to_screen(*start),
+                        to_screen(*end),
+                        &self.wall_color,
+                    );
+                }
+            }
+            if let (true, Some(fog)) = (self.fog, fog) {
+                for y in 0..height {
+                    for x in 0..width {
+                        let brightness = fog.brightness(to_world(x, y), &self.fog_style);
+                        if brightness >= 1.0 {
+                            continue;
+                        }
+                        let i = (y * width + x) * 4;
+                        for c in 0..4 {
+                            let background = self.background[c] as f32;
+                            floor[i + c] = (background
+                                + (floor[i + c] as f32 - background) * brightness)
+                                as u8;
+                        }
+                    }
+                }
+            }
+            self.floor = floor;
+            self.floor_key = Some(key);
+        }
+
+        let in_view = |p: Vec2<f32>| {
+            !self.fog
+                || match fog {
+                    Some(fog) => fog.visible.contains(&p.floor().as_::<i32>()),
+                    None => self
+                        .sector_at(p)
+                        .is_some_and(|sector| explored.contains(&sector)),
+                }
+        };
+        let mut dots = vec![];
+        for item in &map.items {
+            let p = item.get_pos_xz();
+            if item.attributes.get_bool_default("visible", false) && in_view(p) {
+                dots.push((to_screen(p), self.item_color));
+            }
+        }
+        for entity in &map.entities {
+            let p = entity.get_pos_xz();
+            if !entity.is_player()
+                && entity.attributes.get_bool_default("visible", false)
+                && in_view(p)
+            {
+                dots.push((to_screen(p), self.entity_color));
+            }
+        }
+
+        let pixels = self.buffer.pixels_mut();
+        pixels.copy_from_slice(&self.floor);
+        for (p, color) in &dots {
+            draw_dot(pixels, width, height, *p, 1.5, color);
+        }
+
+        // The player marker with its facing
+        draw_dot(pixels, width, height, half, 2.5, &self.player_color);
+        draw_line(
+            pixels,
+            width,
+            height,
+            half,
+            half + player.orientation * 6.0,
+            &self.player_color,
+        );
+    }
+}
+
+/// Ray-casting point in polygon test.
+fn point_in_polygon(polygon: &[Vec2<f32>], point: Vec2<f32>) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        if (polygon[i].y > point.y) != (polygon[j].y > point.y)
+            && point.x
+                < (polygon[j].x - polygon[i].x) * (point.y - polygon[i].y)
+                    / (polygon[j].y - polygon[i].y)
+                    + polygon[i].x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn put_pixel(pixels: &mut [u8], width: usize, height: usize, x: i32, y: i32, color: &Pixel) {
+    if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+        let i = (y as usize * width + x as usize) * 4;
+        pixels[i..i + 4].copy_from_slice(color);
+    }
+}
+
+fn draw_line(
+    pixels: &mut [u8],
+    width: usize,
+    height: usize,
+    from: Vec2<f32>,
+    to: Vec2<f32>,
+    color: &Pixel,
+) {
+    let steps = (to - from)
+        .map(f32::abs)
+        .reduce_partial_max()
+        .ceil()
+        .max(1.0) as i32;
+    // Skip lines far outside of the widget
+    if steps > 4 * (width + height) as i32 {
+        return;
+    }
+    for step in 0..=steps {
+        let p = from + (to - from) * (step as f32 / steps as f32);
+        put_pixel(pixels, width, height, p.x as i32, p.y as i32, color);
+    }
+}
+
+fn draw_dot(
+    pixels: &mut [u8],
+    width: usize,
+    height: usize,
+    center: Vec2<f32>,
+    radius: f32,
+    color: &Pixel,
+) {
+    let r = radius.ceil() as i32;
+    for dy in -r..=r {
+        for dx in -r..=r {
+            if ((dx * dx + dy * dy) as f32) <= radius * radius {
+                put_pixel(
+                    pixels,
+                    width,
+                    height,
+                    center.x as i32 + dx,
+                    center.y as i32 + dy,
+                    color,
+                );
+            }
+        }
+    }
+}
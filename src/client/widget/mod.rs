@@ -1,13 +1,143 @@
+pub mod bar;
 pub mod deco;
+pub mod dialogue;
 pub mod game;
 pub mod messages;
+pub mod minimap;
 pub mod screen;
 pub mod text;
+pub mod textinput;
 
-use crate::{Assets, Entity, Map, Rect, Texture, Value, client::draw2d};
+use crate::{
+    Assets, Entity, Map, Pixel, Rect, Texture, Value,
+    client::{draw2d, richtext::named_color},
+};
 use draw2d::Draw2D;
 use theframework::prelude::*;
 
+/// A 9-slice frame drawn behind a widget, configured in its `[ui]` table. See
+/// `src/client/CONFIG.md` for an example.
+#[derive(Clone)]
+pub struct FrameTile {
+    pub texture: Texture,
+    pub border: (usize, usize, usize, usize),
+    pub scale: usize,
+}
+
+impl FrameTile {
+    /// Read the frame of the `[ui]` table, None if it has no valid `frame_tile`.
+    pub fn from_ui(ui: &toml::Table, assets: &Assets) -> Option<Self> {
+        let id = ui
+            .get("frame_tile")
+            .and_then(toml::Value::as_str)
+            .and_then(|id| Uuid::parse_str(id).ok())?;
+        let texture = assets.tiles.get(&id)?.textures.first()?.clone();
+
+        let border = match ui.get("frame_border") {
+            Some(toml::Value::Integer(border)) => {
+                let border = (*border).max(0) as usize;
+                (border, border, border, border)
+            }
+            Some(toml::Value::Array(values)) if values.len() == 4 => {
+                let values: Vec<usize> = values
+                    .iter()
+                    .map(|v| v.as_integer().unwrap_or(0).max(0) as usize)
+                    .collect();
+                (values[0], values[1], values[2], values[3])
+            }
+            _ => {
+                let border = texture.width.min(texture.height) / 3;
+                (border, border, border, border)
+            }
+        };
+        let scale = ui
+            .get("frame_scale")
+            .and_then(toml::Value::as_integer)
+            .unwrap_or(1)
+            .max(1) as usize;
+
+        Some(Self {
+            texture,
+            border,
+            scale,
+        })
+    }
+
+    pub fn draw(&self, buffer: &mut TheRGBABuffer, rect: &Rect, draw2d: &Draw2D) {
+        let stride = buffer.stride();
+        draw2d.blend_nine_slice(
+            buffer.pixels_mut(),
+            &(
+                rect.x as usize,
+                rect.y as usize,
+                rect.width as usize,
+                rect.height as usize,
+            ),
+            stride,
+            &self.texture.data,
+            &(self.texture.width, self.texture.height),
+            &self.border,
+            self.scale,
+        );
+    }
+}
+
+/// The visual state of a button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonState {
+    Normal,
+    Hover,
+    Pressed,
+    Disabled,
+}
+
+impl ButtonState {
+    pub const ALL: [ButtonState; 4] = [
+        ButtonState::Normal,
+        ButtonState::Hover,
+        ButtonState::Pressed,
+        ButtonState::Disabled,
+    ];
+
+    /// The prefix of the `[ui]` keys of the state, i.e. `hover_tile`.
+    fn prefix(&self) -> &'static str {
+        match self {
+            ButtonState::Normal => "",
+            ButtonState::Hover => "hover_",
+            ButtonState::Pressed => "pressed_",
+            ButtonState::Disabled => "disabled_",
+        }
+    }
+}
+
+/// The tile and tint of a button in one of its states, configured in its `[ui]` table. See
+/// `src/client/CONFIG.md` for an example.
+///
+/// States without a tile use the tile of the normal state.
+#[derive(Clone, Default)]
+pub struct ButtonStyle {
+    pub tile: Option<Texture>,
+    pub tint: Option<Pixel>,
+}
+
+impl ButtonStyle {
+    /// Read the styles of all states, indexed by `ButtonState`.
+    pub fn from_ui(ui: &toml::Table, assets: &Assets) -> [ButtonStyle; 4] {
+        ButtonState::ALL.map(|state| {
+            let tile = ui
+                .get(&format!("{}tile", state.prefix()))
+                .and_then(toml::Value::as_str)
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .and_then(|id| assets.tiles.get(&id)?.textures.first().cloned());
+            let tint = ui
+                .get(&format!("{}tint", state.prefix()))
+                .and_then(toml::Value::as_str)
+                .and_then(named_color);
+            ButtonStyle { tile, tint }
+        })
+    }
+}
+
 /// Used right now for button widgets
 pub struct Widget {
     pub name: String,
@@ -19,6 +149,11 @@ pub struct Widget {
     pub hide: Option<Vec<String>>,
     pub deactivate: Vec<String>,
     pub inventory_index: Option<usize>,
+    /// The UI sound played on click.
+    pub sound: Option<String>,
+    pub frame: Option<FrameTile>,
+    pub styles: [ButtonStyle; 4],
+    pub disabled: bool,
     pub textures: Vec<Texture>,
     pub entity_cursor_id: Option<Uuid>,
     pub entity_clicked_cursor_id: Option<Uuid>,
@@ -44,6 +179,10 @@ impl Widget {
             hide: None,
             deactivate: vec![],
             inventory_index: None,
+            sound: None,
+            frame: None,
+            styles: Default::default(),
+            disabled: false,
             textures: vec![],
             entity_cursor_id: None,
             entity_clicked_cursor_id: None,
@@ -61,8 +200,34 @@ impl Widget {
         draw2d: &Draw2D,
         animation_frame: &usize,
         texture_index: usize,
+        state: ButtonState,
     ) {
+        if let Some(frame) = &self.frame {
+            frame.draw(buffer, &self.rect, draw2d);
+        }
+
         let stride = buffer.stride();
+        let rect = (
+            self.rect.x as usize,
+            self.rect.y as usize,
+            self.rect.width as usize,
+            self.rect.height as usize,
+        );
+
+        let style = &self.styles[state as usize];
+        if let Some(tile) = style
+            .tile
+            .as_ref()
+            .or(self.styles[ButtonState::Normal as usize].tile.as_ref())
+        {
+            draw2d.blend_scale_chunk(
+                buffer.pixels_mut(),
+                &rect,
+                stride,
+                &tile.data,
+                &(tile.width, tile.height),
+            );
+        }
 
         if !self.textures.is_empty() {
             draw2d.blend_scale_chunk(
@@ -109,5 +274,36 @@ impl Widget {
                 }
             }
         }
+
+        if let Some(tint) = &style.tint {
+            draw2d.blend_rect(buffer.pixels_mut(), &rect, stride, tint);
+        }
+    }
+}
+
+/// Converts a hex color string to a [u8; 4] (RGBA).
+/// Accepts "#RRGGBB" or "#RRGGBBAA" formats, anything else is white.
+pub fn hex_to_rgba_u8(hex: &str) -> [u8; 4] {
+    let hex = hex.trim_start_matches('#');
+
+    match hex.len() {
+        6 => match (
+            u8::from_str_radix(&hex[0..2], 16),
+            u8::from_str_radix(&hex[2..4], 16),
+            u8::from_str_radix(&hex[4..6], 16),
+        ) {
+            (Ok(r), Ok(g), Ok(b)) => [r, g, b, 255],
+            _ => [255, 255, 255, 255],
+        },
+        8 => match (
+            u8::from_str_radix(&hex[0..2], 16),
+            u8::from_str_radix(&hex[2..4], 16),
+            u8::from_str_radix(&hex[4..6], 16),
+            u8::from_str_radix(&hex[6..8], 16),
+        ) {
+            (Ok(r), Ok(g), Ok(b), Ok(a)) => [r, g, b, a],
+            _ => [255, 255, 255, 255],
+        },
+        _ => [255, 255, 255, 255],
     }
 }
@@ -1,4 +1,4 @@
-use crate::{Assets, Currencies, Map, Pixel, Rect, WHITE, client::draw2d};
+use crate::{Assets, BitmapFont, Currencies, Map, Pixel, Rect, WHITE, client::draw2d};
 use draw2d::Draw2D;
 use regex::Regex;
 use theframework::prelude::*;
@@ -23,12 +23,16 @@ pub struct TextWidget {
     pub toml_str: String,
     pub buffer: TheRGBABuffer,
     pub font: Option<fontdue::Font>,
+    pub bitmap_font: Option<BitmapFont>,
     pub font_size: f32,
     pub messages: Vec<(String, Pixel)>,
     pub draw2d: Draw2D,
     pub spacing: f32,
     pub table: toml::Table,
     pub text: String,
+    /// The string table key of the text, translated into the language.
+    pub text_key: Option<String>,
+    pub language: String,
     pub color: Pixel,
 }
 
@@ -46,12 +50,15 @@ impl TextWidget {
             toml_str: String::new(),
             buffer: TheRGBABuffer::default(),
             font: None,
+            bitmap_font: None,
             font_size: 20.0,
             messages: vec![],
             draw2d: Draw2D::default(),
             spacing: 1.0,
             table: toml::Table::default(),
             text: String::new(),
+            text_key: None,
+            language: "en".into(),
             color: WHITE,
         }
     }
@@ -80,6 +87,11 @@ impl TextWidget {
                         self.text = v.into();
                     }
                 }
+                if let Some(value) = ui.get("text_key") {
+                    if let Some(v) = value.as_str() {
+                        self.text_key = Some(v.into());
+                    }
+                }
                 if let Some(value) = ui.get("color") {
                     if let Some(v) = value.as_str() {
                         self.color = self.hex_to_rgba_u8(v);
@@ -89,7 +101,10 @@ impl TextWidget {
             self.table = table;
         }
 
-        if let Some(font) = assets.fonts.get(&font_name) {
+        // Bitmap fonts take precedence over a font of the same name
+        if let Some(font) = assets.bitmap_fonts.get(&font_name) {
+            self.bitmap_font = Some(font.clone());
+        } else if let Some(font) = assets.fonts.get(&font_name) {
             self.font = Some(font.clone());
         }
     }
@@ -99,16 +114,20 @@ impl TextWidget {
         buffer: &mut TheRGBABuffer,
         map: &Map,
         currencies: &Currencies,
-        _assets: &Assets,
+        assets: &Assets,
     ) {
-        if let Some(font) = &self.font {
+        if self.font.is_some() || self.bitmap_font.is_some() {
             let stride = buffer.stride();
             let mut y = self.rect.y;
 
             let width = buffer.dim().width;
             let height = buffer.dim().height;
 
-            for line in self.text.lines() {
+            let text = match &self.text_key {
+                Some(key) => assets.tr(&self.language, key, &[]),
+                None => self.text.clone(),
+            };
+            for line in text.lines() {
                 let resolved = substitute_placeholders(line, |cat, key| {
                     match cat {
                         "PLAYER" => {
@@ -118,6 +137,22 @@ impl TextWidget {
                                         return Some(
                                             entity.wallet.get_balance(currencies).to_string(),
                                         );
+                                    } else if key == "EFFECTS" {
+                                        // The icons (or names) of the active status effects
+                                        let effects: Vec<&str> =
+                                            entity
+                                                .effects
+                                                .iter()
+                                                .map(|e| {
+                                                    if e.icon.is_empty() {
+                                                        &e.name
+                                                    } else {
+                                                        &e.icon
+                                                    }
+                                                })
+                                                .map(String::as_str)
+                                                .collect();
+                                        return Some(effects.join(" "));
                                     } else if let Some(value) = entity.attributes.get(key) {
                                         return Some(value.to_string());
                                     }
@@ -137,18 +172,33 @@ impl TextWidget {
                     self.font_size as isize,
                 );
 
-                self.draw2d.text_rect_blend_safe(
-                    buffer.pixels_mut(),
-                    &tuple,
-                    stride,
-                    font,
-                    self.font_size,
-                    &resolved,
-                    &self.color,
-                    draw2d::TheHorizontalAlign::Left,
-                    draw2d::TheVerticalAlign::Center,
-                    &(0, 0, width as isize, height as isize),
-                );
+                if let Some(font) = &self.bitmap_font {
+                    self.draw2d.bitmap_text_rect_blend_safe(
+                        buffer.pixels_mut(),
+                        &tuple,
+                        stride,
+                        font,
+                        font.scale_for(self.font_size),
+                        &resolved,
+                        &self.color,
+                        draw2d::TheHorizontalAlign::Left,
+                        draw2d::TheVerticalAlign::Center,
+                        &(0, 0, width as isize, height as isize),
+                    );
+                } else if let Some(font) = &self.font {
+                    self.draw2d.text_rect_blend_safe(
+                        buffer.pixels_mut(),
+                        &tuple,
+                        stride,
+                        font,
+                        self.font_size,
+                        &resolved,
+                        &self.color,
+                        draw2d::TheHorizontalAlign::Left,
+                        draw2d::TheVerticalAlign::Center,
+                        &(0, 0, width as isize, height as isize),
+                    );
+                }
 
                 y += self.font_size + self.spacing;
             }
@@ -0,0 +1,498 @@
+use crate::client::widget::hex_to_rgba_u8;
+use crate::{
+    Assets, EntityAction, Pixel, Rect,
+    client::{clipboard::Clipboard, draw2d, input::normalize_key},
+};
+use draw2d::Draw2D;
+use theframework::prelude::*;
+
+/// A single line text entry, i.e. for a chat box, naming a character or a debug console.
+/// Configured in the `[ui]` table of the widget. See `src/client/CONFIG.md` for an example.
+///
+/// While focused the widget takes all key events: enter submits the text as an
+/// `EntityAction::Text`, escape drops the focus. Held editing keys repeat after a delay.
+/// Shift with the cursor keys or dragging selects text, control (or command) with A, C, X
+/// and V select all, copy, cut and paste.
+pub struct TextInputWidget {
+    pub name: String,
+    pub rect: Rect,
+    pub toml_str: String,
+    pub font: Option<fontdue::Font>,
+    pub font_size: f32,
+    pub draw2d: Draw2D,
+    pub event: String,
+    pub focus_key: Option<String>,
+    pub placeholder: String,
+    pub max_length: usize,
+    pub keep_focus: bool,
+    pub color: Pixel,
+    pub placeholder_color: Pixel,
+    pub background: Pixel,
+    pub border_color: Pixel,
+    pub focus_color: Pixel,
+    pub selection_color: Pixel,
+
+    pub text: String,
+    pub focused: bool,
+    /// The cursor position in characters.
+    cursor: usize,
+    /// The other end of the selection in characters.
+    anchor: Option<usize>,
+    shift: bool,
+    command: bool,
+    dragging: bool,
+    /// The first visible character.
+    scroll: usize,
+    /// The held editing key and the frames it is held.
+    held: Option<(String, usize)>,
+    frame: usize,
+}
+
+/// Frames before a held key repeats and frames between the repeats.
+const REPEAT_DELAY: usize = 20;
+const REPEAT_RATE: usize = 3;
+
+/// Space between the frame and the text.
+const PADDING: f32 = 4.0;
+
+impl Default for TextInputWidget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextInputWidget {
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            rect: Rect::default(),
+            toml_str: String::new(),
+            font: None,
+            font_size: 16.0,
+            draw2d: Draw2D::default(),
+            event: "text_entered".into(),
+            focus_key: None,
+            placeholder: String::new(),
+            max_length: 128,
+            keep_focus: false,
+            color: [255, 255, 255, 255],
+            placeholder_color: [128, 128, 128, 255],
+            background: [0, 0, 0, 160],
+            border_color: [128, 128, 128, 255],
+            focus_color: [255, 255, 255, 255],
+            selection_color: [72, 120, 200, 160],
+
+            text: String::new(),
+            focused: false,
+            cursor: 0,
+            anchor: None,
+            shift: false,
+            command: false,
+            dragging: false,
+            scroll: 0,
+            held: None,
+            frame: 0,
+        }
+    }
+
+    pub fn init(&mut self, assets: &Assets) {
+        let mut font_name = String::new();
+        if let Ok(table) = self.toml_str.parse::<toml::Table>() {
+            if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
+                if let Some(value) = ui.get("font") {
+                    if let Some(v) = value.as_str() {
+                        font_name = v.into();
+                    }
+                }
+                if let Some(value) = ui.get("font_size") {
+                    if let Some(v) = value.as_float() {
+                        self.font_size = v as f32;
+                    } else if let Some(v) = value.as_integer() {
+                        self.font_size = v as f32;
+                    }
+                }
+                if let Some(value) = ui.get("event") {
+                    if let Some(v) = value.as_str() {
+                        self.event = v.into();
+                    }
+                }
+                if let Some(value) = ui.get("focus_key") {
+                    if let Some(v) = value.as_str() {
+                        self.focus_key = Some(normalize_key(v));
+                    }
+                }
+                if let Some(value) = ui.get("placeholder") {
+                    if let Some(v) = value.as_str() {
+                        self.placeholder = v.into();
+                    }
+                }
+                if let Some(value) = ui.get("max_length") {
+                    if let Some(v) = value.as_integer() {
+                        self.max_length = v.max(1) as usize;
+                    }
+                }
+                if let Some(value) = ui.get("keep_focus") {
+                    if let Some(v) = value.as_bool() {
+                        self.keep_focus = v;
+                    }
+                }
+                for (key, color) in [
+                    ("color", &mut self.color),
+                    ("placeholder_color", &mut self.placeholder_color),
+                    ("background", &mut self.background),
+                    ("border_color", &mut self.border_color),
+                    ("focus_color", &mut self.focus_color),
+                    ("selection_color", &mut self.selection_color),
+                ] {
+                    if let Some(v) = ui.get(key).and_then(toml::Value::as_str) {
+                        *color = hex_to_rgba_u8(v);
+                    }
+                }
+            }
+        }
+
+        if let Some(font) = assets.fonts.get(&font_name) {
+            self.font = Some(font.clone());
+        }
+    }
+
+    pub fn set_focus(&mut self, focused: bool) {
+        if !focused {
+            self.anchor = None;
+            self.shift = false;
+            self.command = false;
+            self.dragging = false;
+        }
+        self.focused = focused;
+        self.held = None;
+        let length = self.text.chars().count();
+        self.cursor = self.cursor.min(length);
+        self.anchor = self.anchor.filter(|anchor| *anchor <= length);
+    }
+
+    /// The selected range in characters, None if nothing is selected.
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        self.anchor
+            .filter(|anchor| *anchor != self.cursor)
+            .map(|anchor| (anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    /// The selected text.
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        Some(self.text.chars().skip(start).take(end - start).collect())
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.text.chars().count();
+    }
+
+    /// Remove the selected text, returns false if nothing was selected.
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            self.anchor = None;
+            return false;
+        };
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.text.replace_range(range, "");
+        self.cursor = start;
+        self.anchor = None;
+        true
+    }
+
+    /// Move the cursor, with shift held the selection follows it.
+    fn move_cursor(&mut self, position: usize) {
+        if self.shift {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = position;
+    }
+
+    /// The byte offset of a character position.
+    fn byte_index(&self, position: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(position)
+            .map(|(index, _)| index)
+            .unwrap_or(self.text.len())
+    }
+
+    /// Insert committed text at the cursor, i.e. typed characters or the result of an IME.
+    /// The text replaces the selection, control characters are dropped.
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        for c in text.chars().filter(|c| !c.is_control()) {
+            if self.text.chars().count() >= self.max_length {
+                break;
+            }
+            let index = self.byte_index(self.cursor);
+            self.text.insert(index, c);
+            self.cursor += 1;
+        }
+    }
+
+    /// Apply an editing key, returns false for keys which do not edit.
+    fn edit(&mut self, key: &str) -> bool {
+        let length = self.text.chars().count();
+        match key {
+            "backspace" => {
+                if !self.delete_selection() && self.cursor > 0 {
+                    self.cursor -= 1;
+                    let index = self.byte_index(self.cursor);
+                    self.text.remove(index);
+                }
+            }
+            "delete" => {
+                if !self.delete_selection() && self.cursor < length {
+                    let index = self.byte_index(self.cursor);
+                    self.text.remove(index);
+                }
+            }
+            // Without shift the cursor keys collapse a selection to its edge
+            "left" | "arrowleft" => match self.selection().filter(|_| !self.shift) {
+                Some((start, _)) => self.move_cursor(start),
+                None => self.move_cursor(self.cursor.saturating_sub(1)),
+            },
+            "right" | "arrowright" => match self.selection().filter(|_| !self.shift) {
+                Some((_, end)) => self.move_cursor(end),
+                None => self.move_cursor((self.cursor + 1).min(length)),
+            },
+            "home" => self.move_cursor(0),
+            "end" => self.move_cursor(length),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handle a key down of the focused widget. Returns the action of a submit.
+    pub fn key_down(&mut self, key: &str, clipboard: &mut Clipboard) -> Option<EntityAction> {
+        let normalized = normalize_key(key);
+        match normalized.as_str() {
+            "shift" => self.shift = true,
+            "control" | "ctrl" | "meta" | "command" | "cmd" | "super" => self.command = true,
+            "a" if self.command => self.select_all(),
+            "c" if self.command => {
+                if let Some(text) = self.selected_text() {
+                    clipboard.set(&text);
+                }
+            }
+            "x" if self.command => {
+                if let Some(text) = self.selected_text() {
+                    clipboard.set(&text);
+                    self.delete_selection();
+                }
+            }
+            "v" if self.command => {
+                let text = clipboard.get();
+                self.insert(&text);
+            }
+            "enter" => {
+                let text = std::mem::take(&mut self.text);
+                self.cursor = 0;
+                self.anchor = None;
+                if !self.keep_focus {
+                    self.set_focus(false);
+                }
+                if !text.trim().is_empty() {
+                    return Some(EntityAction::Text(self.event.clone(), text));
+                }
+            }
+            "escape" => self.set_focus(false),
+            _ => {
+                // Repeats of the system are ignored, we repeat held keys ourselves
+                if self
+                    .held
+                    .as_ref()
+                    .is_some_and(|(held, _)| *held == normalized)
+                {
+                    return None;
+                }
+                if self.edit(&normalized) {
+                    self.held = Some((normalized, 0));
+                } else if key.chars().count() == 1 && !self.command {
+                    self.insert(key);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn key_up(&mut self, key: &str) {
+        let key = normalize_key(key);
+        match key.as_str() {
+            "shift" => self.shift = false,
+            "control" | "ctrl" | "meta" | "command" | "cmd" | "super" => self.command = false,
+            _ => {}
+        }
+        if self.held.as_ref().is_some_and(|(held, _)| *held == key) {
+            self.held = None;
+        }
+    }
+
+    /// A click at the viewport x coordinate places the cursor, with shift held it extends
+    /// the selection. Dragging selects.
+    pub fn touch_down(&mut self, x: f32) {
+        let position = self.cursor_at(x);
+        if self.shift {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = Some(position);
+        }
+        self.cursor = position;
+        self.dragging = true;
+    }
+
+    pub fn touch_dragged(&mut self, x: f32) {
+        if self.dragging {
+            self.cursor = self.cursor_at(x);
+        }
+    }
+
+    pub fn touch_up(&mut self) {
+        self.dragging = false;
+    }
+
+    /// The character position closest to the viewport x coordinate.
+    fn cursor_at(&self, x: f32) -> usize {
+        let chars: Vec<char> = self.text.chars().collect();
+        let Some(font) = &self.font else {
+            return chars.len();
+        };
+        let x = x - (self.rect.x + PADDING);
+        let start = self.scroll.min(chars.len());
+        let mut position = start;
+        let mut left = 0.0;
+        while position < chars.len() {
+            let text: String = chars[start..=position].iter().collect();
+            let right = self.draw2d.get_text_size(font, self.font_size, &text).0 as f32;
+            if x < (left + right) / 2.0 {
+                break;
+            }
+            left = right;
+            position += 1;
+        }
+        position
+    }
+
+    pub fn update_draw(&mut self, buffer: &mut TheRGBABuffer) {
+        self.frame = self.frame.wrapping_add(1);
+
+        // Repeat the held editing key
+        let repeat = match &mut self.held {
+            Some((key, frames)) => {
+                *frames += 1;
+                (*frames >= REPEAT_DELAY && (*frames - REPEAT_DELAY) % REPEAT_RATE == 0)
+                    .then(|| key.clone())
+            }
+            None => None,
+        };
+        if let Some(key) = repeat {
+            self.edit(&key);
+        }
+
+        let stride = buffer.stride();
+        let safe = (
+            0,
+            0,
+            buffer.dim().width as isize,
+            buffer.dim().height as isize,
+        );
+        let rect = (
+            self.rect.x as usize,
+            self.rect.y as usize,
+            self.rect.width as usize,
+            self.rect.height as usize,
+        );
+
+        self.draw2d
+            .blend_rect(buffer.pixels_mut(), &rect, stride, &self.background);
+        let border = if self.focused {
+            &self.focus_color
+        } else {
+            &self.border_color
+        };
+        self.draw2d
+            .rect_outline(buffer.pixels_mut(), &rect, stride, border);
+
+        let Some(font) = &self.font else {
+            return;
+        };
+        let padding = PADDING;
+        let width = (self.rect.width - padding * 2.0).max(0.0);
+
+        // Scroll the text so that the cursor stays visible
+        let chars: Vec<char> = self.text.chars().collect();
+        let mut start = 0;
+        let text_x = |start: usize, end: usize| -> f32 {
+            let before: String = chars[start..end].iter().collect();
+            self.draw2d.get_text_size(font, self.font_size, &before).0 as f32
+        };
+        let cursor_x = |start: usize| text_x(start, self.cursor);
+        while start < self.cursor && cursor_x(start) > width {
+            start += 1;
+        }
+        let visible: String = chars[start..].iter().collect();
+
+        // The selection behind the text
+        if let Some((from, to)) = self.selection() {
+            let from = from.max(start);
+            if to > from {
+                let x0 = text_x(start, from);
+                let x1 = text_x(start, to).min(width);
+                let y = self.rect.y + (self.rect.height - self.font_size) / 2.0;
+                self.draw2d.blend_rect_safe(
+                    buffer.pixels_mut(),
+                    &(
+                        (self.rect.x + padding + x0) as isize,
+                        y as isize,
+                        (x1 - x0).ceil() as isize,
+                        self.font_size as isize,
+                    ),
+                    stride,
+                    &self.selection_color,
+                    &safe,
+                );
+            }
+        }
+
+        let (text, color) = if visible.is_empty() && !self.focused {
+            (self.placeholder.as_str(), &self.placeholder_color)
+        } else {
+            (visible.as_str(), &self.color)
+        };
+        self.draw2d.text_rect_blend_safe(
+            buffer.pixels_mut(),
+            &(
+                (self.rect.x + padding) as isize,
+                self.rect.y as isize,
+                width as isize,
+                self.rect.height as isize,
+            ),
+            stride,
+            font,
+            self.font_size,
+            text,
+            color,
+            draw2d::TheHorizontalAlign::Left,
+            draw2d::TheVerticalAlign::Center,
+            &safe,
+        );
+
+        // The blinking cursor
+        if self.focused && (self.frame / 30) % 2 == 0 {
+            let x = self.rect.x + padding + cursor_x(start);
+            let y = self.rect.y + (self.rect.height - self.font_size) / 2.0;
+            self.draw2d.blend_rect_safe(
+                buffer.pixels_mut(),
+                &(x as isize, y as isize, 1, self.font_size as isize),
+                stride,
+                &self.color,
+                &safe,
+            );
+        }
+        self.scroll = start;
+    }
+}
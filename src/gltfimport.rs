@@ -0,0 +1,517 @@
+use crate::{
+    Batch3D, IntoDataInput, Material, MaterialModifier, MaterialRole, PixelSource, Texture,
+};
+use gltf::animation::{Interpolation, util::ReadOutputs};
+use std::path::Path;
+use theframework::prelude::TheColor;
+use vek::{Mat4, Quaternion, Vec3, Vec4};
+
+/// A glTF 2.0 scene (`.gltf` with embedded or external buffers, or `.glb`): the meshes of its
+/// nodes with their materials, the images and the skins and animations. The scene is turned
+/// into batches by `to_batches`, or posed at a time of an animation by `to_batches_at`.
+#[derive(Debug, Clone, Default)]
+pub struct GltfScene {
+    pub nodes: Vec<GltfNode>,
+    /// The nodes of the scene which have no parent.
+    pub roots: Vec<usize>,
+    pub meshes: Vec<Vec<GltfPrimitive>>,
+    /// The images of the file, the texture index of a material is the index of its image.
+    pub textures: Vec<Texture>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<GltfAnimation>,
+}
+
+/// A node of the scene with its local transform.
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub translation: Vec3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vec3<f32>,
+    pub mesh: Option<usize>,
+    pub skin: Option<usize>,
+}
+
+/// The triangles of a mesh with one material.
+#[derive(Debug, Clone, Default)]
+pub struct GltfPrimitive {
+    pub positions: Vec<Vec3<f32>>,
+    pub normals: Vec<Vec3<f32>>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<(usize, usize, usize)>,
+    /// The joints (indices into the joints of the skin) and their weights per vertex, empty
+    /// if the primitive is not skinned.
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+
+    /// The base color and the index of the base color texture.
+    pub base_color: Vec4<f32>,
+    pub base_color_texture: Option<usize>,
+    pub material: Material,
+}
+
+/// The joints of a skin with their inverse bind matrices.
+#[derive(Debug, Clone, Default)]
+pub struct GltfSkin {
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4<f32>>,
+}
+
+/// The keyframes of the transforms of the nodes.
+#[derive(Debug, Clone, Default)]
+pub struct GltfAnimation {
+    pub name: String,
+    pub channels: Vec<GltfChannel>,
+    /// The time of the last keyframe in seconds.
+    pub duration: f32,
+}
+
+/// The property of a node animated by a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GltfProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+/// The keyframes of one property of a node, translations and scales use xyz of the values,
+/// rotations are quaternions.
+#[derive(Debug, Clone)]
+pub struct GltfChannel {
+    pub node: usize,
+    pub property: GltfProperty,
+    pub step: bool,
+    pub times: Vec<f32>,
+    pub values: Vec<Vec4<f32>>,
+}
+
+impl GltfChannel {
+    /// The value at the time, clamped to the first and the last keyframe.
+    pub fn sample(&self, time: f32) -> Option<Vec4<f32>> {
+        let last = self.times.len().min(self.values.len()).checked_sub(1)?;
+        let next = self.times[..=last].partition_point(|t| *t <= time);
+        if next == 0 {
+            return Some(self.values[0]);
+        }
+        if next > last {
+            return Some(self.values[last]);
+        }
+        let (a, b) = (self.values[next - 1], self.values[next]);
+        let span = self.times[next] - self.times[next - 1];
+        if self.step || span <= 0.0 {
+            return Some(a);
+        }
+        let t = (time - self.times[next - 1]) / span;
+        Some(match self.property {
+            GltfProperty::Rotation => {
+                let q = Quaternion::slerp(Quaternion::from_vec4(a), Quaternion::from_vec4(b), t);
+                q.into_vec4()
+            }
+            _ => a + (b - a) * t,
+        })
+    }
+}
+
+impl GltfScene {
+    /// Load a `.gltf` or `.glb` file, external buffers and images are loaded from its
+    /// directory.
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self, gltf::Error> {
+        let (document, buffers, images) = gltf::import(path)?;
+        Ok(Self::from_document(&document, &buffers, &images))
+    }
+
+    /// Load a `.glb` or a `.gltf` with embedded buffers and images.
+    pub fn load(input: impl IntoDataInput) -> Result<Self, gltf::Error> {
+        let data = input.load_data().map_err(gltf::Error::Io)?;
+        let (document, buffers, images) = gltf::import_slice(&data)?;
+        Ok(Self::from_document(&document, &buffers, &images))
+    }
+
+    fn from_document(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        images: &[gltf::image::Data],
+    ) -> Self {
+        let buffer = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| &data.0[..]);
+
+        let mut scene = GltfScene {
+            textures: images.iter().map(to_texture).collect(),
+            ..Default::default()
+        };
+
+        for node in document.nodes() {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            scene.nodes.push(GltfNode {
+                name: node.name().unwrap_or_default().to_string(),
+                parent: None,
+                translation: Vec3::from(translation),
+                rotation: Quaternion::from_vec4(Vec4::from(rotation)),
+                scale: Vec3::from(scale),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                skin: node.skin().map(|skin| skin.index()),
+            });
+        }
+        for node in document.nodes() {
+            for child in node.children() {
+                scene.nodes[child.index()].parent = Some(node.index());
+            }
+        }
+        scene.roots = match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(gltf_scene) => gltf_scene.nodes().map(|node| node.index()).collect(),
+            None => (0..scene.nodes.len())
+                .filter(|index| scene.nodes[*index].parent.is_none())
+                .collect(),
+        };
+
+        for mesh in document.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    continue;
+                }
+                let reader = primitive.reader(buffer);
+                let Some(positions) = reader.read_positions() else {
+                    continue;
+                };
+                let positions: Vec<Vec3<f32>> = positions.map(Vec3::from).collect();
+                let count = positions.len();
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..count as u32).collect(),
+                };
+
+                let pbr = primitive.material().pbr_metallic_roughness();
+                primitives.push(GltfPrimitive {
+                    normals: reader
+                        .read_normals()
+                        .map(|normals| normals.map(Vec3::from).collect())
+                        .unwrap_or_default(),
+                    uvs: reader
+                        .read_tex_coords(0)
+                        .map(|uvs| uvs.into_f32().collect())
+                        .unwrap_or_else(|| vec![[0.0, 0.0]; count]),
+                    indices: indices
+                        .chunks_exact(3)
+                        .map(|t| (t[0] as usize, t[1] as usize, t[2] as usize))
+                        .filter(|(a, b, c)| *a < count && *b < count && *c < count)
+                        .collect(),
+                    joints: reader
+                        .read_joints(0)
+                        .map(|joints| joints.into_u16().collect())
+                        .unwrap_or_default(),
+                    weights: reader
+                        .read_weights(0)
+                        .map(|weights| weights.into_f32().collect())
+                        .unwrap_or_default(),
+                    base_color: Vec4::from(pbr.base_color_factor()),
+                    base_color_texture: pbr
+                        .base_color_texture()
+                        .map(|info| info.texture().source().index()),
+                    material: to_material(&primitive.material()),
+                    positions,
+                });
+            }
+            scene.meshes.push(primitives);
+        }
+
+        for skin in document.skins() {
+            let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+            let inverse_bind_matrices = skin
+                .reader(buffer)
+                .read_inverse_bind_matrices()
+                .map(|matrices| matrices.map(Mat4::from_col_arrays).collect())
+                .unwrap_or_else(|| vec![Mat4::identity(); joints.len()]);
+            scene.skins.push(GltfSkin {
+                joints,
+                inverse_bind_matrices,
+            });
+        }
+
+        for animation in document.animations() {
+            let mut channels = Vec::new();
+            for channel in animation.channels() {
+                let reader = channel.reader(buffer);
+                let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs())
+                else {
+                    continue;
+                };
+                let (property, values): (GltfProperty, Vec<Vec4<f32>>) = match outputs {
+                    ReadOutputs::Translations(values) => (
+                        GltfProperty::Translation,
+                        values.map(|v| Vec4::new(v[0], v[1], v[2], 0.0)).collect(),
+                    ),
+                    ReadOutputs::Rotations(values) => (
+                        GltfProperty::Rotation,
+                        values.into_f32().map(Vec4::from).collect(),
+                    ),
+                    ReadOutputs::Scales(values) => (
+                        GltfProperty::Scale,
+                        values.map(|v| Vec4::new(v[0], v[1], v[2], 0.0)).collect(),
+                    ),
+                    ReadOutputs::MorphTargetWeights(_) => continue,
+                };
+                let interpolation = channel.sampler().interpolation();
+                // Cubic splines store an in-tangent, the value and an out-tangent per key,
+                // they are sampled linearly between the values
+                let values = if interpolation == Interpolation::CubicSpline {
+                    values.into_iter().skip(1).step_by(3).collect()
+                } else {
+                    values
+                };
+                channels.push(GltfChannel {
+                    node: channel.target().node().index(),
+                    property,
+                    step: interpolation == Interpolation::Step,
+                    times: times.collect(),
+                    values,
+                });
+            }
+            let duration = channels
+                .iter()
+                .filter_map(|channel| channel.times.last())
+                .fold(0.0_f32, |a, b| a.max(*b));
+            scene.animations.push(GltfAnimation {
+                name: animation.name().unwrap_or_default().to_string(),
+                channels,
+                duration,
+            });
+        }
+
+        scene
+    }
+
+    /// The batches of the meshes of the scene in their rest pose. Textured materials use the
+    /// static texture at `texture_offset` plus their texture index (see `textures`).
+    pub fn to_batches(&self, texture_offset: u16) -> Vec<Batch3D> {
+        self.batches(&self.local_transforms(None, 0.0), texture_offset)
+    }
+
+    /// The batches of the meshes of the scene posed at the time (in seconds) of the
+    /// animation, skinned meshes are skinned on the CPU.
+    pub fn to_batches_at(&self, animation: usize, time: f32, texture_offset: u16) -> Vec<Batch3D> {
+        let animation = self.animations.get(animation);
+        self.batches(&self.local_transforms(animation, time), texture_offset)
+    }
+
+    /// The local transforms of the nodes, with the channels of the animation applied.
+    fn local_transforms(
+        &self,
+        animation: Option<&GltfAnimation>,
+        time: f32,
+    ) -> Vec<(Vec3<f32>, Quaternion<f32>, Vec3<f32>)> {
+        let mut transforms: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| (node.translation, node.rotation, node.scale))
+            .collect();
+        for channel in animation.iter().flat_map(|animation| &animation.channels) {
+            let (Some(transform), Some(value)) =
+                (transforms.get_mut(channel.node), channel.sample(time))
+            else {
+                continue;
+            };
+            match channel.property {
+                GltfProperty::Translation => transform.0 = value.xyz(),
+                GltfProperty::Rotation => transform.1 = Quaternion::from_vec4(value).normalized(),
+                GltfProperty::Scale => transform.2 = value.xyz(),
+            }
+        }
+        transforms
+    }
+
+    fn batches(
+        &self,
+        locals: &[(Vec3<f32>, Quaternion<f32>, Vec3<f32>)],
+        texture_offset: u16,
+    ) -> Vec<Batch3D> {
+        // The world matrices of the nodes, parents are resolved before their children
+        let mut worlds: Vec<Option<Mat4<f32>>> = vec![None; self.nodes.len()];
+        fn world(
+            index: usize,
+            nodes: &[GltfNode],
+            locals: &[(Vec3<f32>, Quaternion<f32>, Vec3<f32>)],
+            worlds: &mut Vec<Option<Mat4<f32>>>,
+        ) -> Mat4<f32> {
+            if let Some(matrix) = worlds[index] {
+                return matrix;
+            }
+            let (translation, rotation, scale) = locals[index];
+            let local =
+                Mat4::translation_3d(translation) * Mat4::from(rotation) * Mat4::scaling_3d(scale);
+            let matrix = match nodes[index].parent {
+                Some(parent) => world(parent, nodes, locals, worlds) * local,
+                None => local,
+            };
+            worlds[index] = Some(matrix);
+            matrix
+        }
+
+        let mut batches = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let Some(primitives) = node.mesh.and_then(|mesh| self.meshes.get(mesh)) else {
+                continue;
+            };
+            if !self.is_in_scene(index) {
+                continue;
+            }
+            let matrix = world(index, &self.nodes, locals, &mut worlds);
+            let skin = node.skin.and_then(|skin| self.skins.get(skin));
+            let joint_matrices: Option<Vec<Mat4<f32>>> = skin.map(|skin| {
+                skin.joints
+                    .iter()
+                    .zip(&skin.inverse_bind_matrices)
+                    .map(|(joint, inverse)| {
+                        world(*joint, &self.nodes, locals, &mut worlds) * *inverse
+                    })
+                    .collect()
+            });
+
+            for primitive in primitives {
+                let mut batch = match &joint_matrices {
+                    // Skinned vertices are in world space, the node transform is ignored
+                    Some(joints) if !primitive.joints.is_empty() => primitive.skinned_batch(joints),
+                    _ => primitive.batch().transform(matrix),
+                };
+                batch = match primitive.base_color_texture {
+                    Some(texture) => batch.source(PixelSource::StaticTileIndex(
+                        texture_offset + texture as u16,
+                    )),
+                    None => batch.source(PixelSource::Color(TheColor::from(primitive.base_color))),
+                };
+                batches.push(batch.material(primitive.material.clone()));
+            }
+        }
+        batches
+    }
+
+    /// True if the node or one of its ancestors is a root of the scene.
+    fn is_in_scene(&self, mut index: usize) -> bool {
+        loop {
+            if self.roots.contains(&index) {
+                return true;
+            }
+            match self.nodes[index].parent {
+                Some(parent) => index = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl GltfPrimitive {
+    /// The batch of the primitive in the local space of its node.
+    fn batch(&self) -> Batch3D {
+        let vertices = self
+            .positions
+            .iter()
+            .map(|p| [p.x, p.y, p.z, 1.0])
+            .collect();
+        let mut batch = Batch3D::new(vertices, self.indices.clone(), self.uvs.clone());
+        batch.normals = self.normals.clone();
+        batch
+    }
+
+    /// The batch of the primitive deformed by the joint matrices of its skin.
+    fn skinned_batch(&self, joints: &[Mat4<f32>]) -> Batch3D {
+        let skin = |index: usize| -> Mat4<f32> {
+            let (Some(joint), Some(weight)) = (self.joints.get(index), self.weights.get(index))
+            else {
+                return Mat4::identity();
+            };
+            let mut matrix = Mat4::zero();
+            let mut total = 0.0;
+            for (joint, weight) in joint.iter().zip(weight) {
+                if let Some(joint) = joints.get(*joint as usize) {
+                    matrix = matrix + *joint * *weight;
+                    total += weight;
+                }
+            }
+            if total > 0.0 {
+                matrix * (1.0 / total)
+            } else {
+                Mat4::identity()
+            }
+        };
+
+        let mut vertices = Vec::with_capacity(self.positions.len());
+        let mut normals = Vec::with_capacity(self.normals.len());
+        for (index, position) in self.positions.iter().enumerate() {
+            let matrix = skin(index);
+            let p = matrix.mul_point(*position);
+            vertices.push([p.x, p.y, p.z, 1.0]);
+            if let Some(normal) = self.normals.get(index) {
+                normals.push(matrix.mul_direction(*normal).normalized());
+            }
+        }
+        let mut batch = Batch3D::new(vertices, self.indices.clone(), self.uvs.clone());
+        batch.normals = normals;
+        batch
+    }
+}
+
+/// The closest `Material` of the glTF material: emissive, transparent, metallic, glossy or
+/// matte.
+fn to_material(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let emissive = material.emissive_factor();
+    let emission = emissive[0].max(emissive[1]).max(emissive[2]);
+    let (role, value) = if emission > 0.0 {
+        (MaterialRole::Emissive, emission)
+    } else if material.alpha_mode() == gltf::material::AlphaMode::Blend {
+        (MaterialRole::Transparent, 1.0 - pbr.base_color_factor()[3])
+    } else if pbr.metallic_factor() >= 0.5 {
+        (MaterialRole::Metallic, pbr.metallic_factor())
+    } else if pbr.roughness_factor() < 0.5 {
+        (MaterialRole::Glossy, 1.0 - pbr.roughness_factor())
+    } else {
+        (MaterialRole::Matte, 1.0)
+    };
+    Material::new(role, MaterialModifier::None, value, 0.0)
+}
+
+/// Convert an image of the file to an RGBA texture, 16 bit and float channels are scaled.
+fn to_texture(image: &gltf::image::Data) -> Texture {
+    use gltf::image::Format;
+
+    let (width, height) = (image.width as usize, image.height as usize);
+    let channels = |bytes: usize, count: usize| -> Vec<f32> {
+        image
+            .pixels
+            .chunks_exact(bytes)
+            .map(|c| match bytes {
+                1 => c[0] as f32 / 255.0,
+                2 => u16::from_le_bytes([c[0], c[1]]) as f32 / 65535.0,
+                _ => f32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+            })
+            .collect::<Vec<f32>>()
+            .chunks_exact(count)
+            .flat_map(|c| match count {
+                1 => [c[0], c[0], c[0], 1.0],
+                2 => [c[0], c[0], c[0], c[1]],
+                3 => [c[0], c[1], c[2], 1.0],
+                _ => [c[0], c[1], c[2], c[3]],
+            })
+            .collect()
+    };
+    let rgba = match image.format {
+        Format::R8 => channels(1, 1),
+        Format::R8G8 => channels(1, 2),
+        Format::R8G8B8 => channels(1, 3),
+        Format::R8G8B8A8 => channels(1, 4),
+        Format::R16 => channels(2, 1),
+        Format::R16G16 => channels(2, 2),
+        Format::R16G16B16 => channels(2, 3),
+        Format::R16G16B16A16 => channels(2, 4),
+        Format::R32G32B32FLOAT => channels(4, 3),
+        Format::R32G32B32A32FLOAT => channels(4, 4),
+    };
+    let data = rgba
+        .iter()
+        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    Texture::new(data, width, height)
+}
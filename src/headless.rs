@@ -0,0 +1,400 @@
+//! Render maps and scenes into images without a window, i.e. for thumbnails, visual checks
+//! and documentation shots. The `rusterix-render` binary is a command line front end.
+
+#[cfg(feature = "mapscript")]
+use crate::MapScript;
+use crate::{
+    AccumBuffer, Assets, D2Builder, D3Camera, D3FirstPCamera, D3IsoCamera, D3OrbitCamera, Map,
+    MapCamera, Pixel, Rasterizer, RenderMode, Scene, SceneHandler, SceneManager, SceneManagerCmd,
+    SceneManagerResult, Texture, Tile, Tracer, dynamic_chunk_id,
+};
+use indexmap::IndexMap;
+use std::path::Path;
+use theframework::prelude::*;
+use vek::{Mat3, Mat4, Vec2, Vec3, Vec4};
+
+/// The view of a headless render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeadlessCamera {
+    /// A top down view, `offset` is the grid position at the top left corner of the image and
+    /// `grid_size` the size of a grid cell in pixels.
+    D2 { offset: Vec2<f32>, grid_size: f32 },
+    /// An isometric view of `center`, `scale` is half the visible height in world units.
+    Iso { center: Vec3<f32>, scale: f32 },
+    /// A first person view from `position` looking at `center`.
+    FirstP {
+        position: Vec3<f32>,
+        center: Vec3<f32>,
+    },
+    /// A perspective view orbiting `center`, the angles are in radians.
+    Orbit {
+        center: Vec3<f32>,
+        distance: f32,
+        azimuth: f32,
+        elevation: f32,
+    },
+}
+
+impl HeadlessCamera {
+    /// A top down view which fits the whole map into an image of the given size.
+    pub fn fit_2d(map: &Map, width: usize, height: usize) -> Self {
+        let Some(bbox) = map.bounding_box() else {
+            return HeadlessCamera::D2 {
+                offset: Vec2::zero(),
+                grid_size: map.grid_size,
+            };
+        };
+
+        let extent = Vec2::new(bbox.z.max(1.0), bbox.w.max(1.0));
+        let grid_size = (width as f32 / extent.x).min(height as f32 / extent.y);
+
+        // Center the map on the axis with space left over
+        let visible = Vec2::new(width as f32, height as f32) / grid_size;
+        let offset = Vec2::new(bbox.x, bbox.y) - (visible - extent) / 2.0;
+
+        HeadlessCamera::D2 { offset, grid_size }
+    }
+
+    /// An isometric view which fits the whole map.
+    pub fn fit_iso(map: &Map) -> Self {
+        let (center, extent) = Self::map_center(map);
+        HeadlessCamera::Iso {
+            center,
+            scale: (extent * 0.6).max(2.0),
+        }
+    }
+
+    /// An orbit view which fits the whole map.
+    pub fn fit_orbit(map: &Map) -> Self {
+        let (center, extent) = Self::map_center(map);
+        HeadlessCamera::Orbit {
+            center,
+            distance: (extent * 1.2).max(4.0),
+            azimuth: std::f32::consts::FRAC_PI_4,
+            elevation: 0.698,
+        }
+    }
+
+    /// The view matching the camera of the map.
+    pub fn fit(map: &Map, width: usize, height: usize) -> Self {
+        match map.camera {
+            MapCamera::TwoD => Self::fit_2d(map, width, height),
+            MapCamera::ThreeDIso => Self::fit_iso(map),
+            MapCamera::ThreeDFirstPerson => Self::fit_orbit(map),
+        }
+    }
+
+    /// The 3D camera of the view, None for a top down view.
+    pub fn d3_camera(&self) -> Option<Box<dyn D3Camera>> {
+        match *self {
+            HeadlessCamera::D2 { .. } => None,
+            HeadlessCamera::Iso { center, scale } => {
+                let mut camera = D3IsoCamera::new();
+                camera.center = center;
+                camera.scale = scale;
+                // Keep the whole map between the near and far plane
+                camera.distance = scale * 4.0;
+                camera.far = camera.distance * 2.0 + 100.0;
+                Some(Box::new(camera))
+            }
+            HeadlessCamera::FirstP { position, center } => {
+                let mut camera = D3FirstPCamera::new();
+                camera.set_parameter_vec3("position", position);
+                camera.set_parameter_vec3("center", center);
+                Some(Box::new(camera))
+            }
+            HeadlessCamera::Orbit {
+                center,
+                distance,
+                azimuth,
+                elevation,
+            } => {
+                let mut camera = D3OrbitCamera::new();
+                camera.center = center;
+                camera.distance = distance;
+                camera.azimuth = azimuth;
+                camera.elevation = elevation;
+                camera.far = camera.far.max(distance * 4.0);
+                Some(Box::new(camera))
+            }
+        }
+    }
+
+    /// The transform of grid positions to pixels of a top down view.
+    fn transform_2d(&self) -> Mat3<f32> {
+        match *self {
+            HeadlessCamera::D2 { offset, grid_size } => {
+                // Offset is in grid units; translate so the grid cell at offset lands on pixel (0,0)
+                let translation_matrix = Mat3::<f32>::translation_2d(-offset * grid_size);
+                translation_matrix * Mat3::scaling_3d(Vec3::new(grid_size, grid_size, 1.0))
+            }
+            _ => Mat3::identity(),
+        }
+    }
+
+    /// The focus of the view on the map, the chunks are streamed around it.
+    fn focus(&self) -> Vec2<f32> {
+        match *self {
+            HeadlessCamera::D2 { offset, .. } => offset,
+            HeadlessCamera::Iso { center, .. }
+            | HeadlessCamera::FirstP { center, .. }
+            | HeadlessCamera::Orbit { center, .. } => Vec2::new(center.x, center.z),
+        }
+    }
+
+    /// The 3D center and the largest extent of the map.
+    fn map_center(map: &Map) -> (Vec3<f32>, f32) {
+        match map.bounding_box() {
+            Some(bbox) => (
+                Vec3::new(bbox.x + bbox.z / 2.0, 0.0, bbox.y + bbox.w / 2.0),
+                bbox.z.max(bbox.w),
+            ),
+            None => (Vec3::zero(), 10.0),
+        }
+    }
+}
+
+/// Renders maps and scenes on the CPU without a window.
+///
+/// Top down views of maps are rasterized, 3D views of maps are built chunk by chunk and
+/// rendered by the compute renderer of the scene VM. Prebuilt scenes are rasterized or, with
+/// `samples` above 0, path traced.
+pub struct Headless {
+    pub assets: Assets,
+    scene_handler: SceneHandler,
+
+    /// The hour of the day used for lighting.
+    pub hour: f32,
+    /// The background color of the image.
+    pub background: Pixel,
+    /// The number of path traced samples per pixel for `render_scene`, 0 rasterizes.
+    pub samples: usize,
+}
+
+impl Headless {
+    pub fn new(mut assets: Assets) -> Self {
+        if assets.tile_list.is_empty() && !assets.tiles.is_empty() {
+            let tiles = assets.tiles.clone();
+            assets.set_tiles(tiles);
+        }
+        let mut scene_handler = SceneHandler::empty();
+        scene_handler.build_atlas(&assets.tiles, false);
+
+        Self {
+            assets,
+            scene_handler,
+            hour: 12.0,
+            background: [0, 0, 0, 255],
+            samples: 0,
+        }
+    }
+
+    /// Load a map, either a MapScript `.rxm` file or a serialized (JSON) map.
+    pub fn load_map(&mut self, path: &Path) -> Result<Map, String> {
+        let is_script = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("rxm"));
+
+        if is_script {
+            self.load_script(path)
+        } else {
+            let json = std::fs::read_to_string(path)
+                .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+            serde_json::from_str::<Map>(&json)
+                .map_err(|err| format!("Could not parse {}: {}", path.display(), err))
+        }
+    }
+
+    #[cfg(feature = "mapscript")]
+    fn load_script(&mut self, path: &Path) -> Result<Map, String> {
+        let meta = MapScript::default()
+            .compile_file(path, &self.assets.textures)
+            .map_err(|errors| errors.join("\n"))?;
+        if !meta.tiles.is_empty() {
+            let tiles: IndexMap<Uuid, Tile> = meta.tiles.into_iter().collect();
+            self.add_tiles(tiles);
+        }
+        Ok(meta.map)
+    }
+
+    #[cfg(not(feature = "mapscript"))]
+    fn load_script(&mut self, path: &Path) -> Result<Map, String> {
+        Err(format!(
+            "Could not load {}, MapScript needs the mapscript feature",
+            path.display()
+        ))
+    }
+
+    /// Add tiles to the assets and the atlas of the scene VM.
+    pub fn add_tiles(&mut self, tiles: IndexMap<Uuid, Tile>) {
+        self.scene_handler.build_atlas(&tiles, false);
+        let mut all = self.assets.tiles.clone();
+        all.extend(tiles);
+        self.assets.set_tiles(all);
+    }
+
+    /// Render the map with the given view into a new texture.
+    pub fn render_map(
+        &mut self,
+        map: &Map,
+        camera: &HeadlessCamera,
+        width: usize,
+        height: usize,
+    ) -> Texture {
+        let mut texture = Texture::alloc(width, height);
+
+        match camera.d3_camera() {
+            None => {
+                let mut scene = D2Builder::new().build(
+                    map,
+                    &self.assets,
+                    Vec2::new(width as f32, height as f32),
+                );
+                self.rasterize(&mut scene, camera, &mut texture.data, width, height);
+            }
+            Some(d3_camera) => {
+                self.build_chunks(map, camera);
+
+                let scene_handler = &mut self.scene_handler;
+                scene_handler.build_dynamics_3d(map, d3_camera.as_ref(), 0, &self.assets);
+                scene_handler.settings.apply_hour(self.hour);
+                scene_handler.settings.apply_3d(&mut scene_handler.vm);
+
+                let [r, g, b, a] = self.background;
+                scene_handler
+                    .vm
+                    .execute(scenevm::Atom::SetBackground(Vec4::new(
+                        r as f32 / 255.0,
+                        g as f32 / 255.0,
+                        b as f32 / 255.0,
+                        a as f32 / 255.0,
+                    )));
+                scene_handler
+                    .vm
+                    .execute(scenevm::Atom::SetRenderMode(scenevm::RenderMode::Compute3D));
+                scene_handler.vm.execute(scenevm::Atom::SetCamera3D {
+                    camera: d3_camera.as_scenevm_camera(),
+                });
+                scene_handler
+                    .vm
+                    .render_frame(&mut texture.data, width as u32, height as u32);
+            }
+        }
+
+        texture
+    }
+
+    /// Render a prebuilt scene with the given view into a new texture.
+    pub fn render_scene(
+        &mut self,
+        scene: &mut Scene,
+        camera: &HeadlessCamera,
+        width: usize,
+        height: usize,
+    ) -> Texture {
+        let mut texture = Texture::alloc(width, height);
+
+        match camera.d3_camera() {
+            Some(d3_camera) if self.samples > 0 => {
+                let mut tracer = Tracer::new().background(self.background);
+                tracer.hour = self.hour;
+                tracer.compute_static_bboxes(scene);
+                tracer.compute_dynamic_bboxes(scene);
+
+                let mut buffer = AccumBuffer::new(width, height);
+                for _ in 0..self.samples {
+                    tracer.trace(d3_camera.as_ref(), scene, &mut buffer, 64, &self.assets);
+                }
+                buffer.convert_to_u8(&mut texture.data);
+            }
+            _ => self.rasterize(scene, camera, &mut texture.data, width, height),
+        }
+
+        texture
+    }
+
+    /// Rasterize the scene, top down views render the 2D and 3D views the 3D geometry.
+    fn rasterize(
+        &self,
+        scene: &mut Scene,
+        camera: &HeadlessCamera,
+        pixels: &mut [u8],
+        width: usize,
+        height: usize,
+    ) {
+        let mut rast = match camera.d3_camera() {
+            Some(d3_camera) => Rasterizer::setup(
+                None,
+                d3_camera.view_matrix(),
+                d3_camera.projection_matrix(width as f32, height as f32),
+            )
+            .render_mode(RenderMode::render_3d()),
+            None => Rasterizer::setup(
+                Some(camera.transform_2d()),
+                Mat4::identity(),
+                Mat4::identity(),
+            )
+            .render_mode(RenderMode::render_2d()),
+        }
+        .background(self.background);
+        rast.hour = self.hour;
+        rast.mapmini = scene.mapmini.clone();
+        rast.rasterize(scene, pixels, width, height, 40, &self.assets);
+    }
+
+    /// Build all chunks of the map into the scene VM, the chunks are prioritized around the
+    /// focus of the view.
+    fn build_chunks(&mut self, map: &Map, camera: &HeadlessCamera) {
+        let scene_handler = &mut self.scene_handler;
+        scene_handler.vm.execute(scenevm::Atom::ClearGeometry);
+        scene_handler.billboards.clear();
+
+        let mut manager = SceneManager::new();
+        manager.set_tile_list(
+            self.assets.tile_list.clone(),
+            self.assets.tile_indices.clone(),
+        );
+        manager.set_focus(camera.focus());
+        manager.send(SceneManagerCmd::SetMap(map.clone()));
+
+        loop {
+            let busy = manager.tick();
+            while let Some(result) = manager.receive() {
+                match result {
+                    SceneManagerResult::Chunk(chunk, _togo, _total, billboards) => {
+                        scene_handler.vm.execute(scenevm::Atom::RemoveChunkAt {
+                            origin: chunk.origin,
+                        });
+                        scene_handler.vm.execute(scenevm::Atom::AddChunk {
+                            id: Uuid::new_v4(),
+                            chunk,
+                        });
+                        for billboard in billboards {
+                            scene_handler.billboards.insert(billboard.geo_id, billboard);
+                        }
+                    }
+                    SceneManagerResult::DynamicChunk(chunk, billboards) => {
+                        scene_handler.vm.execute(scenevm::Atom::AddChunk {
+                            id: dynamic_chunk_id(chunk.origin),
+                            chunk,
+                        });
+                        for billboard in billboards {
+                            scene_handler.billboards.insert(billboard.geo_id, billboard);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if !busy {
+                break;
+            }
+        }
+    }
+
+    /// Encode the texture as PNG and write it to the path.
+    pub fn save_png(texture: &Texture, path: &Path) -> Result<(), String> {
+        let png = texture.to_png()?;
+        std::fs::write(path, png)
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+}
@@ -5,9 +5,13 @@ pub mod batch;
 pub mod camera;
 pub mod chunk;
 pub mod chunkbuilder;
+pub mod chunkcache;
 pub mod client;
 pub mod collision_world;
 pub mod edge;
+#[cfg(feature = "gltf")]
+pub mod gltfimport;
+pub mod headless;
 pub mod intodata;
 pub mod map;
 pub mod material_profile;
@@ -17,12 +21,15 @@ pub mod render_settings;
 pub mod rendermode;
 pub mod rusterix;
 pub mod scene;
+pub mod scene_export;
 pub mod scene_handler;
 pub mod scenebuilder;
 pub mod scenemanager;
-// pub mod script;
+#[cfg(feature = "mapscript")]
+pub mod script;
 pub mod server;
 pub mod shader;
+pub mod shader_material;
 pub mod shapestack;
 pub mod terrain;
 pub mod texture;
@@ -101,16 +108,36 @@ pub const WHITE: Pixel = [255, 255, 255, 255];
 pub use crate::{
     batch::{CullMode, GeometrySource, PrimitiveMode, batch2d::Batch2D, batch3d::Batch3D},
     camera::{D3Camera, d3firstp::D3FirstPCamera, d3iso::D3IsoCamera, d3orbit::D3OrbitCamera},
-    chunk::{BillboardMetadata, Chunk},
+    chunk::{BillboardMetadata, Chunk, ChunkPart},
     chunkbuilder::{ChunkBuilder, d2chunkbuilder::D2ChunkBuilder, d3chunkbuilder::D3ChunkBuilder},
+    chunkcache::ChunkCache,
     client::{
         Client,
+        audio::SoundManager,
+        bmfont::{BitmapFont, BitmapGlyph},
+        bubbles::{Bubble, BubbleSpeaker, BubbleStyle, SpeechBubbles},
+        clipboard::Clipboard,
         command::Command,
         daylight::Daylight,
+        debug::{DebugOverlay, FrameTimes, SceneStats},
+        floattext::{ActiveFloatText, FloatTextSettings, FloatTexts},
+        fog::{FogMap, FogOfWar, FogStyle},
+        input::{Gamepad, GamepadMapping, InputBinding, KeyMap, UiNav},
+        interpolation::{EntityInterpolator, InterpolationSettings},
+        lighting::TileLighting,
         parser::{MsgParser, Tok},
+        particles::ParticleSystem,
+        postfilter::{ColorBlindness, PostFilter, PostFilterSettings},
+        prediction::{PlayerPrediction, PredictionSettings},
+        recorder::FrameRecorder,
+        scaling::ViewportScaling,
+        theme::Themes,
+        touch::{TouchButton, TouchControls, TouchEvent, TouchSettings},
+        tween::{Easing, Tween, TweenState, TweenTrigger, WidgetTweens},
     },
     collision_world::CollisionWorld,
     edge::Edges,
+    headless::{Headless, HeadlessCamera},
     intodata::IntoDataInput,
     map::{
         Map, MapCamera, MapToolType,
@@ -121,8 +148,10 @@ pub use crate::{
         linedef::CompiledLinedef,
         linedef::Linedef,
         meta::MapMeta,
-        mini::MapMini,
+        mini::{MapMini, MovementMode, MovementModes, MovementZone},
+        occlusion::SectorOcclusion,
         particle::{Particle, ParticleEmitter},
+        patch::MapPatch,
         pixelsource::NoiseTarget,
         pixelsource::PixelSource,
         sector::Sector,
@@ -138,25 +167,60 @@ pub use crate::{
     rendermode::RenderMode,
     rusterix::Rusterix,
     scene::Scene,
+    scene_export::SceneExportFormat,
     scene_handler::SceneHandler,
     scenebuilder::{
         d2builder::D2Builder, d2material::D2MaterialBuilder, d2preview::D2PreviewBuilder,
     },
     scenemanager::*,
-    // script::mapscript::MapScript,
     server::{
         Server, ServerState,
         assets::Assets,
+        behavior::{BehaviorNode, BehaviorStatus, BehaviorTree},
+        combat::{AttackResult, CombatEvent, CombatEventKind, CombatRules},
+        crafting::Recipe,
         currency::{Currencies, Currency, Wallet},
+        cutscene::{CameraHint, Cutscene, CutsceneStep},
+        dialogue::{Dialogue, DialogueChoice, DialogueNode, DialoguePrompt},
+        effect::{EffectDef, EffectStacking, StatusEffect},
         entity::Entity,
         entity::EntityUpdate,
+        floattext::{FloatText, FloatTextStyle},
+        fog::{FogRules, FogUpdate},
+        follow::FollowRules,
         item::{Item, ItemUpdate},
+        leveling::{LevelCurve, LevelingRules},
+        loot::{LootCurrency, LootEntry, LootTable},
         message::EntityAction,
         message::{Choice, MultipleChoice, PlayerCamera, RegionMessage},
+        metrics::{RegionStats, ScriptStats, TICK_BUCKETS_MS, TickHistogram},
+        movement::MovementRules,
+        network::{
+            ChannelTransport, ConnectionId, NetMessage, NetworkClient, NetworkServer, Session,
+            Transport, TransportEvent,
+            interest::{InterestRules, RegionView},
+            protocol::{Delta, Snapshot, SnapshotDecoder, SnapshotDelta, SnapshotEncoder},
+        },
+        ownership::{Claim, Interactable, OwnershipRules},
+        particles::ParticleEvent,
+        persistence::{MemoryPersistence, PersistKey, PersistedRegion, Persistence},
+        projectile::{Projectile, ProjectileDef},
+        quest::{JournalEntry, Quest, QuestObjective, QuestState, QuestStatus},
         region::RegionInstance,
         regionctx::RegionCtx,
+        routine::{Routine, RoutineEntry},
+        savegame::{GameState, RegionState},
+        schedule::{ScheduleRule, ScheduledEvent},
+        schema::{AttrSchema, AttrSchemas, AttrType},
+        shop::Shop,
+        simulation::{RegionRng, SimulationEntry, SimulationInput, SimulationLog, SimulationRules},
+        sound::{AudioEvent, SoundEvent},
+        spawner::SpawnerDef,
+        turn::{TurnRules, TurnState},
+        weather::{Weather, WeatherKind, WeatherRules, WeatherState},
     },
     shader::{Shader, grid::GridShader, vgradient::VGrayGradientShader},
+    shader_material::ShaderMaterial,
     shapestack::{
         ShapeStack,
         material::{Material, MaterialModifier, MaterialRole},
@@ -177,12 +241,30 @@ pub use crate::{
     vertexblend::VertexBlendPreset,
 };
 
+#[cfg(feature = "gltf")]
+pub use crate::gltfimport::{
+    GltfAnimation, GltfChannel, GltfNode, GltfPrimitive, GltfProperty, GltfScene, GltfSkin,
+};
+#[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+pub use crate::server::network::websocket::{WebSocketClient, WebSocketServer};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::server::network::{
+    tcp::{TcpClientTransport, TcpServerTransport},
+    udp::{UdpClientTransport, UdpServerTransport},
+};
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub use crate::server::persistence::sqlite::SqlitePersistence;
+
+#[cfg(feature = "mapscript")]
+pub use crate::script::mapscript::MapScript;
+
 // Prelude
 pub mod prelude {
     pub use crate::Chunk;
     pub use crate::Client;
     pub use crate::IntoDataInput;
-    // pub use crate::MapScript;
+    #[cfg(feature = "mapscript")]
+    pub use crate::MapScript;
     pub use crate::Rasterizer;
     pub use crate::RenderMode;
     pub use crate::scenebuilder::{
@@ -190,8 +272,10 @@ pub mod prelude {
         d3builder::D3Builder,
     };
     pub use crate::{
-        Assets, Choice, Currencies, Currency, Entity, EntityUpdate, Item, ItemUpdate,
-        MultipleChoice, RegionInstance, RegionMessage, Server, Wallet,
+        Assets, CameraHint, Choice, CombatEvent, Currencies, Currency, DialoguePrompt, Entity,
+        EntityUpdate, FloatText, FogUpdate, Item, ItemUpdate, JournalEntry, MultipleChoice,
+        ParticleEvent, RegionInstance, RegionMessage, RegionStats, Server, SoundEvent, Wallet,
+        WeatherState,
     };
     pub use crate::{BLACK, Pixel, TRANSPARENT, WHITE};
     pub use crate::{Batch2D, Batch3D, CullMode, GeometrySource, PrimitiveMode};
@@ -201,7 +285,7 @@ pub mod prelude {
         Keyform, Light, LightType, Map, MapMeta, MapToolType, NoiseTarget, Particle,
         ParticleEmitter, PixelSource, Sector, SoftRig, SoftRigAnimator, Tile, TileRole, Vertex,
     };
-    pub use crate::{Material, MaterialModifier, MaterialRole};
+    pub use crate::{Material, MaterialModifier, MaterialRole, ShaderMaterial};
     pub use crate::{
         Rect, Scene, SceneManager, SceneManagerCmd, SceneManagerResult, Value, ValueContainer,
     };
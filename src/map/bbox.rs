@@ -39,6 +39,12 @@ impl BBox {
             && point.y <= self.max.y
     }
 
+    /// Returns the distance of a point to the bounding box, 0 inside of it
+    pub fn distance(&self, point: Vec2<f32>) -> f32 {
+        let d = (self.min - point).map2(point - self.max, |a, b| a.max(b).max(0.0));
+        d.magnitude()
+    }
+
     /// Returns true if this bounding box intersects another bounding box
     pub fn intersects(&self, other: &BBox) -> bool {
         self.min.x <= other.max.x
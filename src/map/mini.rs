@@ -1,8 +1,101 @@
 use crate::{BBox, CompiledLinedef};
 use pathfinding::prelude::astar;
+use serde::{Deserialize, Serialize};
 use theframework::prelude::FxHashSet;
 use vek::Vec2;
 
+/// How an entity moves at its current position.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MovementMode {
+    Walk,
+    Swim,
+    Fly,
+    Climb,
+}
+
+impl MovementMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovementMode::Walk => "walk",
+            MovementMode::Swim => "swim",
+            MovementMode::Fly => "fly",
+            MovementMode::Climb => "climb",
+        }
+    }
+
+    pub fn from_str(name: &str) -> Option<Self> {
+        match name.trim() {
+            "walk" => Some(MovementMode::Walk),
+            "swim" => Some(MovementMode::Swim),
+            "fly" => Some(MovementMode::Fly),
+            "climb" => Some(MovementMode::Climb),
+            _ => None,
+        }
+    }
+}
+
+/// Sectors which change how entities move: `water` sectors are swum, `ladder` sectors climbed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementZone {
+    Water,
+    Ladder,
+}
+
+/// The movement modes an entity is capable of. Walkers swim in water and climb ladders,
+/// aquatic creatures (only `swim`) cannot leave the water and flying creatures ignore both.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovementModes {
+    pub walk: bool,
+    pub swim: bool,
+    pub fly: bool,
+    pub climb: bool,
+}
+
+impl Default for MovementModes {
+    fn default() -> Self {
+        Self {
+            walk: true,
+            swim: true,
+            fly: false,
+            climb: true,
+        }
+    }
+}
+
+impl MovementModes {
+    /// Parse a comma separated list of modes, i.e. "walk, swim". Unknown modes are ignored.
+    pub fn parse(modes: &str) -> Self {
+        let mut parsed = Self {
+            walk: false,
+            swim: false,
+            fly: false,
+            climb: false,
+        };
+        for mode in modes.split(',').filter_map(MovementMode::from_str) {
+            match mode {
+                MovementMode::Walk => parsed.walk = true,
+                MovementMode::Swim => parsed.swim = true,
+                MovementMode::Fly => parsed.fly = true,
+                MovementMode::Climb => parsed.climb = true,
+            }
+        }
+        parsed
+    }
+
+    /// The mode used in the zone, None if the entity cannot enter it.
+    pub fn mode_in(&self, zone: Option<MovementZone>) -> Option<MovementMode> {
+        if self.fly {
+            return Some(MovementMode::Fly);
+        }
+        match zone {
+            Some(MovementZone::Water) if self.swim => Some(MovementMode::Swim),
+            Some(MovementZone::Ladder) if self.climb => Some(MovementMode::Climb),
+            Some(MovementZone::Ladder) | None if self.walk => Some(MovementMode::Walk),
+            _ => None,
+        }
+    }
+}
+
 /// A miniature version of the Map used for client side lighting calculations during the rasterization process and server side collision detection etc.
 #[derive(Clone)]
 pub struct MapMini {
@@ -18,6 +111,9 @@ pub struct MapMini {
     occluded_sectors: Vec<(BBox, f32)>,
 
     pub blocked_tiles: FxHashSet<Vec2<i32>>,
+
+    /// The water and ladder sectors as polygons.
+    pub zones: Vec<(MovementZone, Vec<Vec2<f32>>)>,
 }
 
 impl Default for MapMini {
@@ -35,6 +131,7 @@ impl MapMini {
             dynamic_linedefs: vec![],
             occluded_sectors: vec![],
             blocked_tiles: FxHashSet::default(),
+            zones: vec![],
         }
     }
 
@@ -51,6 +148,7 @@ impl MapMini {
             dynamic_linedefs: vec![],
             occluded_sectors,
             blocked_tiles: FxHashSet::default(),
+            zones: vec![],
         }
     }
 
@@ -216,6 +314,47 @@ impl MapMini {
         (current_pos, blocked)
     }
 
+    /// The water or ladder zone at the position.
+    pub fn zone_at(&self, at: Vec2<f32>) -> Option<MovementZone> {
+        self.zones
+            .iter()
+            .find(|(_, polygon)| point_in_polygon(polygon, at))
+            .map(|(zone, _)| *zone)
+    }
+
+    /// The movement mode of an entity with the given modes at the position, None if it
+    /// cannot be there (i.e. an aquatic creature on land).
+    pub fn mode_at(&self, at: Vec2<f32>, modes: &MovementModes) -> Option<MovementMode> {
+        modes.mode_in(self.zone_at(at))
+    }
+
+    fn can_enter(&self, at: Vec2<f32>, modes: &MovementModes) -> bool {
+        self.zones.is_empty() || self.mode_at(at, modes).is_some()
+    }
+
+    /// Like `move_distance` but the move also stops at the zones the entity cannot enter. If
+    /// the full move is not possible we slide along the x or y axis.
+    pub fn move_distance_as(
+        &self,
+        start_pos: Vec2<f32>,
+        move_vector: Vec2<f32>,
+        radius: f32,
+        modes: &MovementModes,
+    ) -> (Vec2<f32>, bool) {
+        let (end_pos, blocked) = self.move_distance(start_pos, move_vector, radius);
+        // Entities which already are outside of their zones can always leave
+        if self.can_enter(end_pos, modes) || !self.can_enter(start_pos, modes) {
+            return (end_pos, blocked);
+        }
+        for slide in [Vec2::new(move_vector.x, 0.0), Vec2::new(0.0, move_vector.y)] {
+            let (end_pos, _) = self.move_distance(start_pos, slide, radius);
+            if self.can_enter(end_pos, modes) {
+                return (end_pos, true);
+            }
+        }
+        (start_pos, true)
+    }
+
     /// Precise collision detection with corner handling
     fn check_intersection(
         &self,
@@ -392,8 +531,12 @@ impl MapMini {
         speed: f32,
         radius: f32,
         tile_size: f32,
+        modes: &MovementModes,
     ) -> (Vec2<f32>, bool) {
         let blocked = &self.blocked_tiles;
+        let enterable = |p: &Vec2<i32>| {
+            !blocked.contains(p) && self.can_enter((p.map(|x| x as f32) + 0.5) * tile_size, modes)
+        };
 
         let from_tile = (from / tile_size).floor().as_::<i32>();
         let to_tile = (to / tile_size).floor().as_::<i32>();
@@ -419,7 +562,7 @@ impl MapMini {
             directions
                 .iter()
                 .map(|d| *pos + *d)
-                .filter(|p| !blocked.contains(p))
+                .filter(enterable)
                 .map(|p| (p, 1))
                 .collect::<Vec<_>>()
         };
@@ -445,7 +588,7 @@ impl MapMini {
             }
 
             let move_vector = to_vector.normalized() * max_distance;
-            let (new_pos, _) = self.move_distance(from, move_vector, radius);
+            let (new_pos, _) = self.move_distance_as(from, move_vector, radius, modes);
             (new_pos, false)
         } else {
             // No path found; return unchanged
@@ -463,6 +606,7 @@ impl MapMini {
         speed: f32,
         agent_radius: f32,
         tile_size: f32,
+        modes: &MovementModes,
     ) -> (Vec2<f32>, bool) {
         // --- 1 · already close enough? ------------------------------------------
         if (target - from).magnitude() <= dest_radius {
@@ -470,6 +614,9 @@ impl MapMini {
         }
 
         let blocked = &self.blocked_tiles;
+        let enterable = |p: &Vec2<i32>| {
+            !blocked.contains(p) && self.can_enter((p.map(|x| x as f32) + 0.5) * tile_size, modes)
+        };
 
         let start_cell = (from / tile_size).floor().as_::<i32>();
         // let goal_cell = (target / tile_size).floor().as_::<i32>();
@@ -496,7 +643,7 @@ impl MapMini {
             directions
                 .iter()
                 .map(|d| *pos + *d)
-                .filter(|p| !blocked.contains(p))
+                .filter(enterable)
                 .map(|p| (p, 1)) // uniform cost
                 .collect::<Vec<_>>()
         };
@@ -526,11 +673,483 @@ impl MapMini {
                 let arrived_now = (target - from).magnitude() <= dest_radius + max_step;
 
                 let move_vec = to_vec.normalized() * max_step;
-                let (new_pos, _) = self.move_distance(from, move_vec, agent_radius);
+                let (new_pos, _) = self.move_distance_as(from, move_vec, agent_radius, modes);
 
                 (new_pos, arrived_now)
             }
             None => (from, false), // no path
         }
     }
+
+    /// Returns true if an agent of the given radius fits into the grid cell: the cell is not
+    /// blocked, not occupied and its center keeps the radius to all blocking geometry.
+    pub fn is_cell_walkable(
+        &self,
+        cell: Vec2<i32>,
+        radius: f32,
+        occupied: &FxHashSet<Vec2<i32>>,
+    ) -> bool {
+        if self.blocked_tiles.contains(&cell) || occupied.contains(&cell) {
+            return false;
+        }
+        let center = cell.map(|c| c as f32) + Vec2::new(0.5, 0.5);
+        for linedef in self.linedefs.iter().chain(self.dynamic_linedefs.iter()) {
+            let coll_radius = radius + linedef.wall_width / 2.0;
+            if self
+                .check_point_against_segment(center, linedef.start, linedef.end, coll_radius)
+                .is_some()
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Finds a path on the occupancy grid (one cell per world unit) with 8-directional A*.
+    /// Cells which are blocked, occupied (i.e. by other entities) or too close to walls for the
+    /// given radius are avoided, diagonal moves do not cut corners. The search is limited to
+    /// `margin` cells around the start and goal. Returns the waypoints, ending at `to`.
+    pub fn find_path(
+        &self,
+        from: Vec2<f32>,
+        to: Vec2<f32>,
+        radius: f32,
+        occupied: &FxHashSet<Vec2<i32>>,
+        margin: i32,
+    ) -> Option<Vec<Vec2<f32>>> {
+        let start = from.floor().as_::<i32>();
+        let goal = to.floor().as_::<i32>();
+
+        let min = Vec2::partial_min(start, goal) - margin;
+        let max = Vec2::partial_max(start, goal) + margin;
+
+        // The goal may be occupied by the target itself
+        let walkable = |cell: Vec2<i32>| {
+            cell == goal || cell == start || self.is_cell_walkable(cell, radius, occupied)
+        };
+        if self.blocked_tiles.contains(&goal) {
+            return None;
+        }
+
+        let center = |c: Vec2<i32>| c.map(|v| v as f32) + Vec2::new(0.5, 0.5);
+
+        let successors = |pos: &Vec2<i32>| {
+            let directions = [
+                Vec2::new(-1, 0),
+                Vec2::new(1, 0),
+                Vec2::new(0, -1),
+                Vec2::new(0, 1),
+                Vec2::new(-1, -1),
+                Vec2::new(-1, 1),
+                Vec2::new(1, -1),
+                Vec2::new(1, 1),
+            ];
+            directions
+                .iter()
+                .filter_map(|d| {
+                    let p = *pos + *d;
+                    if p.x < min.x || p.y < min.y || p.x > max.x || p.y > max.y {
+                        return None;
+                    }
+                    if !walkable(p) || self.is_segment_blocked(center(*pos), center(p)) {
+                        return None;
+                    }
+                    if d.x != 0 && d.y != 0 {
+                        // No corner cutting
+                        let a = Vec2::new(pos.x + d.x, pos.y);
+                        let b = Vec2::new(pos.x, pos.y + d.y);
+                        if !walkable(a) || !walkable(b) {
+                            return None;
+                        }
+                        Some((p, 14))
+                    } else {
+                        Some((p, 10))
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Octile distance
+        let heuristic = |a: &Vec2<i32>| {
+            let d = (goal - *a).map(|x| x.abs());
+            10 * (d.x + d.y) - 6 * d.x.min(d.y)
+        };
+
+        let (path, _) = astar(&start, successors, heuristic, |p| *p == goal)?;
+
+        let mut waypoints: Vec<Vec2<f32>> = path
+            .iter()
+            .skip(1)
+            .take(path.len().saturating_sub(2))
+            .map(|cell| cell.map(|c| c as f32) + Vec2::new(0.5, 0.5))
+            .collect();
+        waypoints.push(to);
+        Some(waypoints)
+    }
+
+    /// Returns true if the segment crosses static or dynamic blocking geometry.
+    fn is_segment_blocked(&self, a: Vec2<f32>, b: Vec2<f32>) -> bool {
+        self.linedefs
+            .iter()
+            .chain(self.dynamic_linedefs.iter())
+            .any(|linedef| self.segments_intersect(a, b, linedef.start, linedef.end))
+    }
+
+    /// Returns true if "b" can be seen from "a": the line between them is neither blocked by
+    /// walls, dynamic geometry (i.e. closed doors) nor by blocking tiles in between.
+    pub fn line_of_sight(&self, a: Vec2<f32>, b: Vec2<f32>) -> bool {
+        if self.is_segment_blocked(a, b) {
+            return false;
+        }
+
+        // Traverse the grid cells along the line
+        let mut cell = a.floor().as_::<i32>();
+        let end = b.floor().as_::<i32>();
+        let dir = b - a;
+        let step = dir.map(|d| if d > 0.0 { 1 } else { -1 });
+
+        let t_delta = dir.map(|d| {
+            if d != 0.0 {
+                1.0 / d.abs()
+            } else {
+                f32::INFINITY
+            }
+        });
+        let mut t_max = Vec2::new(
+            if dir.x != 0.0 {
+                ((cell.x + (step.x > 0) as i32) as f32 - a.x) / dir.x
+            } else {
+                f32::INFINITY
+            },
+            if dir.y != 0.0 {
+                ((cell.y + (step.y > 0) as i32) as f32 - a.y) / dir.y
+            } else {
+                f32::INFINITY
+            },
+        );
+
+        let steps = (end - cell).map(|d| d.abs()).sum();
+        for _ in 0..steps {
+            if t_max.x < t_max.y {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            }
+            if cell == end {
+                break;
+            }
+            if self.blocked_tiles.contains(&cell) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the first point on the segment from "a" to "b" which hits a wall, dynamic
+    /// geometry or a blocking tile, None if the segment is clear.
+    pub fn raycast(&self, a: Vec2<f32>, b: Vec2<f32>) -> Option<Vec2<f32>> {
+        let dir = b - a;
+        let mut hit: Option<f32> = self
+            .linedefs
+            .iter()
+            .chain(self.dynamic_linedefs.iter())
+            .filter_map(|linedef| Self::intersection_at(a, b, linedef.start, linedef.end))
+            .min_by(|x, y| x.total_cmp(y));
+
+        // Traverse the grid cells along the line, the hit is where a blocking cell is entered
+        let mut cell = a.floor().as_::<i32>();
+        let end = b.floor().as_::<i32>();
+        let step = dir.map(|d| if d > 0.0 { 1 } else { -1 });
+        let t_delta = dir.map(|d| {
+            if d != 0.0 {
+                1.0 / d.abs()
+            } else {
+                f32::INFINITY
+            }
+        });
+        let mut t_max = Vec2::new(
+            if dir.x != 0.0 {
+                ((cell.x + (step.x > 0) as i32) as f32 - a.x) / dir.x
+            } else {
+                f32::INFINITY
+            },
+            if dir.y != 0.0 {
+                ((cell.y + (step.y > 0) as i32) as f32 - a.y) / dir.y
+            } else {
+                f32::INFINITY
+            },
+        );
+
+        let steps = (end - cell).map(|d| d.abs()).sum();
+        for _ in 0..steps {
+            let t = t_max.x.min(t_max.y);
+            if hit.is_some_and(|hit| hit <= t) {
+                break;
+            }
+            if t_max.x < t_max.y {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            }
+            if self.blocked_tiles.contains(&cell) {
+                hit = Some(t);
+                break;
+            }
+        }
+        hit.map(|t| a + dir * t)
+    }
+
+    /// The position (0.0 - 1.0) on segment "a" where it crosses segment "b".
+    fn intersection_at(a1: Vec2<f32>, a2: Vec2<f32>, b1: Vec2<f32>, b2: Vec2<f32>) -> Option<f32> {
+        let d = (a2.x - a1.x) * (b2.y - b1.y) - (a2.y - a1.y) * (b2.x - b1.x);
+        if d == 0.0 {
+            return None;
+        }
+        let u = ((b1.x - a1.x) * (b2.y - b1.y) - (b1.y - a1.y) * (b2.x - b1.x)) / d;
+        let v = ((b1.x - a1.x) * (a2.y - a1.y) - (b1.y - a1.y) * (a2.x - a1.x)) / d;
+        ((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v)).then_some(u)
+    }
+
+    /// Returns the grid cells visible from the origin within the radius. Blocking tiles cast
+    /// shadows (recursive shadowcasting), cells behind walls are removed.
+    pub fn visible_cells(&self, origin: Vec2<f32>, radius: f32) -> FxHashSet<Vec2<i32>> {
+        // Octant transforms: xx, xy, yx, yy
+        const OCTANTS: [[i32; 4]; 8] = [
+            [1, 0, 0, 1],
+            [0, 1, 1, 0],
+            [0, -1, 1, 0],
+            [-1, 0, 0, 1],
+            [-1, 0, 0, -1],
+            [0, -1, -1, 0],
+            [0, 1, -1, 0],
+            [1, 0, 0, -1],
+        ];
+
+        let center = origin.floor().as_::<i32>();
+        let mut visible = FxHashSet::default();
+        visible.insert(center);
+
+        let range = radius.ceil() as i32;
+        for octant in OCTANTS {
+            self.cast_light(center, range, radius, 1, 1.0, 0.0, octant, &mut visible);
+        }
+
+        // Walls are not part of the grid
+        visible.retain(|cell| {
+            *cell == center
+                || !self.is_segment_blocked(origin, cell.map(|c| c as f32) + Vec2::new(0.5, 0.5))
+        });
+        visible
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn cast_light(
+        &self,
+        center: Vec2<i32>,
+        range: i32,
+        radius: f32,
+        row: i32,
+        mut start: f32,
+        end: f32,
+        octant: [i32; 4],
+        visible: &mut FxHashSet<Vec2<i32>>,
+    ) {
+        if start < end {
+            return;
+        }
+        let [xx, xy, yx, yy] = octant;
+        let mut next_start = start;
+
+        for distance in row..=range {
+            let mut blocked = false;
+            let dy = -distance;
+            for dx in -distance..=0 {
+                let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+                let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+
+                let cell = center + Vec2::new(dx * xx + dy * xy, dx * yx + dy * yy);
+                if ((dx * dx + dy * dy) as f32) <= radius * radius {
+                    visible.insert(cell);
+                }
+
+                let opaque = self.blocked_tiles.contains(&cell);
+                if blocked {
+                    if opaque {
+                        next_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if opaque && distance < range {
+                    blocked = true;
+                    self.cast_light(
+                        center,
+                        range,
+                        radius,
+                        distance + 1,
+                        start,
+                        left_slope,
+                        octant,
+                        visible,
+                    );
+                    next_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+/// Ray-casting point in polygon test.
+fn point_in_polygon(polygon: &[Vec2<f32>], point: Vec2<f32>) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        if (polygon[i].y > point.y) != (polygon[j].y > point.y)
+            && point.x
+                < (polygon[j].x - polygon[i].x) * (point.y - polygon[i].y)
+                    / (polygon[j].y - polygon[i].y)
+                    + polygon[i].x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_path_around_blocked_tiles() {
+        let mut mini = MapMini::new(Vec2::zero(), 1.0, vec![], vec![]);
+        for y in -1..=3 {
+            mini.blocked_tiles.insert(Vec2::new(2, y));
+        }
+
+        let from = Vec2::new(0.5, 0.5);
+        let to = Vec2::new(4.5, 0.5);
+        let path = mini
+            .find_path(from, to, 0.4, &FxHashSet::default(), 8)
+            .unwrap();
+
+        assert_eq!(*path.last().unwrap(), to);
+        for p in &path {
+            assert!(!mini.blocked_tiles.contains(&p.floor().as_::<i32>()));
+        }
+        assert!(path.iter().any(|p| p.y < -1.0 || p.y > 3.0));
+    }
+
+    #[test]
+    fn test_find_path_respects_walls_and_occupancy() {
+        let wall = CompiledLinedef::new(Vec2::new(2.0, -10.0), Vec2::new(2.0, 10.0), 0.0, 1.0);
+        let mini = MapMini::new(Vec2::zero(), 1.0, vec![wall], vec![]);
+        let from = Vec2::new(0.5, 0.5);
+
+        assert!(
+            mini.find_path(from, Vec2::new(4.5, 0.5), 0.4, &FxHashSet::default(), 4)
+                .is_none()
+        );
+
+        let mut occupied = FxHashSet::default();
+        occupied.insert(Vec2::new(1, 0));
+        let path = mini
+            .find_path(from, Vec2::new(1.5, 2.5), 0.4, &occupied, 4)
+            .unwrap();
+        assert!(
+            !path
+                .iter()
+                .any(|p| p.floor().as_::<i32>() == Vec2::new(1, 0))
+        );
+    }
+
+    #[test]
+    fn test_movement_zones() {
+        let mut mini = MapMini::new(Vec2::zero(), 1.0, vec![], vec![]);
+        let water = vec![
+            Vec2::new(2.0, -5.0),
+            Vec2::new(10.0, -5.0),
+            Vec2::new(10.0, 5.0),
+            Vec2::new(2.0, 5.0),
+        ];
+        mini.zones.push((MovementZone::Water, water));
+
+        let walker = MovementModes::default();
+        let fish = MovementModes::parse("swim");
+        let bird = MovementModes::parse("fly");
+        let land = Vec2::new(0.5, 0.5);
+        let lake = Vec2::new(4.5, 0.5);
+
+        assert_eq!(mini.mode_at(land, &walker), Some(MovementMode::Walk));
+        assert_eq!(mini.mode_at(lake, &walker), Some(MovementMode::Swim));
+        assert_eq!(mini.mode_at(land, &fish), None);
+        assert_eq!(mini.mode_at(lake, &bird), Some(MovementMode::Fly));
+
+        // The fish stops at the shore, the walker wades in
+        let (end, blocked) = mini.move_distance_as(lake, Vec2::new(-3.0, 0.0), 0.4, &fish);
+        assert!(blocked);
+        assert_eq!(mini.zone_at(end), Some(MovementZone::Water));
+        let (end, _) = mini.move_distance_as(land, Vec2::new(3.0, 0.0), 0.4, &walker);
+        assert_eq!(end, Vec2::new(3.5, 0.5));
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let wall = CompiledLinedef::new(Vec2::new(2.0, 0.0), Vec2::new(2.0, 1.0), 0.0, 1.0);
+        let mut mini = MapMini::new(Vec2::zero(), 1.0, vec![wall], vec![]);
+        mini.blocked_tiles.insert(Vec2::new(1, 3));
+
+        assert!(mini.line_of_sight(Vec2::new(0.5, 5.5), Vec2::new(4.5, 5.5)));
+        assert!(!mini.line_of_sight(Vec2::new(0.5, 0.5), Vec2::new(4.5, 0.5)));
+        assert!(!mini.line_of_sight(Vec2::new(0.5, 3.5), Vec2::new(3.5, 3.5)));
+        // The blocking tile itself can be seen
+        assert!(mini.line_of_sight(Vec2::new(0.5, 3.5), Vec2::new(1.5, 3.5)));
+    }
+
+    #[test]
+    fn test_visible_cells_shadow() {
+        let mut mini = MapMini::new(Vec2::zero(), 1.0, vec![], vec![]);
+        mini.blocked_tiles.insert(Vec2::new(2, 0));
+
+        let visible = mini.visible_cells(Vec2::new(0.5, 0.5), 5.0);
+        assert!(visible.contains(&Vec2::new(0, 0)));
+        assert!(visible.contains(&Vec2::new(2, 0)));
+        assert!(visible.contains(&Vec2::new(0, 4)));
+        assert!(!visible.contains(&Vec2::new(4, 0)));
+        assert!(!visible.contains(&Vec2::new(0, 6)));
+    }
+
+    #[test]
+    fn test_raycast() {
+        let wall = CompiledLinedef::new(Vec2::new(2.0, 0.0), Vec2::new(2.0, 1.0), 0.0, 1.0);
+        let mut mini = MapMini::new(Vec2::zero(), 1.0, vec![wall], vec![]);
+        mini.blocked_tiles.insert(Vec2::new(1, 3));
+
+        assert_eq!(mini.raycast(Vec2::new(0.5, 5.5), Vec2::new(4.5, 5.5)), None);
+        assert_eq!(
+            mini.raycast(Vec2::new(0.5, 0.5), Vec2::new(4.5, 0.5)),
+            Some(Vec2::new(2.0, 0.5))
+        );
+        // Blocking tiles are hit where the ray enters them
+        let hit = mini
+            .raycast(Vec2::new(3.5, 3.5), Vec2::new(0.5, 3.5))
+            .unwrap();
+        assert!(hit.distance(Vec2::new(2.0, 3.5)) < 1e-4);
+    }
 }
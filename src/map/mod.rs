@@ -4,7 +4,9 @@ pub mod light;
 pub mod linedef;
 pub mod meta;
 pub mod mini;
+pub mod occlusion;
 pub mod particle;
+pub mod patch;
 pub mod pixelsource;
 pub mod sector;
 pub mod softrig;
@@ -13,8 +15,8 @@ pub mod tile;
 pub mod vertex;
 
 use crate::{
-    BBox, Keyform, MapMini, PixelSource, ShapeFXGraph, SoftRig, SoftRigAnimator, Surface, Terrain,
-    Value, ValueContainer,
+    BBox, Keyform, MapMini, MovementZone, PixelSource, ShapeFXGraph, SoftRig, SoftRigAnimator,
+    Surface, Terrain, Value, ValueContainer,
 };
 use codegridfx::Module;
 use indexmap::IndexMap;
@@ -237,16 +239,67 @@ impl Map {
         self.surfaces = surfaces;
     }
 
+    /// Updates the geometry of the surfaces of the given sectors.
+    pub fn update_sector_surfaces(&mut self, sectors: &FxHashSet<u32>) {
+        let mut surfaces = std::mem::take(&mut self.surfaces);
+        for surface in surfaces.values_mut() {
+            if sectors.contains(&surface.sector_id) {
+                surface.calculate_geometry(self);
+            }
+        }
+        self.surfaces = surfaces;
+    }
+
+    /// Moves the geometry, the lights and the soft rigs of the map by the offset and updates
+    /// the surfaces. The terrain is not moved.
+    pub fn translate(&mut self, offset: Vec2<f32>) {
+        for vertex in &mut self.vertices {
+            vertex.x += offset.x;
+            vertex.y += offset.y;
+        }
+        for light in &mut self.lights {
+            let position = light.position();
+            light.set_position(position + Vec3::new(offset.x, 0.0, offset.y));
+        }
+        for rig in self.softrigs.values_mut() {
+            for keyform in &mut rig.keyforms {
+                for (_, position) in &mut keyform.vertex_positions {
+                    *position += offset;
+                }
+            }
+        }
+        self.update_surfaces();
+    }
+
     /// Return the Map as MapMini
     pub fn as_mini(&self, blocking_tiles: &FxHashSet<Uuid>) -> MapMini {
         let mut linedefs: Vec<CompiledLinedef> = vec![];
         let mut occluded_sectors: Vec<(BBox, f32)> = vec![];
 
         let mut blocked_tiles = FxHashSet::default();
+        let mut zones = vec![];
 
         for sector in self.sectors.iter() {
             let mut add_it = false;
 
+            // Water and ladder sectors change the movement mode of entities
+            let zone = if sector.properties.get_bool_default("water", false) {
+                Some(MovementZone::Water)
+            } else if sector.properties.get_bool_default("ladder", false) {
+                Some(MovementZone::Ladder)
+            } else {
+                None
+            };
+            if let Some(zone) = zone {
+                let polygon: Vec<Vec2<f32>> = sector
+                    .linedefs
+                    .iter()
+                    .filter_map(|id| self.find_linedef(*id))
+                    .filter_map(|linedef| self.get_vertex(linedef.start_vertex))
+                    .collect();
+                zones.push((zone, polygon));
+            }
+
             // We collect occluded sectors
             let occlusion = sector.properties.get_float_default("occlusion", 1.0);
             if occlusion < 1.0 {
@@ -344,9 +397,130 @@ impl Map {
 
         let mut mini = MapMini::new(self.offset, self.grid_size, linedefs, occluded_sectors);
         mini.blocked_tiles = blocked_tiles;
+        mini.zones = zones;
         mini
     }
 
+    /// The area covered by geometry and lights which differ between this map and a newer
+    /// version of it, i.e. after recompiling a MapScript. Elements are matched by id, lights by
+    /// index. If map wide properties differ the bounds of both maps are returned.
+    pub fn changed_bbox(&self, other: &Map) -> Option<BBox> {
+        let mut changed: Option<BBox> = None;
+        let mut add = |bbox: BBox| match &mut changed {
+            Some(changed) => changed.expand_bbox(bbox),
+            None => changed = Some(bbox),
+        };
+        let point = |x: f32, y: f32| BBox::new(Vec2::new(x, y), Vec2::new(x, y));
+
+        if self.properties != other.properties || self.sky_texture != other.sky_texture {
+            let mut bbox = self.bbox();
+            bbox.expand_bbox(other.bbox());
+            return Some(bbox);
+        }
+
+        for vertex in &self.vertices {
+            if other.find_vertex(vertex.id) != Some(vertex) {
+                add(point(vertex.x, vertex.y));
+                if let Some(o) = other.find_vertex(vertex.id) {
+                    add(point(o.x, o.y));
+                }
+            }
+        }
+        for vertex in &other.vertices {
+            if self.find_vertex(vertex.id).is_none() {
+                add(point(vertex.x, vertex.y));
+            }
+        }
+
+        let linedef_bbox = |map: &Map, linedef: &Linedef| {
+            let start = map.find_vertex(linedef.start_vertex)?;
+            let end = map.find_vertex(linedef.end_vertex)?;
+            Some(BBox::new(
+                Vec2::new(start.x.min(end.x), start.y.min(end.y)),
+                Vec2::new(start.x.max(end.x), start.y.max(end.y)),
+            ))
+        };
+        for linedef in &self.linedefs {
+            let o = other.find_linedef(linedef.id);
+            let same = o.is_some_and(|o| {
+                o.start_vertex == linedef.start_vertex
+                    && o.end_vertex == linedef.end_vertex
+                    && o.properties == linedef.properties
+            });
+            if !same {
+                if let Some(bbox) = linedef_bbox(self, linedef) {
+                    add(bbox);
+                }
+                if let Some(bbox) = o.and_then(|o| linedef_bbox(other, o)) {
+                    add(bbox);
+                }
+            }
+        }
+        for linedef in &other.linedefs {
+            if self.find_linedef(linedef.id).is_none() {
+                if let Some(bbox) = linedef_bbox(other, linedef) {
+                    add(bbox);
+                }
+            }
+        }
+
+        for sector in &self.sectors {
+            let o = other.find_sector(sector.id);
+            let same = o.is_some_and(|o| {
+                o.linedefs == sector.linedefs
+                    && o.properties == sector.properties
+                    && o.shader == sector.shader
+            });
+            if !same {
+                add(sector.bounding_box(self));
+                if let Some(o) = o {
+                    add(o.bounding_box(other));
+                }
+            }
+        }
+        for sector in &other.sectors {
+            if self.find_sector(sector.id).is_none() {
+                add(sector.bounding_box(other));
+            }
+        }
+
+        let count = self.lights.len().max(other.lights.len());
+        for index in 0..count {
+            let (a, b) = (self.lights.get(index), other.lights.get(index));
+            if a != b {
+                for light in [a, b].into_iter().flatten() {
+                    let p = light.position_2d();
+                    add(point(p.x, p.y));
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Keep the selected elements of the other map which still exist in this map.
+    pub fn copy_selection(&mut self, other: &Map) {
+        self.selected_vertices = other
+            .selected_vertices
+            .iter()
+            .copied()
+            .filter(|id| self.find_vertex(*id).is_some())
+            .collect();
+        self.selected_linedefs = other
+            .selected_linedefs
+            .iter()
+            .copied()
+            .filter(|id| self.find_linedef(*id).is_some())
+            .collect();
+        self.selected_sectors = other
+            .selected_sectors
+            .iter()
+            .copied()
+            .filter(|id| self.find_sector(*id).is_some())
+            .collect();
+        self.selected_entity_item = other.selected_entity_item;
+    }
+
     /// Generate a bounding box for all vertices in the map
     pub fn bbox(&self) -> BBox {
         // Find min and max coordinates among all vertices
@@ -412,6 +586,38 @@ impl Map {
         Some(Vec4::new(min_x, min_y, width, height))
     }
 
+    /// The vertices animated by the soft rigs of the map.
+    pub fn rigged_vertices(&self) -> FxHashSet<u32> {
+        self.softrigs
+            .values()
+            .flat_map(|rig| &rig.keyforms)
+            .flat_map(|keyform| keyform.vertex_positions.iter().map(|(id, _)| *id))
+            .collect()
+    }
+
+    /// True if the sector is animated: movers with the `dynamic` property and sectors using
+    /// rigged vertices (see `rigged_vertices`).
+    pub fn is_dynamic_sector(&self, sector: &Sector, rigged: &FxHashSet<u32>) -> bool {
+        sector.properties.get_bool_default("dynamic", false)
+            || (!rigged.is_empty()
+                && sector.linedefs.iter().any(|id| {
+                    self.find_linedef(*id).is_some_and(|linedef| {
+                        rigged.contains(&linedef.start_vertex)
+                            || rigged.contains(&linedef.end_vertex)
+                    })
+                }))
+    }
+
+    /// The ids of the animated sectors, see `is_dynamic_sector`.
+    pub fn dynamic_sectors(&self) -> FxHashSet<u32> {
+        let rigged = self.rigged_vertices();
+        self.sectors
+            .iter()
+            .filter(|sector| self.is_dynamic_sector(sector, &rigged))
+            .map(|sector| sector.id)
+            .collect()
+    }
+
     /// Tick the soft animator.
     pub fn tick(&mut self, delta_time: f32) {
         if let Some(anim) = &mut self.soft_animator {
@@ -0,0 +1,142 @@
+use crate::{Map, SceneManager};
+use std::collections::VecDeque;
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// Sector based occlusion culling of the scene chunks. Sectors see each other through the
+/// edges they share (the portals), unless one of the linedefs of the edge has the `opaque`
+/// property, i.e. a closed door. From inside of a sector only the chunks overlapping the
+/// sectors within `depth` portals are visible. Outside of all sectors nothing is culled.
+#[derive(Debug, Clone, Default)]
+pub struct SectorOcclusion {
+    pub depth: usize,
+    /// The neighbors of every sector through its portals.
+    portals: FxHashMap<u32, Vec<u32>>,
+    /// The chunks every sector overlaps.
+    sector_chunks: FxHashMap<u32, FxHashSet<(i32, i32)>>,
+    /// The visible chunks from within every sector, computed on demand.
+    cache: FxHashMap<u32, FxHashSet<(i32, i32)>>,
+}
+
+impl SectorOcclusion {
+    pub fn new(map: &Map, chunk_size: i32, depth: usize) -> Self {
+        // Sectors share an edge if their linedefs connect the same vertices, in any direction
+        let mut edges: FxHashMap<(u32, u32), (Vec<u32>, bool)> = FxHashMap::default();
+        let mut sector_chunks = FxHashMap::default();
+
+        for sector in &map.sectors {
+            if sector.layer.is_some() {
+                continue;
+            }
+            for linedef in sector
+                .linedefs
+                .iter()
+                .filter_map(|id| map.find_linedef(*id))
+            {
+                let key = (
+                    linedef.start_vertex.min(linedef.end_vertex),
+                    linedef.start_vertex.max(linedef.end_vertex),
+                );
+                let edge = edges.entry(key).or_default();
+                if !edge.0.contains(&sector.id) {
+                    edge.0.push(sector.id);
+                }
+                edge.1 |= linedef.properties.get_bool_default("opaque", false);
+            }
+
+            // Walls extend a bit beyond the sector outline
+            let mut bbox = sector.bounding_box(map);
+            bbox.expand(Vec2::broadcast(1.0));
+            sector_chunks.insert(
+                sector.id,
+                SceneManager::generate_chunk_coords(&bbox, chunk_size),
+            );
+        }
+
+        let mut portals: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+        for (sectors, opaque) in edges.values() {
+            if *opaque {
+                continue;
+            }
+            for a in sectors {
+                for b in sectors.iter().filter(|b| *b != a) {
+                    let neighbors = portals.entry(*a).or_default();
+                    if !neighbors.contains(b) {
+                        neighbors.push(*b);
+                    }
+                }
+            }
+        }
+
+        Self {
+            depth,
+            portals,
+            sector_chunks,
+            cache: FxHashMap::default(),
+        }
+    }
+
+    /// The sector the position is in, occlusion only applies inside of sectors.
+    pub fn sector_at(&self, map: &Map, position: Vec2<f32>) -> Option<u32> {
+        map.find_sector_at(position)
+            .map(|sector| sector.id)
+            .filter(|id| self.sector_chunks.contains_key(id))
+    }
+
+    /// The chunks visible from within the sector.
+    pub fn visible_chunks(&mut self, sector: u32) -> &FxHashSet<(i32, i32)> {
+        self.cache.entry(sector).or_insert_with(|| {
+            reachable_sectors(&self.portals, sector, self.depth)
+                .iter()
+                .filter_map(|id| self.sector_chunks.get(id))
+                .flatten()
+                .copied()
+                .collect()
+        })
+    }
+}
+
+/// The sectors within `depth` portals of the start sector, including it.
+fn reachable_sectors(
+    portals: &FxHashMap<u32, Vec<u32>>,
+    start: u32,
+    depth: usize,
+) -> FxHashSet<u32> {
+    let mut reached = FxHashSet::default();
+    reached.insert(start);
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((sector, distance)) = queue.pop_front() {
+        if distance >= depth {
+            continue;
+        }
+        for neighbor in portals.get(&sector).into_iter().flatten() {
+            if reached.insert(*neighbor) {
+                queue.push_back((*neighbor, distance + 1));
+            }
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reachable_sectors_stop_at_depth() {
+        // A corridor of sectors 0 - 1 - 2 - 3 and a side room 4 at sector 1
+        let mut portals: FxHashMap<u32, Vec<u32>> = FxHashMap::default();
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (1, 4)] {
+            portals.entry(a).or_default().push(b);
+            portals.entry(b).or_default().push(a);
+        }
+
+        let reached = reachable_sectors(&portals, 0, 2);
+        let mut reached: Vec<u32> = reached.into_iter().collect();
+        reached.sort();
+        assert_eq!(reached, vec![0, 1, 2, 4]);
+
+        assert_eq!(reachable_sectors(&portals, 3, 0).len(), 1);
+        assert_eq!(reachable_sectors(&portals, 3, 10).len(), 5);
+    }
+}
@@ -26,6 +26,10 @@ pub struct ParticleEmitter {
     pub radius_range: (f32, f32),   // Radius size range
     pub speed_range: (f32, f32),    // Velocity magnitude range
 
+    /// Blend the particles additively instead of by their alpha, i.e. for fire and sparks.
+    #[serde(default)]
+    pub additive: bool,
+
     pub particles: Vec<Particle>, // Active particles
 }
 
@@ -46,6 +50,8 @@ impl ParticleEmitter {
             radius_range: (0.05, 0.15),
             speed_range: (0.5, 1.5),
 
+            additive: false,
+
             particles: vec![],
         }
     }
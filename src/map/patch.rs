@@ -0,0 +1,209 @@
+use crate::{BBox, Linedef, Map, Sector, Surface, Vertex};
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// An edit of a map: the vertices, linedefs, sectors and surfaces which were added or changed
+/// and the ids of the removed elements. Editors send patches to the scene manager (see
+/// `SceneManager::map_changed`) instead of the whole map, so that only the chunks covering
+/// the edit are rebuilt.
+#[derive(Debug, Clone, Default)]
+pub struct MapPatch {
+    pub vertices: Vec<Vertex>,
+    pub linedefs: Vec<Linedef>,
+    pub sectors: Vec<Sector>,
+    pub surfaces: Vec<Surface>,
+
+    pub removed_vertices: Vec<u32>,
+    pub removed_linedefs: Vec<u32>,
+    /// The surfaces of removed sectors are removed too.
+    pub removed_sectors: Vec<u32>,
+}
+
+impl MapPatch {
+    /// A patch with the current state of the given elements of the edited map, elements which
+    /// do not exist anymore are removed. The surfaces of the sectors are included.
+    pub fn from_map(map: &Map, vertices: &[u32], linedefs: &[u32], sectors: &[u32]) -> Self {
+        let mut patch = Self::default();
+        for id in vertices {
+            match map.find_vertex(*id) {
+                Some(vertex) => patch.vertices.push(vertex.clone()),
+                None => patch.removed_vertices.push(*id),
+            }
+        }
+        for id in linedefs {
+            match map.find_linedef(*id) {
+                Some(linedef) => patch.linedefs.push(linedef.clone()),
+                None => patch.removed_linedefs.push(*id),
+            }
+        }
+        for id in sectors {
+            match map.find_sector(*id) {
+                Some(sector) => {
+                    patch.sectors.push(sector.clone());
+                    if let Some(surface) = map.get_surface_for_sector_id(*id) {
+                        patch.surfaces.push(surface.clone());
+                    }
+                }
+                None => patch.removed_sectors.push(*id),
+            }
+        }
+        patch
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+            && self.linedefs.is_empty()
+            && self.sectors.is_empty()
+            && self.surfaces.is_empty()
+            && self.removed_vertices.is_empty()
+            && self.removed_linedefs.is_empty()
+            && self.removed_sectors.is_empty()
+    }
+
+    /// The sectors of the map touched by the patch: the patched sectors and the sectors using
+    /// patched linedefs or vertices.
+    pub fn affected_sectors(&self, map: &Map) -> FxHashSet<u32> {
+        let vertices: FxHashSet<u32> = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.id)
+            .chain(self.removed_vertices.iter().copied())
+            .collect();
+        let linedefs: FxHashSet<u32> = map
+            .linedefs
+            .iter()
+            .filter(|linedef| {
+                vertices.contains(&linedef.start_vertex) || vertices.contains(&linedef.end_vertex)
+            })
+            .map(|linedef| linedef.id)
+            .chain(self.linedefs.iter().map(|linedef| linedef.id))
+            .chain(self.removed_linedefs.iter().copied())
+            .collect();
+
+        map.sectors
+            .iter()
+            .filter(|sector| sector.linedefs.iter().any(|id| linedefs.contains(id)))
+            .map(|sector| sector.id)
+            .chain(self.sectors.iter().map(|sector| sector.id))
+            .chain(self.removed_sectors.iter().copied())
+            .chain(self.surfaces.iter().map(|surface| surface.sector_id))
+            .collect()
+    }
+
+    /// The area of the map covered by the patched elements and their sectors. Before the
+    /// patch is applied this is where the elements were, afterwards where they are now.
+    pub fn bbox(&self, map: &Map) -> Option<BBox> {
+        let mut bbox: Option<BBox> = None;
+        let mut add = |other: BBox| match &mut bbox {
+            Some(bbox) => bbox.expand_bbox(other),
+            None => bbox = Some(other),
+        };
+        let point = |p: Vec2<f32>| BBox::new(p, p);
+
+        let vertex_ids = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.id)
+            .chain(self.removed_vertices.iter().copied());
+        for p in vertex_ids.filter_map(|id| map.get_vertex(id)) {
+            add(point(p));
+        }
+
+        let linedef_ids = self
+            .linedefs
+            .iter()
+            .map(|linedef| linedef.id)
+            .chain(self.removed_linedefs.iter().copied());
+        for linedef in linedef_ids.filter_map(|id| map.find_linedef(id)) {
+            for p in [linedef.start_vertex, linedef.end_vertex]
+                .into_iter()
+                .filter_map(|id| map.get_vertex(id))
+            {
+                add(point(p));
+            }
+        }
+
+        // Sectors are built into the chunk of their center, cover the whole sector
+        for sector in self
+            .affected_sectors(map)
+            .into_iter()
+            .filter_map(|id| map.find_sector(id))
+        {
+            add(sector.bounding_box(map));
+        }
+
+        bbox
+    }
+
+    /// Apply the patch to the map and update the geometry of the surfaces of the affected
+    /// sectors.
+    pub fn apply(&self, map: &mut Map) {
+        map.vertices
+            .retain(|vertex| !self.removed_vertices.contains(&vertex.id));
+        map.linedefs
+            .retain(|linedef| !self.removed_linedefs.contains(&linedef.id));
+        map.sectors
+            .retain(|sector| !self.removed_sectors.contains(&sector.id));
+        map.surfaces
+            .retain(|_, surface| !self.removed_sectors.contains(&surface.sector_id));
+
+        for vertex in &self.vertices {
+            match map.find_vertex_mut(vertex.id) {
+                Some(v) => *v = vertex.clone(),
+                None => map.vertices.push(vertex.clone()),
+            }
+        }
+        for linedef in &self.linedefs {
+            match map.find_linedef_mut(linedef.id) {
+                Some(l) => *l = linedef.clone(),
+                None => map.linedefs.push(linedef.clone()),
+            }
+        }
+        for sector in &self.sectors {
+            match map.find_sector_mut(sector.id) {
+                Some(s) => *s = sector.clone(),
+                None => map.sectors.push(sector.clone()),
+            }
+        }
+        for surface in &self.surfaces {
+            map.surfaces.insert(surface.id, surface.clone());
+        }
+
+        // Moved vertices change the geometry of the surfaces using them
+        let affected = self.affected_sectors(map);
+        map.update_sector_surfaces(&affected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_covers_old_and_new_positions() {
+        let mut map = Map::new();
+        let a = map.add_vertex_at(0.0, 0.0);
+        let b = map.add_vertex_at(4.0, 0.0);
+        let c = map.add_vertex_at(40.0, 40.0);
+        map.create_linedef(a, b);
+
+        let mut edited = map.clone();
+        edited.update_vertex(b, Vec2::new(20.0, 0.0));
+        edited.vertices.retain(|vertex| vertex.id != c);
+
+        let patch = MapPatch::from_map(&edited, &[b, c], &[], &[]);
+        assert_eq!(patch.vertices.len(), 1);
+        assert_eq!(patch.removed_vertices, vec![c]);
+
+        let before = patch.bbox(&map).unwrap();
+        assert_eq!(before.max, Vec2::new(40.0, 40.0));
+
+        patch.apply(&mut map);
+        assert!(map.find_vertex(c).is_none());
+        assert_eq!(map.get_vertex(b), Some(Vec2::new(20.0, 0.0)));
+
+        let after = patch.bbox(&map).unwrap();
+        assert_eq!(after.min, Vec2::new(20.0, 0.0));
+        assert_eq!(after.max, Vec2::new(20.0, 0.0));
+    }
+}
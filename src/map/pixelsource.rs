@@ -34,6 +34,8 @@ pub enum PixelSource {
     DynamicTileIndex(u16),
     Pixel(Pixel),
     Terrain,
+    /// A rusteria shader material registered in the assets.
+    ShaderId(Uuid),
 }
 
 use PixelSource::*;
@@ -50,6 +52,7 @@ impl PixelSource {
         match self {
             TileId(id) => assets.tiles.get(id).cloned(),
             MaterialId(id) => assets.materials.get(id).cloned(),
+            ShaderId(_) => self.tile_from_tile_list(assets),
             Color(color) => {
                 let apply_to: NoiseTarget = values.get_int_default("noise_target", 0).into();
                 let noise_intensity = values.get_float_default("noise_intensity", 0.0);
@@ -122,7 +125,7 @@ impl PixelSource {
     /// Generate a tile from the tile_list indices
     pub fn tile_from_tile_list(&self, assets: &Assets) -> Option<Tile> {
         match self {
-            TileId(id) | MaterialId(id) => {
+            TileId(id) | MaterialId(id) | ShaderId(id) => {
                 if let Some(index) = assets.tile_indices.get(id) {
                     assets.tile_list.get(*index as usize).cloned()
                 } else {
@@ -1,5 +1,6 @@
 use crate::value::{Value, ValueContainer};
 use crate::value_toml::ValueTomlLoader;
+use crate::{Map, WeatherState};
 use rustc_hash::FxHashMap;
 use scenevm::{Atom, SceneVM};
 use vek::Vec4;
@@ -66,6 +67,9 @@ pub struct RenderSettings {
 
     /// Daylight simulation settings
     pub simulation: DaylightSimulation,
+
+    /// The weather of the region, tints the sky and sun and adds fog
+    pub weather: WeatherState,
 }
 
 /// Daylight simulation settings for time-of-day rendering
@@ -197,6 +201,7 @@ impl Default for RenderSettings {
             frame_time_ms: 1000.0 / 30.0,
             transitions: FxHashMap::default(),
             simulation: DaylightSimulation::default(),
+            weather: WeatherState::default(),
         }
     }
 }
@@ -218,6 +223,11 @@ impl RenderSettings {
         Ok(())
     }
 
+    /// Apply the render values stored in the properties of a map, i.e. the sky color set by a MapScript.
+    pub fn read_map(&mut self, map: &Map) -> Result<(), Box<dyn std::error::Error>> {
+        self.apply_render_values(&map.properties)
+    }
+
     /// Schedule a timed render setting change.
     /// `time` is the duration in seconds over which the setting interpolates from its current value.
     pub fn set(
@@ -354,11 +364,13 @@ impl RenderSettings {
     pub fn apply_2d(&mut self, vm: &mut SceneVM) {
         self.update_transitions();
 
+        let weathered = self.weathered();
+
         // gp1: Sky color (RGB) + unused w
         vm.execute(Atom::SetGP1(Vec4::new(
-            self.sky_color[0],
-            self.sky_color[1],
-            self.sky_color[2],
+            weathered.sky_color[0],
+            weathered.sky_color[1],
+            weathered.sky_color[2],
             0.0,
         )));
     }
@@ -369,21 +381,22 @@ impl RenderSettings {
 
         // Convert sRGB colors to linear space (gamma 2.2) on CPU instead of per-pixel in shader
         let to_linear = |c: f32| c.powf(2.2);
+        let weathered = self.weathered();
 
         // gp0: Sky color (RGB, linear) + unused w
         vm.execute(Atom::SetGP0(Vec4::new(
-            to_linear(self.sky_color[0]),
-            to_linear(self.sky_color[1]),
-            to_linear(self.sky_color[2]),
+            to_linear(weathered.sky_color[0]),
+            to_linear(weathered.sky_color[1]),
+            to_linear(weathered.sky_color[2]),
             0.0,
         )));
 
         // gp1: Sun color (RGB, linear) + sun intensity (w)
         vm.execute(Atom::SetGP1(Vec4::new(
-            to_linear(self.sun_color[0]),
-            to_linear(self.sun_color[1]),
-            to_linear(self.sun_color[2]),
-            self.sun_intensity,
+            to_linear(weathered.sun_color[0]),
+            to_linear(weathered.sun_color[1]),
+            to_linear(weathered.sun_color[2]),
+            weathered.sun_intensity,
         )));
 
         // gp2: Sun direction (XYZ, normalized) + sun enabled (w)
@@ -408,7 +421,7 @@ impl RenderSettings {
             to_linear(self.fog_color[0]),
             to_linear(self.fog_color[1]),
             to_linear(self.fog_color[2]),
-            self.fog_density,
+            weathered.fog_density,
         )));
 
         // gp5: Rendering quality settings
@@ -430,6 +443,17 @@ impl RenderSettings {
         )));
     }
 
+    /// The sky, sun and fog values with the weather applied.
+    fn weathered(&self) -> RenderSettings {
+        let tint = self.weather.tint();
+        let mut settings = self.clone();
+        settings.sky_color = mul_color(self.sky_color, tint);
+        settings.sun_color = mul_color(self.sun_color, tint);
+        settings.sun_intensity = self.sun_intensity * self.weather.sun_factor();
+        settings.fog_density = self.fog_density.max(self.weather.fog_density());
+        settings
+    }
+
     fn update_transitions(&mut self) {
         if self.transitions.is_empty() {
             return;
@@ -766,6 +790,11 @@ fn lerp_color(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
     ]
 }
 
+/// Component-wise multiplication of two RGB colors
+fn mul_color(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2]]
+}
+
 /// Parse a hex color string like "#RRGGBB" or "RRGGBB" into RGB floats (0.0-1.0)
 fn parse_hex_color(hex: &str) -> Result<[f32; 3], Box<dyn std::error::Error>> {
     let hex = hex.trim_start_matches('#');
@@ -788,6 +817,7 @@ fn parse_hex_color(hex: &str) -> Result<[f32; 3], Box<dyn std::error::Error>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::WeatherKind;
 
     #[test]
     fn load_example_toml() {
@@ -821,4 +851,26 @@ mod tests {
         assert!((settings.sun_intensity - 3.0).abs() < f32::EPSILON);
         assert!(settings.transitions.is_empty());
     }
+
+    #[test]
+    fn weather_darkens_and_fogs() {
+        let mut settings = RenderSettings::default();
+        let clear = settings.weathered();
+        assert_eq!(clear.sky_color, settings.sky_color);
+        assert_eq!(clear.fog_density, settings.fog_density);
+
+        settings.weather = WeatherState {
+            kind: WeatherKind::Fog,
+            previous: WeatherKind::Clear,
+            blend: 0.5,
+        };
+        let half = settings.weathered();
+        settings.weather.blend = 1.0;
+        let fog = settings.weathered();
+
+        assert!(half.sun_intensity < settings.sun_intensity);
+        assert!(fog.sun_intensity < half.sun_intensity);
+        assert!(fog.fog_density > half.fog_density);
+        assert!(half.fog_density > 0.0);
+    }
 }
@@ -0,0 +1,440 @@
+use crate::{Assets, Batch3D, PixelSource, PrimitiveMode, Scene, Texture};
+use serde_json::json;
+use std::fmt::Write as _;
+use std::path::Path;
+use theframework::prelude::*;
+use vek::{Vec3, Vec4};
+
+/// The file formats a scene can be exported to, see `Scene::export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneExportFormat {
+    /// A Wavefront `.obj` with a `.mtl` file and the textures as PNG files next to it.
+    Obj,
+    /// A glTF 2.0 `.gltf` with a `.bin` buffer and the textures as PNG files next to it.
+    Gltf,
+}
+
+/// Where the pixels of an exported batch come from, batches of the same source share a
+/// material.
+#[derive(Debug, Clone, PartialEq)]
+enum MaterialKey {
+    StaticTile(u16),
+    DynamicTile(u16),
+    Tile(Uuid),
+    Terrain((i32, i32)),
+    Color([u8; 4]),
+}
+
+struct ExportMaterial {
+    key: MaterialKey,
+    name: String,
+    color: [u8; 4],
+    texture: Option<Texture>,
+}
+
+/// A batch in world space.
+struct ExportMesh {
+    name: String,
+    material: usize,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<(usize, usize, usize)>,
+}
+
+impl Scene {
+    /// Write all 3D batches of the scene (the static, dynamic and overlay batches and the
+    /// geometry and terrain of the built chunks) with their materials and textures to the
+    /// path, so that the scene can be inspected or rendered in other tools. The textures of
+    /// tiles are taken from the assets at the current animation frame. Batches which are not
+    /// drawn as triangles are skipped.
+    pub fn export(
+        &self,
+        path: impl AsRef<Path>,
+        format: SceneExportFormat,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let (meshes, materials) = self.export_meshes(assets);
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "scene".into());
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+
+        // The textures are written next to the scene, named by the scene and their material
+        let mut texture_files = vec![None; materials.len()];
+        for (index, material) in materials.iter().enumerate() {
+            if let Some(texture) = &material.texture {
+                let file = format!("{}_{}.png", stem, material.name);
+                std::fs::write(dir.join(&file), texture.to_png()?)
+                    .map_err(|err| err.to_string())?;
+                texture_files[index] = Some(file);
+            }
+        }
+
+        match format {
+            SceneExportFormat::Obj => {
+                write_obj(path, dir, &stem, &meshes, &materials, &texture_files)
+            }
+            SceneExportFormat::Gltf => {
+                write_gltf(path, dir, &stem, &meshes, &materials, &texture_files)
+            }
+        }
+    }
+
+    /// Collect the 3D batches in world space with their materials.
+    fn export_meshes(&self, assets: &Assets) -> (Vec<ExportMesh>, Vec<ExportMaterial>) {
+        let mut meshes = Vec::new();
+        let mut materials: Vec<ExportMaterial> = Vec::new();
+
+        let mut add = |name: String, batch: &Batch3D, key: MaterialKey| {
+            if batch.mode != PrimitiveMode::Triangles || batch.indices.is_empty() {
+                return;
+            }
+            let material = match materials.iter().position(|m| m.key == key) {
+                Some(index) => index,
+                None => {
+                    materials.push(self.export_material(key, materials.len(), assets));
+                    materials.len() - 1
+                }
+            };
+            meshes.push(export_mesh(name, batch, material));
+        };
+
+        let lists = [
+            ("static", &self.d3_static),
+            ("dynamic", &self.d3_dynamic),
+            ("overlay", &self.d3_overlay),
+        ];
+        for (list, batches) in lists {
+            for (index, batch) in batches.iter().enumerate() {
+                add(
+                    format!("{}_{}", list, index),
+                    batch,
+                    source_key(&batch.source),
+                );
+            }
+        }
+
+        let mut coords: Vec<&(i32, i32)> = self.chunks.keys().collect();
+        coords.sort();
+        for coord in coords {
+            let chunk = &self.chunks[coord];
+            let prefix = format!("chunk_{}_{}", coord.0, coord.1);
+            let batches = chunk.batches3d.iter().chain(&chunk.batches3d_opacity);
+            for (index, batch) in batches.enumerate() {
+                add(
+                    format!("{}_{}", prefix, index),
+                    batch,
+                    source_key(&batch.source),
+                );
+            }
+            if let Some(batch) = &chunk.terrain_batch3d {
+                let key = if chunk.terrain_texture.is_some() {
+                    MaterialKey::Terrain(*coord)
+                } else {
+                    source_key(&batch.source)
+                };
+                add(format!("{}_terrain", prefix), batch, key);
+            }
+        }
+
+        (meshes, materials)
+    }
+
+    /// The material of the key, with the texture it samples.
+    fn export_material(&self, key: MaterialKey, index: usize, assets: &Assets) -> ExportMaterial {
+        let frame = |textures: &[Texture]| {
+            (!textures.is_empty()).then(|| textures[self.animation_frame % textures.len()].clone())
+        };
+        let texture = match &key {
+            MaterialKey::StaticTile(index) => assets
+                .tile_list
+                .get(*index as usize)
+                .and_then(|tile| frame(&tile.textures)),
+            MaterialKey::DynamicTile(index) => self
+                .dynamic_textures
+                .get(*index as usize)
+                .and_then(|tile| frame(&tile.textures)),
+            MaterialKey::Tile(id) => assets
+                .tiles
+                .get(id)
+                .or_else(|| assets.materials.get(id))
+                .and_then(|tile| frame(&tile.textures)),
+            MaterialKey::Terrain(coord) => self
+                .chunks
+                .get(coord)
+                .and_then(|chunk| chunk.terrain_texture.clone()),
+            MaterialKey::Color(_) => None,
+        };
+        let color = match key {
+            MaterialKey::Color(color) => color,
+            _ => [255, 255, 255, 255],
+        };
+        ExportMaterial {
+            key,
+            name: format!("material_{}", index),
+            color,
+            texture,
+        }
+    }
+}
+
+/// The material key of the pixel source, sources without a texture are gray.
+fn source_key(source: &PixelSource) -> MaterialKey {
+    match source {
+        PixelSource::StaticTileIndex(index) => MaterialKey::StaticTile(*index),
+        PixelSource::DynamicTileIndex(index) => MaterialKey::DynamicTile(*index),
+        PixelSource::TileId(id) | PixelSource::MaterialId(id) => MaterialKey::Tile(*id),
+        PixelSource::Color(color) => MaterialKey::Color(color.to_u8_array()),
+        PixelSource::Pixel(pixel) => MaterialKey::Color(*pixel),
+        _ => MaterialKey::Color([128, 128, 128, 255]),
+    }
+}
+
+/// The batch with its transform applied.
+fn export_mesh(name: String, batch: &Batch3D, material: usize) -> ExportMesh {
+    let transform = batch.transform_3d;
+    let positions = batch
+        .vertices
+        .iter()
+        .map(|v| {
+            let p = transform * Vec4::new(v[0], v[1], v[2], v[3]);
+            let w = if p.w.abs() > f32::EPSILON { p.w } else { 1.0 };
+            [p.x / w, p.y / w, p.z / w]
+        })
+        .collect::<Vec<_>>();
+    let normals = if batch.normals.len() == batch.vertices.len() {
+        batch
+            .normals
+            .iter()
+            .map(|n| {
+                let n = transform.mul_direction(*n);
+                let n = if n.magnitude_squared() > 0.0 {
+                    n.normalized()
+                } else {
+                    Vec3::unit_y()
+                };
+                [n.x, n.y, n.z]
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+    let uvs = if batch.uvs.len() == batch.vertices.len() {
+        batch.uvs.clone()
+    } else {
+        vec![[0.0, 0.0]; batch.vertices.len()]
+    };
+    let count = positions.len();
+    ExportMesh {
+        name,
+        material,
+        positions,
+        normals,
+        uvs,
+        indices: batch
+            .indices
+            .iter()
+            .filter(|(a, b, c)| *a < count && *b < count && *c < count)
+            .copied()
+            .collect(),
+    }
+}
+
+fn write_obj(
+    path: &Path,
+    dir: &Path,
+    stem: &str,
+    meshes: &[ExportMesh],
+    materials: &[ExportMaterial],
+    texture_files: &[Option<String>],
+) -> Result<(), String> {
+    let mut mtl = String::new();
+    for (material, texture) in materials.iter().zip(texture_files) {
+        let [r, g, b, a] = material.color.map(|c| c as f32 / 255.0);
+        let _ = writeln!(mtl, "newmtl {}", material.name);
+        let _ = writeln!(mtl, "Kd {} {} {}", r, g, b);
+        let _ = writeln!(mtl, "d {}", a);
+        if let Some(file) = texture {
+            let _ = writeln!(mtl, "map_Kd {}", file);
+        }
+        mtl.push('\n');
+    }
+    let mtl_file = format!("{}.mtl", stem);
+    std::fs::write(dir.join(&mtl_file), mtl).map_err(|err| err.to_string())?;
+
+    let mut obj = format!("mtllib {}\n", mtl_file);
+    // OBJ indices are global and 1-based
+    let (mut positions, mut normals) = (1, 1);
+    for mesh in meshes {
+        let _ = writeln!(obj, "o {}", mesh.name);
+        let _ = writeln!(obj, "usemtl {}", materials[mesh.material].name);
+        for p in &mesh.positions {
+            let _ = writeln!(obj, "v {} {} {}", p[0], p[1], p[2]);
+        }
+        // The v axis of OBJ points up
+        for uv in &mesh.uvs {
+            let _ = writeln!(obj, "vt {} {}", uv[0], 1.0 - uv[1]);
+        }
+        for n in &mesh.normals {
+            let _ = writeln!(obj, "vn {} {} {}", n[0], n[1], n[2]);
+        }
+        let has_normals = !mesh.normals.is_empty();
+        for (a, b, c) in &mesh.indices {
+            let _ = write!(obj, "f");
+            for index in [a, b, c] {
+                let vertex = positions + index;
+                if has_normals {
+                    let _ = write!(obj, " {}/{}/{}", vertex, vertex, normals + index);
+                } else {
+                    let _ = write!(obj, " {}/{}", vertex, vertex);
+                }
+            }
+            obj.push('\n');
+        }
+        positions += mesh.positions.len();
+        normals += mesh.normals.len();
+    }
+    std::fs::write(path, obj).map_err(|err| err.to_string())
+}
+
+fn write_gltf(
+    path: &Path,
+    dir: &Path,
+    stem: &str,
+    meshes: &[ExportMesh],
+    materials: &[ExportMaterial],
+    texture_files: &[Option<String>],
+) -> Result<(), String> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    // Append the data as a buffer view with an accessor, returns the index of the accessor
+    let mut push = |data: &[u8], target: u32, accessor: serde_json::Value| {
+        while buffer.len() % 4 != 0 {
+            buffer.push(0);
+        }
+        views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": data.len(),
+            "target": target,
+        }));
+        buffer.extend_from_slice(data);
+        let mut accessor = accessor;
+        accessor["bufferView"] = json!(views.len() - 1);
+        accessors.push(accessor);
+        accessors.len() - 1
+    };
+    let floats = |values: &mut dyn Iterator<Item = f32>| -> Vec<u8> {
+        values.flat_map(f32::to_le_bytes).collect()
+    };
+
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+
+    let mut gltf_meshes = Vec::new();
+    for mesh in meshes {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in &mesh.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        let count = mesh.positions.len();
+        let position = push(
+            &floats(&mut mesh.positions.iter().flatten().copied()),
+            ARRAY_BUFFER,
+            json!({ "componentType": FLOAT, "count": count, "type": "VEC3", "min": min, "max": max }),
+        );
+        let uv = push(
+            &floats(&mut mesh.uvs.iter().flatten().copied()),
+            ARRAY_BUFFER,
+            json!({ "componentType": FLOAT, "count": count, "type": "VEC2" }),
+        );
+        let mut attributes = json!({ "POSITION": position, "TEXCOORD_0": uv });
+        if !mesh.normals.is_empty() {
+            attributes["NORMAL"] = json!(push(
+                &floats(&mut mesh.normals.iter().flatten().copied()),
+                ARRAY_BUFFER,
+                json!({ "componentType": FLOAT, "count": count, "type": "VEC3" }),
+            ));
+        }
+        let indices: Vec<u8> = mesh
+            .indices
+            .iter()
+            .flat_map(|(a, b, c)| [*a as u32, *b as u32, *c as u32])
+            .flat_map(u32::to_le_bytes)
+            .collect();
+        let indices = push(
+            &indices,
+            ELEMENT_ARRAY_BUFFER,
+            json!({ "componentType": UNSIGNED_INT, "count": mesh.indices.len() * 3, "type": "SCALAR" }),
+        );
+        gltf_meshes.push(json!({
+            "name": mesh.name,
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices,
+                "material": mesh.material,
+            }],
+        }));
+    }
+
+    let mut images = Vec::new();
+    let gltf_materials: Vec<serde_json::Value> = materials
+        .iter()
+        .zip(texture_files)
+        .map(|(material, texture)| {
+            let color = material.color.map(|c| c as f32 / 255.0);
+            let mut pbr = json!({
+                "baseColorFactor": color,
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            });
+            if let Some(file) = texture {
+                images.push(json!({ "uri": file }));
+                pbr["baseColorTexture"] = json!({ "index": images.len() - 1 });
+            }
+            let mut material = json!({ "name": material.name, "pbrMetallicRoughness": pbr });
+            if color[3] < 1.0 {
+                material["alphaMode"] = json!("BLEND");
+            }
+            material
+        })
+        .collect();
+    // Pixel art textures are sampled without filtering
+    let textures: Vec<serde_json::Value> = (0..images.len())
+        .map(|index| json!({ "source": index, "sampler": 0 }))
+        .collect();
+
+    let bin_file = format!("{}.bin", stem);
+    std::fs::write(dir.join(&bin_file), &buffer).map_err(|err| err.to_string())?;
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "Rusterix" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..gltf_meshes.len()).collect::<Vec<_>>() }],
+        "nodes": (0..gltf_meshes.len()).map(|index| json!({ "mesh": index })).collect::<Vec<_>>(),
+        "meshes": gltf_meshes,
+        "materials": gltf_materials,
+        "textures": textures,
+        "images": images,
+        "samplers": [{ "magFilter": 9728, "minFilter": 9728 }],
+        "buffers": [{ "uri": bin_file, "byteLength": buffer.len() }],
+        "bufferViews": views,
+        "accessors": accessors,
+    });
+    let json = serde_json::to_string_pretty(&document).map_err(|err| err.to_string())?;
+    std::fs::write(path, json).map_err(|err| err.to_string())
+}
@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use crate::{
-    Assets, BillboardAnimation, BillboardMetadata, D3Camera, Item, Map, PixelSource,
+    Assets, BillboardAnimation, BillboardMetadata, D3Camera, Entity, Item, Map, PixelSource,
     RenderSettings, Texture, Tile, Value,
 };
 use indexmap::IndexMap;
@@ -75,6 +75,9 @@ pub struct SceneHandler {
     // Animation state per billboard
     pub(crate) billboard_anim_states: FxHashMap<GeoId, BillboardAnimState>,
 
+    /// Smoothed positions of entities, used instead of their map positions when present.
+    pub entity_positions: FxHashMap<u32, Vec3<f32>>,
+
     // Local render-frame counter for timing animations at fixed FPS
     frame_counter: usize,
 
@@ -155,6 +158,7 @@ impl SceneHandler {
 
             billboards: FxHashMap::default(),
             billboard_anim_states: FxHashMap::default(),
+            entity_positions: FxHashMap::default(),
             frame_counter: 0,
             render_fps: 30.0,
             game_tick_fps: 4.0, // default 250ms ticks
@@ -346,6 +350,14 @@ impl SceneHandler {
     }
 
     /// Build dynamic elements of the 2D Map: Entities, Items, Lights ...
+    /// The position to render the entity at, see `entity_positions`.
+    pub fn entity_position(&self, entity: &Entity) -> Vec3<f32> {
+        self.entity_positions
+            .get(&entity.id)
+            .copied()
+            .unwrap_or(entity.position)
+    }
+
     pub fn build_dynamics_2d(&mut self, map: &Map, assets: &Assets) {
         self.vm.execute(Atom::ClearDynamics);
         self.vm.execute(Atom::ClearLights);
@@ -384,14 +396,15 @@ impl SceneHandler {
         }
 
         for entity in &map.entities {
-            let entity_pos = Vec2::new(entity.position.x, entity.position.z);
+            let position = self.entity_position(entity);
+            let entity_pos = Vec2::new(position.x, position.z);
             let pos = Vec2::new(entity_pos.x, entity_pos.y);
 
             // Find light on entity
             if let Some(Value::Light(light)) = entity.attributes.get("light") {
                 if light.active {
                     let mut light = light.clone();
-                    light.set_position(entity.position);
+                    light.set_position(position);
                 }
             }
 
@@ -401,7 +414,7 @@ impl SceneHandler {
                     if light.active {
                         self.vm.execute(Atom::AddLight {
                             id: GeoId::ItemLight(item.id),
-                            light: Light::new_pointlight(entity.position)
+                            light: Light::new_pointlight(position)
                                 .with_color(Vec3::from(light.get_color()))
                                 .with_intensity(light.get_intensity())
                                 .with_emitting(light.active)
@@ -447,6 +460,7 @@ impl SceneHandler {
 
         // Entities
         for entity in &map.entities {
+            let position = self.entity_position(entity);
             let show_entity = true; // !(entity.is_player() && camera.id() == "firstp");
 
             if show_entity {
@@ -454,7 +468,7 @@ impl SceneHandler {
                 if let Some(Value::Light(light)) = entity.attributes.get("light") {
                     self.vm.execute(Atom::AddLight {
                         id: GeoId::ItemLight(entity.id),
-                        light: Light::new_pointlight(entity.position)
+                        light: Light::new_pointlight(position)
                             .with_color(Vec3::from(light.get_color().map(|c| c.powf(2.2)))) // Convert light to linear
                             .with_intensity(light.get_intensity())
                             .with_emitting(light.active)
@@ -469,7 +483,7 @@ impl SceneHandler {
                     if let Some(Value::Light(light)) = item.attributes.get("light") {
                         self.vm.execute(Atom::AddLight {
                             id: GeoId::ItemLight(item.id),
-                            light: Light::new_pointlight(entity.position)
+                            light: Light::new_pointlight(position)
                                 .with_color(Vec3::from(light.get_color().map(|c| c.powf(2.2)))) // Convert light to linear
                                 .with_intensity(light.get_intensity())
                                 .with_emitting(light.active)
@@ -484,8 +498,7 @@ impl SceneHandler {
                     if entity.attributes.get_bool_default("visible", false) {
                         let size = 2.0;
                         if let Some(tile) = source.tile_from_tile_list(assets) {
-                            let center3 =
-                                Vec3::new(entity.position.x, size * 0.5, entity.position.z);
+                            let center3 = Vec3::new(position.x, size * 0.5, position.z);
 
                             let dynamic = DynamicObject::billboard_tile(
                                 GeoId::Item(entity.id),
@@ -1,8 +1,13 @@
 use crate::{
-    Assets, BBox, Batch3D, Chunk, ChunkBuilder, D2ChunkBuilder, D3ChunkBuilder, Map, TerrainChunk,
-    Tile,
+    Assets, BBox, Batch3D, BillboardMetadata, Chunk, ChunkBuilder, ChunkCache, ChunkPart,
+    D2ChunkBuilder, D3ChunkBuilder, Map, MapPatch, TerrainChunk, Tile,
 };
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use instant::{Duration, Instant};
 use scenevm::Chunk as VMChunk;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use theframework::prelude::*;
 
 #[allow(clippy::large_enum_variant)]
@@ -10,6 +15,10 @@ pub enum SceneManagerCmd {
     SetTileList(Vec<Tile>, FxHashMap<Uuid, u16>),
     SetPalette(ThePalette),
     SetMap(Map),
+    /// A new version of the current map, only the chunks covering changes are rebuilt.
+    UpdateMap(Map),
+    /// An edit of the current map, only the chunks covering the edit are rebuilt.
+    PatchMap(MapPatch),
     SetBuilder2D(Option<Box<dyn ChunkBuilder>>),
     AddDirty(Vec<(i32, i32)>),
     SetDirtyTerrainChunks(Vec<TerrainChunk>),
@@ -22,17 +31,182 @@ pub enum SceneManagerCmd {
 pub enum SceneManagerResult {
     Startup,
     Clear,
+    /// The static geometry of a chunk, replaces the chunk and its dynamic geometry.
     Chunk(VMChunk, i32, i32, Vec<crate::BillboardMetadata>),
+    /// The dynamic geometry of a chunk (see `ChunkPart`), follows its static geometry and is
+    /// sent again when it is rebuilt without the static geometry. Replaces the previous
+    /// dynamic geometry of the chunk, an empty chunk removes it.
+    DynamicChunk(VMChunk, Vec<crate::BillboardMetadata>),
     ProcessedHeights(Vec2<i32>, FxHashMap<(i32, i32), f32>),
     UpdatedBatch3D((i32, i32), Batch3D),
+    /// The chunk was evicted to stay within the memory budget, remove it from the scene. It is
+    /// rebuilt once the focus comes close again.
+    Evicted((i32, i32)),
     Quit,
 }
 
-/// WASM-compatible scene manager that processes chunks incrementally without threads
+/// What happened while streaming the chunks, for progress bars and profiling, see
+/// `SceneManager::record_events`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneManagerEvent {
+    /// A chunk was built, or its static geometry loaded from the cache, and handed out.
+    ChunkBuilt {
+        coord: (i32, i32),
+        lod: usize,
+        triangles: usize,
+        /// The time it took to build or load the chunk.
+        duration: Duration,
+        cached: bool,
+        /// The chunks still to build.
+        queued: usize,
+    },
+    /// A chunk was evicted, see `SceneManagerResult::Evicted`.
+    ChunkEvicted((i32, i32)),
+    /// All chunks within the interest radius are handed out.
+    Finished,
+}
+
+/// The state of the chunk streaming, including the adjacent regions. See `SceneManager::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SceneManagerStats {
+    /// The chunks of the map.
+    pub total_chunks: usize,
+    /// The chunks handed out and not evicted.
+    pub resident_chunks: usize,
+    /// The chunks waiting to be built or being built.
+    pub queued_chunks: usize,
+    /// The dirty chunks outside of the interest radius.
+    pub deferred_chunks: usize,
+    /// The estimated memory (in bytes) of the resident chunks.
+    pub memory_usage: usize,
+    /// The triangles of the resident chunks.
+    pub triangles: usize,
+
+    /// The chunks built (or loaded) since the manager was created.
+    pub chunks_built: u64,
+    /// How many of the built chunks were loaded from the cache.
+    pub cache_hits: u64,
+    /// The chunks evicted since the manager was created.
+    pub chunks_evicted: u64,
+    /// The total, the longest and the last build time of the built chunks.
+    pub build_time: Duration,
+    pub max_build_time: Duration,
+    pub last_build_time: Duration,
+}
+
+impl SceneManagerStats {
+    /// The average build time of the built chunks.
+    pub fn average_build_time(&self) -> Duration {
+        if self.chunks_built == 0 {
+            return Duration::ZERO;
+        }
+        self.build_time / self.chunks_built as u32
+    }
+
+    fn add(&mut self, other: &SceneManagerStats) {
+        self.total_chunks += other.total_chunks;
+        self.resident_chunks += other.resident_chunks;
+        self.queued_chunks += other.queued_chunks;
+        self.deferred_chunks += other.deferred_chunks;
+        self.memory_usage += other.memory_usage;
+        self.triangles += other.triangles;
+        self.chunks_built += other.chunks_built;
+        self.cache_hits += other.cache_hits;
+        self.chunks_evicted += other.chunks_evicted;
+        self.build_time += other.build_time;
+        self.max_build_time = self.max_build_time.max(other.max_build_time);
+    }
+}
+
+/// A chunk built by a worker.
+struct BuiltChunk {
+    coord: (i32, i32),
+    generation: u64,
+    /// None if the build was cancelled before it started.
+    built: Option<ChunkGeometry>,
+}
+
+/// The static geometry of a chunk with its billboards and the dynamic geometry with its
+/// billboards, if the chunk has any.
+struct ChunkGeometry {
+    vmchunk: VMChunk,
+    billboards: Vec<BillboardMetadata>,
+    dynamic: Option<(VMChunk, Vec<BillboardMetadata>)>,
+    duration: Duration,
+    cached: bool,
+}
+
+/// An adjacent region map, streamed by its own manager from a copy of the map moved to its
+/// place in the world.
+struct Neighbor {
+    bbox: BBox,
+    manager: SceneManager,
+    loaded: bool,
+}
+
+/// A chunk handed out to the host, tracked for the memory budget.
+struct Resident {
+    bytes: usize,
+    triangles: usize,
+    // The tick the focus was last close to the chunk
+    last_used: u64,
+}
+
+/// The detail levels of the chunks by the distance of their center to the focus. Chunks
+/// farther away than the n-th distance are built at level n + 1, level 0 is the full detail.
+/// Without distances all chunks are built at full detail.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkLod {
+    /// The ascending distances (in map units) at which the next level starts.
+    pub distances: Vec<f32>,
+    /// How far (in map units) a chunk has to move past a distance to change its level, so
+    /// that chunks at the border do not pop back and forth.
+    pub hysteresis: f32,
+}
+
+impl ChunkLod {
+    /// The level of a chunk at the distance, chunks keep their current level within the
+    /// hysteresis.
+    pub fn level(&self, distance: f32, current: Option<usize>) -> usize {
+        let past = |offset: f32| {
+            self.distances
+                .iter()
+                .filter(|d| distance > **d + offset)
+                .count()
+        };
+        match current {
+            Some(current) => {
+                let hysteresis = self.hysteresis.max(0.0);
+                current.clamp(past(hysteresis), past(-hysteresis))
+            }
+            None => past(0.0),
+        }
+    }
+}
+
+/// Builds the chunks of a map incrementally. By default one chunk is built per `tick` on the
+/// calling thread, which also works in WASM. With workers (see `set_workers`) the chunks are
+/// built on a thread pool and at most `budget` finished chunks are handed out per `tick`, so
+/// that many dirty chunks (after a big edit or a teleport) do not stall a frame. Chunks
+/// closest to the focus (see `set_focus`) and in front of the view direction (see
+/// `set_view_direction`) are built and handed out first, chunks outside of the
+/// `interest_radius` wait until the focus comes close. With a `memory_budget` the least
+/// recently used chunks are evicted once the handed out chunks exceed it. The chunks of
+/// adjacent regions (see `add_neighbor`) are streamed in when the focus approaches them, they
+/// are built on the same thread pool. With a `ChunkLod` (see `set_lod`) distant chunks are
+/// built with less detail, and rebuilt when the focus moves them to another level. With a
+/// cache directory (see `set_cache_dir`) built chunks are stored on disk and loaded instead
+/// of built when the same map is opened again.
+///
+/// The animated geometry (see `ChunkPart`) is handed out separately from the static geometry
+/// of a chunk. It is rebuilt on its own by `animate` and `dynamic_changed`, without
+/// rebuilding the static geometry. `stats` and the recorded `events` report the progress and
+/// the cost of the streaming.
 pub struct SceneManager {
-    // Internal state (no channels needed)
-    assets: Assets,
-    map: Map,
+    // Internal state (no channels needed), shared with the build jobs and only copied when
+    // changed while jobs still use them
+    assets: Arc<Assets>,
+    map: Arc<Map>,
     terrain_modifiers: bool,
     chunk_size: i32,
 
@@ -50,6 +224,59 @@ pub struct SceneManager {
     // Processing state
     processing_final_update: bool,
     final_update_iter: std::vec::IntoIter<(i32, i32)>,
+
+    /// Finished chunks handed out per tick when building on workers.
+    pub budget: usize,
+    /// Dirty chunks farther away from the focus (in map units) are not built until the
+    /// focus comes closer, None builds all chunks.
+    pub interest_radius: Option<f32>,
+    focus: Vec2<f32>,
+    view_direction: Option<Vec2<f32>>,
+    // Dirty chunks outside of the interest radius
+    deferred: FxHashSet<(i32, i32)>,
+
+    // The level of detail of the chunks, the level every chunk was last built at and the
+    // focus of the last level update
+    lod: ChunkLod,
+    lods: FxHashMap<(i32, i32), usize>,
+    lod_focus: Option<Vec2<f32>>,
+
+    cache: Option<ChunkCache>,
+
+    /// The estimated memory (in bytes) of the handed out chunks after which the least
+    /// recently used ones are evicted, None keeps all chunks.
+    pub memory_budget: Option<usize>,
+    resident: FxHashMap<(i32, i32), Resident>,
+    resident_bytes: usize,
+    evicted: FxHashSet<(i32, i32)>,
+    ticks: u64,
+
+    // The handed out chunks with dynamic geometry and the ones whose dynamic geometry is
+    // rebuilt on the next tick
+    dynamic: FxHashSet<(i32, i32)>,
+    dynamic_dirty: FxHashSet<(i32, i32)>,
+
+    /// Record the events of the streaming, see `events`.
+    pub record_events: bool,
+    events: Vec<SceneManagerEvent>,
+    // The counters of the statistics, the rest is collected by `stats`
+    counters: SceneManagerStats,
+
+    /// The distance (in map units) to an adjacent region at which its chunks are streamed in.
+    pub stream_radius: f32,
+    neighbors: FxHashMap<Uuid, Neighbor>,
+
+    // Worker state, the generation changes with the map and the assets and marks results
+    // of older versions as stale
+    // The pool is shared with the managers of the adjacent regions
+    pool: Option<Arc<rayon::ThreadPool>>,
+    workers: usize,
+    generation: u64,
+    // The chunks being built with the flag which cancels their build
+    in_flight: FxHashMap<(i32, i32), Arc<AtomicBool>>,
+    ready: Vec<BuiltChunk>,
+    sender: Sender<BuiltChunk>,
+    receiver: Receiver<BuiltChunk>,
 }
 
 impl Default for SceneManager {
@@ -60,9 +287,10 @@ impl Default for SceneManager {
 
 impl SceneManager {
     pub fn new() -> Self {
+        let (sender, receiver) = unbounded();
         Self {
-            assets: Assets::default(),
-            map: Map::default(),
+            assets: Arc::new(Assets::default()),
+            map: Arc::new(Map::default()),
             terrain_modifiers: true,
             chunk_size: 16,
 
@@ -78,7 +306,106 @@ impl SceneManager {
 
             processing_final_update: false,
             final_update_iter: Vec::new().into_iter(),
+
+            budget: 4,
+            interest_radius: None,
+            focus: Vec2::zero(),
+            view_direction: None,
+            deferred: FxHashSet::default(),
+
+            lod: ChunkLod::default(),
+            lods: FxHashMap::default(),
+            lod_focus: None,
+
+            cache: None,
+
+            memory_budget: None,
+            resident: FxHashMap::default(),
+            resident_bytes: 0,
+            evicted: FxHashSet::default(),
+            ticks: 0,
+
+            dynamic: FxHashSet::default(),
+            dynamic_dirty: FxHashSet::default(),
+
+            record_events: false,
+            events: Vec::new(),
+            counters: SceneManagerStats::default(),
+
+            stream_radius: 32.0,
+            neighbors: FxHashMap::default(),
+
+            pool: None,
+            workers: 0,
+            generation: 0,
+            in_flight: FxHashMap::default(),
+            ready: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Build the chunks on a pool of the given number of worker threads, 0 builds them on the
+    /// calling thread. Falls back to the calling thread where threads are not available. The
+    /// adjacent regions use the same pool.
+    pub fn set_workers(&mut self, workers: usize) {
+        if workers == self.workers {
+            return;
         }
+        let pool = if workers > 0 && crate::IS_THREADED {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(workers)
+                .thread_name(|i| format!("chunk-builder-{}", i))
+                .build()
+                .ok()
+                .map(Arc::new)
+        } else {
+            None
+        };
+        self.share_pool(workers, pool);
+    }
+
+    fn share_pool(&mut self, workers: usize, pool: Option<Arc<rayon::ThreadPool>>) {
+        self.workers = workers;
+        self.pool = pool;
+        for neighbor in self.neighbors.values_mut() {
+            neighbor.manager.share_pool(workers, self.pool.clone());
+        }
+    }
+
+    /// The position (in map units) the chunks are prioritized around, i.e. the camera.
+    pub fn set_focus(&mut self, focus: Vec2<f32>) {
+        self.focus = focus;
+    }
+
+    /// The direction the camera looks at, chunks in front of the camera are built before the
+    /// ones behind it. None only prioritizes by distance.
+    pub fn set_view_direction(&mut self, direction: Option<Vec2<f32>>) {
+        self.view_direction = direction
+            .filter(|direction| direction.magnitude_squared() > 1e-6)
+            .map(|direction| direction.normalized());
+    }
+
+    /// Set the level of detail of the chunks, the built chunks are rebuilt at their new level.
+    pub fn set_lod(&mut self, lod: ChunkLod) {
+        if lod == self.lod {
+            return;
+        }
+        self.lod = lod;
+        self.lods.clear();
+        self.lod_focus = None;
+        self.dirty.extend(self.all.iter().copied());
+    }
+
+    /// Store the built chunks in the directory and load them from there, None disables the
+    /// cache.
+    pub fn set_cache_dir(&mut self, dir: Option<PathBuf>) {
+        self.cache = dir.map(ChunkCache::new);
+    }
+
+    /// The size of the chunks in map units.
+    pub fn chunk_size(&self) -> i32 {
+        self.chunk_size
     }
 
     /// Check for a result (pop from queue)
@@ -92,15 +419,46 @@ impl SceneManager {
 
     /// Send a command (process immediately, no channels needed)
     pub fn send(&mut self, cmd: SceneManagerCmd) {
+        // The adjacent regions share the assets
+        for neighbor in self.neighbors.values_mut() {
+            match &cmd {
+                SceneManagerCmd::SetTileList(tiles, indices) => neighbor
+                    .manager
+                    .send(SceneManagerCmd::SetTileList(tiles.clone(), indices.clone())),
+                SceneManagerCmd::SetPalette(palette) => neighbor
+                    .manager
+                    .send(SceneManagerCmd::SetPalette(palette.clone())),
+                _ => {}
+            }
+        }
+
+        // Re-dirtied chunks are built from the same map and assets
+        if !matches!(cmd, SceneManagerCmd::Quit | SceneManagerCmd::AddDirty(_)) {
+            self.generation += 1;
+        }
+        // The cache hashes the map and the tile list, only rehash them once they changed
+        if matches!(
+            cmd,
+            SceneManagerCmd::SetTileList(..)
+                | SceneManagerCmd::SetMap(_)
+                | SceneManagerCmd::UpdateMap(_)
+                | SceneManagerCmd::PatchMap(_)
+                | SceneManagerCmd::SetDirtyTerrainChunks(_)
+        ) {
+            if let Some(cache) = &mut self.cache {
+                cache.invalidate();
+            }
+        }
         match cmd {
             SceneManagerCmd::SetTileList(tiles, indices) => {
-                self.assets.tile_list = tiles;
-                self.assets.tile_indices = indices;
+                let assets = Arc::make_mut(&mut self.assets);
+                assets.tile_list = tiles;
+                assets.tile_indices = indices;
                 self.dirty = Self::generate_chunk_coords(&self.map.bbox(), self.chunk_size);
                 self.all = self.dirty.clone();
             }
             SceneManagerCmd::SetPalette(palette) => {
-                self.assets.palette = palette;
+                Arc::make_mut(&mut self.assets).palette = palette;
                 self.dirty = Self::generate_chunk_coords(&self.map.bbox(), self.chunk_size);
                 self.all = self.dirty.clone();
             }
@@ -112,8 +470,15 @@ impl SceneManager {
             SceneManagerCmd::SetMap(new_map) => {
                 if self.map.id != new_map.id {
                     self.results.push(SceneManagerResult::Clear);
+                    self.resident.clear();
+                    self.resident_bytes = 0;
+                    self.evicted.clear();
+                    self.dynamic.clear();
+                    self.dynamic_dirty.clear();
+                    self.lods.clear();
+                    self.lod_focus = None;
                 }
-                self.map = new_map;
+                self.map = Arc::new(new_map);
                 let mut bbox = self.map.bbox();
                 if let Some(tbbox) = self.map.terrain.compute_bounds() {
                     bbox.expand_bbox(tbbox);
@@ -126,6 +491,38 @@ impl SceneManager {
                 self.all = self.dirty.clone();
                 self.total_chunks = self.dirty.len() as i32;
             }
+            SceneManagerCmd::UpdateMap(mut new_map) => {
+                let changed = self.map.changed_bbox(&new_map);
+
+                // Keep the identity and selection so clients keep their scene and state
+                new_map.id = self.map.id;
+                new_map.copy_selection(&self.map);
+                self.map = Arc::new(new_map);
+
+                if let Some(bbox) = changed {
+                    // Walls extend beyond their vertices, include the neighboring chunks
+                    let bbox = bbox.expanded(Vec2::broadcast(2.0));
+                    for coord in Self::generate_chunk_coords(&bbox, self.chunk_size) {
+                        self.dirty.insert(coord);
+                        self.all.insert(coord);
+                    }
+                }
+            }
+            SceneManagerCmd::PatchMap(patch) => {
+                // Rebuild where the edited elements were and where they are now
+                let before = patch.bbox(&self.map);
+                patch.apply(Arc::make_mut(&mut self.map));
+                let after = patch.bbox(&self.map);
+
+                for bbox in [before, after].into_iter().flatten() {
+                    // Walls extend beyond their vertices, include the neighboring chunks
+                    let bbox = bbox.expanded(Vec2::broadcast(2.0));
+                    for coord in Self::generate_chunk_coords(&bbox, self.chunk_size) {
+                        self.dirty.insert(coord);
+                        self.all.insert(coord);
+                    }
+                }
+            }
             SceneManagerCmd::AddDirty(dirty_chunks) => {
                 for d in dirty_chunks {
                     self.dirty.insert(d);
@@ -136,7 +533,10 @@ impl SceneManager {
                 for chunk in dirty_chunks {
                     let coord = (chunk.origin.x, chunk.origin.y);
                     let local = self.map.terrain.get_chunk_coords(coord.0, coord.1);
-                    self.map.terrain.chunks.insert(local, chunk);
+                    Arc::make_mut(&mut self.map)
+                        .terrain
+                        .chunks
+                        .insert(local, chunk);
                     self.dirty.insert(coord);
                     self.all.insert(coord);
                     if !self.terrain_modifiers {
@@ -159,6 +559,73 @@ impl SceneManager {
                 self.results.push(SceneManagerResult::Quit);
             }
         }
+        self.deferred.retain(|coord| self.all.contains(coord));
+        self.evicted.retain(|coord| self.all.contains(coord));
+        self.dynamic_dirty.retain(|coord| self.all.contains(coord));
+    }
+
+    /// Stream the chunks of an adjacent region map next to the current map, with the offset
+    /// (in map units) of its origin to the origin of the current map. Its chunks are built
+    /// once the focus comes within the `stream_radius` of the region and evicted when the
+    /// focus moves away again. The offset is snapped to whole chunks so that the chunks of
+    /// the regions do not share coordinates, the regions should not overlap. The terrain of
+    /// adjacent regions is not streamed.
+    pub fn add_neighbor(&mut self, mut map: Map, offset: Vec2<f32>) {
+        self.remove_neighbor(map.id);
+
+        let chunk_size = self.chunk_size as f32;
+        map.translate((offset / chunk_size).round() * chunk_size);
+        map.terrain = Default::default();
+        let id = map.id;
+        let bbox = map.bbox();
+
+        let mut manager = SceneManager::new();
+        manager.assets = self.assets.clone();
+        manager.chunk_builder_d2 = self.chunk_builder_d2.as_ref().map(|b| b.boxed_clone());
+        manager.chunk_builder_d3 = self.chunk_builder_d3.as_ref().map(|b| b.boxed_clone());
+        manager.share_pool(self.workers, self.pool.clone());
+        manager.budget = self.budget;
+        manager.lod = self.lod.clone();
+        manager.interest_radius = Some(self.stream_radius);
+        manager.record_events = self.record_events;
+        manager.set_map(map);
+
+        self.neighbors.insert(
+            id,
+            Neighbor {
+                bbox,
+                manager,
+                loaded: false,
+            },
+        );
+    }
+
+    /// Stop streaming the adjacent region, its chunks are evicted.
+    pub fn remove_neighbor(&mut self, id: Uuid) {
+        if let Some(mut neighbor) = self.neighbors.remove(&id) {
+            neighbor.manager.unload();
+            self.events.append(&mut neighbor.manager.events);
+            self.results.extend(
+                neighbor
+                    .manager
+                    .results
+                    .drain(..)
+                    .filter(|result| matches!(result, SceneManagerResult::Evicted(_))),
+            );
+        }
+    }
+
+    /// Stop streaming all adjacent regions.
+    pub fn clear_neighbors(&mut self) {
+        let ids: Vec<Uuid> = self.neighbors.keys().copied().collect();
+        for id in ids {
+            self.remove_neighbor(id);
+        }
+    }
+
+    /// The ids of the adjacent regions.
+    pub fn neighbors(&self) -> Vec<Uuid> {
+        self.neighbors.keys().copied().collect()
     }
 
     pub fn set_tile_list(&mut self, tiles: Vec<Tile>, tile_indices: FxHashMap<Uuid, u16>) {
@@ -177,6 +644,52 @@ impl SceneManager {
         self.send(SceneManagerCmd::SetMap(map));
     }
 
+    pub fn update_map(&mut self, map: Map) {
+        self.send(SceneManagerCmd::UpdateMap(map));
+    }
+
+    /// Apply an edit of the map, only the chunks covering the edit are rebuilt instead of the
+    /// whole scene.
+    pub fn map_changed(&mut self, patch: &MapPatch) {
+        if !patch.is_empty() {
+            self.send(SceneManagerCmd::PatchMap(patch.clone()));
+        }
+    }
+
+    /// Apply an edit of the dynamic sectors of the map (see `ChunkPart`), i.e. moved movers.
+    /// Only the dynamic geometry of the chunks covering the edit is rebuilt, edits of static
+    /// geometry have to go through `map_changed`.
+    pub fn dynamic_changed(&mut self, patch: &MapPatch) {
+        if patch.is_empty() {
+            return;
+        }
+        let before = patch.bbox(&self.map);
+        patch.apply(Arc::make_mut(&mut self.map));
+        let after = patch.bbox(&self.map);
+
+        for bbox in [before, after].into_iter().flatten() {
+            let bbox = bbox.expanded(Vec2::broadcast(2.0));
+            for coord in Self::generate_chunk_coords(&bbox, self.chunk_size) {
+                if self.resident.contains_key(&coord) {
+                    self.dynamic_dirty.insert(coord);
+                }
+            }
+        }
+    }
+
+    /// Advance the soft rig animation of the map and rebuild the dynamic geometry of the
+    /// handed out chunks on the next tick.
+    pub fn animate(&mut self, delta_time: f32) {
+        if self.map.soft_animator.is_none() {
+            return;
+        }
+        let map = Arc::make_mut(&mut self.map);
+        map.tick(delta_time);
+        let sectors = map.dynamic_sectors();
+        map.update_sector_surfaces(&sectors);
+        self.dynamic_dirty.extend(self.dynamic.iter().copied());
+    }
+
     pub fn add_dirty(&mut self, dirty: Vec<(i32, i32)>) {
         self.send(SceneManagerCmd::AddDirty(dirty));
     }
@@ -196,6 +709,69 @@ impl SceneManager {
     /// Process one chunk per call. Call this from your main loop/update function.
     /// Returns true if there's more work to do, false if idle.
     pub fn tick(&mut self) -> bool {
+        let busy = self.tick_region();
+        self.tick_neighbors() || busy
+    }
+
+    /// Stream the adjacent regions close to the focus and forward their chunks, the regions
+    /// are unloaded once the focus is a chunk farther away than the stream radius.
+    fn tick_neighbors(&mut self) -> bool {
+        let mut busy = false;
+        for neighbor in self.neighbors.values_mut() {
+            let distance = neighbor.bbox.distance(self.focus);
+            if distance <= self.stream_radius {
+                neighbor.loaded = true;
+                let manager = &mut neighbor.manager;
+                manager.focus = self.focus;
+                manager.view_direction = self.view_direction;
+                manager.interest_radius = Some(self.stream_radius);
+                manager.record_events = self.record_events;
+                busy |= manager.tick_region();
+            } else if neighbor.loaded && distance > self.stream_radius + self.chunk_size as f32 {
+                neighbor.loaded = false;
+                neighbor.manager.unload();
+            }
+
+            // The regions share the scene, only the current region clears it
+            for result in neighbor.manager.results.drain(..) {
+                match result {
+                    SceneManagerResult::Startup
+                    | SceneManagerResult::Clear
+                    | SceneManagerResult::Quit => {}
+                    result => self.results.push(result),
+                }
+            }
+        }
+        busy
+    }
+
+    /// Evict all handed out chunks, they are built again once needed.
+    fn unload(&mut self) {
+        for coord in self.resident.keys() {
+            self.results.push(SceneManagerResult::Evicted(*coord));
+            if self.record_events {
+                self.events.push(SceneManagerEvent::ChunkEvicted(*coord));
+            }
+        }
+        self.counters.chunks_evicted += self.resident.len() as u64;
+        self.resident.clear();
+        self.resident_bytes = 0;
+        self.dynamic.clear();
+        self.dynamic_dirty.clear();
+        for cancel in self.in_flight.values() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        self.ready.clear();
+        self.deferred.clear();
+        self.evicted.clear();
+        self.dirty = self.all.clone();
+    }
+
+    /// Process one chunk of the current region.
+    fn tick_region(&mut self) -> bool {
+        self.update_residency();
+        self.rebuild_dynamic();
+
         // If we're doing final terrain mesh updates
         if self.processing_final_update {
             if let Some(coord) = self.final_update_iter.next() {
@@ -217,36 +793,45 @@ impl SceneManager {
             }
         }
 
-        // Process one dirty chunk
-        if let Some(&coord) = self.dirty.iter().next() {
-            self.dirty.remove(&coord);
+        self.update_lods();
+        self.update_interest();
 
-            let mut chunk = Chunk::new(Vec2::new(coord.0, coord.1), self.chunk_size);
-            let mut vmchunk = VMChunk::new(Vec2::new(coord.0, coord.1), self.chunk_size);
+        if self.pool.is_some() {
+            return self.tick_workers();
+        }
 
-            if let Some(cb_d2) = &mut self.chunk_builder_d2 {
-                cb_d2.build(&self.map, &self.assets, &mut chunk, &mut vmchunk);
-            }
+        // Process the dirty chunk with the highest priority
+        let priority =
+            |coord| view_priority(coord, self.chunk_size, self.focus, self.view_direction);
+        let next = self
+            .dirty
+            .iter()
+            .copied()
+            .min_by(|a, b| priority(*a).total_cmp(&priority(*b)));
+        if let Some(coord) = next {
+            self.dirty.remove(&coord);
+            let lod = self.chunk_lod(coord);
+            self.prepare_cache();
 
-            if let Some(cb_d3) = &mut self.chunk_builder_d3 {
-                cb_d3.build(&self.map, &self.assets, &mut chunk, &mut vmchunk);
-            }
+            let geometry = load_or_build(self.cache.as_ref(), coord, lod, |part| {
+                build_chunk(
+                    &self.map,
+                    &self.assets,
+                    self.chunk_builder_d2.as_deref_mut(),
+                    self.chunk_builder_d3.as_deref_mut(),
+                    coord,
+                    self.chunk_size,
+                    lod,
+                    part,
+                )
+            });
 
             // Send the chunk with billboards
-            let billboards = chunk.billboards.clone();
-            self.results.push(SceneManagerResult::Chunk(
-                vmchunk,
-                self.dirty.len() as i32,
-                self.total_chunks,
-                billboards,
-            ));
+            self.hand_out(coord, geometry, self.dirty.len() as i32);
 
             // Check if we just finished all dirty chunks
             if self.dirty.is_empty() {
-                // Start final terrain mesh update phase
-                let all_coords: Vec<(i32, i32)> = self.all.iter().copied().collect();
-                self.final_update_iter = all_coords.into_iter();
-                self.processing_final_update = true;
+                self.start_final_update();
             }
 
             true // More work to do
@@ -255,6 +840,390 @@ impl SceneManager {
         }
     }
 
+    /// Rebuild the built chunks whose level changed since the focus moved.
+    fn update_lods(&mut self) {
+        if self.lod.distances.is_empty()
+            || self
+                .lod_focus
+                .is_some_and(|focus| focus.distance(self.focus) < 1.0)
+        {
+            return;
+        }
+        self.lod_focus = Some(self.focus);
+
+        for (coord, level) in &self.lods {
+            let distance = focus_distance(*coord, self.chunk_size, self.focus).sqrt();
+            if self.lod.level(distance, Some(*level)) != *level && self.all.contains(coord) {
+                self.dirty.insert(*coord);
+            }
+        }
+    }
+
+    /// Defer the dirty chunks which left the interest radius, cancel their builds and resume
+    /// the deferred chunks which are within the radius again.
+    fn update_interest(&mut self) {
+        let Some(radius) = self.interest_radius else {
+            self.dirty.extend(self.deferred.drain());
+            return;
+        };
+        let (chunk_size, focus) = (self.chunk_size, self.focus);
+        let inside =
+            |coord: &(i32, i32)| focus_distance(*coord, chunk_size, focus).sqrt() <= radius;
+
+        let left: Vec<(i32, i32)> = self.dirty.iter().filter(|c| !inside(c)).copied().collect();
+        for coord in left {
+            self.dirty.remove(&coord);
+            self.deferred.insert(coord);
+        }
+        for (coord, cancel) in &self.in_flight {
+            if !inside(coord) {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        let entered: Vec<(i32, i32)> = self
+            .deferred
+            .iter()
+            .filter(|c| inside(c))
+            .copied()
+            .collect();
+        for coord in entered {
+            self.deferred.remove(&coord);
+            self.dirty.insert(coord);
+        }
+    }
+
+    /// Queue the chunk as a result and track its memory.
+    fn hand_out(&mut self, coord: (i32, i32), geometry: ChunkGeometry, togo: i32) {
+        let ChunkGeometry {
+            vmchunk,
+            billboards,
+            dynamic,
+            duration,
+            cached,
+        } = geometry;
+        let mut bytes = estimate_bytes(&vmchunk, &billboards);
+        let mut triangles = count_triangles(&vmchunk);
+        if let Some((vmchunk, billboards)) = &dynamic {
+            bytes += estimate_bytes(vmchunk, billboards);
+            triangles += count_triangles(vmchunk);
+        }
+        let resident = Resident {
+            bytes,
+            triangles,
+            last_used: self.ticks,
+        };
+        if let Some(old) = self.resident.insert(coord, resident) {
+            self.resident_bytes -= old.bytes;
+        }
+        self.resident_bytes += bytes;
+        self.evicted.remove(&coord);
+
+        let counters = &mut self.counters;
+        counters.chunks_built += 1;
+        counters.cache_hits += cached as u64;
+        counters.build_time += duration;
+        counters.max_build_time = counters.max_build_time.max(duration);
+        counters.last_build_time = duration;
+        self.record(SceneManagerEvent::ChunkBuilt {
+            coord,
+            lod: self.lods.get(&coord).copied().unwrap_or(0),
+            triangles,
+            duration,
+            cached,
+            queued: togo.max(0) as usize,
+        });
+
+        self.results.push(SceneManagerResult::Chunk(
+            vmchunk,
+            togo,
+            self.total_chunks,
+            billboards,
+        ));
+        // The static chunk replaced the previous dynamic geometry
+        self.dynamic_dirty.remove(&coord);
+        match dynamic {
+            Some((vmchunk, billboards)) => {
+                self.dynamic.insert(coord);
+                self.results
+                    .push(SceneManagerResult::DynamicChunk(vmchunk, billboards));
+            }
+            None => {
+                self.dynamic.remove(&coord);
+            }
+        }
+    }
+
+    /// Rebuild the dynamic geometry of the chunks marked by `animate` and `dynamic_changed`.
+    /// The dynamic geometry is small, it is built on the calling thread.
+    fn rebuild_dynamic(&mut self) {
+        let coords: Vec<(i32, i32)> = self.dynamic_dirty.drain().collect();
+        for coord in coords {
+            if !self.resident.contains_key(&coord) {
+                continue;
+            }
+            let lod = self.lods.get(&coord).copied().unwrap_or(0);
+            let (vmchunk, billboards) = build_chunk(
+                &self.map,
+                &self.assets,
+                None,
+                self.chunk_builder_d3.as_deref_mut(),
+                coord,
+                self.chunk_size,
+                lod,
+                ChunkPart::Dynamic,
+            );
+            // Send an empty chunk once to remove the geometry which moved away
+            if has_geometry(&vmchunk, &billboards) {
+                self.dynamic.insert(coord);
+            } else if !self.dynamic.remove(&coord) {
+                continue;
+            }
+            self.results
+                .push(SceneManagerResult::DynamicChunk(vmchunk, billboards));
+        }
+    }
+
+    /// Mark the chunks close to the focus as used, rebuild the evicted chunks the focus came
+    /// back to and evict the least recently used chunks over the memory budget. Chunks within
+    /// the interest radius (or a few chunks without one) count as close.
+    fn update_residency(&mut self) {
+        let Some(budget) = self.memory_budget else {
+            self.dirty.extend(self.evicted.drain());
+            return;
+        };
+        self.ticks += 1;
+
+        let (chunk_size, focus) = (self.chunk_size, self.focus);
+        let radius = self
+            .interest_radius
+            .unwrap_or(chunk_size as f32 * KEEP_RADIUS_CHUNKS);
+        let close = |coord: (i32, i32)| focus_distance(coord, chunk_size, focus).sqrt() <= radius;
+
+        for (coord, resident) in &mut self.resident {
+            if close(*coord) {
+                resident.last_used = self.ticks;
+            }
+        }
+        let returned: Vec<(i32, i32)> = self
+            .evicted
+            .iter()
+            .filter(|c| close(**c))
+            .copied()
+            .collect();
+        for coord in returned {
+            self.evicted.remove(&coord);
+            self.dirty.insert(coord);
+        }
+
+        if self.resident_bytes <= budget {
+            return;
+        }
+        // Oldest first, the farthest of the same age first
+        let mut candidates: Vec<((i32, i32), u64)> = self
+            .resident
+            .iter()
+            .filter(|(_, resident)| resident.last_used < self.ticks)
+            .map(|(coord, resident)| (*coord, resident.last_used))
+            .collect();
+        candidates.sort_by(|a, b| {
+            a.1.cmp(&b.1).then_with(|| {
+                focus_distance(b.0, chunk_size, focus)
+                    .total_cmp(&focus_distance(a.0, chunk_size, focus))
+            })
+        });
+        for (coord, _) in candidates {
+            if self.resident_bytes <= budget {
+                break;
+            }
+            if let Some(resident) = self.resident.remove(&coord) {
+                self.resident_bytes -= resident.bytes;
+                self.dynamic.remove(&coord);
+                self.evicted.insert(coord);
+                self.results.push(SceneManagerResult::Evicted(coord));
+                self.counters.chunks_evicted += 1;
+                self.record(SceneManagerEvent::ChunkEvicted(coord));
+            }
+        }
+    }
+
+    /// Queue the event if events are recorded.
+    fn record(&mut self, event: SceneManagerEvent) {
+        if self.record_events {
+            self.events.push(event);
+        }
+    }
+
+    /// Take the recorded events of the current region and the adjacent regions, see
+    /// `record_events`.
+    pub fn events(&mut self) -> Vec<SceneManagerEvent> {
+        let mut events = std::mem::take(&mut self.events);
+        for neighbor in self.neighbors.values_mut() {
+            events.append(&mut neighbor.manager.events);
+        }
+        events
+    }
+
+    /// The statistics of the chunk streaming of the current region and the adjacent regions.
+    pub fn stats(&self) -> SceneManagerStats {
+        let mut stats = SceneManagerStats {
+            total_chunks: self.all.len(),
+            resident_chunks: self.resident.len(),
+            queued_chunks: self.remaining_chunks(),
+            deferred_chunks: self.deferred.len(),
+            memory_usage: self.resident_bytes,
+            triangles: self
+                .resident
+                .values()
+                .map(|resident| resident.triangles)
+                .sum(),
+            ..self.counters
+        };
+        for neighbor in self.neighbors.values() {
+            stats.add(&neighbor.manager.stats());
+        }
+        stats
+    }
+
+    /// The estimated memory (in bytes) of the chunks handed out and not evicted.
+    pub fn memory_usage(&self) -> usize {
+        self.resident_bytes
+    }
+
+    /// The number of chunks handed out and not evicted.
+    pub fn resident_chunks(&self) -> usize {
+        self.resident.len()
+    }
+
+    /// Hash the content of the map for the cache once it changed.
+    fn prepare_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            if !cache.has_content() {
+                cache.set_content(&self.map, &self.assets);
+            }
+        }
+    }
+
+    /// The level to build the chunk at, which is recorded as its current level.
+    fn chunk_lod(&mut self, coord: (i32, i32)) -> usize {
+        if self.lod.distances.is_empty() {
+            return 0;
+        }
+        let distance = focus_distance(coord, self.chunk_size, self.focus).sqrt();
+        let level = self.lod.level(distance, self.lods.get(&coord).copied());
+        self.lods.insert(coord, level);
+        level
+    }
+
+    /// Start the final terrain mesh update phase.
+    fn start_final_update(&mut self) {
+        self.record(SceneManagerEvent::Finished);
+        let all_coords: Vec<(i32, i32)> = self.all.iter().copied().collect();
+        self.final_update_iter = all_coords.into_iter();
+        self.processing_final_update = true;
+    }
+
+    /// Collect the chunks of the workers, start new jobs and hand out the finished chunks
+    /// within the budget.
+    fn tick_workers(&mut self) -> bool {
+        let (chunk_size, focus, view) = (self.chunk_size, self.focus, self.view_direction);
+        let priority = |coord| view_priority(coord, chunk_size, focus, view);
+
+        while let Ok(built) = self.receiver.try_recv() {
+            self.in_flight.remove(&built.coord);
+            if !self.all.contains(&built.coord) {
+                continue;
+            }
+            if built.built.is_none() {
+                // Cancelled, the chunk waits until it is within the interest radius again
+                self.deferred.insert(built.coord);
+            } else if built.generation == self.generation {
+                self.ready.push(built);
+            } else {
+                // Built from an older map, build it again
+                self.dirty.insert(built.coord);
+            }
+        }
+
+        // Keep every worker busy with the chunks of the highest priority, chunks which are
+        // already being built wait for their job to finish
+        let free = (self.workers * 2).saturating_sub(self.in_flight.len());
+        if free > 0 {
+            let mut coords: Vec<(i32, i32)> = self
+                .dirty
+                .iter()
+                .filter(|coord| !self.in_flight.contains_key(coord))
+                .copied()
+                .collect();
+            coords.sort_by(|a, b| priority(*a).total_cmp(&priority(*b)));
+            coords.truncate(free);
+
+            if !coords.is_empty() {
+                self.prepare_cache();
+                let (map, assets) = (self.map.clone(), self.assets.clone());
+                for coord in coords {
+                    self.dirty.remove(&coord);
+                    let cancel = Arc::new(AtomicBool::new(false));
+                    self.in_flight.insert(coord, cancel.clone());
+                    let lod = self.chunk_lod(coord);
+
+                    let map = map.clone();
+                    let assets = assets.clone();
+                    let mut d2 = self.chunk_builder_d2.as_ref().map(|b| b.boxed_clone());
+                    let mut d3 = self.chunk_builder_d3.as_ref().map(|b| b.boxed_clone());
+                    let sender = self.sender.clone();
+                    let generation = self.generation;
+                    let cache = self.cache.clone();
+                    if let Some(pool) = &self.pool {
+                        pool.spawn(move || {
+                            let built = (!cancel.load(Ordering::Relaxed)).then(|| {
+                                load_or_build(cache.as_ref(), coord, lod, |part| {
+                                    build_chunk(
+                                        &map,
+                                        &assets,
+                                        d2.as_deref_mut(),
+                                        d3.as_deref_mut(),
+                                        coord,
+                                        chunk_size,
+                                        lod,
+                                        part,
+                                    )
+                                })
+                            });
+                            let _ = sender.send(BuiltChunk {
+                                coord,
+                                generation,
+                                built,
+                            });
+                        });
+                    }
+                }
+            }
+        }
+
+        // Hand out the finished chunks of the highest priority
+        let handed_out = self.ready.len().min(self.budget.max(1));
+        if handed_out > 0 {
+            self.ready
+                .sort_by(|a, b| priority(b.coord).total_cmp(&priority(a.coord)));
+            for _ in 0..handed_out {
+                if let Some(BuiltChunk {
+                    coord,
+                    built: Some(geometry),
+                    ..
+                }) = self.ready.pop()
+                {
+                    let togo = self.remaining_chunks() as i32;
+                    self.hand_out(coord, geometry, togo);
+                }
+            }
+            if self.remaining_chunks() == 0 {
+                self.start_final_update();
+            }
+        }
+
+        self.is_busy()
+    }
+
     /// Process multiple chunks at once (useful for batch processing)
     /// Returns the number of chunks processed
     pub fn tick_batch(&mut self, max_chunks: usize) -> usize {
@@ -269,7 +1238,7 @@ impl SceneManager {
     }
 
     /// Returns all chunks which cover the given bounding box.
-    fn generate_chunk_coords(bbox: &BBox, chunk_size: i32) -> FxHashSet<(i32, i32)> {
+    pub(crate) fn generate_chunk_coords(bbox: &BBox, chunk_size: i32) -> FxHashSet<(i32, i32)> {
         let min_x = (bbox.min.x / chunk_size as f32).floor() as i32;
         let min_y = (bbox.min.y / chunk_size as f32).floor() as i32;
         let max_x = (bbox.max.x / chunk_size as f32).ceil() as i32;
@@ -286,11 +1255,174 @@ impl SceneManager {
 
     /// Check if the manager is currently processing chunks
     pub fn is_busy(&self) -> bool {
-        !self.dirty.is_empty() || self.processing_final_update
+        self.remaining_chunks() > 0 || self.processing_final_update
     }
 
-    /// Get the number of chunks remaining to process
+    /// Get the number of chunks remaining to process, without the chunks outside of the
+    /// interest radius
     pub fn remaining_chunks(&self) -> usize {
-        self.dirty.len()
+        self.dirty.len() + self.in_flight.len() + self.ready.len()
+    }
+}
+
+/// The scene id of the dynamic geometry of the chunk at the origin, adding a dynamic chunk
+/// with this id replaces the previous one.
+pub fn dynamic_chunk_id(origin: Vec2<i32>) -> Uuid {
+    Uuid::from_u64_pair(
+        0x6479_6e61_6d69_6300,
+        ((origin.x as u32 as u64) << 32) | origin.y as u32 as u64,
+    )
+}
+
+/// Without an interest radius chunks within this many chunks of the focus count as used.
+const KEEP_RADIUS_CHUNKS: f32 = 4.0;
+
+/// The estimated memory of a chunk: the vertices (position and uv) and indices of its
+/// triangles and its billboards.
+fn estimate_bytes(vmchunk: &VMChunk, billboards: &[BillboardMetadata]) -> usize {
+    let triangles = count_triangles(vmchunk);
+    std::mem::size_of::<VMChunk>()
+        + triangles * 3 * (std::mem::size_of::<[f32; 6]>() + std::mem::size_of::<usize>())
+        + std::mem::size_of_val(billboards)
+}
+
+/// The triangles of the chunk.
+fn count_triangles(vmchunk: &VMChunk) -> usize {
+    vmchunk
+        .polys3d_map
+        .values()
+        .flatten()
+        .map(|poly| poly.indices.len())
+        .sum()
+}
+
+/// True if the chunk has triangles or billboards.
+fn has_geometry(vmchunk: &VMChunk, billboards: &[BillboardMetadata]) -> bool {
+    !vmchunk.polys3d_map.is_empty() || !billboards.is_empty()
+}
+
+/// The squared distance of the center of the chunk to the focus.
+fn focus_distance(coord: (i32, i32), chunk_size: i32, focus: Vec2<f32>) -> f32 {
+    let half = chunk_size as f32 / 2.0;
+    Vec2::new(coord.0 as f32 + half, coord.1 as f32 + half).distance_squared(focus)
+}
+
+/// The build priority of the chunk, lower first: the squared distance of its center to the
+/// focus, chunks behind the view direction count up to twice as far.
+fn view_priority(
+    coord: (i32, i32),
+    chunk_size: i32,
+    focus: Vec2<f32>,
+    view_direction: Option<Vec2<f32>>,
+) -> f32 {
+    let half = chunk_size as f32 / 2.0;
+    let offset = Vec2::new(coord.0 as f32 + half, coord.1 as f32 + half) - focus;
+    let distance = offset.magnitude_squared();
+    match view_direction {
+        Some(view) if distance > 1e-6 => {
+            let cos = offset.normalized().dot(view);
+            distance * (1.5 - 0.5 * cos).powi(2)
+        }
+        _ => distance,
+    }
+}
+
+/// Build the dynamic geometry of the chunk and load its static geometry from the cache, or
+/// build it and store it in the cache.
+fn load_or_build(
+    cache: Option<&ChunkCache>,
+    coord: (i32, i32),
+    lod: usize,
+    mut build: impl FnMut(ChunkPart) -> (VMChunk, Vec<BillboardMetadata>),
+) -> ChunkGeometry {
+    let start = Instant::now();
+    let dynamic = Some(build(ChunkPart::Dynamic))
+        .filter(|(vmchunk, billboards)| has_geometry(vmchunk, billboards));
+
+    let cached = cache.and_then(|cache| cache.load(coord, lod));
+    let hit = cached.is_some();
+    let (vmchunk, billboards) = match cached {
+        Some(vmchunk) => (vmchunk, vec![]),
+        None => {
+            let (vmchunk, billboards) = build(ChunkPart::Static);
+            // The billboards live outside of the chunk, chunks with billboards are always built
+            if let Some(cache) = cache.filter(|_| billboards.is_empty()) {
+                cache.store(coord, lod, &vmchunk);
+            }
+            (vmchunk, billboards)
+        }
+    };
+    ChunkGeometry {
+        vmchunk,
+        billboards,
+        dynamic,
+        duration: start.elapsed(),
+        cached: hit,
+    }
+}
+
+/// Build the part of the chunk at the coordinate with the 2D and the 3D builder, the 2D
+/// builder only builds static geometry.
+#[allow(clippy::too_many_arguments)]
+fn build_chunk(
+    map: &Map,
+    assets: &Assets,
+    d2: Option<&mut (dyn ChunkBuilder + 'static)>,
+    d3: Option<&mut (dyn ChunkBuilder + 'static)>,
+    coord: (i32, i32),
+    chunk_size: i32,
+    lod: usize,
+    part: ChunkPart,
+) -> (VMChunk, Vec<BillboardMetadata>) {
+    let mut chunk = Chunk::new(Vec2::new(coord.0, coord.1), chunk_size);
+    chunk.lod = lod;
+    chunk.part = part;
+    let mut vmchunk = VMChunk::new(Vec2::new(coord.0, coord.1), chunk_size);
+
+    if let Some(cb_d2) = d2.filter(|_| part != ChunkPart::Dynamic) {
+        cb_d2.build(map, assets, &mut chunk, &mut vmchunk);
+    }
+
+    if let Some(cb_d3) = d3 {
+        cb_d3.build(map, assets, &mut chunk, &mut vmchunk);
+    }
+
+    (vmchunk, chunk.billboards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_map_rebuilds_changed_chunks() {
+        let mut map = Map::new();
+        let a = map.add_vertex_at(0.0, 0.0);
+        let b = map.add_vertex_at(4.0, 0.0);
+        map.create_linedef(a, b);
+        let c = map.add_vertex_at(100.0, 100.0);
+        let d = map.add_vertex_at(104.0, 100.0);
+        map.create_linedef(c, d);
+        map.selected_vertices = vec![a, d];
+
+        let mut manager = SceneManager::new();
+        manager.set_map(map.clone());
+        assert!(manager.dirty.contains(&(0, 0)));
+        manager.dirty.clear();
+
+        let mut edited = map.clone();
+        edited.id = Uuid::new_v4();
+        edited.selected_vertices.clear();
+        edited.update_vertex(d, Vec2::new(104.0, 104.0));
+        manager.update_map(edited);
+
+        let expected: FxHashSet<(i32, i32)> = [(96, 96)].into_iter().collect();
+        assert_eq!(manager.dirty, expected);
+        assert_eq!(manager.map.id, map.id);
+        assert_eq!(manager.map.selected_vertices, vec![a, d]);
+        assert_eq!(manager.map.get_vertex(d), Some(Vec2::new(104.0, 104.0)));
+
+        manager.update_map((*manager.map).clone());
+        assert_eq!(manager.dirty, expected);
     }
 }
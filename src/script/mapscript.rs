@@ -1,8 +1,13 @@
 use crate::script::ParseError;
-use crate::{Entity, Light, Map, MapMeta, PixelSource, Texture, Tile, Value};
-// use rustpython::vm;
-// use rustpython::vm::*;
-use std::sync::{LazyLock, RwLock};
+use crate::{
+    Entity, Item, Light, LightType, Linedef, Map, MapMeta, PixelSource, Sector, Texture, Tile,
+    Value, ValueContainer, Vertex, VertexBlendPreset,
+};
+use rustpython::vm;
+use rustpython::vm::function::FuncArgs;
+use rustpython::vm::*;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, RwLock};
 use theframework::prelude::*;
 use vek::Vec2;
 
@@ -57,6 +62,16 @@ static CURSORSTATE: LazyLock<RwLock<MapCursorState>> =
 static SAVEDSTATE: LazyLock<RwLock<MapCursorState>> =
     LazyLock::new(|| RwLock::new(MapCursorState::default()));
 
+/// The directory includes are resolved against.
+static BASE_PATH: LazyLock<RwLock<PathBuf>> = LazyLock::new(|| RwLock::new(PathBuf::new()));
+/// The chain of files currently being included, used to detect recursion.
+static INCLUDE_STACK: LazyLock<RwLock<Vec<PathBuf>>> = LazyLock::new(|| RwLock::new(vec![]));
+/// All files included by the current compilation.
+static INCLUDED_PATHS: LazyLock<RwLock<Vec<PathBuf>>> = LazyLock::new(|| RwLock::new(vec![]));
+
+/// The script state is global, compilations run one at a time.
+static COMPILING: Mutex<()> = Mutex::new(());
+
 fn push() {
     *SAVEDSTATE.write().unwrap() = *CURSORSTATE.read().unwrap()
 }
@@ -83,6 +98,118 @@ fn hex_to_rgb_f32(hex: &str) -> [f32; 3] {
     }
 }
 
+/// The positional and keyword arguments of a call, keywords take precedence.
+struct Args<'a> {
+    args: FuncArgs,
+    vm: &'a VirtualMachine,
+}
+
+impl<'a> Args<'a> {
+    fn new(args: FuncArgs, vm: &'a VirtualMachine) -> Self {
+        Self { args, vm }
+    }
+
+    /// The argument at the given position or with the given name.
+    fn get(&self, index: usize, name: &str) -> Option<PyObjectRef> {
+        self.args
+            .kwargs
+            .get(name)
+            .or_else(|| self.args.args.get(index))
+            .cloned()
+    }
+
+    fn number(&self, index: usize, name: &str) -> PyResult<f32> {
+        let Some(value) = self.get(index, name) else {
+            return Err(self
+                .vm
+                .new_type_error(format!("Missing argument '{}'", name)));
+        };
+        if value.class().is(self.vm.ctx.types.int_type) {
+            Ok(value.try_into_value::<i32>(self.vm)? as f32)
+        } else if value.class().is(self.vm.ctx.types.float_type) {
+            value.try_into_value::<f32>(self.vm)
+        } else {
+            Err(self
+                .vm
+                .new_type_error(format!("Expected a number for '{}'", name)))
+        }
+    }
+
+    /// An integer argument, i.e. the id of a map element.
+    fn id(&self, index: usize, name: &str) -> PyResult<u32> {
+        match self.get(index, name) {
+            Some(value) if value.class().is(self.vm.ctx.types.int_type) => {
+                value.try_into_value::<u32>(self.vm)
+            }
+            Some(_) => Err(self
+                .vm
+                .new_type_error(format!("Expected an integer for '{}'", name))),
+            None => Err(self
+                .vm
+                .new_type_error(format!("Missing argument '{}'", name))),
+        }
+    }
+
+    fn number_or(&self, index: usize, name: &str, default: f32) -> PyResult<f32> {
+        if self.get(index, name).is_some() {
+            self.number(index, name)
+        } else {
+            Ok(default)
+        }
+    }
+
+    fn string(&self, index: usize, name: &str) -> PyResult<String> {
+        let Some(value) = self.get(index, name) else {
+            return Err(self
+                .vm
+                .new_type_error(format!("Missing argument '{}'", name)));
+        };
+        String::try_from_object(self.vm, value).map_err(|_| {
+            self.vm
+                .new_type_error(format!("Expected a string for '{}'", name))
+        })
+    }
+
+    fn string_or(&self, index: usize, name: &str, default: &str) -> PyResult<String> {
+        if self.get(index, name).is_some() {
+            self.string(index, name)
+        } else {
+            Ok(default.to_string())
+        }
+    }
+
+    /// Read an area given as x, y, width, depth starting at the given argument index.
+    fn area(&self, index: usize) -> PyResult<(f32, f32, f32, f32)> {
+        Ok((
+            self.number(index, "x")?,
+            self.number(index + 1, "y")?,
+            self.number(index + 2, "width")?.max(0.0),
+            self.number(index + 3, "depth")?.max(0.0),
+        ))
+    }
+
+    /// The texture of the given argument as a tile id.
+    fn texture(&self, index: usize, name: &str) -> PyResult<Uuid> {
+        let texture = self.string(index, name)?;
+        get_texture(&texture).ok_or_else(|| {
+            self.vm
+                .new_type_error(format!("Could not find texture {}", texture))
+        })
+    }
+}
+
+/// The per cell tile and blend overrides of the terrain.
+type TerrainOverrides = (
+    FxHashMap<(i32, i32), PixelSource>,
+    FxHashMap<(i32, i32), (VertexBlendPreset, PixelSource)>,
+);
+
+/// The position of a light at the cursor and the given height.
+fn light_position(height: f32) -> Vec3<f32> {
+    let state = CURSORSTATE.read().unwrap();
+    Vec3::new(state.position.x, height, state.position.y)
+}
+
 fn add_entity(name: String, class_name: String, texture: String) {
     let state = CURSORSTATE.read().unwrap();
 
@@ -123,6 +250,570 @@ fn add_point_light(color: String, intensity: f32, start_distance: f32, end_dista
     map.lights.push(light);
 }
 
+/// point_light(color, intensity=1, range=5, flicker=0, height=0.5, start=0)
+fn point_light(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let range = args.number_or(2, "range", 5.0)?;
+
+    let mut light = Light::new(LightType::Point);
+    light.set_position(light_position(args.number_or(4, "height", 0.5)?));
+    light.set_color(hex_to_rgb_f32(&args.string(0, "color")?));
+    light.set_intensity(args.number_or(1, "intensity", 1.0)?);
+    light.set_start_distance(args.number_or(5, "start", 0.0)?.min(range));
+    light.set_end_distance(range);
+    light.set_flicker(args.number_or(3, "flicker", 0.0)?);
+
+    MAP.write().unwrap().lights.push(light);
+    Ok(())
+}
+
+/// area_light(color, width, depth, intensity=1, range=5, flicker=0, height=wall height)
+fn area_light(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let range = args.number_or(4, "range", 5.0)?;
+    let height = args.number_or(6, "height", *DEFAULT_WALL_HEIGHT.read().unwrap())?;
+
+    // Area lights are placed like ceiling panels and emit downwards
+    let mut light = Light::new(LightType::Area);
+    light.set_position(light_position(height));
+    light.set_color(hex_to_rgb_f32(&args.string(0, "color")?));
+    light
+        .properties
+        .set("width", Value::Float(args.number(1, "width")?));
+    light
+        .properties
+        .set("height", Value::Float(args.number(2, "depth")?));
+    light
+        .properties
+        .set("normal", Value::Vec3([0.0, -1.0, 0.0]));
+    light.set_intensity(args.number_or(3, "intensity", 1.0)?);
+    light.set_start_distance(0.0);
+    light.set_end_distance(range);
+    light.set_flicker(args.number_or(5, "flicker", 0.0)?);
+
+    MAP.write().unwrap().lights.push(light);
+    Ok(())
+}
+
+/// sky("#rrggbb") sets the sky color, any other value names the sky texture.
+fn sky(value: String, vm: &VirtualMachine) -> PyResult<()> {
+    if value.starts_with('#') {
+        MAP.write()
+            .unwrap()
+            .properties
+            .set("sky_color", Value::Vec3(hex_to_rgb_f32(&value)));
+        Ok(())
+    } else if let Some(id) = get_texture(&value) {
+        MAP.write().unwrap().sky_texture = Some(id);
+        Ok(())
+    } else {
+        Err(vm.new_type_error(format!("Could not find texture {}", value)))
+    }
+}
+
+/// terrain(texture) enables the terrain with the given default texture.
+fn terrain(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let id = args.texture(0, "texture")?;
+
+    let mut map = MAP.write().unwrap();
+    map.properties.set("terrain_enabled", Value::Bool(true));
+    map.properties.set(
+        "default_terrain_tile",
+        Value::Source(PixelSource::TileId(id)),
+    );
+    Ok(())
+}
+
+/// terrain_height(x, y, height, smoothness=1)
+fn terrain_height(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    add_terrain_control(
+        &mut MAP.write().unwrap(),
+        args.number(0, "x")?,
+        args.number(1, "y")?,
+        args.number(2, "height")?,
+        args.number_or(3, "smoothness", 1.0)?,
+    );
+    Ok(())
+}
+
+/// heightmap(texture, x, y, width, depth, height=1, step=1) samples the brightness of the
+/// texture over the area.
+fn heightmap(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let name = args.string(0, "texture")?;
+    let Some(texture) = TEXTURES.read().unwrap().get(&name).cloned() else {
+        return Err(vm.new_type_error(format!("Could not find texture {}", name)));
+    };
+    let area = args.area(1)?;
+    let height = args.number_or(5, "height", 1.0)?;
+    let step = args.number_or(6, "step", 1.0)?;
+
+    add_terrain_grid(area, step, |u, v| {
+        let p = texture.sample_nearest(u, v);
+        (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0 * height
+    })
+    .map_err(|err| vm.new_value_error(err))
+}
+
+/// terrain_noise(x, y, width, depth, height=1, frequency=0.1, octaves=4, seed=0, step=2)
+fn terrain_noise(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let area = args.area(0)?;
+    let height = args.number_or(4, "height", 1.0)?;
+    let frequency = args.number_or(5, "frequency", 0.1)?;
+    let octaves = args.number_or(6, "octaves", 4.0)?.max(1.0) as usize;
+    let seed = args.number_or(7, "seed", 0.0)?;
+    let step = args.number_or(8, "step", 2.0)?;
+
+    let (x0, y0, width, depth) = area;
+    add_terrain_grid(area, step, |u, v| {
+        let x = (x0 + u * width) * frequency + seed * 17.31;
+        let y = (y0 + v * depth) * frequency + seed * 7.13;
+        rusteria::procedural::fbm2(x, y, octaves) * height
+    })
+    .map_err(|err| vm.new_value_error(err))
+}
+
+/// terrain_source(x, y, width, depth, texture) overrides the tile of the terrain cells.
+fn terrain_source(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let area = args.area(0)?;
+    let id = args.texture(4, "texture")?;
+    set_terrain_cells(area, |overrides, cell| {
+        overrides.0.insert(cell, PixelSource::TileId(id));
+    });
+    Ok(())
+}
+
+/// terrain_blend(x, y, width, depth, texture, mode="full_blend") blends the texture into the
+/// terrain cells with a blend preset.
+fn terrain_blend(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let area = args.area(0)?;
+    let id = args.texture(4, "texture")?;
+    let mode = args.string_or(5, "mode", "full_blend")?;
+    let Some(preset) = VertexBlendPreset::from_name(&mode) else {
+        return Err(vm.new_value_error(format!("Unknown blend mode '{}'", mode)));
+    };
+    set_terrain_cells(area, |overrides, cell| {
+        overrides.1.insert(cell, (preset, PixelSource::TileId(id)));
+    });
+    Ok(())
+}
+
+/// Add a terrain control point at the given grid position.
+fn add_terrain_control(map: &mut Map, x: f32, y: f32, height: f32, smoothness: f32) {
+    let id = map.add_vertex_at_3d(x, y, height, false);
+    if let Some(vertex) = map.find_vertex_mut(id) {
+        vertex.properties.set("terrain_control", Value::Bool(true));
+        vertex
+            .properties
+            .set("smoothness", Value::Float(smoothness));
+    }
+    map.properties.set("terrain_enabled", Value::Bool(true));
+}
+
+/// Add terrain control points every `step` units over the area. The height function receives
+/// the normalized position inside the area.
+fn add_terrain_grid(
+    (x, y, width, depth): (f32, f32, f32, f32),
+    step: f32,
+    height_at: impl Fn(f32, f32) -> f32,
+) -> Result<(), String> {
+    if step <= 0.0 {
+        return Err("Terrain step needs to be larger than 0".into());
+    }
+
+    let mut map = MAP.write().unwrap();
+    let columns = (width / step).floor() as usize;
+    let rows = (depth / step).floor() as usize;
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let (dx, dy) = (column as f32 * step, row as f32 * step);
+            let u = if width > 0.0 { dx / width } else { 0.0 };
+            let v = if depth > 0.0 { dy / depth } else { 0.0 };
+            add_terrain_control(&mut map, x + dx, y + dy, height_at(u, v), 1.0);
+        }
+    }
+    Ok(())
+}
+
+/// Call `apply` for every terrain cell of the area with the tile and blend overrides of the map.
+fn set_terrain_cells(
+    (x, y, width, depth): (f32, f32, f32, f32),
+    apply: impl Fn(&mut TerrainOverrides, (i32, i32)),
+) {
+    let mut map = MAP.write().unwrap();
+    let mut overrides: TerrainOverrides = (
+        match map.properties.get("tiles") {
+            Some(Value::TileOverrides(tiles)) => tiles.clone(),
+            _ => FxHashMap::default(),
+        },
+        match map.properties.get("blend_tiles") {
+            Some(Value::BlendOverrides(tiles)) => tiles.clone(),
+            _ => FxHashMap::default(),
+        },
+    );
+
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (x1, y1) = ((x + width).ceil() as i32, (y + depth).ceil() as i32);
+    for cy in y0..y1 {
+        for cx in x0..x1 {
+            apply(&mut overrides, (cx, cy));
+        }
+    }
+
+    map.properties
+        .set("tiles", Value::TileOverrides(overrides.0));
+    map.properties
+        .set("blend_tiles", Value::BlendOverrides(overrides.1));
+}
+
+/// include(path, x=0, y=0, rotation=0) runs the file and merges its geometry, lights and
+/// entities into the map, rotated by the angle in degrees and moved to the position.
+fn include(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let path = BASE_PATH.read().unwrap().join(args.string(0, "path")?);
+    let position = Vec2::new(args.number_or(1, "x", 0.0)?, args.number_or(2, "y", 0.0)?);
+    let rotation = args.number_or(3, "rotation", 0.0)?;
+
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+    if INCLUDE_STACK.read().unwrap().contains(&canonical) {
+        return Err(vm.new_value_error(format!("Recursive include of {}", path.display())));
+    }
+    let source = std::fs::read_to_string(&path)
+        .map_err(|e| vm.new_value_error(format!("Could not include {}: {}", path.display(), e)))?;
+
+    // The fragment is compiled into an empty map and shares the textures and defaults
+    let map = std::mem::take(&mut *MAP.write().unwrap());
+    let state = *CURSORSTATE.read().unwrap();
+    let saved_state = *SAVEDSTATE.read().unwrap();
+    *CURSORSTATE.write().unwrap() = MapCursorState::default();
+    let base_path = std::mem::replace(
+        &mut *BASE_PATH.write().unwrap(),
+        path.parent().map(Path::to_path_buf).unwrap_or_default(),
+    );
+    INCLUDE_STACK.write().unwrap().push(canonical);
+
+    let scope = vm.new_scope_with_builtins();
+    add_functions(&scope, vm);
+    let result = vm
+        .compile(
+            &source,
+            vm::compiler::Mode::Exec,
+            path.display().to_string(),
+        )
+        .map_err(|err| vm.new_syntax_error(&err, Some(&source)))
+        .and_then(|code_obj| vm.run_code_obj(code_obj, scope));
+
+    INCLUDE_STACK.write().unwrap().pop();
+    *BASE_PATH.write().unwrap() = base_path;
+    *CURSORSTATE.write().unwrap() = state;
+    *SAVEDSTATE.write().unwrap() = saved_state;
+    let mut fragment = std::mem::replace(&mut *MAP.write().unwrap(), map);
+    result?;
+
+    INCLUDED_PATHS.write().unwrap().push(path);
+
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let transform = |x: f32, y: f32| Vec2::new(x * cos - y * sin, x * sin + y * cos);
+    for vertex in &mut fragment.vertices {
+        let p = transform(vertex.x, vertex.y);
+        vertex.x = p.x;
+        vertex.y = p.y;
+    }
+
+    // Geometry is inserted through the same path as pasted prefabs
+    let mut map = MAP.write().unwrap();
+    map.paste_at_position(&fragment, position);
+    map.clear_selection();
+
+    let place = |p: Vec3<f32>| {
+        let xz = transform(p.x, p.z) + position;
+        Vec3::new(xz.x, p.y, xz.y)
+    };
+    for mut light in fragment.lights {
+        light.set_position(place(light.position()));
+        map.lights.push(light);
+    }
+    for mut entity in fragment.entities {
+        entity.set_position(place(entity.position));
+        map.entities.push(entity);
+    }
+    for mut item in fragment.items {
+        item.set_position(place(item.position));
+        map.items.push(item);
+    }
+
+    Ok(())
+}
+
+/// The placement of an entity or item.
+struct Placement {
+    position: Vec3<f32>,
+    orientation: Option<Vec2<f32>>,
+    attributes: Vec<(String, Value)>,
+}
+
+/// Read the class name, position, orientation and attributes of an entity or item call. The
+/// position defaults to the cursor, all keyword arguments which are not part of the placement
+/// become attributes.
+fn placement(args: &Args) -> PyResult<Placement> {
+    let vm = args.vm;
+    let class_name = args.string(0, "class_name")?;
+    let cursor = CURSORSTATE.read().unwrap().position;
+    let position = Vec3::new(
+        args.number_or(usize::MAX, "x", cursor.x)?,
+        args.number_or(usize::MAX, "height", 1.0)?,
+        args.number_or(usize::MAX, "y", cursor.y)?,
+    );
+    let orientation = match args.get(usize::MAX, "orientation") {
+        Some(_) => {
+            let (sin, cos) = args
+                .number(usize::MAX, "orientation")?
+                .to_radians()
+                .sin_cos();
+            Some(Vec2::new(cos, sin))
+        }
+        None => None,
+    };
+
+    let mut attributes = vec![
+        (
+            "name".to_string(),
+            Value::Str(args.string_or(usize::MAX, "name", &class_name)?),
+        ),
+        ("class_name".to_string(), Value::Str(class_name)),
+    ];
+    if args.get(usize::MAX, "texture").is_some() {
+        attributes.push((
+            "tile_id".into(),
+            Value::Id(args.texture(usize::MAX, "texture")?),
+        ));
+    }
+
+    let mut keys: Vec<&String> = args.args.kwargs.keys().collect();
+    keys.sort();
+    for key in keys {
+        if matches!(
+            key.as_str(),
+            "class_name" | "name" | "x" | "y" | "height" | "orientation" | "texture"
+        ) {
+            continue;
+        }
+        let value = args.args.kwargs[key].clone();
+        let class = value.class();
+        let value = if class.is(vm.ctx.types.bool_type) {
+            Value::Bool(value.try_into_value::<bool>(vm)?)
+        } else if class.is(vm.ctx.types.int_type) {
+            Value::Int(value.try_into_value::<i32>(vm)?)
+        } else if class.is(vm.ctx.types.float_type) {
+            Value::Float(value.try_into_value::<f32>(vm)?)
+        } else if class.is(vm.ctx.types.str_type) {
+            Value::Str(value.try_into_value::<String>(vm)?)
+        } else {
+            return Err(vm.new_type_error(format!("Unsupported value for attribute '{}'", key)));
+        };
+        attributes.push((key.clone(), value));
+    }
+
+    Ok(Placement {
+        position,
+        orientation,
+        attributes,
+    })
+}
+
+/// entity(class_name, x=cursor, y=cursor, height=1, orientation=0, name=class_name,
+/// texture=None, **attributes)
+fn entity(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let placement = placement(&Args::new(args, vm))?;
+
+    let mut entity = Entity::default();
+    entity.set_position(placement.position);
+    if let Some(orientation) = placement.orientation {
+        entity.set_orientation(orientation);
+    }
+    for (key, value) in placement.attributes {
+        entity.set_attribute(&key, value);
+    }
+
+    MAP.write().unwrap().entities.push(entity);
+    Ok(())
+}
+
+/// item(class_name, x=cursor, y=cursor, height=1, name=class_name, texture=None, **attributes)
+fn item(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let placement = placement(&Args::new(args, vm))?;
+
+    let mut item = Item::default();
+    item.set_position(placement.position);
+    for (key, value) in placement.attributes {
+        item.set_attribute(&key, value);
+    }
+
+    MAP.write().unwrap().items.push(item);
+    Ok(())
+}
+
+/// vertex(id, x, y, z=0, name="")
+fn vertex(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let id = args.id(0, "id")?;
+    let mut vertex = Vertex::new_3d(
+        id,
+        args.number(1, "x")?,
+        args.number(2, "y")?,
+        args.number_or(3, "z", 0.0)?,
+    );
+    vertex.name = args.string_or(usize::MAX, "name", "")?;
+
+    let mut map = MAP.write().unwrap();
+    if map.find_vertex(id).is_some() {
+        return Err(vm.new_value_error(format!("Vertex {} already exists", id)));
+    }
+    map.vertices.push(vertex);
+    Ok(())
+}
+
+/// linedef(id, start_vertex, end_vertex, name="")
+fn linedef(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let id = args.id(0, "id")?;
+    let mut linedef = Linedef::new(id, args.id(1, "start")?, args.id(2, "end")?);
+    linedef.name = args.string_or(usize::MAX, "name", "")?;
+
+    let mut map = MAP.write().unwrap();
+    if map.find_linedef(id).is_some() {
+        return Err(vm.new_value_error(format!("Linedef {} already exists", id)));
+    }
+    for vertex_id in [linedef.start_vertex, linedef.end_vertex] {
+        if map.find_vertex(vertex_id).is_none() {
+            return Err(vm.new_value_error(format!("Unknown vertex {}", vertex_id)));
+        }
+    }
+    map.linedefs.push(linedef);
+    Ok(())
+}
+
+/// sector(id, linedef_id, ..., name="")
+fn sector(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let id = args.id(0, "id")?;
+    let linedefs = (1..args.args.args.len())
+        .map(|index| args.id(index, "linedef"))
+        .collect::<PyResult<Vec<u32>>>()?;
+    let name = args.string_or(usize::MAX, "name", "")?;
+
+    let mut map = MAP.write().unwrap();
+    if map.find_sector(id).is_some() {
+        return Err(vm.new_value_error(format!("Sector {} already exists", id)));
+    }
+    for linedef_id in &linedefs {
+        let Some(linedef) = map.find_linedef_mut(*linedef_id) else {
+            return Err(vm.new_value_error(format!("Unknown linedef {}", linedef_id)));
+        };
+        linedef.sector_ids.push(id);
+    }
+    let mut sector = Sector::new(id, linedefs);
+    sector.name = name;
+    map.sectors.push(sector);
+
+    CURSORSTATE.write().unwrap().last_sector = Some(id);
+    Ok(())
+}
+
+/// set_property(kind, id, key, value, type="") sets a property of the map ("map", id 0), a
+/// vertex, linedef or sector. The type selects the kind of value if it is not a float or string.
+fn set_property(args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let args = Args::new(args, vm);
+    let kind = args.string(0, "kind")?;
+    let id = args.id(1, "id")?;
+    let key = args.string(2, "key")?;
+    let value = property_value(&args)?;
+
+    let mut map = MAP.write().unwrap();
+    let properties = match kind.as_str() {
+        "map" => Some(&mut map.properties),
+        "vertex" => map.find_vertex_mut(id).map(|v| &mut v.properties),
+        "linedef" => map.find_linedef_mut(id).map(|l| &mut l.properties),
+        "sector" => map.find_sector_mut(id).map(|s| &mut s.properties),
+        _ => return Err(vm.new_value_error(format!("Unknown property target '{}'", kind))),
+    };
+    let Some(properties) = properties else {
+        return Err(vm.new_value_error(format!("Unknown {} {}", kind, id)));
+    };
+    properties.set(&key, value);
+    Ok(())
+}
+
+/// Read the value of a set_property call with its optional type.
+fn property_value(args: &Args) -> PyResult<Value> {
+    let kind = args.string_or(4, "type", "")?;
+    let invalid = |text: &str| {
+        args.vm
+            .new_value_error(format!("Invalid {} value '{}'", kind, text))
+    };
+    let floats = |text: &str| -> PyResult<Vec<f32>> {
+        text.split(',')
+            .map(|v| v.trim().parse::<f32>().map_err(|_| invalid(text)))
+            .collect()
+    };
+    let id = |text: &str| Uuid::parse_str(text).map_err(|_| invalid(text));
+    let color = |text: &str| -> PyResult<TheColor> {
+        let v: [f32; 4] = floats(text)?.try_into().map_err(|_| invalid(text))?;
+        Ok(TheColor::from(Vec4::from(v)))
+    };
+
+    Ok(match kind.as_str() {
+        "" if args
+            .get(3, "value")
+            .is_some_and(|value| value.class().is(args.vm.ctx.types.str_type)) =>
+        {
+            Value::Str(args.string(3, "value")?)
+        }
+        "" | "float" => Value::Float(args.number(3, "value")?),
+        "string" => Value::Str(args.string(3, "value")?),
+        "bool" => Value::Bool(args.number(3, "value")? != 0.0),
+        "int" => Value::Int(args.number(3, "value")? as i32),
+        "uint" => Value::UInt(args.id(3, "value")?),
+        "vec2" | "vec3" | "vec4" | "id" | "color" | "source" => {
+            let text = args.string(3, "value")?;
+            match kind.as_str() {
+                "vec2" => Value::Vec2(floats(&text)?.try_into().map_err(|_| invalid(&text))?),
+                "vec3" => Value::Vec3(floats(&text)?.try_into().map_err(|_| invalid(&text))?),
+                "vec4" => Value::Vec4(floats(&text)?.try_into().map_err(|_| invalid(&text))?),
+                "id" => Value::Id(id(&text)?),
+                "color" => Value::Color(color(&text)?),
+                _ => {
+                    let (source, param) = text.split_once(':').unwrap_or((text.as_str(), ""));
+                    let index = || param.parse::<u16>().map_err(|_| invalid(&text));
+                    Value::Source(match source {
+                        "off" => PixelSource::Off,
+                        "terrain" => PixelSource::Terrain,
+                        "tile" => PixelSource::TileId(id(param)?),
+                        "material" => PixelSource::MaterialId(id(param)?),
+                        "shader" => PixelSource::ShaderId(id(param)?),
+                        "shapefx" => PixelSource::ShapeFXGraphId(id(param)?),
+                        "static" => PixelSource::StaticTileIndex(index()?),
+                        "dynamic" => PixelSource::DynamicTileIndex(index()?),
+                        "color" => PixelSource::Color(color(param)?),
+                        "sequence" => PixelSource::Sequence(param.to_string()),
+                        _ => return Err(invalid(&text)),
+                    })
+                }
+            }
+        }
+        _ => {
+            return Err(args
+                .vm
+                .new_value_error(format!("Unknown property type '{}'", kind)));
+        }
+    })
+}
+
 fn set_default(key: PyObjectRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
     let key: String = String::try_from_object(vm, key)?;
 
@@ -595,8 +1286,157 @@ fn turn_right() -> PyResult<()> {
     rotate(90.0)
 }
 
+/// Make the MapScript functions available to the script.
+fn add_functions(scope: &vm::scope::Scope, vm: &VirtualMachine) {
+    let _ = scope.globals.set_item(
+        "add_entity",
+        vm.new_function("add_entity", add_entity).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "add_point_light",
+        vm.new_function("add_point_light", add_point_light).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "point_light",
+        vm.new_function("point_light", point_light).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "area_light",
+        vm.new_function("area_light", area_light).into(),
+        vm,
+    );
+
+    let _ = scope
+        .globals
+        .set_item("sky", vm.new_function("sky", sky).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("terrain", vm.new_function("terrain", terrain).into(), vm);
+
+    let _ = scope.globals.set_item(
+        "terrain_height",
+        vm.new_function("terrain_height", terrain_height).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "heightmap",
+        vm.new_function("heightmap", heightmap).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "terrain_noise",
+        vm.new_function("terrain_noise", terrain_noise).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "terrain_source",
+        vm.new_function("terrain_source", terrain_source).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "terrain_blend",
+        vm.new_function("terrain_blend", terrain_blend).into(),
+        vm,
+    );
+
+    let _ = scope
+        .globals
+        .set_item("push", vm.new_function("push", push).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("pop", vm.new_function("pop", pop).into(), vm);
+
+    let _ = scope.globals.set_item(
+        "set_default",
+        vm.new_function("set_default", set_default).into(),
+        vm,
+    );
+
+    let _ = scope
+        .globals
+        .set_item("set", vm.new_function("set", set).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("wall", vm.new_function("wall", wall).into(), vm);
+
+    let _ = scope.globals.set_item(
+        "move_forward",
+        vm.new_function("turn_left", move_forward).into(),
+        vm,
+    );
+
+    let _ = scope
+        .globals
+        .set_item("move_to", vm.new_function("move_to", move_to).into(), vm);
+
+    let _ = scope.globals.set_item(
+        "turn_left",
+        vm.new_function("turn_left", turn_left).into(),
+        vm,
+    );
+
+    let _ = scope.globals.set_item(
+        "turn_right",
+        vm.new_function("turn_right", turn_right).into(),
+        vm,
+    );
+
+    let _ = scope
+        .globals
+        .set_item("rotate", vm.new_function("rotate", rotate).into(), vm);
+    let _ = scope
+        .globals
+        .set_item("include", vm.new_function("include", include).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("entity", vm.new_function("entity", entity).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("item", vm.new_function("item", item).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("vertex", vm.new_function("vertex", vertex).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("linedef", vm.new_function("linedef", linedef).into(), vm);
+
+    let _ = scope
+        .globals
+        .set_item("sector", vm.new_function("sector", sector).into(), vm);
+
+    let _ = scope.globals.set_item(
+        "set_property",
+        vm.new_function("set_property", set_property).into(),
+        vm,
+    );
+}
+
+/// Compiles MapScripts, Python scripts which draw walls, sectors, lights and entities at a
+/// cursor moving over the 2D grid. Variables, expressions and loops are plain Python.
 pub struct MapScript {
     error: Option<ParseError>,
+
+    /// The file compiled by `compile_file`, includes are resolved relative to it.
+    path: Option<PathBuf>,
+    /// All files included by the last compilation.
+    included_paths: Vec<PathBuf>,
 }
 
 impl Default for MapScript {
@@ -607,7 +1447,128 @@ impl Default for MapScript {
 
 impl MapScript {
     pub fn new() -> Self {
-        Self { error: None }
+        Self {
+            error: None,
+            path: None,
+            included_paths: vec![],
+        }
+    }
+
+    /// The files included by the last compilation, so file watchers can track them.
+    pub fn included_paths(&self) -> Vec<PathBuf> {
+        self.included_paths.clone()
+    }
+
+    /// Export the geometry, properties, sources, entities, items and point lights of the map
+    /// as a MapScript.
+    /// Compiling the output recreates the map with the same ids.
+    pub fn export(map: &Map) -> String {
+        let mut out = String::new();
+        if !map.name.is_empty() && !map.name.contains('\n') {
+            out += &format!("# {}\n", map.name);
+        }
+
+        export_properties(&mut out, "map", 0, &map.properties);
+
+        out += "\n# Vertices\n";
+        for vertex in &map.vertices {
+            out += &format!(
+                "vertex({}, {}, {}",
+                vertex.id,
+                number(vertex.x),
+                number(vertex.y)
+            );
+            if vertex.z != 0.0 {
+                out += &format!(", z={}", number(vertex.z));
+            }
+            export_name(&mut out, &vertex.name);
+            export_properties(&mut out, "vertex", vertex.id, &vertex.properties);
+        }
+
+        out += "\n# Linedefs\n";
+        for linedef in &map.linedefs {
+            out += &format!(
+                "linedef({}, {}, {}",
+                linedef.id, linedef.start_vertex, linedef.end_vertex
+            );
+            export_name(&mut out, &linedef.name);
+            export_properties(&mut out, "linedef", linedef.id, &linedef.properties);
+        }
+
+        out += "\n# Sectors\n";
+        for sector in &map.sectors {
+            out += &format!("sector({}", sector.id);
+            for linedef_id in &sector.linedefs {
+                out += &format!(", {}", linedef_id);
+            }
+            export_name(&mut out, &sector.name);
+            export_properties(&mut out, "sector", sector.id, &sector.properties);
+        }
+
+        if !map.entities.is_empty() || !map.items.is_empty() {
+            out += "\n# Entities and items\n";
+        }
+        for entity in &map.entities {
+            let orientation = entity
+                .orientation
+                .y
+                .atan2(entity.orientation.x)
+                .to_degrees();
+            let orientation = (orientation * 1000.0).round() / 1000.0;
+            let orientation = (orientation != 0.0).then_some(orientation);
+            export_placement(
+                &mut out,
+                "entity",
+                entity.position,
+                orientation,
+                &entity.attributes,
+            );
+        }
+        for item in &map.items {
+            export_placement(&mut out, "item", item.position, None, &item.attributes);
+        }
+
+        if !map.lights.is_empty() {
+            out += "\n# Lights\n";
+        }
+        for light in &map.lights {
+            if light.light_type != LightType::Point {
+                out += &format!("# skipped {:?} light\n", light.light_type);
+                continue;
+            }
+            let position = light.position();
+            let [r, g, b] = light.get_color();
+            out += &format!(
+                "move_to({}, {})\npoint_light(\"#{:02x}{:02x}{:02x}\", {}, range={}, flicker={}, height={}, start={})\n",
+                number(position.x),
+                number(position.z),
+                (r.clamp(0.0, 1.0) * 255.0).round() as u8,
+                (g.clamp(0.0, 1.0) * 255.0).round() as u8,
+                (b.clamp(0.0, 1.0) * 255.0).round() as u8,
+                number(light.get_intensity()),
+                number(light.get_end_distance()),
+                number(light.get_flicker()),
+                number(position.y),
+                number(light.get_start_distance()),
+            );
+        }
+
+        out
+    }
+
+    /// Read and compile the given file. Includes are resolved relative to its directory.
+    pub fn compile_file(
+        &mut self,
+        path: &Path,
+        textures: &FxHashMap<String, Texture>,
+    ) -> Result<MapMeta, Vec<String>> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| vec![format!("Could not read {}: {}", path.display(), e)])?;
+
+        self.path = Some(path.to_path_buf());
+        let result = self.compile(&source, textures, None, None, None);
+        self.path = None;
+        result
     }
 
     /// Parse the source and return the new or compiled map.
@@ -619,6 +1580,8 @@ impl MapScript {
         ctx_linedef: Option<u32>,
         ctx_sector: Option<u32>,
     ) -> Result<MapMeta, Vec<String>> {
+        let _compiling = COMPILING.lock().unwrap_or_else(|e| e.into_inner());
+
         self.error = None;
         *MAP.write().unwrap() = ctx_map.unwrap_or_default();
         *TILES.write().unwrap() = FxHashMap::default();
@@ -628,6 +1591,8 @@ impl MapScript {
         *DEFAULT_WALL_TEXTURE_ROW3.write().unwrap() = None;
         *DEFAULT_CEILING_TEXTURE.write().unwrap() = None;
         *DEFAULT_FLOOR_TEXTURE.write().unwrap() = None;
+        *DEFAULT_WALL_HEIGHT.write().unwrap() = 2.0;
+        *DEFAULT_WALL_WIDTH.write().unwrap() = 0.0;
 
         let state = MapCursorState {
             last_wall: ctx_linedef,
@@ -638,6 +1603,19 @@ impl MapScript {
         *CURSORSTATE.write().unwrap() = state;
         *SAVEDSTATE.write().unwrap() = state;
 
+        *BASE_PATH.write().unwrap() = self
+            .path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        *INCLUDE_STACK.write().unwrap() = self
+            .path
+            .iter()
+            .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+            .collect();
+        INCLUDED_PATHS.write().unwrap().clear();
+
         let interpreter = rustpython::InterpreterConfig::new()
             .init_stdlib()
             .interpreter();
@@ -645,66 +1623,7 @@ impl MapScript {
         interpreter.enter(|vm| {
             let scope = vm.new_scope_with_builtins();
 
-            let _ = scope.globals.set_item(
-                "add_entity",
-                vm.new_function("add_entity", add_entity).into(),
-                vm,
-            );
-
-            let _ = scope.globals.set_item(
-                "add_point_light",
-                vm.new_function("add_point_light", add_point_light).into(),
-                vm,
-            );
-
-            let _ = scope
-                .globals
-                .set_item("push", vm.new_function("push", push).into(), vm);
-
-            let _ = scope
-                .globals
-                .set_item("pop", vm.new_function("pop", pop).into(), vm);
-
-            let _ = scope.globals.set_item(
-                "set_default",
-                vm.new_function("set_default", set_default).into(),
-                vm,
-            );
-
-            let _ = scope
-                .globals
-                .set_item("set", vm.new_function("set", set).into(), vm);
-
-            let _ = scope
-                .globals
-                .set_item("wall", vm.new_function("wall", wall).into(), vm);
-
-            let _ = scope.globals.set_item(
-                "move_forward",
-                vm.new_function("turn_left", move_forward).into(),
-                vm,
-            );
-
-            let _ =
-                scope
-                    .globals
-                    .set_item("move_to", vm.new_function("move_to", move_to).into(), vm);
-
-            let _ = scope.globals.set_item(
-                "turn_left",
-                vm.new_function("turn_left", turn_left).into(),
-                vm,
-            );
-
-            let _ = scope.globals.set_item(
-                "turn_right",
-                vm.new_function("turn_right", turn_right).into(),
-                vm,
-            );
-
-            let _ = scope
-                .globals
-                .set_item("rotate", vm.new_function("rotate", rotate).into(), vm);
+            add_functions(&scope, vm);
 
             if let Ok(code_obj) = vm
                 .compile(source, vm::compiler::Mode::Exec, "<embedded>".to_owned())
@@ -724,8 +1643,377 @@ impl MapScript {
                 }
             }
 
+            self.included_paths = INCLUDED_PATHS.read().unwrap().clone();
+
             let meta = MapMeta::new(MAP.read().unwrap().clone(), TILES.read().unwrap().clone());
             Ok(meta)
         })
     }
 }
+
+/// A float as a Python literal, non finite values become 0.
+fn number(value: f32) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        "0.0".into()
+    }
+}
+
+/// A string as a Python literal.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' => quoted += "\\\\",
+            '"' => quoted += "\\\"",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Close an exported element call with its optional name argument.
+fn export_name(out: &mut String, name: &str) {
+    if name.is_empty() {
+        *out += ")\n";
+    } else {
+        *out += &format!(", name={})\n", quote(name));
+    }
+}
+
+/// Export the properties of an element in key order, values without a text form are skipped.
+fn export_properties(out: &mut String, kind: &str, id: u32, properties: &ValueContainer) {
+    for key in properties.keys_sorted() {
+        match properties.get(key).and_then(export_value) {
+            Some(value) => {
+                *out += &format!(
+                    "set_property(\"{}\", {}, {}, {})\n",
+                    kind,
+                    id,
+                    quote(key),
+                    value
+                )
+            }
+            None => *out += &format!("# skipped {} {} property {}\n", kind, id, quote(key)),
+        }
+    }
+}
+
+/// The keywords of Python, they can not be used as attribute names of exported placements.
+const PYTHON_KEYWORDS: [&str; 35] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// Export an entity or item call with its attributes. Internal attributes and attributes which
+/// are not numbers, booleans or strings are skipped.
+fn export_placement(
+    out: &mut String,
+    kind: &str,
+    position: Vec3<f32>,
+    orientation: Option<f32>,
+    attributes: &ValueContainer,
+) {
+    let Some(class_name) = attributes.get_str("class_name") else {
+        *out += &format!("# skipped {} without class name\n", kind);
+        return;
+    };
+
+    *out += &format!(
+        "{}({}, x={}, y={}, height={}",
+        kind,
+        quote(class_name),
+        number(position.x),
+        number(position.z),
+        number(position.y)
+    );
+    if let Some(orientation) = orientation {
+        *out += &format!(", orientation={}", number(orientation));
+    }
+    for key in attributes.keys_sorted() {
+        let is_identifier = key
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if key == "class_name"
+            || key.starts_with('_')
+            || !is_identifier
+            || PYTHON_KEYWORDS.contains(&key.as_str())
+        {
+            continue;
+        }
+        let value = match attributes.get(key) {
+            Some(Value::Bool(v)) => if *v { "True" } else { "False" }.to_string(),
+            Some(Value::Int(v)) => v.to_string(),
+            Some(Value::Float(v)) => number(*v),
+            Some(Value::Str(v)) => quote(v),
+            _ => continue,
+        };
+        *out += &format!(", {}={}", key, value);
+    }
+    *out += ")\n";
+}
+
+/// The value arguments of a set_property call.
+fn export_value(value: &Value) -> Option<String> {
+    let floats = |values: &[f32]| {
+        values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    Some(match value {
+        Value::Float(v) => number(*v),
+        Value::Str(v) => quote(v),
+        Value::Bool(v) => format!("{}, type=\"bool\"", *v as i32),
+        Value::Int(v) => format!("{}, type=\"int\"", v),
+        Value::UInt(v) => format!("{}, type=\"uint\"", v),
+        Value::Vec2(v) => format!("\"{}\", type=\"vec2\"", floats(v)),
+        Value::Vec3(v) => format!("\"{}\", type=\"vec3\"", floats(v)),
+        Value::Vec4(v) => format!("\"{}\", type=\"vec4\"", floats(v)),
+        Value::Id(id) => format!("\"{}\", type=\"id\"", id),
+        Value::Color(color) => {
+            format!(
+                "\"{}\", type=\"color\"",
+                floats(&color.to_vec4().into_array())
+            )
+        }
+        Value::Source(source) => {
+            let source = match source {
+                PixelSource::Off => "off".into(),
+                PixelSource::Terrain => "terrain".into(),
+                PixelSource::TileId(id) => format!("tile:{}", id),
+                PixelSource::MaterialId(id) => format!("material:{}", id),
+                PixelSource::ShaderId(id) => format!("shader:{}", id),
+                PixelSource::ShapeFXGraphId(id) => format!("shapefx:{}", id),
+                PixelSource::StaticTileIndex(index) => format!("static:{}", index),
+                PixelSource::DynamicTileIndex(index) => format!("dynamic:{}", index),
+                PixelSource::Color(color) => {
+                    format!("color:{}", floats(&color.to_vec4().into_array()))
+                }
+                PixelSource::Sequence(name) => format!("sequence:{}", name),
+                _ => return None,
+            };
+            format!("{}, type=\"source\"", quote(&source))
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lights_and_sky() {
+        let src = r##"
+# a lit room
+sky("#87ceeb")
+wall(4)
+turn_right()
+wall(4)
+move_to(2, 2)
+point_light("#ffcc88", 2.0, range=8, flicker=0.25)
+area_light("#ffffff", 1, 2, intensity=3)
+"##;
+
+        let mut script = MapScript::default();
+        let meta = script
+            .compile(src, &FxHashMap::default(), None, None, None)
+            .expect("compile mapscript");
+
+        assert_eq!(meta.map.linedefs.len(), 2);
+        assert_eq!(
+            meta.map.properties.get_vec3("sky_color"),
+            Some(hex_to_rgb_f32("#87ceeb"))
+        );
+
+        let lights = &meta.map.lights;
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights[0].light_type, LightType::Point);
+        assert_eq!(lights[0].get_end_distance(), 8.0);
+        assert_eq!(lights[0].get_flicker(), 0.25);
+        assert_eq!(lights[0].position(), Vec3::new(2.0, 0.5, 2.0));
+        assert_eq!(lights[1].light_type, LightType::Area);
+        assert_eq!(lights[1].get_intensity(), 3.0);
+        assert_eq!(lights[1].position().y, 2.0);
+
+        assert!(
+            script
+                .compile("point_light(2)", &FxHashMap::default(), None, None, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn terrain_directives() {
+        let src = r#"
+terrain_height(0, 0, 2.0)
+terrain_noise(0, 0, 4, 4, height=3, step=2, seed=1)
+"#;
+        let meta = MapScript::default()
+            .compile(src, &FxHashMap::default(), None, None, None)
+            .expect("compile terrain");
+        assert!(
+            meta.map
+                .properties
+                .get_bool_default("terrain_enabled", false)
+        );
+
+        // One explicit control point and a 3x3 noise grid
+        let controls: Vec<_> = meta
+            .map
+            .vertices
+            .iter()
+            .filter(|v| v.properties.get_bool_default("terrain_control", false))
+            .collect();
+        assert_eq!(controls.len(), 10);
+        assert!(controls.iter().all(|v| v.z >= 0.0 && v.z <= 3.0));
+
+        assert!(
+            MapScript::default()
+                .compile(
+                    "terrain_noise(0, 0, 4, 4, step=0)",
+                    &FxHashMap::default(),
+                    None,
+                    None,
+                    None
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn include_files() {
+        let dir = std::env::temp_dir().join(format!("mapscript_include_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("rooms")).unwrap();
+        std::fs::write(
+            dir.join("rooms/room.rxm"),
+            "wall(2)\nturn_right()\nwall(2)\nmove_to(1, 1)\npoint_light(\"#ffffff\")\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("world.rxm"),
+            "include(\"rooms/room.rxm\", 10, 0)\ninclude(\"rooms/room.rxm\", 0, 10, rotation=90)\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("loop.rxm"), "include(\"loop.rxm\")\n").unwrap();
+
+        let mut script = MapScript::default();
+        let meta = script
+            .compile_file(&dir.join("world.rxm"), &FxHashMap::default())
+            .expect("compile includes");
+        assert_eq!(meta.map.linedefs.len(), 4);
+        assert_eq!(script.included_paths().len(), 2);
+
+        let lights = &meta.map.lights;
+        assert_eq!(lights[0].position(), Vec3::new(11.0, 0.5, 1.0));
+        assert!((lights[1].position() - Vec3::new(-1.0, 0.5, 11.0)).magnitude() < 0.001);
+
+        assert!(
+            script
+                .compile_file(&dir.join("loop.rxm"), &FxHashMap::default())
+                .is_err()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn variables_and_loops() {
+        let src = r#"
+# a colonnade of pillars
+spacing = 2
+for i in range(4):
+    move_to(i * spacing, (i + 1) % 2)
+    add_entity("pillar_" + str(i), "Pillar", "stone")
+for _ in range(2):
+    wall(spacing / 2)
+"#;
+        let meta = MapScript::default()
+            .compile(src, &FxHashMap::default(), None, None, None)
+            .expect("compile loops");
+
+        assert_eq!(meta.map.entities.len(), 4);
+        assert_eq!(meta.map.entities[3].position.x, 6.0);
+        assert_eq!(meta.map.entities[3].position.z, 0.0);
+        assert_eq!(
+            meta.map.entities[3].get_attr_string("name"),
+            Some("pillar_3".into())
+        );
+        assert_eq!(meta.map.linedefs.len(), 2);
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let src = r##"
+set_default("wall_height", 3)
+for _ in range(4):
+    wall(4)
+    turn_right()
+move_to(2, 2)
+point_light("#ff8000", 2, range=6)
+"##;
+        let mut script = MapScript::default();
+        let meta = script
+            .compile(src, &FxHashMap::default(), None, None, None)
+            .expect("compile room");
+        assert_eq!(meta.map.sectors.len(), 1);
+
+        let exported = MapScript::export(&meta.map);
+        let meta = script
+            .compile(&exported, &FxHashMap::default(), None, None, None)
+            .expect("compile export");
+        assert_eq!(meta.map.sectors[0].linedefs.len(), 4);
+        assert_eq!(
+            meta.map.linedefs[0]
+                .properties
+                .get_float_default("wall_height", 0.0),
+            3.0
+        );
+        assert_eq!(meta.map.lights.len(), 1);
+        assert_eq!(MapScript::export(&meta.map), exported);
+    }
+
+    #[test]
+    fn entities_and_items() {
+        let src = r#"
+move_to(3, 4)
+entity("Guard", name="Bob", orientation=90, health=20, speed=1.5, faction="town")
+item("Torch", x=1, y=2, height=0.5)
+"#;
+        let mut script = MapScript::default();
+        let meta = script
+            .compile(src, &FxHashMap::default(), None, None, None)
+            .expect("compile entities");
+
+        let guard = &meta.map.entities[0];
+        assert_eq!(guard.position, Vec3::new(3.0, 1.0, 4.0));
+        assert_eq!(guard.get_attr_string("class_name"), Some("Guard".into()));
+        assert_eq!(guard.get_attr_string("name"), Some("Bob".into()));
+        assert_eq!(guard.attributes.get("health"), Some(&Value::Int(20)));
+        assert_eq!(guard.attributes.get("speed"), Some(&Value::Float(1.5)));
+
+        let torch = &meta.map.items[0];
+        assert_eq!(torch.position, Vec3::new(1.0, 0.5, 2.0));
+        assert_eq!(torch.get_attr_string("name"), Some("Torch".into()));
+
+        let exported = MapScript::export(&meta.map);
+        let meta = script
+            .compile(&exported, &FxHashMap::default(), None, None, None)
+            .expect("compile exported entities");
+        assert_eq!(
+            meta.map.entities[0].attributes.get("faction"),
+            Some(&Value::Str("town".into()))
+        );
+        assert_eq!(MapScript::export(&meta.map), exported);
+    }
+}
@@ -1,4 +1,13 @@
-use crate::{ShapeFXGraph, Value, prelude::*};
+use crate::server::crafting::parse_recipes;
+use crate::server::cutscene::{Cutscene, parse_cutscenes};
+use crate::server::effect::parse_effects;
+use crate::server::loot::parse_loot_tables;
+use crate::server::quest::parse_quests;
+use crate::server::spawner::parse_spawners;
+use crate::{
+    BehaviorTree, BitmapFont, Dialogue, EffectDef, LootTable, Quest, Recipe, ShapeFXGraph,
+    SpawnerDef, Value, prelude::*,
+};
 use indexmap::IndexMap;
 use std::path::Path;
 use theframework::prelude::*;
@@ -14,6 +23,8 @@ pub struct Assets {
 
     pub tiles: IndexMap<Uuid, Tile>,
     pub materials: FxHashMap<Uuid, Tile>,
+    /// Rusteria shader materials, referenced by PixelSource::ShaderId.
+    pub shader_materials: FxHashMap<Uuid, ShaderMaterial>,
     pub textures: FxHashMap<String, Texture>,
 
     pub tile_list: Vec<Tile>,
@@ -37,6 +48,8 @@ pub struct Assets {
     pub atlas: Texture,
 
     pub fonts: FxHashMap<String, fontdue::Font>,
+    /// Bitmap fonts, used by widgets instead of a font of the same name.
+    pub bitmap_fonts: FxHashMap<String, BitmapFont>,
     pub palette: ThePalette,
 
     // The global render graph
@@ -44,6 +57,30 @@ pub struct Assets {
 
     /// A map of locale names to their translations.
     pub locales: FxHashMap<String, FxHashMap<String, String>>,
+
+    /// The dialogue trees by name.
+    pub dialogues: FxHashMap<String, Dialogue>,
+
+    /// The quest definitions by name.
+    pub quests: FxHashMap<String, Quest>,
+
+    /// The behavior trees by name.
+    pub behaviors: FxHashMap<String, BehaviorTree>,
+
+    /// The status effect definitions by name.
+    pub effects: FxHashMap<String, EffectDef>,
+
+    /// The crafting recipes by name.
+    pub recipes: FxHashMap<String, Recipe>,
+
+    /// The loot tables by name.
+    pub loot_tables: FxHashMap<String, LootTable>,
+
+    /// The entity spawners by name.
+    pub spawners: FxHashMap<String, SpawnerDef>,
+
+    /// The cutscenes by name.
+    pub cutscenes: FxHashMap<String, Cutscene>,
 }
 
 impl Default for Assets {
@@ -64,6 +101,7 @@ impl Assets {
             tile_list: vec![],
             tile_indices: FxHashMap::default(),
             materials: FxHashMap::default(),
+            shader_materials: FxHashMap::default(),
             screens: FxHashMap::default(),
             character_maps: FxHashMap::default(),
             entity_tiles: FxHashMap::default(),
@@ -72,15 +110,24 @@ impl Assets {
             config: String::new(),
             atlas: Texture::default(),
             fonts: FxHashMap::default(),
+            bitmap_fonts: FxHashMap::default(),
             palette: ThePalette::default(),
             global: ShapeFXGraph::default(),
             locales: FxHashMap::default(),
+            dialogues: FxHashMap::default(),
+            quests: FxHashMap::default(),
+            behaviors: FxHashMap::default(),
+            effects: FxHashMap::default(),
+            recipes: FxHashMap::default(),
+            loot_tables: FxHashMap::default(),
+            spawners: FxHashMap::default(),
+            cutscenes: FxHashMap::default(),
         }
     }
 
-    /// Reads all locale tables (locale_*) from the config file.
+    /// Reads all locale tables (locale_*) from the config file, merged into the translations
+    /// of their language.
     pub fn read_locales(&mut self) {
-        self.locales.clear();
         if let Ok(table) = self.config.parse::<Table>() {
             for (key, value) in table.iter() {
                 if let Some(locale_name) = key.strip_prefix("locale_") {
@@ -92,13 +139,124 @@ impl Assets {
                             }
                         }
                         // println!("Found locale: {} {:?}", locale_name, translations);
-                        self.locales.insert(locale_name.to_string(), translations);
+                        self.locales
+                            .entry(locale_name.to_string())
+                            .or_default()
+                            .extend(translations);
                     }
                 }
             }
         }
     }
 
+    /// Parse and add a string table of the language, merged into its translations. Nested
+    /// tables are flattened into dotted keys. See `src/server/CONFIG.md` for an example.
+    pub fn add_string_table(&mut self, language: &str, toml: &str) -> Result<(), String> {
+        fn flatten(prefix: &str, table: &Table, strings: &mut FxHashMap<String, String>) {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match value {
+                    toml::Value::String(text) => {
+                        strings.insert(key, text.clone());
+                    }
+                    toml::Value::Table(table) => flatten(&key, table, strings),
+                    _ => {}
+                }
+            }
+        }
+
+        let table: Table = toml
+            .parse()
+            .map_err(|err: toml::de::Error| format!("{}: {}", language, err))?;
+        flatten(
+            "",
+            &table,
+            self.locales.entry(language.to_string()).or_default(),
+        );
+        Ok(())
+    }
+
+    /// Translate the key into the language, falling back to English and then to the key
+    /// itself. `{name}` placeholders of the translation are replaced by the args.
+    pub fn tr(&self, language: &str, key: &str, args: &[(&str, &str)]) -> String {
+        let mut text = [language, "en"]
+            .iter()
+            .find_map(|language| self.locales.get(*language)?.get(key))
+            .cloned()
+            .unwrap_or_else(|| key.to_string());
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+
+    /// Parse and add a BMFont (.fnt text format) of the given name, the pages are the
+    /// textures of `BitmapFont::page_files` in order.
+    pub fn add_bitmap_font(
+        &mut self,
+        name: &str,
+        fnt: &str,
+        pages: Vec<Texture>,
+    ) -> Result<(), String> {
+        let font = BitmapFont::from_fnt(fnt, pages).map_err(|err| format!("{}: {}", name, err))?;
+        self.bitmap_fonts.insert(name.to_string(), font);
+        Ok(())
+    }
+
+    /// Parse and add the dialogue tree of the given name.
+    pub fn add_dialogue(&mut self, name: &str, toml: &str) -> Result<(), String> {
+        let dialogue = Dialogue::from_toml(toml).map_err(|err| format!("{}: {}", name, err))?;
+        self.dialogues.insert(name.to_string(), dialogue);
+        Ok(())
+    }
+
+    /// Parse and add the quests defined in the TOML source.
+    pub fn add_quests(&mut self, toml: &str) -> Result<(), String> {
+        self.quests.extend(parse_quests(toml)?);
+        Ok(())
+    }
+
+    /// Parse and add the behavior tree of the given name.
+    pub fn add_behavior(&mut self, name: &str, toml: &str) -> Result<(), String> {
+        let tree = BehaviorTree::from_toml(toml).map_err(|err| format!("{}: {}", name, err))?;
+        self.behaviors.insert(name.to_string(), tree);
+        Ok(())
+    }
+
+    /// Parse and add the status effects defined in the TOML source.
+    pub fn add_effects(&mut self, toml: &str) -> Result<(), String> {
+        self.effects.extend(parse_effects(toml)?);
+        Ok(())
+    }
+
+    /// Parse and add the crafting recipes defined in the TOML source.
+    pub fn add_recipes(&mut self, toml: &str) -> Result<(), String> {
+        self.recipes.extend(parse_recipes(toml)?);
+        Ok(())
+    }
+
+    /// Parse and add the loot tables defined in the TOML source.
+    pub fn add_loot_tables(&mut self, toml: &str) -> Result<(), String> {
+        self.loot_tables.extend(parse_loot_tables(toml)?);
+        Ok(())
+    }
+
+    /// Parse and add the entity spawners defined in the TOML source.
+    pub fn add_spawners(&mut self, toml: &str) -> Result<(), String> {
+        self.spawners.extend(parse_spawners(toml)?);
+        Ok(())
+    }
+
+    /// Parse and add the cutscenes defined in the TOML source.
+    pub fn add_cutscenes(&mut self, toml: &str) -> Result<(), String> {
+        self.cutscenes.extend(parse_cutscenes(toml)?);
+        Ok(())
+    }
+
     /// Clears the tile list.
     pub fn clean_tile_list(&mut self) {
         self.tile_list.clear();
@@ -173,6 +331,36 @@ impl Assets {
         self.materials = tiles;
     }
 
+    /// Register a shader material. Its shade function is baked into a tile of the tile_list
+    /// so the chunk builders can reference it like any other material.
+    pub fn add_shader_material(&mut self, material: ShaderMaterial) {
+        self.bake_shader_material(&material);
+        self.shader_materials.insert(material.id, material);
+    }
+
+    /// Re-bake all shader materials, i.e. after the tile list was cleared or the palette changed.
+    pub fn bake_shader_materials(&mut self) {
+        let materials: Vec<ShaderMaterial> = self.shader_materials.values().cloned().collect();
+        for material in &materials {
+            self.bake_shader_material(material);
+        }
+    }
+
+    fn bake_shader_material(&mut self, material: &ShaderMaterial) {
+        if let Some(texture) = material.bake(&self.palette) {
+            let mut tile = Tile::from_texture(texture);
+            tile.id = material.id;
+
+            if let Some(&index) = self.tile_indices.get(&tile.id) {
+                self.tile_list[index as usize] = tile;
+            } else {
+                let index = self.tile_list.len() as u16;
+                self.tile_indices.insert(tile.id, index);
+                self.tile_list.push(tile);
+            }
+        }
+    }
+
     /// Returns an FxHashSet of Uuid representing the blocking tiles and materials.
     pub fn blocking_tiles(&self) -> FxHashSet<Uuid> {
         let mut blocking_tiles = FxHashSet::default();
@@ -247,17 +435,17 @@ impl Assets {
         }
     }
 
-    /*
     /// Compile all source maps
+    #[cfg(feature = "mapscript")]
     pub fn compile_source_maps(&mut self) {
         let keys = self.map_sources.keys().cloned().collect::<Vec<String>>();
         for name in keys {
             let _ = self.compile_source_map(name);
         }
-    }*/
+    }
 
-    /*
     /// Compile the given source map
+    #[cfg(feature = "mapscript")]
     pub fn compile_source_map(&mut self, name: String) -> Result<(), Vec<String>> {
         if let Some(source) = self.map_sources.get(&name) {
             let mut mapscript = MapScript::default();
@@ -272,7 +460,7 @@ impl Assets {
             }
         }
         Ok(())
-    }*/
+    }
 
     /// Get a map by name.
     pub fn get_map(&self, name: &str) -> Option<&Map> {
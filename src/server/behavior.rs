@@ -0,0 +1,444 @@
+use crate::server::dialogue::check_condition;
+use crate::server::region::is_entity_dead_ctx;
+use crate::vm::VMValue;
+use crate::{EntityAction, RegionCtx};
+use theframework::prelude::*;
+
+/// A behavior tree, loaded from TOML. See `src/server/CONFIG.md` for an example.
+///
+/// The tree is ticked every game tick for each entity whose `behavior` attribute names it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BehaviorTree {
+    pub root: BehaviorNode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BehaviorNode {
+    /// Runs the children in order until one does not fail.
+    Selector { children: Vec<BehaviorNode> },
+    /// Runs the children in order until one does not succeed.
+    Sequence { children: Vec<BehaviorNode> },
+    /// Swaps success and failure of the child.
+    Inverter { child: Box<BehaviorNode> },
+    /// Succeeds when the child finished, regardless of its result.
+    Succeeder { child: Box<BehaviorNode> },
+    /// Fails for the given amount of ticks after the child succeeded.
+    Cooldown {
+        child: Box<BehaviorNode>,
+        ticks: i64,
+    },
+    /// Checks a condition on the attributes of the entity, i.e. `health < 5`.
+    Condition { condition: String },
+    /// Sends the event to the entity and succeeds.
+    Event { event: String },
+    /// Runs for the given amount of ticks.
+    Wait { ticks: i64 },
+    /// Walks around randomly, never finishes.
+    RandomWalk {
+        #[serde(default = "default_one")]
+        distance: f32,
+        #[serde(default = "default_one")]
+        speed: f32,
+        #[serde(default)]
+        max_sleep: i32,
+    },
+    /// Walks around randomly in the current sector, never finishes.
+    RandomWalkInSector {
+        #[serde(default = "default_one")]
+        distance: f32,
+        #[serde(default = "default_one")]
+        speed: f32,
+        #[serde(default)]
+        max_sleep: i32,
+    },
+    /// Moves to the center of the named sector.
+    Goto {
+        sector: String,
+        #[serde(default = "default_one")]
+        speed: f32,
+    },
+    /// Moves within the radius of the entity whose id is stored in the `target` attribute.
+    CloseIn {
+        target: String,
+        #[serde(default = "default_one")]
+        radius: f32,
+        #[serde(default = "default_one")]
+        speed: f32,
+    },
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BehaviorStatus {
+    Success,
+    Failure,
+    Running,
+}
+
+impl BehaviorTree {
+    /// Parse a behavior tree from TOML.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        toml::from_str(toml).map_err(|err| err.to_string())
+    }
+}
+
+impl BehaviorNode {
+    /// The number of nodes in the subtree, used to give each node a stable index.
+    fn size(&self) -> usize {
+        match self {
+            BehaviorNode::Selector { children } | BehaviorNode::Sequence { children } => {
+                1 + children.iter().map(|child| child.size()).sum::<usize>()
+            }
+            BehaviorNode::Inverter { child }
+            | BehaviorNode::Succeeder { child }
+            | BehaviorNode::Cooldown { child, .. } => 1 + child.size(),
+            _ => 1,
+        }
+    }
+}
+
+/// The state of the behavior tree of an entity. Nodes are identified by their pre-order index.
+#[derive(Debug, Clone, Default)]
+pub struct BehaviorRunner {
+    pub tree: String,
+    /// Action leaves whose action is in progress.
+    running: FxHashSet<usize>,
+    /// The end tick of running wait leaves.
+    waits: FxHashMap<usize, i64>,
+    /// The end tick of active cooldowns.
+    cooldowns: FxHashMap<usize, i64>,
+}
+
+/// Tick the behavior trees of all entities which have a `behavior` attribute.
+pub fn tick_behaviors(ctx: &mut RegionCtx) {
+    let entities: Vec<(u32, String)> = ctx
+        .map
+        .entities
+        .iter()
+        .filter_map(|entity| Some((entity.id, entity.get_attr_string("behavior")?)))
+        .collect();
+
+    ctx.behaviors
+        .retain(|id, _| entities.iter().any(|(entity_id, _)| entity_id == id));
+
+    for (id, name) in entities {
+        if is_entity_dead_ctx(ctx, id) {
+            continue;
+        }
+        let Some(tree) = ctx.assets.behaviors.get(&name).cloned() else {
+            continue;
+        };
+
+        let mut runner = ctx.behaviors.remove(&id).unwrap_or_default();
+        if runner.tree != name {
+            runner = BehaviorRunner {
+                tree: name,
+                ..Default::default()
+            };
+        }
+
+        let mut visited = vec![];
+        tick_node(&tree.root, 0, ctx, id, &mut runner, &mut visited);
+
+        // Stop the actions of leaves which were not reached anymore
+        let aborted: Vec<usize> = runner
+            .running
+            .iter()
+            .filter(|index| !visited.contains(index))
+            .copied()
+            .collect();
+        for index in &aborted {
+            runner.running.remove(index);
+        }
+        if !aborted.is_empty() && runner.running.is_empty() {
+            if let Some(entity) = ctx.get_entity_mut(id) {
+                entity.action = EntityAction::Off;
+            }
+        }
+        runner.waits.retain(|index, _| visited.contains(index));
+
+        ctx.behaviors.insert(id, runner);
+    }
+}
+
+fn tick_node(
+    node: &BehaviorNode,
+    index: usize,
+    ctx: &mut RegionCtx,
+    entity_id: u32,
+    runner: &mut BehaviorRunner,
+    visited: &mut Vec<usize>,
+) -> BehaviorStatus {
+    visited.push(index);
+    match node {
+        BehaviorNode::Selector { children } | BehaviorNode::Sequence { children } => {
+            let continue_on = if matches!(node, BehaviorNode::Selector { .. }) {
+                BehaviorStatus::Failure
+            } else {
+                BehaviorStatus::Success
+            };
+            let mut child_index = index + 1;
+            for child in children {
+                let status = tick_node(child, child_index, ctx, entity_id, runner, visited);
+                if status != continue_on {
+                    return status;
+                }
+                child_index += child.size();
+            }
+            continue_on
+        }
+        BehaviorNode::Inverter { child } => {
+            match tick_node(child, index + 1, ctx, entity_id, runner, visited) {
+                BehaviorStatus::Success => BehaviorStatus::Failure,
+                BehaviorStatus::Failure => BehaviorStatus::Success,
+                BehaviorStatus::Running => BehaviorStatus::Running,
+            }
+        }
+        BehaviorNode::Succeeder { child } => {
+            match tick_node(child, index + 1, ctx, entity_id, runner, visited) {
+                BehaviorStatus::Running => BehaviorStatus::Running,
+                _ => BehaviorStatus::Success,
+            }
+        }
+        BehaviorNode::Cooldown { child, ticks } => {
+            if runner
+                .cooldowns
+                .get(&index)
+                .is_some_and(|end| ctx.ticks < *end)
+            {
+                return BehaviorStatus::Failure;
+            }
+            let status = tick_node(child, index + 1, ctx, entity_id, runner, visited);
+            if status == BehaviorStatus::Success {
+                runner.cooldowns.insert(index, ctx.ticks + ticks);
+            }
+            status
+        }
+        BehaviorNode::Condition { condition } => {
+            let holds = ctx
+                .map
+                .entities
+                .iter()
+                .find(|entity| entity.id == entity_id)
+                .is_some_and(|entity| check_condition(condition, entity));
+            if holds {
+                BehaviorStatus::Success
+            } else {
+                BehaviorStatus::Failure
+            }
+        }
+        BehaviorNode::Event { event } => {
+            ctx.to_execute_entity
+                .push((entity_id, event.clone(), VMValue::zero()));
+            BehaviorStatus::Success
+        }
+        BehaviorNode::Wait { ticks } => {
+            let end = *runner.waits.entry(index).or_insert(ctx.ticks + ticks);
+            if ctx.ticks >= end {
+                runner.waits.remove(&index);
+                BehaviorStatus::Success
+            } else {
+                BehaviorStatus::Running
+            }
+        }
+        BehaviorNode::RandomWalk {
+            distance,
+            speed,
+            max_sleep,
+        } => run_action(index, ctx, entity_id, runner, |_| {
+            Some(EntityAction::RandomWalk(
+                *distance,
+                *speed,
+                *max_sleep,
+                0,
+                vek::Vec2::zero(),
+            ))
+        }),
+        BehaviorNode::RandomWalkInSector {
+            distance,
+            speed,
+            max_sleep,
+        } => run_action(index, ctx, entity_id, runner, |_| {
+            Some(EntityAction::RandomWalkInSector(
+                *distance,
+                *speed,
+                *max_sleep,
+                0,
+                vek::Vec2::zero(),
+            ))
+        }),
+        BehaviorNode::Goto { sector, speed } => run_action(index, ctx, entity_id, runner, |ctx| {
+            let coord = ctx
+                .map
+                .sectors
+                .iter()
+                .find(|s| s.name == *sector)
+                .and_then(|s| s.center(&ctx.map))?;
+            Some(EntityAction::Goto(coord, *speed))
+        }),
+        BehaviorNode::CloseIn {
+            target,
+            radius,
+            speed,
+        } => {
+            let target_id = ctx
+                .map
+                .entities
+                .iter()
+                .find(|entity| entity.id == entity_id)
+                .and_then(|entity| entity.attributes.get(target))
+                .and_then(|value| value.to_f32())
+                .map(|value| value as u32);
+            let Some(target_id) = target_id else {
+                return BehaviorStatus::Failure;
+            };
+            let alive = ctx.map.entities.iter().any(|entity| entity.id == target_id)
+                && !is_entity_dead_ctx(ctx, target_id);
+            if !alive {
+                return BehaviorStatus::Failure;
+            }
+            run_action(index, ctx, entity_id, runner, |_| {
+                Some(EntityAction::CloseIn(target_id, *radius, *speed))
+            })
+        }
+    }
+}
+
+/// Start the action of a leaf and run until the entity finished it, i.e. its action is off.
+/// Fails if the action cannot be created.
+fn run_action(
+    index: usize,
+    ctx: &mut RegionCtx,
+    entity_id: u32,
+    runner: &mut BehaviorRunner,
+    action: impl FnOnce(&RegionCtx) -> Option<EntityAction>,
+) -> BehaviorStatus {
+    if runner.running.contains(&index) {
+        let finished = ctx
+            .map
+            .entities
+            .iter()
+            .find(|entity| entity.id == entity_id)
+            .is_none_or(|entity| entity.action == EntityAction::Off);
+        if finished {
+            runner.running.remove(&index);
+            return BehaviorStatus::Success;
+        }
+        return BehaviorStatus::Running;
+    }
+
+    let Some(action) = action(ctx) else {
+        return BehaviorStatus::Failure;
+    };
+    let Some(entity) = ctx.get_entity_mut(entity_id) else {
+        return BehaviorStatus::Failure;
+    };
+    entity.action = action;
+    runner.running.insert(index);
+    BehaviorStatus::Running
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entity, Value};
+
+    const HUNTER: &str = r#"
+        [root]
+        type = "selector"
+
+        [[root.children]]
+        type = "sequence"
+        children = [
+            { type = "condition", condition = "target > 0" },
+            { type = "close_in", target = "target", radius = 1.0, speed = 1.5 },
+            { type = "event", event = "attack" },
+        ]
+
+        [[root.children]]
+        type = "random_walk"
+        distance = 2.0
+    "#;
+
+    const SHOUTER: &str = r#"
+        [root]
+        type = "selector"
+        children = [
+            { type = "cooldown", ticks = 4, child = { type = "event", event = "shout" } },
+            { type = "wait", ticks = 2 },
+        ]
+    "#;
+
+    fn region(name: &str, toml: &str) -> RegionCtx {
+        let mut ctx = RegionCtx::default();
+        ctx.assets.add_behavior(name, toml).unwrap();
+        for id in [1, 2] {
+            let mut entity = Entity::new();
+            entity.id = id;
+            entity.set_attribute("target", Value::Int(0));
+            ctx.map.entities.push(entity);
+        }
+        ctx.map.entities[0].set_attribute("behavior", Value::Str(name.into()));
+        ctx
+    }
+
+    #[test]
+    fn actions() {
+        let mut ctx = region("hunter", HUNTER);
+
+        // Without a target the entity walks around
+        tick_behaviors(&mut ctx);
+        assert!(matches!(
+            ctx.map.entities[0].action,
+            EntityAction::RandomWalk(..)
+        ));
+
+        // A target aborts the walk and the entity closes in
+        ctx.map.entities[0].set_attribute("target", Value::Int(2));
+        tick_behaviors(&mut ctx);
+        assert_eq!(
+            ctx.map.entities[0].action,
+            EntityAction::CloseIn(2, 1.0, 1.5)
+        );
+        tick_behaviors(&mut ctx);
+        assert!(ctx.to_execute_entity.is_empty());
+
+        // Once the action finished the sequence continues
+        ctx.map.entities[0].action = EntityAction::Off;
+        tick_behaviors(&mut ctx);
+        assert_eq!(ctx.to_execute_entity.len(), 1);
+        assert_eq!(ctx.to_execute_entity[0].1, "attack");
+
+        // Dead targets are not followed
+        ctx.map.entities[1].set_attribute("mode", Value::Str("dead".into()));
+        tick_behaviors(&mut ctx);
+        assert!(matches!(
+            ctx.map.entities[0].action,
+            EntityAction::RandomWalk(..)
+        ));
+    }
+
+    #[test]
+    fn cooldown_and_wait() {
+        let mut ctx = region("shouter", SHOUTER);
+        let mut shouts = vec![];
+        for tick in 0..9 {
+            ctx.ticks = tick;
+            tick_behaviors(&mut ctx);
+            if !ctx.to_execute_entity.is_empty() {
+                shouts.push(tick);
+                ctx.to_execute_entity.clear();
+            }
+        }
+        assert_eq!(shouts, vec![0, 4, 8]);
+
+        // The runner is dropped with the behavior attribute
+        ctx.map.entities[0].attributes.remove("behavior");
+        tick_behaviors(&mut ctx);
+        assert!(ctx.behaviors.is_empty());
+    }
+}
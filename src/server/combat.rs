@@ -0,0 +1,239 @@
+use crate::server::follow::send_party_aggro;
+use crate::vm::VMValue;
+use crate::{Entity, RegionCtx, RegionMessage};
+use rand::Rng;
+use theframework::prelude::*;
+
+/// The combat rules, read from the `[combat]` table of the game config. See `src/server/CONFIG.md`
+/// for an example.
+///
+/// An attacker with a `damage` attribute uses it as its own formula, the damage type is taken
+/// from its `damage_type` attribute.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CombatRules {
+    pub attack: String,
+    pub defense: String,
+    pub hit_die: u32,
+    pub critical: u32,
+    pub critical_multiplier: f32,
+    pub damage: String,
+    pub armor: String,
+    pub resistance: String,
+    pub min_damage: i32,
+    pub cooldown: i64,
+}
+
+impl Default for CombatRules {
+    fn default() -> Self {
+        Self {
+            attack: String::new(),
+            defense: String::new(),
+            hit_die: 20,
+            critical: 20,
+            critical_multiplier: 2.0,
+            damage: "1".into(),
+            armor: String::new(),
+            resistance: "RES_".into(),
+            min_damage: 1,
+            cooldown: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CombatEventKind {
+    Hit,
+    Miss,
+    Kill,
+}
+
+/// A combat event, sent to the client for feedback like floating damage numbers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CombatEvent {
+    pub region: u32,
+    pub kind: CombatEventKind,
+    pub attacker: u32,
+    pub target: u32,
+    pub damage: i32,
+    pub critical: bool,
+}
+
+/// The outcome of an attack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttackResult {
+    Hit {
+        damage: i32,
+        critical: bool,
+    },
+    Miss,
+    /// The attacker is still on cooldown, the target is unknown or dead.
+    Invalid,
+}
+
+/// Evaluate a damage formula like `2d6 + STR - 1` on the attributes of the entity.
+/// Unknown attributes count as zero.
+pub fn evaluate_formula(formula: &str, entity: &Entity, rng: &mut impl Rng) -> i32 {
+    let mut total = 0;
+    let mut sign = 1;
+
+    let spaced = formula.replace('+', " + ").replace('-', " - ");
+    for token in spaced.split_whitespace() {
+        match token {
+            "+" => sign = 1,
+            "-" => sign = -sign,
+            _ => {
+                let value = if let Ok(value) = token.parse::<i32>() {
+                    value
+                } else if let Some((count, sides)) =
+                    token.split_once(['d', 'D']).and_then(|(count, sides)| {
+                        let count = if count.is_empty() {
+                            Some(1)
+                        } else {
+                            count.parse::<u32>().ok()
+                        };
+                        Some((count?, sides.parse::<u32>().ok()?))
+                    })
+                {
+                    (0..count)
+                        .map(|_| rng.random_range(1..=sides.max(1)) as i32)
+                        .sum()
+                } else {
+                    entity
+                        .attributes
+                        .get(token)
+                        .and_then(|v| v.to_f32())
+                        .unwrap_or(0.0) as i32
+                };
+                total += sign * value;
+                sign = 1;
+            }
+        }
+    }
+    total
+}
+
+fn attribute(entity: &Entity, key: &str) -> i32 {
+    if key.is_empty() {
+        return 0;
+    }
+    entity
+        .attributes
+        .get(key)
+        .and_then(|v| v.to_f32())
+        .unwrap_or(0.0) as i32
+}
+
+/// Resolve an attack of the attacker on the target. Hits send the "take_damage" event to the
+/// target, the attacker receives a "hit" or "miss" event. The parties of both share the aggro.
+pub fn attack(ctx: &mut RegionCtx, attacker_id: u32, target_id: u32) -> AttackResult {
+    if ctx
+        .combat_cooldowns
+        .get(&attacker_id)
+        .is_some_and(|tick| ctx.ticks < *tick)
+    {
+        return AttackResult::Invalid;
+    }
+
+    let find = |id: u32| ctx.map.entities.iter().find(|entity| entity.id == id);
+    let (Some(attacker), Some(target)) = (find(attacker_id), find(target_id)) else {
+        return AttackResult::Invalid;
+    };
+    if target.attributes.get_str_default("mode", "active".into()) == "dead" {
+        return AttackResult::Invalid;
+    }
+
+    let rules = &ctx.combat;
+    let rng = &mut ctx.rng;
+
+    let roll = rng.random_range(1..=rules.hit_die.max(1));
+    let critical = roll >= rules.critical;
+    let hit = critical
+        || (roll > 1
+            && roll as i32 + attribute(attacker, &rules.attack)
+                >= attribute(target, &rules.defense));
+
+    let result = if hit {
+        let formula = attacker
+            .get_attr_string("damage")
+            .unwrap_or(rules.damage.clone());
+        let mut damage = evaluate_formula(&formula, attacker, rng) as f32;
+        if critical {
+            damage *= rules.critical_multiplier;
+        }
+        damage -= attribute(target, &rules.armor) as f32;
+
+        let damage_type = attacker
+            .get_attr_string("damage_type")
+            .unwrap_or("physical".into());
+        let resistance = attribute(target, &format!("{}{}", rules.resistance, damage_type));
+        damage *= 1.0 - resistance.clamp(0, 100) as f32 / 100.0;
+
+        AttackResult::Hit {
+            damage: (damage.round() as i32).max(rules.min_damage),
+            critical,
+        }
+    } else {
+        AttackResult::Miss
+    };
+
+    if rules.cooldown > 0 {
+        ctx.combat_cooldowns
+            .insert(attacker_id, ctx.ticks + rules.cooldown);
+    }
+
+    match result {
+        AttackResult::Hit { damage, critical } => {
+            ctx.to_execute_entity.push((
+                target_id,
+                "take_damage".into(),
+                VMValue::new(attacker_id as f32, damage as f32, 0.0),
+            ));
+            ctx.to_execute_entity.push((
+                attacker_id,
+                "hit".into(),
+                VMValue::new(target_id as f32, damage as f32, critical as i32 as f32),
+            ));
+            send_combat_event(
+                ctx,
+                CombatEventKind::Hit,
+                attacker_id,
+                target_id,
+                damage,
+                critical,
+            );
+        }
+        _ => {
+            ctx.to_execute_entity.push((
+                attacker_id,
+                "miss".into(),
+                VMValue::broadcast(target_id as f32),
+            ));
+            send_combat_event(ctx, CombatEventKind::Miss, attacker_id, target_id, 0, false);
+        }
+    }
+    send_party_aggro(ctx, attacker_id, target_id);
+
+    result
+}
+
+/// Send a combat event to the server.
+pub fn send_combat_event(
+    ctx: &RegionCtx,
+    kind: CombatEventKind,
+    attacker: u32,
+    target: u32,
+    damage: i32,
+    critical: bool,
+) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Combat(CombatEvent {
+            region: ctx.region_id,
+            kind,
+            attacker,
+            target,
+            damage,
+            critical,
+        }));
+    }
+}
@@ -0,0 +1,374 @@
+use crate::server::region::with_regionctx;
+use crate::{Entity, RegionCtx, RegionMessage, Server, Value};
+use theframework::prelude::*;
+use vek::{Vec2, Vec3};
+
+const HELP: &str = "\
+help                                  List the commands
+regions                               List the regions
+inspect <region>                      Show the state of a region
+teleport <entity> <x> <y>             Move the entity to the position
+teleport <entity> <sector> [region]   Move the entity to the sector of its or another region
+spawn <class> <region> <x> <y>        Create an entity of the class
+give <entity> <item class>            Add an item to the inventory of the entity
+set <entity> <attribute> <value>      Set an attribute of the entity
+time [HH:MM]                          Show or set the time of all regions
+pause / resume                        Pause or resume all regions
+
+Entities are given by id or name, `player` is the first player.";
+
+/// Split the command line into words, double quotes group words.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Parse a console value: booleans, integers, floats, otherwise a string.
+fn parse_value(value: &str) -> Value {
+    if let Ok(value) = value.parse::<bool>() {
+        Value::Bool(value)
+    } else if let Ok(value) = value.parse::<i32>() {
+        Value::Int(value)
+    } else if let Ok(value) = value.parse::<f32>() {
+        Value::Float(value)
+    } else {
+        Value::Str(value.to_string())
+    }
+}
+
+fn parse_coord(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .map_err(|_| format!("Invalid coordinate '{}'.", value))
+}
+
+/// Find the entity in the region by id or name, `player` matches the first player.
+fn find_entity(ctx: &RegionCtx, reference: &str) -> Option<u32> {
+    let id = reference.parse::<u32>().ok();
+    ctx.map
+        .entities
+        .iter()
+        .find(|entity| {
+            Some(entity.id) == id
+                || entity
+                    .get_attr_string("name")
+                    .is_some_and(|name| name.eq_ignore_ascii_case(reference))
+        })
+        .or_else(|| {
+            (reference == "player")
+                .then(|| ctx.map.entities.iter().find(|entity| entity.is_player()))
+                .flatten()
+        })
+        .map(|entity| entity.id)
+}
+
+fn format_minutes(ticks: i64, ticks_per_minute: u32) -> String {
+    let minutes = (ticks / ticks_per_minute.max(1) as i64).rem_euclid(24 * 60);
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+impl Server {
+    /// Execute an admin command like `give player Sword` and return its output. The commands
+    /// are dispatched into the region instances, see `help` for the list.
+    pub fn execute_command(&mut self, line: &str) -> Result<String, String> {
+        let words = tokenize(line);
+        let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(HELP.to_string()),
+            ["regions"] => Ok(self.console_regions()),
+            ["inspect", region] => {
+                let region_id = self.console_region(region)?;
+                with_regionctx(region_id, |ctx| {
+                    let players: Vec<String> = ctx
+                        .map
+                        .entities
+                        .iter()
+                        .filter(|entity| entity.is_player())
+                        .map(|entity| format!("{} ({})", ctx.get_entity_name(entity.id), entity.id))
+                        .collect();
+                    format!(
+                        "{} ({})\nTicks: {}, time: {}{}\nWeather: {}\nEntities: {}, items: {}, projectiles: {}\nScheduled events: {}\nPlayers: {}",
+                        region,
+                        region_id,
+                        ctx.ticks,
+                        format_minutes(ctx.ticks, ctx.ticks_per_minute),
+                        if ctx.paused { " (paused)" } else { "" },
+                        ctx.weather.state.kind.name(),
+                        ctx.map.entities.len(),
+                        ctx.map.items.len(),
+                        ctx.projectiles.len(),
+                        ctx.schedule.len(),
+                        players.join(", ")
+                    )
+                })
+                .ok_or("Region is not running.".to_string())
+            }
+            ["teleport", entity, x, y] if x.parse::<f32>().is_ok() => {
+                let position = Vec2::new(parse_coord(x)?, parse_coord(y)?);
+                self.console_entity(entity, |ctx, id| {
+                    if let Some(entity) = ctx.get_entity_mut(id) {
+                        entity.set_pos_xz(position);
+                    }
+                    ctx.check_player_for_section_change_id(id);
+                    Ok(format!("Teleported to {}, {}.", position.x, position.y))
+                })
+            }
+            ["teleport", entity, sector] => self.console_entity(entity, |ctx, id| {
+                let center = ctx
+                    .map
+                    .sectors
+                    .iter()
+                    .find(|s| s.name == *sector)
+                    .and_then(|s| s.center(&ctx.map))
+                    .ok_or(format!("Unknown sector '{}'.", sector))?;
+                if let Some(entity) = ctx.get_entity_mut(id) {
+                    entity.set_pos_xz(center);
+                }
+                ctx.check_player_for_section_change_id(id);
+                Ok(format!("Teleported to '{}'.", sector))
+            }),
+            ["teleport", entity, sector, region] => {
+                if !self.region_name_id_map.contains_key(*region) {
+                    return Err(format!("Unknown region '{}'.", region));
+                }
+                self.console_entity(entity, |ctx, id| {
+                    let index = ctx
+                        .map
+                        .entities
+                        .iter()
+                        .position(|e| e.id == id)
+                        .ok_or("Unknown entity.".to_string())?;
+                    let removed = ctx.map.entities.remove(index);
+                    ctx.entity_classes.remove(&removed.id);
+                    if let Some(sender) = ctx.from_sender.get() {
+                        _ = sender.send(RegionMessage::TransferEntity(
+                            ctx.region_id,
+                            removed,
+                            region.to_string(),
+                            sector.to_string(),
+                        ));
+                    }
+                    Ok(format!("Transferred to '{}' in '{}'.", sector, region))
+                })
+            }
+            ["spawn", class, region, x, y] => {
+                let region_id = self.console_region(region)?;
+                let position = Vec3::new(parse_coord(x)?, 1.0, parse_coord(y)?);
+                let known =
+                    with_regionctx(region_id, |ctx| ctx.assets.entities.contains_key(*class))
+                        .unwrap_or(false);
+                if !known {
+                    return Err(format!("Unknown entity class '{}'.", class));
+                }
+                let mut entity = Entity::default();
+                entity.set_position(position);
+                entity.set_attribute("name", Value::Str(class.to_string()));
+                entity.set_attribute("class_name", Value::Str(class.to_string()));
+                self.send_to_region(region_id, RegionMessage::CreateEntity(region_id, entity));
+                Ok(format!("Spawned '{}' in '{}'.", class, region))
+            }
+            ["give", entity, class] => self.console_entity(entity, |ctx, id| {
+                let class_name = ctx
+                    .assets
+                    .items
+                    .keys()
+                    .find(|name| name.eq_ignore_ascii_case(class))
+                    .cloned()
+                    .ok_or(format!("Unknown item class '{}'.", class))?;
+                let item = ctx
+                    .create_item(class_name.clone())
+                    .ok_or(format!("Unknown item class '{}'.", class))?;
+                ctx.get_entity_mut(id)
+                    .ok_or("Unknown entity.".to_string())?
+                    .add_item(item)?;
+                Ok(format!("Gave '{}'.", class_name))
+            }),
+            ["set", entity, attribute, value] => self.console_entity(entity, |ctx, id| {
+                let value = parse_value(value);
+                let entity = ctx
+                    .get_entity_mut(id)
+                    .ok_or("Unknown entity.".to_string())?;
+                entity.set_attribute(attribute, value.clone());
+                Ok(format!("{} = {}", attribute, value))
+            }),
+            ["time"] => Ok(self
+                .console_region_ids()
+                .into_iter()
+                .filter_map(|(name, id)| {
+                    with_regionctx(id, |ctx| {
+                        format!(
+                            "{}: {}",
+                            name,
+                            format_minutes(ctx.ticks, ctx.ticks_per_minute)
+                        )
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            ["time", time] => {
+                let minute = time
+                    .split_once(':')
+                    .and_then(|(h, m)| Some((h.parse::<i64>().ok()?, m.parse::<i64>().ok()?)))
+                    .filter(|(h, m)| (0..24).contains(h) && (0..60).contains(m))
+                    .map(|(h, m)| h * 60 + m)
+                    .ok_or(format!("Invalid time '{}'.", time))?;
+                for (_, id) in self.console_region_ids() {
+                    let time = with_regionctx(id, |ctx| {
+                        let per_day = 24 * 60 * ctx.ticks_per_minute as i64;
+                        let day = ctx.ticks.div_euclid(per_day);
+                        let ticks = day * per_day + minute * ctx.ticks_per_minute as i64;
+                        TheTime::from_ticks(ticks, ctx.ticks_per_minute)
+                    });
+                    if let Some(time) = time {
+                        self.send_to_region(id, RegionMessage::Time(id, time));
+                    }
+                }
+                Ok(format!("Time set to {}.", time))
+            }
+            ["pause"] => {
+                self.pause();
+                Ok("Paused.".into())
+            }
+            ["resume"] => {
+                self.continue_instances();
+                Ok("Resumed.".into())
+            }
+            _ => Err(format!("Unknown command '{}', try 'help'.", line.trim())),
+        }
+    }
+
+    /// The regions sorted by id.
+    fn console_region_ids(&self) -> Vec<(String, u32)> {
+        let mut regions: Vec<(String, u32)> = self
+            .region_name_id_map
+            .iter()
+            .map(|(name, id)| (name.clone(), *id))
+            .collect();
+        regions.sort_by_key(|(_, id)| *id);
+        regions
+    }
+
+    fn console_regions(&self) -> String {
+        self.console_region_ids()
+            .into_iter()
+            .map(|(name, id)| {
+                let counts =
+                    with_regionctx(id, |ctx| (ctx.map.entities.len(), ctx.map.items.len()))
+                        .unwrap_or_default();
+                format!(
+                    "{} ({}): {} entities, {} items",
+                    name, id, counts.0, counts.1
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn console_region(&self, name: &str) -> Result<u32, String> {
+        self.region_name_id_map
+            .get(name)
+            .copied()
+            .ok_or(format!("Unknown region '{}'.", name))
+    }
+
+    /// Find the entity in all regions and run the command in its region.
+    fn console_entity(
+        &self,
+        reference: &str,
+        f: impl FnOnce(&mut RegionCtx, u32) -> Result<String, String>,
+    ) -> Result<String, String> {
+        for (_, region_id) in self.console_region_ids() {
+            let found = with_regionctx(region_id, |ctx| find_entity(ctx, reference)).flatten();
+            if let Some(id) = found {
+                return with_regionctx(region_id, |ctx| f(ctx, id))
+                    .unwrap_or(Err("Region is not running.".into()));
+            }
+        }
+        Err(format!("Unknown entity '{}'.", reference))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::region::{get_regionctx, register_regionctx, unregister_regionctx};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn words_and_values() {
+        assert_eq!(
+            tokenize("  set player  name \"Old Tom\" "),
+            vec!["set", "player", "name", "Old Tom"]
+        );
+        assert_eq!(parse_value("true"), Value::Bool(true));
+        assert_eq!(parse_value("-3"), Value::Int(-3));
+        assert_eq!(parse_value("1.5"), Value::Float(1.5));
+        assert_eq!(parse_value("Tom"), Value::Str("Tom".into()));
+        assert_eq!(format_minutes(4 * (25 * 60 + 5), 4), "01:05");
+    }
+
+    #[test]
+    fn commands() {
+        let region_id = 41750;
+        let mut ctx = RegionCtx {
+            ticks_per_minute: 4,
+            ..Default::default()
+        };
+        for (id, name, player) in [(1, "Guard", false), (2, "Hero", true)] {
+            let mut entity = Entity::new();
+            entity.id = id;
+            entity.set_attribute("name", Value::Str(name.into()));
+            entity.set_attribute("player", Value::Bool(player));
+            ctx.map.entities.push(entity);
+        }
+        register_regionctx(region_id, Arc::new(Mutex::new(ctx)));
+        let mut server = Server::default();
+        server.region_name_id_map.insert("Town".into(), region_id);
+
+        assert_eq!(
+            server.execute_command("regions"),
+            Ok("Town (41750): 2 entities, 0 items".into())
+        );
+        assert_eq!(
+            server.execute_command("set player level 3"),
+            Ok("level = 3".into())
+        );
+        assert_eq!(
+            server.execute_command("set guard mood angry"),
+            Ok("mood = angry".into())
+        );
+        assert!(server.execute_command("set Thief mood angry").is_err());
+        assert!(server.execute_command("inspect Castle").is_err());
+        assert!(server.execute_command("dance").is_err());
+
+        let ctx = get_regionctx(region_id).unwrap();
+        unregister_regionctx(region_id);
+        let ctx = ctx.lock().unwrap();
+        assert_eq!(
+            ctx.map.entities[1].attributes.get("level"),
+            Some(&Value::Int(3))
+        );
+        assert_eq!(
+            ctx.map.entities[0].get_attr_string("mood"),
+            Some("angry".to_string())
+        );
+    }
+}
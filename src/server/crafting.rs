@@ -0,0 +1,175 @@
+use crate::vm::VMValue;
+use crate::{Entity, RegionCtx, RegionMessage};
+use indexmap::IndexMap;
+use theframework::prelude::*;
+
+/// A crafting recipe, loaded from TOML. A file can define several recipes. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// Inputs are consumed, tools have to be in the inventory or equipped and skills are minimum
+/// attribute values of the crafter.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Recipe {
+    /// The item class of the result.
+    pub output: String,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default)]
+    pub inputs: IndexMap<String, u32>,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub skills: IndexMap<String, f32>,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+/// Parse the recipes of a TOML file.
+pub fn parse_recipes(toml: &str) -> Result<FxHashMap<String, Recipe>, String> {
+    toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+fn class_name(item: &crate::Item) -> Option<String> {
+    item.get_attr_string("class_name")
+}
+
+/// Check if the entity can craft the recipe.
+pub fn check_recipe(recipe: &Recipe, entity: &Entity) -> Result<(), String> {
+    for (skill, level) in &recipe.skills {
+        let value = entity
+            .attributes
+            .get(skill)
+            .and_then(|v| v.to_f32())
+            .unwrap_or(0.0);
+        if value < *level {
+            return Err(format!("Requires {} {}.", skill, level));
+        }
+    }
+
+    for tool in &recipe.tools {
+        let has_tool = entity
+            .iter_inventory()
+            .map(|(_, item)| item)
+            .chain(entity.equipped.values())
+            .any(|item| class_name(item).as_deref() == Some(tool.as_str()));
+        if !has_tool {
+            return Err(format!("Requires a {}.", tool));
+        }
+    }
+
+    let mut consumed = 0;
+    for (input, count) in &recipe.inputs {
+        let available = entity
+            .iter_inventory()
+            .filter(|(_, item)| class_name(item).as_deref() == Some(input.as_str()))
+            .count() as u32;
+        if available < *count {
+            return Err(format!("Requires {} {} (has {}).", count, input, available));
+        }
+        consumed += *count as usize;
+    }
+
+    let free = entity
+        .inventory
+        .iter()
+        .filter(|slot| slot.is_none())
+        .count();
+    if free + consumed < recipe.count as usize {
+        return Err("Inventory full.".into());
+    }
+
+    Ok(())
+}
+
+/// Craft the recipe for the entity: consume the inputs and add the output items to the
+/// inventory. Sends a "crafted" event with the output class to the entity, or a "craft_failed"
+/// event and message with the reason. Returns the ids of the new items.
+pub fn craft(ctx: &mut RegionCtx, entity_id: u32, name: &str) -> Result<Vec<u32>, String> {
+    let result = try_craft(ctx, entity_id, name);
+    match &result {
+        Ok(_) => {
+            let output = ctx
+                .assets
+                .recipes
+                .get(name)
+                .map(|recipe| recipe.output.clone())
+                .unwrap_or_default();
+            ctx.to_execute_entity
+                .push((entity_id, "crafted".into(), VMValue::from(output)));
+        }
+        Err(reason) => {
+            ctx.to_execute_entity.push((
+                entity_id,
+                "craft_failed".into(),
+                VMValue::from(reason.clone()),
+            ));
+            if let Some(sender) = ctx.from_sender.get() {
+                _ = sender.send(RegionMessage::Message(
+                    ctx.region_id,
+                    None,
+                    None,
+                    entity_id,
+                    reason.clone(),
+                    "craft".into(),
+                ));
+            }
+        }
+    }
+    result
+}
+
+fn try_craft(ctx: &mut RegionCtx, entity_id: u32, name: &str) -> Result<Vec<u32>, String> {
+    let recipe = ctx
+        .assets
+        .recipes
+        .get(name)
+        .cloned()
+        .ok_or(format!("Unknown recipe '{}'.", name))?;
+    if !ctx.assets.items.contains_key(&recipe.output) {
+        return Err(format!("Unknown item '{}'.", recipe.output));
+    }
+
+    let entity = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == entity_id)
+        .ok_or("Unknown entity.".to_string())?;
+    check_recipe(&recipe, entity)?;
+
+    // Consume the inputs
+    let to_consume: Vec<u32> = recipe
+        .inputs
+        .iter()
+        .flat_map(|(input, count)| {
+            entity
+                .iter_inventory()
+                .filter(|(_, item)| class_name(item).as_deref() == Some(input.as_str()))
+                .map(|(_, item)| item.id)
+                .take(*count as usize)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let items: Vec<_> = (0..recipe.count)
+        .filter_map(|_| ctx.create_item(recipe.output.clone()))
+        .collect();
+
+    let Some(entity) = ctx.get_entity_mut(entity_id) else {
+        return Err("Unknown entity.".into());
+    };
+    for id in to_consume {
+        entity.remove_item(id);
+    }
+
+    let mut ids = vec![];
+    for item in items {
+        let id = item.id;
+        if entity.add_item(item).is_ok() {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
@@ -0,0 +1,243 @@
+use crate::vm::VMValue;
+use crate::{EntityAction, RegionCtx, RegionMessage, Value};
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// A cutscene, loaded from TOML. A file can define several cutscenes. See `src/server/CONFIG.md`
+/// for an example.
+///
+/// Entities are referenced by their name, `player` is the entity which started the cutscene.
+/// The steps run one after the other, only `wait` pauses the sequence. While a cutscene with
+/// `lock_input` runs the actions of its player are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Cutscene {
+    #[serde(default)]
+    pub lock_input: bool,
+    #[serde(default)]
+    pub steps: Vec<CutsceneStep>,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CutsceneStep {
+    /// Walk the entity to the position.
+    Move {
+        entity: String,
+        to: [f32; 2],
+        #[serde(default = "default_speed")]
+        speed: f32,
+    },
+    /// Turn the entity to north, south, east or west.
+    Face { entity: String, direction: String },
+    /// Set the rig sequence of the entity (its `rig_sequence` attribute).
+    Sequence { entity: String, name: String },
+    /// Show a message of the entity to the player.
+    Message { entity: String, text: String },
+    /// Send a camera hint to the client of the player, i.e. "focus" on an entity or position.
+    Camera {
+        hint: String,
+        #[serde(default)]
+        entity: Option<String>,
+        #[serde(default)]
+        position: Option<[f32; 2]>,
+    },
+    /// Pause the cutscene for the given game ticks.
+    Wait { ticks: i64 },
+}
+
+/// Parse the cutscene definitions of a TOML file.
+pub fn parse_cutscenes(toml: &str) -> Result<FxHashMap<String, Cutscene>, String> {
+    toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+/// A camera hint for the client of a player, sent by cutscenes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CameraHint {
+    pub region: u32,
+    /// The player entity.
+    pub player: u32,
+    pub hint: String,
+    pub entity: Option<u32>,
+    pub position: Option<Vec2<f32>>,
+}
+
+/// A running cutscene.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActiveCutscene {
+    pub name: String,
+    pub cutscene: Cutscene,
+    pub player: u32,
+    pub step: usize,
+    /// The tick the next step runs.
+    pub resume_at: i64,
+}
+
+/// Start the cutscene for the player, a cutscene locking the input stops the player. Fails for
+/// unknown cutscenes or if the player already watches one.
+pub fn play_cutscene(ctx: &mut RegionCtx, player: u32, name: &str) -> Result<(), String> {
+    let cutscene = ctx
+        .assets
+        .cutscenes
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("Unknown cutscene '{}'.", name))?;
+    if ctx.cutscenes.iter().any(|active| active.player == player) {
+        return Err("A cutscene is already running.".into());
+    }
+    if cutscene.lock_input {
+        if let Some(entity) = ctx.get_entity_mut(player) {
+            entity.action = EntityAction::Off;
+        }
+    }
+    ctx.cutscenes.push(ActiveCutscene {
+        name: name.to_string(),
+        cutscene,
+        player,
+        step: 0,
+        resume_at: ctx.ticks,
+    });
+    ctx.to_execute_entity.push((
+        player,
+        "cutscene_started".into(),
+        VMValue::from(name.to_string()),
+    ));
+    Ok(())
+}
+
+/// True if the player watches a cutscene which locks the input.
+pub fn is_input_locked(ctx: &RegionCtx, player: u32) -> bool {
+    ctx.cutscenes
+        .iter()
+        .any(|active| active.player == player && active.cutscene.lock_input)
+}
+
+fn find_entity(ctx: &RegionCtx, name: &str, player: u32) -> Result<u32, String> {
+    if name == "player" {
+        return Ok(player);
+    }
+    ctx.map
+        .entities
+        .iter()
+        .find(|entity| entity.attributes.get_str("name") == Some(name))
+        .map(|entity| entity.id)
+        .ok_or_else(|| format!("Unknown entity '{}'.", name))
+}
+
+/// Run one step, returns the ticks to wait before the next one.
+fn run_step(ctx: &mut RegionCtx, step: &CutsceneStep, player: u32) -> Result<i64, String> {
+    match step {
+        CutsceneStep::Move { entity, to, speed } => {
+            let id = find_entity(ctx, entity, player)?;
+            if let Some(entity) = ctx.get_entity_mut(id) {
+                entity.action = EntityAction::Goto(Vec2::new(to[0], to[1]), *speed);
+            }
+        }
+        CutsceneStep::Face { entity, direction } => {
+            let id = find_entity(ctx, entity, player)?;
+            if let Some(entity) = ctx.get_entity_mut(id) {
+                match direction.as_str() {
+                    "north" => entity.face_north(),
+                    "south" => entity.face_south(),
+                    "east" => entity.face_east(),
+                    "west" => entity.face_west(),
+                    _ => return Err(format!("Unknown direction '{}'.", direction)),
+                }
+            }
+        }
+        CutsceneStep::Sequence { entity, name } => {
+            let id = find_entity(ctx, entity, player)?;
+            if let Some(entity) = ctx.get_entity_mut(id) {
+                entity.set_attribute("rig_sequence", Value::Str(name.clone()));
+            }
+        }
+        CutsceneStep::Message { entity, text } => {
+            let id = find_entity(ctx, entity, player)?;
+            if let Some(sender) = ctx.from_sender.get() {
+                _ = sender.send(RegionMessage::Message(
+                    ctx.region_id,
+                    Some(id),
+                    None,
+                    player,
+                    text.clone(),
+                    "cutscene".into(),
+                ));
+            }
+        }
+        CutsceneStep::Camera {
+            hint,
+            entity,
+            position,
+        } => {
+            let entity = match entity {
+                Some(name) => Some(find_entity(ctx, name, player)?),
+                None => None,
+            };
+            send_camera_hint(ctx, player, hint, entity, position.map(Vec2::from));
+        }
+        CutsceneStep::Wait { ticks } => return Ok(*ticks),
+    }
+    Ok(0)
+}
+
+fn send_camera_hint(
+    ctx: &RegionCtx,
+    player: u32,
+    hint: &str,
+    entity: Option<u32>,
+    position: Option<Vec2<f32>>,
+) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::CameraHint(CameraHint {
+            region: ctx.region_id,
+            player,
+            hint: hint.to_string(),
+            entity,
+            position,
+        }));
+    }
+}
+
+/// Advance the running cutscenes. Finished cutscenes send a `cutscene_ended` event to their
+/// player and a `reset` camera hint to its client. Cutscenes of players who left the region
+/// are dropped.
+pub fn tick_cutscenes(ctx: &mut RegionCtx) {
+    let mut cutscenes = std::mem::take(&mut ctx.cutscenes);
+    let mut errors = vec![];
+
+    cutscenes.retain_mut(|active| {
+        if !ctx
+            .map
+            .entities
+            .iter()
+            .any(|entity| entity.id == active.player)
+        {
+            return false;
+        }
+        while active.resume_at <= ctx.ticks {
+            let Some(step) = active.cutscene.steps.get(active.step).cloned() else {
+                ctx.to_execute_entity.push((
+                    active.player,
+                    "cutscene_ended".into(),
+                    VMValue::from(active.name.clone()),
+                ));
+                send_camera_hint(ctx, active.player, "reset", None, None);
+                return false;
+            };
+            active.step += 1;
+            match run_step(ctx, &step, active.player) {
+                Ok(ticks) => active.resume_at = ctx.ticks + ticks,
+                Err(err) => errors.push(format!("[warn] Cutscene '{}': {}", active.name, err)),
+            }
+        }
+        true
+    });
+
+    ctx.cutscenes = cutscenes;
+    for error in errors {
+        ctx.send_log_message(error);
+    }
+}
@@ -1,9 +1,9 @@
-use crate::{Entity, Item, Light, LightType, PixelSource, Value};
+use crate::{AttrSchemas, Entity, Item, Light, LightType, PixelSource, Value, ValueContainer};
 use theframework::prelude::*;
 use toml::Table;
 
-/// Apply toml data to an Entity.
-pub fn apply_entity_data(entity: &mut Entity, toml: &str) {
+/// Apply toml data to an Entity. The attributes are checked against the `[schema]` of the data.
+pub fn apply_entity_data(entity: &mut Entity, toml: &str) -> Result<(), String> {
     match toml.parse::<Table>() {
         Ok(map) => {
             for (attr, v) in map.iter() {
@@ -36,15 +36,20 @@ pub fn apply_entity_data(entity: &mut Entity, toml: &str) {
                     entity.set_attribute("light", crate::Value::Light(light));
                 }
             }
+
+            let (values, hidden) = apply_schema(&map, &entity.attributes)?;
+            for (key, value) in values {
+                entity.set_attribute(&key, value);
+            }
+            entity.hidden_attributes = hidden;
+            Ok(())
         }
-        Err(err) => {
-            println!("error {:?}", err);
-        }
+        Err(err) => Err(err.to_string()),
     }
 }
 
-/// Apply toml data to an Item.
-pub fn apply_item_data(item: &mut Item, toml: &str) {
+/// Apply toml data to an Item. The attributes are checked against the `[schema]` of the data.
+pub fn apply_item_data(item: &mut Item, toml: &str) -> Result<(), String> {
     match toml.parse::<Table>() {
         Ok(map) => {
             for (attr, v) in map.iter() {
@@ -124,10 +129,38 @@ pub fn apply_item_data(item: &mut Item, toml: &str) {
                     item.set_attribute("light", crate::Value::Light(light));
                 }
             }
+
+            let (values, hidden) = apply_schema(&map, &item.attributes)?;
+            for (key, value) in values {
+                item.set_attribute(&key, value);
+            }
+            item.hidden_attributes = hidden;
+            Ok(())
         }
-        Err(err) => {
-            println!("error {:?}", err);
-        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Check the attributes of the data against its schema. Returns the checked values including
+/// the defaults and the attributes which are not replicated, or the schema errors.
+fn apply_schema(
+    map: &Table,
+    attributes: &ValueContainer,
+) -> Result<(Vec<(String, Value)>, FxHashSet<String>), String> {
+    let schemas = AttrSchemas::from_table(map).map_err(|err| format!("Schema: {}", err))?;
+    if schemas.is_empty() {
+        return Ok((vec![], FxHashSet::default()));
+    }
+    let keys = map
+        .get("attributes")
+        .and_then(toml::Value::as_table)
+        .map(|values| values.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let (values, errors) = schemas.resolve(attributes, keys.iter());
+    if errors.is_empty() {
+        Ok((values, schemas.hidden()))
+    } else {
+        Err(errors.join(" "))
     }
 }
 
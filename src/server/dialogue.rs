@@ -0,0 +1,353 @@
+use crate::vm::VMValue;
+use crate::{Entity, RegionCtx, RegionMessage, Value};
+use indexmap::IndexMap;
+use theframework::prelude::*;
+
+/// A dialogue tree, loaded from TOML. See `src/server/CONFIG.md` for an example.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Dialogue {
+    /// The node the dialogue starts with.
+    pub start: String,
+    pub nodes: IndexMap<String, DialogueNode>,
+}
+
+/// A line of the speaker and the choices of the listener.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DialogueNode {
+    pub text: String,
+    /// The event sent to the speaker when the node is entered.
+    #[serde(default)]
+    pub event: Option<String>,
+    /// Without choices the dialogue ends after the node.
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+}
+
+/// A choice of the listener.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DialogueChoice {
+    pub text: String,
+    /// The next node, the dialogue ends if there is none.
+    #[serde(default)]
+    pub next: Option<String>,
+    /// Only offered if the condition on the attributes of the listener holds.
+    #[serde(default)]
+    pub condition: Option<String>,
+    /// The event sent to the speaker when the choice is selected.
+    #[serde(default)]
+    pub event: Option<String>,
+}
+
+impl Dialogue {
+    /// Parse a dialogue from TOML and validate its node references.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let dialogue: Dialogue = toml::from_str(toml).map_err(|err| err.to_string())?;
+        if !dialogue.nodes.contains_key(&dialogue.start) {
+            return Err(format!("Unknown start node '{}'.", dialogue.start));
+        }
+        for (name, node) in &dialogue.nodes {
+            for choice in &node.choices {
+                if let Some(next) = &choice.next {
+                    if !dialogue.nodes.contains_key(next) {
+                        return Err(format!("Node '{}': unknown next node '{}'.", name, next));
+                    }
+                }
+            }
+        }
+        Ok(dialogue)
+    }
+}
+
+/// Evaluate a condition like `gold >= 10`, `class_name == "Warrior"`, `has_key` or `!has_key`
+/// against the attributes of the entity.
+pub fn check_condition(condition: &str, entity: &Entity) -> bool {
+    let condition = condition.trim();
+    for op in ["==", "!=", ">=", "<=", ">", "<"] {
+        if let Some((key, expected)) = condition.split_once(op) {
+            let expected = expected.trim().trim_matches('"');
+            let Some(value) = entity.attributes.get(key.trim()) else {
+                return op == "!=";
+            };
+            return match (value.to_f32(), expected.parse::<f32>()) {
+                (Some(value), Ok(expected)) => match op {
+                    "==" => value == expected,
+                    "!=" => value != expected,
+                    ">=" => value >= expected,
+                    "<=" => value <= expected,
+                    ">" => value > expected,
+                    _ => value < expected,
+                },
+                _ => match op {
+                    "==" => value.to_string() == expected,
+                    "!=" => value.to_string() != expected,
+                    _ => false,
+                },
+            };
+        }
+    }
+    if let Some(key) = condition.strip_prefix('!') {
+        return !is_truthy(entity.attributes.get(key.trim()));
+    }
+    is_truthy(entity.attributes.get(condition))
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    match value {
+        Some(Value::Bool(value)) => *value,
+        Some(Value::Str(value)) => !value.is_empty(),
+        Some(value) => value.to_f32().is_none_or(|v| v != 0.0),
+        None => false,
+    }
+}
+
+/// The current node of a dialogue and its choices, sent to the client of the listener.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DialoguePrompt {
+    pub region: u32,
+    /// The speaker
+    pub from: u32,
+    /// The listener
+    pub to: u32,
+    pub text: String,
+    /// The index of the choice in the node and its text. Only choices whose condition
+    /// holds are offered.
+    pub choices: Vec<(u32, String)>,
+}
+
+/// A running dialogue of a listener.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActiveDialogue {
+    pub dialogue: String,
+    pub node: String,
+    pub speaker: u32,
+}
+
+/// Start the dialogue of the given name between the speaker and the listener.
+pub fn start_dialogue(
+    ctx: &mut RegionCtx,
+    name: &str,
+    speaker: u32,
+    listener: u32,
+) -> Result<(), String> {
+    let dialogue = ctx
+        .assets
+        .dialogues
+        .get(name)
+        .ok_or(format!("Unknown dialogue '{}'.", name))?;
+    let start = dialogue.start.clone();
+    ctx.active_dialogues.insert(
+        listener,
+        ActiveDialogue {
+            dialogue: name.to_string(),
+            node: String::new(),
+            speaker,
+        },
+    );
+    enter_node(ctx, listener, &start);
+    Ok(())
+}
+
+/// The listener selected the choice with the given index of the current node.
+pub fn select_dialogue_choice(ctx: &mut RegionCtx, listener: u32, index: u32) {
+    let Some(active) = ctx.active_dialogues.get(&listener).cloned() else {
+        return;
+    };
+    let Some(choice) = ctx
+        .assets
+        .dialogues
+        .get(&active.dialogue)
+        .and_then(|dialogue| dialogue.nodes.get(&active.node))
+        .and_then(|node| node.choices.get(index as usize))
+        .cloned()
+    else {
+        return;
+    };
+
+    // The attributes may have changed since the choice was offered
+    if let Some(condition) = &choice.condition {
+        let allowed = ctx
+            .map
+            .entities
+            .iter()
+            .find(|entity| entity.id == listener)
+            .is_some_and(|entity| check_condition(condition, entity));
+        if !allowed {
+            return;
+        }
+    }
+
+    if let Some(event) = choice.event {
+        ctx.to_execute_entity
+            .push((active.speaker, event, VMValue::broadcast(listener as f32)));
+    }
+
+    match choice.next {
+        Some(next) => enter_node(ctx, listener, &next),
+        None => end_dialogue(ctx, listener),
+    }
+}
+
+/// End the dialogue of the listener.
+pub fn end_dialogue(ctx: &mut RegionCtx, listener: u32) {
+    ctx.active_dialogues.remove(&listener);
+}
+
+fn enter_node(ctx: &mut RegionCtx, listener: u32, name: &str) {
+    let Some(active) = ctx.active_dialogues.get_mut(&listener) else {
+        return;
+    };
+    active.node = name.to_string();
+    let active = active.clone();
+
+    let Some(event) = ctx
+        .assets
+        .dialogues
+        .get(&active.dialogue)
+        .and_then(|dialogue| dialogue.nodes.get(name))
+        .map(|node| node.event.clone())
+    else {
+        end_dialogue(ctx, listener);
+        return;
+    };
+
+    if let Some(event) = event {
+        ctx.to_execute_entity
+            .push((active.speaker, event, VMValue::broadcast(listener as f32)));
+    }
+
+    if !send_dialogue_prompt(ctx, listener) {
+        end_dialogue(ctx, listener);
+    }
+}
+
+/// Send the current node of the dialogue of the listener with the choices the listener meets
+/// the conditions of, i.e. again after loading a save game. Returns false if the dialogue or
+/// the node is unknown or no choice is left, the dialogue is over then.
+pub fn send_dialogue_prompt(ctx: &RegionCtx, listener: u32) -> bool {
+    let Some(active) = ctx.active_dialogues.get(&listener) else {
+        return false;
+    };
+    let Some(node) = ctx
+        .assets
+        .dialogues
+        .get(&active.dialogue)
+        .and_then(|dialogue| dialogue.nodes.get(&active.node))
+    else {
+        return false;
+    };
+
+    let entity = ctx.map.entities.iter().find(|entity| entity.id == listener);
+    let choices = node
+        .choices
+        .iter()
+        .enumerate()
+        .filter(|(_, choice)| {
+            choice.condition.as_ref().is_none_or(|condition| {
+                entity.is_some_and(|entity| check_condition(condition, entity))
+            })
+        })
+        .map(|(index, choice)| (index as u32, choice.text.clone()))
+        .collect::<Vec<_>>();
+    let finished = choices.is_empty();
+
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Dialogue(DialoguePrompt {
+            region: ctx.region_id,
+            from: active.speaker,
+            to: listener,
+            text: node.text.clone(),
+            choices,
+        }));
+    }
+
+    !finished
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+
+    const DIALOGUE: &str = r#"
+        start = "greeting"
+
+        [nodes.greeting]
+        text = "Welcome, traveller."
+
+        [[nodes.greeting.choices]]
+        text = "Show me your wares."
+        condition = "gold >= 10"
+        event = "trade"
+
+        [[nodes.greeting.choices]]
+        text = "Farewell."
+        next = "bye"
+
+        [nodes.bye]
+        text = "Safe travels."
+    "#;
+
+    fn entity(id: u32, gold: i32) -> Entity {
+        let mut entity = Entity::new();
+        entity.id = id;
+        entity.set_attribute("gold", Value::Int(gold));
+        entity
+    }
+
+    #[test]
+    fn conditions() {
+        let mut entity = entity(1, 12);
+        entity.set_attribute("class_name", Value::Str("Warrior".into()));
+        entity.set_attribute("has_key", Value::Bool(false));
+
+        assert!(check_condition("gold >= 10", &entity));
+        assert!(!check_condition("gold < 10", &entity));
+        assert!(check_condition("class_name == \"Warrior\"", &entity));
+        assert!(!check_condition("has_key", &entity));
+        assert!(check_condition("!has_key", &entity));
+        assert!(check_condition("title != \"King\"", &entity));
+    }
+
+    #[test]
+    fn validate_nodes() {
+        assert!(Dialogue::from_toml(DIALOGUE).is_ok());
+        assert!(Dialogue::from_toml(&DIALOGUE.replace("next = \"bye\"", "next = \"b\"")).is_err());
+        assert!(
+            Dialogue::from_toml(&DIALOGUE.replace("start = \"greeting\"", "start = \"g\""))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn choices() {
+        let (sender, receiver) = unbounded();
+        let mut ctx = RegionCtx::default();
+        ctx.from_sender.set(sender).unwrap();
+        ctx.assets.add_dialogue("merchant", DIALOGUE).unwrap();
+        ctx.map.entities.push(entity(1, 5));
+        ctx.map.entities.push(entity(2, 0));
+
+        // The listener cannot afford the wares
+        start_dialogue(&mut ctx, "merchant", 2, 1).unwrap();
+        match receiver.try_recv() {
+            Ok(RegionMessage::Dialogue(prompt)) => {
+                assert_eq!((prompt.from, prompt.to), (2, 1));
+                assert_eq!(prompt.choices, vec![(1, "Farewell.".to_string())]);
+            }
+            _ => panic!("Expected a dialogue prompt"),
+        }
+
+        // Choices which are not offered cannot be selected
+        select_dialogue_choice(&mut ctx, 1, 0);
+        assert!(ctx.to_execute_entity.is_empty());
+        assert_eq!(ctx.active_dialogues[&1].node, "greeting");
+
+        // The last node has no choices and ends the dialogue
+        select_dialogue_choice(&mut ctx, 1, 1);
+        assert!(
+            matches!(receiver.try_recv(), Ok(RegionMessage::Dialogue(prompt))
+            if prompt.text == "Safe travels.")
+        );
+        assert!(ctx.active_dialogues.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::vm::VMValue;
+use crate::{Entity, RegionCtx, Value};
+use theframework::prelude::*;
+
+/// A status effect definition, loaded from TOML. A file can define several effects. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// Modifiers are added to the attributes of the entity (once per stack) while the effect is
+/// active and removed again on expiry.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EffectDef {
+    pub duration: i64,
+    /// The ticks between two events, 0 to only send the expiry event.
+    #[serde(default)]
+    pub interval: i64,
+    /// The event sent to the entity every interval. Defaults to the name of the effect.
+    #[serde(default)]
+    pub event: Option<String>,
+    #[serde(default)]
+    pub stacking: EffectStacking,
+    #[serde(default = "default_max_stacks")]
+    pub max_stacks: u32,
+    #[serde(default)]
+    pub modifiers: FxHashMap<String, f32>,
+    /// The icon shown by the client, i.e. a tile name.
+    #[serde(default)]
+    pub icon: String,
+}
+
+fn default_max_stacks() -> u32 {
+    1
+}
+
+/// What happens if an active effect is applied again.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectStacking {
+    /// Restart the duration.
+    #[default]
+    Refresh,
+    /// Add the duration to the remaining time.
+    Extend,
+    /// Add a stack (up to max_stacks) and restart the duration.
+    Stack,
+    /// Keep the active effect.
+    Ignore,
+}
+
+/// Parse the effect definitions of a TOML file.
+pub fn parse_effects(toml: &str) -> Result<FxHashMap<String, EffectDef>, String> {
+    toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+/// An active effect on an entity.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusEffect {
+    pub name: String,
+    pub icon: String,
+    pub stacks: u32,
+    /// The tick the effect expires.
+    pub until: i64,
+    /// The tick of the next event.
+    pub next_event: i64,
+}
+
+/// Add the modifiers times the factor to the attributes of the entity.
+pub(crate) fn add_modifiers(entity: &mut Entity, modifiers: &FxHashMap<String, f32>, factor: f32) {
+    for (key, modifier) in modifiers {
+        let delta = modifier * factor;
+        let value = match entity.attributes.get(key) {
+            Some(Value::Int(v)) => Value::Int(*v + delta.round() as i32),
+            Some(value) => Value::Float(value.to_f32().unwrap_or(0.0) + delta),
+            None => Value::Float(delta),
+        };
+        entity.set_attribute(key, value);
+    }
+}
+
+/// Apply the effect to the entity, the duration overrides the duration of the definition if
+/// positive. Returns false if the effect or the entity is unknown.
+pub fn apply_effect(ctx: &mut RegionCtx, entity_id: u32, name: &str, duration: i64) -> bool {
+    let Some(def) = ctx.assets.effects.get(name).cloned() else {
+        return false;
+    };
+    let ticks = ctx.ticks;
+    let duration = if duration > 0 { duration } else { def.duration };
+    let Some(entity) = ctx.get_entity_mut(entity_id) else {
+        return false;
+    };
+
+    if let Some(index) = entity.effects.iter().position(|e| e.name == name) {
+        let mut effect = entity.effects[index].clone();
+        let mut added_stacks = 0;
+        match def.stacking {
+            EffectStacking::Refresh => effect.until = ticks + duration,
+            EffectStacking::Extend => effect.until += duration,
+            EffectStacking::Stack => {
+                if effect.stacks < def.max_stacks.max(1) {
+                    effect.stacks += 1;
+                    added_stacks = 1;
+                }
+                effect.until = ticks + duration;
+            }
+            EffectStacking::Ignore => return true,
+        }
+        if added_stacks > 0 {
+            add_modifiers(entity, &def.modifiers, added_stacks as f32);
+        }
+        entity.effects[index] = effect;
+    } else {
+        add_modifiers(entity, &def.modifiers, 1.0);
+        entity.effects.push(StatusEffect {
+            name: name.to_string(),
+            icon: def.icon.clone(),
+            stacks: 1,
+            until: ticks + duration,
+            next_event: ticks + def.interval,
+        });
+    }
+    entity.mark_dirty_effects();
+    true
+}
+
+/// Remove the effect from the entity and revert its modifiers. Returns false if it was not active.
+pub fn remove_effect(ctx: &mut RegionCtx, entity_id: u32, name: &str) -> bool {
+    let def = ctx.assets.effects.get(name).cloned().unwrap_or_default();
+    let Some(entity) = ctx.get_entity_mut(entity_id) else {
+        return false;
+    };
+    let Some(index) = entity.effects.iter().position(|e| e.name == name) else {
+        return false;
+    };
+    let effect = entity.effects.remove(index);
+    add_modifiers(entity, &def.modifiers, -(effect.stacks as f32));
+    entity.mark_dirty_effects();
+    true
+}
+
+/// The stacks of the effect on the entity, 0 if it is not active.
+pub fn effect_stacks(entity: &Entity, name: &str) -> u32 {
+    entity
+        .effects
+        .iter()
+        .find(|e| e.name == name)
+        .map(|e| e.stacks)
+        .unwrap_or(0)
+}
+
+/// Send the interval events of the active effects and remove the expired ones. Expired
+/// effects send an "effect_expired" event with the name of the effect.
+pub fn tick_effects(ctx: &mut RegionCtx) {
+    let ticks = ctx.ticks;
+    let mut expired = vec![];
+
+    for entity in ctx.map.entities.iter_mut() {
+        for effect in entity.effects.iter_mut() {
+            let Some(def) = ctx.assets.effects.get(&effect.name) else {
+                continue;
+            };
+            if def.interval > 0 && ticks >= effect.next_event && ticks <= effect.until {
+                effect.next_event = ticks + def.interval;
+                let event = def.event.clone().unwrap_or(effect.name.clone());
+                ctx.to_execute_entity.push((
+                    entity.id,
+                    event,
+                    VMValue::new_with_string(effect.stacks as f32, 0.0, 0.0, effect.name.clone()),
+                ));
+            }
+            if ticks >= effect.until {
+                expired.push((entity.id, effect.name.clone()));
+            }
+        }
+    }
+
+    for (id, name) in expired {
+        if remove_effect(ctx, id, &name) {
+            ctx.to_execute_entity
+                .push((id, "effect_expired".into(), VMValue::from(name)));
+        }
+    }
+}
@@ -3,7 +3,7 @@ use rand::Rng;
 use theframework::prelude::*;
 use vek::{Vec2, Vec3};
 
-use crate::{EntityAction, prelude::*};
+use crate::{EntityAction, QuestState, StatusEffect, prelude::*};
 
 /// The Rust representation of an Entity. The real entity class lives in Python, this class is the Rust side
 /// instantiation (to avoid unnecessary Python look ups for common attributes). The class gets synced with the Python side.
@@ -38,11 +38,16 @@ pub struct Entity {
     /// - `0b00001000` (8): Inventory changed
     /// - `0b00010000` (16): Equipped items changed
     /// - `0b00100000` (32): Wallet changed
+    /// - `0b01000000` (64): Status effects changed
     pub dirty_flags: u8,
 
     /// Dirty Attributes
     pub dirty_attributes: FxHashSet<String>,
 
+    /// Attributes which are not replicated to the clients, from the class schema.
+    #[serde(default)]
+    pub hidden_attributes: FxHashSet<String>,
+
     /// Inventory: A container for the entity's items
     #[serde(skip_deserializing)]
     pub inventory: Vec<Option<Item>>,
@@ -59,6 +64,14 @@ pub struct Entity {
 
     /// Wallet
     pub wallet: Wallet,
+
+    /// The quests of the entity by name
+    #[serde(default)]
+    pub quests: IndexMap<String, QuestState>,
+
+    /// The active status effects
+    #[serde(default)]
+    pub effects: Vec<StatusEffect>,
 }
 
 impl Default for Entity {
@@ -83,6 +96,7 @@ impl Entity {
 
             dirty_flags: 0,
             dirty_attributes: FxHashSet::default(),
+            hidden_attributes: FxHashSet::default(),
 
             inventory: vec![],
             inventory_additions: FxHashMap::default(),
@@ -92,6 +106,10 @@ impl Entity {
             equipped: IndexMap::default(),
 
             wallet: Wallet::default(),
+
+            quests: IndexMap::default(),
+
+            effects: vec![],
         }
     }
 
@@ -407,6 +425,11 @@ impl Entity {
         self.dirty_flags |= field;
     }
 
+    /// Mark the status effects as dirty
+    pub fn mark_dirty_effects(&mut self) {
+        self.mark_dirty_field(0b1000000);
+    }
+
     /// Mark a dynamic attribute as dirty
     pub fn mark_dirty_attribute(&mut self, key: &str) {
         self.dirty_attributes.insert(key.to_string());
@@ -414,7 +437,7 @@ impl Entity {
 
     /// Mark all fields and attributes as dirty.
     pub fn mark_all_dirty(&mut self) {
-        self.dirty_flags = 0b11111;
+        self.dirty_flags = 0b1011111;
         self.dirty_attributes = self.attributes.keys().cloned().collect();
     }
 
@@ -477,6 +500,7 @@ impl Entity {
             attributes: self
                 .dirty_attributes
                 .iter()
+                .filter(|key| !self.hidden_attributes.contains(*key))
                 .filter_map(|key| self.attributes.get(key).map(|v| (key.clone(), v.clone())))
                 .collect(),
             inventory_additions: if !self.inventory_additions.is_empty() {
@@ -504,6 +528,11 @@ impl Entity {
             } else {
                 None
             },
+            effects: if self.dirty_flags & 0b1000000 != 0 {
+                Some(self.effects.clone())
+            } else {
+                None
+            },
         }
     }
 
@@ -576,6 +605,10 @@ impl Entity {
             }
         }
 
+        if let Some(effects) = update.effects {
+            self.effects = effects;
+        }
+
         rc
     }
 
@@ -654,6 +687,7 @@ pub struct EntityUpdate {
     pub inventory_updates: Option<FxHashMap<usize, ItemUpdate>>,
     pub equipped_updates: Option<IndexMap<String, Item>>,
     pub wallet_updates: Option<FxHashMap<String, i64>>,
+    pub effects: Option<Vec<StatusEffect>>,
 }
 
 impl EntityUpdate {
@@ -676,6 +710,7 @@ impl EntityUpdate {
             inventory_removals: None,
             equipped_updates: None,
             wallet_updates: None,
+            effects: None,
         })
     }
 }
@@ -0,0 +1,63 @@
+use crate::{RegionCtx, RegionMessage};
+use theframework::prelude::*;
+
+/// The look of a floating text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FloatTextStyle {
+    #[default]
+    Info,
+    Damage,
+    Critical,
+    Heal,
+    Miss,
+    Status,
+}
+
+impl FloatTextStyle {
+    pub const ALL: [FloatTextStyle; 6] = [
+        FloatTextStyle::Info,
+        FloatTextStyle::Damage,
+        FloatTextStyle::Critical,
+        FloatTextStyle::Heal,
+        FloatTextStyle::Miss,
+        FloatTextStyle::Status,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FloatTextStyle::Info => "info",
+            FloatTextStyle::Damage => "damage",
+            FloatTextStyle::Critical => "critical",
+            FloatTextStyle::Heal => "heal",
+            FloatTextStyle::Miss => "miss",
+            FloatTextStyle::Status => "status",
+        }
+    }
+}
+
+/// A text rising above an entity, i.e. a damage or heal number or a status popup like
+/// "Poisoned", sent by the `float_text` script call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FloatText {
+    pub region: u32,
+    pub entity: u32,
+    pub text: String,
+    pub style: FloatTextStyle,
+}
+
+/// Send a floating text above the entity to the server.
+pub fn send_float_text(ctx: &RegionCtx, entity: u32, text: &str, style: FloatTextStyle) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::FloatText(FloatText {
+            region: ctx.region_id,
+            entity,
+            text: text.to_string(),
+            style,
+        }));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{RegionCtx, RegionMessage};
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// The fog of war rules, read from the `[fog_of_war]` table of the game config. See
+/// `src/server/CONFIG.md` for an example.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FogRules {
+    pub enabled: bool,
+    pub radius: f32,
+}
+
+impl Default for FogRules {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 8.0,
+        }
+    }
+}
+
+/// The field of view of a player, sent whenever the visible tiles change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FogUpdate {
+    pub region: u32,
+    /// The player entity.
+    pub player: u32,
+    /// The tiles the player currently sees.
+    pub visible: Vec<Vec2<i32>>,
+    /// The newly explored tiles, or all explored tiles if `reset` is set.
+    pub explored: Vec<Vec2<i32>>,
+    /// Replace the explored tiles of the client, i.e. after entering the region or loading a
+    /// save game.
+    pub reset: bool,
+}
+
+/// Update the field of view of the players of the region and add it to their explored tiles.
+/// Players receive a `FogUpdate` when their visible tiles change.
+pub fn tick_fog(ctx: &mut RegionCtx) {
+    if !ctx.fog_of_war.enabled {
+        return;
+    }
+
+    let radius = ctx.fog_of_war.radius;
+    let players: Vec<(u32, Vec2<f32>, f32)> = ctx
+        .map
+        .entities
+        .iter()
+        .filter(|entity| entity.is_player())
+        .map(|entity| {
+            let sight = entity.attributes.get_float_default("sight", radius);
+            (entity.id, entity.get_pos_xz(), sight.max(0.0))
+        })
+        .collect();
+
+    // Players who left receive their full explored set when they return
+    ctx.fog_visible
+        .retain(|id, _| players.iter().any(|(player, _, _)| player == id));
+
+    for (id, position, sight) in players {
+        let visible = ctx.mapmini.visible_cells(position, sight);
+        let reset = match ctx.fog_visible.get(&id) {
+            Some(known) if *known == visible => continue,
+            Some(_) => false,
+            None => true,
+        };
+
+        let explored = ctx.explored.entry(id).or_default();
+        let mut new: Vec<Vec2<i32>> = visible
+            .iter()
+            .filter(|cell| explored.insert(**cell))
+            .copied()
+            .collect();
+        if reset {
+            new = explored.iter().copied().collect();
+        }
+
+        let mut cells: Vec<Vec2<i32>> = visible.iter().copied().collect();
+        cells.sort_by_key(|c| (c.y, c.x));
+        new.sort_by_key(|c| (c.y, c.x));
+
+        send_fog(ctx, id, cells, new, reset);
+        ctx.fog_visible.insert(id, visible);
+    }
+}
+
+/// Send the field of view of a player to the server.
+fn send_fog(
+    ctx: &RegionCtx,
+    player: u32,
+    visible: Vec<Vec2<i32>>,
+    explored: Vec<Vec2<i32>>,
+    reset: bool,
+) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Fog(FogUpdate {
+            region: ctx.region_id,
+            player,
+            visible,
+            explored,
+            reset,
+        }));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::server::region::is_entity_dead_ctx;
+use crate::vm::VMValue;
+use crate::{EntityAction, RegionCtx};
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// The follower settings, read from the `[follow]` table of the game config. See
+/// `src/server/CONFIG.md` for an example.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FollowRules {
+    pub spacing: f32,
+    pub speed: f32,
+    pub teleport_distance: f32,
+}
+
+impl Default for FollowRules {
+    fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            speed: 1.0,
+            teleport_distance: 12.0,
+        }
+    }
+}
+
+fn entity_exists(ctx: &RegionCtx, id: u32) -> bool {
+    ctx.map.entities.iter().any(|entity| entity.id == id)
+}
+
+/// Let the entity follow the leader. Fails for unknown entities and if the leader already
+/// follows the entity, directly or through other followers.
+pub fn follow(ctx: &mut RegionCtx, follower: u32, leader: u32) -> Result<(), String> {
+    if follower == leader {
+        return Err("An entity cannot follow itself.".into());
+    }
+    if !entity_exists(ctx, leader) {
+        return Err(format!("Unknown leader '{}'.", leader));
+    }
+    let mut id = leader;
+    while let Some(next) = ctx.followers.get(&id) {
+        if *next == follower {
+            return Err("The leader follows the entity.".into());
+        }
+        id = *next;
+    }
+
+    let speed = ctx.follow.speed;
+    let entity = ctx.get_entity_mut(follower).ok_or("Unknown entity.")?;
+    entity.action = EntityAction::Follow(leader, speed);
+    ctx.followers.insert(follower, leader);
+    Ok(())
+}
+
+/// Stop following, returns false if the entity did not follow anyone.
+pub fn stop_following(ctx: &mut RegionCtx, follower: u32) -> bool {
+    if ctx.followers.remove(&follower).is_none() {
+        return false;
+    }
+    if let Some(entity) = ctx.get_entity_mut(follower) {
+        if matches!(entity.action, EntityAction::Follow(..)) {
+            entity.action = EntityAction::Off;
+        }
+    }
+    true
+}
+
+/// The followers of the leader, sorted by id so that each keeps its slot in the formation.
+pub fn followers_of(ctx: &RegionCtx, leader: u32) -> Vec<u32> {
+    let mut followers: Vec<u32> = ctx
+        .followers
+        .iter()
+        .filter(|(_, l)| **l == leader)
+        .map(|(id, _)| *id)
+        .collect();
+    followers.sort_unstable();
+    followers
+}
+
+/// The position of the follower in the formation behind its leader. The followers walk in
+/// rows of two, the rows are `spacing` apart.
+pub fn formation_position(ctx: &RegionCtx, follower: u32) -> Option<Vec2<f32>> {
+    let leader_id = *ctx.followers.get(&follower)?;
+    let leader = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == leader_id)?;
+    let slot = followers_of(ctx, leader_id)
+        .iter()
+        .position(|id| *id == follower)?;
+
+    let forward = if leader.orientation.magnitude_squared() > 0.0 {
+        leader.orientation.normalized()
+    } else {
+        Vec2::new(1.0, 0.0)
+    };
+    let side = Vec2::new(-forward.y, forward.x);
+    let spacing = ctx.follow.spacing;
+    let back = (slot / 2 + 1) as f32 * spacing;
+    let across = if slot % 2 == 0 { -0.5 } else { 0.5 } * spacing;
+
+    Some(leader.get_pos_xz() - forward * back + side * across)
+}
+
+/// Keep the followers of the region with their leaders. Idle followers resume following,
+/// followers too far away from their slot are placed on it. If the leader is gone or dead the
+/// follower receives a `leader_lost` event.
+pub fn tick_followers(ctx: &mut RegionCtx) {
+    let followers: Vec<(u32, u32)> = ctx.followers.iter().map(|(f, l)| (*f, *l)).collect();
+    for (id, leader) in followers {
+        if !entity_exists(ctx, id) {
+            ctx.followers.remove(&id);
+            continue;
+        }
+        if !entity_exists(ctx, leader) || is_entity_dead_ctx(ctx, leader) {
+            stop_following(ctx, id);
+            ctx.to_execute_entity.push((
+                id,
+                "leader_lost".into(),
+                VMValue::broadcast(leader as f32),
+            ));
+            continue;
+        }
+        if is_entity_dead_ctx(ctx, id) {
+            continue;
+        }
+
+        let Some(target) = formation_position(ctx, id) else {
+            continue;
+        };
+        let (speed, teleport_distance) = (ctx.follow.speed, ctx.follow.teleport_distance);
+        let mut teleported = false;
+        if let Some(entity) = ctx.get_entity_mut(id) {
+            if entity.get_pos_xz().distance(target) > teleport_distance {
+                entity.set_pos_xz(target);
+                teleported = true;
+            }
+            if entity.action == EntityAction::Off {
+                entity.action = EntityAction::Follow(leader, speed);
+            }
+        }
+        if teleported {
+            ctx.check_player_for_section_change_id(id);
+        }
+    }
+}
+
+/// The members of the party of the entity: its leader and all followers of the leader.
+pub fn party_of(ctx: &RegionCtx, id: u32) -> Vec<u32> {
+    let leader = ctx.followers.get(&id).copied().unwrap_or(id);
+    let mut party = followers_of(ctx, leader);
+    if party.is_empty() {
+        return party;
+    }
+    party.insert(0, leader);
+    party
+}
+
+/// Share the aggro of an attack with the parties: the other members of the target's party
+/// receive a `party_attacked` event (attacker, target), the other members of the attacker's
+/// party a `party_attacking` event (target, attacker).
+pub fn send_party_aggro(ctx: &mut RegionCtx, attacker: u32, target: u32) {
+    for member in party_of(ctx, target) {
+        if member != target && member != attacker {
+            ctx.to_execute_entity.push((
+                member,
+                "party_attacked".into(),
+                VMValue::new(attacker as f32, target as f32, 0.0),
+            ));
+        }
+    }
+    for member in party_of(ctx, attacker) {
+        if member != attacker && member != target {
+            ctx.to_execute_entity.push((
+                member,
+                "party_attacking".into(),
+                VMValue::new(target as f32, attacker as f32, 0.0),
+            ));
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::server::region::unregister_regionctx;
+use crate::server::{LOCAL_PLAYERS, REGIONPIPE};
+use crate::{Assets, Map, RegionInstance, RegionMessage, Server};
+use std::sync::{Arc, Mutex};
+use theframework::prelude::*;
+
+impl Server {
+    /// Register a region map which runs as independent instances, i.e. dungeons or arenas.
+    /// Entities transferred to the template name enter their instance instead of a shared region.
+    pub fn register_instance_template(&mut self, name: String, map: Map, config_toml: String) {
+        self.instance_templates.insert(name, (map, config_toml));
+    }
+
+    pub fn is_instance_template(&self, name: &str) -> bool {
+        self.instance_templates.contains_key(name)
+    }
+
+    /// Create a new instance of the template and return its region id. The instance is named
+    /// `template#id` and its map gets a new id, use `region_map_id` to look it up.
+    pub fn create_instance(&mut self, template: &str, assets: &Assets) -> Result<u32, String> {
+        let (mut map, config_toml) = self
+            .instance_templates
+            .get(template)
+            .cloned()
+            .ok_or(format!("Unknown instance template '{}'.", template))?;
+        map.id = Uuid::new_v4();
+
+        let name = format!("{}#{}", template, self.instances.len());
+        let region_id = self.spawn_region_instance(name, map, assets, config_toml);
+        self.instances_of
+            .entry(template.to_string())
+            .or_default()
+            .push(region_id);
+        Ok(region_id)
+    }
+
+    /// Assign the entity to the instance, it enters this instance when it is transferred to the
+    /// template. Use it to keep a party together.
+    pub fn join_instance(&mut self, entity_id: u32, region_id: u32) -> Result<(), String> {
+        if self.instance_template_of(region_id).is_none() {
+            return Err(format!("Region {} is not an instance.", region_id));
+        }
+        self.instance_members.insert(entity_id, region_id);
+        Ok(())
+    }
+
+    /// Remove the assignment of the entity, it enters a new instance the next time.
+    pub fn leave_instance(&mut self, entity_id: u32) {
+        self.instance_members.remove(&entity_id);
+    }
+
+    /// The instance the entity belongs to.
+    pub fn instance_of(&self, entity_id: u32) -> Option<u32> {
+        self.instance_members.get(&entity_id).copied()
+    }
+
+    /// The template the region is an instance of.
+    pub fn instance_template_of(&self, region_id: u32) -> Option<&String> {
+        self.instances_of
+            .iter()
+            .find(|(_, ids)| ids.contains(&region_id))
+            .map(|(template, _)| template)
+    }
+
+    /// The map id of the region with the given name, for instances the id of the copied map.
+    pub fn region_map_id(&self, name: &str) -> Option<Uuid> {
+        let region_id = self.region_name_id_map.get(name)?;
+        self.region_id_map
+            .iter()
+            .find(|(_, id)| *id == region_id)
+            .map(|(map_id, _)| *map_id)
+    }
+
+    /// The name of the instance of the template the entity enters: its assigned instance or a
+    /// new one.
+    pub(crate) fn enter_instance(
+        &mut self,
+        template: &str,
+        entity_id: u32,
+        assets: &Assets,
+    ) -> Result<String, String> {
+        let assigned = self.instance_of(entity_id).filter(|id| {
+            self.instance_template_of(*id)
+                .is_some_and(|t| t == template)
+        });
+        let region_id = match assigned {
+            Some(region_id) => region_id,
+            None => {
+                let region_id = self.create_instance(template, assets)?;
+                self.instance_members.insert(entity_id, region_id);
+                region_id
+            }
+        };
+        self.region_name_id_map
+            .iter()
+            .find(|(_, id)| **id == region_id)
+            .map(|(name, _)| name.clone())
+            .ok_or(format!("Instance {} is not running.", region_id))
+    }
+
+    /// Shut down the instance. Its entities and items are dropped and its members lose their
+    /// assignment, transfer players out before closing it.
+    pub fn close_instance(&mut self, region_id: u32) -> Result<(), String> {
+        let template = self
+            .instance_template_of(region_id)
+            .cloned()
+            .ok_or(format!("Region {} is not an instance.", region_id))?;
+        if let Some(ids) = self.instances_of.get_mut(&template) {
+            ids.retain(|id| *id != region_id);
+        }
+
+        self.send_to_region(region_id, RegionMessage::Quit);
+        if let Ok(mut pipes) = REGIONPIPE.write() {
+            pipes.remove(&region_id);
+        }
+        if let Ok(mut players) = LOCAL_PLAYERS.write() {
+            players.retain(|(id, _)| *id != region_id);
+        }
+        unregister_regionctx(region_id);
+
+        self.region_id_map.retain(|_, id| *id != region_id);
+        self.region_name_id_map.retain(|_, id| *id != region_id);
+        self.instance_members.retain(|_, id| *id != region_id);
+        self.entities.remove(&region_id);
+        self.items.remove(&region_id);
+        self.messages.remove(&region_id);
+        self.multiple_choice.remove(&region_id);
+        self.dialogues.remove(&region_id);
+        self.combat_events.remove(&region_id);
+        self.sounds.remove(&region_id);
+        self.times.remove(&region_id);
+        self.weathers.remove(&region_id);
+        self.stats.remove(&region_id);
+
+        // Keep the slot so that the ids of the other regions stay valid
+        if let Some(instance) = self.instances.get_mut(region_id as usize) {
+            *instance = Arc::new(Mutex::new(RegionInstance::new(region_id)));
+        }
+        self.closed_regions.insert(region_id);
+        Ok(())
+    }
+}
@@ -30,6 +30,10 @@ pub struct Item {
 
     /// Dirty dynamic attributes
     pub dirty_attributes: FxHashSet<String>,
+
+    /// Attributes which are not replicated to the clients, from the class schema.
+    #[serde(default)]
+    pub hidden_attributes: FxHashSet<String>,
 }
 
 impl Default for Item {
@@ -50,6 +54,7 @@ impl Item {
             attributes: ValueContainer::default(),
             dirty_flags: 0,
             dirty_attributes: FxHashSet::default(),
+            hidden_attributes: FxHashSet::default(),
         }
     }
 
@@ -192,6 +197,9 @@ impl Item {
     pub fn get_update(&self) -> ItemUpdate {
         let mut updated_attributes = FxHashMap::default();
         for key in &self.dirty_attributes {
+            if self.hidden_attributes.contains(key) {
+                continue;
+            }
             if let Some(value) = self.attributes.get(key) {
                 updated_attributes.insert(key.clone(), value.clone());
             }
@@ -0,0 +1,152 @@
+use crate::server::effect::add_modifiers;
+use crate::vm::VMValue;
+use crate::{RegionCtx, Value};
+use theframework::prelude::*;
+
+/// An experience curve. Reaching level 2 needs `base` experience, each further level needs
+/// `factor` times the experience of the previous one. Every level gained adds the bonuses to the
+/// attributes of the entity.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LevelCurve {
+    pub base: f32,
+    pub factor: f32,
+    pub max_level: i32,
+    pub bonuses: FxHashMap<String, f32>,
+}
+
+impl Default for LevelCurve {
+    fn default() -> Self {
+        Self {
+            base: 100.0,
+            factor: 1.5,
+            max_level: 20,
+            bonuses: FxHashMap::default(),
+        }
+    }
+}
+
+impl LevelCurve {
+    /// The total experience needed to reach the level, levels start at 1.
+    pub fn xp_for_level(&self, level: i32) -> i64 {
+        let mut total = 0.0;
+        let mut step = self.base;
+        for _ in 1..level.min(self.max_level) {
+            total += step;
+            step *= self.factor;
+        }
+        total.round() as i64
+    }
+
+    /// The level reached with the total experience.
+    pub fn level_for_xp(&self, xp: i64) -> i32 {
+        let mut level = 1;
+        while level < self.max_level && self.xp_for_level(level + 1) <= xp {
+            level += 1;
+        }
+        level
+    }
+}
+
+/// The leveling rules, read from the `[leveling]` and `[skills]` tables of the game config. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// The level and experience of a skill are kept in the `skill_<name>` and `skill_<name>_xp`
+/// attributes of the entity, so they are replicated to the clients like any other attribute.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LevelingRules {
+    pub level: String,
+    pub xp: String,
+    #[serde(flatten)]
+    pub curve: LevelCurve,
+    #[serde(skip)]
+    pub skills: FxHashMap<String, LevelCurve>,
+}
+
+impl Default for LevelingRules {
+    fn default() -> Self {
+        Self {
+            level: "LEVEL".into(),
+            xp: "XP".into(),
+            curve: LevelCurve::default(),
+            skills: FxHashMap::default(),
+        }
+    }
+}
+
+impl LevelingRules {
+    /// Read the rules from the `[leveling]` and `[skills]` tables of the config, missing values
+    /// use the defaults.
+    pub fn from_config(config: &toml::Table) -> Result<Self, String> {
+        let mut rules: Self = config_section(config, "leveling")?;
+        rules.skills = config_section(config, "skills")?;
+        Ok(rules)
+    }
+
+    /// The curve and the level and experience attributes of the skill, or of the character
+    /// level if the skill is empty.
+    fn curve_of(&self, skill: &str) -> Result<(&LevelCurve, String, String), String> {
+        if skill.is_empty() {
+            return Ok((&self.curve, self.level.clone(), self.xp.clone()));
+        }
+        let curve = self
+            .skills
+            .get(skill)
+            .ok_or_else(|| format!("Unknown skill '{}'.", skill))?;
+        Ok((
+            curve,
+            format!("skill_{}", skill),
+            format!("skill_{}_xp", skill),
+        ))
+    }
+}
+
+/// Grant experience to the entity, for the skill or for the character level if the skill is
+/// empty. Levels are never lost, every gained level adds the bonuses of the curve and sends a
+/// `level_up` (new level, previous level) or `skill_up` (new level, previous level, skill)
+/// event to the entity. Returns the new level.
+pub fn grant_xp(ctx: &mut RegionCtx, id: u32, skill: &str, amount: i32) -> Result<i32, String> {
+    let (curve, level_attr, xp_attr) = ctx.leveling.curve_of(skill)?;
+    let curve = curve.clone();
+    let entity = ctx.get_entity_mut(id).ok_or("Unknown entity.")?;
+
+    let xp = (entity.attributes.get_int_default(&xp_attr, 0) as i64 + amount as i64).max(0);
+    let previous = entity.attributes.get_int_default(&level_attr, 1);
+    let level = curve.level_for_xp(xp).max(previous);
+
+    entity.set_attribute(&xp_attr, Value::Int(xp.min(i32::MAX as i64) as i32));
+    if level == previous {
+        return Ok(level);
+    }
+    entity.set_attribute(&level_attr, Value::Int(level));
+    add_modifiers(entity, &curve.bonuses, (level - previous) as f32);
+
+    let event = if skill.is_empty() {
+        ("level_up", VMValue::new(level as f32, previous as f32, 0.0))
+    } else {
+        (
+            "skill_up",
+            VMValue {
+                x: level as f32,
+                y: previous as f32,
+                z: 0.0,
+                string: Some(skill.to_string()),
+            },
+        )
+    };
+    ctx.to_execute_entity.push((id, event.0.into(), event.1));
+    Ok(level)
+}
+
+/// The level of the entity in the skill, or its character level if the skill is empty.
+pub fn skill_level(ctx: &RegionCtx, id: u32, skill: &str) -> Result<i32, String> {
+    let (_, level_attr, _) = ctx.leveling.curve_of(skill)?;
+    let entity = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == id)
+        .ok_or("Unknown entity.")?;
+    Ok(entity.attributes.get_int_default(&level_attr, 1))
+}
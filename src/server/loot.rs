@@ -0,0 +1,154 @@
+use crate::{Item, RegionCtx, Value};
+use rand::Rng;
+use theframework::prelude::*;
+use vek::Vec3;
+
+/// A loot table, loaded from TOML. A file can define several tables. See `src/server/CONFIG.md` for
+/// an example.
+///
+/// Every roll picks one entry by weight. Currency drops spawn a monetary item whose worth is
+/// the rolled amount.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LootTable {
+    #[serde(default = "default_range")]
+    pub rolls: [u32; 2],
+    #[serde(default)]
+    pub entries: Vec<LootEntry>,
+    #[serde(default)]
+    pub currency: Option<LootCurrency>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LootEntry {
+    /// The item class to drop.
+    #[serde(default)]
+    pub item: Option<String>,
+    /// A nested table to roll instead of an item.
+    #[serde(default)]
+    pub table: Option<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default = "default_range")]
+    pub count: [u32; 2],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LootCurrency {
+    /// The monetary item class.
+    pub item: String,
+    /// The amount in base currency.
+    pub amount: [i32; 2],
+}
+
+fn default_range() -> [u32; 2] {
+    [1, 1]
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Nested tables deeper than this are ignored, guards against cyclic tables.
+const MAX_LOOT_DEPTH: u32 = 8;
+
+/// Parse the loot tables of a TOML file.
+pub fn parse_loot_tables(toml: &str) -> Result<FxHashMap<String, LootTable>, String> {
+    toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+fn roll_range(rng: &mut impl Rng, range: [u32; 2]) -> u32 {
+    rng.random_range(range[0].min(range[1])..=range[0].max(range[1]))
+}
+
+/// Roll the table and return the item classes to drop with their worth for currency drops.
+pub fn roll_loot(
+    tables: &FxHashMap<String, LootTable>,
+    name: &str,
+    rng: &mut impl Rng,
+) -> Result<Vec<(String, Option<i32>)>, String> {
+    let mut drops = vec![];
+    roll_table(tables, name, rng, 0, &mut drops)?;
+    Ok(drops)
+}
+
+fn roll_table(
+    tables: &FxHashMap<String, LootTable>,
+    name: &str,
+    rng: &mut impl Rng,
+    depth: u32,
+    drops: &mut Vec<(String, Option<i32>)>,
+) -> Result<(), String> {
+    if depth > MAX_LOOT_DEPTH {
+        return Ok(());
+    }
+    let table = tables
+        .get(name)
+        .ok_or(format!("Unknown loot table '{}'.", name))?;
+
+    if let Some(currency) = &table.currency {
+        let [min, max] = currency.amount;
+        let amount = rng.random_range(min.min(max)..=min.max(max));
+        if amount > 0 {
+            drops.push((currency.item.clone(), Some(amount)));
+        }
+    }
+
+    let total: u32 = table.entries.iter().map(|entry| entry.weight).sum();
+    if total == 0 {
+        return Ok(());
+    }
+
+    for _ in 0..roll_range(rng, table.rolls) {
+        let mut pick = rng.random_range(0..total);
+        let Some(entry) = table.entries.iter().find(|entry| {
+            if pick < entry.weight {
+                true
+            } else {
+                pick -= entry.weight;
+                false
+            }
+        }) else {
+            continue;
+        };
+
+        let count = roll_range(rng, entry.count);
+        if let Some(item) = &entry.item {
+            for _ in 0..count {
+                drops.push((item.clone(), None));
+            }
+        } else if let Some(nested) = &entry.table {
+            for _ in 0..count {
+                roll_table(tables, nested, rng, depth + 1, drops)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Roll the loot table and spawn the items into the map at the given position. Returns the
+/// ids of the spawned items.
+pub fn drop_loot(ctx: &mut RegionCtx, name: &str, position: Vec3<f32>) -> Result<Vec<u32>, String> {
+    let drops = roll_loot(&ctx.assets.loot_tables, name, &mut ctx.rng)?;
+    if let Some((class_name, _)) = drops
+        .iter()
+        .find(|(class_name, _)| !ctx.assets.items.contains_key(class_name))
+    {
+        return Err(format!("Unknown item '{}'.", class_name));
+    }
+
+    let mut items: Vec<Item> = vec![];
+    for (class_name, worth) in drops {
+        let Some(mut item) = ctx.create_item(class_name) else {
+            continue;
+        };
+        if let Some(worth) = worth {
+            item.set_attribute("worth", Value::Int(worth));
+        }
+        item.position = position;
+        items.push(item);
+    }
+
+    let ids = items.iter().map(|item| item.id).collect();
+    ctx.map.items.extend(items);
+    Ok(ids)
+}
@@ -1,4 +1,7 @@
-use crate::{Entity, Value};
+use crate::{
+    CameraHint, CombatEvent, DialoguePrompt, Entity, FloatText, FogUpdate, JournalEntry,
+    ParticleEvent, RegionStats, SoundEvent, Value, WeatherState,
+};
 use codegridfx::DebugModule;
 use theframework::prelude::*;
 
@@ -27,14 +30,34 @@ pub enum RegionMessage {
     LogMessage(String),
     /// Time event of a Region
     Time(u32, TheTime),
+    /// The weather of a Region changed
+    Weather(u32, WeatherState),
     /// Tell: RegionId, SenderId_entity, SenderId_item, ReceiverId, Message
     Message(u32, Option<u32>, Option<u32>, u32, String, String),
     /// TransferEntity: Move the Entity from the region to a new region (name) in sector (name)
     TransferEntity(u32, Entity, String, String),
     /// Send a multiple choice
     MultipleChoice(MultipleChoice),
+    /// Send the current node of a dialogue
+    Dialogue(DialoguePrompt),
+    /// The quest journal of an entity changed: RegionId, EntityId, Journal
+    Journal(u32, u32, Vec<JournalEntry>),
+    /// A hit, miss or kill
+    Combat(CombatEvent),
+    /// A positional sound
+    Sound(SoundEvent),
+    /// A particle effect
+    Particles(ParticleEvent),
+    /// A text floating above an entity
+    FloatText(FloatText),
+    /// A camera hint of a cutscene for the client of a player
+    CameraHint(CameraHint),
+    /// The field of view of a player
+    Fog(FogUpdate),
     /// Send the debug id of a character or item
     DebugData(DebugModule),
+    /// Performance metrics: RegionId, None requests them from the region which answers with Some
+    Stats(u32, Option<Box<RegionStats>>),
     /// Pause the server.
     Pause,
     /// Continue after pause
@@ -51,6 +74,8 @@ pub enum EntityAction {
     Forward,
     Right,
     Backward,
+    /// Analog movement (i.e. a gamepad stick), direction and strength in map space
+    Analog(Vec2<f32>),
     // Item clicked, item id, click distance and optional explicit intent
     ItemClicked(u32, f32, Option<String>),
     // Entity clicked, entity id and click distance
@@ -69,8 +94,18 @@ pub enum EntityAction {
     Goto(Vec2<f32>, f32),
     /// CloseIn: Move within a radius of a target entity with a given speed
     CloseIn(u32, f32, f32),
+    /// FollowPath: Move along the waypoints with a given speed
+    FollowPath(Vec<Vec2<f32>>, f32),
+    /// Follow: Keep the formation slot behind the leader entity with a given speed
+    Follow(u32, f32),
     /// A multiple choice item was selected by the user
     Choice(Choice),
+    /// Buy: Buy the item from the vendor, vendor id and item id
+    Buy(u32, u32),
+    /// Sell: Sell the item to the vendor, vendor id and item id
+    Sell(u32, u32),
+    /// Text: Text submitted by a text input widget, event name and text
+    Text(String, String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
@@ -135,6 +170,8 @@ pub enum Choice {
     Cancel(u32, u32),
     /// An item to sell, item_id, seller_id, buyer_id
     ItemToSell(u32, u32, u32),
+    /// A choice of the current dialogue node, speaker_id, listener_id, choice index
+    Dialogue(u32, u32, u32),
 }
 
 /// Multiple choices for the player
@@ -0,0 +1,98 @@
+use theframework::prelude::*;
+
+/// The upper bounds in milliseconds of the tick duration buckets, the last bucket holds all
+/// longer ticks.
+pub const TICK_BUCKETS_MS: [f32; 8] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 250.0];
+
+/// A histogram of tick durations.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TickHistogram {
+    /// The counts per bucket of `TICK_BUCKETS_MS` plus the overflow bucket.
+    pub buckets: [u32; TICK_BUCKETS_MS.len() + 1],
+    pub count: u32,
+    pub total_ms: f32,
+    pub max_ms: f32,
+}
+
+impl TickHistogram {
+    pub fn add(&mut self, ms: f32) {
+        let bucket = TICK_BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(TICK_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    pub fn average_ms(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total_ms / self.count as f32
+        }
+    }
+}
+
+/// The script execution time of a class.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ScriptStats {
+    pub calls: u32,
+    pub total_ms: f32,
+    pub max_ms: f32,
+}
+
+/// The performance metrics of a region, requested with `RegionMessage::Stats`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RegionStats {
+    pub region: u32,
+    /// The durations of the game ticks.
+    pub game_ticks: TickHistogram,
+    /// The durations of the redraw ticks, without the game ticks they run.
+    pub redraw_ticks: TickHistogram,
+    /// The configured time between game ticks.
+    pub game_tick_budget_ms: f32,
+    /// Game ticks which took longer than the budget, the region falls behind its tick rate.
+    pub overruns: u32,
+    /// The script execution time by class.
+    pub scripts: FxHashMap<String, ScriptStats>,
+    /// The messages received in the last redraw tick and the most received in one.
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    /// The events waiting for the entity and item scripts.
+    pub pending_entity_events: usize,
+    pub pending_item_events: usize,
+    pub entities: usize,
+    pub items: usize,
+    pub players: usize,
+    pub projectiles: usize,
+}
+
+impl RegionStats {
+    pub fn record_script(&mut self, class_name: &str, ms: f32) {
+        let stats = self.scripts.entry(class_name.to_string()).or_default();
+        stats.calls += 1;
+        stats.total_ms += ms;
+        stats.max_ms = stats.max_ms.max(ms);
+    }
+
+    pub fn record_game_tick(&mut self, ms: f32) {
+        self.game_ticks.add(ms);
+        if self.game_tick_budget_ms > 0.0 && ms > self.game_tick_budget_ms {
+            self.overruns += 1;
+        }
+    }
+
+    pub fn record_queue_depth(&mut self, depth: usize) {
+        self.queue_depth = depth;
+        self.max_queue_depth = self.max_queue_depth.max(depth);
+    }
+
+    /// The script classes sorted by their total execution time, the most expensive first.
+    pub fn slowest_scripts(&self) -> Vec<(&String, &ScriptStats)> {
+        let mut scripts: Vec<_> = self.scripts.iter().collect();
+        scripts.sort_by(|a, b| b.1.total_ms.total_cmp(&a.1.total_ms));
+        scripts
+    }
+}
@@ -1,13 +1,45 @@
 pub mod assets;
+pub mod behavior;
+pub mod combat;
+pub mod console;
+pub mod crafting;
 pub mod currency;
+pub mod cutscene;
 pub mod data;
+pub mod dialogue;
+pub mod effect;
 pub mod entity;
+pub mod floattext;
+pub mod fog;
+pub mod follow;
+pub mod instance;
 pub mod item;
+pub mod leveling;
+pub mod loot;
 pub mod message;
+pub mod metrics;
+pub mod movement;
+pub mod network;
+pub mod ownership;
+pub mod particles;
+pub mod persistence;
+pub mod projectile;
 pub mod py_fn;
+pub mod quest;
 pub mod region;
 pub mod region_host;
 pub mod regionctx;
+pub mod routine;
+pub mod savegame;
+pub mod schedule;
+pub mod schema;
+pub mod shop;
+pub mod simulation;
+pub mod sound;
+pub mod spawner;
+pub mod trigger;
+pub mod turn;
+pub mod weather;
 
 use crossbeam_channel::{Receiver, Sender};
 use rayon::prelude::*;
@@ -15,7 +47,14 @@ use rayon::prelude::*;
 use crate::Command;
 use crate::EntityAction;
 use crate::prelude::*;
+use crate::server::network::NetMessage;
+use crate::server::persistence::{Persistence, PersistenceCache};
+use crate::server::savegame::{GameState, RegionState, SAVEGAME_VERSION};
+use crate::server::simulation::SimulationLog;
+use instant::Instant;
+use serde::de::DeserializeOwned;
 use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::Duration;
 use theframework::prelude::*;
 
 // Pipes to the regions
@@ -56,7 +95,19 @@ pub struct Server {
     pub items: FxHashMap<u32, Vec<Item>>,
     pub messages: FxHashMap<u32, Vec<Message>>,
     pub multiple_choice: FxHashMap<u32, Vec<MultipleChoice>>,
+    pub dialogues: FxHashMap<u32, Vec<DialoguePrompt>>,
+    /// The latest quest journal by entity
+    pub journals: FxHashMap<u32, Vec<JournalEntry>>,
+    pub combat_events: FxHashMap<u32, Vec<CombatEvent>>,
+    pub sounds: FxHashMap<u32, Vec<SoundEvent>>,
+    pub particles: FxHashMap<u32, Vec<ParticleEvent>>,
+    pub float_texts: FxHashMap<u32, Vec<FloatText>>,
+    pub camera_hints: FxHashMap<u32, Vec<CameraHint>>,
+    pub fog: FxHashMap<u32, Vec<FogUpdate>>,
     pub times: FxHashMap<u32, TheTime>,
+    pub weathers: FxHashMap<u32, WeatherState>,
+    /// The latest performance metrics by region, see `request_stats`.
+    pub stats: FxHashMap<u32, RegionStats>,
 
     pub state: ServerState,
 
@@ -64,6 +115,25 @@ pub struct Server {
     pub log_changed: bool,
 
     pub instances: Vec<Arc<Mutex<RegionInstance>>>,
+
+    /// If true, region messages for remote clients are collected during update.
+    remote: bool,
+    remote_messages: Vec<NetMessage>,
+
+    /// The maps and configs of the regions which are instanced, by template name.
+    instance_templates: FxHashMap<String, (Map, String)>,
+    /// The instance region ids of each template.
+    pub instances_of: FxHashMap<String, Vec<u32>>,
+    /// The instance each entity belongs to.
+    pub instance_members: FxHashMap<u32, u32>,
+    /// Closed instances, their slots in `instances` are not ticked anymore.
+    closed_regions: FxHashSet<u32>,
+
+    /// The optional backend storing the world state of the regions.
+    persistence: Option<Box<dyn Persistence>>,
+    persistence_caches: FxHashMap<u32, PersistenceCache>,
+    persistence_interval: Duration,
+    last_persisted: Instant,
 }
 
 impl Default for Server {
@@ -88,7 +158,17 @@ impl Server {
             items: FxHashMap::default(),
             messages: FxHashMap::default(),
             multiple_choice: FxHashMap::default(),
+            dialogues: FxHashMap::default(),
+            journals: FxHashMap::default(),
+            combat_events: FxHashMap::default(),
+            sounds: FxHashMap::default(),
+            particles: FxHashMap::default(),
+            float_texts: FxHashMap::default(),
+            camera_hints: FxHashMap::default(),
+            fog: FxHashMap::default(),
+            stats: FxHashMap::default(),
             times: FxHashMap::default(),
+            weathers: FxHashMap::default(),
 
             state: ServerState::Off,
 
@@ -96,6 +176,19 @@ impl Server {
             log_changed: true,
 
             instances: vec![],
+
+            remote: false,
+            remote_messages: vec![],
+
+            instance_templates: FxHashMap::default(),
+            instances_of: FxHashMap::default(),
+            instance_members: FxHashMap::default(),
+            closed_regions: FxHashSet::default(),
+
+            persistence: None,
+            persistence_caches: FxHashMap::default(),
+            persistence_interval: Duration::from_secs(30),
+            last_persisted: Instant::now(),
         }
     }
 
@@ -123,6 +216,29 @@ impl Server {
         assets: &Assets,
         config_toml: String,
     ) {
+        let region_id = self.spawn_region_instance(name.clone(), map, assets, config_toml);
+
+        if let Some(persistence) = &mut self.persistence {
+            match persistence.load_region(&name) {
+                Ok(state) => {
+                    self.persistence_caches
+                        .insert(region_id, PersistenceCache::load(region_id, state));
+                }
+                Err(err) => {
+                    self.log_message(format!("{}: Could not load the world state: {}", name, err));
+                }
+            }
+        }
+    }
+
+    /// Create and register the region instance, returns its id.
+    fn spawn_region_instance(
+        &mut self,
+        name: String,
+        map: Map,
+        assets: &Assets,
+        config_toml: String,
+    ) -> u32 {
         let mut region_instance = RegionInstance::new(self.instances.len() as u32);
         // region_instance.id = self.get_next_id();
 
@@ -136,22 +252,88 @@ impl Server {
 
         self.from_region.push(region_instance.from_receiver.clone());
 
+        let region_id = region_instance.id;
         region_instance.init(name, map, assets, config_toml, self.debug_mode);
         self.instances.push(Arc::new(Mutex::new(region_instance)));
+        region_id
+    }
+
+    /// Set the backend which stores the world state of the regions. The state is loaded when a
+    /// region instance is created and the changes are written in the given interval.
+    pub fn set_persistence(&mut self, persistence: Box<dyn Persistence>, interval: Duration) {
+        self.persistence = Some(persistence);
+        self.persistence_interval = interval;
+        self.last_persisted = Instant::now();
+    }
+
+    /// Write the changed entities, items and sectors of all regions to the persistence backend.
+    pub fn persist(&mut self) {
+        self.last_persisted = Instant::now();
+        let Some(persistence) = &mut self.persistence else {
+            return;
+        };
+
+        let mut errors = vec![];
+        for (name, region_id) in &self.region_name_id_map {
+            let Some(cache) = self.persistence_caches.get_mut(region_id) else {
+                continue;
+            };
+            let changes = cache.collect(*region_id);
+            if !changes.is_empty() {
+                if let Err(err) = persistence.store_region(name, &changes) {
+                    errors.push(format!(
+                        "{}: Could not store the world state: {}",
+                        name, err
+                    ));
+                }
+            }
+        }
+        for error in errors {
+            self.log_message(error);
+        }
     }
 
     /// Send a system tick to all instances.
     pub fn system_tick(&self) {
-        self.instances.par_iter().for_each(|instance| {
-            instance.lock().unwrap().system_tick();
-        });
+        self.instances
+            .par_iter()
+            .enumerate()
+            .filter(|(index, _)| !self.closed_regions.contains(&(*index as u32)))
+            .for_each(|(_, instance)| {
+                instance.lock().unwrap().system_tick();
+            });
+    }
+
+    /// The recorded inputs of the given region, if it records in deterministic mode.
+    pub fn get_simulation_log(&self, region_id: &Uuid) -> Option<SimulationLog> {
+        let region_id = self.region_id_map.get(region_id)?;
+        let instance = self.instances.get(*region_id as usize)?;
+        instance.lock().unwrap().simulation_log().cloned()
+    }
+
+    /// Replay the recorded inputs in the given region. The region has to be freshly created
+    /// with the seed of the log and not have received a redraw tick yet.
+    pub fn replay_simulation(&self, region_id: &Uuid, log: SimulationLog) -> Result<(), String> {
+        let region_id = self
+            .region_id_map
+            .get(region_id)
+            .ok_or("Unknown region.".to_string())?;
+        let instance = self
+            .instances
+            .get(*region_id as usize)
+            .ok_or("Unknown region.".to_string())?;
+        instance.lock().unwrap().replay(log)
     }
 
     /// Send a redraw tick to all instances.
     pub fn redraw_tick(&self) {
-        self.instances.par_iter().for_each(|instance| {
-            instance.lock().unwrap().redraw_tick();
-        });
+        self.instances
+            .par_iter()
+            .enumerate()
+            .filter(|(index, _)| !self.closed_regions.contains(&(*index as u32)))
+            .for_each(|(_, instance)| {
+                instance.lock().unwrap().redraw_tick();
+            });
     }
 
     /// Process a set of commands from a client.
@@ -233,6 +415,96 @@ impl Server {
         }
     }
 
+    /// Get the dialogue prompts for a given region and clear them.
+    pub fn get_dialogues(&mut self, region_id: &Uuid) -> Vec<DialoguePrompt> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.dialogues.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Get the quest journal of the given entity.
+    pub fn get_journal(&self, entity_id: u32) -> Option<&Vec<JournalEntry>> {
+        self.journals.get(&entity_id)
+    }
+
+    /// Get the combat events for a given region and clear them.
+    pub fn get_combat_events(&mut self, region_id: &Uuid) -> Vec<CombatEvent> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.combat_events.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Get the sounds for a given region and clear them.
+    pub fn get_sounds(&mut self, region_id: &Uuid) -> Vec<SoundEvent> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.sounds.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Get the particle effects for a given region and clear them.
+    pub fn get_particles(&mut self, region_id: &Uuid) -> Vec<ParticleEvent> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.particles.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Get the fog of war updates of the players of a given region and clear them.
+    pub fn get_fog(&mut self, region_id: &Uuid) -> Vec<FogUpdate> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.fog.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Get the floating texts for a given region and clear them.
+    pub fn get_float_texts(&mut self, region_id: &Uuid) -> Vec<FloatText> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.float_texts.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Get the cutscene camera hints for a given region and clear them.
+    pub fn get_camera_hints(&mut self, region_id: &Uuid) -> Vec<CameraHint> {
+        if let Some(region_id) = self.region_id_map.get(region_id) {
+            self.camera_hints.remove(region_id).unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Ask all regions for their performance metrics, the answers are available via
+    /// `get_stats` after the next `update`.
+    pub fn request_stats(&self) {
+        if let Ok(pipes) = REGIONPIPE.read() {
+            for (id, sender) in pipes.iter() {
+                _ = sender.send(RegionMessage::Stats(*id, None));
+            }
+        }
+    }
+
+    /// Get the latest performance metrics for the given region.
+    pub fn get_stats(&self, region_id: &Uuid) -> Option<&RegionStats> {
+        let region_id = self.region_id_map.get(region_id)?;
+        self.stats.get(region_id)
+    }
+
+    /// Get the current weather for the given region.
+    pub fn get_weather(&self, region_id: &Uuid) -> Option<WeatherState> {
+        let region_id = self.region_id_map.get(region_id)?;
+        self.weathers.get(region_id).copied()
+    }
+
     /// Get the current time for the given region.
     pub fn get_time(&self, region_id: &Uuid) -> Option<TheTime> {
         if let Some(region_id) = self.region_id_map.get(region_id) {
@@ -262,12 +534,19 @@ impl Server {
     }
 
     /// Retrieves all messages from the regions. Returns the name of the new region should the
-    /// players region change.
+    /// players region change, for instanced regions the name of the instance.
     pub fn update(&mut self, assets: &mut Assets) -> Option<String> {
         let mut rc: Option<String> = None;
 
-        for receiver in &self.from_region {
+        // Transfers into instanced regions may create region instances while receiving
+        let receivers = self.from_region.clone();
+        for receiver in &receivers {
             while let Ok(message) = receiver.try_recv() {
+                if self.remote {
+                    if let Some(message) = NetMessage::from_region(&message) {
+                        self.remote_messages.push(message);
+                    }
+                }
                 match message {
                     RegionMessage::RegisterPlayer(region_id, entity_id) => {
                         if let Ok(mut players) = LOCAL_PLAYERS.write() {
@@ -353,15 +632,64 @@ impl Server {
                             self.multiple_choice.insert(choices.region, multi_choice);
                         }
                     }
+                    RegionMessage::Dialogue(prompt) => {
+                        self.dialogues
+                            .entry(prompt.region)
+                            .or_default()
+                            .push(prompt);
+                    }
+                    RegionMessage::Journal(_, entity_id, journal) => {
+                        self.journals.insert(entity_id, journal);
+                    }
+                    RegionMessage::Combat(event) => {
+                        self.combat_events
+                            .entry(event.region)
+                            .or_default()
+                            .push(event);
+                    }
+                    RegionMessage::Sound(sound) => {
+                        self.sounds.entry(sound.region).or_default().push(sound);
+                    }
+                    RegionMessage::Particles(event) => {
+                        self.particles.entry(event.region).or_default().push(event);
+                    }
+                    RegionMessage::FloatText(text) => {
+                        self.float_texts.entry(text.region).or_default().push(text);
+                    }
+                    RegionMessage::CameraHint(hint) => {
+                        self.camera_hints.entry(hint.region).or_default().push(hint);
+                    }
+                    RegionMessage::Fog(update) => {
+                        self.fog.entry(update.region).or_default().push(update);
+                    }
                     RegionMessage::Time(id, time) => {
                         self.times.insert(id, time);
                     }
+                    RegionMessage::Stats(id, Some(stats)) => {
+                        self.stats.insert(id, *stats);
+                    }
+                    RegionMessage::Weather(id, weather) => {
+                        self.weathers.insert(id, weather);
+                    }
                     RegionMessage::TransferEntity(
                         from_region_id,
                         entity,
                         dest_region_name,
                         dest_sector_name,
                     ) => {
+                        // Entities entering an instanced region go to their instance
+                        let dest_region_name = if self.is_instance_template(&dest_region_name) {
+                            match self.enter_instance(&dest_region_name, entity.id, assets) {
+                                Ok(instance_name) => instance_name,
+                                Err(err) => {
+                                    self.log_message(err);
+                                    dest_region_name
+                                }
+                            }
+                        } else {
+                            dest_region_name
+                        };
+
                         // If we cannot find the destination region, send the entity back from where it came
                         let mut dest_id = from_region_id;
                         if let Some(region_id) = self.region_name_id_map.get(&dest_region_name) {
@@ -419,9 +747,138 @@ impl Server {
             }
         }
 
+        if self.persistence.is_some() && self.last_persisted.elapsed() >= self.persistence_interval
+        {
+            self.persist();
+        }
+
         rc
     }
 
+    /// Collect the region messages for remote clients during update.
+    pub fn enable_remote(&mut self) {
+        self.remote = true;
+    }
+
+    /// Get the region messages collected for remote clients and clear them.
+    pub fn take_remote_messages(&mut self) -> Vec<NetMessage> {
+        std::mem::take(&mut self.remote_messages)
+    }
+
+    /// Send a message to the given region instance.
+    pub fn send_to_region(&self, region_id: u32, message: RegionMessage) {
+        if let Ok(pipe) = REGIONPIPE.read() {
+            if let Some(sender) = pipe.get(&region_id) {
+                if let Err(err) = sender.send(message) {
+                    println!("{:?}", err.to_string());
+                }
+            }
+        }
+    }
+
+    /// Remove the entity from the local players, i.e. when it is controlled by a remote client.
+    pub fn unregister_local_player(&mut self, entity_id: u32) {
+        if let Ok(mut players) = LOCAL_PLAYERS.write() {
+            players.retain(|(_, id)| *id != entity_id);
+        }
+    }
+
+    /// Capture the state of all region instances: the changes to their maps, entity and item
+    /// attributes, inventories, wallets, the time and pending notifications.
+    pub fn get_game_state(&self) -> GameState {
+        let mut names: Vec<(&String, &u32)> = self.region_name_id_map.iter().collect();
+        names.sort_by_key(|(_, id)| **id);
+
+        GameState {
+            version: SAVEGAME_VERSION,
+            regions: names
+                .into_iter()
+                .filter_map(|(name, id)| {
+                    let instance = self.instances.get(*id as usize)?.lock().ok()?;
+                    RegionState::capture(*id, name.clone(), instance.dirty_snapshot())
+                })
+                .collect(),
+            local_players: LOCAL_PLAYERS
+                .read()
+                .map(|players| players.iter().map(|(_, id)| *id).collect())
+                .unwrap_or_default(),
+            next_id: crate::server::region::peek_global_id(),
+        }
+    }
+
+    /// Restore a state captured by `get_game_state` into the running region instances. Regions are
+    /// matched by name, regions which do not exist on this server are skipped.
+    pub fn set_game_state(&mut self, state: GameState) {
+        crate::server::region::reserve_global_ids(state.next_id);
+
+        let mut players = vec![];
+        for region in state.regions {
+            let Some(region_id) = self.region_name_id_map.get(&region.name).copied() else {
+                self.log_message(format!(
+                    "Save game: Region '{}' does not exist, skipped.",
+                    region.name
+                ));
+                continue;
+            };
+
+            let authored = self
+                .instances
+                .get(region_id as usize)
+                .and_then(|instance| instance.lock().ok())
+                .map(|instance| instance.authored_map().clone())
+                .unwrap_or_default();
+            let ticks = region.ticks;
+            if let Some(remap) = region.restore(region_id, &authored) {
+                // The regions resend their entities and items with the next redraw tick
+                self.entities.remove(&region_id);
+                self.items.remove(&region_id);
+                self.messages.remove(&region_id);
+                self.multiple_choice.remove(&region_id);
+                self.dialogues.remove(&region_id);
+                crate::server::region::with_regionctx(region_id, |ctx| {
+                    self.times
+                        .insert(region_id, TheTime::from_ticks(ticks, ctx.ticks_per_minute));
+                    for entity in &ctx.map.entities {
+                        let is_local = state
+                            .local_players
+                            .iter()
+                            .any(|id| remap.get(id).unwrap_or(id) == &entity.id);
+                        if is_local {
+                            players.push((region_id, entity.id));
+                        }
+                    }
+                });
+            }
+        }
+
+        if let Ok(mut local_players) = LOCAL_PLAYERS.write() {
+            *local_players = players;
+        }
+    }
+
+    /// Save the state of all region instances to the given file.
+    pub fn save_state(&self, path: &std::path::Path) -> Result<(), String> {
+        let json = self.get_game_state().to_json()?;
+        std::fs::write(path, json).map_err(|err| err.to_string())
+    }
+
+    /// Load a state saved by `save_state` into the running region instances.
+    pub fn load_state(&mut self, path: &std::path::Path) -> Result<(), String> {
+        let json = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        self.set_game_state(GameState::from_json(&json)?);
+        Ok(())
+    }
+
+    /// Add a message to the log.
+    fn log_message(&mut self, message: String) {
+        if self.log.is_empty() {
+            self.log = message;
+        } else {
+            self.log += &format!("{}{}", "\n", message);
+        }
+        self.log_changed = true;
+    }
+
     /// Update existing entities (or create new ones if they do not exist).
     pub fn process_entity_updates(
         entities: &mut Vec<Entity>,
@@ -552,6 +1009,7 @@ impl Server {
 
     /// Shuts down all region instances.
     pub fn stop(&mut self) {
+        self.persist();
         if let Ok(pipes) = REGIONPIPE.read() {
             for sender in pipes.values() {
                 _ = sender.send(RegionMessage::Quit);
@@ -571,12 +1029,27 @@ impl Server {
         self.entities.clear();
         self.items.clear();
         self.messages.clear();
+        self.dialogues.clear();
+        self.journals.clear();
+        self.combat_events.clear();
+        self.sounds.clear();
+        self.particles.clear();
+        self.float_texts.clear();
+        self.camera_hints.clear();
+        self.fog.clear();
+        self.stats.clear();
         self.id_gen = 0;
         self.region_id_map.clear();
         self.region_name_id_map.clear();
         self.state = ServerState::Off;
         self.from_region.clear();
         self.times.clear();
+        self.remote_messages.clear();
+        self.persistence_caches.clear();
+        self.instance_templates.clear();
+        self.instances_of.clear();
+        self.instance_members.clear();
+        self.closed_regions.clear();
         self.clear_log();
 
         // Clear the store
@@ -592,3 +1065,14 @@ impl Server {
         id
     }
 }
+
+/// Read a table of the config into its rules, a missing table uses the defaults.
+pub fn config_section<T: DeserializeOwned + Default>(
+    config: &toml::Table,
+    key: &str,
+) -> Result<T, String> {
+    match config.get(key) {
+        Some(table) => table.clone().try_into().map_err(|err| err.to_string()),
+        None => Ok(T::default()),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::vm::VMValue;
+use crate::{Entity, MovementMode, MovementModes, RegionCtx, Value};
+use theframework::prelude::*;
+
+/// The speed factors of the movement modes, read from the `[movement]` table of the game
+/// config. See `src/server/CONFIG.md` for an example.
+///
+/// Entities list the modes they are capable of in their `movement` attribute (i.e. "swim" for
+/// fish or "fly" for birds, default "walk, swim, climb") and can override the factor of a mode
+/// with a `<mode>_speed` attribute. Sectors with the `water` or `ladder` property are swum or
+/// climbed. The current mode is kept in the `movement_mode` attribute, a change sends a
+/// `movement_changed` event with the new mode.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MovementRules {
+    pub walk: f32,
+    pub swim: f32,
+    pub climb: f32,
+    pub fly: f32,
+}
+
+impl Default for MovementRules {
+    fn default() -> Self {
+        Self {
+            walk: 1.0,
+            swim: 0.5,
+            climb: 0.4,
+            fly: 1.2,
+        }
+    }
+}
+
+/// The movement modes the entity is capable of.
+pub fn movement_modes(entity: &Entity) -> MovementModes {
+    match entity.attributes.get_str("movement") {
+        Some(modes) => MovementModes::parse(modes),
+        None => MovementModes::default(),
+    }
+}
+
+/// The mode of the entity at its position. Entities which ended up where they cannot be
+/// (i.e. placed there) keep their first capable mode.
+pub fn movement_mode(ctx: &RegionCtx, entity: &Entity) -> MovementMode {
+    let modes = movement_modes(entity);
+    ctx.mapmini
+        .mode_at(entity.get_pos_xz(), &modes)
+        .unwrap_or(if modes.fly {
+            MovementMode::Fly
+        } else if modes.walk {
+            MovementMode::Walk
+        } else {
+            MovementMode::Swim
+        })
+}
+
+/// The speed factor of the entity in its current mode.
+pub fn speed_factor(ctx: &RegionCtx, entity: &Entity) -> f32 {
+    let mode = movement_mode(ctx, entity);
+    let default = match mode {
+        MovementMode::Walk => ctx.movement.walk,
+        MovementMode::Swim => ctx.movement.swim,
+        MovementMode::Climb => ctx.movement.climb,
+        MovementMode::Fly => ctx.movement.fly,
+    };
+    entity
+        .attributes
+        .get_float_default(&format!("{}_speed", mode.as_str()), default)
+}
+
+/// Update the `movement_mode` attribute after the entity moved and send the
+/// `movement_changed` event if it changed. The first mode is set without an event.
+pub fn update_movement_mode(ctx: &mut RegionCtx, entity: &mut Entity) {
+    let mode = movement_mode(ctx, entity).as_str();
+    let changed = match entity.attributes.get_str("movement_mode") {
+        Some(current) if current == mode => return,
+        current => current.is_some(),
+    };
+    entity.set_attribute("movement_mode", Value::Str(mode.into()));
+    if changed {
+        ctx.to_execute_entity
+            .push((entity.id, "movement_changed".into(), VMValue::from(mode)));
+    }
+}
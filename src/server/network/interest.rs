@@ -0,0 +1,127 @@
+use crate::Map;
+use crate::server::region::with_regionctx;
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// Limits the entities and items sent to a session to the surroundings of its player: all
+/// within the radius and, with `sector_graph`, all in the sector of the player and the
+/// sectors sharing a linedef with it.
+#[derive(Debug, Clone)]
+pub struct InterestRules {
+    pub radius: f32,
+    pub sector_graph: bool,
+}
+
+impl Default for InterestRules {
+    fn default() -> Self {
+        Self {
+            radius: 20.0,
+            sector_graph: true,
+        }
+    }
+}
+
+/// The sectors connected to each sector by a shared linedef.
+pub fn sector_graph(map: &Map) -> FxHashMap<u32, FxHashSet<u32>> {
+    let mut graph: FxHashMap<u32, FxHashSet<u32>> = FxHashMap::default();
+    for linedef in &map.linedefs {
+        for a in &linedef.sector_ids {
+            for b in &linedef.sector_ids {
+                if a != b {
+                    graph.entry(*a).or_default().insert(*b);
+                }
+            }
+        }
+    }
+    graph
+}
+
+/// The positions and sectors of the entities and items of a region.
+#[derive(Debug, Clone, Default)]
+pub struct RegionView {
+    pub entities: Vec<(u32, Vec2<f32>, Option<u32>)>,
+    pub items: Vec<(u32, Vec2<f32>, Option<u32>)>,
+}
+
+impl RegionView {
+    /// Capture the view of the region, the sectors are only looked up if needed.
+    pub fn capture(region_id: u32, sectors: bool) -> Self {
+        with_regionctx(region_id, |ctx| {
+            let sector_at = |position: Vec2<f32>| {
+                if sectors {
+                    ctx.map.find_sector_at(position).map(|sector| sector.id)
+                } else {
+                    None
+                }
+            };
+            Self {
+                entities: ctx
+                    .map
+                    .entities
+                    .iter()
+                    .map(|entity| {
+                        let position = entity.get_pos_xz();
+                        (entity.id, position, sector_at(position))
+                    })
+                    .collect(),
+                items: ctx
+                    .map
+                    .items
+                    .iter()
+                    .map(|item| {
+                        let position = item.get_pos_xz();
+                        (item.id, position, sector_at(position))
+                    })
+                    .collect(),
+            }
+        })
+        .unwrap_or_default()
+    }
+
+    /// The entities and items visible to the player. Without a player everything is visible.
+    pub fn visible(
+        &self,
+        rules: &InterestRules,
+        graph: &FxHashMap<u32, FxHashSet<u32>>,
+        player: Option<u32>,
+    ) -> (FxHashSet<u32>, FxHashSet<u32>) {
+        let Some((player, position, sector)) =
+            player.and_then(|id| self.entities.iter().find(|(e, _, _)| *e == id))
+        else {
+            return (
+                self.entities.iter().map(|(id, _, _)| *id).collect(),
+                self.items.iter().map(|(id, _, _)| *id).collect(),
+            );
+        };
+
+        let mut sectors = FxHashSet::default();
+        if rules.sector_graph {
+            if let Some(sector) = sector {
+                sectors.insert(*sector);
+                if let Some(neighbors) = graph.get(sector) {
+                    sectors.extend(neighbors.iter().copied());
+                }
+            }
+        }
+        let radius_sq = rules.radius * rules.radius;
+        let is_visible = |(_, pos, sector): &&(u32, Vec2<f32>, Option<u32>)| {
+            pos.distance_squared(*position) <= radius_sq
+                || sector.is_some_and(|sector| sectors.contains(&sector))
+        };
+
+        let mut entities: FxHashSet<u32> = self
+            .entities
+            .iter()
+            .filter(is_visible)
+            .map(|(id, _, _)| *id)
+            .collect();
+        entities.insert(*player);
+        let items = self
+            .items
+            .iter()
+            .filter(is_visible)
+            .map(|(id, _, _)| *id)
+            .collect();
+        (entities, items)
+    }
+}
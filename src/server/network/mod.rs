@@ -0,0 +1,1247 @@
+pub mod interest;
+pub mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tcp;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod udp;
+#[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+pub mod websocket;
+
+use crate::server::Message;
+use crate::server::network::interest::{InterestRules, RegionView, sector_graph};
+use crate::server::network::protocol::{Snapshot, SnapshotDecoder, SnapshotDelta, SnapshotEncoder};
+use crate::server::region::with_regionctx;
+use crate::{
+    Assets, CameraHint, Choice, CombatEvent, DialoguePrompt, Entity, EntityAction, FloatText,
+    FogUpdate, Item, JournalEntry, Map, MultipleChoice, ParticleEvent, RegionMessage, SoundEvent,
+    WeatherState,
+};
+use crate::{EntityUpdate, ItemUpdate, Server, Value};
+use crossbeam_channel::{Receiver, Sender, TryRecvError, unbounded};
+use instant::Instant;
+use std::time::Duration;
+use theframework::prelude::*;
+
+/// Identifies a connection of a transport. Client transports only have connection 0.
+pub type ConnectionId = u32;
+
+/// The messages exchanged between a Server and its remote clients.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NetMessage {
+    /// Client: Join the region of the given name. Carries the session of a previous
+    /// connection when reconnecting.
+    Hello(Option<Uuid>, String),
+    /// Server: The session, the RegionId and the player entity if the session already has one.
+    Welcome(Uuid, u32, Option<u32>),
+    /// Server: The request could not be handled.
+    Error(String),
+    /// Client: Create a player entity of the given class in the region of the session. The
+    /// server builds it from the player start of the class in the map.
+    CreatePlayer(String),
+    /// Server: The player entity of the session was created. RegionId, EntityId
+    RegisterPlayer(u32, u32),
+    /// Client: An event of the player entity.
+    UserEvent(String, Value),
+    /// Client: An action of the player entity.
+    UserAction(EntityAction),
+    /// Server: Entity updates for a given region
+    EntitiesUpdate(u32, Vec<Vec<u8>>),
+    /// Server: Item updates for a given region
+    ItemsUpdate(u32, Vec<Vec<u8>>),
+    /// Server: Remove the given item from the region
+    RemoveItem(u32, u32),
+    /// Server: Entities and items which left the interest area of the player, they still
+    /// exist in the region. RegionId, EntityIds, ItemIds
+    LeftInterest(u32, Vec<u32>, Vec<u32>),
+    /// Server: RegionId, SenderId_entity, SenderId_item, ReceiverId, Message, Category
+    Message(u32, Option<u32>, Option<u32>, u32, String, String),
+    /// Server: Multiple choices. RegionId, From, To, Choices
+    MultipleChoice(u32, u32, u32, Vec<Choice>),
+    /// Server: The current node of a dialogue.
+    Dialogue(DialoguePrompt),
+    /// Server: The quest journal of an entity.
+    Journal(u32, u32, Vec<JournalEntry>),
+    /// Server: A hit, miss or kill.
+    Combat(CombatEvent),
+    /// Server: A positional sound.
+    Sound(SoundEvent),
+    /// Server: A particle effect.
+    Particles(ParticleEvent),
+    /// Server: A text floating above an entity.
+    FloatText(FloatText),
+    /// Server: A cutscene camera hint for a player.
+    CameraHint(CameraHint),
+    /// Server: The field of view of a player.
+    Fog(FogUpdate),
+    /// Server: The time of a region in minutes.
+    Time(u32, i64),
+    /// Server: The weather of a region.
+    Weather(u32, WeatherState),
+    /// Server: The entities and items of the region as a delta against an acknowledged snapshot.
+    Snapshot(SnapshotDelta),
+    /// Client: The snapshot with the given sequence was received.
+    Ack(u32),
+    Ping,
+    Pong,
+}
+
+impl NetMessage {
+    /// The wire messages for the region messages remote clients are interested in.
+    pub fn from_region(message: &RegionMessage) -> Option<Self> {
+        match message {
+            RegionMessage::RegisterPlayer(region_id, entity_id) => {
+                Some(NetMessage::RegisterPlayer(*region_id, *entity_id))
+            }
+            RegionMessage::EntitiesUpdate(region_id, updates) => {
+                Some(NetMessage::EntitiesUpdate(*region_id, updates.clone()))
+            }
+            RegionMessage::ItemsUpdate(region_id, updates) => {
+                Some(NetMessage::ItemsUpdate(*region_id, updates.clone()))
+            }
+            RegionMessage::RemoveItem(region_id, item_id) => {
+                Some(NetMessage::RemoveItem(*region_id, *item_id))
+            }
+            RegionMessage::Message(region_id, entity, item, receiver, message, category) => {
+                Some(NetMessage::Message(
+                    *region_id,
+                    *entity,
+                    *item,
+                    *receiver,
+                    message.clone(),
+                    category.clone(),
+                ))
+            }
+            RegionMessage::MultipleChoice(choices) => Some(NetMessage::MultipleChoice(
+                choices.region,
+                choices.from,
+                choices.to,
+                choices.choices.clone(),
+            )),
+            RegionMessage::Dialogue(prompt) => Some(NetMessage::Dialogue(prompt.clone())),
+            RegionMessage::Journal(region_id, entity_id, journal) => {
+                Some(NetMessage::Journal(*region_id, *entity_id, journal.clone()))
+            }
+            RegionMessage::Combat(event) => Some(NetMessage::Combat(event.clone())),
+            RegionMessage::Sound(sound) => Some(NetMessage::Sound(sound.clone())),
+            RegionMessage::Particles(event) => Some(NetMessage::Particles(event.clone())),
+            RegionMessage::FloatText(text) => Some(NetMessage::FloatText(text.clone())),
+            RegionMessage::CameraHint(hint) => Some(NetMessage::CameraHint(hint.clone())),
+            RegionMessage::Fog(update) => Some(NetMessage::Fog(update.clone())),
+            RegionMessage::Time(region_id, time) => {
+                Some(NetMessage::Time(*region_id, time.total_minutes() as i64))
+            }
+            RegionMessage::Weather(region_id, weather) => {
+                Some(NetMessage::Weather(*region_id, *weather))
+            }
+            _ => None,
+        }
+    }
+
+    /// The region a server message belongs to.
+    pub fn region_id(&self) -> Option<u32> {
+        match self {
+            NetMessage::Welcome(_, region_id, _)
+            | NetMessage::RegisterPlayer(region_id, _)
+            | NetMessage::EntitiesUpdate(region_id, _)
+            | NetMessage::ItemsUpdate(region_id, _)
+            | NetMessage::RemoveItem(region_id, _)
+            | NetMessage::LeftInterest(region_id, ..)
+            | NetMessage::Message(region_id, ..)
+            | NetMessage::MultipleChoice(region_id, ..)
+            | NetMessage::Journal(region_id, ..)
+            | NetMessage::Time(region_id, _)
+            | NetMessage::Weather(region_id, _) => Some(*region_id),
+            NetMessage::Snapshot(delta) => Some(delta.region_id),
+            NetMessage::Dialogue(prompt) => Some(prompt.region),
+            NetMessage::Combat(event) => Some(event.region),
+            NetMessage::Sound(sound) => Some(sound.region),
+            NetMessage::Particles(event) => Some(event.region),
+            NetMessage::FloatText(text) => Some(text.region),
+            NetMessage::CameraHint(hint) => Some(hint.region),
+            NetMessage::Fog(update) => Some(update.region),
+            _ => None,
+        }
+    }
+
+    /// Serialize (pack) the message using bincode.
+    pub fn pack(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap_or_default()
+    }
+
+    /// Deserialize (unpack) a message, returns None for invalid data.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        bincode::deserialize(data).ok()
+    }
+}
+
+/// Events reported by a transport.
+#[derive(Debug, Clone)]
+pub enum TransportEvent {
+    Connected(ConnectionId),
+    Disconnected(ConnectionId),
+    Message(ConnectionId, NetMessage),
+}
+
+/// Moves NetMessages between a Server and its clients. Server transports address clients by
+/// connection, client transports have a single connection with id 0.
+pub trait Transport: Send {
+    /// Send a message to the given connection. Fails if the transport can not carry the
+    /// message, delivery itself is best-effort.
+    fn send(&mut self, connection: ConnectionId, message: &NetMessage) -> Result<(), String>;
+
+    /// Returns all events since the last call.
+    fn poll(&mut self) -> Vec<TransportEvent>;
+
+    /// Close the given connection.
+    fn close(&mut self, _connection: ConnectionId) {}
+
+    /// Re-establish the connection of a client transport. Returns false if not supported.
+    fn reconnect(&mut self) -> bool {
+        false
+    }
+}
+
+/// An in-process transport over crossbeam channels. Messages are serialized like on the wire.
+pub struct ChannelTransport {
+    sender: Option<Sender<Vec<u8>>>,
+    receiver: Receiver<Vec<u8>>,
+    connected: bool,
+    closed: bool,
+}
+
+impl ChannelTransport {
+    /// Create a connected server and client transport.
+    pub fn pair() -> (Self, Self) {
+        let (to_client, from_server) = unbounded();
+        let (to_server, from_client) = unbounded();
+        (
+            Self::new(to_client, from_client),
+            Self::new(to_server, from_server),
+        )
+    }
+
+    fn new(sender: Sender<Vec<u8>>, receiver: Receiver<Vec<u8>>) -> Self {
+        Self {
+            sender: Some(sender),
+            receiver,
+            connected: false,
+            closed: false,
+        }
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, _connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        if let Some(sender) = &self.sender {
+            _ = sender.send(message.pack());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let mut events = vec![];
+        if self.closed {
+            return events;
+        }
+        if !self.connected {
+            self.connected = true;
+            events.push(TransportEvent::Connected(0));
+        }
+        loop {
+            match self.receiver.try_recv() {
+                Ok(data) => {
+                    if let Some(message) = NetMessage::unpack(&data) {
+                        events.push(TransportEvent::Message(0, message));
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    events.push(TransportEvent::Disconnected(0));
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    fn close(&mut self, _connection: ConnectionId) {
+        self.sender = None;
+    }
+}
+
+/// The player entity of the given class, built from its player start in the map of the
+/// region. The client only chooses the class, never the id, attributes or position.
+fn player_start(region_id: u32, class_name: &str) -> Option<Entity> {
+    with_regionctx(region_id, |ctx| {
+        ctx.player_starts
+            .iter()
+            .find(|entity| entity.get_attr_string("class_name").as_deref() == Some(class_name))
+            .cloned()
+    })
+    .flatten()
+}
+
+/// Check an action sent by a client. Only the actions a client sends are accepted, the ids of
+/// the player in their payload have to be the player entity of the session.
+fn player_action(action: EntityAction, entity_id: u32) -> Option<EntityAction> {
+    let valid = match &action {
+        EntityAction::Off
+        | EntityAction::Left
+        | EntityAction::Forward
+        | EntityAction::Right
+        | EntityAction::Backward
+        | EntityAction::ItemClicked(..)
+        | EntityAction::EntityClicked(..)
+        | EntityAction::TerrainClicked(..)
+        | EntityAction::Intent(..)
+        | EntityAction::Buy(..)
+        | EntityAction::Sell(..)
+        | EntityAction::Text(..) => true,
+        EntityAction::Analog(direction) => direction.x.is_finite() && direction.y.is_finite(),
+        EntityAction::Choice(Choice::ItemToSell(_, _, buyer_id)) => *buyer_id == entity_id,
+        EntityAction::Choice(Choice::Dialogue(_, listener_id, _)) => *listener_id == entity_id,
+        EntityAction::Choice(Choice::Cancel(_, to_id)) => *to_id == entity_id,
+        EntityAction::SleepAndSwitch(..)
+        | EntityAction::RandomWalk(..)
+        | EntityAction::RandomWalkInSector(..)
+        | EntityAction::Goto(..)
+        | EntityAction::CloseIn(..)
+        | EntityAction::FollowPath(..)
+        | EntityAction::Follow(..) => false,
+    };
+    valid.then_some(action)
+}
+
+/// The state of a remote player. Sessions outlive their connection for
+/// `NetworkServer::session_timeout` so clients can reconnect to their player.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: Uuid,
+    pub connection: Option<ConnectionId>,
+    pub region_id: u32,
+    /// The player entity, once the region created it.
+    pub entity_id: Option<u32>,
+    pub disconnected_at: Option<Instant>,
+}
+
+/// Serves the regions of a Server to remote clients over a transport.
+pub struct NetworkServer {
+    transport: Box<dyn Transport>,
+
+    /// Send delta compressed snapshots instead of forwarding the entity and item updates.
+    snapshots: bool,
+    encoders: FxHashMap<Uuid, SnapshotEncoder>,
+
+    pub sessions: FxHashMap<Uuid, Session>,
+    connections: FxHashMap<ConnectionId, Uuid>,
+
+    /// Sessions waiting for their player entity, in request order.
+    pending_players: Vec<Uuid>,
+
+    /// Only send the entities and items near the player of a session.
+    interest: Option<InterestRules>,
+    // The sector graph of each region with the map revision it was built from
+    sector_graphs: FxHashMap<u32, (u32, FxHashMap<u32, FxHashSet<u32>>)>,
+    /// The entities and items each session currently receives.
+    visible: FxHashMap<Uuid, (FxHashSet<u32>, FxHashSet<u32>)>,
+
+    pub session_timeout: Duration,
+}
+
+impl NetworkServer {
+    /// Serve the given Server over the transport, enables remote forwarding on the server.
+    pub fn new(transport: Box<dyn Transport>, server: &mut Server) -> Self {
+        server.enable_remote();
+        Self {
+            transport,
+            snapshots: false,
+            encoders: FxHashMap::default(),
+            sessions: FxHashMap::default(),
+            connections: FxHashMap::default(),
+            pending_players: vec![],
+            interest: None,
+            sector_graphs: FxHashMap::default(),
+            visible: FxHashMap::default(),
+            session_timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Send delta compressed snapshots of the region state to each session, using the builder
+    /// pattern. Recommended for the UDP transport.
+    pub fn snapshots(mut self, snapshots: bool) -> Self {
+        self.snapshots = snapshots;
+        self
+    }
+
+    /// Only send the entities and items near the player to each session instead of the whole
+    /// region, using the builder pattern.
+    pub fn interest(mut self, interest: Option<InterestRules>) -> Self {
+        self.interest = interest;
+        self
+    }
+
+    /// Process client messages and forward the region messages collected by the server.
+    /// Call this after `Server::update`.
+    pub fn update(&mut self, server: &mut Server) {
+        for event in self.transport.poll() {
+            match event {
+                TransportEvent::Connected(_) => {}
+                TransportEvent::Disconnected(connection) => {
+                    if let Some(session_id) = self.connections.remove(&connection) {
+                        if let Some(session) = self.sessions.get_mut(&session_id) {
+                            session.connection = None;
+                            session.disconnected_at = Some(Instant::now());
+                        }
+                    }
+                }
+                TransportEvent::Message(connection, message) => {
+                    self.handle(connection, message, server);
+                }
+            }
+        }
+
+        // Drop sessions which did not reconnect in time, their entities stay in the region
+        let timeout = self.session_timeout;
+        self.sessions.retain(|_, session| {
+            session
+                .disconnected_at
+                .is_none_or(|time| time.elapsed() < timeout)
+        });
+        let sessions = &self.sessions;
+        self.pending_players.retain(|id| sessions.contains_key(id));
+        self.encoders.retain(|id, _| sessions.contains_key(id));
+        self.visible.retain(|id, _| sessions.contains_key(id));
+
+        self.update_interest(server);
+
+        for message in server.take_remote_messages() {
+            if let NetMessage::RegisterPlayer(region_id, entity_id) = message {
+                let index = self.pending_players.iter().position(|id| {
+                    self.sessions
+                        .get(id)
+                        .is_some_and(|session| session.region_id == region_id)
+                });
+                if let Some(index) = index {
+                    let session_id = self.pending_players.remove(index);
+                    server.unregister_local_player(entity_id);
+                    if let Some(session) = self.sessions.get_mut(&session_id) {
+                        session.entity_id = Some(entity_id);
+                        if let Some(connection) = session.connection {
+                            send(self.transport.as_mut(), connection, &message);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if self.snapshots
+                && matches!(
+                    message,
+                    NetMessage::EntitiesUpdate(..) | NetMessage::ItemsUpdate(..)
+                )
+            {
+                continue;
+            }
+
+            // The ids of the updates, to filter them by the interest of the sessions
+            let ids: Option<Vec<u32>> = match &message {
+                NetMessage::EntitiesUpdate(_, updates) if self.interest.is_some() => Some(
+                    updates
+                        .iter()
+                        .map(|data| EntityUpdate::unpack(data).id)
+                        .collect(),
+                ),
+                NetMessage::ItemsUpdate(_, updates) if self.interest.is_some() => Some(
+                    updates
+                        .iter()
+                        .map(|data| ItemUpdate::unpack(data).id)
+                        .collect(),
+                ),
+                _ => None,
+            };
+
+            let region_id = message.region_id();
+            for session in self.sessions.values() {
+                if let Some(connection) = session.connection {
+                    if region_id.is_none() || region_id == Some(session.region_id) {
+                        match (&message, &ids, self.visible.get(&session.id)) {
+                            (
+                                NetMessage::EntitiesUpdate(region_id, updates),
+                                Some(ids),
+                                Some((entities, _)),
+                            ) => {
+                                let updates = filter_updates(updates, ids, entities);
+                                if !updates.is_empty() {
+                                    send(
+                                        self.transport.as_mut(),
+                                        connection,
+                                        &NetMessage::EntitiesUpdate(*region_id, updates),
+                                    );
+                                }
+                            }
+                            (
+                                NetMessage::ItemsUpdate(region_id, updates),
+                                Some(ids),
+                                Some((_, items)),
+                            ) => {
+                                let updates = filter_updates(updates, ids, items);
+                                if !updates.is_empty() {
+                                    send(
+                                        self.transport.as_mut(),
+                                        connection,
+                                        &NetMessage::ItemsUpdate(*region_id, updates),
+                                    );
+                                }
+                            }
+                            _ => send(self.transport.as_mut(), connection, &message),
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.snapshots {
+            self.send_snapshots(server);
+        }
+    }
+
+    /// Send the snapshot of its region to every connected session. Regions are only captured
+    /// if a session is interested in them.
+    fn send_snapshots(&mut self, server: &Server) {
+        let mut snapshots: FxHashMap<u32, Snapshot> = FxHashMap::default();
+        for session in self.sessions.values() {
+            let Some(connection) = session.connection else {
+                continue;
+            };
+            let mut snapshot = snapshots
+                .entry(session.region_id)
+                .or_insert_with(|| {
+                    Snapshot::capture(
+                        session.region_id,
+                        server
+                            .entities
+                            .get(&session.region_id)
+                            .map(Vec::as_slice)
+                            .unwrap_or_default(),
+                        server
+                            .items
+                            .get(&session.region_id)
+                            .map(Vec::as_slice)
+                            .unwrap_or_default(),
+                    )
+                })
+                .clone();
+            if let Some((entities, items)) = self.visible.get(&session.id) {
+                snapshot.entities.retain(|id, _| entities.contains(id));
+                snapshot.items.retain(|id, _| items.contains(id));
+            }
+            let encoder = self.encoders.entry(session.id).or_default();
+            if let Some(delta) = encoder.encode(snapshot) {
+                send(
+                    self.transport.as_mut(),
+                    connection,
+                    &NetMessage::Snapshot(delta),
+                );
+            }
+        }
+    }
+
+    fn handle(&mut self, connection: ConnectionId, message: NetMessage, server: &mut Server) {
+        if let NetMessage::Hello(session_id, region_name) = message {
+            let Some(region_id) = server.region_name_id_map.get(&region_name).copied() else {
+                send(
+                    self.transport.as_mut(),
+                    connection,
+                    &NetMessage::Error(format!("Unknown region '{}'", region_name)),
+                );
+                return;
+            };
+
+            // Reattach to a previous session or start a new one
+            let session = match session_id.and_then(|id| self.sessions.get_mut(&id)) {
+                Some(session) if session.connection.is_none() => {
+                    session.connection = Some(connection);
+                    session.disconnected_at = None;
+                    session.clone()
+                }
+                _ => {
+                    let session = Session {
+                        id: Uuid::new_v4(),
+                        connection: Some(connection),
+                        region_id,
+                        entity_id: None,
+                        disconnected_at: None,
+                    };
+                    self.sessions.insert(session.id, session.clone());
+                    session
+                }
+            };
+            self.connections.insert(connection, session.id);
+
+            send(
+                self.transport.as_mut(),
+                connection,
+                &NetMessage::Welcome(session.id, session.region_id, session.entity_id),
+            );
+            if self.snapshots {
+                // The client starts without baselines, the next snapshot is sent in full
+                self.encoders.entry(session.id).or_default().reset();
+            } else if self.interest.is_some() {
+                // The next update sends the visible entities and items in full
+                self.visible.remove(&session.id);
+            } else {
+                self.send_snapshot(connection, session.region_id, server);
+            }
+            return;
+        }
+
+        if let NetMessage::Ping = message {
+            send(self.transport.as_mut(), connection, &NetMessage::Pong);
+            return;
+        }
+
+        let Some(session) = self
+            .connections
+            .get(&connection)
+            .and_then(|id| self.sessions.get(id))
+        else {
+            send(
+                self.transport.as_mut(),
+                connection,
+                &NetMessage::Error("No session".into()),
+            );
+            return;
+        };
+        let region_id = session.region_id;
+
+        if let NetMessage::Ack(sequence) = message {
+            if let Some(encoder) = self.encoders.get_mut(&session.id) {
+                encoder.ack(sequence);
+            }
+            return;
+        }
+
+        match (message, session.entity_id) {
+            (NetMessage::CreatePlayer(class_name), None) => {
+                if self.pending_players.contains(&session.id) {
+                    return;
+                }
+                match player_start(region_id, &class_name) {
+                    Some(entity) => {
+                        self.pending_players.push(session.id);
+                        server.send_to_region(
+                            region_id,
+                            RegionMessage::CreateEntity(region_id, entity),
+                        );
+                    }
+                    None => send(
+                        self.transport.as_mut(),
+                        connection,
+                        &NetMessage::Error(format!("Unknown player class '{}'", class_name)),
+                    ),
+                }
+            }
+            (NetMessage::UserEvent(event, value), Some(entity_id)) => {
+                server.send_to_region(region_id, RegionMessage::UserEvent(entity_id, event, value));
+            }
+            (NetMessage::UserAction(action), Some(entity_id)) => {
+                match player_action(action, entity_id) {
+                    Some(action) => server
+                        .send_to_region(region_id, RegionMessage::UserAction(entity_id, action)),
+                    None => send(
+                        self.transport.as_mut(),
+                        connection,
+                        &NetMessage::Error("Invalid action".into()),
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Update the entities and items each session receives. Without snapshots the ones entering
+    /// the interest area are sent in full and the ones leaving it are removed from the client.
+    fn update_interest(&mut self, server: &Server) {
+        let Some(rules) = &self.interest else {
+            return;
+        };
+        let mut views: FxHashMap<u32, RegionView> = FxHashMap::default();
+        for session in self.sessions.values() {
+            let Some(connection) = session.connection else {
+                continue;
+            };
+            let region_id = session.region_id;
+            let view = views
+                .entry(region_id)
+                .or_insert_with(|| RegionView::capture(region_id, rules.sector_graph));
+            let revision = with_regionctx(region_id, |ctx| ctx.map_revision).unwrap_or_default();
+            let (graph_revision, graph) = self.sector_graphs.entry(region_id).or_default();
+            if *graph_revision != revision {
+                *graph =
+                    with_regionctx(region_id, |ctx| sector_graph(&ctx.map)).unwrap_or_default();
+                *graph_revision = revision;
+            }
+            let visible = view.visible(rules, graph, session.entity_id);
+            let old = self.visible.remove(&session.id).unwrap_or_default();
+
+            if !self.snapshots {
+                let messages = interest_changes(
+                    region_id,
+                    server
+                        .entities
+                        .get(&region_id)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default(),
+                    server
+                        .items
+                        .get(&region_id)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default(),
+                    &old,
+                    &visible,
+                );
+                for message in messages {
+                    send(self.transport.as_mut(), connection, &message);
+                }
+            }
+            self.visible.insert(session.id, visible);
+        }
+    }
+
+    /// Send the full state of the entities and items of the region to a new connection.
+    fn send_snapshot(&mut self, connection: ConnectionId, region_id: u32, server: &Server) {
+        if let Some(entities) = server.entities.get(&region_id) {
+            let updates = entities
+                .iter()
+                .map(|entity| {
+                    let mut entity = entity.clone();
+                    entity.mark_all_dirty();
+                    entity.get_update().pack()
+                })
+                .collect();
+            send(
+                self.transport.as_mut(),
+                connection,
+                &NetMessage::EntitiesUpdate(region_id, updates),
+            );
+        }
+        if let Some(items) = server.items.get(&region_id) {
+            let updates = items
+                .iter()
+                .map(|item| {
+                    let mut item = item.clone();
+                    item.mark_all_dirty();
+                    item.get_update().pack()
+                })
+                .collect();
+            send(
+                self.transport.as_mut(),
+                connection,
+                &NetMessage::ItemsUpdate(region_id, updates),
+            );
+        }
+    }
+}
+
+/// Keep the updates whose id is visible, the ids are in the order of the updates.
+fn filter_updates(updates: &[Vec<u8>], ids: &[u32], visible: &FxHashSet<u32>) -> Vec<Vec<u8>> {
+    updates
+        .iter()
+        .zip(ids)
+        .filter(|(_, id)| visible.contains(id))
+        .map(|(data, _)| data.clone())
+        .collect()
+}
+
+/// The messages which update a client from the old to the new visible entities and items: the
+/// ones entering the interest area are sent in full, the ones leaving it are removed.
+fn interest_changes(
+    region_id: u32,
+    entities: &[Entity],
+    items: &[Item],
+    old: &(FxHashSet<u32>, FxHashSet<u32>),
+    visible: &(FxHashSet<u32>, FxHashSet<u32>),
+) -> Vec<NetMessage> {
+    let mut messages = vec![];
+
+    let entered: Vec<Vec<u8>> = entities
+        .iter()
+        .filter(|e| visible.0.contains(&e.id) && !old.0.contains(&e.id))
+        .map(|entity| {
+            let mut entity = entity.clone();
+            entity.mark_all_dirty();
+            entity.get_update().pack()
+        })
+        .collect();
+    if !entered.is_empty() {
+        messages.push(NetMessage::EntitiesUpdate(region_id, entered));
+    }
+    let entered: Vec<Vec<u8>> = items
+        .iter()
+        .filter(|i| visible.1.contains(&i.id) && !old.1.contains(&i.id))
+        .map(|item| {
+            let mut item = item.clone();
+            item.mark_all_dirty();
+            item.get_update().pack()
+        })
+        .collect();
+    if !entered.is_empty() {
+        messages.push(NetMessage::ItemsUpdate(region_id, entered));
+    }
+
+    let left_entities: Vec<u32> = old.0.difference(&visible.0).copied().collect();
+    let left_items: Vec<u32> = old.1.difference(&visible.1).copied().collect();
+    if !left_entities.is_empty() || !left_items.is_empty() {
+        messages.push(NetMessage::LeftInterest(
+            region_id,
+            left_entities,
+            left_items,
+        ));
+    }
+    messages
+}
+
+/// Send a message over the transport, a message the transport can not carry only affects the
+/// one connection and is logged.
+fn send(transport: &mut dyn Transport, connection: ConnectionId, message: &NetMessage) {
+    if let Err(err) = transport.send(connection, message) {
+        eprintln!("{}", err);
+    }
+}
+
+/// The client side of a remote Server. Mirrors the entities, items, messages and time of the
+/// joined region and reconnects to the same session after connection loss.
+pub struct NetworkClient {
+    transport: Box<dyn Transport>,
+    region_name: String,
+
+    pub session: Option<Uuid>,
+    pub region_id: Option<u32>,
+    pub player_id: Option<u32>,
+    pub connected: bool,
+
+    pub entities: Vec<Entity>,
+    pub items: Vec<Item>,
+    messages: Vec<Message>,
+    choices: Vec<MultipleChoice>,
+    dialogues: Vec<DialoguePrompt>,
+    combat_events: Vec<CombatEvent>,
+    sounds: Vec<SoundEvent>,
+    particles: Vec<ParticleEvent>,
+    float_texts: Vec<FloatText>,
+    camera_hints: Vec<CameraHint>,
+    fog: Vec<FogUpdate>,
+    /// The quest journal of the player.
+    pub journal: Vec<JournalEntry>,
+    time: Option<TheTime>,
+    weather: Option<WeatherState>,
+    pub errors: Vec<String>,
+
+    player_class: Option<String>,
+    disconnected_at: Option<Instant>,
+    decoder: SnapshotDecoder,
+    snapshot: Option<Snapshot>,
+    pub reconnect_delay: Duration,
+}
+
+impl NetworkClient {
+    /// Join the region of the given name over the transport.
+    pub fn new(transport: Box<dyn Transport>, region_name: impl Into<String>) -> Self {
+        Self {
+            transport,
+            region_name: region_name.into(),
+
+            session: None,
+            region_id: None,
+            player_id: None,
+            connected: false,
+
+            entities: vec![],
+            items: vec![],
+            messages: vec![],
+            choices: vec![],
+            dialogues: vec![],
+            combat_events: vec![],
+            sounds: vec![],
+            particles: vec![],
+            float_texts: vec![],
+            camera_hints: vec![],
+            fog: vec![],
+            journal: vec![],
+            time: None,
+            weather: None,
+            errors: vec![],
+
+            player_class: None,
+            disconnected_at: None,
+            decoder: SnapshotDecoder::default(),
+            snapshot: None,
+            reconnect_delay: Duration::from_secs(2),
+        }
+    }
+
+    /// Request a player entity of the given class. It is created once the session is
+    /// established.
+    pub fn create_player(&mut self, class_name: String) {
+        if self.connected && self.region_id.is_some() && self.player_id.is_none() {
+            self.send(&NetMessage::CreatePlayer(class_name.clone()));
+        }
+        self.player_class = Some(class_name);
+    }
+
+    /// Process all received messages and reconnect if the connection was lost.
+    pub fn update(&mut self, assets: &mut Assets) {
+        for event in self.transport.poll() {
+            match event {
+                TransportEvent::Connected(_) => {
+                    self.connected = true;
+                    self.disconnected_at = None;
+                    self.send_hello();
+                }
+                TransportEvent::Disconnected(_) => {
+                    self.connected = false;
+                    self.disconnected_at = Some(Instant::now());
+                }
+                TransportEvent::Message(_, message) => self.handle(message, assets),
+            }
+        }
+
+        if let Some(time) = self.disconnected_at {
+            if time.elapsed() >= self.reconnect_delay {
+                self.disconnected_at = if self.transport.reconnect() {
+                    Some(Instant::now())
+                } else {
+                    None
+                };
+            }
+        }
+    }
+
+    /// Send a message to the server, failures are reported in `errors`.
+    fn send(&mut self, message: &NetMessage) {
+        if let Err(err) = self.transport.send(0, message) {
+            self.errors.push(err);
+        }
+    }
+
+    fn send_hello(&mut self) {
+        self.send(&NetMessage::Hello(self.session, self.region_name.clone()));
+    }
+
+    fn handle(&mut self, message: NetMessage, assets: &mut Assets) {
+        match message {
+            NetMessage::Welcome(session, region_id, player_id) => {
+                // A new session on a reconnect means the server forgot us, start over
+                if self.session != Some(session) {
+                    self.entities.clear();
+                    self.items.clear();
+                }
+                self.decoder.reset();
+                self.snapshot = None;
+                self.session = Some(session);
+                self.region_id = Some(region_id);
+                self.player_id = player_id;
+                if player_id.is_none() {
+                    if let Some(class_name) = self.player_class.clone() {
+                        self.send(&NetMessage::CreatePlayer(class_name));
+                    }
+                }
+            }
+            NetMessage::Error(error) => self.errors.push(error),
+            NetMessage::RegisterPlayer(_, entity_id) => self.player_id = Some(entity_id),
+            NetMessage::EntitiesUpdate(_, updates) => {
+                let updates = updates
+                    .iter()
+                    .map(|data| EntityUpdate::unpack(data))
+                    .collect();
+                Server::process_entity_updates(&mut self.entities, updates, assets);
+            }
+            NetMessage::ItemsUpdate(_, updates) => {
+                let updates = updates
+                    .iter()
+                    .map(|data| ItemUpdate::unpack(data))
+                    .collect();
+                Server::process_item_updates(&mut self.items, updates);
+            }
+            NetMessage::RemoveItem(_, item_id) => self.items.retain(|item| item.id != item_id),
+            NetMessage::LeftInterest(_, entity_ids, item_ids) => {
+                self.entities
+                    .retain(|entity| !entity_ids.contains(&entity.id));
+                self.items.retain(|item| !item_ids.contains(&item.id));
+            }
+            NetMessage::Message(_, entity, item, receiver, message, category) => {
+                self.messages
+                    .push((entity, item, receiver, message, category));
+            }
+            NetMessage::MultipleChoice(region, from, to, choices) => {
+                let mut multiple_choice = MultipleChoice::new(region, from, to);
+                multiple_choice.choices = choices;
+                self.choices.push(multiple_choice);
+            }
+            NetMessage::Dialogue(prompt) => self.dialogues.push(prompt),
+            NetMessage::Combat(event) => self.combat_events.push(event),
+            NetMessage::Sound(sound) => self.sounds.push(sound),
+            NetMessage::Particles(event) => self.particles.push(event),
+            NetMessage::FloatText(text) => self.float_texts.push(text),
+            NetMessage::CameraHint(hint) => {
+                if self.player_id == Some(hint.player) {
+                    self.camera_hints.push(hint);
+                }
+            }
+            NetMessage::Fog(update) => {
+                if self.player_id == Some(update.player) {
+                    self.fog.push(update);
+                }
+            }
+            NetMessage::Journal(_, entity_id, journal) => {
+                if self.player_id == Some(entity_id) {
+                    self.journal = journal;
+                }
+            }
+            NetMessage::Time(_, minutes) => self.time = Some(TheTime::from_ticks(minutes, 1)),
+            NetMessage::Weather(_, weather) => self.weather = Some(weather),
+            NetMessage::Snapshot(delta) => {
+                if let Some(snapshot) = self.decoder.decode(&delta) {
+                    self.send(&NetMessage::Ack(snapshot.sequence));
+                    self.apply_snapshot(snapshot, assets);
+                }
+            }
+            NetMessage::Ping => self.send(&NetMessage::Pong),
+            _ => {}
+        }
+    }
+
+    /// Apply the states which differ from the previous snapshot and remove missing elements.
+    fn apply_snapshot(&mut self, snapshot: Snapshot, assets: &mut Assets) {
+        let previous = self.snapshot.take().unwrap_or_default();
+
+        let updates = snapshot
+            .entities
+            .iter()
+            .filter(|(id, data)| previous.entities.get(id) != Some(data))
+            .map(|(_, data)| EntityUpdate::unpack(data))
+            .collect();
+        self.entities
+            .retain(|entity| snapshot.entities.contains_key(&entity.id));
+        Server::process_entity_updates(&mut self.entities, updates, assets);
+
+        let updates = snapshot
+            .items
+            .iter()
+            .filter(|(id, data)| previous.items.get(id) != Some(data))
+            .map(|(_, data)| ItemUpdate::unpack(data))
+            .collect();
+        self.items
+            .retain(|item| snapshot.items.contains_key(&item.id));
+        Server::process_item_updates(&mut self.items, updates);
+
+        self.snapshot = Some(snapshot);
+    }
+
+    /// Send an action of the player entity.
+    pub fn player_action(&mut self, action: EntityAction) {
+        if self.connected {
+            self.send(&NetMessage::UserAction(action));
+        }
+    }
+
+    /// Send an event of the player entity.
+    pub fn player_event(&mut self, event: String, value: Value) {
+        if self.connected {
+            self.send(&NetMessage::UserEvent(event, value));
+        }
+    }
+
+    /// Apply the mirrored entities and items to the map of the region.
+    pub fn apply_entities_items(&self, map: &mut Map) {
+        map.entities = self.entities.clone();
+        map.items = self.items.clone();
+    }
+
+    /// Get the received messages and clear them.
+    pub fn get_messages(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// Get the received multiple choices and clear them.
+    pub fn get_choices(&mut self) -> Vec<MultipleChoice> {
+        std::mem::take(&mut self.choices)
+    }
+
+    /// Get the received dialogue prompts and clear them.
+    pub fn get_dialogues(&mut self) -> Vec<DialoguePrompt> {
+        std::mem::take(&mut self.dialogues)
+    }
+
+    /// Get the received combat events and clear them.
+    pub fn get_combat_events(&mut self) -> Vec<CombatEvent> {
+        std::mem::take(&mut self.combat_events)
+    }
+
+    /// Get the received sounds and clear them.
+    pub fn get_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// Get the received particle effects and clear them.
+    pub fn get_particles(&mut self) -> Vec<ParticleEvent> {
+        std::mem::take(&mut self.particles)
+    }
+
+    /// Get the received fog of war updates of the player and clear them.
+    pub fn get_fog(&mut self) -> Vec<FogUpdate> {
+        std::mem::take(&mut self.fog)
+    }
+
+    /// Get the received floating texts and clear them.
+    pub fn get_float_texts(&mut self) -> Vec<FloatText> {
+        std::mem::take(&mut self.float_texts)
+    }
+
+    /// Get the received cutscene camera hints of the player and clear them.
+    pub fn get_camera_hints(&mut self) -> Vec<CameraHint> {
+        std::mem::take(&mut self.camera_hints)
+    }
+
+    /// The last received time of the region.
+    pub fn get_time(&self) -> Option<TheTime> {
+        self.time
+    }
+
+    /// The last received weather of the region.
+    pub fn get_weather(&self) -> Option<WeatherState> {
+        self.weather
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vek::Vec2;
+
+    #[test]
+    fn player_actions_are_bound_to_the_session() {
+        let player = 7;
+        let other = 8;
+
+        let buy = EntityAction::Choice(Choice::ItemToSell(1, 2, player));
+        assert_eq!(player_action(buy.clone(), player), Some(buy));
+        let buy_for_other = EntityAction::Choice(Choice::ItemToSell(1, 2, other));
+        assert_eq!(player_action(buy_for_other, player), None);
+
+        let answer = EntityAction::Choice(Choice::Dialogue(2, other, 0));
+        assert_eq!(player_action(answer, player), None);
+        let cancel = EntityAction::Choice(Choice::Cancel(2, other));
+        assert_eq!(player_action(cancel, player), None);
+
+        // Movement only the server issues is rejected
+        let sleep = EntityAction::SleepAndSwitch(100, Box::new(EntityAction::Off));
+        assert_eq!(player_action(sleep, player), None);
+        let goto = EntityAction::Goto(Vec2::new(100.0, 100.0), 50.0);
+        assert_eq!(player_action(goto, player), None);
+        let path = EntityAction::FollowPath(vec![Vec2::new(100.0, 100.0)], 50.0);
+        assert_eq!(player_action(path, player), None);
+        assert_eq!(
+            player_action(EntityAction::CloseIn(2, 0.5, 50.0), player),
+            None
+        );
+        assert_eq!(player_action(EntityAction::Follow(2, 50.0), player), None);
+
+        let analog = EntityAction::Analog(Vec2::new(0.5, -1.0));
+        assert_eq!(player_action(analog.clone(), player), Some(analog));
+        let analog = EntityAction::Analog(Vec2::new(f32::NAN, 1.0));
+        assert_eq!(player_action(analog, player), None);
+        let analog = EntityAction::Analog(Vec2::new(0.0, f32::INFINITY));
+        assert_eq!(player_action(analog, player), None);
+
+        assert_eq!(
+            player_action(EntityAction::Forward, player),
+            Some(EntityAction::Forward)
+        );
+    }
+
+    fn entity(id: u32) -> Entity {
+        let mut entity = Entity::new();
+        entity.id = id;
+        entity
+    }
+
+    #[test]
+    fn entering_and_leaving_interest() {
+        let rules = InterestRules {
+            radius: 5.0,
+            sector_graph: false,
+        };
+        let graph = FxHashMap::default();
+        let mut view = RegionView {
+            entities: vec![
+                (1, Vec2::new(0.0, 0.0), None),
+                (2, Vec2::new(20.0, 0.0), None),
+            ],
+            items: vec![(3, Vec2::new(2.0, 0.0), None)],
+        };
+        let entities = vec![entity(1), entity(2)];
+        let mut item = Item::new();
+        item.id = 3;
+        let items = vec![item];
+
+        // The player and the item near it enter
+        let visible = view.visible(&rules, &graph, Some(1));
+        let messages = interest_changes(0, &entities, &items, &Default::default(), &visible);
+        match &messages[..] {
+            [
+                NetMessage::EntitiesUpdate(0, entered_entities),
+                NetMessage::ItemsUpdate(0, entered_items),
+            ] => {
+                assert_eq!(EntityUpdate::unpack(&entered_entities[0]).id, 1);
+                assert_eq!(entered_entities.len(), 1);
+                assert_eq!(ItemUpdate::unpack(&entered_items[0]).id, 3);
+            }
+            messages => panic!("Unexpected messages {:?}", messages),
+        }
+
+        // The other entity comes close, the item is carried away
+        view.entities[1].1 = Vec2::new(3.0, 0.0);
+        view.items[0].1 = Vec2::new(30.0, 0.0);
+        let old = visible;
+        let visible = view.visible(&rules, &graph, Some(1));
+        let messages = interest_changes(0, &entities, &items, &old, &visible);
+        match &messages[..] {
+            [
+                NetMessage::EntitiesUpdate(0, entered),
+                NetMessage::LeftInterest(0, left_entities, left_items),
+            ] => {
+                assert_eq!(entered.len(), 1);
+                assert_eq!(EntityUpdate::unpack(&entered[0]).id, 2);
+                assert!(left_entities.is_empty());
+                assert_eq!(left_items, &vec![3]);
+            }
+            messages => panic!("Unexpected messages {:?}", messages),
+        }
+
+        assert!(interest_changes(0, &entities, &items, &visible, &visible).is_empty());
+    }
+
+    #[test]
+    fn sector_graph_interest() {
+        let rules = InterestRules {
+            radius: 5.0,
+            sector_graph: true,
+        };
+        let mut graph: FxHashMap<u32, FxHashSet<u32>> = FxHashMap::default();
+        graph.insert(10, [11].into_iter().collect());
+        let view = RegionView {
+            entities: vec![
+                (1, Vec2::new(0.0, 0.0), Some(10)),
+                (2, Vec2::new(20.0, 0.0), Some(11)),
+                (3, Vec2::new(40.0, 0.0), Some(12)),
+            ],
+            items: vec![],
+        };
+
+        let (entities, _) = view.visible(&rules, &graph, Some(1));
+        let expected: FxHashSet<u32> = [1, 2].into_iter().collect();
+        assert_eq!(entities, expected);
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{Entity, Item};
+use std::collections::VecDeque;
+use theframework::prelude::*;
+
+/// The amount of sent / received snapshots kept as delta baselines.
+const HISTORY: usize = 32;
+
+/// The full state of the entities and items of a region, packed as full updates by id.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub sequence: u32,
+    pub region_id: u32,
+    pub entities: FxHashMap<u32, Vec<u8>>,
+    pub items: FxHashMap<u32, Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Capture the entities and items of a region.
+    pub fn capture(region_id: u32, entities: &[Entity], items: &[Item]) -> Self {
+        Self {
+            sequence: 0,
+            region_id,
+            entities: entities
+                .iter()
+                .map(|entity| {
+                    let mut entity = entity.clone();
+                    entity.mark_all_dirty();
+                    (entity.id, entity.get_update().pack())
+                })
+                .collect(),
+            items: items
+                .iter()
+                .map(|item| {
+                    let mut item = item.clone();
+                    item.mark_all_dirty();
+                    (item.id, item.get_update().pack())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The packed state of an entity or item relative to the baseline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Delta {
+    /// The complete state, for new elements or if the size changed.
+    Full(Vec<u8>),
+    /// The run length encoded XOR against the baseline state.
+    Xor(Vec<u8>),
+}
+
+impl Delta {
+    /// Encode the current state against the baseline state.
+    pub fn encode(baseline: Option<&[u8]>, current: &[u8]) -> Self {
+        match baseline {
+            Some(baseline) if baseline.len() == current.len() => {
+                let xor: Vec<u8> = baseline.iter().zip(current).map(|(a, b)| a ^ b).collect();
+                Delta::Xor(rle_encode(&xor))
+            }
+            _ => Delta::Full(current.to_vec()),
+        }
+    }
+
+    /// Decode the state, returns None if the baseline is missing or does not match.
+    pub fn decode(&self, baseline: Option<&[u8]>) -> Option<Vec<u8>> {
+        match self {
+            Delta::Full(data) => Some(data.clone()),
+            Delta::Xor(data) => {
+                let baseline = baseline?;
+                let xor = rle_decode(data, baseline.len())?;
+                Some(baseline.iter().zip(&xor).map(|(a, b)| a ^ b).collect())
+            }
+        }
+    }
+}
+
+/// Encode runs of zero bytes as (zero count, literal count, literals) pairs.
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let mut zeros = 0;
+        while i < data.len() && data[i] == 0 && zeros < 255 {
+            zeros += 1;
+            i += 1;
+        }
+        let start = i;
+        while i < data.len() && data[i] != 0 && i - start < 255 {
+            i += 1;
+        }
+        out.push(zeros as u8);
+        out.push((i - start) as u8);
+        out.extend_from_slice(&data[start..i]);
+    }
+    out
+}
+
+fn rle_decode(data: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i + 1 < data.len() {
+        let zeros = data[i] as usize;
+        let literals = data[i + 1] as usize;
+        i += 2;
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(data.get(i..i + literals)?);
+        i += literals;
+    }
+    (out.len() == len).then_some(out)
+}
+
+/// The changes of a region snapshot against a baseline snapshot the client acknowledged.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SnapshotDelta {
+    pub sequence: u32,
+    pub baseline: Option<u32>,
+    pub region_id: u32,
+    pub entities: Vec<(u32, Delta)>,
+    pub removed_entities: Vec<u32>,
+    pub items: Vec<(u32, Delta)>,
+    pub removed_items: Vec<u32>,
+}
+
+impl SnapshotDelta {
+    /// True if nothing changed against the baseline.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+            && self.removed_entities.is_empty()
+            && self.items.is_empty()
+            && self.removed_items.is_empty()
+    }
+}
+
+/// Encode the elements of the current state against the baseline state.
+fn encode_elements(
+    baseline: Option<&FxHashMap<u32, Vec<u8>>>,
+    current: &FxHashMap<u32, Vec<u8>>,
+) -> (Vec<(u32, Delta)>, Vec<u32>) {
+    let mut changed = vec![];
+    for (id, data) in current {
+        let base = baseline.and_then(|b| b.get(id));
+        if base != Some(data) {
+            changed.push((*id, Delta::encode(base.map(Vec::as_slice), data)));
+        }
+    }
+    let removed = baseline
+        .map(|b| {
+            b.keys()
+                .filter(|id| !current.contains_key(id))
+                .copied()
+                .collect()
+        })
+        .unwrap_or_default();
+    (changed, removed)
+}
+
+/// Decode the elements against the baseline state, returns None if a delta cannot be applied.
+fn decode_elements(
+    baseline: Option<&FxHashMap<u32, Vec<u8>>>,
+    changed: &[(u32, Delta)],
+    removed: &[u32],
+) -> Option<FxHashMap<u32, Vec<u8>>> {
+    let mut elements = baseline.cloned().unwrap_or_default();
+    for id in removed {
+        elements.remove(id);
+    }
+    for (id, delta) in changed {
+        let data = delta.decode(baseline.and_then(|b| b.get(id)).map(Vec::as_slice))?;
+        elements.insert(*id, data);
+    }
+    Some(elements)
+}
+
+/// Server side: numbers the snapshots of a connection and encodes them against the last
+/// snapshot the client acknowledged.
+#[derive(Default)]
+pub struct SnapshotEncoder {
+    sequence: u32,
+    sent: VecDeque<Snapshot>,
+    acked: Option<Snapshot>,
+}
+
+impl SnapshotEncoder {
+    /// Encode the snapshot. Returns None if the client already acknowledged the same state,
+    /// until then changes are resent with every snapshot so lost packets do not matter.
+    pub fn encode(&mut self, mut snapshot: Snapshot) -> Option<SnapshotDelta> {
+        if self.acked.as_ref().is_some_and(|acked| {
+            acked.region_id == snapshot.region_id
+                && acked.entities == snapshot.entities
+                && acked.items == snapshot.items
+        }) {
+            return None;
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+        snapshot.sequence = self.sequence;
+
+        // The client only keeps the last HISTORY snapshots, if the acks stall the baseline falls
+        // out of its window and the snapshot is sent in full
+        if self
+            .acked
+            .as_ref()
+            .is_some_and(|acked| self.sequence.wrapping_sub(acked.sequence) >= HISTORY as u32)
+        {
+            self.acked = None;
+        }
+
+        // A region change invalidates the baseline
+        let baseline = self
+            .acked
+            .as_ref()
+            .filter(|acked| acked.region_id == snapshot.region_id);
+        let (entities, removed_entities) =
+            encode_elements(baseline.map(|b| &b.entities), &snapshot.entities);
+        let (items, removed_items) = encode_elements(baseline.map(|b| &b.items), &snapshot.items);
+
+        let delta = SnapshotDelta {
+            sequence: snapshot.sequence,
+            baseline: baseline.map(|b| b.sequence),
+            region_id: snapshot.region_id,
+            entities,
+            removed_entities,
+            items,
+            removed_items,
+        };
+
+        self.sent.push_back(snapshot);
+        if self.sent.len() > HISTORY {
+            self.sent.pop_front();
+        }
+        Some(delta)
+    }
+
+    /// The client received the snapshot with the given sequence, use it as the new baseline.
+    pub fn ack(&mut self, sequence: u32) {
+        if let Some(index) = self.sent.iter().position(|s| s.sequence == sequence) {
+            self.acked = self.sent.get(index).cloned();
+            self.sent.drain(..index);
+        }
+    }
+
+    /// Forget all baselines, the next snapshot is sent in full.
+    pub fn reset(&mut self) {
+        self.sent.clear();
+        self.acked = None;
+    }
+}
+
+/// Client side: reconstructs the snapshots from the received deltas.
+#[derive(Default)]
+pub struct SnapshotDecoder {
+    received: VecDeque<Snapshot>,
+    latest: Option<u32>,
+}
+
+impl SnapshotDecoder {
+    /// Decode the delta. Returns None for outdated deltas or if the baseline is unknown.
+    pub fn decode(&mut self, delta: &SnapshotDelta) -> Option<Snapshot> {
+        if let Some(latest) = self.latest {
+            // Sequence numbers wrap, compare by distance
+            if delta.sequence.wrapping_sub(latest) as i32 <= 0 {
+                return None;
+            }
+        }
+
+        let baseline = match delta.baseline {
+            Some(sequence) => Some(self.received.iter().find(|s| s.sequence == sequence)?),
+            None => None,
+        };
+
+        let snapshot = Snapshot {
+            sequence: delta.sequence,
+            region_id: delta.region_id,
+            entities: decode_elements(
+                baseline.map(|b| &b.entities),
+                &delta.entities,
+                &delta.removed_entities,
+            )?,
+            items: decode_elements(
+                baseline.map(|b| &b.items),
+                &delta.items,
+                &delta.removed_items,
+            )?,
+        };
+
+        self.latest = Some(delta.sequence);
+        self.received.push_back(snapshot.clone());
+        if self.received.len() > HISTORY {
+            self.received.pop_front();
+        }
+        Some(snapshot)
+    }
+
+    /// Forget all received snapshots.
+    pub fn reset(&mut self) {
+        self.received.clear();
+        self.latest = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(region_id: u32, entities: &[(u32, Vec<u8>)]) -> Snapshot {
+        Snapshot {
+            sequence: 0,
+            region_id,
+            entities: entities.iter().cloned().collect(),
+            items: FxHashMap::default(),
+        }
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let mut data = vec![0; 600];
+        data.extend([1, 2, 3]);
+        data.extend(vec![0; 10]);
+        data.extend((0..600).map(|i| (i % 255 + 1) as u8));
+        data.push(0);
+
+        for data in [vec![], vec![0], vec![7], data] {
+            let encoded = rle_encode(&data);
+            assert_eq!(rle_decode(&encoded, data.len()), Some(data.clone()));
+        }
+        assert_eq!(rle_decode(&rle_encode(&[0, 1]), 3), None);
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let baseline = vec![1, 2, 3, 4, 5, 6];
+        let current = vec![1, 2, 9, 4, 5, 6];
+
+        let delta = Delta::encode(Some(&baseline), &current);
+        assert!(matches!(delta, Delta::Xor(_)));
+        assert_eq!(delta.decode(Some(&baseline)), Some(current.clone()));
+        assert_eq!(delta.decode(None), None);
+
+        // A size change or a missing baseline sends the full state
+        let longer = vec![1, 2, 3];
+        let delta = Delta::encode(Some(&baseline), &longer);
+        assert_eq!(delta, Delta::Full(longer.clone()));
+        assert_eq!(delta.decode(None), Some(longer));
+        assert_eq!(Delta::encode(None, &current), Delta::Full(current.clone()));
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut encoder = SnapshotEncoder::default();
+        let mut decoder = SnapshotDecoder::default();
+
+        let first = snapshot(1, &[(1, vec![1; 8]), (2, vec![2; 8])]);
+        let delta = encoder.encode(first.clone()).unwrap();
+        assert_eq!(delta.baseline, None);
+        let decoded = decoder.decode(&delta).unwrap();
+        assert_eq!(decoded.entities, first.entities);
+        encoder.ack(decoded.sequence);
+
+        // Unchanged and acknowledged state is not sent again
+        assert!(encoder.encode(first).is_none());
+
+        let second = snapshot(1, &[(1, vec![1, 1, 1, 5, 1, 1, 1, 1]), (3, vec![3; 4])]);
+        let delta = encoder.encode(second.clone()).unwrap();
+        assert_eq!(delta.baseline, Some(decoded.sequence));
+        assert_eq!(delta.removed_entities, vec![2]);
+        assert!(matches!(
+            delta.entities.iter().find(|(id, _)| *id == 1),
+            Some((_, Delta::Xor(_)))
+        ));
+        let decoded = decoder.decode(&delta).unwrap();
+        assert_eq!(decoded.entities, second.entities);
+
+        // Outdated deltas are ignored
+        assert!(decoder.decode(&delta).is_none());
+    }
+
+    #[test]
+    fn snapshot_lost_acks() {
+        let mut encoder = SnapshotEncoder::default();
+        let mut decoder = SnapshotDecoder::default();
+
+        let delta = encoder.encode(snapshot(1, &[(1, vec![0; 8])])).unwrap();
+        let decoded = decoder.decode(&delta).unwrap();
+        encoder.ack(decoded.sequence);
+
+        // All further acks are lost, the client has to be able to decode every snapshot
+        for i in 1..HISTORY as u8 * 3 {
+            let current = snapshot(1, &[(1, vec![i; 8])]);
+            let delta = encoder.encode(current.clone()).unwrap();
+            let decoded = decoder
+                .decode(&delta)
+                .expect("baseline out of the client window");
+            assert_eq!(decoded.entities, current.entities);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::server::network::{ConnectionId, NetMessage, Transport, TransportEvent};
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use theframework::prelude::*;
+
+/// Frames larger than this are treated as a protocol error.
+const MAX_FRAME: usize = 64 * 1024 * 1024;
+
+type Connections = Arc<Mutex<FxHashMap<ConnectionId, Sender<Vec<u8>>>>>;
+
+/// Write a length prefixed frame.
+fn write_frame(stream: &mut TcpStream, data: &[u8]) -> std::io::Result<()> {
+    stream.write_all(&(data.len() as u32).to_le_bytes())?;
+    stream.write_all(data)
+}
+
+/// Read a length prefixed frame. The frame grows with the received bytes, a length prefix alone
+/// does not allocate.
+fn read_frame(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+    let mut data = vec![];
+    stream.by_ref().take(len as u64).read_to_end(&mut data)?;
+    if data.len() < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+/// Serve a connected stream with a reader and a writer thread. Dropping the sender of the
+/// outgoing frames closes the connection.
+fn serve(
+    stream: TcpStream,
+    connection: ConnectionId,
+    outgoing: Receiver<Vec<u8>>,
+    events: Sender<TransportEvent>,
+    on_close: impl FnOnce() + Send + 'static,
+) -> std::io::Result<()> {
+    _ = stream.set_nodelay(true);
+    let mut reader = stream.try_clone()?;
+    let mut writer = stream;
+
+    thread::spawn(move || {
+        for data in outgoing.iter() {
+            if write_frame(&mut writer, &data).is_err() {
+                break;
+            }
+        }
+        // Unblocks the reader
+        _ = writer.shutdown(Shutdown::Both);
+    });
+
+    thread::spawn(move || {
+        _ = events.send(TransportEvent::Connected(connection));
+        while let Ok(data) = read_frame(&mut reader) {
+            if let Some(message) = NetMessage::unpack(&data) {
+                _ = events.send(TransportEvent::Message(connection, message));
+            }
+        }
+        _ = reader.shutdown(Shutdown::Both);
+        on_close();
+        _ = events.send(TransportEvent::Disconnected(connection));
+    });
+
+    Ok(())
+}
+
+/// A server transport sending length prefixed NetMessages over TCP.
+pub struct TcpServerTransport {
+    events: Receiver<TransportEvent>,
+    connections: Connections,
+}
+
+impl TcpServerTransport {
+    /// Listen for clients on the given address, i.e. "0.0.0.0:7879".
+    pub fn bind(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (event_sender, events) = unbounded();
+        let connections: Connections = Arc::new(Mutex::new(FxHashMap::default()));
+
+        let registry = connections.clone();
+        thread::spawn(move || {
+            let mut next_id: ConnectionId = 0;
+            for stream in listener.incoming().flatten() {
+                let connection = next_id;
+                next_id = next_id.wrapping_add(1);
+
+                let (sender, outgoing) = unbounded();
+                registry.lock().unwrap().insert(connection, sender);
+
+                let closed = registry.clone();
+                let on_close = move || {
+                    closed.lock().unwrap().remove(&connection);
+                };
+                if serve(stream, connection, outgoing, event_sender.clone(), on_close).is_err() {
+                    registry.lock().unwrap().remove(&connection);
+                }
+            }
+        });
+
+        Ok(Self {
+            events,
+            connections,
+        })
+    }
+}
+
+impl Transport for TcpServerTransport {
+    fn send(&mut self, connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        if let Some(sender) = self.connections.lock().unwrap().get(&connection) {
+            _ = sender.send(message.pack());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.events.try_iter().collect()
+    }
+
+    fn close(&mut self, connection: ConnectionId) {
+        self.connections.lock().unwrap().remove(&connection);
+    }
+}
+
+/// A client transport connecting to a TcpServerTransport.
+pub struct TcpClientTransport {
+    address: String,
+    events: Receiver<TransportEvent>,
+    event_sender: Sender<TransportEvent>,
+    outgoing: Option<Sender<Vec<u8>>>,
+}
+
+impl TcpClientTransport {
+    /// Connect to the given address, a Connected or Disconnected event reports the result.
+    pub fn connect(address: &str) -> Self {
+        let (event_sender, events) = unbounded();
+        let mut client = Self {
+            address: address.to_string(),
+            events,
+            event_sender,
+            outgoing: None,
+        };
+        client.reconnect();
+        client
+    }
+}
+
+impl Transport for TcpClientTransport {
+    fn send(&mut self, _connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        if let Some(sender) = &self.outgoing {
+            _ = sender.send(message.pack());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.events.try_iter().collect()
+    }
+
+    fn close(&mut self, _connection: ConnectionId) {
+        self.outgoing = None;
+    }
+
+    fn reconnect(&mut self) -> bool {
+        let (sender, outgoing) = unbounded();
+        self.outgoing = Some(sender);
+
+        // Connect in the background, the caller is usually the game loop
+        let address = self.address.clone();
+        let events = self.event_sender.clone();
+        thread::spawn(move || {
+            let served = TcpStream::connect(&address)
+                .and_then(|stream| serve(stream, 0, outgoing, events.clone(), || {}));
+            if served.is_err() {
+                _ = events.send(TransportEvent::Disconnected(0));
+            }
+        });
+        true
+    }
+}
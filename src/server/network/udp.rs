@@ -0,0 +1,635 @@
+use crate::server::network::{ConnectionId, NetMessage, Transport, TransportEvent};
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use instant::Instant;
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use theframework::prelude::*;
+
+/// Identifies rusterix datagrams.
+const MAGIC: [u8; 2] = *b"RX";
+/// Magic, connection nonce, kind and sequence.
+const HEADER: usize = 11;
+
+/// Peers which stay silent longer than this are disconnected.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Idle connections send an empty datagram this often to stay alive.
+const KEEPALIVE: Duration = Duration::from_secs(1);
+/// Reliable datagrams are resent this often until they are acknowledged.
+const RESEND: Duration = Duration::from_millis(200);
+
+/// Reliable datagrams further ahead of the next expected one are dropped unacknowledged.
+const RELIABLE_WINDOW: u32 = 256;
+/// The most bytes of reliable payloads a peer buffers ahead of a missing one.
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+/// Datagrams from new addresses are ignored while the server has this many peers.
+const MAX_PEERS: usize = 1024;
+
+/// The largest payload which fits into a datagram.
+pub const MAX_PAYLOAD: usize = 65507 - HEADER;
+
+/// Wrapping "a is newer than b" for sequence numbers.
+fn newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// How a datagram is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Outdated datagrams are dropped.
+    Unreliable = 0,
+    /// Resent until acknowledged and delivered in order.
+    Reliable = 1,
+    /// Acknowledges the reliable datagram of the sequence.
+    Ack = 2,
+}
+
+/// The header of a datagram. The nonce identifies the connection of a client, a new nonce from
+/// a known address is a new connection.
+struct Header {
+    nonce: u32,
+    kind: Kind,
+    sequence: u32,
+}
+
+impl Header {
+    /// Parse the header, None for foreign datagrams.
+    fn parse(datagram: &[u8]) -> Option<Self> {
+        if datagram.len() < HEADER || datagram[0..2] != MAGIC {
+            return None;
+        }
+        let kind = match datagram[6] {
+            0 => Kind::Unreliable,
+            1 => Kind::Reliable,
+            2 => Kind::Ack,
+            _ => return None,
+        };
+        Some(Self {
+            nonce: u32::from_le_bytes(datagram[2..6].try_into().ok()?),
+            kind,
+            sequence: u32::from_le_bytes(datagram[7..11].try_into().ok()?),
+        })
+    }
+
+    fn datagram(&self, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(HEADER + payload.len());
+        datagram.extend_from_slice(&MAGIC);
+        datagram.extend_from_slice(&self.nonce.to_le_bytes());
+        datagram.push(self.kind as u8);
+        datagram.extend_from_slice(&self.sequence.to_le_bytes());
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+}
+
+/// Snapshots and pings are superseded by the next ones and are not worth resending, all other
+/// messages are sent reliably.
+fn is_reliable(message: &NetMessage) -> bool {
+    !matches!(
+        message,
+        NetMessage::Snapshot(_) | NetMessage::Ack(_) | NetMessage::Ping | NetMessage::Pong
+    )
+}
+
+/// The state of one side of a UDP connection.
+struct Peer {
+    connection: ConnectionId,
+    nonce: u32,
+
+    send_sequence: u32,
+    received_sequence: Option<u32>,
+
+    reliable_sequence: u32,
+    /// The sent reliable datagrams which are not acknowledged yet.
+    unacked: VecDeque<(u32, Vec<u8>, Instant)>,
+    next_reliable: u32,
+    /// The reliable payloads received ahead of a missing one.
+    pending: BTreeMap<u32, Vec<u8>>,
+    pending_bytes: usize,
+
+    last_received: Instant,
+    last_sent: Instant,
+}
+
+impl Peer {
+    fn new(connection: ConnectionId, nonce: u32) -> Self {
+        Self {
+            connection,
+            nonce,
+            send_sequence: 0,
+            received_sequence: None,
+            reliable_sequence: 0,
+            unacked: VecDeque::new(),
+            next_reliable: 1,
+            pending: BTreeMap::new(),
+            pending_bytes: 0,
+            last_received: Instant::now(),
+            last_sent: Instant::now(),
+        }
+    }
+
+    /// Send a datagram, an empty payload is a keepalive.
+    fn send(
+        &mut self,
+        socket: &UdpSocket,
+        address: SocketAddr,
+        payload: &[u8],
+        reliable: bool,
+    ) -> Result<(), String> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(format!(
+                "Message of {} bytes exceeds the UDP limit of {} bytes",
+                payload.len(),
+                MAX_PAYLOAD
+            ));
+        }
+        let datagram = if reliable {
+            self.reliable_sequence = self.reliable_sequence.wrapping_add(1);
+            let datagram = self
+                .header(Kind::Reliable, self.reliable_sequence)
+                .datagram(payload);
+            self.unacked
+                .push_back((self.reliable_sequence, datagram.clone(), Instant::now()));
+            datagram
+        } else {
+            self.send_sequence = self.send_sequence.wrapping_add(1);
+            self.header(Kind::Unreliable, self.send_sequence)
+                .datagram(payload)
+        };
+        // Lost datagrams are handled like dropped ones
+        _ = socket.send_to(&datagram, address);
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Resend the unacknowledged reliable datagrams and keep the connection alive.
+    fn update(&mut self, socket: &UdpSocket, address: SocketAddr) {
+        for (_, datagram, sent) in self.unacked.iter_mut() {
+            if sent.elapsed() >= RESEND {
+                _ = socket.send_to(datagram, address);
+                *sent = Instant::now();
+                self.last_sent = *sent;
+            }
+        }
+        if self.last_sent.elapsed() >= KEEPALIVE {
+            _ = self.send(socket, address, &[], false);
+        }
+    }
+
+    /// Returns the payloads which are ready for delivery, drops duplicate and outdated
+    /// datagrams and acknowledges reliable ones.
+    fn receive(
+        &mut self,
+        socket: &UdpSocket,
+        address: SocketAddr,
+        header: &Header,
+        payload: &[u8],
+    ) -> Vec<Vec<u8>> {
+        self.last_received = Instant::now();
+        match header.kind {
+            Kind::Unreliable => {
+                if self
+                    .received_sequence
+                    .is_some_and(|received| !newer(header.sequence, received))
+                {
+                    return vec![];
+                }
+                self.received_sequence = Some(header.sequence);
+                if payload.is_empty() {
+                    vec![]
+                } else {
+                    vec![payload.to_vec()]
+                }
+            }
+            Kind::Ack => {
+                self.unacked
+                    .retain(|(sequence, _, _)| *sequence != header.sequence);
+                vec![]
+            }
+            Kind::Reliable => {
+                // Already delivered, the ack got lost
+                let delivered = newer(self.next_reliable, header.sequence);
+
+                // Datagrams too far ahead or beyond the buffer are not acknowledged, the
+                // sender resends them
+                let ahead = header.sequence.wrapping_sub(self.next_reliable);
+                if !delivered
+                    && (ahead >= RELIABLE_WINDOW
+                        || (ahead > 0 && self.pending_bytes + payload.len() > MAX_PENDING_BYTES))
+                {
+                    return vec![];
+                }
+
+                let ack = self.header(Kind::Ack, header.sequence).datagram(&[]);
+                _ = socket.send_to(&ack, address);
+                if delivered {
+                    return vec![];
+                }
+                if !self.pending.contains_key(&header.sequence) {
+                    self.pending_bytes += payload.len();
+                    self.pending.insert(header.sequence, payload.to_vec());
+                }
+
+                let mut ready = vec![];
+                while let Some(payload) = self.pending.remove(&self.next_reliable) {
+                    self.pending_bytes -= payload.len();
+                    ready.push(payload);
+                    self.next_reliable = self.next_reliable.wrapping_add(1);
+                }
+                ready
+            }
+        }
+    }
+
+    fn header(&self, kind: Kind, sequence: u32) -> Header {
+        Header {
+            nonce: self.nonce,
+            kind,
+            sequence,
+        }
+    }
+}
+
+/// Read datagrams until the socket fails, calls `on_datagram` for each one and `on_idle`
+/// whenever the read times out.
+fn receive_loop(
+    socket: &UdpSocket,
+    mut on_datagram: impl FnMut(SocketAddr, &[u8]),
+    mut on_idle: impl FnMut() -> bool,
+) {
+    let mut buffer = vec![0; 65536];
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((len, address)) => on_datagram(address, &buffer[..len]),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            // i.e. ICMP port unreachable reported on some platforms
+            Err(_) => {}
+        }
+        if !on_idle() {
+            break;
+        }
+    }
+}
+
+type Peers = Arc<Mutex<FxHashMap<SocketAddr, Peer>>>;
+
+/// A server transport sending NetMessages as datagrams. Snapshots are sequenced and unreliable,
+/// outdated ones are dropped and the deltas tolerate the loss. All other messages are resent
+/// until acknowledged. Messages larger than `MAX_PAYLOAD` are not sent, use the TCP transport
+/// for regions with large states.
+pub struct UdpServerTransport {
+    socket: UdpSocket,
+    events: Receiver<TransportEvent>,
+    peers: Peers,
+    /// Stops the receive thread.
+    stop: Arc<AtomicBool>,
+}
+
+impl UdpServerTransport {
+    /// Listen for clients on the given address, i.e. "0.0.0.0:7880".
+    pub fn bind(address: &str) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        let receiver = socket.try_clone()?;
+
+        let (event_sender, events) = unbounded();
+        let peers: Peers = Arc::new(Mutex::new(FxHashMap::default()));
+
+        let registry = peers.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut next_id: ConnectionId = 0;
+            let on_datagram = |address: SocketAddr, datagram: &[u8]| {
+                let Some(header) = Header::parse(datagram) else {
+                    return;
+                };
+                let mut peers = registry.lock().unwrap();
+
+                // The client reconnected from the same address
+                if peers
+                    .get(&address)
+                    .is_some_and(|peer| peer.nonce != header.nonce)
+                {
+                    if let Some(peer) = peers.remove(&address) {
+                        _ = event_sender.send(TransportEvent::Disconnected(peer.connection));
+                    }
+                }
+
+                // Spoofed source addresses must not add peers without limit
+                if !peers.contains_key(&address) && peers.len() >= MAX_PEERS {
+                    return;
+                }
+
+                let peer = peers.entry(address).or_insert_with(|| {
+                    let peer = Peer::new(next_id, header.nonce);
+                    next_id = next_id.wrapping_add(1);
+                    _ = event_sender.send(TransportEvent::Connected(peer.connection));
+                    peer
+                });
+                for payload in peer.receive(&receiver, address, &header, &datagram[HEADER..]) {
+                    if let Some(message) = NetMessage::unpack(&payload) {
+                        _ = event_sender.send(TransportEvent::Message(peer.connection, message));
+                    }
+                }
+            };
+            let on_idle = || {
+                if stopped.load(Ordering::Relaxed) {
+                    return false;
+                }
+                let mut peers = registry.lock().unwrap();
+                peers.retain(|_, peer| {
+                    let alive = peer.last_received.elapsed() < TIMEOUT;
+                    if !alive {
+                        _ = event_sender.send(TransportEvent::Disconnected(peer.connection));
+                    }
+                    alive
+                });
+                true
+            };
+            receive_loop(&receiver, on_datagram, on_idle);
+        });
+
+        Ok(Self {
+            socket,
+            events,
+            peers,
+            stop,
+        })
+    }
+}
+
+impl Drop for UdpServerTransport {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Transport for UdpServerTransport {
+    fn send(&mut self, connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        let payload = message.pack();
+        let mut peers = self.peers.lock().unwrap();
+        match peers
+            .iter_mut()
+            .find(|(_, peer)| peer.connection == connection)
+        {
+            Some((address, peer)) => {
+                peer.send(&self.socket, *address, &payload, is_reliable(message))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let mut peers = self.peers.lock().unwrap();
+        for (address, peer) in peers.iter_mut() {
+            peer.update(&self.socket, *address);
+        }
+        self.events.try_iter().collect()
+    }
+
+    fn close(&mut self, connection: ConnectionId) {
+        self.peers
+            .lock()
+            .unwrap()
+            .retain(|_, peer| peer.connection != connection);
+    }
+}
+
+/// A client transport talking to a UdpServerTransport.
+pub struct UdpClientTransport {
+    socket: UdpSocket,
+    address: SocketAddr,
+    peer: Arc<Mutex<Peer>>,
+    events: Receiver<TransportEvent>,
+    event_sender: Sender<TransportEvent>,
+    /// Stops the receive thread of the current socket.
+    stop: Arc<AtomicBool>,
+}
+
+impl UdpClientTransport {
+    /// Connect to the server at the given address. UDP is connectionless, the transport reports
+    /// Connected right away and Disconnected once the server stays silent.
+    pub fn connect(address: &str) -> std::io::Result<Self> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or(std::io::ErrorKind::AddrNotAvailable)?;
+
+        let (event_sender, events) = unbounded();
+        let mut client = Self {
+            socket: Self::bind(address)?,
+            address,
+            peer: Arc::new(Mutex::new(Peer::new(0, Self::nonce()))),
+            events,
+            event_sender,
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        client.listen()?;
+        Ok(client)
+    }
+
+    fn bind(server: SocketAddr) -> std::io::Result<UdpSocket> {
+        let local = if server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        Ok(socket)
+    }
+
+    /// A random identifier of the connection.
+    fn nonce() -> u32 {
+        Uuid::new_v4().as_u128() as u32
+    }
+
+    fn listen(&mut self) -> std::io::Result<()> {
+        let receiver = self.socket.try_clone()?;
+        let server = self.address;
+        let peer = self.peer.clone();
+        let events = self.event_sender.clone();
+        let stop = self.stop.clone();
+
+        _ = events.send(TransportEvent::Connected(0));
+        thread::spawn(move || {
+            let on_datagram = |address: SocketAddr, datagram: &[u8]| {
+                if address != server {
+                    return;
+                }
+                let mut peer = peer.lock().unwrap();
+                let Some(header) = Header::parse(datagram).filter(|h| h.nonce == peer.nonce) else {
+                    return;
+                };
+                for payload in peer.receive(&receiver, address, &header, &datagram[HEADER..]) {
+                    if let Some(message) = NetMessage::unpack(&payload) {
+                        _ = events.send(TransportEvent::Message(0, message));
+                    }
+                }
+            };
+            let on_idle = || {
+                if stop.load(Ordering::Relaxed) {
+                    return false;
+                }
+                let alive = peer.lock().unwrap().last_received.elapsed() < TIMEOUT;
+                if !alive {
+                    _ = events.send(TransportEvent::Disconnected(0));
+                }
+                alive
+            };
+            receive_loop(&receiver, on_datagram, on_idle);
+        });
+        Ok(())
+    }
+}
+
+impl Drop for UdpClientTransport {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Transport for UdpClientTransport {
+    fn send(&mut self, _connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        let payload = message.pack();
+        self.peer
+            .lock()
+            .unwrap()
+            .send(&self.socket, self.address, &payload, is_reliable(message))
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.peer.lock().unwrap().update(&self.socket, self.address);
+        self.events.try_iter().collect()
+    }
+
+    fn reconnect(&mut self) -> bool {
+        // Start over on a new socket with a new nonce, the server sees a new connection and
+        // the old one times out.
+        self.stop.store(true, Ordering::Relaxed);
+        let Ok(socket) = Self::bind(self.address) else {
+            return false;
+        };
+        self.socket = socket;
+        self.peer = Arc::new(Mutex::new(Peer::new(0, Self::nonce())));
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.listen().is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket() -> (UdpSocket, SocketAddr) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        (socket, address)
+    }
+
+    fn reliable(peer: &Peer, sequence: u32, payload: &[u8]) -> Vec<u8> {
+        peer.header(Kind::Reliable, sequence).datagram(payload)
+    }
+
+    #[test]
+    fn foreign_datagrams() {
+        assert!(Header::parse(b"hello world").is_none());
+        assert!(Header::parse(b"RX").is_none());
+        let mut datagram = Header {
+            nonce: 1,
+            kind: Kind::Ack,
+            sequence: 2,
+        }
+        .datagram(&[]);
+        assert!(Header::parse(&datagram).is_some());
+        datagram[6] = 9;
+        assert!(Header::parse(&datagram).is_none());
+    }
+
+    #[test]
+    fn reliable_in_order_without_duplicates() {
+        let (socket, address) = socket();
+        let mut peer = Peer::new(0, 42);
+
+        let receive = |peer: &mut Peer, datagram: &[u8]| {
+            let header = Header::parse(datagram).unwrap();
+            peer.receive(&socket, address, &header, &datagram[HEADER..])
+        };
+
+        let second = reliable(&peer, 2, b"b");
+        let first = reliable(&peer, 1, b"a");
+        assert!(receive(&mut peer, &second).is_empty());
+        assert_eq!(
+            receive(&mut peer, &first),
+            vec![b"a".to_vec(), b"b".to_vec()]
+        );
+        // A resent datagram whose ack was lost
+        assert!(receive(&mut peer, &first).is_empty());
+    }
+
+    #[test]
+    fn reliable_window() {
+        let (socket, address) = socket();
+        let mut peer = Peer::new(0, 42);
+
+        let receive = |peer: &mut Peer, datagram: &[u8]| {
+            let header = Header::parse(datagram).unwrap();
+            peer.receive(&socket, address, &header, &datagram[HEADER..])
+        };
+
+        // Far ahead sequences are not buffered
+        assert!(receive(&mut peer, &reliable(&peer, RELIABLE_WINDOW + 1, b"a")).is_empty());
+        assert!(peer.pending.is_empty());
+
+        // The buffer ahead of a missing datagram is capped, the next one is always accepted
+        let large = vec![0; MAX_PAYLOAD];
+        let mut sequence = 2;
+        while peer.pending_bytes + large.len() <= MAX_PENDING_BYTES {
+            receive(&mut peer, &reliable(&peer, sequence, &large));
+            sequence += 1;
+        }
+        let buffered = peer.pending.len();
+        assert!(receive(&mut peer, &reliable(&peer, sequence, &large)).is_empty());
+        assert_eq!(peer.pending.len(), buffered);
+
+        let ready = receive(&mut peer, &reliable(&peer, 1, b"first"));
+        assert_eq!(ready.len(), buffered + 1);
+        assert_eq!(peer.pending_bytes, 0);
+    }
+
+    #[test]
+    fn dropping_the_server_releases_the_port() {
+        let server = UdpServerTransport::bind("127.0.0.1:0").unwrap();
+        let address = server.socket.local_addr().unwrap();
+        drop(server);
+        thread::sleep(Duration::from_millis(300));
+        assert!(UdpSocket::bind(address).is_ok());
+    }
+
+    #[test]
+    fn oversize_and_acked_sends() {
+        let (socket, address) = socket();
+        let mut peer = Peer::new(0, 42);
+
+        assert!(
+            peer.send(&socket, address, &[0; MAX_PAYLOAD + 1], true)
+                .is_err()
+        );
+        assert!(peer.unacked.is_empty());
+
+        assert!(peer.send(&socket, address, b"hello", true).is_ok());
+        assert_eq!(peer.unacked.len(), 1);
+        let ack = peer.header(Kind::Ack, 1).datagram(&[]);
+        let header = Header::parse(&ack).unwrap();
+        peer.receive(&socket, address, &header, &[]);
+        assert!(peer.unacked.is_empty());
+    }
+}
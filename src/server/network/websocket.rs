@@ -0,0 +1,186 @@
+use crate::server::network::{ConnectionId, NetMessage, Transport, TransportEvent};
+use crossbeam_channel::{Receiver, Sender, TryRecvError, unbounded};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use theframework::prelude::*;
+use tungstenite::{Message, WebSocket};
+
+/// How long a connection thread blocks on reading before it sends queued messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+type Connections = Arc<Mutex<FxHashMap<ConnectionId, Sender<Vec<u8>>>>>;
+
+/// Pump messages between a socket and the channels of its transport until the connection closes
+/// or the outgoing channel is dropped.
+fn run_socket<S: Read + Write>(
+    mut socket: WebSocket<S>,
+    connection: ConnectionId,
+    outgoing: Receiver<Vec<u8>>,
+    events: &Sender<TransportEvent>,
+) {
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                Ok(data) => {
+                    if socket.send(Message::binary(data)).is_err() {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    _ = socket.close(None);
+                    _ = socket.flush();
+                    return;
+                }
+            }
+        }
+
+        match socket.read() {
+            Ok(Message::Binary(data)) => {
+                if let Some(message) = NetMessage::unpack(&data) {
+                    _ = events.send(TransportEvent::Message(connection, message));
+                }
+            }
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+/// A server transport accepting WebSocket clients, each connection is served by its own thread.
+pub struct WebSocketServer {
+    events: Receiver<TransportEvent>,
+    connections: Connections,
+}
+
+impl WebSocketServer {
+    /// Listen for clients on the given address, i.e. "0.0.0.0:7878".
+    pub fn bind(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (event_sender, events) = unbounded();
+        let connections: Connections = Arc::new(Mutex::new(FxHashMap::default()));
+
+        let registry = connections.clone();
+        thread::spawn(move || {
+            let mut next_id: ConnectionId = 0;
+            for stream in listener.incoming().flatten() {
+                let connection = next_id;
+                next_id = next_id.wrapping_add(1);
+
+                let events = event_sender.clone();
+                let registry = registry.clone();
+                thread::spawn(move || {
+                    let Ok(socket) = tungstenite::accept(stream) else {
+                        return;
+                    };
+                    _ = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL));
+
+                    let (sender, outgoing) = unbounded();
+                    registry.lock().unwrap().insert(connection, sender);
+                    _ = events.send(TransportEvent::Connected(connection));
+
+                    run_socket(socket, connection, outgoing, &events);
+
+                    registry.lock().unwrap().remove(&connection);
+                    _ = events.send(TransportEvent::Disconnected(connection));
+                });
+            }
+        });
+
+        Ok(Self {
+            events,
+            connections,
+        })
+    }
+}
+
+impl Transport for WebSocketServer {
+    fn send(&mut self, connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        if let Some(sender) = self.connections.lock().unwrap().get(&connection) {
+            _ = sender.send(message.pack());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.events.try_iter().collect()
+    }
+
+    fn close(&mut self, connection: ConnectionId) {
+        // Dropping the sender ends the connection thread
+        self.connections.lock().unwrap().remove(&connection);
+    }
+}
+
+/// A client transport connecting to a WebSocketServer, i.e. "ws://127.0.0.1:7878".
+pub struct WebSocketClient {
+    url: String,
+    events: Receiver<TransportEvent>,
+    event_sender: Sender<TransportEvent>,
+    outgoing: Option<Sender<Vec<u8>>>,
+}
+
+impl WebSocketClient {
+    /// Connect to the given url in the background, a Connected or Disconnected event reports
+    /// the result.
+    pub fn connect(url: &str) -> Self {
+        let (event_sender, events) = unbounded();
+        let mut client = Self {
+            url: url.to_string(),
+            events,
+            event_sender,
+            outgoing: None,
+        };
+        client.reconnect();
+        client
+    }
+}
+
+impl Transport for WebSocketClient {
+    fn send(&mut self, _connection: ConnectionId, message: &NetMessage) -> Result<(), String> {
+        if let Some(sender) = &self.outgoing {
+            _ = sender.send(message.pack());
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.events.try_iter().collect()
+    }
+
+    fn close(&mut self, _connection: ConnectionId) {
+        self.outgoing = None;
+    }
+
+    fn reconnect(&mut self) -> bool {
+        let (sender, outgoing) = unbounded();
+        self.outgoing = Some(sender);
+
+        let url = self.url.clone();
+        let events = self.event_sender.clone();
+        thread::spawn(move || {
+            let host = url
+                .trim_start_matches("ws://")
+                .split('/')
+                .next()
+                .unwrap_or_default();
+            let socket = TcpStream::connect(host)
+                .ok()
+                .and_then(|stream| tungstenite::client(url.as_str(), stream).ok());
+
+            if let Some((socket, _)) = socket {
+                _ = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL));
+                _ = events.send(TransportEvent::Connected(0));
+                run_socket(socket, 0, outgoing, &events);
+            }
+            _ = events.send(TransportEvent::Disconnected(0));
+        });
+        true
+    }
+}
@@ -0,0 +1,224 @@
+use crate::vm::VMValue;
+use crate::{RegionCtx, Value};
+use theframework::prelude::*;
+
+/// Something entities interact with: an item (i.e. a chest) or a sector (i.e. a door).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Interactable {
+    Item(u32),
+    Sector(String),
+}
+
+impl Interactable {
+    /// Script arguments are item ids or sector names.
+    pub fn from_vm(value: &VMValue) -> Self {
+        match value.as_string() {
+            Some(name) => Interactable::Sector(name.to_string()),
+            None => Interactable::Item(value.x as u32),
+        }
+    }
+}
+
+/// The ownership settings, read from the `[ownership]` table of the game config. See
+/// `src/server/CONFIG.md` for an example.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OwnershipRules {
+    pub claim_ticks: i64,
+}
+
+impl Default for OwnershipRules {
+    fn default() -> Self {
+        Self { claim_ticks: 40 }
+    }
+}
+
+/// An exclusive claim on an interactable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Claim {
+    pub entity: u32,
+    /// The tick the claim expires, None until released.
+    pub until: Option<i64>,
+}
+
+/// Read an attribute of the item or a property of the sector.
+fn get_value(ctx: &RegionCtx, target: &Interactable, key: &str) -> Option<Value> {
+    match target {
+        Interactable::Item(id) => ctx
+            .map
+            .items
+            .iter()
+            .find(|item| item.id == *id)?
+            .attributes
+            .get(key)
+            .cloned(),
+        Interactable::Sector(name) => ctx
+            .map
+            .sectors
+            .iter()
+            .find(|sector| sector.name == *name)?
+            .properties
+            .get(key)
+            .cloned(),
+    }
+}
+
+fn set_value(ctx: &mut RegionCtx, target: &Interactable, key: &str, value: Value) -> bool {
+    match target {
+        Interactable::Item(id) => match ctx.get_item_mut(*id) {
+            Some(item) => {
+                item.set_attribute(key, value);
+                true
+            }
+            None => false,
+        },
+        Interactable::Sector(name) => {
+            match ctx
+                .map
+                .sectors
+                .iter_mut()
+                .find(|sector| sector.name == *name)
+            {
+                Some(sector) => {
+                    sector.properties.set(key, value);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+}
+
+fn exists(ctx: &RegionCtx, target: &Interactable) -> bool {
+    match target {
+        Interactable::Item(id) => ctx.map.items.iter().any(|item| item.id == *id),
+        Interactable::Sector(name) => ctx.map.sectors.iter().any(|sector| sector.name == *name),
+    }
+}
+
+/// The entity which currently claims the interactable.
+pub fn claimed_by(ctx: &RegionCtx, target: &Interactable) -> Option<u32> {
+    let claim = ctx.claims.get(target)?;
+    if claim.until.is_some_and(|until| until <= ctx.ticks) {
+        return None;
+    }
+    Some(claim.entity)
+}
+
+/// The owner of the interactable, set by its `owner` attribute or property.
+pub fn owner_of(ctx: &RegionCtx, target: &Interactable) -> Option<u32> {
+    match get_value(ctx, target, "owner")? {
+        Value::Int(id) if id >= 0 => Some(id as u32),
+        Value::UInt(id) => Some(id),
+        _ => None,
+    }
+}
+
+/// True if the entity owns or claims the interactable.
+pub fn is_owned_by(ctx: &RegionCtx, target: &Interactable, entity: u32) -> bool {
+    owner_of(ctx, target) == Some(entity) || claimed_by(ctx, target) == Some(entity)
+}
+
+pub fn is_locked(ctx: &RegionCtx, target: &Interactable) -> bool {
+    matches!(get_value(ctx, target, "locked"), Some(Value::Bool(true)))
+}
+
+/// Claim the interactable so that no other entity can use it until the claim expires or is
+/// released. Claiming again renews the claim.
+pub fn claim(ctx: &mut RegionCtx, entity: u32, target: Interactable) -> Result<(), String> {
+    if !exists(ctx, &target) {
+        return Err(format!("Unknown interactable '{:?}'.", target));
+    }
+    if claimed_by(ctx, &target).is_some_and(|id| id != entity) {
+        return Err("Claimed by another entity.".into());
+    }
+    let until = if ctx.ownership.claim_ticks > 0 {
+        Some(ctx.ticks + ctx.ownership.claim_ticks)
+    } else {
+        None
+    };
+    ctx.claims.insert(target, Claim { entity, until });
+    Ok(())
+}
+
+/// Release the claim of the entity, returns false if it did not claim the interactable.
+pub fn release(ctx: &mut RegionCtx, entity: u32, target: &Interactable) -> bool {
+    if claimed_by(ctx, target) != Some(entity) {
+        return false;
+    }
+    ctx.claims.remove(target);
+    true
+}
+
+/// Check if the entity can use the interactable: it must not be locked or claimed by another
+/// entity. The error is a message key for the player.
+pub fn can_use(ctx: &RegionCtx, entity: u32, target: &Interactable) -> Result<(), String> {
+    if claimed_by(ctx, target).is_some_and(|id| id != entity) {
+        return Err("{claimed}".into());
+    }
+    if is_locked(ctx, target) {
+        return Err("{locked}".into());
+    }
+    Ok(())
+}
+
+/// True if the entity carries an item whose `key` attribute opens the lock of the interactable.
+pub fn has_key(ctx: &RegionCtx, entity: u32, target: &Interactable) -> bool {
+    let Some(Value::Str(lock)) = get_value(ctx, target, "key") else {
+        return false;
+    };
+    ctx.map
+        .entities
+        .iter()
+        .find(|e| e.id == entity)
+        .is_some_and(|e| {
+            e.inventory
+                .iter()
+                .flatten()
+                .any(|item| item.attributes.get_str("key") == Some(lock.as_str()))
+        })
+}
+
+/// Lock or unlock the interactable with a key of the entity. Sends a `locked` or `unlocked`
+/// event to the entity.
+pub fn set_locked(
+    ctx: &mut RegionCtx,
+    entity: u32,
+    target: &Interactable,
+    locked: bool,
+) -> Result<(), String> {
+    if !has_key(ctx, entity, target) {
+        return Err("No matching key.".into());
+    }
+    set_value(ctx, target, "locked", Value::Bool(locked));
+    let name = match target {
+        Interactable::Item(id) => id.to_string(),
+        Interactable::Sector(name) => name.clone(),
+    };
+    let event = if locked { "locked" } else { "unlocked" };
+    ctx.to_execute_entity
+        .push((entity, event.into(), VMValue::from(name)));
+    Ok(())
+}
+
+/// Send a `stolen` event (thief, item class) to the owner of an item taken by another entity.
+pub fn send_theft(ctx: &mut RegionCtx, owner: u32, thief: u32, class_name: &str) {
+    if owner != thief {
+        let mut value = VMValue::from(class_name);
+        value.x = thief as f32;
+        ctx.to_execute_entity.push((owner, "stolen".into(), value));
+    }
+}
+
+/// Drop the expired claims and the claims of removed items.
+pub fn tick_claims(ctx: &mut RegionCtx) {
+    let ticks = ctx.ticks;
+    let items: FxHashSet<u32> = ctx.map.items.iter().map(|item| item.id).collect();
+    ctx.claims.retain(|target, claim| {
+        claim.until.is_none_or(|until| until > ticks)
+            && match target {
+                Interactable::Item(id) => items.contains(id),
+                Interactable::Sector(_) => true,
+            }
+    });
+}
@@ -0,0 +1,23 @@
+use crate::{ParticleEmitter, RegionCtx, RegionMessage};
+use theframework::prelude::*;
+
+/// A particle effect of a region, i.e. sparks of a hit or the smoke of a spell, emitted by the
+/// `emit_particles` script call and rendered by the clients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParticleEvent {
+    pub region: u32,
+    pub emitter: ParticleEmitter,
+    /// Seconds the emitter spawns particles, the particles fade out afterwards.
+    pub duration: f32,
+}
+
+/// Send a particle effect to the server.
+pub fn send_particles(ctx: &RegionCtx, emitter: ParticleEmitter, duration: f32) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Particles(ParticleEvent {
+            region: ctx.region_id,
+            emitter,
+            duration,
+        }));
+    }
+}
@@ -0,0 +1,424 @@
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub mod sqlite;
+
+use crate::server::region::{get_global_id, reserve_global_ids, with_regionctx};
+use crate::{Entity, Item, Map, RegionCtx, ValueContainer};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use theframework::prelude::*;
+
+/// The identity of a persisted entity or item which stays the same between builds: its class
+/// and the creator id of the element it was created from. The ids are handed out at startup
+/// and change when the authored map changes.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct PersistKey {
+    pub class_name: String,
+    pub origin: Uuid,
+}
+
+impl PersistKey {
+    pub fn of_entity(entity: &Entity) -> Self {
+        Self {
+            class_name: entity.get_attr_string("class_name").unwrap_or_default(),
+            origin: entity.creator_id,
+        }
+    }
+
+    pub fn of_item(item: &Item) -> Self {
+        Self {
+            class_name: item.get_attr_string("class_name").unwrap_or_default(),
+            origin: item.creator_id,
+        }
+    }
+}
+
+/// The persisted state of a region: the entities and items which changed since the region was
+/// created and the runtime properties of its sectors.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PersistedRegion {
+    /// Entities including their inventories.
+    pub entities: Vec<Entity>,
+    pub items: Vec<Item>,
+    pub sectors: Vec<(u32, ValueContainer)>,
+    pub removed_entities: Vec<PersistKey>,
+    pub removed_items: Vec<PersistKey>,
+}
+
+impl PersistedRegion {
+    /// True if there is nothing to persist.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+            && self.items.is_empty()
+            && self.sectors.is_empty()
+            && self.removed_entities.is_empty()
+            && self.removed_items.is_empty()
+    }
+}
+
+/// A backend which stores the world state of regions, keyed by the region name.
+pub trait Persistence: Send {
+    /// Load the persisted state of the region.
+    fn load_region(&mut self, region: &str) -> Result<PersistedRegion, String>;
+
+    /// Store the changes of the region. Entities and items replace the previous state with the
+    /// same `PersistKey`, sectors the one with the same id. Removed entities and items stay
+    /// removed on the next load.
+    fn store_region(&mut self, region: &str, changes: &PersistedRegion) -> Result<(), String>;
+}
+
+/// A Persistence which keeps the state in memory, i.e. for tests or the web.
+#[derive(Default)]
+pub struct MemoryPersistence {
+    pub regions: FxHashMap<String, PersistedRegion>,
+}
+
+impl Persistence for MemoryPersistence {
+    fn load_region(&mut self, region: &str) -> Result<PersistedRegion, String> {
+        Ok(self.regions.get(region).cloned().unwrap_or_default())
+    }
+
+    fn store_region(&mut self, region: &str, changes: &PersistedRegion) -> Result<(), String> {
+        let state = self.regions.entry(region.to_string()).or_default();
+
+        state.entities.retain(|e| {
+            let key = PersistKey::of_entity(e);
+            !changes.removed_entities.contains(&key)
+                && !changes
+                    .entities
+                    .iter()
+                    .any(|c| PersistKey::of_entity(c) == key)
+        });
+        state.entities.extend(changes.entities.iter().cloned());
+        state.items.retain(|i| {
+            let key = PersistKey::of_item(i);
+            !changes.removed_items.contains(&key)
+                && !changes.items.iter().any(|c| PersistKey::of_item(c) == key)
+        });
+        state.items.extend(changes.items.iter().cloned());
+        state
+            .sectors
+            .retain(|(id, _)| !changes.sectors.iter().any(|(c, _)| c == id));
+        state.sectors.extend(changes.sectors.iter().cloned());
+
+        for key in &changes.removed_entities {
+            if !state.removed_entities.contains(key) {
+                state.removed_entities.push(key.clone());
+            }
+        }
+        for key in &changes.removed_items {
+            if !state.removed_items.contains(key) {
+                state.removed_items.push(key.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hash_of<T: Serialize>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(value)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+fn entity_hash(entity: &Entity) -> u64 {
+    // The inventory is not part of the serialized entity
+    let mut hasher = DefaultHasher::new();
+    hash_of(entity).hash(&mut hasher);
+    hash_of(&entity.inventory).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Tracks the persisted state of a region to find the dirty entities, items and sectors.
+#[derive(Default)]
+pub struct PersistenceCache {
+    entities: FxHashMap<u32, (PersistKey, u64)>,
+    items: FxHashMap<u32, (PersistKey, u64)>,
+    sectors: FxHashMap<u32, u64>,
+}
+
+impl PersistedRegion {
+    /// Apply the state over the map: entities and items replace the ones with the same
+    /// `PersistKey` and keep their id in the map, removed ones are dropped and the others are
+    /// added. Added entities and items keep their persisted id unless the map already uses it.
+    /// Returns the changed ids, from the persisted id to the id in the map.
+    pub fn apply_to_map(&self, map: &mut Map) -> FxHashMap<u32, u32> {
+        let mut remap = FxHashMap::default();
+
+        // New ids must not collide with the persisted ones
+        let persisted = self.entities.iter().map(|e| e.id);
+        if let Some(max_id) = persisted.chain(self.items.iter().map(|i| i.id)).max() {
+            reserve_global_ids(max_id + 1);
+        }
+        let mut used: FxHashSet<u32> = map
+            .entities
+            .iter()
+            .map(|e| e.id)
+            .chain(map.items.iter().map(|i| i.id))
+            .collect();
+        let mut id_for = |id: u32, existing: Option<u32>| {
+            let new_id = match existing {
+                Some(existing) => existing,
+                None if used.contains(&id) => get_global_id(),
+                None => id,
+            };
+            used.insert(new_id);
+            if new_id != id {
+                remap.insert(id, new_id);
+            }
+            new_id
+        };
+
+        map.entities
+            .retain(|e| !self.removed_entities.contains(&PersistKey::of_entity(e)));
+        for entity in &self.entities {
+            let mut entity = entity.clone();
+            entity.mark_all_dirty();
+            let key = PersistKey::of_entity(&entity);
+            let existing = map
+                .entities
+                .iter()
+                .position(|e| PersistKey::of_entity(e) == key);
+            entity.id = id_for(entity.id, existing.map(|index| map.entities[index].id));
+            match existing {
+                Some(index) => map.entities[index] = entity,
+                None => map.entities.push(entity),
+            }
+        }
+
+        map.items
+            .retain(|i| !self.removed_items.contains(&PersistKey::of_item(i)));
+        for item in &self.items {
+            let mut item = item.clone();
+            item.mark_all_dirty();
+            let key = PersistKey::of_item(&item);
+            let existing = map.items.iter().position(|i| PersistKey::of_item(i) == key);
+            item.id = id_for(item.id, existing.map(|index| map.items[index].id));
+            match existing {
+                Some(index) => map.items[index] = item,
+                None => map.items.push(item),
+            }
+        }
+
+        for (id, properties) in &self.sectors {
+            if let Some(sector) = map.find_sector_mut(*id) {
+                sector.properties = properties.clone();
+            }
+        }
+
+        remap
+    }
+}
+
+impl PersistenceCache {
+    /// Apply the persisted state to the region and use the result as the clean state.
+    /// Player entities are owned by their clients and are not persisted.
+    pub fn load(region_id: u32, state: PersistedRegion) -> Self {
+        with_regionctx(region_id, |ctx: &mut RegionCtx| {
+            let remap = state.apply_to_map(&mut ctx.map);
+            for entity in &state.entities {
+                if let Some(class_name) = entity.get_attr_string("class_name") {
+                    let id = remap.get(&entity.id).copied().unwrap_or(entity.id);
+                    ctx.entity_classes.insert(id, class_name);
+                }
+            }
+            for item in &state.items {
+                if let Some(class_name) = item.get_attr_string("class_name") {
+                    let id = remap.get(&item.id).copied().unwrap_or(item.id);
+                    ctx.item_classes.insert(id, class_name);
+                }
+            }
+            ctx.map_changed();
+        });
+
+        let mut cache = Self::default();
+        cache.collect(region_id);
+        cache
+    }
+
+    /// Use the current state of the region, including the player entities, as the clean state.
+    pub fn capture(ctx: &RegionCtx) -> Self {
+        Self::default().diff(ctx, true).1
+    }
+
+    /// Collect the entities, items and sectors which changed since the last call.
+    pub fn collect(&mut self, region_id: u32) -> PersistedRegion {
+        let mut changes = PersistedRegion::default();
+
+        with_regionctx(region_id, |ctx: &mut RegionCtx| {
+            let (diff, cache) = self.diff(ctx, false);
+            changes = diff;
+            *self = cache;
+        });
+
+        changes
+    }
+
+    /// The entities, items and sectors which differ from the clean state, without updating it.
+    pub fn changes(&self, ctx: &RegionCtx, players: bool) -> PersistedRegion {
+        self.diff(ctx, players).0
+    }
+
+    /// Compare the region with the clean state, returns the changes and the new clean state.
+    fn diff(&self, ctx: &RegionCtx, players: bool) -> (PersistedRegion, Self) {
+        let mut changes = PersistedRegion::default();
+        let mut cache = Self::default();
+
+        for entity in &ctx.map.entities {
+            let is_player = ctx
+                .entity_classes
+                .get(&entity.id)
+                .is_some_and(|class| ctx.entity_player_classes.contains(class));
+            if is_player && !players {
+                continue;
+            }
+            let hash = entity_hash(entity);
+            if self.entities.get(&entity.id).map(|(_, h)| *h) != Some(hash) {
+                changes.entities.push(entity.clone());
+            }
+            cache
+                .entities
+                .insert(entity.id, (PersistKey::of_entity(entity), hash));
+        }
+        changes.removed_entities = self
+            .entities
+            .iter()
+            .filter(|(id, _)| !cache.entities.contains_key(id))
+            .map(|(_, (key, _))| key.clone())
+            .collect();
+
+        for item in &ctx.map.items {
+            let hash = hash_of(item);
+            if self.items.get(&item.id).map(|(_, h)| *h) != Some(hash) {
+                changes.items.push(item.clone());
+            }
+            cache
+                .items
+                .insert(item.id, (PersistKey::of_item(item), hash));
+        }
+        changes.removed_items = self
+            .items
+            .iter()
+            .filter(|(id, _)| !cache.items.contains_key(id))
+            .map(|(_, (key, _))| key.clone())
+            .collect();
+
+        for sector in &ctx.map.sectors {
+            let hash = hash_of(&sector.properties);
+            if self.sectors.get(&sector.id) != Some(&hash) {
+                changes.sectors.push((sector.id, sector.properties.clone()));
+            }
+            cache.sectors.insert(sector.id, hash);
+        }
+
+        (changes, cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+
+    pub(super) fn entity(id: u32, class_name: &str) -> Entity {
+        let mut entity = Entity::new();
+        entity.id = id;
+        entity.set_attribute("class_name", Value::Str(class_name.into()));
+        entity
+    }
+
+    pub(super) fn item(id: u32, class_name: &str) -> Item {
+        let mut item = Item::new();
+        item.id = id;
+        item.set_attribute("class_name", Value::Str(class_name.into()));
+        item
+    }
+
+    #[test]
+    fn cache_changes() {
+        let mut ctx = RegionCtx::default();
+        ctx.map.entities.push(entity(1, "Guard"));
+        ctx.map.entities.push(entity(2, "Player"));
+        ctx.map.items.push(item(3, "Torch"));
+        ctx.entity_classes.insert(2, "Player".into());
+        ctx.entity_player_classes.insert("Player".into());
+
+        let cache = PersistenceCache::capture(&ctx);
+        assert!(cache.changes(&ctx, true).is_empty());
+
+        ctx.map.entities[0].set_attribute("health", Value::Int(3));
+        ctx.map.entities[1].set_attribute("health", Value::Int(3));
+        let torch = PersistKey::of_item(&ctx.map.items[0]);
+        ctx.map.items.clear();
+
+        let changes = cache.changes(&ctx, false);
+        assert_eq!(changes.entities.len(), 1);
+        assert_eq!(changes.entities[0].id, 1);
+        assert!(changes.items.is_empty());
+        assert_eq!(changes.removed_items, vec![torch]);
+        assert_eq!(cache.changes(&ctx, true).entities.len(), 2);
+    }
+
+    #[test]
+    fn apply_by_key() {
+        // The authored map of a new build, the guard has another id and the id of the torch
+        // was the id of a spawned wolf in the last build
+        let mut map = Map::default();
+        let guard = entity(5, "Guard");
+        map.entities.push(guard.clone());
+        map.items.push(item(7, "Torch"));
+        let barrel = item(8, "Barrel");
+        map.items.push(barrel.clone());
+
+        let mut persisted_guard = guard.clone();
+        persisted_guard.id = 1;
+        persisted_guard.set_attribute("health", Value::Int(3));
+        let state = PersistedRegion {
+            entities: vec![persisted_guard, entity(7, "Wolf")],
+            removed_items: vec![PersistKey::of_item(&barrel)],
+            ..Default::default()
+        };
+        let remap = state.apply_to_map(&mut map);
+
+        assert_eq!(map.entities.len(), 2);
+        assert_eq!(map.entities[0].id, 5);
+        assert_eq!(map.entities[0].attributes.get_int_default("health", 0), 3);
+        let wolf = map.entities[1].id;
+        assert!(wolf != 5 && wolf != 7);
+        assert_eq!(remap.get(&1), Some(&5));
+        assert_eq!(remap.get(&7), Some(&wolf));
+
+        assert_eq!(map.items.len(), 1);
+        assert_eq!(map.items[0].id, 7);
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        let guard = entity(1, "Guard");
+        let torch = item(2, "Torch");
+        let mut persistence = MemoryPersistence::default();
+        let changes = PersistedRegion {
+            entities: vec![guard.clone()],
+            items: vec![torch.clone()],
+            ..Default::default()
+        };
+        persistence.store_region("Town", &changes).unwrap();
+
+        let mut moved = guard.clone();
+        moved.id = 9;
+        let changes = PersistedRegion {
+            entities: vec![moved],
+            removed_items: vec![PersistKey::of_item(&torch)],
+            ..Default::default()
+        };
+        persistence.store_region("Town", &changes).unwrap();
+
+        let state = persistence.load_region("Town").unwrap();
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].id, 9);
+        assert!(state.items.is_empty());
+        assert_eq!(state.removed_items, vec![PersistKey::of_item(&torch)]);
+        assert!(persistence.load_region("Castle").unwrap().is_empty());
+    }
+}
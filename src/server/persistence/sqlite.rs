@@ -0,0 +1,254 @@
+use crate::server::persistence::{PersistKey, PersistedRegion, Persistence};
+use crate::{Entity, Item, ValueContainer};
+use rusqlite::{Connection, params};
+use std::path::Path;
+use uuid::Uuid;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entities (
+    region TEXT NOT NULL,
+    class_name TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    data TEXT,
+    inventory TEXT,
+    PRIMARY KEY (region, class_name, origin)
+);
+CREATE TABLE IF NOT EXISTS items (
+    region TEXT NOT NULL,
+    class_name TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    data TEXT,
+    PRIMARY KEY (region, class_name, origin)
+);
+CREATE TABLE IF NOT EXISTS sectors (
+    region TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    properties TEXT NOT NULL,
+    PRIMARY KEY (region, id)
+);
+";
+
+/// A Persistence storing the world state in an SQLite database. Elements are stored as JSON,
+/// entities and items by their `PersistKey`. Removed entities and items are kept as rows
+/// without data.
+pub struct SqlitePersistence {
+    connection: Connection,
+}
+
+impl SqlitePersistence {
+    /// Open or create the database at the given path.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Self::with_connection(Connection::open(path).map_err(|err| err.to_string())?)
+    }
+
+    /// Create a database which only lives in memory.
+    pub fn in_memory() -> Result<Self, String> {
+        Self::with_connection(Connection::open_in_memory().map_err(|err| err.to_string())?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, String> {
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|err| err.to_string())?;
+        Ok(Self { connection })
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|err| err.to_string())
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|err| err.to_string())
+}
+
+fn to_key(class_name: String, origin: &str) -> Result<PersistKey, String> {
+    Ok(PersistKey {
+        class_name,
+        origin: Uuid::parse_str(origin).map_err(|err| err.to_string())?,
+    })
+}
+
+impl Persistence for SqlitePersistence {
+    fn load_region(&mut self, region: &str) -> Result<PersistedRegion, String> {
+        let mut state = PersistedRegion::default();
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT class_name, origin, data, inventory FROM entities WHERE region = ?1")
+            .map_err(|err| err.to_string())?;
+        let rows = statement
+            .query_map(params![region], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })
+            .map_err(|err| err.to_string())?;
+        for row in rows {
+            match row.map_err(|err| err.to_string())? {
+                (class_name, origin, None, _) => {
+                    state.removed_entities.push(to_key(class_name, &origin)?)
+                }
+                (_, _, Some(data), inventory) => {
+                    let mut entity: Entity = from_json(&data)?;
+                    if let Some(inventory) = inventory {
+                        entity.inventory = from_json(&inventory)?;
+                    }
+                    state.entities.push(entity);
+                }
+            }
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT class_name, origin, data FROM items WHERE region = ?1")
+            .map_err(|err| err.to_string())?;
+        let rows = statement
+            .query_map(params![region], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })
+            .map_err(|err| err.to_string())?;
+        for row in rows {
+            match row.map_err(|err| err.to_string())? {
+                (class_name, origin, None) => {
+                    state.removed_items.push(to_key(class_name, &origin)?)
+                }
+                (_, _, Some(data)) => state.items.push(from_json::<Item>(&data)?),
+            }
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, properties FROM sectors WHERE region = ?1")
+            .map_err(|err| err.to_string())?;
+        let rows = statement
+            .query_map(params![region], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|err| err.to_string())?;
+        for row in rows {
+            let (id, properties) = row.map_err(|err| err.to_string())?;
+            state
+                .sectors
+                .push((id, from_json::<ValueContainer>(&properties)?));
+        }
+
+        Ok(state)
+    }
+
+    fn store_region(&mut self, region: &str, changes: &PersistedRegion) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| err.to_string())?;
+
+        for entity in &changes.entities {
+            let key = PersistKey::of_entity(entity);
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO entities (region, class_name, origin, data, inventory) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        region,
+                        key.class_name,
+                        key.origin.to_string(),
+                        to_json(entity)?,
+                        to_json(&entity.inventory)?
+                    ],
+                )
+                .map_err(|err| err.to_string())?;
+        }
+        for key in &changes.removed_entities {
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO entities (region, class_name, origin, data, inventory) VALUES (?1, ?2, ?3, NULL, NULL)",
+                    params![region, key.class_name, key.origin.to_string()],
+                )
+                .map_err(|err| err.to_string())?;
+        }
+
+        for item in &changes.items {
+            let key = PersistKey::of_item(item);
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO items (region, class_name, origin, data) VALUES (?1, ?2, ?3, ?4)",
+                    params![region, key.class_name, key.origin.to_string(), to_json(item)?],
+                )
+                .map_err(|err| err.to_string())?;
+        }
+        for key in &changes.removed_items {
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO items (region, class_name, origin, data) VALUES (?1, ?2, ?3, NULL)",
+                    params![region, key.class_name, key.origin.to_string()],
+                )
+                .map_err(|err| err.to_string())?;
+        }
+
+        for (id, properties) in &changes.sectors {
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO sectors (region, id, properties) VALUES (?1, ?2, ?3)",
+                    params![region, id, to_json(properties)?],
+                )
+                .map_err(|err| err.to_string())?;
+        }
+
+        transaction.commit().map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+    use crate::server::persistence::tests::{entity, item};
+
+    #[test]
+    fn in_memory_round_trip() {
+        let mut persistence = SqlitePersistence::in_memory().unwrap();
+
+        let mut guard = entity(1, "Guard");
+        guard.inventory = vec![Some(item(2, "Sword")), None];
+        let torch = item(3, "Torch");
+        let mut properties = ValueContainer::default();
+        properties.set("floor_light", Value::Float(0.5));
+        let changes = PersistedRegion {
+            entities: vec![guard.clone()],
+            items: vec![torch.clone()],
+            sectors: vec![(4, properties.clone())],
+            ..Default::default()
+        };
+        persistence.store_region("Town", &changes).unwrap();
+
+        let changes = PersistedRegion {
+            removed_items: vec![PersistKey::of_item(&torch)],
+            ..Default::default()
+        };
+        persistence.store_region("Town", &changes).unwrap();
+
+        let state = persistence.load_region("Town").unwrap();
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(
+            PersistKey::of_entity(&state.entities[0]),
+            PersistKey::of_entity(&guard)
+        );
+        let inventory: Vec<Option<u32>> = state.entities[0]
+            .inventory
+            .iter()
+            .map(|item| item.as_ref().map(|item| item.id))
+            .collect();
+        assert_eq!(inventory, vec![Some(2), None]);
+        assert!(state.items.is_empty());
+        assert_eq!(state.removed_items, vec![PersistKey::of_item(&torch)]);
+        assert_eq!(state.sectors, vec![(4, properties)]);
+
+        assert!(persistence.load_region("Castle").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::server::follow::send_party_aggro;
+use crate::server::region::is_entity_dead_ctx;
+use crate::vm::VMValue;
+use crate::{RegionCtx, RegionMessage, Value};
+use theframework::prelude::*;
+use vek::{Vec2, Vec3};
+
+/// The flight parameters of a projectile, read from the `[projectile]` table of the item data. See
+/// `src/server/CONFIG.md` for an example.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ProjectileDef {
+    pub speed: f32,
+    pub gravity: f32,
+    pub range: f32,
+    pub radius: f32,
+}
+
+impl Default for ProjectileDef {
+    fn default() -> Self {
+        Self {
+            speed: 0.5,
+            gravity: 0.0,
+            range: 12.0,
+            radius: 0.4,
+        }
+    }
+}
+
+impl ProjectileDef {
+    /// Read the projectile from the item data, the defaults if the table is missing.
+    pub fn from_data(toml: &str) -> Result<Self, String> {
+        let table = toml.parse::<toml::Table>().map_err(|err| err.to_string())?;
+        match table.get("projectile") {
+            Some(projectile) => projectile.clone().try_into().map_err(|err| err.to_string()),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// A projectile in flight. It is an item in the map, so clients receive its movement with the
+/// regular item updates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Projectile {
+    pub item_id: u32,
+    pub owner: u32,
+    pub velocity: Vec3<f32>,
+    pub def: ProjectileDef,
+    pub traveled: f32,
+}
+
+/// What a projectile hit in a tick.
+enum ProjectileHit {
+    Entity(u32),
+    Wall,
+    Ground,
+    OutOfRange,
+}
+
+/// Launch a projectile of the item class from the owner towards the target. Returns the id of
+/// the projectile item.
+pub fn spawn_projectile(
+    ctx: &mut RegionCtx,
+    owner: u32,
+    class_name: &str,
+    target: Vec2<f32>,
+) -> Result<u32, String> {
+    let def = match ctx.item_class_data.get(class_name) {
+        Some(data) => ProjectileDef::from_data(data)?,
+        None => ProjectileDef::default(),
+    };
+    let origin = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == owner)
+        .map(|entity| entity.position)
+        .ok_or("Unknown entity.")?;
+
+    let delta = target - Vec2::new(origin.x, origin.z);
+    let distance = delta.magnitude();
+    if distance <= f32::EPSILON || def.speed <= 0.0 {
+        return Err("Invalid projectile target.".into());
+    }
+    let direction = delta / distance * def.speed;
+
+    // With gravity the projectile starts upwards so that it comes down at the target
+    let flight_ticks = distance / def.speed;
+    let velocity = Vec3::new(direction.x, 0.5 * def.gravity * flight_ticks, direction.y);
+
+    let mut item = ctx
+        .create_item(class_name.to_string())
+        .ok_or(format!("Unknown item '{}'.", class_name))?;
+    item.set_position(origin);
+    item.set_attribute("projectile", Value::Bool(true));
+
+    let item_id = item.id;
+    ctx.map.items.push(item);
+    ctx.projectiles.push(Projectile {
+        item_id,
+        owner,
+        velocity,
+        def,
+        traveled: 0.0,
+    });
+    Ok(item_id)
+}
+
+/// Move all projectiles of the region and resolve their hits. The owner receives a
+/// `projectile_hit` event with the hit entity (-1 if none) and the hit position, the hit entity
+/// a `hit_by_projectile` event with the owner.
+pub fn tick_projectiles(ctx: &mut RegionCtx) {
+    let mut projectiles = std::mem::take(&mut ctx.projectiles);
+    projectiles.retain_mut(|projectile| {
+        let Some(from) = ctx
+            .map
+            .items
+            .iter()
+            .find(|item| item.id == projectile.item_id)
+            .map(|item| item.position)
+        else {
+            return false;
+        };
+
+        let mut to = from + projectile.velocity;
+        projectile.velocity.y -= projectile.def.gravity;
+        projectile.traveled += Vec2::new(projectile.velocity.x, projectile.velocity.z).magnitude();
+
+        let (a, b) = (Vec2::new(from.x, from.z), Vec2::new(to.x, to.z));
+        let hit = if let Some(entity_id) = first_entity_hit(ctx, projectile, a, b) {
+            Some(ProjectileHit::Entity(entity_id))
+        } else if !ctx.mapmini.line_of_sight(a, b) {
+            Some(ProjectileHit::Wall)
+        } else if projectile.def.gravity > 0.0 && to.y <= 0.0 {
+            to.y = 0.0;
+            Some(ProjectileHit::Ground)
+        } else if projectile.traveled >= projectile.def.range {
+            Some(ProjectileHit::OutOfRange)
+        } else {
+            None
+        };
+
+        match hit {
+            None => {
+                if let Some(item) = ctx.get_item_mut(projectile.item_id) {
+                    item.set_position(to);
+                }
+                true
+            }
+            Some(hit) => {
+                let target = match hit {
+                    ProjectileHit::Entity(entity_id) => {
+                        ctx.to_execute_entity.push((
+                            entity_id,
+                            "hit_by_projectile".into(),
+                            VMValue::broadcast(projectile.owner as f32),
+                        ));
+                        send_party_aggro(ctx, projectile.owner, entity_id);
+                        entity_id as f32
+                    }
+                    ProjectileHit::Wall | ProjectileHit::Ground | ProjectileHit::OutOfRange => -1.0,
+                };
+                ctx.to_execute_entity.push((
+                    projectile.owner,
+                    "projectile_hit".into(),
+                    VMValue::new(target, to.x, to.z),
+                ));
+                remove_projectile(ctx, projectile.item_id);
+                false
+            }
+        }
+    });
+    ctx.projectiles.extend(projectiles);
+}
+
+/// The living entity closest to the start of the segment within the hit radius of the path.
+fn first_entity_hit(
+    ctx: &RegionCtx,
+    projectile: &Projectile,
+    a: Vec2<f32>,
+    b: Vec2<f32>,
+) -> Option<u32> {
+    let segment = b - a;
+    let length = segment.magnitude_squared();
+    ctx.map
+        .entities
+        .iter()
+        .filter(|entity| entity.id != projectile.owner)
+        .filter_map(|entity| {
+            let p = entity.get_pos_xz();
+            let t = if length > 0.0 {
+                ((p - a).dot(segment) / length).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let closest = a + segment * t;
+            (closest.distance(p) <= projectile.def.radius).then_some((entity.id, t))
+        })
+        .filter(|(id, _)| !is_entity_dead_ctx(ctx, *id))
+        .min_by(|x, y| x.1.total_cmp(&y.1))
+        .map(|(id, _)| id)
+}
+
+fn remove_projectile(ctx: &mut RegionCtx, item_id: u32) {
+    ctx.map.items.retain(|item| item.id != item_id);
+    ctx.item_classes.remove(&item_id);
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::RemoveItem(ctx.region_id, item_id));
+    }
+}
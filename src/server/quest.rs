@@ -0,0 +1,333 @@
+use crate::vm::VMValue;
+use crate::{Entity, RegionCtx, RegionMessage};
+use theframework::prelude::*;
+
+/// A quest definition, loaded from TOML. A file can define several quests. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// Objectives are completed in order, either by the given event or by `advance_quest`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Quest {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub objectives: Vec<QuestObjective>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct QuestObjective {
+    pub text: String,
+    /// The entity event which progresses the objective, i.e. "entered", "take_damage",
+    /// "kill" or "picked_up".
+    #[serde(default)]
+    pub on: Option<String>,
+    /// Only count events with this value, i.e. a sector name or an item class.
+    #[serde(default)]
+    pub value: Option<String>,
+    /// How often the event has to happen.
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+/// Parse the quests of a TOML file.
+pub fn parse_quests(toml: &str) -> Result<FxHashMap<String, Quest>, String> {
+    toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum QuestStatus {
+    #[default]
+    Active,
+    Completed,
+    Failed,
+}
+
+/// The progress of an entity in a quest.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct QuestState {
+    pub status: QuestStatus,
+    /// The index of the current objective.
+    pub objective: u32,
+    /// How often the event of the current objective happened.
+    pub progress: u32,
+}
+
+/// A quest in the journal of an entity.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub quest: String,
+    pub title: String,
+    pub description: String,
+    pub status: QuestStatus,
+    /// The objectives reached so far and if they are completed.
+    pub objectives: Vec<(String, bool)>,
+}
+
+/// Start the quest for the entity. Returns false if the quest is unknown or already started.
+pub fn start_quest(ctx: &mut RegionCtx, entity_id: u32, name: &str) -> bool {
+    if !ctx.assets.quests.contains_key(name) {
+        return false;
+    }
+    let Some(entity) = ctx.get_entity_mut(entity_id) else {
+        return false;
+    };
+    if entity.quests.contains_key(name) {
+        return false;
+    }
+    entity
+        .quests
+        .insert(name.to_string(), QuestState::default());
+    send_journal(ctx, entity_id);
+    true
+}
+
+/// Complete the current objective of the quest. Returns false if the quest is not active.
+pub fn advance_quest(ctx: &mut RegionCtx, entity_id: u32, name: &str) -> bool {
+    let Some(count) = ctx
+        .assets
+        .quests
+        .get(name)
+        .map(|q| q.objectives.len() as u32)
+    else {
+        return false;
+    };
+    let Some(state) = ctx
+        .get_entity_mut(entity_id)
+        .and_then(|entity| entity.quests.get_mut(name))
+    else {
+        return false;
+    };
+    if state.status != QuestStatus::Active {
+        return false;
+    }
+
+    state.objective += 1;
+    state.progress = 0;
+    if state.objective >= count {
+        state.status = QuestStatus::Completed;
+        ctx.to_execute_entity.push((
+            entity_id,
+            "quest_completed".into(),
+            VMValue::from(name.to_string()),
+        ));
+    }
+    send_journal(ctx, entity_id);
+    true
+}
+
+/// The state of the quest for the entity: "none", "active", "completed" or "failed".
+pub fn quest_state(ctx: &RegionCtx, entity_id: u32, name: &str) -> &'static str {
+    let state = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == entity_id)
+        .and_then(|entity| entity.quests.get(name));
+    match state.map(|state| state.status) {
+        None => "none",
+        Some(QuestStatus::Active) => "active",
+        Some(QuestStatus::Completed) => "completed",
+        Some(QuestStatus::Failed) => "failed",
+    }
+}
+
+/// Progress the active quests of the entity whose current objective waits for the event.
+pub fn on_quest_event(ctx: &mut RegionCtx, entity_id: u32, event: &str, value: &VMValue) {
+    let Some(entity) = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == entity_id)
+    else {
+        return;
+    };
+
+    let mut completed = vec![];
+    for (name, state) in &entity.quests {
+        if state.status != QuestStatus::Active {
+            continue;
+        }
+        let Some(objective) = ctx
+            .assets
+            .quests
+            .get(name)
+            .and_then(|quest| quest.objectives.get(state.objective as usize))
+        else {
+            continue;
+        };
+        if objective.on.as_deref() != Some(event) {
+            continue;
+        }
+        if let Some(expected) = &objective.value {
+            let matches = value.as_string() == Some(expected.as_str())
+                || expected.parse::<f32>().is_ok_and(|v| v == value.x);
+            if !matches {
+                continue;
+            }
+        }
+        completed.push((name.clone(), state.progress + 1 >= objective.count));
+    }
+
+    for (name, done) in completed {
+        if done {
+            advance_quest(ctx, entity_id, &name);
+        } else if let Some(state) = ctx
+            .get_entity_mut(entity_id)
+            .and_then(|entity| entity.quests.get_mut(&name))
+        {
+            state.progress += 1;
+        }
+    }
+}
+
+/// The journal of the entity.
+pub fn journal(ctx: &RegionCtx, entity: &Entity) -> Vec<JournalEntry> {
+    entity
+        .quests
+        .iter()
+        .filter_map(|(name, state)| {
+            let quest = ctx.assets.quests.get(name)?;
+            let reached = if state.status == QuestStatus::Active {
+                state.objective as usize + 1
+            } else {
+                quest.objectives.len()
+            };
+            Some(JournalEntry {
+                quest: name.clone(),
+                title: quest.title.clone(),
+                description: quest.description.clone(),
+                status: state.status,
+                objectives: quest
+                    .objectives
+                    .iter()
+                    .take(reached)
+                    .enumerate()
+                    .map(|(index, objective)| {
+                        (
+                            objective.text.clone(),
+                            state.status == QuestStatus::Completed
+                                || index < state.objective as usize,
+                        )
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Send the journal of the entity to the server.
+pub fn send_journal(ctx: &RegionCtx, entity_id: u32) {
+    let Some(entity) = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == entity_id)
+    else {
+        return;
+    };
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Journal(
+            ctx.region_id,
+            entity_id,
+            journal(ctx, entity),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUESTS: &str = r#"
+        [rescue_cat]
+        title = "Rescue the Cat"
+
+        [[rescue_cat.objectives]]
+        text = "Search the cellar."
+        on = "entered"
+        value = "Cellar"
+
+        [[rescue_cat.objectives]]
+        text = "Defeat the rats."
+        on = "kill"
+        count = 3
+
+        [[rescue_cat.objectives]]
+        text = "Return to the innkeeper."
+    "#;
+
+    fn region() -> RegionCtx {
+        let mut ctx = RegionCtx::default();
+        ctx.assets.add_quests(QUESTS).unwrap();
+        let mut entity = Entity::new();
+        entity.id = 1;
+        ctx.map.entities.push(entity);
+        ctx
+    }
+
+    #[test]
+    fn objectives() {
+        let mut ctx = region();
+        assert_eq!(quest_state(&ctx, 1, "rescue_cat"), "none");
+        assert!(!start_quest(&mut ctx, 1, "unknown"));
+        assert!(start_quest(&mut ctx, 1, "rescue_cat"));
+        assert!(!start_quest(&mut ctx, 1, "rescue_cat"));
+
+        // Only the event and value of the current objective progress the quest
+        on_quest_event(&mut ctx, 1, "kill", &VMValue::zero());
+        on_quest_event(&mut ctx, 1, "entered", &VMValue::from("Tavern"));
+        assert_eq!(ctx.map.entities[0].quests["rescue_cat"].objective, 0);
+        on_quest_event(&mut ctx, 1, "entered", &VMValue::from("Cellar"));
+        assert_eq!(ctx.map.entities[0].quests["rescue_cat"].objective, 1);
+
+        for _ in 0..2 {
+            on_quest_event(&mut ctx, 1, "kill", &VMValue::zero());
+        }
+        assert_eq!(
+            ctx.map.entities[0].quests["rescue_cat"],
+            QuestState {
+                status: QuestStatus::Active,
+                objective: 1,
+                progress: 2,
+            }
+        );
+        on_quest_event(&mut ctx, 1, "kill", &VMValue::zero());
+        assert_eq!(ctx.map.entities[0].quests["rescue_cat"].objective, 2);
+
+        // The last objective has no event and is completed by the script
+        assert!(advance_quest(&mut ctx, 1, "rescue_cat"));
+        assert_eq!(quest_state(&ctx, 1, "rescue_cat"), "completed");
+        assert!(!advance_quest(&mut ctx, 1, "rescue_cat"));
+        assert_eq!(ctx.to_execute_entity.len(), 1);
+        assert_eq!(ctx.to_execute_entity[0].1, "quest_completed");
+    }
+
+    #[test]
+    fn journal_entries() {
+        let mut ctx = region();
+        start_quest(&mut ctx, 1, "rescue_cat");
+        advance_quest(&mut ctx, 1, "rescue_cat");
+
+        let entries = journal(&ctx, &ctx.map.entities[0]);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title, "Rescue the Cat");
+        assert_eq!(
+            entries[0].objectives,
+            vec![
+                ("Search the cellar.".to_string(), true),
+                ("Defeat the rats.".to_string(), false),
+            ]
+        );
+
+        advance_quest(&mut ctx, 1, "rescue_cat");
+        advance_quest(&mut ctx, 1, "rescue_cat");
+        let entries = journal(&ctx, &ctx.map.entities[0]);
+        assert_eq!(entries[0].status, QuestStatus::Completed);
+        assert_eq!(entries[0].objectives.len(), 3);
+        assert!(entries[0].objectives.iter().all(|(_, done)| *done));
+    }
+}
@@ -1,11 +1,32 @@
+use crate::server::behavior::tick_behaviors;
+use crate::server::config_section;
+use crate::server::cutscene::{is_input_locked, tick_cutscenes};
+use crate::server::effect::tick_effects;
+use crate::server::fog::tick_fog;
+use crate::server::follow::{formation_position, tick_followers};
+use crate::server::leveling::LevelingRules;
+use crate::server::metrics::RegionStats;
+use crate::server::movement::{movement_modes, speed_factor, update_movement_mode};
+use crate::server::ownership::{Interactable, can_use, claimed_by, tick_claims};
+use crate::server::persistence::{PersistedRegion, PersistenceCache};
+use crate::server::projectile::tick_projectiles;
 use crate::server::py_fn::*;
-use crate::server::region_host::{run_client_fn, run_server_fn};
+use crate::server::region_host::{run_client_fn, run_server_fn, run_setup_fn};
+use crate::server::routine::{Routine, tick_routines};
+use crate::server::schedule::tick_schedule;
+use crate::server::shop;
+use crate::server::simulation::{RegionRng, Simulation, SimulationLog, SimulationRules};
+use crate::server::spawner::due_spawns;
+use crate::server::trigger::{sector_trigger_enabled, send_sector_used, tick_sector_dwell};
+use crate::server::turn::{TurnState, end_turn, start_round, take_round};
+use crate::server::weather::{Weather, send_weather, tick_weather};
 use crate::vm::*;
 use crate::{
-    Assets, Choice, Currency, Entity, EntityAction, Item, Map, PixelSource, PlayerCamera,
-    RegionCtx, Value, ValueContainer,
+    Assets, AttrSchemas, Choice, Currency, Entity, EntityAction, Item, Map, PixelSource,
+    PlayerCamera, RegionCtx, Value, ValueContainer,
 };
 use crossbeam_channel::{Receiver, Sender, unbounded};
+use instant::Instant;
 use rand::*;
 
 use std::sync::{Arc, Mutex};
@@ -25,6 +46,11 @@ pub fn register_regionctx(id: u32, instance: Arc<Mutex<RegionCtx>>) {
     REGIONCTX.write().unwrap().insert(id, instance);
 }
 
+/// Remove a RegionCtx from the store.
+pub fn unregister_regionctx(id: u32) {
+    REGIONCTX.write().unwrap().remove(&id);
+}
+
 /// Clear the store.
 pub fn clear_regionctx_store() {
     REGIONCTX.write().unwrap().clear();
@@ -60,10 +86,22 @@ pub fn get_global_id() -> u32 {
     GLOBAL_ID_GEN.fetch_add(1, Ordering::Relaxed)
 }
 
+/// The next global id without consuming it.
+pub fn peek_global_id() -> u32 {
+    GLOBAL_ID_GEN.load(Ordering::Relaxed)
+}
+
+/// Make sure that new ids start at the given id, i.e. after restoring a save game.
+pub fn reserve_global_ids(next_id: u32) {
+    GLOBAL_ID_GEN.fetch_max(next_id, Ordering::Relaxed);
+}
+
 use EntityAction::*;
 
 use super::RegionMessage;
 use super::data::{apply_entity_data, apply_item_data};
+use super::dialogue::{end_dialogue, select_dialogue_choice};
+use super::quest::on_quest_event;
 use RegionMessage::*;
 
 pub struct RegionInstance {
@@ -86,6 +124,13 @@ pub struct RegionInstance {
 
     /// Entity block mode
     entity_block_mode: i32,
+
+    /// The deterministic mode and the record / replay state
+    simulation: Simulation,
+
+    /// The map after the startup and its clean state, see `dirty_snapshot`.
+    authored: Map,
+    baseline: PersistenceCache,
 }
 
 impl RegionInstance {
@@ -359,6 +404,11 @@ impl RegionInstance {
             from_sender,
 
             entity_block_mode: 0,
+
+            simulation: Simulation::default(),
+
+            authored: Map::default(),
+            baseline: PersistenceCache::default(),
         }
     }
 
@@ -432,6 +482,27 @@ impl RegionInstance {
                 }
             }
 
+            match AttrSchemas::from_data(entity_data) {
+                Ok(schemas) if !schemas.is_empty() => {
+                    ctx.entity_schemas.insert(name.clone(), schemas);
+                }
+                Ok(_) => {}
+                Err(err) => ctx.startup_errors.push(format!(
+                    "[error] {}: Character Schema '{}': {}",
+                    self.name, name, err,
+                )),
+            }
+            match Routine::from_data(entity_data) {
+                Ok(routine) if !routine.is_empty() => {
+                    ctx.entity_routines.insert(name.clone(), routine);
+                }
+                Ok(_) => {}
+                Err(err) => ctx.startup_errors.push(format!(
+                    "[error] {}: Character Routine '{}': {}",
+                    self.name, name, err,
+                )),
+            }
+
             ctx.entity_class_data
                 .insert(name.clone(), entity_data.clone());
         }
@@ -506,17 +577,31 @@ impl RegionInstance {
             //         self.name, name, err,
             //     ));
             // }
+            match AttrSchemas::from_data(item_data) {
+                Ok(schemas) if !schemas.is_empty() => {
+                    ctx.item_schemas.insert(name.clone(), schemas);
+                }
+                Ok(_) => {}
+                Err(err) => ctx.startup_errors.push(format!(
+                    "[error] {}: Item Schema '{}': {}",
+                    self.name, name, err,
+                )),
+            }
             ctx.item_class_data.insert(name.clone(), item_data.clone());
         }
 
         // Remove player based entities, these only get created on demand from a client
         let player_classes = ctx.entity_player_classes.clone();
-        ctx.map
-            .entities
-            .retain(|entity| match entity.get_attr_string("class_name") {
-                Some(class_name) => !player_classes.contains(&class_name),
-                None => true,
-            });
+        let (player_starts, entities): (Vec<Entity>, Vec<Entity>) =
+            std::mem::take(&mut ctx.map.entities)
+                .into_iter()
+                .partition(|entity| {
+                    entity
+                        .get_attr_string("class_name")
+                        .is_some_and(|class_name| player_classes.contains(&class_name))
+                });
+        ctx.map.entities = entities;
+        ctx.player_starts = player_starts;
 
         // Set an entity id and mark all fields dirty for the first transmission to the server.
         for e in ctx.map.entities.iter_mut() {
@@ -565,6 +650,9 @@ impl RegionInstance {
                             if ctx.item_programs.contains_key(item_name) {
                                 let mut item = Item::default();
                                 item.id = get_global_id();
+                                // Stable between builds, see `PersistKey`
+                                item.creator_id =
+                                    Uuid::from_u64_pair(surface.sector_id as u64, s.id as u64);
                                 item.attributes.set("name", Value::Str(s.name.to_string()));
                                 item.attributes
                                     .set("class_name", Value::Str(item_name.to_string()));
@@ -599,7 +687,7 @@ impl RegionInstance {
         ctx.from_sender.set(self.from_sender.clone()).unwrap();
         ctx.to_receiver.set(self.to_receiver.clone()).unwrap();
         ctx.region_id = self.id;
-        ctx.mapmini = ctx.map.as_mini(&ctx.blocking_tiles);
+        ctx.map_changed();
 
         // Build collision geometry for all chunks (new collision system)
         use crate::chunkbuilder::{ChunkBuilder, d3chunkbuilder::D3ChunkBuilder};
@@ -649,7 +737,71 @@ impl RegionInstance {
         let target_fps = get_config_i32_default(&ctx, "game", "target_fps", 30) as f32;
 
         ctx.delta_time = 1.0 / target_fps;
+        ctx.stats.region = self.id;
+        ctx.stats.game_tick_budget_ms =
+            get_config_i32_default(&ctx, "game", "game_tick_ms", 250) as f32;
         ctx.health_attr = get_config_string_default(&ctx, "game", "health", "HP").to_string();
+        match config_section(&ctx.config, "combat") {
+            Ok(rules) => ctx.combat = rules,
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Combat config: {}", self.name, err)),
+        }
+        match LevelingRules::from_config(&ctx.config) {
+            Ok(rules) => ctx.leveling = rules,
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Leveling config: {}", self.name, err)),
+        }
+        match config_section(&ctx.config, "ownership") {
+            Ok(rules) => ctx.ownership = rules,
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Ownership config: {}", self.name, err)),
+        }
+        match config_section(&ctx.config, "turns") {
+            Ok(rules) => ctx.turns = TurnState::new(rules),
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Turn config: {}", self.name, err)),
+        }
+        match config_section(&ctx.config, "movement") {
+            Ok(rules) => ctx.movement = rules,
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Movement config: {}", self.name, err)),
+        }
+        match config_section(&ctx.config, "follow") {
+            Ok(rules) => ctx.follow = rules,
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Follow config: {}", self.name, err)),
+        }
+        match config_section(&ctx.config, "fog_of_war") {
+            Ok(rules) => ctx.fog_of_war = rules,
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Fog of war config: {}", self.name, err)),
+        }
+        match config_section(&ctx.config, "weather") {
+            Ok(rules) => ctx.weather = Weather::new(rules),
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Weather config: {}", self.name, err)),
+        }
+        match config_section::<SimulationRules>(&ctx.config, "simulation") {
+            Ok(rules) => {
+                if let Some(seed) = rules.seed {
+                    ctx.rng = RegionRng::seeded(seed, &self.name);
+                }
+                let game_tick_ms = get_config_i32_default(&ctx, "game", "game_tick_ms", 250) as f32;
+                let frames_per_tick = (game_tick_ms * target_fps / 1000.0).round() as u64;
+                self.simulation = Simulation::new(rules, &self.name, frames_per_tick);
+            }
+            Err(err) => ctx
+                .startup_errors
+                .push(format!("[error] {}: Simulation config: {}", self.name, err)),
+        }
 
         self.entity_block_mode = {
             let mode = get_config_string_default(&ctx, "game", "entity_block_mode", "always");
@@ -664,7 +816,12 @@ impl RegionInstance {
                 if let Some(data) = ctx.entity_class_data.get(&class_name) {
                     for e in ctx.map.entities.iter_mut() {
                         if e.id == entity.id {
-                            apply_entity_data(e, data);
+                            if let Err(err) = apply_entity_data(e, data) {
+                                ctx.startup_errors.push(format!(
+                                    "[error] {}: Character '{}': {}",
+                                    self.name, class_name, err
+                                ));
+                            }
 
                             // Fill up the inventory slots
                             if let Some(Value::Int(inv_slots)) = e.attributes.get("inventory_slots")
@@ -697,6 +854,7 @@ impl RegionInstance {
                     ctx.curr_entity_id = entity.id;
 
                     if let Some(program) = ctx.entity_programs.get(&class_name).cloned() {
+                        run_setup_fn(&mut self.exec, &program, ctx);
                         let args = [VMValue::from_string("startup"), VMValue::zero()];
                         run_server_fn(&mut self.exec, &args, &program, ctx);
                     }
@@ -729,7 +887,9 @@ impl RegionInstance {
                         }
                     }
 
-                    if !sector_name.is_empty() {
+                    if !sector_name.is_empty()
+                        && sector_trigger_enabled(ctx, &sector_name, "entered", true)
+                    {
                         // let cmd = format!("{}.event(\"entered\", \"{}\")", class_name, sector_name);
                         // _ = self.execute(&cmd);
                         if let Some(program) = ctx.entity_programs.get(&class_name).cloned() {
@@ -888,7 +1048,12 @@ impl RegionInstance {
                     if let Some(data) = ctx.item_class_data.get(&class_name) {
                         for i in ctx.map.items.iter_mut() {
                             if i.id == item.id {
-                                apply_item_data(i, data);
+                                if let Err(err) = apply_item_data(i, data) {
+                                    ctx.startup_errors.push(format!(
+                                        "[error] {}: Item '{}': {}",
+                                        self.name, class_name, err
+                                    ));
+                                }
                                 *item = i.clone();
                             }
                         }
@@ -912,6 +1077,7 @@ impl RegionInstance {
                     ctx.curr_item_id = Some(item.id);
 
                     if let Some(program) = ctx.item_programs.get(&class_name).cloned() {
+                        run_setup_fn(&mut self.exec, &program, ctx);
                         let args = [VMValue::from_string("startup"), VMValue::zero()];
                         run_server_fn(&mut self.exec, &args, &program, ctx);
                     }
@@ -932,6 +1098,9 @@ impl RegionInstance {
             for l in messages {
                 ctx.send_log_message(l);
             }
+
+            self.authored = ctx.map.clone();
+            self.baseline = PersistenceCache::capture(ctx);
         });
 
         // Send startup log message
@@ -941,8 +1110,39 @@ impl RegionInstance {
         );
     }
 
-    /// System tick
+    /// The entities, items and sector properties which changed since the startup, including
+    /// the players. Applied over the authored map it restores the current state.
+    pub fn dirty_snapshot(&self) -> Option<PersistedRegion> {
+        with_regionctx(self.id, |ctx| self.baseline.changes(ctx, true))
+    }
+
+    /// The map of the region as it was after the startup.
+    pub fn authored_map(&self) -> &Map {
+        &self.authored
+    }
+
+    /// System tick. In deterministic mode the region advances its ticks in the redraw tick
+    /// and the call is ignored.
     pub fn system_tick(&mut self) {
+        if !self.simulation.rules.is_deterministic() {
+            self.game_tick();
+        }
+    }
+
+    /// The recorded inputs of the region, if recording is enabled.
+    pub fn simulation_log(&self) -> Option<&SimulationLog> {
+        self.simulation.recording.as_ref()
+    }
+
+    /// Replay the recorded inputs instead of the live ones. Has to be called before the first
+    /// redraw tick of the region.
+    pub fn replay(&mut self, log: SimulationLog) -> Result<(), String> {
+        self.simulation.start_replay(log)
+    }
+
+    /// Advance the game by one tick.
+    fn game_tick(&mut self) {
+        let start = Instant::now();
         let mut ticks = 0;
 
         with_regionctx(self.id, |ctx| {
@@ -960,6 +1160,8 @@ impl RegionInstance {
                 self.from_sender
                     .send(RegionMessage::Time(self.id, ctx.time))
                     .unwrap();
+                send_weather(ctx);
+                tick_schedule(ctx);
             }
         });
 
@@ -1045,6 +1247,33 @@ impl RegionInstance {
             });
         });
 
+        // Tick the weather, status effects, behavior trees, routines, followers, cutscenes,
+        // projectiles and the fog of war
+        let mut spawns = vec![];
+        with_regionctx(self.id, |ctx| {
+            if !ctx.paused {
+                tick_weather(ctx);
+                tick_effects(ctx);
+                tick_behaviors(ctx);
+                tick_routines(ctx);
+                tick_followers(ctx);
+                tick_sector_dwell(ctx);
+                tick_claims(ctx);
+                tick_cutscenes(ctx);
+                tick_projectiles(ctx);
+                tick_fog(ctx);
+                spawns = due_spawns(ctx, &self.name);
+            }
+        });
+
+        // Create the entities of the spawners
+        for entity in spawns {
+            self.create_entity_instance(entity);
+        }
+
+        // Resolve the round in turn-based mode
+        self.resolve_round();
+
         // Check Proximity Alerts
         with_regionctx(self.id, |ctx| {
             for (id, radius) in ctx.entity_proximity_alerts.iter() {
@@ -1075,13 +1304,120 @@ impl RegionInstance {
                     .send(RegionMessage::DebugData(ctx.debug.clone()))
                     .unwrap();
             }
+
+            ctx.stats
+                .record_game_tick(start.elapsed().as_secs_f32() * 1000.0);
         });
     }
 
+    /// Resolve one step of the action of each entity in initiative order if the round is ready,
+    /// then start the next round.
+    fn resolve_round(&mut self) {
+        let Some(round) = with_regionctx(self.id, |ctx| {
+            if ctx.paused || !ctx.turns.is_enabled() {
+                None
+            } else {
+                take_round(ctx)
+            }
+        })
+        .flatten() else {
+            return;
+        };
+        // move_entity moves 4 units per second scaled by the direction
+        let (step, scale) = with_regionctx(self.id, |ctx| {
+            (ctx.turns.rules.step, 1.0 / (4.0 * ctx.delta_time))
+        })
+        .unwrap_or((1.0, 1.0));
+
+        for (id, action) in round {
+            let Some(mut entity) = with_regionctx(self.id, |ctx| {
+                ctx.map.entities.iter().find(|e| e.id == id).cloned()
+            })
+            .flatten() else {
+                continue;
+            };
+
+            let target = match &action {
+                EntityAction::Forward
+                | EntityAction::Backward
+                | EntityAction::Left
+                | EntityAction::Right => {
+                    match action {
+                        EntityAction::Forward => entity.face_north(),
+                        EntityAction::Backward => entity.face_south(),
+                        EntityAction::Left => entity.face_west(),
+                        _ => entity.face_east(),
+                    }
+                    let intent = entity.attributes.get_str_default("intent", "".into());
+                    if intent.is_empty() {
+                        self.move_entity(&mut entity, step * scale, self.entity_block_mode);
+                    } else {
+                        let position = entity.get_forward_pos(1.0);
+                        self.send_entity_intent_events(&mut entity, position);
+                    }
+                    None
+                }
+                EntityAction::Analog(direction) => {
+                    let strength = direction.magnitude().min(1.0);
+                    if strength > 0.0 {
+                        entity.set_orientation(direction.normalized());
+                        let intent = entity.attributes.get_str_default("intent", "".into());
+                        if intent.is_empty() {
+                            self.move_entity(&mut entity, step * scale, self.entity_block_mode);
+                        } else {
+                            let position = entity.get_forward_pos(1.0);
+                            self.send_entity_intent_events(&mut entity, position);
+                        }
+                    }
+                    None
+                }
+                EntityAction::Goto(coord, _) => Some(*coord),
+                EntityAction::Follow(..) => {
+                    with_regionctx(self.id, |ctx| formation_position(ctx, id)).flatten()
+                }
+                _ => None,
+            };
+
+            if let Some(target) = target {
+                let to_target = target - entity.get_pos_xz();
+                let distance = to_target.magnitude();
+                if distance > 0.01 {
+                    entity.set_orientation(to_target / distance);
+                    self.move_entity(
+                        &mut entity,
+                        step.min(distance) * scale,
+                        self.entity_block_mode,
+                    );
+                }
+                if distance <= step && matches!(action, EntityAction::Goto(..)) {
+                    entity.action = EntityAction::Off;
+                }
+            }
+
+            with_regionctx(self.id, |ctx| {
+                if let Some(e) = ctx.get_entity_mut(id) {
+                    *e = entity;
+                }
+                ctx.check_player_for_section_change_id(id);
+                end_turn(ctx, id);
+            });
+        }
+
+        with_regionctx(self.id, start_round);
+    }
+
     /// Redraw tick
     pub fn redraw_tick(&mut self) {
+        let start = Instant::now();
+        let tick_due = self.simulation.advance_frame();
+
         // Catch up with the server messages
-        while let Ok(msg) = self.to_receiver.try_recv() {
+        let mut messages: Vec<RegionMessage> = self.to_receiver.try_iter().collect();
+        with_regionctx(self.id, |ctx| {
+            ctx.stats.record_queue_depth(messages.len());
+        });
+        self.simulation.process(self.id, &mut messages);
+        for msg in messages {
             match msg {
                 Pause => {
                     with_regionctx(self.id, |ctx: &mut RegionCtx| {
@@ -1148,6 +1484,9 @@ impl RegionInstance {
                     //     );
                     // }
                 }
+                UserAction(entity_id, _)
+                    if with_regionctx(self.id, |ctx| is_input_locked(ctx, entity_id))
+                        .unwrap_or(false) => {}
                 UserAction(entity_id, action) => match action {
                     Intent(intent) => {
                         with_regionctx(self.id, |ctx: &mut RegionCtx| {
@@ -1286,59 +1625,17 @@ impl RegionInstance {
                     Choice(choice) => match &choice {
                         Choice::ItemToSell(item_id, seller_id, buyer_id) => {
                             with_regionctx(self.id, |ctx: &mut RegionCtx| {
-                                let mut msg_to_buyer: Option<String> = None;
-                                let mut msg_role = "system";
-
-                                // Get the price of the item.
-                                let mut price = 0;
-                                let mut can_afford = false;
-                                if let Some(entity) = get_entity_mut(&mut ctx.map, *seller_id) {
-                                    if let Some(item) = entity.get_item(*item_id) {
-                                        if let Some(w) = item.get_attribute("worth") {
-                                            if let Some(worth) = w.to_i32() {
-                                                price = worth as i64;
-                                            }
-                                        }
-                                    }
-                                }
-
-                                // Check if the buyer can afford
-                                if let Some(entity) = get_entity_mut(&mut ctx.map, *buyer_id) {
-                                    can_afford = entity.wallet.can_afford(price, &ctx.currencies);
-                                }
-
-                                if can_afford {
-                                    let mut item_to_sell: Option<Item> = None;
-                                    if let Some(entity) = get_entity_mut(&mut ctx.map, *seller_id) {
-                                        if let Some(item) = entity.remove_item(*item_id) {
-                                            item_to_sell = Some(item);
-                                            _ = entity.add_base_currency(price, &ctx.currencies);
-                                        }
-                                    }
-                                    if let Some(item) = item_to_sell {
-                                        if let Some(entity) =
-                                            get_entity_mut(&mut ctx.map, *buyer_id)
-                                        {
-                                            msg_to_buyer = Some(format!(
-                                                "{{you_bought}} {{I:{}.name, article=indef, case=lower}}",
-                                                item.id
-                                            ));
-                                            _ = entity.add_item(item);
-                                            _ = entity.spend_currency(price, &ctx.currencies);
-                                        }
-                                    }
-                                } else {
-                                    msg_to_buyer = Some("{cant_afford}".into());
-                                    msg_role = "warning";
-                                }
-
-                                if let Some(msg_to_buyer) = msg_to_buyer {
-                                    send_message(ctx, *buyer_id, msg_to_buyer, msg_role);
-                                }
+                                trade(ctx, *buyer_id, *seller_id, *item_id, true);
+                            });
+                        }
+                        Choice::Dialogue(_speaker_id, listener_id, index) => {
+                            with_regionctx(self.id, |ctx: &mut RegionCtx| {
+                                select_dialogue_choice(ctx, *listener_id, *index);
                             });
                         }
                         Choice::Cancel(from_id, to_id) => {
                             with_regionctx(self.id, |ctx: &mut RegionCtx| {
+                                end_dialogue(ctx, *to_id);
                                 if let Some(_class_name) = ctx.entity_classes.get(from_id) {
                                     // let cmd = format!("{}.event('goodbye', {})", class_name, to_id);
                                     ctx.to_execute_entity.push((
@@ -1350,9 +1647,27 @@ impl RegionInstance {
                             });
                         }
                     },
+                    Buy(vendor_id, item_id) => {
+                        with_regionctx(self.id, |ctx: &mut RegionCtx| {
+                            trade(ctx, entity_id, vendor_id, item_id, true);
+                        });
+                    }
+                    Sell(vendor_id, item_id) => {
+                        with_regionctx(self.id, |ctx: &mut RegionCtx| {
+                            trade(ctx, entity_id, vendor_id, item_id, false);
+                        });
+                    }
+                    Text(event, text) => {
+                        with_regionctx(self.id, |ctx: &mut RegionCtx| {
+                            ctx.to_execute_entity
+                                .push((entity_id, event, VMValue::from(text)));
+                        });
+                    }
                     _ => {
                         with_regionctx(self.id, |ctx: &mut RegionCtx| {
-                            if let Some(entity) = ctx
+                            if ctx.turns.is_enabled() {
+                                ctx.turns.queue(entity_id, action);
+                            } else if let Some(entity) = ctx
                                 .map
                                 .entities
                                 .iter_mut()
@@ -1376,6 +1691,11 @@ impl RegionInstance {
                         ctx.time = time;
                     });
                 }
+                Stats(_, None) => {
+                    if let Some(stats) = self.stats() {
+                        _ = self.from_sender.send(Stats(self.id, Some(Box::new(stats))));
+                    }
+                }
                 Quit => {
                     println!("Shutting down '{}'. Goodbye.", self.name);
                 }
@@ -1389,15 +1709,23 @@ impl RegionInstance {
         let mut item_updates: Vec<Vec<u8>> = vec![];
 
         let mut entities = vec![];
+        let mut turn_based = false;
         with_regionctx(self.id, |ctx: &mut RegionCtx| {
             if ctx.paused {
                 return;
             }
             entities = ctx.map.entities.clone();
+            turn_based = ctx.turns.is_enabled();
         });
 
         for entity in &mut entities {
-            match &entity.action.clone() {
+            // In turn-based mode the actions are resolved per round
+            let action = if turn_based {
+                EntityAction::Off
+            } else {
+                entity.action.clone()
+            };
+            match &action {
                 EntityAction::Forward => {
                     if entity.is_player() {
                         let intent = entity.attributes.get_str_default("intent", "".into());
@@ -1497,6 +1825,30 @@ impl RegionInstance {
                         self.move_entity(entity, -1.0, self.entity_block_mode);
                     }
                 }
+                EntityAction::Analog(direction) => {
+                    let strength = direction.magnitude().min(1.0);
+                    if strength > 0.0 {
+                        let intent = entity.attributes.get_str_default("intent", "".into());
+                        let first_person = matches!(
+                            entity.attributes.get("player_camera"),
+                            Some(Value::PlayerCamera(PlayerCamera::D3FirstP))
+                        );
+                        if !intent.is_empty() && entity.is_player() {
+                            // If intent is set we send "intent" events
+                            entity.set_orientation(direction.normalized());
+                            let position = entity.get_forward_pos(1.0);
+                            self.send_entity_intent_events(entity, position);
+                            entity.action = EntityAction::Off;
+                        } else if first_person {
+                            // First person: x turns, y moves forward and backward
+                            entity.turn_right(4.0 * direction.x);
+                            self.move_entity(entity, -direction.y, self.entity_block_mode);
+                        } else {
+                            entity.set_orientation(direction.normalized());
+                            self.move_entity(entity, strength, self.entity_block_mode);
+                        }
+                    }
+                }
                 EntityAction::CloseIn(target, target_radius, speed) => {
                     if is_entity_dead(self.id, *target) {
                         continue;
@@ -1509,7 +1861,8 @@ impl RegionInstance {
                     let mut coord: Option<vek::Vec2<f32>> = None;
 
                     with_regionctx(self.id, |ctx| {
-                        let speed: f32 = 4.0 * speed * ctx.delta_time;
+                        let speed: f32 = 4.0 * speed * speed_factor(ctx, entity) * ctx.delta_time;
+                        let modes = movement_modes(entity);
 
                         if let Some(entity) =
                             ctx.map.entities.iter().find(|entity| entity.id == *target)
@@ -1525,9 +1878,11 @@ impl RegionInstance {
                                 speed,
                                 radius,
                                 1.0,
+                                &modes,
                             );
 
                             entity.set_pos_xz(new_position);
+                            update_movement_mode(ctx, entity);
                             if arrived {
                                 entity.action = EntityAction::Off;
 
@@ -1549,18 +1904,42 @@ impl RegionInstance {
                         }
                     });
                 }
+                EntityAction::Follow(_, speed) => {
+                    let position = entity.get_pos_xz();
+                    let radius = entity.attributes.get_float_default("radius", 0.5) - 0.01;
+
+                    with_regionctx(self.id, |ctx| {
+                        let Some(target) = formation_position(ctx, entity.id) else {
+                            entity.action = EntityAction::Off;
+                            return;
+                        };
+                        if position.distance(target) > 0.25 * ctx.follow.spacing {
+                            let speed = 4.0 * speed * speed_factor(ctx, entity) * ctx.delta_time;
+                            let modes = movement_modes(entity);
+                            let (new_position, _) = ctx
+                                .mapmini
+                                .move_towards(position, target, speed, radius, 1.0, &modes);
+                            entity.face_at(target);
+                            entity.set_pos_xz(new_position);
+                            update_movement_mode(ctx, entity);
+                            ctx.check_player_for_section_change(entity);
+                        }
+                    });
+                }
                 EntityAction::Goto(coord, speed) => {
                     let position = entity.get_pos_xz();
                     let radius = entity.attributes.get_float_default("radius", 0.5) - 0.01;
 
                     with_regionctx(self.id, |ctx| {
-                        let speed = 4.0 * speed * ctx.delta_time;
+                        let speed = 4.0 * speed * speed_factor(ctx, entity) * ctx.delta_time;
+                        let modes = movement_modes(entity);
 
                         let (new_position, arrived) = ctx
                             .mapmini
-                            .move_towards(position, *coord, speed, radius, 1.0);
+                            .move_towards(position, *coord, speed, radius, 1.0, &modes);
 
                         entity.set_pos_xz(new_position);
+                        update_movement_mode(ctx, entity);
                         if arrived {
                             entity.action = EntityAction::Off;
 
@@ -1585,19 +1964,75 @@ impl RegionInstance {
                         ctx.check_player_for_section_change(entity);
                     });
                 }
+                EntityAction::FollowPath(waypoints, speed) => {
+                    let position = entity.get_pos_xz();
+                    let radius = entity.attributes.get_float_default("radius", 0.5) - 0.01;
+
+                    with_regionctx(self.id, |ctx| {
+                        let step = 4.0 * speed * speed_factor(ctx, entity) * ctx.delta_time;
+                        let modes = movement_modes(entity);
+
+                        let mut waypoints = waypoints.clone();
+                        let Some(next) = waypoints.first().copied() else {
+                            entity.action = EntityAction::Off;
+                            return;
+                        };
+
+                        let to_vector = next - position;
+                        let new_position = if to_vector.magnitude() <= step {
+                            waypoints.remove(0);
+                            next
+                        } else {
+                            ctx.mapmini
+                                .move_distance_as(
+                                    position,
+                                    to_vector.normalized() * step,
+                                    radius,
+                                    &modes,
+                                )
+                                .0
+                        };
+                        entity.set_pos_xz(new_position);
+                        update_movement_mode(ctx, entity);
+
+                        if waypoints.is_empty() {
+                            entity.action = EntityAction::Off;
+
+                            let mut sector_name: String = String::new();
+                            if let Some(s) = ctx.map.find_sector_at(new_position) {
+                                sector_name = s.name.clone();
+                            }
+
+                            // Send arrived event
+                            if ctx.entity_classes.contains_key(&entity.id) {
+                                ctx.to_execute_entity.push((
+                                    entity.id,
+                                    "arrived".into(),
+                                    VMValue::from(sector_name),
+                                ));
+                            }
+                        } else {
+                            entity.action = EntityAction::FollowPath(waypoints, *speed);
+                        }
+                        ctx.check_player_for_section_change(entity);
+                    });
+                }
                 EntityAction::RandomWalk(distance, speed, max_sleep, state, target) => {
                     if *state == 0 {
                         // State 0: Uninitialized, find a target location.
-                        let pos = find_random_position(entity.get_pos_xz(), *distance);
+                        let curr_pos = entity.get_pos_xz();
+                        let pos = with_regionctx(self.id, |ctx| {
+                            find_random_position(curr_pos, *distance, &mut ctx.rng)
+                        })
+                        .unwrap_or(curr_pos);
                         entity.action = RandomWalk(*distance, *speed, *max_sleep, 1, pos);
                         entity.face_at(pos);
                     } else if *state == 1 {
                         // State 1: Walk towards
                         if target.distance(entity.get_pos_xz()) < 0.1 {
                             // Arrived, Sleep
-                            let mut rng = rand::rng();
                             entity.action = self.create_sleep_switch_action(
-                                rng.random_range(*max_sleep / 2..=*max_sleep) as u32,
+                                self.random_sleep(*max_sleep),
                                 RandomWalk(*distance, *speed, *max_sleep, 0, *target),
                             );
                         } else {
@@ -1605,11 +2040,8 @@ impl RegionInstance {
                             let max_sleep = *max_sleep;
                             let blocked = self.move_entity(entity, 1.0, self.entity_block_mode);
                             if blocked {
-                                let mut rng = rand::rng();
-                                entity.action = self.create_sleep_switch_action(
-                                    rng.random_range(max_sleep / 2..=max_sleep) as u32,
-                                    t,
-                                );
+                                entity.action = self
+                                    .create_sleep_switch_action(self.random_sleep(max_sleep), t);
                             }
                         }
                     }
@@ -1620,7 +2052,8 @@ impl RegionInstance {
                         let curr_pos = entity.get_pos_xz().clone();
                         with_regionctx(self.id, |ctx| {
                             if let Some(sector) = ctx.map.find_sector_at(curr_pos) {
-                                let mut new_pos = find_random_position(curr_pos, *distance);
+                                let mut new_pos =
+                                    find_random_position(curr_pos, *distance, &mut ctx.rng);
                                 let mut found = false;
 
                                 for _ in 0..10 {
@@ -1628,7 +2061,8 @@ impl RegionInstance {
                                         found = true;
                                         break;
                                     } else {
-                                        new_pos = find_random_position(curr_pos, *distance);
+                                        new_pos =
+                                            find_random_position(curr_pos, *distance, &mut ctx.rng);
                                     }
                                 }
 
@@ -1648,9 +2082,8 @@ impl RegionInstance {
                         // State 1: Walk towards
                         if target.distance(entity.get_pos_xz()) < 0.1 {
                             // Arrived, Sleep
-                            let mut rng = rand::rng();
                             entity.action = self.create_sleep_switch_action(
-                                rng.random_range(*max_sleep / 2..=*max_sleep) as u32,
+                                self.random_sleep(*max_sleep),
                                 RandomWalkInSector(*distance, *speed, *max_sleep, 0, *target),
                             );
                         } else {
@@ -1658,11 +2091,8 @@ impl RegionInstance {
                             let max_sleep = *max_sleep;
                             let blocked = self.move_entity(entity, 1.0, self.entity_block_mode);
                             if blocked {
-                                let mut rng = rand::rng();
-                                entity.action = self.create_sleep_switch_action(
-                                    rng.random_range(max_sleep / 2..=max_sleep) as u32,
-                                    t,
-                                );
+                                entity.action = self
+                                    .create_sleep_switch_action(self.random_sleep(max_sleep), t);
                             }
                         }
                     }
@@ -1719,6 +2149,7 @@ impl RegionInstance {
             let mut state_data = FxHashMap::default();
 
             with_regionctx(self.id, |ctx| {
+                on_quest_event(ctx, todo.0, &todo.1, &todo.2);
                 ctx.curr_entity_id = todo.0;
                 ctx.curr_item_id = None;
                 state_data = ctx.entity_state_data.clone();
@@ -1820,6 +2251,30 @@ impl RegionInstance {
             //     );
             // }
         }
+
+        with_regionctx(self.id, |ctx| {
+            ctx.stats
+                .redraw_ticks
+                .add(start.elapsed().as_secs_f32() * 1000.0);
+        });
+
+        if tick_due {
+            self.game_tick();
+        }
+    }
+
+    /// The current metrics of the region.
+    pub fn stats(&self) -> Option<RegionStats> {
+        with_regionctx(self.id, |ctx| {
+            let mut stats = ctx.stats.clone();
+            stats.pending_entity_events = ctx.to_execute_entity.len();
+            stats.pending_item_events = ctx.to_execute_item.len();
+            stats.entities = ctx.map.entities.len();
+            stats.items = ctx.map.items.len();
+            stats.players = ctx.map.entities.iter().filter(|e| e.is_player()).count();
+            stats.projectiles = ctx.projectiles.len();
+            stats
+        })
     }
 
     /*
@@ -1868,10 +2323,19 @@ impl RegionInstance {
         .unwrap()
     }
 
+    /// A random sleep between half and the full maximum in minutes, drawn from the region rng.
+    fn random_sleep(&self, max_sleep: i32) -> u32 {
+        with_regionctx(self.id, |ctx| {
+            ctx.rng.random_range(max_sleep / 2..=max_sleep) as u32
+        })
+        .unwrap_or(0)
+    }
+
     /// Moves an entity forward or backward. Returns true if blocked.
     fn move_entity(&self, entity: &mut Entity, dir: f32, entity_block_mode: i32) -> bool {
         with_regionctx(self.id, |ctx| {
-            let speed = 4.0 * ctx.delta_time;
+            let speed = 4.0 * speed_factor(ctx, entity) * ctx.delta_time;
+            let modes = movement_modes(entity);
             let move_vector = entity.orientation * speed * dir;
             let position = entity.get_pos_xz();
             let radius = entity.attributes.get_float_default("radius", 0.5) - 0.01;
@@ -2029,7 +2493,7 @@ impl RegionInstance {
             // Finally, let the geometry/linedef collision do its thing (OLD SYSTEM)
             let (end_position, geometry_blocked) =
                 ctx.mapmini
-                    .move_distance(position, new_position - position, radius);
+                    .move_distance_as(position, new_position - position, radius, &modes);
 
             // Move the entity after geometry
             entity.set_pos_xz(end_position);
@@ -2069,6 +2533,7 @@ impl RegionInstance {
                 entity.position.y = y + 1.5;
             }
 
+            update_movement_mode(ctx, entity);
             ctx.check_player_for_section_change(entity);
             geometry_blocked || collision_blocked
         })
@@ -2090,10 +2555,13 @@ impl RegionInstance {
                 ctx.map.entities.push(entity.clone());
 
                 // Setting the data for the entity
+                let mut schema_error = None;
                 if let Some(data) = ctx.entity_class_data.get(&class_name) {
                     for e in ctx.map.entities.iter_mut() {
                         if e.id == entity.id {
-                            apply_entity_data(e, data);
+                            if let Err(err) = apply_entity_data(e, data) {
+                                schema_error = Some(err);
+                            }
 
                             // Fill up the inventory slots
                             if let Some(Value::Int(inv_slots)) = e.attributes.get("inventory_slots")
@@ -2111,6 +2579,12 @@ impl RegionInstance {
                         }
                     }
                 }
+                if let Some(err) = schema_error {
+                    ctx.send_log_message(format!(
+                        "[warn] {}: Character '{}': {}",
+                        self.name, class_name, err
+                    ));
+                }
 
                 ctx.curr_entity_id = entity.id;
 
@@ -2152,6 +2626,7 @@ impl RegionInstance {
             with_regionctx(self.id, |ctx: &mut RegionCtx| {
                 // Send startup event
                 if let Some(program) = ctx.entity_programs.get(&class_name).cloned() {
+                    run_setup_fn(&mut self.exec, &program, ctx);
                     let args = [VMValue::from_string("startup"), VMValue::zero()];
                     run_server_fn(&mut self.exec, &args, &program, ctx);
                 }
@@ -2276,6 +2751,13 @@ impl RegionInstance {
             }
             if !found_target {
                 if let Some(i_id) = get_item_at(ctx, position) {
+                    // Items claimed by another entity cannot be used
+                    if claimed_by(ctx, &Interactable::Item(i_id)).is_some_and(|id| id != entity.id)
+                    {
+                        entity.set_attribute("intent", Value::Str(String::new()));
+                        send_message(ctx, entity.id, "{claimed}".into(), "system");
+                        return;
+                    }
                     value.x = i_id as f32;
                     target_item_id = Some(i_id);
                     found_target = true;
@@ -2285,9 +2767,19 @@ impl RegionInstance {
             let intent = entity.attributes.get_str_default("intent", "".into());
 
             if !found_target {
-                let message = format!("{{nothing_to_{}}}", intent);
                 entity.set_attribute("intent", Value::Str(String::new()));
-                send_message(ctx, entity.id, message, "system");
+                let sector = entity.attributes.get_str_default("sector", "".into());
+                let usable =
+                    !sector.is_empty() && sector_trigger_enabled(ctx, &sector, "used", false);
+                match can_use(ctx, entity.id, &Interactable::Sector(sector.clone())) {
+                    Err(message) if usable => send_message(ctx, entity.id, message, "system"),
+                    _ => {
+                        if !send_sector_used(ctx, entity.id, &sector) {
+                            let message = format!("{{nothing_to_{}}}", intent);
+                            send_message(ctx, entity.id, message, "system");
+                        }
+                    }
+                }
                 return;
             }
 
@@ -2798,6 +3290,26 @@ fn deal_damage(id: u32, dict: PyObjectRef, vm: &VirtualMachine) {
 
 */
 
+/// Buy an item from or sell an item to a vendor and tell the customer about the outcome.
+fn trade(ctx: &mut RegionCtx, customer_id: u32, vendor_id: u32, item_id: u32, buying: bool) {
+    let result = if buying {
+        shop::buy(ctx, customer_id, vendor_id, item_id)
+    } else {
+        shop::sell(ctx, customer_id, vendor_id, item_id)
+    };
+    match result {
+        Ok(_) => {
+            let msg = format!(
+                "{{{}}} {{I:{}.name, article=indef, case=lower}}",
+                if buying { "you_bought" } else { "you_sold" },
+                item_id
+            );
+            send_message(ctx, customer_id, msg, "system");
+        }
+        Err(err) => send_message(ctx, customer_id, err, "warning"),
+    }
+}
+
 /// Send a message to the entity.
 fn send_message(ctx: &RegionCtx, id: u32, message: String, role: &str) {
     let msg = RegionMessage::Message(ctx.region_id, Some(id), None, id, message, role.to_string());
@@ -1,13 +1,37 @@
+use crate::server::combat::{AttackResult, CombatEventKind, attack, send_combat_event};
+use crate::server::crafting::craft;
+use crate::server::cutscene::play_cutscene;
+use crate::server::dialogue::start_dialogue;
+use crate::server::effect::{apply_effect, effect_stacks, remove_effect};
+use crate::server::floattext::{FloatTextStyle, send_float_text};
+use crate::server::follow::{follow, stop_following};
+use crate::server::leveling::{grant_xp, skill_level};
+use crate::server::loot::drop_loot;
 use crate::server::message::RegionMessage;
+use crate::server::ownership::{
+    Interactable, can_use, claim, is_locked, is_owned_by, owner_of, release, send_theft, set_locked,
+};
+use crate::server::particles::send_particles;
+use crate::server::projectile::spawn_projectile;
+use crate::server::quest::{advance_quest, quest_state, start_quest};
 use crate::server::region::add_debug_value;
+use crate::server::schedule::{schedule, unschedule};
+use crate::server::shop::{open_shop, transfer_funds};
+use crate::server::sound::send_sound;
+use crate::server::weather::{WeatherKind, send_weather};
 use crate::vm::*;
 use crate::{
-    Choice, EntityAction, Item, MultipleChoice, PixelSource, PlayerCamera, RegionCtx, Value,
+    AttrSchemas, Choice, EntityAction, Item, MultipleChoice, ParticleEmitter, PixelSource,
+    PlayerCamera, RegionCtx, Value,
 };
+use instant::Instant;
 use rand::Rng;
 use scenevm::GeoId;
-use theframework::prelude::TheValue;
-use vek::Vec2;
+use theframework::prelude::{FxHashMap, FxHashSet, TheValue};
+use vek::{Vec2, Vec3};
+
+/// How many cells around the start and goal `path_to` searches.
+const PATH_SEARCH_MARGIN: i32 = 16;
 
 struct RegionHost<'a> {
     ctx: &'a mut RegionCtx,
@@ -27,6 +51,53 @@ fn opening_geo_for_item(item: &Item) -> Option<GeoId> {
     Some(GeoId::Hole(host_id, profile_id))
 }
 
+/// Check a `set_attr` value against the attribute schema of the class, if it has one.
+fn check_attr(
+    schemas: &FxHashMap<String, AttrSchemas>,
+    class_name: &str,
+    key: &str,
+    value: Value,
+) -> Result<Value, String> {
+    match schemas.get(class_name) {
+        Some(schemas) => schemas.check(key, value),
+        None => Ok(value),
+    }
+}
+
+/// Pack a list of ids: x/y the first two ids, z the count, string the comma separated list.
+fn pack_ids(ids: &[u32]) -> VMValue {
+    let ids_str: Vec<String> = ids.iter().map(|i| i.to_string()).collect();
+    let mut v = VMValue::zero();
+    if let Some(id0) = ids.first() {
+        v.x = *id0 as f32;
+    }
+    if let Some(id1) = ids.get(1) {
+        v.y = *id1 as f32;
+    }
+    v.z = ids.len() as f32;
+    v.string = Some(ids_str.join(","));
+    v
+}
+
+/// The map position of a vector argument, vec3 positions use x and z.
+fn arg_position(v: &VMValue) -> Vec2<f32> {
+    if v.as_string() == Some("vec3") {
+        Vec2::new(v.x, v.z)
+    } else {
+        Vec2::new(v.x, v.y)
+    }
+}
+
+/// The position of the current item or entity.
+fn source_position(ctx: &mut RegionCtx) -> Option<Vec2<f32>> {
+    match ctx.curr_item_id {
+        Some(item_id) => ctx.get_item_mut(item_id).map(|item| item.get_pos_xz()),
+        None => ctx
+            .get_current_entity_mut()
+            .map(|entity| entity.get_pos_xz()),
+    }
+}
+
 impl<'a> HostHandler for RegionHost<'a> {
     fn on_host_call(&mut self, name: &str, args: &[VMValue]) -> Option<VMValue> {
         match name {
@@ -147,9 +218,31 @@ impl<'a> HostHandler for RegionHost<'a> {
                     (args.get(0).and_then(|v| v.as_string()), args.get(1))
                 {
                     if let Some(item_id) = self.ctx.curr_item_id {
-                        if let Some(item) = self.ctx.get_item_mut(item_id) {
-                            // Single conversion path with optional type hints (string tag or attr type).
-                            let converted = val.to_value_with_hint(item.attributes.get(key));
+                        // Single conversion path with optional type hints (string tag or attr type).
+                        let converted = self.ctx.get_item_mut(item_id).map(|item| {
+                            (
+                                item.get_attr_string("class_name").unwrap_or_default(),
+                                val.to_value_with_hint(item.attributes.get(key)),
+                            )
+                        });
+                        let converted = match converted {
+                            Some((class_name, value)) => {
+                                match check_attr(&self.ctx.item_schemas, &class_name, key, value) {
+                                    Ok(value) => Some(value),
+                                    Err(err) => {
+                                        self.ctx.send_log_message(format!(
+                                            "[warn] {} ({}) => set_attr: {}",
+                                            class_name, item_id, err
+                                        ));
+                                        None
+                                    }
+                                }
+                            }
+                            None => None,
+                        };
+                        if let (Some(converted), Some(item)) =
+                            (converted, self.ctx.get_item_mut(item_id))
+                        {
                             item.set_attribute(key, converted);
 
                             let (queue_active, queued_id, active_val) = if key == "active" {
@@ -187,8 +280,24 @@ impl<'a> HostHandler for RegionHost<'a> {
                             }
                         }
                     } else if let Some(entity) = self.ctx.get_current_entity_mut() {
+                        let class_name = entity.get_attr_string("class_name").unwrap_or_default();
                         let converted = val.to_value_with_hint(entity.attributes.get(key));
-                        entity.set_attribute(key, converted);
+                        match check_attr(&self.ctx.entity_schemas, &class_name, key, converted) {
+                            Ok(value) => {
+                                if let Some(entity) = self.ctx.get_current_entity_mut() {
+                                    entity.set_attribute(key, value);
+                                }
+                            }
+                            Err(err) => {
+                                let id = self.ctx.curr_entity_id;
+                                self.ctx.send_log_message(format!(
+                                    "[warn] {} ({}) => set_attr: {}",
+                                    self.ctx.get_entity_name(id),
+                                    id,
+                                    err
+                                ));
+                            }
+                        }
                     }
                 }
             }
@@ -257,11 +366,10 @@ impl<'a> HostHandler for RegionHost<'a> {
                     if lo > hi {
                         std::mem::swap(&mut lo, &mut hi);
                     }
-                    let mut rng = rand::rng();
-                    let r: i32 = rng.random_range(lo..=hi);
+                    let r: i32 = self.ctx.rng.random_range(lo..=hi);
                     return Some(VMValue::broadcast(r as f32));
                 } else {
-                    let r: f32 = rand::random();
+                    let r: f32 = self.ctx.rng.random();
                     return Some(VMValue::broadcast(r));
                 }
             }
@@ -287,6 +395,31 @@ impl<'a> HostHandler for RegionHost<'a> {
                     }
                 }
             }
+            "schedule" => {
+                if let (Some(rule), Some(event)) = (
+                    args.get(0).and_then(|v| v.as_string()),
+                    args.get(1).and_then(|v| v.as_string()),
+                ) {
+                    match schedule(self.ctx, rule, event) {
+                        Ok(id) => return Some(VMValue::from_i32(id as i32)),
+                        Err(err) => {
+                            let id = self.ctx.curr_entity_id;
+                            self.ctx.send_log_message(format!(
+                                "[warn] {} ({}) => schedule: {}",
+                                self.ctx.get_entity_name(id),
+                                id,
+                                err
+                            ));
+                            return Some(VMValue::from_i32(-1));
+                        }
+                    }
+                }
+            }
+            "unschedule" => {
+                if let Some(id) = args.get(0).map(|v| v.x as u32) {
+                    return Some(VMValue::from_bool(unschedule(self.ctx, id)));
+                }
+            }
             "random_walk" => {
                 // distance, speed, max_sleep
                 let distance = args.get(0).map(|v| v.x).unwrap_or(1.0);
@@ -334,6 +467,23 @@ impl<'a> HostHandler for RegionHost<'a> {
             }
             "take" => {
                 if let Some(item_id) = args.get(0).map(|v| v.x as u32) {
+                    let entity_id = self.ctx.curr_entity_id;
+                    let target = Interactable::Item(item_id);
+                    if let Err(message) = can_use(self.ctx, entity_id, &target) {
+                        if let Some(sender) = self.ctx.from_sender.get() {
+                            let _ = sender.send(RegionMessage::Message(
+                                self.ctx.region_id,
+                                Some(entity_id),
+                                None,
+                                entity_id,
+                                message,
+                                "system".into(),
+                            ));
+                        }
+                        return Some(VMValue::from_bool(false));
+                    }
+                    let owner = owner_of(self.ctx, &target);
+
                     let mut removed: Option<Item> = None;
                     if let Some(pos) = self.ctx.map.items.iter().position(|item| {
                         item.id == item_id && !item.attributes.get_bool_default("static", false)
@@ -344,6 +494,7 @@ impl<'a> HostHandler for RegionHost<'a> {
                     if let Some(item) = removed {
                         let entity_id = self.ctx.curr_entity_id;
                         let mut rc = true;
+                        let class_name = item.attributes.get_str_default("class_name", "".into());
 
                         if let Some(entity) = self
                             .ctx
@@ -424,6 +575,16 @@ impl<'a> HostHandler for RegionHost<'a> {
                                 let _ = sender.send(msg);
                             }
                         }
+                        if rc {
+                            if let Some(owner) = owner {
+                                send_theft(self.ctx, owner, entity_id, &class_name);
+                            }
+                            self.ctx.to_execute_entity.push((
+                                entity_id,
+                                "picked_up".into(),
+                                VMValue::from(class_name),
+                            ));
+                        }
                     } else if self.ctx.debug_mode {
                         add_debug_value(&mut self.ctx, TheValue::Text("Unknown Item".into()), true);
                     }
@@ -652,18 +813,97 @@ impl<'a> HostHandler for RegionHost<'a> {
                     }
                 }
 
-                // Pack result: x/y first two ids, z = count, string = comma list
-                let ids_str: Vec<String> = ids.iter().map(|i| i.to_string()).collect();
-                let mut v = VMValue::zero();
-                if let Some(id0) = ids.get(0) {
-                    v.x = *id0 as f32;
+                return Some(pack_ids(&ids));
+            }
+            "entities_in_cone" => {
+                // args: [direction, angle in degrees, radius], operates on current entity or item
+                let direction = args.get(0).map(arg_position).unwrap_or(Vec2::zero());
+                let half_angle = args.get(1).map(|v| v.x.to_radians() * 0.5).unwrap_or(0.0);
+                let radius = args.get(2).map(|v| v.x.max(0.0)).unwrap_or(0.0);
+
+                let mut ids: Vec<u32> = Vec::new();
+                if let Some(pos) = source_position(self.ctx) {
+                    if direction.magnitude_squared() > 0.0 {
+                        let direction = direction.normalized();
+                        for other in &self.ctx.map.entities {
+                            if self.ctx.curr_item_id.is_none()
+                                && other.id == self.ctx.curr_entity_id
+                            {
+                                continue;
+                            }
+                            let to_other = other.get_pos_xz() - pos;
+                            let other_radius = other.attributes.get_float_default("radius", 0.5);
+                            let distance = to_other.magnitude();
+                            if distance > radius + other_radius {
+                                continue;
+                            }
+                            let angle = if distance > 0.0 {
+                                direction.dot(to_other / distance).clamp(-1.0, 1.0).acos()
+                            } else {
+                                0.0
+                            };
+                            if angle <= half_angle {
+                                ids.push(other.id);
+                            }
+                        }
+                    }
                 }
-                if let Some(id1) = ids.get(1) {
-                    v.y = *id1 as f32;
+                return Some(pack_ids(&ids));
+            }
+            "items_in_radius" => {
+                // args: [radius], operates on current entity or item
+                let radius = args.get(0).map(|v| v.x.max(0.0)).unwrap_or(0.5);
+
+                let mut ids: Vec<u32> = Vec::new();
+                if let Some(pos) = source_position(self.ctx) {
+                    for item in &self.ctx.map.items {
+                        if self.ctx.curr_item_id == Some(item.id) {
+                            continue;
+                        }
+                        if (pos - item.get_pos_xz()).magnitude_squared() <= radius * radius {
+                            ids.push(item.id);
+                        }
+                    }
+                }
+                return Some(pack_ids(&ids));
+            }
+            "raycast" => {
+                // args: [from, to]. Returns the hit position in x/y and in z the id of the hit
+                // entity, -1 if a wall was hit or -2 if the ray is clear.
+                if let (Some(from), Some(to)) = (args.get(0), args.get(1)) {
+                    let (from, to) = (arg_position(from), arg_position(to));
+                    let wall = self.ctx.mapmini.raycast(from, to);
+                    let end = wall.unwrap_or(to);
+
+                    let segment = end - from;
+                    let length = segment.magnitude_squared();
+                    let entity = self
+                        .ctx
+                        .map
+                        .entities
+                        .iter()
+                        .filter(|entity| {
+                            self.ctx.curr_item_id.is_some() || entity.id != self.ctx.curr_entity_id
+                        })
+                        .filter_map(|entity| {
+                            let p = entity.get_pos_xz();
+                            let t = if length > 0.0 {
+                                ((p - from).dot(segment) / length).clamp(0.0, 1.0)
+                            } else {
+                                0.0
+                            };
+                            let radius = entity.attributes.get_float_default("radius", 0.5);
+                            ((from + segment * t).distance(p) <= radius).then_some((entity.id, t))
+                        })
+                        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+                    let (hit, id) = match (entity, wall) {
+                        (Some((id, t)), _) => (from + segment * t, id as f32),
+                        (None, Some(wall)) => (wall, -1.0),
+                        (None, None) => (to, -2.0),
+                    };
+                    return Some(VMValue::new(hit.x, hit.y, id));
                 }
-                v.z = ids.len() as f32;
-                v.string = Some(ids_str.join(","));
-                return Some(v);
             }
             "list_get" => {
                 // list is arg0 (comma-separated string), index is arg1
@@ -746,6 +986,289 @@ impl<'a> HostHandler for RegionHost<'a> {
                     ));
                 }
             }
+            "apply_effect" | "has_effect" | "remove_effect" => {
+                if let (Some(target), Some(effect)) = (
+                    args.get(0).map(|v| v.x as u32),
+                    args.get(1).and_then(|v| v.as_string()),
+                ) {
+                    if name == "apply_effect" && !self.ctx.assets.effects.contains_key(effect) {
+                        let id = self.ctx.curr_entity_id;
+                        self.ctx.send_log_message(format!(
+                            "[warn] {} ({}) => apply_effect: Unknown effect '{}'.",
+                            self.ctx.get_entity_name(id),
+                            id,
+                            effect
+                        ));
+                        return Some(VMValue::from_bool(false));
+                    }
+                    return Some(match name {
+                        "apply_effect" => {
+                            VMValue::from_bool(apply_effect(self.ctx, target, effect, 0))
+                        }
+                        "remove_effect" => {
+                            VMValue::from_bool(remove_effect(self.ctx, target, effect))
+                        }
+                        _ => VMValue::from_u32(
+                            self.ctx
+                                .map
+                                .entities
+                                .iter()
+                                .find(|e| e.id == target)
+                                .map(|e| effect_stacks(e, effect))
+                                .unwrap_or(0),
+                        ),
+                    });
+                }
+            }
+            "craft" => {
+                if let Some(recipe) = args.get(0).and_then(|v| v.as_string()) {
+                    let id = self.ctx.curr_entity_id;
+                    let item_id = match craft(self.ctx, id, recipe) {
+                        Ok(ids) => ids.first().map(|id| *id as i32).unwrap_or(-1),
+                        Err(_) => -1,
+                    };
+                    return Some(VMValue::from_i32(item_id));
+                }
+            }
+            "drop_loot" => {
+                if let Some(table) = args.get(0).and_then(|v| v.as_string()) {
+                    // Vectors are map positions, otherwise drop at the current entity
+                    let position = match args.get(1).and_then(|v| v.as_string()) {
+                        Some("vec2") => args.get(1).map(|v| Vec3::new(v.x, 0.0, v.y)),
+                        Some("vec3") => args.get(1).map(|v| Vec3::new(v.x, v.y, v.z)),
+                        _ => self.ctx.get_current_entity_mut().map(|e| e.position),
+                    };
+                    if let Some(position) = position {
+                        match drop_loot(self.ctx, table, position) {
+                            Ok(ids) => return Some(VMValue::from_i32(ids.len() as i32)),
+                            Err(err) => {
+                                let id = self.ctx.curr_entity_id;
+                                self.ctx.send_log_message(format!(
+                                    "[warn] {} ({}) => drop_loot: {}",
+                                    self.ctx.get_entity_name(id),
+                                    id,
+                                    err
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            "play_sound" => {
+                if let (Some(name), Some(volume), Some(range)) = (
+                    args.get(0).and_then(|v| v.as_string()),
+                    args.get(1),
+                    args.get(2),
+                ) {
+                    // The sound plays at the position of the calling entity or item
+                    let position = match self.ctx.curr_item_id {
+                        Some(item_id) => self.ctx.get_item_mut(item_id).map(|item| item.position),
+                        None => self
+                            .ctx
+                            .get_current_entity_mut()
+                            .map(|entity| entity.position),
+                    };
+                    if let Some(position) = position {
+                        send_sound(self.ctx, name, position, volume.x, range.x);
+                    }
+                }
+            }
+            "emit_particles" => {
+                if let (Some(color), Some(rate), Some(duration)) =
+                    (args.get(0), args.get(1), args.get(2))
+                {
+                    // The particles rise from the calling entity or item
+                    let position = match self.ctx.curr_item_id {
+                        Some(item_id) => self.ctx.get_item_mut(item_id).map(|item| item.position),
+                        None => self
+                            .ctx
+                            .get_current_entity_mut()
+                            .map(|entity| entity.position),
+                    };
+                    if let Some(position) = position {
+                        let mut emitter = ParticleEmitter::new(position, Vec3::unit_y());
+                        emitter.color = [
+                            (color.x.clamp(0.0, 1.0) * 255.0) as u8,
+                            (color.y.clamp(0.0, 1.0) * 255.0) as u8,
+                            (color.z.clamp(0.0, 1.0) * 255.0) as u8,
+                            255,
+                        ];
+                        emitter.rate = rate.x.max(0.0);
+                        send_particles(self.ctx, emitter, duration.x.max(0.0));
+                    }
+                }
+            }
+            "float_text" => {
+                if let (Some(entity), Some(text)) =
+                    (args.get(0), args.get(1).and_then(|v| v.as_string()))
+                {
+                    let style = args
+                        .get(2)
+                        .and_then(|v| v.as_string())
+                        .and_then(FloatTextStyle::from_name)
+                        .unwrap_or_default();
+                    send_float_text(self.ctx, entity.x as u32, text, style);
+                }
+            }
+            "follow" => {
+                if let Some(leader) = args.get(0) {
+                    let id = self.ctx.curr_entity_id;
+                    match follow(self.ctx, id, leader.x as u32) {
+                        Ok(()) => return Some(VMValue::from_bool(true)),
+                        Err(err) => {
+                            self.ctx.send_log_message(format!(
+                                "[warn] {} ({}) => follow: {}",
+                                self.ctx.get_entity_name(id),
+                                id,
+                                err
+                            ));
+                            return Some(VMValue::from_bool(false));
+                        }
+                    }
+                }
+            }
+            "stop_following" => {
+                let id = self.ctx.curr_entity_id;
+                return Some(VMValue::from_bool(stop_following(self.ctx, id)));
+            }
+            "claim" | "lock" | "unlock" => {
+                if let Some(target) = args.get(0).map(Interactable::from_vm) {
+                    let id = self.ctx.curr_entity_id;
+                    let result = if name == "claim" {
+                        claim(self.ctx, id, target)
+                    } else {
+                        set_locked(self.ctx, id, &target, name == "lock")
+                    };
+                    match result {
+                        Ok(()) => return Some(VMValue::from_bool(true)),
+                        Err(err) => {
+                            self.ctx.send_log_message(format!(
+                                "[warn] {} ({}) => {}: {}",
+                                self.ctx.get_entity_name(id),
+                                id,
+                                name,
+                                err
+                            ));
+                            return Some(VMValue::from_bool(false));
+                        }
+                    }
+                }
+            }
+            "release" => {
+                if let Some(target) = args.get(0).map(Interactable::from_vm) {
+                    let id = self.ctx.curr_entity_id;
+                    return Some(VMValue::from_bool(release(self.ctx, id, &target)));
+                }
+            }
+            "is_owned_by" => {
+                if let (Some(target), Some(entity)) =
+                    (args.get(0).map(Interactable::from_vm), args.get(1))
+                {
+                    return Some(VMValue::from_bool(is_owned_by(
+                        self.ctx,
+                        &target,
+                        entity.x as u32,
+                    )));
+                }
+            }
+            "is_locked" => {
+                if let Some(target) = args.get(0).map(Interactable::from_vm) {
+                    return Some(VMValue::from_bool(is_locked(self.ctx, &target)));
+                }
+            }
+            "play_cutscene" => {
+                if let Some(cutscene) = args.get(0).and_then(|v| v.as_string()) {
+                    let id = self.ctx.curr_entity_id;
+                    let cutscene = cutscene.to_string();
+                    if let Err(err) = play_cutscene(self.ctx, id, &cutscene) {
+                        self.ctx.send_log_message(format!(
+                            "[warn] {} ({}) => play_cutscene: {}",
+                            self.ctx.get_entity_name(id),
+                            id,
+                            err
+                        ));
+                        return Some(VMValue::from_bool(false));
+                    }
+                    return Some(VMValue::from_bool(true));
+                }
+            }
+            "grant_xp" | "skill_level" => {
+                if let Some(target) = args.get(0).map(|v| v.x as u32) {
+                    let skill = args
+                        .get(if name == "grant_xp" { 2 } else { 1 })
+                        .and_then(|v| v.as_string())
+                        .unwrap_or_default()
+                        .to_string();
+                    let result = if name == "grant_xp" {
+                        let amount = args.get(1).map(|v| v.x as i32).unwrap_or(0);
+                        grant_xp(self.ctx, target, &skill, amount)
+                    } else {
+                        skill_level(self.ctx, target, &skill)
+                    };
+                    match result {
+                        Ok(level) => return Some(VMValue::broadcast(level as f32)),
+                        Err(err) => {
+                            let id = self.ctx.curr_entity_id;
+                            self.ctx.send_log_message(format!(
+                                "[warn] {} ({}) => {}: {}",
+                                self.ctx.get_entity_name(id),
+                                id,
+                                name,
+                                err
+                            ));
+                            return Some(VMValue::zero());
+                        }
+                    }
+                }
+            }
+            "shoot" => {
+                if let Some(class_name) = args.get(0).and_then(|v| v.as_string()) {
+                    // Vectors are map positions, otherwise the id of the target entity
+                    let target = match args.get(1) {
+                        Some(v) if matches!(v.as_string(), Some("vec2")) => {
+                            Some(Vec2::new(v.x, v.y))
+                        }
+                        Some(v) if matches!(v.as_string(), Some("vec3")) => {
+                            Some(Vec2::new(v.x, v.z))
+                        }
+                        Some(v) => self
+                            .ctx
+                            .map
+                            .entities
+                            .iter()
+                            .find(|entity| entity.id == v.x as u32)
+                            .map(|entity| entity.get_pos_xz()),
+                        None => None,
+                    };
+                    let id = self.ctx.curr_entity_id;
+                    let result = target
+                        .ok_or("Unknown target.".to_string())
+                        .and_then(|target| spawn_projectile(self.ctx, id, class_name, target));
+                    match result {
+                        Ok(item_id) => return Some(VMValue::from_i32(item_id as i32)),
+                        Err(err) => {
+                            self.ctx.send_log_message(format!(
+                                "[warn] {} ({}) => shoot: {}",
+                                self.ctx.get_entity_name(id),
+                                id,
+                                err
+                            ));
+                            return Some(VMValue::from_i32(-1));
+                        }
+                    }
+                }
+            }
+            "attack" => {
+                if let Some(target) = args.get(0) {
+                    let attacker = self.ctx.curr_entity_id;
+                    let damage = match attack(self.ctx, attacker, target.x as u32) {
+                        AttackResult::Hit { damage, .. } => damage,
+                        AttackResult::Miss => 0,
+                        AttackResult::Invalid => -1,
+                    };
+                    return Some(VMValue::from_i32(damage));
+                }
+            }
             "took_damage" => {
                 if let (Some(from), Some(amount_val)) = (args.get(0), args.get(1)) {
                     let mut kill = false;
@@ -800,6 +1323,7 @@ impl<'a> HostHandler for RegionHost<'a> {
                             "kill".into(),
                             VMValue::broadcast(id as f32),
                         ));
+                        send_combat_event(self.ctx, CombatEventKind::Kill, from, id, amount, false);
                     }
                 }
             }
@@ -897,6 +1421,85 @@ impl<'a> HostHandler for RegionHost<'a> {
                     }
                 }
             }
+            "open_shop" => {
+                if let Some(to) = args.get(0).map(|v| v.x as u32) {
+                    let vendor = self.ctx.curr_entity_id;
+                    return Some(VMValue::from_bool(open_shop(self.ctx, vendor, to)));
+                }
+            }
+            "transfer_funds" => {
+                if let (Some(to), Some(amount)) = (
+                    args.get(0).map(|v| v.x as u32),
+                    args.get(1).map(|v| v.x as i64),
+                ) {
+                    let from = self.ctx.curr_entity_id;
+                    let ok = transfer_funds(self.ctx, from, to, amount).is_ok();
+                    return Some(VMValue::from_bool(ok));
+                }
+            }
+            "start_quest" | "advance_quest" | "quest_state" => {
+                if let (Some(id), Some(quest)) = (
+                    args.get(0).map(|v| v.x as u32),
+                    args.get(1).and_then(|v| v.as_string()),
+                ) {
+                    return Some(match name {
+                        "start_quest" => VMValue::from_bool(start_quest(self.ctx, id, quest)),
+                        "advance_quest" => VMValue::from_bool(advance_quest(self.ctx, id, quest)),
+                        _ => VMValue::from_string(quest_state(self.ctx, id, quest)),
+                    });
+                }
+            }
+            "set_weather" => {
+                if let Some(name) = args.get(0).and_then(|v| v.as_string()) {
+                    if let Some(kind) = WeatherKind::from_name(name) {
+                        let ticks = self.ctx.ticks;
+                        self.ctx.weather.set(kind, ticks, &mut self.ctx.rng);
+                        send_weather(self.ctx);
+                        return Some(VMValue::from_bool(true));
+                    }
+                    return Some(VMValue::from_bool(false));
+                }
+            }
+            "weather" => {
+                return Some(VMValue::from_string(self.ctx.weather.state.kind.name()));
+            }
+            "set_behavior" => {
+                if let Some(name) = args.get(0).and_then(|v| v.as_string()) {
+                    let name = name.to_string();
+                    if !name.is_empty() && !self.ctx.assets.behaviors.contains_key(&name) {
+                        let id = self.ctx.curr_entity_id;
+                        self.ctx.send_log_message(format!(
+                            "[warn] {} ({}) => set_behavior: Unknown behavior '{}'.",
+                            self.ctx.get_entity_name(id),
+                            id,
+                            name
+                        ));
+                    } else if let Some(entity) = self.ctx.get_current_entity_mut() {
+                        if name.is_empty() {
+                            entity.attributes.remove("behavior");
+                            entity.action = EntityAction::Off;
+                        } else {
+                            entity.set_attribute("behavior", Value::Str(name));
+                        }
+                    }
+                }
+            }
+            "start_dialogue" => {
+                if let (Some(name), Some(to)) = (
+                    args.get(0).and_then(|v| v.as_string()),
+                    args.get(1).map(|v| v.x as u32),
+                ) {
+                    let speaker = self.ctx.curr_entity_id;
+                    if let Err(err) = start_dialogue(self.ctx, name, speaker, to) {
+                        self.ctx.send_log_message(format!(
+                            "[warn] {} ({}) => start_dialogue: {}",
+                            self.ctx.get_entity_name(speaker),
+                            speaker,
+                            err
+                        ));
+                    }
+                }
+            }
             "drop_items" => {
                 if let Some(filter) = args.get(0).and_then(|v| v.as_string()) {
                     if let Some(entity) = self.ctx.get_current_entity_mut() {
@@ -1106,6 +1709,91 @@ impl<'a> HostHandler for RegionHost<'a> {
                     }
                 });
             } */
+            "can_see" => {
+                if let Some(target) = args.get(0).map(|v| v.x as u32) {
+                    let from = if let Some(item_id) = self.ctx.curr_item_id {
+                        self.ctx.get_item_mut(item_id).map(|i| i.get_pos_xz())
+                    } else {
+                        self.ctx.get_current_entity_mut().map(|e| e.get_pos_xz())
+                    };
+                    let to = self
+                        .ctx
+                        .map
+                        .entities
+                        .iter()
+                        .find(|e| e.id == target)
+                        .map(|e| e.get_pos_xz());
+                    let visible = match (from, to) {
+                        (Some(from), Some(to)) => self.ctx.mapmini.line_of_sight(from, to),
+                        _ => false,
+                    };
+                    return Some(VMValue::from_bool(visible));
+                }
+            }
+            "visible_tiles" => {
+                let radius = args.get(0).map(|v| v.x.max(0.0)).unwrap_or(0.0);
+                let from = if let Some(item_id) = self.ctx.curr_item_id {
+                    self.ctx.get_item_mut(item_id).map(|i| i.get_pos_xz())
+                } else {
+                    self.ctx.get_current_entity_mut().map(|e| e.get_pos_xz())
+                };
+                let mut cells: Vec<Vec2<i32>> = from
+                    .map(|from| {
+                        self.ctx
+                            .mapmini
+                            .visible_cells(from, radius)
+                            .into_iter()
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                cells.sort_by_key(|c| (c.y, c.x));
+
+                // Pack result: z = count, string = comma list of "x:y" cells
+                let cells_str: Vec<String> =
+                    cells.iter().map(|c| format!("{}:{}", c.x, c.y)).collect();
+                let mut v = VMValue::zero();
+                v.z = cells.len() as f32;
+                v.string = Some(cells_str.join(","));
+                return Some(v);
+            }
+            "path_to" => {
+                if let (Some(x), Some(y)) = (args.get(0), args.get(1)) {
+                    let id = self.ctx.curr_entity_id;
+                    let to = Vec2::new(x.x, y.x);
+
+                    // Other living entities block their cells
+                    let occupied: FxHashSet<Vec2<i32>> = self
+                        .ctx
+                        .map
+                        .entities
+                        .iter()
+                        .filter(|e| {
+                            e.id != id
+                                && e.attributes.get_str_default("mode", "active".into()) != "dead"
+                        })
+                        .map(|e| e.get_pos_xz().floor().as_::<i32>())
+                        .collect();
+
+                    let mut found = false;
+                    if let Some(entity) = self.ctx.map.entities.iter().find(|e| e.id == id) {
+                        let radius = entity.attributes.get_float_default("radius", 0.5) - 0.01;
+                        let speed = entity.attributes.get_float_default("speed", 1.0);
+                        if let Some(path) = self.ctx.mapmini.find_path(
+                            entity.get_pos_xz(),
+                            to,
+                            radius,
+                            &occupied,
+                            PATH_SEARCH_MARGIN,
+                        ) {
+                            found = true;
+                            if let Some(entity) = self.ctx.get_current_entity_mut() {
+                                entity.action = EntityAction::FollowPath(path, speed);
+                            }
+                        }
+                    }
+                    return Some(VMValue::from_bool(found));
+                }
+            }
             "close_in" => {
                 if let (Some(target), Some(radius), Some(speed)) =
                     (args.get(0), args.get(1), args.get(2))
@@ -1147,6 +1835,10 @@ impl<'a> HostHandler for RegionHost<'a> {
     }
 }
 
+// Class scripts run on the in-crate VM (see `crate::vm`), which is pure Rust and also runs in
+// WASM builds. A class implements any of `setup()`, `event(event, value)` for server events
+// and `user_event(event, value)` for player input.
+
 // Run an event
 pub fn run_server_fn(
     exec: &mut Execution,
@@ -1156,8 +1848,19 @@ pub fn run_server_fn(
 ) {
     if let Some(index) = program.user_functions_name_map.get("event").copied() {
         exec.reset(program.globals);
-        let mut host = RegionHost { ctx: region_ctx };
-        let _ret = exec.execute_function_host(args, index, program, &mut host);
+        execute_timed(exec, args, index, program, region_ctx);
+    }
+}
+
+// Run the optional setup() function of a class, called once before the "startup" event
+pub fn run_setup_fn(
+    exec: &mut Execution,
+    program: &crate::vm::Program,
+    region_ctx: &mut RegionCtx,
+) {
+    if let Some(index) = program.user_functions_name_map.get("setup").copied() {
+        exec.reset(program.globals);
+        execute_timed(exec, &[], index, program, region_ctx);
     }
 }
 
@@ -1170,7 +1873,103 @@ pub fn run_client_fn(
 ) {
     if let Some(index) = program.user_functions_name_map.get("user_event").copied() {
         exec.reset(program.globals);
-        let mut host = RegionHost { ctx: region_ctx };
-        let _ret = exec.execute_function_host(args, index, program, &mut host);
+        execute_timed(exec, args, index, program, region_ctx);
+    }
+}
+
+/// Execute the function of the class script, its execution time is recorded in the region stats.
+fn execute_timed(
+    exec: &mut Execution,
+    args: &[VMValue],
+    index: usize,
+    program: &crate::vm::Program,
+    region_ctx: &mut RegionCtx,
+) {
+    let start = Instant::now();
+    let mut host = RegionHost { ctx: region_ctx };
+    let _ret = exec.execute_function_host(args, index, program, &mut host);
+
+    let class_name = match region_ctx.curr_item_id {
+        Some(id) => region_ctx.item_classes.get(&id),
+        None => region_ctx.entity_classes.get(&region_ctx.curr_entity_id),
+    }
+    .cloned()
+    .unwrap_or_default();
+    region_ctx
+        .stats
+        .record_script(&class_name, start.elapsed().as_secs_f32() * 1000.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entity;
+
+    const CLASS: &str = r#"
+        fn setup() {
+            set_attr("stage", "setup");
+        }
+
+        fn event(event, value) {
+            if event == "startup" {
+                set_attr("stage", "startup");
+            }
+        }
+
+        fn user_event(event, value) {
+            set_attr("input", value);
+        }
+    "#;
+
+    /// A region with a single entity as the current entity.
+    fn region() -> RegionCtx {
+        let mut ctx = RegionCtx::default();
+        let mut entity = Entity::new();
+        entity.id = 1;
+        ctx.map.entities.push(entity);
+        ctx.curr_entity_id = 1;
+        ctx
+    }
+
+    fn attr(ctx: &RegionCtx, key: &str) -> Option<String> {
+        ctx.map.entities[0]
+            .attributes
+            .get_str(key)
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn class_hooks() {
+        let program = VM::default().prepare_str(CLASS).unwrap();
+        let mut exec = Execution::new(program.globals);
+
+        let mut ctx = region();
+
+        run_setup_fn(&mut exec, &program, &mut ctx);
+        assert_eq!(attr(&ctx, "stage").as_deref(), Some("setup"));
+
+        let args = [VMValue::from_string("startup"), VMValue::zero()];
+        run_server_fn(&mut exec, &args, &program, &mut ctx);
+        assert_eq!(attr(&ctx, "stage").as_deref(), Some("startup"));
+
+        let args = [VMValue::from_string("key_down"), VMValue::from_string("w")];
+        run_client_fn(&mut exec, &args, &program, &mut ctx);
+        assert_eq!(attr(&ctx, "input").as_deref(), Some("w"));
+    }
+
+    #[test]
+    fn optional_hooks() {
+        let program = VM::default()
+            .prepare_str("fn event(event, value) {}")
+            .unwrap();
+        let mut exec = Execution::new(program.globals);
+
+        let mut ctx = region();
+
+        run_setup_fn(&mut exec, &program, &mut ctx);
+        let args = [VMValue::from_string("key_down"), VMValue::from_string("w")];
+        run_client_fn(&mut exec, &args, &program, &mut ctx);
+        assert_eq!(attr(&ctx, "stage"), None);
+        assert_eq!(attr(&ctx, "input"), None);
     }
 }
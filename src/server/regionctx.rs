@@ -1,4 +1,22 @@
 use crate::prelude::*;
+use crate::server::behavior::BehaviorRunner;
+use crate::server::combat::CombatRules;
+use crate::server::cutscene::ActiveCutscene;
+use crate::server::dialogue::ActiveDialogue;
+use crate::server::fog::FogRules;
+use crate::server::follow::FollowRules;
+use crate::server::leveling::LevelingRules;
+use crate::server::metrics::RegionStats;
+use crate::server::movement::MovementRules;
+use crate::server::ownership::{Claim, Interactable, OwnershipRules};
+use crate::server::projectile::Projectile;
+use crate::server::routine::Routine;
+use crate::server::schedule::ScheduledEvent;
+use crate::server::schema::AttrSchemas;
+use crate::server::simulation::RegionRng;
+use crate::server::trigger::send_sector_change;
+use crate::server::turn::TurnState;
+use crate::server::weather::Weather;
 use crate::vm::{Program, VMValue};
 use crate::{CollisionWorld, MapMini};
 use crossbeam_channel::{Receiver, Sender};
@@ -11,6 +29,8 @@ use uuid::Uuid;
 pub struct RegionCtx {
     pub map: Map,
     pub mapmini: MapMini,
+    /// Increased by `map_changed`, data derived from the map is rebuilt once it changes.
+    pub map_revision: u32,
     pub collision_world: CollisionWorld,
 
     pub paused: bool,
@@ -37,10 +57,16 @@ pub struct RegionCtx {
     pub item_classes: FxHashMap<u32, String>,
 
     pub entity_player_classes: FxHashSet<String>,
+    /// The player entities placed in the map, players are created from them on demand.
+    pub player_starts: Vec<Entity>,
 
     pub entity_class_data: FxHashMap<String, String>,
     pub item_class_data: FxHashMap<String, String>,
 
+    /// The attribute schemas of the classes which define one.
+    pub entity_schemas: FxHashMap<String, AttrSchemas>,
+    pub item_schemas: FxHashMap<String, AttrSchemas>,
+
     pub entity_proximity_alerts: FxHashMap<u32, f32>,
     pub item_proximity_alerts: FxHashMap<u32, f32>,
 
@@ -57,6 +83,9 @@ pub struct RegionCtx {
     pub startup_errors: Vec<String>,
 
     pub delta_time: f32,
+
+    /// The source of all randomness of the region, seeded in deterministic mode.
+    pub rng: RegionRng,
     pub config: Table,
     pub assets: Assets,
 
@@ -65,10 +94,73 @@ pub struct RegionCtx {
 
     pub health_attr: String,
 
+    pub combat: CombatRules,
+    /// The tick until which an entity cannot attack again.
+    pub combat_cooldowns: FxHashMap<u32, i64>,
+
+    /// The experience curves of the character levels and skills.
+    pub leveling: LevelingRules,
+
+    pub weather: Weather,
+
+    /// The events scheduled at in-game times.
+    pub schedule: Vec<ScheduledEvent>,
+
+    /// The tick the next entity of a spawner is due, by spawner name.
+    pub spawn_timers: FxHashMap<String, i64>,
+
+    /// The projectiles in flight.
+    pub projectiles: Vec<Projectile>,
+
+    pub movement: MovementRules,
+
+    pub follow: FollowRules,
+    /// The leader of each following entity.
+    pub followers: FxHashMap<u32, u32>,
+
+    pub fog_of_war: FogRules,
+    /// The tiles each player has explored.
+    pub explored: FxHashMap<u32, FxHashSet<Vec2<i32>>>,
+    /// The tiles last sent as visible to each player in the region.
+    pub fog_visible: FxHashMap<u32, FxHashSet<Vec2<i32>>>,
+
+    /// The daily routines of the entity classes which define one.
+    pub entity_routines: FxHashMap<String, Routine>,
+    /// The index of the active routine entry by entity.
+    pub active_routines: FxHashMap<u32, usize>,
+
+    /// The turn-based mode and its current round.
+    pub turns: TurnState,
+
+    pub ownership: OwnershipRules,
+    /// The active claims on items and sectors.
+    pub claims: FxHashMap<Interactable, Claim>,
+
+    /// The running cutscenes.
+    pub cutscenes: Vec<ActiveCutscene>,
+
+    /// The sector and the tick each entity entered it, for the `dwell` events.
+    pub sector_dwell: FxHashMap<u32, (String, i64)>,
+
+    /// The performance metrics of the region.
+    pub stats: RegionStats,
+
     pub currencies: Currencies,
+
+    /// The running dialogues by listener.
+    pub active_dialogues: FxHashMap<u32, ActiveDialogue>,
+
+    /// The behavior tree state by entity.
+    pub behaviors: FxHashMap<u32, BehaviorRunner>,
 }
 
 impl RegionCtx {
+    /// Rebuild the data derived from the map after it was replaced or edited.
+    pub fn map_changed(&mut self) {
+        self.mapmini = self.map.as_mini(&self.blocking_tiles);
+        self.map_revision += 1;
+    }
+
     /// Search for a mutable reference to an entity with the given ID.
     pub fn get_entity_mut(&mut self, entity_id: u32) -> Option<&mut Entity> {
         self.map
@@ -125,8 +217,12 @@ impl RegionCtx {
         item.set_attribute("name", Value::Str(class_name.clone()));
 
         // Setting the data for the item.
-        if let Some(data) = self.item_class_data.get(&class_name) {
-            super::data::apply_item_data(&mut item, data);
+        if let Some(Err(err)) = self
+            .item_class_data
+            .get(&class_name)
+            .map(|data| super::data::apply_item_data(&mut item, data))
+        {
+            self.send_log_message(format!("[warn] Item '{}': {}", class_name, err));
         }
 
         if let Some(class_name) = item.get_attr_string("class_name") {
@@ -185,92 +281,38 @@ impl RegionCtx {
     /// Check if the player moved to a different sector and if yes send "enter" and "left" events
     pub fn check_player_for_section_change(&mut self, entity: &mut Entity) {
         // Determine, set and notify the entity about the sector it is in.
-        if let Some(sector) = self.map.find_sector_at(entity.get_pos_xz()) {
-            if let Some(Value::Str(old_sector_name)) = entity.attributes.get("sector") {
-                if sector.name != *old_sector_name {
-                    // Send entered event
-                    if !sector.name.is_empty() {
-                        self.to_execute_entity.push((
-                            entity.id,
-                            "entered".into(),
-                            VMValue::from(sector.name.clone()),
-                        ));
-                    }
-                    // Send left event
-                    if !old_sector_name.is_empty() {
-                        self.to_execute_entity.push((
-                            entity.id,
-                            "left".into(),
-                            VMValue::from(old_sector_name.clone()),
-                        ));
-                    }
-
-                    entity
-                        .attributes
-                        .set("sector", Value::Str(sector.name.clone()));
-                }
+        let sector_name = self
+            .map
+            .find_sector_at(entity.get_pos_xz())
+            .map(|s| s.name.clone())
+            .unwrap_or_default();
+        if let Some(Value::Str(old_sector_name)) = entity.attributes.get("sector") {
+            if sector_name != *old_sector_name {
+                send_sector_change(self, entity.id, old_sector_name, &sector_name);
+                entity.attributes.set("sector", Value::Str(sector_name));
             }
-        } else if let Some(Value::Str(old_sector_name)) = entity.attributes.get("sector") {
-            // Send left event
-            if !old_sector_name.is_empty() {
-                if let Some(_class_name) = self.entity_classes.get(&entity.id) {
-                    // let cmd = format!("{}.event(\"left\", \"{}\")", class_name, old_sector_name);
-                    // println!("{cmd}");
-                    self.to_execute_entity.push((
-                        entity.id,
-                        "bumped_into_item".into(),
-                        VMValue::from(old_sector_name.clone()),
-                    ));
-                }
-            }
-            entity.attributes.set("sector", Value::Str(String::new()));
         }
     }
 
     pub fn check_player_for_section_change_id(&mut self, id: u32) {
         if let Some(idx) = self.map.entities.iter().position(|e| e.id == id) {
-            // Read-only data first to avoid overlapping mutable borrows
             let pos = self.map.entities[idx].get_pos_xz();
-            let old_sector = self
-                .map
-                .entities
-                .get(idx)
-                .and_then(|e| e.attributes.get_str("sector"))
+            let old_sector = self.map.entities[idx]
+                .attributes
+                .get_str("sector")
                 .map(|s| s.to_string())
                 .unwrap_or_default();
-            let sector_name = self.map.find_sector_at(pos).map(|s| s.name.clone());
-
-            if let Some(entity) = self.map.entities.get_mut(idx) {
-                if let Some(sector_name) = sector_name {
-                    if sector_name != old_sector {
-                        if !sector_name.is_empty() {
-                            self.to_execute_entity.push((
-                                entity.id,
-                                "entered".into(),
-                                VMValue::from(sector_name.clone()),
-                            ));
-                        }
-                        if !old_sector.is_empty() {
-                            self.to_execute_entity.push((
-                                entity.id,
-                                "left".into(),
-                                VMValue::from(old_sector.clone()),
-                            ));
-                        }
-                        entity
-                            .attributes
-                            .set("sector", Value::Str(sector_name.clone()));
-                    }
-                } else {
-                    if !old_sector.is_empty() {
-                        self.to_execute_entity.push((
-                            entity.id,
-                            "left".into(),
-                            VMValue::from(old_sector.clone()),
-                        ));
-                    }
-                    entity.attributes.set("sector", Value::Str(String::new()));
-                }
+            let sector_name = self
+                .map
+                .find_sector_at(pos)
+                .map(|s| s.name.clone())
+                .unwrap_or_default();
+
+            if sector_name != old_sector {
+                send_sector_change(self, id, &old_sector, &sector_name);
+                self.map.entities[idx]
+                    .attributes
+                    .set("sector", Value::Str(sector_name));
             }
         }
     }
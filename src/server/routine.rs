@@ -0,0 +1,190 @@
+use crate::server::region::is_entity_dead_ctx;
+use crate::server::schedule::{MINUTES_PER_DAY, current_minute, parse_time};
+use crate::vm::VMValue;
+use crate::{EntityAction, RegionCtx, Value};
+use theframework::prelude::*;
+use vek::Vec2;
+
+/// An activity of a daily routine, see `Routine`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoutineEntry {
+    pub from: String,
+    pub to: String,
+    pub activity: String,
+    /// Walk to the center of the sector.
+    #[serde(default)]
+    pub sector: Option<String>,
+    /// Walk to the named vertex.
+    #[serde(default)]
+    pub marker: Option<String>,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Walk around in the sector after arriving.
+    #[serde(default)]
+    pub wander: bool,
+    /// Attributes set when the activity starts.
+    #[serde(default)]
+    pub attributes: toml::Table,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+impl RoutineEntry {
+    /// The start and end minute of the day, the range may wrap past midnight.
+    fn range(&self) -> Option<(i64, i64)> {
+        Some((parse_time(&self.from)?, parse_time(&self.to)?))
+    }
+
+    fn contains(&self, minute_of_day: i64) -> bool {
+        match self.range() {
+            Some((from, to)) if from <= to => (from..to).contains(&minute_of_day),
+            Some((from, to)) => minute_of_day >= from || minute_of_day < to,
+            None => false,
+        }
+    }
+}
+
+/// The daily routine of an entity class, read from the `[[routine]]` tables of the entity data. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// The first entry containing the time of day is active. When it changes the region sets the
+/// attributes, sends the entity on its way and sends a `routine` event with the activity.
+#[derive(Debug, Clone, Default)]
+pub struct Routine {
+    pub entries: Vec<RoutineEntry>,
+}
+
+impl Routine {
+    /// Read the routine from the entity data, empty if the entity has none.
+    pub fn from_data(toml: &str) -> Result<Self, String> {
+        let table = toml.parse::<toml::Table>().map_err(|err| err.to_string())?;
+        let Some(entries) = table.get("routine") else {
+            return Ok(Self::default());
+        };
+        let entries: Vec<RoutineEntry> =
+            entries.clone().try_into().map_err(|err| err.to_string())?;
+        for entry in &entries {
+            if entry.range().is_none() {
+                return Err(format!(
+                    "Invalid time range '{}' - '{}' of '{}'.",
+                    entry.from, entry.to, entry.activity
+                ));
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The index of the active entry at the minute of the day.
+    pub fn entry_at(&self, minute_of_day: i64) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.contains(minute_of_day))
+    }
+}
+
+fn to_value(value: &toml::Value) -> Option<Value> {
+    match value {
+        toml::Value::Boolean(value) => Some(Value::Bool(*value)),
+        toml::Value::Integer(value) => Some(Value::Int(*value as i32)),
+        toml::Value::Float(value) => Some(Value::Float(*value as f32)),
+        toml::Value::String(value) => Some(Value::Str(value.clone())),
+        _ => None,
+    }
+}
+
+/// The target of the entry in the map.
+fn target_position(ctx: &RegionCtx, entry: &RoutineEntry) -> Option<Vec2<f32>> {
+    if let Some(name) = &entry.sector {
+        ctx.map
+            .sectors
+            .iter()
+            .find(|s| s.name == *name)
+            .and_then(|s| s.center(&ctx.map))
+    } else if let Some(name) = &entry.marker {
+        ctx.map
+            .vertices
+            .iter()
+            .find(|v| v.name == *name)
+            .map(|v| Vec2::new(v.x, v.y))
+    } else {
+        None
+    }
+}
+
+/// Run the daily routines of the entities of the region. Players, dead entities and followers
+/// are skipped.
+pub fn tick_routines(ctx: &mut RegionCtx) {
+    let minute_of_day = current_minute(ctx).rem_euclid(MINUTES_PER_DAY);
+    let entities: Vec<(u32, String)> = ctx
+        .map
+        .entities
+        .iter()
+        .filter(|entity| !entity.is_player())
+        .filter_map(|entity| Some((entity.id, ctx.entity_classes.get(&entity.id)?.clone())))
+        .filter(|(_, class_name)| ctx.entity_routines.contains_key(class_name))
+        .collect();
+
+    ctx.active_routines
+        .retain(|id, _| entities.iter().any(|(entity_id, _)| entity_id == id));
+
+    for (id, class_name) in entities {
+        if is_entity_dead_ctx(ctx, id) || ctx.followers.contains_key(&id) {
+            continue;
+        }
+        let Some(routine) = ctx.entity_routines.get(&class_name) else {
+            continue;
+        };
+        let Some(index) = routine.entry_at(minute_of_day) else {
+            ctx.active_routines.remove(&id);
+            continue;
+        };
+        let entry = routine.entries[index].clone();
+
+        if ctx.active_routines.get(&id) != Some(&index) {
+            ctx.active_routines.insert(id, index);
+            let target = target_position(ctx, &entry);
+            if let Some(entity) = ctx.get_entity_mut(id) {
+                for (key, value) in &entry.attributes {
+                    if let Some(value) = to_value(value) {
+                        entity.set_attribute(key, value);
+                    }
+                }
+                entity.set_attribute("activity", Value::Str(entry.activity.clone()));
+                if let Some(target) = target {
+                    entity.action = EntityAction::Goto(target, entry.speed);
+                }
+            }
+            ctx.to_execute_entity
+                .push((id, "routine".into(), VMValue::from(entry.activity)));
+        } else if entry.wander {
+            // Arrived in the sector, walk around in it
+            let Some(sector) = &entry.sector else {
+                continue;
+            };
+            let Some(position) = ctx
+                .map
+                .entities
+                .iter()
+                .find(|entity| entity.id == id)
+                .filter(|entity| entity.action == EntityAction::Off)
+                .map(|entity| entity.get_pos_xz())
+            else {
+                continue;
+            };
+            let inside = ctx
+                .map
+                .find_sector_at(position)
+                .is_some_and(|s| s.name == *sector);
+            if let (true, Some(entity)) = (inside, ctx.get_entity_mut(id)) {
+                entity.action =
+                    EntityAction::RandomWalkInSector(1.0, entry.speed, 8, 0, Vec2::zero());
+            }
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use crate::server::cutscene::ActiveCutscene;
+use crate::server::dialogue::{ActiveDialogue, end_dialogue, send_dialogue_prompt};
+use crate::server::follow::follow;
+use crate::server::ownership::{Claim, Interactable};
+use crate::server::persistence::PersistedRegion;
+use crate::server::projectile::Projectile;
+use crate::server::region::with_regionctx;
+use crate::server::schedule::ScheduledEvent;
+use crate::server::simulation::RegionRng;
+use crate::server::turn::TurnState;
+use crate::server::weather::{Weather, send_weather};
+use crate::{Item, Map, RegionCtx, ValueContainer};
+use theframework::prelude::*;
+
+/// The version of the save game format.
+pub const SAVEGAME_VERSION: u32 = 2;
+
+/// The runtime state of a region instance.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RegionState {
+    pub name: String,
+    /// The map including its entities (with their wallets and equipped items) and items. Only
+    /// set if the state was captured without a delta.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<Map>,
+    /// The changes since the startup of the region, applied over its authored map.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<PersistedRegion>,
+    /// The inventories of the entities, they are not part of the serialized entities.
+    pub inventories: FxHashMap<u32, Vec<Option<Item>>>,
+
+    pub ticks: i64,
+
+    /// Pending notifications: Id, Tick, Notification
+    pub notifications_entities: Vec<(u32, i64, String)>,
+    pub notifications_items: Vec<(u32, i64, String)>,
+    /// Events scheduled at in-game times
+    #[serde(default)]
+    pub schedule: Vec<ScheduledEvent>,
+
+    pub entity_classes: FxHashMap<u32, String>,
+    pub item_classes: FxHashMap<u32, String>,
+
+    pub entity_state_data: FxHashMap<u32, ValueContainer>,
+    pub item_state_data: FxHashMap<u32, ValueContainer>,
+
+    pub entity_proximity_alerts: FxHashMap<u32, f32>,
+    pub item_proximity_alerts: FxHashMap<u32, f32>,
+
+    /// The tiles explored by the players, by player entity.
+    #[serde(default)]
+    pub explored: FxHashMap<u32, Vec<Vec2<i32>>>,
+
+    /// The running dialogues by listener.
+    #[serde(default)]
+    pub active_dialogues: FxHashMap<u32, ActiveDialogue>,
+    /// The tick until which an entity cannot attack again.
+    #[serde(default)]
+    pub combat_cooldowns: FxHashMap<u32, i64>,
+    /// The weather and its next change, the rules come from the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weather: Option<Weather>,
+    /// The tick the next entity of a spawner is due, by spawner name.
+    #[serde(default)]
+    pub spawn_timers: FxHashMap<String, i64>,
+    /// The projectiles in flight, their items are part of the map.
+    #[serde(default)]
+    pub projectiles: Vec<Projectile>,
+    /// The state of the random number generator of the region, see `RegionRng::save_state`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rng_state: Option<u64>,
+    /// The leader of each following entity.
+    #[serde(default)]
+    pub followers: FxHashMap<u32, u32>,
+    /// The active claims on items and sectors.
+    #[serde(default)]
+    pub claims: Vec<(Interactable, Claim)>,
+    /// The current round of the turn-based mode and the queued actions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turns: Option<TurnState>,
+    /// The running cutscenes.
+    #[serde(default)]
+    pub cutscenes: Vec<ActiveCutscene>,
+}
+
+impl RegionState {
+    /// Capture the state of the given region instance. With a delta from
+    /// `RegionInstance::dirty_snapshot` the map itself is not stored.
+    pub fn capture(region_id: u32, name: String, delta: Option<PersistedRegion>) -> Option<Self> {
+        with_regionctx(region_id, |ctx: &mut RegionCtx| Self {
+            name,
+            map: if delta.is_none() {
+                Some(ctx.map.clone())
+            } else {
+                None
+            },
+            delta,
+            inventories: ctx
+                .map
+                .entities
+                .iter()
+                .filter(|entity| !entity.inventory.is_empty())
+                .map(|entity| (entity.id, entity.inventory.clone()))
+                .collect(),
+            ticks: ctx.ticks,
+            notifications_entities: ctx.notifications_entities.clone(),
+            notifications_items: ctx.notifications_items.clone(),
+            schedule: ctx.schedule.clone(),
+            entity_classes: ctx.entity_classes.clone(),
+            item_classes: ctx.item_classes.clone(),
+            entity_state_data: ctx.entity_state_data.clone(),
+            item_state_data: ctx.item_state_data.clone(),
+            entity_proximity_alerts: ctx.entity_proximity_alerts.clone(),
+            item_proximity_alerts: ctx.item_proximity_alerts.clone(),
+            explored: ctx
+                .explored
+                .iter()
+                .map(|(id, cells)| (*id, cells.iter().copied().collect()))
+                .collect(),
+            active_dialogues: ctx.active_dialogues.clone(),
+            combat_cooldowns: ctx.combat_cooldowns.clone(),
+            weather: Some(ctx.weather.clone()),
+            spawn_timers: ctx.spawn_timers.clone(),
+            projectiles: ctx.projectiles.clone(),
+            rng_state: Some(ctx.rng.save_state()),
+            followers: ctx.followers.clone(),
+            turns: Some(ctx.turns.clone()),
+            cutscenes: ctx.cutscenes.clone(),
+            claims: ctx
+                .claims
+                .iter()
+                .map(|(target, claim)| (target.clone(), claim.clone()))
+                .collect(),
+        })
+    }
+
+    /// Restore the state into the given region instance, a delta is applied over the authored
+    /// map of the region. All entities and items are marked dirty so that the server receives
+    /// their full state with the next redraw tick. Returns the ids which changed when applying
+    /// the delta (see `PersistedRegion::apply_to_map`), None if there is nothing to restore.
+    pub fn restore(mut self, region_id: u32, authored: &Map) -> Option<FxHashMap<u32, u32>> {
+        let mut remap = FxHashMap::default();
+        let mut map = match (self.map.take(), self.delta.take()) {
+            (Some(map), _) => map,
+            (None, Some(delta)) => {
+                let mut map = authored.clone();
+                remap = delta.apply_to_map(&mut map);
+                map
+            }
+            (None, None) => return None,
+        };
+        self.remap_ids(&remap);
+        with_regionctx(region_id, |ctx: &mut RegionCtx| {
+            map.id = ctx.map.id;
+            for entity in map.entities.iter_mut() {
+                if let Some(inventory) = self.inventories.get(&entity.id) {
+                    entity.inventory = inventory.clone();
+                }
+                entity.mark_all_dirty();
+            }
+            for item in map.items.iter_mut() {
+                item.mark_all_dirty();
+            }
+            ctx.map = map;
+            ctx.map_changed();
+
+            ctx.ticks = self.ticks;
+            ctx.time = TheTime::from_ticks(self.ticks, ctx.ticks_per_minute);
+
+            ctx.notifications_entities = self.notifications_entities;
+            ctx.notifications_items = self.notifications_items;
+            ctx.schedule = self.schedule;
+            ctx.entity_classes = self.entity_classes;
+            ctx.item_classes = self.item_classes;
+            ctx.entity_state_data = self.entity_state_data;
+            ctx.item_state_data = self.item_state_data;
+            ctx.entity_proximity_alerts = self.entity_proximity_alerts;
+            ctx.item_proximity_alerts = self.item_proximity_alerts;
+            ctx.combat_cooldowns = self.combat_cooldowns;
+            ctx.spawn_timers = self.spawn_timers;
+            ctx.claims = self.claims.into_iter().collect();
+            ctx.cutscenes = self.cutscenes;
+            if let Some(turns) = self.turns {
+                let rules = std::mem::take(&mut ctx.turns.rules);
+                ctx.turns = turns;
+                ctx.turns.rules = rules;
+            }
+            ctx.projectiles = self.projectiles;
+            if let Some(state) = self.rng_state {
+                ctx.rng = RegionRng::from_state(state);
+            }
+            if let Some(weather) = self.weather {
+                let rules = std::mem::take(&mut ctx.weather.rules);
+                ctx.weather = weather;
+                ctx.weather.rules = rules;
+                send_weather(ctx);
+            }
+            ctx.explored = self
+                .explored
+                .into_iter()
+                .map(|(id, cells)| (id, cells.into_iter().collect()))
+                .collect();
+            // The players receive their restored explored tiles with the next tick
+            ctx.fog_visible.clear();
+            ctx.to_execute_entity.clear();
+            ctx.to_execute_item.clear();
+
+            // The follow actions are not part of the saved entities
+            ctx.followers.clear();
+            for (follower, leader) in self.followers {
+                _ = follow(ctx, follower, leader);
+            }
+
+            // Show the open dialogues again, the ones which no longer exist are over
+            ctx.active_dialogues = self.active_dialogues;
+            let listeners: Vec<u32> = ctx.active_dialogues.keys().copied().collect();
+            for listener in listeners {
+                if !send_dialogue_prompt(ctx, listener) {
+                    end_dialogue(ctx, listener);
+                }
+            }
+        })
+        .map(|_| remap)
+    }
+
+    /// Replace the ids of the entities and items which changed when applying the delta.
+    fn remap_ids(&mut self, remap: &FxHashMap<u32, u32>) {
+        if remap.is_empty() {
+            return;
+        }
+        let id = |id: u32| remap.get(&id).copied().unwrap_or(id);
+        fn remapped<V>(table: &mut FxHashMap<u32, V>, id: impl Fn(u32) -> u32) {
+            *table = std::mem::take(table)
+                .into_iter()
+                .map(|(key, value)| (id(key), value))
+                .collect();
+        }
+
+        remapped(&mut self.inventories, id);
+        remapped(&mut self.entity_classes, id);
+        remapped(&mut self.item_classes, id);
+        remapped(&mut self.entity_state_data, id);
+        remapped(&mut self.item_state_data, id);
+        remapped(&mut self.entity_proximity_alerts, id);
+        remapped(&mut self.item_proximity_alerts, id);
+        remapped(&mut self.explored, id);
+        remapped(&mut self.active_dialogues, id);
+        remapped(&mut self.combat_cooldowns, id);
+        remapped(&mut self.followers, id);
+        for leader in self.followers.values_mut() {
+            *leader = id(*leader);
+        }
+        for (target, claim) in &mut self.claims {
+            if let Interactable::Item(item) = target {
+                *item = id(*item);
+            }
+            claim.entity = id(claim.entity);
+        }
+        if let Some(turns) = &mut self.turns {
+            remapped(&mut turns.actions, id);
+        }
+        for cutscene in &mut self.cutscenes {
+            cutscene.player = id(cutscene.player);
+        }
+        for projectile in &mut self.projectiles {
+            projectile.item_id = id(projectile.item_id);
+            projectile.owner = id(projectile.owner);
+        }
+        for dialogue in self.active_dialogues.values_mut() {
+            dialogue.speaker = id(dialogue.speaker);
+        }
+        for (entity, _, _) in &mut self.notifications_entities {
+            *entity = id(*entity);
+        }
+        for (item, _, _) in &mut self.notifications_items {
+            *item = id(*item);
+        }
+        for event in &mut self.schedule {
+            event.entity = event.entity.map(id);
+            event.item = event.item.map(id);
+        }
+    }
+}
+
+/// A save game: the state of all region instances of a server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameState {
+    pub version: u32,
+    pub regions: Vec<RegionState>,
+    /// The entity ids of the local players.
+    pub local_players: Vec<u32>,
+    /// The next global id for entities and items.
+    pub next_id: u32,
+}
+
+impl GameState {
+    /// Serialize the state to JSON.
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|err| err.to_string())
+    }
+
+    /// Deserialize the state from JSON.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let state: GameState = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if state.version > SAVEGAME_VERSION {
+            return Err(format!(
+                "Unsupported save game version {} (supported: {}).",
+                state.version, SAVEGAME_VERSION
+            ));
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::cutscene::{Cutscene, CutsceneStep, is_input_locked};
+    use crate::server::effect::StatusEffect;
+    use crate::server::projectile::ProjectileDef;
+    use crate::server::region::{get_regionctx, register_regionctx, unregister_regionctx};
+    use crate::server::schedule::ScheduleRule;
+    use crate::server::weather::WeatherKind;
+    use crate::{Entity, EntityAction, Value};
+    use rand::RngCore;
+    use std::sync::{Arc, Mutex};
+    use vek::{Vec2, Vec3};
+
+    /// The authored map of the test regions: an entity with id 1 and an item with id 2.
+    fn authored() -> Map {
+        let mut map = Map::default();
+        let mut entity = Entity::new();
+        entity.id = 1;
+        map.entities.push(entity);
+        let mut item = Item::new();
+        item.id = 2;
+        map.items.push(item);
+        map
+    }
+
+    const DIALOGUE: &str = r#"
+        start = "greeting"
+
+        [nodes.greeting]
+        text = "Welcome."
+
+        [[nodes.greeting.choices]]
+        text = "Farewell."
+    "#;
+
+    fn region() -> RegionCtx {
+        let mut ctx = RegionCtx {
+            map: authored(),
+            ticks_per_minute: 4,
+            ..Default::default()
+        };
+        ctx.assets.add_dialogue("welcome", DIALOGUE).unwrap();
+        ctx
+    }
+
+    /// Save the region, send the state through JSON and restore it into a freshly started
+    /// region with the authored map. Returns the restored region and the changed ids. Each test
+    /// needs its own region id.
+    fn save_and_load(
+        region_id: u32,
+        ctx: RegionCtx,
+        delta: Option<PersistedRegion>,
+        authored: Map,
+    ) -> (RegionCtx, FxHashMap<u32, u32>) {
+        register_regionctx(region_id, Arc::new(Mutex::new(ctx)));
+        let state = RegionState::capture(region_id, "region".into(), delta).unwrap();
+        let json = serde_json::to_string(&state).unwrap();
+
+        let mut started = region();
+        started.map = authored.clone();
+        register_regionctx(region_id, Arc::new(Mutex::new(started)));
+        let state: RegionState = serde_json::from_str(&json).unwrap();
+        let remap = state.restore(region_id, &authored).unwrap();
+
+        let ctx = get_regionctx(region_id).unwrap();
+        unregister_regionctx(region_id);
+        let ctx = Arc::try_unwrap(ctx)
+            .unwrap_or_else(|_| panic!("Region {} is still in use", region_id))
+            .into_inner()
+            .unwrap();
+        (ctx, remap)
+    }
+
+    /// Save the region after applying the change and load it again.
+    fn round_trip(region_id: u32, change: impl FnOnce(&mut RegionCtx)) -> RegionCtx {
+        let mut ctx = region();
+        change(&mut ctx);
+        save_and_load(region_id, ctx, None, authored()).0
+    }
+
+    #[test]
+    fn region_round_trip() {
+        let ctx = round_trip(41550, |ctx| {
+            ctx.ticks = 120;
+            ctx.map.entities[0].set_attribute("HP", Value::Int(7));
+            ctx.map.entities[0]
+                .inventory
+                .push(Some(ctx.map.items[0].clone()));
+            ctx.entity_classes.insert(1, "Player".into());
+            ctx.notifications_entities.push((1, 140, "wake_up".into()));
+            ctx.entity_proximity_alerts.insert(1, 2.5);
+        });
+
+        assert_eq!(ctx.ticks, 120);
+        let entity = &ctx.map.entities[0];
+        assert_eq!(entity.attributes.get_int_default("HP", 0), 7);
+        assert_eq!(entity.inventory.len(), 1);
+        assert_eq!(
+            ctx.entity_classes.get(&1).map(String::as_str),
+            Some("Player")
+        );
+        assert_eq!(
+            ctx.notifications_entities,
+            vec![(1, 140, String::from("wake_up"))]
+        );
+        assert_eq!(ctx.entity_proximity_alerts.get(&1), Some(&2.5));
+    }
+
+    #[test]
+    fn dialogue_round_trip() {
+        let ctx = round_trip(41570, |ctx| {
+            for (listener, dialogue) in [(1, "welcome"), (2, "removed")] {
+                ctx.active_dialogues.insert(
+                    listener,
+                    ActiveDialogue {
+                        dialogue: dialogue.into(),
+                        node: "greeting".into(),
+                        speaker: 2,
+                    },
+                );
+            }
+        });
+
+        let active = &ctx.active_dialogues[&1];
+        assert_eq!(
+            (active.dialogue.as_str(), active.node.as_str()),
+            ("welcome", "greeting")
+        );
+        assert_eq!(active.speaker, 2);
+        // Dialogues which no longer exist are over
+        assert!(!ctx.active_dialogues.contains_key(&2));
+    }
+
+    #[test]
+    fn combat_cooldown_round_trip() {
+        let ctx = round_trip(41620, |ctx| {
+            ctx.combat_cooldowns.insert(1, 24);
+        });
+        assert_eq!(ctx.combat_cooldowns.get(&1), Some(&24));
+    }
+
+    #[test]
+    fn status_effect_round_trip() {
+        let effect = StatusEffect {
+            name: "poison".into(),
+            icon: String::new(),
+            stacks: 2,
+            until: 80,
+            next_event: 44,
+        };
+        let ctx = round_trip(41630, |ctx| {
+            ctx.ticks = 40;
+            ctx.map.entities[0].effects.push(effect.clone());
+        });
+        assert_eq!(ctx.map.entities[0].effects, vec![effect]);
+    }
+
+    #[test]
+    fn weather_round_trip() {
+        let mut weather = Weather::default();
+        let ctx = round_trip(41670, |ctx| {
+            ctx.weather.set(WeatherKind::Rain, 10, &mut ctx.rng);
+            weather = ctx.weather.clone();
+        });
+        assert_eq!(ctx.weather.state.kind, WeatherKind::Rain);
+        assert_eq!(ctx.weather, weather);
+    }
+
+    #[test]
+    fn schedule_round_trip() {
+        let event = ScheduledEvent {
+            id: 3,
+            entity: Some(1),
+            item: None,
+            rule: ScheduleRule::Daily(8 * 60),
+            event: "wake_up".into(),
+            next: 8 * 60,
+        };
+        let ctx = round_trip(41680, |ctx| ctx.schedule.push(event.clone()));
+        assert_eq!(ctx.schedule, vec![event]);
+    }
+
+    #[test]
+    fn spawn_timer_round_trip() {
+        let ctx = round_trip(41700, |ctx| {
+            ctx.spawn_timers.insert("wolves".into(), 300);
+        });
+        assert_eq!(ctx.spawn_timers.get("wolves"), Some(&300));
+    }
+
+    #[test]
+    fn projectile_round_trip() {
+        let projectile = Projectile {
+            item_id: 2,
+            owner: 1,
+            velocity: Vec3::new(0.5, 0.1, 0.0),
+            def: ProjectileDef::default(),
+            traveled: 1.5,
+        };
+        let ctx = round_trip(41720, |ctx| ctx.projectiles.push(projectile.clone()));
+        assert_eq!(ctx.projectiles, vec![projectile]);
+    }
+
+    #[test]
+    fn rng_round_trip() {
+        let ctx = round_trip(41740, |ctx| ctx.rng = RegionRng::seeded(7, "region"));
+
+        // The saved region continues with the same numbers as the restored one
+        let mut saved = RegionRng::seeded(7, "region");
+        saved.save_state();
+        let mut restored = ctx.rng;
+        let numbers: Vec<u64> = (0..4).map(|_| restored.next_u64()).collect();
+        let expected: Vec<u64> = (0..4).map(|_| saved.next_u64()).collect();
+        assert_eq!(numbers, expected);
+    }
+
+    #[test]
+    fn follower_round_trip() {
+        let ctx = round_trip(41770, |ctx| {
+            let mut entity = Entity::new();
+            entity.id = 3;
+            ctx.map.entities.push(entity);
+            follow(ctx, 3, 1).unwrap();
+        });
+        assert_eq!(ctx.followers.get(&3), Some(&1));
+        assert!(matches!(
+            ctx.map.entities[1].action,
+            EntityAction::Follow(1, _)
+        ));
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        // The guard had id 1 in the build the game was saved with and has id 5 now
+        let mut guard = Entity::new();
+        guard.id = 1;
+        guard.set_attribute("class_name", Value::Str("Guard".into()));
+        let mut ctx = region();
+        ctx.map.entities = vec![guard.clone()];
+        ctx.map.entities[0].set_attribute("HP", Value::Int(3));
+        ctx.combat_cooldowns.insert(1, 9);
+        let delta = PersistedRegion {
+            entities: ctx.map.entities.clone(),
+            ..Default::default()
+        };
+
+        let mut authored = Map::default();
+        guard.id = 5;
+        authored.entities.push(guard);
+        let (ctx, remap) = save_and_load(41860, ctx, Some(delta), authored);
+
+        assert_eq!(remap.get(&1), Some(&5));
+        assert_eq!(ctx.map.entities.len(), 1);
+        assert_eq!(ctx.map.entities[0].id, 5);
+        assert_eq!(ctx.map.entities[0].attributes.get_int_default("HP", 0), 3);
+        assert_eq!(ctx.combat_cooldowns.get(&5), Some(&9));
+    }
+
+    #[test]
+    fn claim_round_trip() {
+        let claims = [
+            (
+                Interactable::Item(2),
+                Claim {
+                    entity: 1,
+                    until: Some(60),
+                },
+            ),
+            (
+                Interactable::Sector("Door".into()),
+                Claim {
+                    entity: 1,
+                    until: None,
+                },
+            ),
+        ];
+        let ctx = round_trip(41870, |ctx| ctx.claims.extend(claims.clone()));
+        for (target, claim) in &claims {
+            assert_eq!(ctx.claims.get(target), Some(claim));
+        }
+    }
+
+    #[test]
+    fn turn_round_trip() {
+        let ctx = round_trip(41880, |ctx| {
+            ctx.turns.round = 3;
+            ctx.turns.started = 90;
+            ctx.turns.queue(1, EntityAction::Forward);
+        });
+        assert_eq!((ctx.turns.round, ctx.turns.started), (3, 90));
+        assert_eq!(ctx.turns.actions.get(&1), Some(&EntityAction::Forward));
+    }
+
+    #[test]
+    fn cutscene_round_trip() {
+        let cutscene = ActiveCutscene {
+            name: "intro".into(),
+            cutscene: Cutscene {
+                lock_input: true,
+                steps: vec![CutsceneStep::Wait { ticks: 8 }],
+            },
+            player: 1,
+            step: 1,
+            resume_at: 48,
+        };
+        let ctx = round_trip(41900, |ctx| ctx.cutscenes.push(cutscene.clone()));
+        assert_eq!(ctx.cutscenes, vec![cutscene]);
+        assert!(is_input_locked(&ctx, 1));
+    }
+
+    #[test]
+    fn explored_round_trip() {
+        let cells: FxHashSet<Vec2<i32>> = [Vec2::new(0, 0), Vec2::new(3, -2)].into_iter().collect();
+        let ctx = round_trip(42150, |ctx| {
+            ctx.explored.insert(1, cells.clone());
+            ctx.fog_visible.insert(1, cells.clone());
+        });
+        assert_eq!(ctx.explored.get(&1), Some(&cells));
+        // The visible tiles are sent again with the next tick
+        assert!(ctx.fog_visible.is_empty());
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let state = GameState {
+            version: SAVEGAME_VERSION + 1,
+            regions: vec![],
+            local_players: vec![],
+            next_id: 3,
+        };
+        assert!(GameState::from_json(&state.to_json().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::RegionCtx;
+use crate::vm::VMValue;
+use theframework::prelude::*;
+
+pub(crate) const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// When a scheduled event fires, in absolute in-game minutes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleRule {
+    /// Once at the given minute.
+    At(i64),
+    /// Every day at the given minute of the day.
+    Daily(i64),
+    /// Repeatedly with the given interval in minutes.
+    Every(i64),
+}
+
+impl ScheduleRule {
+    /// Parse a rule relative to the current minute. Supported are:
+    ///
+    /// - `at 20:00`: once at the next 20:00.
+    /// - `in 30 minutes`, `in 2 hours`: once after the given time.
+    /// - `day 3 at 8:30`: once at the absolute day and time.
+    /// - `every day at 20:00` or `daily at 20:00`.
+    /// - `every 15 minutes`, `every hour`, `every 2 hours`.
+    pub fn parse(rule: &str, now: i64) -> Result<Self, String> {
+        let words: Vec<String> = rule
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect();
+        let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+        let invalid = || format!("Invalid schedule '{}'.", rule);
+
+        match words.as_slice() {
+            ["at", time] => {
+                let minute = parse_time(time).ok_or_else(invalid)?;
+                Ok(ScheduleRule::At(next_daily(minute, now)))
+            }
+            ["in", amount, unit] => {
+                let minutes = parse_duration(amount, unit).ok_or_else(invalid)?;
+                Ok(ScheduleRule::At(now + minutes))
+            }
+            ["day", day, "at", time] => {
+                let day = day.parse::<i64>().map_err(|_| invalid())?;
+                let minute = parse_time(time).ok_or_else(invalid)?;
+                Ok(ScheduleRule::At(day * MINUTES_PER_DAY + minute))
+            }
+            ["every", "day", "at", time] | ["daily", "at", time] => {
+                let minute = parse_time(time).ok_or_else(invalid)?;
+                Ok(ScheduleRule::Daily(minute))
+            }
+            ["every", unit] => {
+                let minutes = parse_duration("1", unit).ok_or_else(invalid)?;
+                Ok(ScheduleRule::Every(minutes))
+            }
+            ["every", amount, unit] => {
+                let minutes = parse_duration(amount, unit).ok_or_else(invalid)?;
+                if minutes <= 0 {
+                    return Err(invalid());
+                }
+                Ok(ScheduleRule::Every(minutes))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The first minute after `now` the rule fires, None if it does not fire anymore.
+    pub fn next_after(&self, now: i64) -> Option<i64> {
+        match self {
+            ScheduleRule::At(minute) => (*minute > now).then_some(*minute),
+            ScheduleRule::Daily(minute) => Some(next_daily(*minute, now)),
+            ScheduleRule::Every(interval) => Some(now + interval),
+        }
+    }
+}
+
+/// Parse `HH:MM` into the minute of the day.
+pub(crate) fn parse_time(time: &str) -> Option<i64> {
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes) = (hours.parse::<i64>().ok()?, minutes.parse::<i64>().ok()?);
+    ((0..24).contains(&hours) && (0..60).contains(&minutes)).then_some(hours * 60 + minutes)
+}
+
+fn parse_duration(amount: &str, unit: &str) -> Option<i64> {
+    let amount = amount.parse::<i64>().ok()?;
+    let factor = match unit.trim_end_matches('s') {
+        "minute" | "min" => 1,
+        "hour" => 60,
+        "day" => MINUTES_PER_DAY,
+        _ => return None,
+    };
+    Some(amount * factor)
+}
+
+/// The next minute after `now` with the given minute of the day.
+fn next_daily(minute_of_day: i64, now: i64) -> i64 {
+    let today = now - now.rem_euclid(MINUTES_PER_DAY) + minute_of_day;
+    if today > now {
+        today
+    } else {
+        today + MINUTES_PER_DAY
+    }
+}
+
+/// An event registered by an entity or item script.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledEvent {
+    pub id: u32,
+    pub entity: Option<u32>,
+    pub item: Option<u32>,
+    pub rule: ScheduleRule,
+    pub event: String,
+    /// The minute the event fires next.
+    pub next: i64,
+}
+
+/// The current in-game minute of the region.
+pub fn current_minute(ctx: &RegionCtx) -> i64 {
+    ctx.ticks / ctx.ticks_per_minute.max(1) as i64
+}
+
+/// Schedule the event for the current entity or item. Returns the id of the scheduled event.
+pub fn schedule(ctx: &mut RegionCtx, rule: &str, event: &str) -> Result<u32, String> {
+    let now = current_minute(ctx);
+    let rule = ScheduleRule::parse(rule, now)?;
+    let next = rule
+        .next_after(now)
+        .ok_or("The scheduled time has already passed.".to_string())?;
+
+    let id = ctx.schedule.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+    let (entity, item) = match ctx.curr_item_id {
+        Some(item_id) => (None, Some(item_id)),
+        None => (Some(ctx.curr_entity_id), None),
+    };
+    ctx.schedule.push(ScheduledEvent {
+        id,
+        entity,
+        item,
+        rule,
+        event: event.to_string(),
+        next,
+    });
+    Ok(id)
+}
+
+/// Remove the scheduled event, returns false if it does not exist.
+pub fn unschedule(ctx: &mut RegionCtx, id: u32) -> bool {
+    let count = ctx.schedule.len();
+    ctx.schedule.retain(|event| event.id != id);
+    ctx.schedule.len() != count
+}
+
+/// Send the due events. Each event receives the current minute of the day as its value.
+pub fn tick_schedule(ctx: &mut RegionCtx) {
+    let now = current_minute(ctx);
+    let minute_of_day = now.rem_euclid(MINUTES_PER_DAY) as f32;
+
+    let mut schedule = std::mem::take(&mut ctx.schedule);
+    schedule.retain_mut(|event| {
+        if event.next > now {
+            return true;
+        }
+        let value = VMValue::broadcast(minute_of_day);
+        if let Some(id) = event.entity {
+            ctx.to_execute_entity.push((id, event.event.clone(), value));
+        } else if let Some(id) = event.item {
+            ctx.to_execute_item.push((id, event.event.clone(), value));
+        }
+        match event.rule.next_after(now) {
+            Some(next) => {
+                event.next = next;
+                true
+            }
+            None => false,
+        }
+    });
+
+    // Drop the events of removed entities and items
+    schedule.retain(|event| {
+        event
+            .entity
+            .is_none_or(|id| ctx.entity_classes.contains_key(&id))
+            && event
+                .item
+                .is_none_or(|id| ctx.item_classes.contains_key(&id))
+    });
+    ctx.schedule = schedule;
+}
@@ -0,0 +1,247 @@
+use crate::{Value, ValueContainer};
+use theframework::prelude::*;
+use toml::Table;
+
+/// The type of a schema attribute.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttrType {
+    Bool,
+    Int,
+    Float,
+    String,
+}
+
+impl AttrType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AttrType::Bool => "bool",
+            AttrType::Int => "int",
+            AttrType::Float => "float",
+            AttrType::String => "string",
+        }
+    }
+}
+
+fn default_replicate() -> bool {
+    true
+}
+
+/// The definition of an attribute of an entity or item class.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttrSchema {
+    #[serde(rename = "type")]
+    pub kind: AttrType,
+    /// The value if the class data does not set the attribute.
+    #[serde(default)]
+    pub default: Option<toml::Value>,
+    /// If false the attribute stays on the server and is not sent in updates.
+    #[serde(default = "default_replicate")]
+    pub replicate: bool,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+impl AttrSchema {
+    /// Convert the value to the type of the attribute and clamp it to its range.
+    pub fn convert(&self, key: &str, value: Value) -> Result<Value, String> {
+        let value = match (self.kind, value) {
+            (AttrType::Bool, Value::Bool(value)) => Value::Bool(value),
+            (AttrType::Int, Value::Int(value)) => Value::Int(value),
+            (AttrType::Int, Value::Float(value)) if value.fract() == 0.0 => {
+                Value::Int(value as i32)
+            }
+            (AttrType::Float, Value::Float(value)) => Value::Float(value),
+            (AttrType::Float, Value::Int(value)) => Value::Float(value as f32),
+            (AttrType::String, Value::Str(value)) => Value::Str(value),
+            (kind, value) => {
+                return Err(format!(
+                    "Attribute '{}' expects a {}, got '{}'.",
+                    key,
+                    kind.name(),
+                    value
+                ));
+            }
+        };
+
+        Ok(match value {
+            Value::Int(value) => {
+                let min = self.min.map_or(i32::MIN, |min| min.ceil() as i32);
+                let max = self.max.map_or(i32::MAX, |max| max.floor() as i32);
+                Value::Int(value.clamp(min, max.max(min)))
+            }
+            Value::Float(value) => {
+                let min = self.min.unwrap_or(f32::MIN);
+                let max = self.max.unwrap_or(f32::MAX);
+                Value::Float(value.clamp(min, max.max(min)))
+            }
+            value => value,
+        })
+    }
+
+    /// The converted default value, if any.
+    pub fn default_value(&self, key: &str) -> Option<Result<Value, String>> {
+        let value = match self.default.as_ref()? {
+            toml::Value::Boolean(value) => Value::Bool(*value),
+            toml::Value::Integer(value) => Value::Int(*value as i32),
+            toml::Value::Float(value) => Value::Float(*value as f32),
+            toml::Value::String(value) => Value::Str(value.clone()),
+            value => {
+                return Some(Err(format!(
+                    "Attribute '{}' has an unsupported default '{}'.",
+                    key, value
+                )));
+            }
+        };
+        Some(self.convert(key, value))
+    }
+}
+
+/// The attribute schema of a class, read from the `[schema]` table of the class data. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// The schema is enforced when the class data is applied and by `set_attr`. Attributes the
+/// schema does not know are accepted unless the schema is strict.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AttrSchemas {
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(flatten)]
+    pub attributes: FxHashMap<String, AttrSchema>,
+}
+
+impl AttrSchemas {
+    /// Read the schema from the class data, empty if the table is missing.
+    pub fn from_data(toml: &str) -> Result<Self, String> {
+        let table = toml.parse::<Table>().map_err(|err| err.to_string())?;
+        Self::from_table(&table)
+    }
+
+    /// Read the schema from the parsed class data.
+    pub fn from_table(table: &Table) -> Result<Self, String> {
+        match table.get("schema") {
+            Some(schema) => schema.clone().try_into().map_err(|err| err.to_string()),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.strict && self.attributes.is_empty()
+    }
+
+    /// Check the value for the attribute, returns it converted and clamped.
+    pub fn check(&self, key: &str, value: Value) -> Result<Value, String> {
+        match self.attributes.get(key) {
+            Some(schema) => schema.convert(key, value),
+            None if self.strict => Err(format!("Unknown attribute '{}'.", key)),
+            None => Ok(value),
+        }
+    }
+
+    /// Check the given attributes and add the defaults of the missing ones. Returns the values
+    /// to set and the errors.
+    pub fn resolve<'a>(
+        &self,
+        attributes: &ValueContainer,
+        keys: impl Iterator<Item = &'a String>,
+    ) -> (Vec<(String, Value)>, Vec<String>) {
+        let mut values = vec![];
+        let mut errors = vec![];
+
+        for key in keys {
+            if let Some(value) = attributes.get(key) {
+                match self.check(key, value.clone()) {
+                    Ok(value) => values.push((key.clone(), value)),
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+
+        for (key, schema) in &self.attributes {
+            if attributes.contains(key) {
+                continue;
+            }
+            match schema.default_value(key) {
+                Some(Ok(value)) => values.push((key.clone(), value)),
+                Some(Err(err)) => errors.push(err),
+                None => {}
+            }
+        }
+
+        (values, errors)
+    }
+
+    /// The attributes which are not sent to the clients.
+    pub fn hidden(&self) -> FxHashSet<String> {
+        self.attributes
+            .iter()
+            .filter(|(_, schema)| !schema.replicate)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &str = r#"
+        [attributes]
+        name = "Guard"
+
+        [schema]
+        strict = true
+        health = { type = "int", default = 20, min = 0, max = 100 }
+        speed = { type = "float", default = 1.0, min = 0.1 }
+        secret = { type = "string", replicate = false }
+    "#;
+
+    #[test]
+    fn conversion() {
+        let schemas = AttrSchemas::from_data(DATA).unwrap();
+        assert!(AttrSchemas::from_data("[attributes]").unwrap().is_empty());
+
+        assert_eq!(
+            schemas.check("health", Value::Float(30.0)),
+            Ok(Value::Int(30))
+        );
+        assert_eq!(
+            schemas.check("health", Value::Int(120)),
+            Ok(Value::Int(100))
+        );
+        assert_eq!(schemas.check("speed", Value::Int(0)), Ok(Value::Float(0.1)));
+        assert!(schemas.check("health", Value::Float(2.5)).is_err());
+        assert!(schemas.check("secret", Value::Int(1)).is_err());
+        assert!(schemas.check("mana", Value::Int(1)).is_err());
+        assert_eq!(
+            AttrSchemas::default().check("mana", Value::Int(1)),
+            Ok(Value::Int(1))
+        );
+        assert_eq!(
+            schemas.hidden(),
+            FxHashSet::from_iter(["secret".to_string()])
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let schemas = AttrSchemas::from_data(DATA).unwrap();
+        let mut attributes = ValueContainer::new();
+        attributes.set("health", Value::Int(-5));
+        attributes.set("mana", Value::Int(3));
+        let keys = ["health".to_string(), "mana".to_string()];
+
+        let (mut values, errors) = schemas.resolve(&attributes, keys.iter());
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            values,
+            vec![
+                ("health".to_string(), Value::Int(0)),
+                ("speed".to_string(), Value::Float(1.0)),
+            ]
+        );
+        assert_eq!(errors, vec!["Unknown attribute 'mana'.".to_string()]);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::vm::VMValue;
+use crate::{Choice, Item, MultipleChoice, RegionCtx, RegionMessage, Value};
+use theframework::prelude::*;
+
+/// A vendor, read from the `[shop]` table of the entity data. See `src/server/CONFIG.md` for an
+/// example.
+///
+/// The `worth` of items is given in the base currency.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Shop {
+    pub stock: Vec<String>,
+    pub markup: f32,
+    pub buy_ratio: f32,
+    pub buys: Vec<String>,
+    pub currency: Option<String>,
+}
+
+impl Default for Shop {
+    fn default() -> Self {
+        Self {
+            stock: vec![],
+            markup: 1.0,
+            buy_ratio: 0.5,
+            buys: vec![],
+            currency: None,
+        }
+    }
+}
+
+impl Shop {
+    /// Read the shop from the entity data, None if the entity is no vendor.
+    pub fn from_data(toml: &str) -> Option<Self> {
+        let table = toml.parse::<toml::Table>().ok()?;
+        table.get("shop")?.clone().try_into().ok()
+    }
+
+    /// The price in base currency the vendor asks for the item.
+    pub fn sell_price(&self, item: &Item) -> i64 {
+        (worth(item) as f32 * self.markup).round() as i64
+    }
+
+    /// The price in base currency the vendor pays for the item, None if it does not buy it.
+    pub fn buy_price(&self, item: &Item) -> Option<i64> {
+        let class_name = item.get_attr_string("class_name").unwrap_or_default();
+        if !self.buys.is_empty() && !self.buys.contains(&class_name) {
+            return None;
+        }
+        Some((worth(item) as f32 * self.buy_ratio).round() as i64)
+    }
+}
+
+fn worth(item: &Item) -> i64 {
+    item.get_attribute("worth")
+        .and_then(|worth| worth.to_i32())
+        .unwrap_or(0) as i64
+}
+
+/// The shop of the given entity.
+pub fn shop_of(ctx: &RegionCtx, vendor_id: u32) -> Option<Shop> {
+    let class_name = ctx.entity_classes.get(&vendor_id)?;
+    Shop::from_data(ctx.entity_class_data.get(class_name)?)
+}
+
+/// Transfer funds in base currency between two entities.
+pub fn transfer_funds(ctx: &mut RegionCtx, from: u32, to: u32, amount: i64) -> Result<(), String> {
+    if amount <= 0 {
+        return Ok(());
+    }
+    let currencies = ctx.currencies.clone();
+    {
+        let payer = ctx.get_entity_mut(from).ok_or("Unknown entity.")?;
+        if !payer.wallet.can_afford(amount, &currencies) {
+            return Err("{cant_afford}".into());
+        }
+        payer.spend_currency(amount, &currencies)?;
+    }
+    let payee = ctx.get_entity_mut(to).ok_or("Unknown entity.")?;
+    payee.add_base_currency(amount, &currencies)
+}
+
+/// Add the missing stock items to the inventory of the vendor.
+pub fn restock(ctx: &mut RegionCtx, vendor_id: u32) {
+    let Some(shop) = shop_of(ctx, vendor_id) else {
+        return;
+    };
+    for class_name in shop.stock {
+        let in_stock = ctx
+            .map
+            .entities
+            .iter()
+            .find(|entity| entity.id == vendor_id)
+            .is_some_and(|entity| {
+                entity
+                    .iter_inventory()
+                    .any(|(_, item)| item.get_attr_string("class_name") == Some(class_name.clone()))
+            });
+        if in_stock {
+            continue;
+        }
+        if let Some(item) = ctx.create_item(class_name) {
+            if let Some(vendor) = ctx.get_entity_mut(vendor_id) {
+                _ = vendor.add_item(item);
+            }
+        }
+    }
+}
+
+/// If the vendor is open for business, false outside of its routine's working hours.
+pub fn is_open(ctx: &RegionCtx, vendor_id: u32) -> bool {
+    ctx.map
+        .entities
+        .iter()
+        .find(|entity| entity.id == vendor_id)
+        .is_some_and(|vendor| vendor.attributes.get_bool_default("shop_open", true))
+}
+
+/// Restock the vendor and offer its inventory to the customer. The asked price of each item is
+/// stored in its `price` attribute for the client. Fails if the `shop_open` attribute of the
+/// vendor is false, i.e. outside of its routine's working hours.
+pub fn open_shop(ctx: &mut RegionCtx, vendor_id: u32, customer_id: u32) -> bool {
+    let Some(shop) = shop_of(ctx, vendor_id) else {
+        return false;
+    };
+    if !is_open(ctx, vendor_id) {
+        return false;
+    }
+    restock(ctx, vendor_id);
+
+    let region_id = ctx.region_id;
+    let Some(vendor) = ctx.get_entity_mut(vendor_id) else {
+        return false;
+    };
+    let mut choices = MultipleChoice::new(region_id, vendor_id, customer_id);
+    let slots: Vec<usize> = vendor.iter_inventory().map(|(slot, _)| slot).collect();
+    for slot in slots {
+        if let Some(item) = vendor.get_item_in_slot_mut(slot) {
+            let price = shop.sell_price(item);
+            item.set_attribute("price", Value::Int(price as i32));
+            choices.add(Choice::ItemToSell(item.id, vendor_id, customer_id));
+        }
+    }
+
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::MultipleChoice(choices));
+    }
+    true
+}
+
+/// The customer buys the item from the vendor. Returns the paid price.
+pub fn buy(
+    ctx: &mut RegionCtx,
+    customer_id: u32,
+    vendor_id: u32,
+    item_id: u32,
+) -> Result<i64, String> {
+    let shop = shop_of(ctx, vendor_id).ok_or("Not a vendor.")?;
+    if !is_open(ctx, vendor_id) {
+        return Err("The shop is closed.".into());
+    }
+    let item = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == vendor_id)
+        .and_then(|vendor| vendor.get_item(item_id))
+        .ok_or("The item is not for sale.")?;
+    let price = shop.sell_price(item);
+
+    let has_space = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == customer_id)
+        .is_some_and(|customer| customer.inventory.iter().any(|slot| slot.is_none()));
+    if !has_space {
+        return Err("Inventory full.".into());
+    }
+
+    transfer_funds(ctx, customer_id, vendor_id, price)?;
+
+    let mut item = ctx
+        .get_entity_mut(vendor_id)
+        .and_then(|vendor| vendor.remove_item(item_id))
+        .ok_or("The item is not for sale.")?;
+    item.attributes.remove("price");
+    if let Some(customer) = ctx.get_entity_mut(customer_id) {
+        _ = customer.add_item(item);
+    }
+
+    ctx.to_execute_entity.push((
+        vendor_id,
+        "sold".into(),
+        VMValue::new(item_id as f32, customer_id as f32, price as f32),
+    ));
+    Ok(price)
+}
+
+/// The customer sells the item to the vendor. Returns the received price.
+pub fn sell(
+    ctx: &mut RegionCtx,
+    customer_id: u32,
+    vendor_id: u32,
+    item_id: u32,
+) -> Result<i64, String> {
+    let shop = shop_of(ctx, vendor_id).ok_or("Not a vendor.")?;
+    if !is_open(ctx, vendor_id) {
+        return Err("The shop is closed.".into());
+    }
+    let item = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == customer_id)
+        .and_then(|customer| customer.get_item(item_id))
+        .ok_or("Unknown item.")?;
+    let price = shop
+        .buy_price(item)
+        .ok_or("The vendor does not buy this item.")?;
+
+    let has_space = ctx
+        .map
+        .entities
+        .iter()
+        .find(|entity| entity.id == vendor_id)
+        .is_some_and(|vendor| vendor.inventory.iter().any(|slot| slot.is_none()));
+    if !has_space {
+        return Err("The vendor cannot take more items.".into());
+    }
+
+    // The vendor pays in its currency
+    let currencies = ctx.currencies.clone();
+    let payout = match &shop.currency {
+        Some(symbol) => Some((symbol, currencies.convert_from_base(price, symbol)?)),
+        None => None,
+    };
+    let vendor = ctx.get_entity_mut(vendor_id).ok_or("Unknown entity.")?;
+    if !vendor.wallet.can_afford(price, &currencies) {
+        return Err("The vendor cannot afford this item.".into());
+    }
+    vendor.spend_currency(price, &currencies)?;
+
+    let item = ctx
+        .get_entity_mut(customer_id)
+        .and_then(|customer| customer.remove_item(item_id))
+        .ok_or("Unknown item.")?;
+    if let Some(vendor) = ctx.get_entity_mut(vendor_id) {
+        _ = vendor.add_item(item);
+    }
+    if let Some(customer) = ctx.get_entity_mut(customer_id) {
+        match payout {
+            Some((symbol, amount)) => customer.add_currency(symbol, amount, &currencies)?,
+            None => customer.add_base_currency(price, &currencies)?,
+        }
+    }
+
+    ctx.to_execute_entity.push((
+        vendor_id,
+        "bought".into(),
+        VMValue::new(item_id as f32, customer_id as f32, price as f32),
+    ));
+    Ok(price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entity;
+
+    const CUSTOMER: u32 = 1;
+    const VENDOR: u32 = 2;
+    const ITEM: u32 = 3;
+
+    fn region(vendor_data: Option<&str>) -> RegionCtx {
+        let mut ctx = RegionCtx::default();
+        for id in [CUSTOMER, VENDOR] {
+            let mut entity = Entity::new();
+            entity.id = id;
+            entity.inventory = vec![None; 4];
+            ctx.map.entities.push(entity);
+        }
+        let mut item = Item::new();
+        item.id = ITEM;
+        _ = ctx.map.entities[1].add_item(item);
+
+        if let Some(data) = vendor_data {
+            ctx.entity_classes.insert(VENDOR, "Vendor".into());
+            ctx.entity_class_data
+                .insert("Vendor".into(), data.to_string());
+        }
+        ctx
+    }
+
+    fn owner_of_item(ctx: &RegionCtx) -> Option<u32> {
+        ctx.map
+            .entities
+            .iter()
+            .find(|entity| entity.get_item(ITEM).is_some())
+            .map(|entity| entity.id)
+    }
+
+    #[test]
+    fn buy_from_vendor() {
+        let mut ctx = region(Some("[shop]\nmarkup = 2.0\n"));
+        assert_eq!(buy(&mut ctx, CUSTOMER, VENDOR, ITEM), Ok(0));
+        assert_eq!(owner_of_item(&ctx), Some(CUSTOMER));
+    }
+
+    #[test]
+    fn buy_from_non_vendor_fails() {
+        let mut ctx = region(None);
+        assert!(buy(&mut ctx, CUSTOMER, VENDOR, ITEM).is_err());
+        assert_eq!(owner_of_item(&ctx), Some(VENDOR));
+        assert!(ctx.to_execute_entity.is_empty());
+    }
+
+    #[test]
+    fn buy_from_closed_shop_fails() {
+        let mut ctx = region(Some("[shop]\n"));
+        ctx.map.entities[1].set_attribute("shop_open", Value::Bool(false));
+        assert!(buy(&mut ctx, CUSTOMER, VENDOR, ITEM).is_err());
+        assert!(!open_shop(&mut ctx, VENDOR, CUSTOMER));
+        assert_eq!(owner_of_item(&ctx), Some(VENDOR));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Entity, EntityAction, RegionMessage, Value};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::collections::VecDeque;
+use theframework::prelude::*;
+
+/// The simulation settings, read from the `[simulation]` table of the game config. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// In deterministic mode the regions advance their game ticks themselves after a fixed number
+/// of redraw frames (`game_tick_ms` / frame time), external system ticks are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SimulationRules {
+    pub seed: Option<u64>,
+    pub record: bool,
+}
+
+impl SimulationRules {
+    pub fn is_deterministic(&self) -> bool {
+        self.seed.is_some()
+    }
+}
+
+/// The random number generator of a region. Seeded in deterministic mode, otherwise from OS
+/// entropy. All server side randomness has to be drawn from it.
+#[derive(Debug, Clone)]
+pub struct RegionRng(StdRng);
+
+impl Default for RegionRng {
+    fn default() -> Self {
+        Self(StdRng::from_os_rng())
+    }
+}
+
+impl RegionRng {
+    /// The generator of the region for the seed, each region gets its own stream.
+    pub fn seeded(seed: u64, region_name: &str) -> Self {
+        // FNV-1a, stable across runs and platforms
+        let hash = region_name.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        Self(StdRng::seed_from_u64(seed ^ hash))
+    }
+
+    /// Restart the generator from a seed drawn from it and return that seed. A generator
+    /// created with `from_state` continues with the same numbers, so save games keep a
+    /// deterministic simulation on track.
+    pub fn save_state(&mut self) -> u64 {
+        let state = self.0.next_u64();
+        self.0 = StdRng::seed_from_u64(state);
+        state
+    }
+
+    /// The generator continuing from a state returned by `save_state`.
+    pub fn from_state(state: u64) -> Self {
+        Self(StdRng::seed_from_u64(state))
+    }
+}
+
+impl RngCore for RegionRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.0.fill_bytes(dst)
+    }
+}
+
+/// An input a region receives from the outside, the part of the simulation which has to be
+/// recorded to replay it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SimulationInput {
+    Event(u32, String, Value),
+    UserEvent(u32, String, Value),
+    UserAction(u32, EntityAction),
+    CreateEntity(Entity),
+}
+
+impl SimulationInput {
+    pub fn from_message(message: &RegionMessage) -> Option<Self> {
+        match message {
+            RegionMessage::Event(id, event, value) => {
+                Some(Self::Event(*id, event.clone(), value.clone()))
+            }
+            RegionMessage::UserEvent(id, event, value) => {
+                Some(Self::UserEvent(*id, event.clone(), value.clone()))
+            }
+            RegionMessage::UserAction(id, action) => Some(Self::UserAction(*id, action.clone())),
+            RegionMessage::CreateEntity(_, entity) => Some(Self::CreateEntity(entity.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn into_message(self, region_id: u32) -> RegionMessage {
+        match self {
+            Self::Event(id, event, value) => RegionMessage::Event(id, event, value),
+            Self::UserEvent(id, event, value) => RegionMessage::UserEvent(id, event, value),
+            Self::UserAction(id, action) => RegionMessage::UserAction(id, action),
+            Self::CreateEntity(entity) => RegionMessage::CreateEntity(region_id, entity),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimulationEntry {
+    /// The redraw frame the input was processed in.
+    pub frame: u64,
+    pub input: SimulationInput,
+}
+
+/// The recorded inputs of a region. Replaying them in a fresh server with the same seed, map
+/// and config reproduces the session.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SimulationLog {
+    pub region: String,
+    pub seed: u64,
+    pub entries: Vec<SimulationEntry>,
+}
+
+impl SimulationLog {
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|err| err.to_string())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| err.to_string())
+    }
+}
+
+/// The record / replay state of a region.
+#[derive(Debug, Default)]
+pub struct Simulation {
+    pub rules: SimulationRules,
+    /// Redraw frames since the region started.
+    pub frame: u64,
+    /// Redraw frames per game tick in deterministic mode.
+    pub frames_per_tick: u64,
+    pub recording: Option<SimulationLog>,
+    pub replay: Option<VecDeque<SimulationEntry>>,
+}
+
+impl Simulation {
+    pub fn new(rules: SimulationRules, region: &str, frames_per_tick: u64) -> Self {
+        let recording = match (rules.seed, rules.record) {
+            (Some(seed), true) => Some(SimulationLog {
+                region: region.to_string(),
+                seed,
+                entries: vec![],
+            }),
+            _ => None,
+        };
+        Self {
+            rules,
+            frame: 0,
+            frames_per_tick: frames_per_tick.max(1),
+            recording,
+            replay: None,
+        }
+    }
+
+    /// Replay the inputs of the log, live inputs are ignored from now on.
+    pub fn start_replay(&mut self, log: SimulationLog) -> Result<(), String> {
+        if self.rules.seed != Some(log.seed) {
+            return Err(format!(
+                "The log was recorded with seed {}, the region does not run with it.",
+                log.seed
+            ));
+        }
+        if self.frame > 0 {
+            return Err("The region is already running.".into());
+        }
+        self.recording = None;
+        self.replay = Some(log.entries.into());
+        Ok(())
+    }
+
+    /// Record or replay the inputs of the current frame.
+    pub fn process(&mut self, region_id: u32, messages: &mut Vec<RegionMessage>) {
+        if let Some(replay) = &mut self.replay {
+            messages.retain(|message| SimulationInput::from_message(message).is_none());
+            while replay
+                .front()
+                .is_some_and(|entry| entry.frame <= self.frame)
+            {
+                if let Some(entry) = replay.pop_front() {
+                    messages.push(entry.input.into_message(region_id));
+                }
+            }
+        } else if let Some(log) = &mut self.recording {
+            for message in messages.iter() {
+                if let Some(input) = SimulationInput::from_message(message) {
+                    log.entries.push(SimulationEntry {
+                        frame: self.frame,
+                        input,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Advance the frame counter, returns true if a game tick is due in deterministic mode.
+    pub fn advance_frame(&mut self) -> bool {
+        self.frame += 1;
+        self.rules.is_deterministic() && self.frame % self.frames_per_tick == 0
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{RegionCtx, RegionMessage};
+use theframework::prelude::*;
+use vek::Vec3;
+
+/// A positional sound of a region, played by the `play_sound` script call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SoundEvent {
+    pub region: u32,
+    pub name: String,
+    pub position: Vec3<f32>,
+    pub volume: f32,
+    /// The distance at which the sound fades out.
+    pub range: f32,
+}
+
+impl SoundEvent {
+    /// The volume heard by a listener at the position, it fades linearly to zero at the range.
+    pub fn volume_at(&self, listener: Vec3<f32>) -> f32 {
+        if self.range <= 0.0 {
+            return 0.0;
+        }
+        let distance = self.position.distance(listener);
+        self.volume * (1.0 - distance / self.range).clamp(0.0, 1.0)
+    }
+}
+
+/// A sound as heard by the listener, for the audio stack of the host application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioEvent {
+    pub name: String,
+    pub position: Vec3<f32>,
+    /// The volume attenuated by the distance to the listener.
+    pub volume: f32,
+    pub distance: f32,
+    /// The stereo pan, -1 left to 1 right of the listener.
+    pub pan: f32,
+}
+
+/// Send a positional sound to the server.
+pub fn send_sound(ctx: &RegionCtx, name: &str, position: Vec3<f32>, volume: f32, range: f32) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Sound(SoundEvent {
+            region: ctx.region_id,
+            name: name.to_string(),
+            position,
+            volume,
+            range,
+        }));
+    }
+}
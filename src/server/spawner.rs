@@ -0,0 +1,132 @@
+use crate::server::region::is_entity_dead_ctx;
+use crate::{Entity, RegionCtx, Value};
+use rand::Rng;
+use theframework::prelude::*;
+use vek::{Vec2, Vec3};
+
+/// A spawner definition, loaded from TOML. A file can define several spawners. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// Spawned entities carry the name of their spawner in the `spawner` attribute.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpawnerDef {
+    #[serde(default)]
+    pub region: String,
+    pub class: String,
+    #[serde(default = "default_max")]
+    pub max: u32,
+    #[serde(default)]
+    pub delay: i64,
+    /// Fill up to max when the region starts, otherwise every entity waits for the delay.
+    #[serde(default = "default_initial")]
+    pub initial: bool,
+    #[serde(default)]
+    pub sector: Option<String>,
+    #[serde(default)]
+    pub marker: Option<String>,
+    #[serde(default)]
+    pub radius: f32,
+}
+
+fn default_max() -> u32 {
+    1
+}
+
+fn default_initial() -> bool {
+    true
+}
+
+/// Parse the spawner definitions of a TOML file.
+pub fn parse_spawners(toml: &str) -> Result<FxHashMap<String, SpawnerDef>, String> {
+    toml::from_str(toml).map_err(|err| err.to_string())
+}
+
+/// A random spawn position of the spawner in the map.
+fn spawn_position(ctx: &mut RegionCtx, def: &SpawnerDef) -> Option<Vec2<f32>> {
+    let rng = &mut ctx.rng;
+    if let Some(name) = &def.sector {
+        let sector = ctx.map.sectors.iter().find(|s| s.name == *name)?;
+        let bbox = sector.bounding_box(&ctx.map);
+        for _ in 0..20 {
+            let point = Vec2::new(
+                rng.random_range(bbox.min.x..=bbox.max.x),
+                rng.random_range(bbox.min.y..=bbox.max.y),
+            );
+            let cell = point.map(|c| c.floor() as i32);
+            if sector.is_inside(&ctx.map, point)
+                && ctx
+                    .mapmini
+                    .is_cell_walkable(cell, 0.3, &FxHashSet::default())
+            {
+                return Some(point);
+            }
+        }
+        sector.center(&ctx.map)
+    } else if let Some(name) = &def.marker {
+        let vertex = ctx.map.vertices.iter().find(|v| v.name == *name)?;
+        let angle = rng.random_range(0.0..std::f32::consts::TAU);
+        let distance = rng.random_range(0.0..=def.radius.max(0.0));
+        Some(Vec2::new(vertex.x, vertex.y) + Vec2::new(angle.cos(), angle.sin()) * distance)
+    } else {
+        None
+    }
+}
+
+/// Collect the entities the spawners of the region have to create now. The returned entities
+/// still need to be instantiated by the region.
+pub fn due_spawns(ctx: &mut RegionCtx, region_name: &str) -> Vec<Entity> {
+    let spawners: Vec<(String, SpawnerDef)> = ctx
+        .assets
+        .spawners
+        .iter()
+        .filter(|(_, def)| def.region.is_empty() || def.region == region_name)
+        .map(|(name, def)| (name.clone(), def.clone()))
+        .collect();
+
+    let mut spawns = vec![];
+    for (name, def) in spawners {
+        if !ctx.assets.entities.contains_key(&def.class) {
+            continue;
+        }
+        let alive = ctx
+            .map
+            .entities
+            .iter()
+            .filter(|entity| entity.get_attr_string("spawner").as_deref() == Some(name.as_str()))
+            .filter(|entity| !is_entity_dead_ctx(ctx, entity.id))
+            .count() as u32;
+        if alive >= def.max {
+            ctx.spawn_timers.remove(&name);
+            continue;
+        }
+
+        let first = !ctx.spawn_timers.contains_key(&name) && ctx.ticks <= 1;
+        let count = if first && def.initial {
+            def.max - alive
+        } else {
+            let due = *ctx
+                .spawn_timers
+                .entry(name.clone())
+                .or_insert(ctx.ticks + def.delay);
+            if ctx.ticks < due {
+                continue;
+            }
+            ctx.spawn_timers.remove(&name);
+            1
+        };
+
+        for _ in 0..count {
+            let Some(position) = spawn_position(ctx, &def) else {
+                ctx.send_log_message(format!("[warn] Spawner '{}': Unknown spawn area.", name));
+                break;
+            };
+            let mut entity = Entity::default();
+            entity.set_position(Vec3::new(position.x, 1.0, position.y));
+            entity.set_attribute("name", Value::Str(def.class.clone()));
+            entity.set_attribute("class_name", Value::Str(def.class.clone()));
+            entity.set_attribute("spawner", Value::Str(name.clone()));
+            spawns.push(entity);
+        }
+    }
+    spawns
+}
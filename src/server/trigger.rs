@@ -0,0 +1,93 @@
+use crate::RegionCtx;
+use crate::vm::VMValue;
+use theframework::prelude::*;
+
+/// Whether the sector enables the trigger event. Sectors control their events with properties:
+///
+/// - `trigger_entered` / `trigger_left` (default true): the entity entered or left the sector.
+/// - `trigger_used` (default false): a player interacted inside the sector without a target.
+/// - `dwell_ticks` (default 0, off): send a `dwell` event every time the entity stayed this
+///   many ticks in the sector.
+///
+/// All events are sent to the script of the entity, the value holds the name of the sector.
+pub fn sector_trigger_enabled(ctx: &RegionCtx, sector: &str, event: &str, default: bool) -> bool {
+    ctx.map
+        .sectors
+        .iter()
+        .find(|s| s.name == sector)
+        .map(|s| {
+            s.properties
+                .get_bool_default(&format!("trigger_{}", event), default)
+        })
+        .unwrap_or(default)
+}
+
+/// Send the `left` and `entered` events for an entity which moved between the sectors.
+pub fn send_sector_change(ctx: &mut RegionCtx, id: u32, old_sector: &str, new_sector: &str) {
+    if old_sector == new_sector {
+        return;
+    }
+    if !new_sector.is_empty() && sector_trigger_enabled(ctx, new_sector, "entered", true) {
+        ctx.to_execute_entity
+            .push((id, "entered".into(), VMValue::from(new_sector)));
+    }
+    if !old_sector.is_empty() && sector_trigger_enabled(ctx, old_sector, "left", true) {
+        ctx.to_execute_entity
+            .push((id, "left".into(), VMValue::from(old_sector)));
+    }
+    ctx.sector_dwell.remove(&id);
+}
+
+/// Send the `used` event if the entity is inside a sector which enables it. Returns false if
+/// the sector does not handle the interaction.
+pub fn send_sector_used(ctx: &mut RegionCtx, id: u32, sector: &str) -> bool {
+    if sector.is_empty() || !sector_trigger_enabled(ctx, sector, "used", false) {
+        return false;
+    }
+    ctx.to_execute_entity
+        .push((id, "used".into(), VMValue::from(sector)));
+    true
+}
+
+/// Send the `dwell` events to the entities which stayed long enough in their sector, the
+/// value holds the sector name and the ticks spent inside.
+pub fn tick_sector_dwell(ctx: &mut RegionCtx) {
+    let mut events = vec![];
+    let mut present = FxHashSet::default();
+
+    for entity in &ctx.map.entities {
+        let sector = entity.attributes.get_str_default("sector", "".into());
+        if sector.is_empty() {
+            continue;
+        }
+        let Some(dwell_ticks) = ctx
+            .map
+            .sectors
+            .iter()
+            .find(|s| s.name == sector)
+            .map(|s| s.properties.get_int_default("dwell_ticks", 0) as i64)
+            .filter(|ticks| *ticks > 0)
+        else {
+            continue;
+        };
+        present.insert(entity.id);
+
+        let since = match ctx.sector_dwell.get(&entity.id) {
+            Some((name, since)) if *name == sector => *since,
+            _ => {
+                ctx.sector_dwell
+                    .insert(entity.id, (sector.clone(), ctx.ticks));
+                continue;
+            }
+        };
+        let stayed = ctx.ticks - since;
+        if stayed > 0 && stayed % dwell_ticks == 0 {
+            let mut value = VMValue::from(sector);
+            value.x = stayed as f32;
+            events.push((entity.id, "dwell".to_string(), value));
+        }
+    }
+
+    ctx.sector_dwell.retain(|id, _| present.contains(id));
+    ctx.to_execute_entity.extend(events);
+}
@@ -0,0 +1,140 @@
+use crate::server::region::is_entity_dead_ctx;
+use crate::vm::VMValue;
+use crate::{EntityAction, RegionCtx};
+use theframework::prelude::*;
+
+/// The turn-based mode, read from the `[turns]` table of the game config. See
+/// `src/server/CONFIG.md` for an example.
+///
+/// In turn-based mode entities do not move continuously. Every round sends a `turn_start`
+/// event to all entities, the actions of the players are queued until each player acted (or
+/// the timeout passed). Then every entity resolves one step of its action in initiative order
+/// and receives a `turn_end` event. Both events carry the round number.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TurnRules {
+    pub enabled: bool,
+    pub initiative: String,
+    pub step: f32,
+    pub timeout: i64,
+}
+
+impl Default for TurnRules {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initiative: "DEX".into(),
+            step: 1.0,
+            timeout: 0,
+        }
+    }
+}
+
+/// The state of the current round. The rules are read from the config and not saved.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TurnState {
+    #[serde(skip)]
+    pub rules: TurnRules,
+    pub round: u32,
+    /// The tick the round started.
+    pub started: i64,
+    /// The queued actions of the players.
+    pub actions: FxHashMap<u32, EntityAction>,
+}
+
+impl TurnState {
+    pub fn new(rules: TurnRules) -> Self {
+        Self {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.rules.enabled
+    }
+
+    /// Queue the action of a player for the current round, releasing a key does not count.
+    pub fn queue(&mut self, entity: u32, action: EntityAction) {
+        if action != EntityAction::Off {
+            self.actions.insert(entity, action);
+        }
+    }
+}
+
+/// The living entities of the region in initiative order, ties are broken by the id.
+pub fn initiative_order(ctx: &RegionCtx) -> Vec<u32> {
+    let initiative = &ctx.turns.rules.initiative;
+    let mut order: Vec<(f32, u32)> = ctx
+        .map
+        .entities
+        .iter()
+        .filter(|entity| !is_entity_dead_ctx(ctx, entity.id))
+        .map(|entity| {
+            (
+                entity.attributes.get_float_default(initiative, 0.0),
+                entity.id,
+            )
+        })
+        .collect();
+    order.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    order.into_iter().map(|(_, id)| id).collect()
+}
+
+/// Start the next round and send the `turn_start` events.
+pub fn start_round(ctx: &mut RegionCtx) {
+    ctx.turns.round += 1;
+    ctx.turns.started = ctx.ticks;
+    ctx.turns.actions.clear();
+
+    let round = VMValue::broadcast(ctx.turns.round as f32);
+    for id in initiative_order(ctx) {
+        ctx.to_execute_entity
+            .push((id, "turn_start".into(), round.clone()));
+    }
+}
+
+/// If the current round is ready, the entities with the action to resolve in initiative
+/// order. The players use their queued action, the other entities their current one.
+pub fn take_round(ctx: &mut RegionCtx) -> Option<Vec<(u32, EntityAction)>> {
+    if ctx.turns.round == 0 {
+        start_round(ctx);
+        return None;
+    }
+
+    let players: Vec<u32> = ctx
+        .map
+        .entities
+        .iter()
+        .filter(|entity| entity.is_player() && !is_entity_dead_ctx(ctx, entity.id))
+        .map(|entity| entity.id)
+        .collect();
+    let ready = players.iter().all(|id| ctx.turns.actions.contains_key(id));
+    let timeout = ctx.turns.rules.timeout;
+    let timed_out = timeout > 0 && ctx.ticks - ctx.turns.started >= timeout;
+    if !ready && !timed_out {
+        return None;
+    }
+
+    let mut round = vec![];
+    for id in initiative_order(ctx) {
+        let action = if players.contains(&id) {
+            ctx.turns.actions.remove(&id).unwrap_or_default()
+        } else {
+            ctx.map
+                .entities
+                .iter()
+                .find(|entity| entity.id == id)
+                .map(|entity| entity.action.clone())
+                .unwrap_or_default()
+        };
+        round.push((id, action));
+    }
+    Some(round)
+}
+
+/// Send the `turn_end` event to the entity.
+pub fn end_turn(ctx: &mut RegionCtx, id: u32) {
+    let round = VMValue::broadcast(ctx.turns.round as f32);
+    ctx.to_execute_entity.push((id, "turn_end".into(), round));
+}
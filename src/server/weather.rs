@@ -0,0 +1,234 @@
+use crate::{RegionCtx, RegionMessage};
+use rand::Rng;
+use theframework::prelude::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    Rain,
+    Fog,
+    Snow,
+}
+
+impl WeatherKind {
+    pub const ALL: [WeatherKind; 4] = [
+        WeatherKind::Clear,
+        WeatherKind::Rain,
+        WeatherKind::Fog,
+        WeatherKind::Snow,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "clear",
+            WeatherKind::Rain => "rain",
+            WeatherKind::Fog => "fog",
+            WeatherKind::Snow => "snow",
+        }
+    }
+
+    /// The color the sky and sun light is multiplied with.
+    fn tint(&self) -> [f32; 3] {
+        match self {
+            WeatherKind::Clear => [1.0, 1.0, 1.0],
+            WeatherKind::Rain => [0.6, 0.65, 0.7],
+            WeatherKind::Fog => [0.8, 0.8, 0.82],
+            WeatherKind::Snow => [0.85, 0.88, 0.95],
+        }
+    }
+
+    /// The factor of the sun intensity.
+    fn sun(&self) -> f32 {
+        match self {
+            WeatherKind::Clear => 1.0,
+            WeatherKind::Rain => 0.4,
+            WeatherKind::Fog => 0.6,
+            WeatherKind::Snow => 0.7,
+        }
+    }
+
+    /// The fog density.
+    fn fog(&self) -> f32 {
+        match self {
+            WeatherKind::Fog => 0.15,
+            WeatherKind::Rain | WeatherKind::Snow => 0.03,
+            WeatherKind::Clear => 0.0,
+        }
+    }
+}
+
+/// The weather of a region as seen by the client, `blend` is the progress of the transition
+/// from the previous weather (0.0 - 1.0).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct WeatherState {
+    pub kind: WeatherKind,
+    pub previous: WeatherKind,
+    pub blend: f32,
+}
+
+impl Default for WeatherState {
+    fn default() -> Self {
+        Self {
+            kind: WeatherKind::Clear,
+            previous: WeatherKind::Clear,
+            blend: 1.0,
+        }
+    }
+}
+
+impl WeatherState {
+    fn mix(&self, f: impl Fn(&WeatherKind) -> f32) -> f32 {
+        f(&self.previous) + (f(&self.kind) - f(&self.previous)) * self.blend
+    }
+
+    /// The color the sky and sun light is multiplied with.
+    pub fn tint(&self) -> [f32; 3] {
+        [
+            self.mix(|kind| kind.tint()[0]),
+            self.mix(|kind| kind.tint()[1]),
+            self.mix(|kind| kind.tint()[2]),
+        ]
+    }
+
+    /// The factor of the sun intensity.
+    pub fn sun_factor(&self) -> f32 {
+        self.mix(|kind| kind.sun())
+    }
+
+    /// The minimum fog density.
+    pub fn fog_density(&self) -> f32 {
+        self.mix(|kind| kind.fog())
+    }
+
+    /// The strength (0.0 - 1.0) of the given precipitation, used for particle overlays.
+    pub fn amount(&self, kind: WeatherKind) -> f32 {
+        self.mix(|k| if *k == kind { 1.0 } else { 0.0 })
+    }
+}
+
+/// The weather rules, read from the `[weather]` table of the game config. See
+/// `src/server/CONFIG.md` for an example.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct WeatherRules {
+    pub enabled: bool,
+    pub initial: WeatherKind,
+    pub transition: i64,
+    pub duration: [i64; 2],
+    pub weights: FxHashMap<WeatherKind, u32>,
+}
+
+impl Default for WeatherRules {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial: WeatherKind::Clear,
+            transition: 40,
+            duration: [400, 1200],
+            weights: FxHashMap::default(),
+        }
+    }
+}
+
+impl WeatherRules {
+    /// Pick the next weather by weight, clear if no weights are given.
+    fn pick(&self, rng: &mut impl Rng) -> WeatherKind {
+        let total: u32 = self.weights.values().sum();
+        if total == 0 {
+            return WeatherKind::Clear;
+        }
+        let mut pick = rng.random_range(0..total);
+        for kind in WeatherKind::ALL {
+            let weight = self.weights.get(&kind).copied().unwrap_or(0);
+            if pick < weight {
+                return kind;
+            }
+            pick -= weight;
+        }
+        WeatherKind::Clear
+    }
+}
+
+/// The weather state machine of a region. The rules are read from the config and not saved.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Weather {
+    #[serde(skip)]
+    pub rules: WeatherRules,
+    pub state: WeatherState,
+    /// The tick the transition to the current weather started.
+    started: i64,
+    /// The tick the next weather is picked.
+    next_change: i64,
+}
+
+impl Weather {
+    pub fn new(rules: WeatherRules) -> Self {
+        Self {
+            state: WeatherState {
+                kind: rules.initial,
+                previous: rules.initial,
+                blend: 1.0,
+            },
+            rules,
+            started: 0,
+            next_change: 0,
+        }
+    }
+
+    /// Start the transition to the given weather.
+    pub fn set(&mut self, kind: WeatherKind, ticks: i64, rng: &mut impl Rng) {
+        self.state.previous = self.state.kind;
+        self.state.kind = kind;
+        self.state.blend = if self.rules.transition > 0 { 0.0 } else { 1.0 };
+        self.started = ticks;
+        self.next_change = ticks + self.roll_duration(rng);
+    }
+
+    fn roll_duration(&self, rng: &mut impl Rng) -> i64 {
+        let [min, max] = self.rules.duration;
+        rng.random_range(min.min(max)..=min.max(max))
+    }
+
+    /// Advance the weather, returns true if the state changed.
+    pub fn tick(&mut self, ticks: i64, rng: &mut impl Rng) -> bool {
+        if self.rules.enabled {
+            if self.next_change == 0 {
+                self.next_change = ticks + self.roll_duration(rng);
+            } else if ticks >= self.next_change {
+                let kind = self.rules.pick(rng);
+                if kind != self.state.kind {
+                    self.set(kind, ticks, rng);
+                    return true;
+                }
+                self.next_change = ticks + self.roll_duration(rng);
+            }
+        }
+
+        if self.state.blend < 1.0 {
+            let elapsed = (ticks - self.started) as f32;
+            self.state.blend = (elapsed / self.rules.transition.max(1) as f32).min(1.0);
+            return true;
+        }
+        false
+    }
+}
+
+/// Advance the weather of the region and send it to the server when it changed.
+pub fn tick_weather(ctx: &mut RegionCtx) {
+    if ctx.weather.tick(ctx.ticks, &mut ctx.rng) {
+        send_weather(ctx);
+    }
+}
+
+/// Send the weather of the region to the server.
+pub fn send_weather(ctx: &RegionCtx) {
+    if let Some(sender) = ctx.from_sender.get() {
+        _ = sender.send(RegionMessage::Weather(ctx.region_id, ctx.weather.state));
+    }
+}
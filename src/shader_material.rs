@@ -0,0 +1,100 @@
+use crate::Texture;
+use rusteria::{Execution, Program, RenderBuffer, Rusteria};
+use std::sync::{Arc, Mutex};
+use theframework::prelude::*;
+
+/// A compiled rusteria program used as a material. The `shade` function of the program is
+/// baked into a texture for the chunk builders and can optionally be evaluated per hit by the tracer.
+#[derive(Clone)]
+pub struct ShaderMaterial {
+    pub id: Uuid,
+    pub program: Program,
+
+    /// The width and height of the baked texture.
+    pub resolution: usize,
+
+    /// If true the tracer evaluates the shade function per hit instead of sampling the baked texture.
+    pub procedural: bool,
+}
+
+impl ShaderMaterial {
+    /// Compile the given rusteria code. The code needs to define a `shade` function.
+    pub fn new(code: &str) -> Result<Self, String> {
+        let mut rs = Rusteria::default();
+        let module = rs
+            .parse_str(code)
+            .map_err(|e| format!("Error parsing module: {e}"))?;
+        rs.compile(&module)
+            .map_err(|e| format!("Error compiling module: {e}"))?;
+
+        if rs.context.program.shade_index.is_none() {
+            return Err("Module has no shade function".into());
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            program: rs.context.program.clone(),
+            resolution: 64,
+            procedural: false,
+        })
+    }
+
+    /// Sets the id using the builder pattern.
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Sets the resolution of the baked texture using the builder pattern.
+    pub fn resolution(mut self, resolution: usize) -> Self {
+        self.resolution = resolution.max(1);
+        self
+    }
+
+    /// Enables per hit evaluation in the tracer using the builder pattern.
+    pub fn procedural(mut self, procedural: bool) -> Self {
+        self.procedural = procedural;
+        self
+    }
+
+    /// Bake the shade function into a texture of the material resolution.
+    pub fn bake(&self, palette: &ThePalette) -> Option<Texture> {
+        let shade_index = self.program.shade_index?;
+
+        let mut rs = Rusteria::default();
+        rs.context.program = self.program.clone();
+
+        let size = self.resolution;
+        let mut buffer = Arc::new(Mutex::new(RenderBuffer::new(size, size)));
+        rs.shade(&mut buffer, shade_index, palette);
+
+        let bytes = buffer.lock().unwrap().as_rgba_bytes();
+        let mut texture = Texture::new(bytes, size, size);
+        texture.generate_normals(true);
+
+        Some(texture)
+    }
+
+    /// Evaluate the shade function for a single hit and return the resulting color. The uv is wrapped
+    /// into the 0..1 range to match sampling the repeating baked texture.
+    pub fn evaluate(
+        &self,
+        execution: &mut Execution,
+        uv: Vec2<f32>,
+        hitpoint: Vec3<f32>,
+        normal: Vec3<f32>,
+        palette: &ThePalette,
+    ) -> Option<Vec3<f32>> {
+        let shade_index = self.program.shade_index?;
+
+        execution.uv = Vec3::new(uv.x - uv.x.floor(), uv.y - uv.y.floor(), 0.0);
+        execution.hitpoint = hitpoint;
+        execution.normal = normal;
+        execution.color = Vec3::zero();
+
+        execution.reset(self.program.globals);
+        execution.shade(shade_index, &self.program, palette);
+
+        Some(execution.color)
+    }
+}
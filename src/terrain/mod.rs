@@ -224,7 +224,7 @@ impl Terrain {
                         }
                     }
                 }
-                PixelSource::MaterialId(id) => {
+                PixelSource::MaterialId(id) | PixelSource::ShaderId(id) => {
                     if let Some(index) = assets.tile_indices.get(id) {
                         if let Some(texture) = assets.tile_list[*index as usize].textures.first() {
                             return (texture.sample_nearest(uv.x, uv.y), true);
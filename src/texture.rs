@@ -551,6 +551,17 @@ impl Texture {
         TheRGBABuffer::from(self.data.clone(), self.width as u32, self.height as u32)
     }
 
+    /// Encode the color data as PNG.
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        let img =
+            image::RgbaImage::from_raw(self.width as u32, self.height as u32, self.data.clone())
+                .ok_or("Texture data does not match its size.")?;
+        let mut buf = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .map_err(|err| err.to_string())?;
+        Ok(buf)
+    }
+
     /// Generates normals from this texture's color data using Sobel filter on luma,
     /// and stores them in the unified data_ext format (preserves any existing material data).
     ///
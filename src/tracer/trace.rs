@@ -9,6 +9,7 @@ use bvh::aabb::Bounded;
 use bvh::ray::Ray as BvhRay;
 use rand::Rng;
 use rayon::prelude::*;
+use rusteria::Execution;
 use vek::{Vec2, Vec3, Vec4};
 
 fn srgb_to_linear(c: f32) -> f32 {
@@ -48,6 +49,9 @@ pub struct Tracer {
     render_miss: Vec<u16>,
 
     pub hour: f32,
+
+    /// Evaluate procedural shader materials per hit instead of sampling their baked textures.
+    pub procedural_materials: bool,
 }
 
 impl Default for Tracer {
@@ -69,6 +73,7 @@ impl Tracer {
             render_hit: vec![],
             render_miss: vec![],
             hour: 12.0,
+            procedural_materials: false,
         }
     }
 
@@ -84,6 +89,12 @@ impl Tracer {
         self
     }
 
+    /// Enables per hit evaluation of procedural shader materials using the builder pattern.
+    pub fn procedural_materials(mut self, procedural_materials: bool) -> Self {
+        self.procedural_materials = procedural_materials;
+        self
+    }
+
     /// Precomputes the bounding boxes of all static batches.
     pub fn compute_static_bboxes(&mut self, scene: &Scene) {
         self.static_bboxes.clear();
@@ -165,6 +176,7 @@ impl Tracer {
                 let tile = *tile;
                 let mut lin_tile = vec![Vec4::zero(); tile.width * tile.height];
                 let mut rng = rand::rng();
+                let mut execution = Execution::new(0);
 
                 for ty in 0..tile.height {
                     for tx in 0..tile.width {
@@ -206,6 +218,7 @@ impl Tracer {
                                                 &mut hit,
                                                 assets,
                                                 Some(chunk),
+                                                &mut execution,
                                             )
                                         {
                                             hitinfo = hit;
@@ -223,6 +236,7 @@ impl Tracer {
                                                 &mut hit,
                                                 assets,
                                                 Some(chunk),
+                                                &mut execution,
                                             )
                                         {
                                             hitinfo = hit;
@@ -242,7 +256,13 @@ impl Tracer {
                                 if let Some(mut hit) = batch.intersect(&ray, false) {
                                     if hit.t < hitinfo.t
                                         && self.evaluate_hit(
-                                            &ray, scene, batch, &mut hit, assets, None,
+                                            &ray,
+                                            scene,
+                                            batch,
+                                            &mut hit,
+                                            assets,
+                                            None,
+                                            &mut execution,
                                         )
                                     {
                                         hitinfo = hit;
@@ -261,7 +281,13 @@ impl Tracer {
                                 if let Some(mut hit) = batch.intersect(&ray, false) {
                                     if hit.t < hitinfo.t
                                         && self.evaluate_hit(
-                                            &ray, scene, batch, &mut hit, assets, None,
+                                            &ray,
+                                            scene,
+                                            batch,
+                                            &mut hit,
+                                            assets,
+                                            None,
+                                            &mut execution,
                                         )
                                     {
                                         hitinfo = hit;
@@ -374,6 +400,7 @@ impl Tracer {
         buffer.frame += 1;
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn evaluate_hit(
         &self,
         ray: &Ray,
@@ -382,58 +409,64 @@ impl Tracer {
         hit: &mut HitInfo,
         assets: &Assets,
         chunk: Option<&Chunk>,
+        execution: &mut Execution,
     ) -> bool {
-        let mut texel = match batch.source {
-            PixelSource::StaticTileIndex(index) => {
-                let textile = &assets.tile_list[index as usize];
-                let index = scene.animation_frame % textile.textures.len();
-
-                /*
-                if let Some(mut normal) = hit.normal {
-                    let texel = pixel_to_vec4(&&textile.textures[index].sample_with_normal(
+        let procedural = self.procedural_texel(ray, batch, hit, assets, execution);
+        let mut texel = if let Some(texel) = procedural {
+            texel
+        } else {
+            match batch.source {
+                PixelSource::StaticTileIndex(index) => {
+                    let textile = &assets.tile_list[index as usize];
+                    let index = scene.animation_frame % textile.textures.len();
+
+                    /*
+                    if let Some(mut normal) = hit.normal {
+                        let texel = pixel_to_vec4(&&textile.textures[index].sample_with_normal(
+                            hit.uv.x,
+                            hit.uv.y,
+                            self.sample_mode,
+                            batch.repeat_mode,
+                            Some(&mut normal),
+                            0.2,
+                        ));
+                        hit.normal = Some(normal);
+                        texel
+                    } else {
+                        */
+                    pixel_to_vec4(&&textile.textures[index].sample(
                         hit.uv.x,
                         hit.uv.y,
                         self.sample_mode,
                         batch.repeat_mode,
-                        Some(&mut normal),
-                        0.2,
-                    ));
-                    hit.normal = Some(normal);
-                    texel
-                } else {
-                    */
-                pixel_to_vec4(&&textile.textures[index].sample(
-                    hit.uv.x,
-                    hit.uv.y,
-                    self.sample_mode,
-                    batch.repeat_mode,
-                ))
-            }
-            PixelSource::DynamicTileIndex(index) => {
-                let textile = &scene.dynamic_textures[index as usize];
-                let index = scene.animation_frame % textile.textures.len();
-                pixel_to_vec4(&textile.textures[index].sample(
-                    hit.uv.x,
-                    hit.uv.y,
-                    self.sample_mode,
-                    batch.repeat_mode,
-                ))
-            }
-            PixelSource::Pixel(col) => pixel_to_vec4(&col),
-            PixelSource::Terrain => {
-                // if let Some(terrain) = &scene.terrain {
-                //     let w = ray.at(hit.t);
-                //     pixel_to_vec4(&terrain.sample_baked(Vec2::new(w.x, w.y)))
-                // } else {
-                if let Some(chunk) = chunk {
-                    let w = ray.at(hit.t);
-                    let texel = chunk.sample_terrain_texture(Vec2::new(w.x, w.z), Vec2::one());
-                    pixel_to_vec4(&texel)
-                } else {
-                    Vec4::zero()
+                    ))
+                }
+                PixelSource::DynamicTileIndex(index) => {
+                    let textile = &scene.dynamic_textures[index as usize];
+                    let index = scene.animation_frame % textile.textures.len();
+                    pixel_to_vec4(&textile.textures[index].sample(
+                        hit.uv.x,
+                        hit.uv.y,
+                        self.sample_mode,
+                        batch.repeat_mode,
+                    ))
+                }
+                PixelSource::Pixel(col) => pixel_to_vec4(&col),
+                PixelSource::Terrain => {
+                    // if let Some(terrain) = &scene.terrain {
+                    //     let w = ray.at(hit.t);
+                    //     pixel_to_vec4(&terrain.sample_baked(Vec2::new(w.x, w.y)))
+                    // } else {
+                    if let Some(chunk) = chunk {
+                        let w = ray.at(hit.t);
+                        let texel = chunk.sample_terrain_texture(Vec2::new(w.x, w.z), Vec2::one());
+                        pixel_to_vec4(&texel)
+                    } else {
+                        Vec4::zero()
+                    }
                 }
+                _ => Vec4::zero(),
             }
-            _ => Vec4::zero(),
         };
         let tex_lin = texel.map(srgb_to_linear);
         if let Some(material) = &batch.material {
@@ -474,6 +507,45 @@ impl Tracer {
         }
     }
 
+    /// Evaluate the shade function of a procedural shader material, if the batch uses one.
+    fn procedural_texel(
+        &self,
+        ray: &Ray,
+        batch: &Batch3D,
+        hit: &HitInfo,
+        assets: &Assets,
+        execution: &mut Execution,
+    ) -> Option<Vec4<f32>> {
+        if !self.procedural_materials {
+            return None;
+        }
+
+        // Shader materials are baked into the tile list, the tile id is the material id
+        let PixelSource::StaticTileIndex(index) = batch.source else {
+            return None;
+        };
+        let tile = assets.tile_list.get(index as usize)?;
+        let material = assets
+            .shader_materials
+            .get(&tile.id)
+            .filter(|material| material.procedural)?;
+
+        let color = material.evaluate(
+            execution,
+            hit.uv,
+            ray.at(hit.t),
+            hit.normal.unwrap_or(Vec3::unit_y()),
+            &assets.palette,
+        )?;
+
+        Some(Vec4::new(
+            color.x.clamp(0.0, 1.0),
+            color.y.clamp(0.0, 1.0),
+            color.z.clamp(0.0, 1.0),
+            1.0,
+        ))
+    }
+
     #[inline(always)]
     pub fn reflect(&self, i: Vec3<f32>, n: Vec3<f32>) -> Vec3<f32> {
         i - 2.0 * i.dot(n) * n
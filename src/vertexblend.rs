@@ -244,6 +244,15 @@ impl VertexBlendPreset {
         }
     }
 
+    /// Look up a preset by name, case and underscores are ignored (i.e. "top_left_soft").
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.replace('_', "").to_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| format!("{:?}", preset).to_lowercase() == name)
+    }
+
     /// Canonical iteration order (sorted by importance / frequency).
     pub const ALL: &'static [VertexBlendPreset] = &[
         // ── Most common ─────────────────────────────
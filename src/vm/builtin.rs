@@ -196,6 +196,22 @@ impl Default for Builtins {
                 argc: 2,
             },
         );
+        b.insert(
+            "schedule",
+            2,
+            NodeOp::HostCall {
+                name: "schedule".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "unschedule",
+            1,
+            NodeOp::HostCall {
+                name: "unschedule".into(),
+                argc: 1,
+            },
+        );
         b.insert(
             "random_walk",
             3,
@@ -244,6 +260,142 @@ impl Default for Builtins {
                 argc: 0,
             },
         );
+        b.insert(
+            "entities_in_cone",
+            3,
+            NodeOp::HostCall {
+                name: "entities_in_cone".into(),
+                argc: 3,
+            },
+        );
+        b.insert(
+            "items_in_radius",
+            1,
+            NodeOp::HostCall {
+                name: "items_in_radius".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "raycast",
+            2,
+            NodeOp::HostCall {
+                name: "raycast".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "play_sound",
+            3,
+            NodeOp::HostCall {
+                name: "play_sound".into(),
+                argc: 3,
+            },
+        );
+        b.insert(
+            "emit_particles",
+            3,
+            NodeOp::HostCall {
+                name: "emit_particles".into(),
+                argc: 3,
+            },
+        );
+        b.insert(
+            "float_text",
+            3,
+            NodeOp::HostCall {
+                name: "float_text".into(),
+                argc: 3,
+            },
+        );
+        b.insert(
+            "follow",
+            1,
+            NodeOp::HostCall {
+                name: "follow".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "stop_following",
+            0,
+            NodeOp::HostCall {
+                name: "stop_following".into(),
+                argc: 0,
+            },
+        );
+        b.insert(
+            "claim",
+            1,
+            NodeOp::HostCall {
+                name: "claim".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "release",
+            1,
+            NodeOp::HostCall {
+                name: "release".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "lock",
+            1,
+            NodeOp::HostCall {
+                name: "lock".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "unlock",
+            1,
+            NodeOp::HostCall {
+                name: "unlock".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "is_locked",
+            1,
+            NodeOp::HostCall {
+                name: "is_locked".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "is_owned_by",
+            2,
+            NodeOp::HostCall {
+                name: "is_owned_by".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "grant_xp",
+            3,
+            NodeOp::HostCall {
+                name: "grant_xp".into(),
+                argc: 3,
+            },
+        );
+        b.insert(
+            "skill_level",
+            2,
+            NodeOp::HostCall {
+                name: "skill_level".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "play_cutscene",
+            1,
+            NodeOp::HostCall {
+                name: "play_cutscene".into(),
+                argc: 1,
+            },
+        );
         b.insert(
             "list_get",
             2,
@@ -332,6 +484,134 @@ impl Default for Builtins {
                 argc: 2,
             },
         );
+        b.insert(
+            "open_shop",
+            1,
+            NodeOp::HostCall {
+                name: "open_shop".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "transfer_funds",
+            2,
+            NodeOp::HostCall {
+                name: "transfer_funds".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "start_quest",
+            2,
+            NodeOp::HostCall {
+                name: "start_quest".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "advance_quest",
+            2,
+            NodeOp::HostCall {
+                name: "advance_quest".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "quest_state",
+            2,
+            NodeOp::HostCall {
+                name: "quest_state".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "apply_effect",
+            2,
+            NodeOp::HostCall {
+                name: "apply_effect".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "has_effect",
+            2,
+            NodeOp::HostCall {
+                name: "has_effect".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "remove_effect",
+            2,
+            NodeOp::HostCall {
+                name: "remove_effect".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "craft",
+            1,
+            NodeOp::HostCall {
+                name: "craft".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "drop_loot",
+            2,
+            NodeOp::HostCall {
+                name: "drop_loot".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "shoot",
+            2,
+            NodeOp::HostCall {
+                name: "shoot".into(),
+                argc: 2,
+            },
+        );
+        b.insert(
+            "attack",
+            1,
+            NodeOp::HostCall {
+                name: "attack".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "set_weather",
+            1,
+            NodeOp::HostCall {
+                name: "set_weather".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "weather",
+            0,
+            NodeOp::HostCall {
+                name: "weather".into(),
+                argc: 0,
+            },
+        );
+        b.insert(
+            "set_behavior",
+            1,
+            NodeOp::HostCall {
+                name: "set_behavior".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "start_dialogue",
+            2,
+            NodeOp::HostCall {
+                name: "start_dialogue".into(),
+                argc: 2,
+            },
+        );
         b.insert(
             "drop",
             1,
@@ -356,6 +636,30 @@ impl Default for Builtins {
                 argc: 2,
             },
         );
+        b.insert(
+            "can_see",
+            1,
+            NodeOp::HostCall {
+                name: "can_see".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "visible_tiles",
+            1,
+            NodeOp::HostCall {
+                name: "visible_tiles".into(),
+                argc: 1,
+            },
+        );
+        b.insert(
+            "path_to",
+            2,
+            NodeOp::HostCall {
+                name: "path_to".into(),
+                argc: 2,
+            },
+        );
         b.insert(
             "close_in",
             3,
@@ -1,11 +1,98 @@
-use crate::Batch3D;
+use crate::{Batch3D, Material, MaterialModifier, MaterialRole, PixelSource, Texture};
+use rustc_hash::FxHashMap;
+use std::path::Path;
+use theframework::prelude::TheColor;
+use vek::{Vec2, Vec3, Vec4};
 
 #[derive(Clone, Debug)]
 pub struct Wavefront {
     pub vertices: Vec<[f32; 4]>, // 4D vertices for compatibility with Batch
-    pub texture_coords: Vec<[f32; 2]>, // Texture coordinates
-    pub normals: Vec<[f32; 3]>,  // Normals
+    pub texture_coords: Vec<[f32; 2]>, // Texture coordinates, one per vertex (or none)
+    pub normals: Vec<[f32; 3]>,  // Normals, one per vertex (or none)
     pub indices: Vec<(usize, usize, usize)>, // Triangle indices
+
+    /// The objects and groups of the file, the fields above hold all of them merged.
+    pub objects: Vec<WavefrontObject>,
+    /// The materials of the MTL files, see `parse_mtl`.
+    pub materials: Vec<WavefrontMaterial>,
+    /// The MTL files referenced by the file.
+    pub material_libs: Vec<String>,
+}
+
+/// An object (`o`) or group (`g`) of an OBJ file with the material (`usemtl`) of its faces.
+/// Faces of another material start a new object of the same name.
+#[derive(Clone, Debug, Default)]
+pub struct WavefrontObject {
+    pub name: String,
+    pub material: Option<String>,
+    pub vertices: Vec<[f32; 4]>,
+    pub texture_coords: Vec<[f32; 2]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<(usize, usize, usize)>,
+}
+
+/// A material (`newmtl`) of an MTL file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WavefrontMaterial {
+    pub name: String,
+    /// The diffuse color (`Kd`).
+    pub diffuse: [f32; 3],
+    /// The specular color (`Ks`) and exponent (`Ns`).
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    /// The emissive color (`Ke`).
+    pub emissive: [f32; 3],
+    /// The opacity (`d`, or 1 - `Tr`).
+    pub opacity: f32,
+    /// The metallic factor of the PBR extension (`Pm`).
+    pub metallic: f32,
+    /// The path of the diffuse texture (`map_Kd`), relative to the MTL file unless the MTL
+    /// file was loaded by `parse_file`.
+    pub diffuse_texture: Option<String>,
+}
+
+impl Default for WavefrontMaterial {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0, 0.0, 0.0],
+            shininess: 0.0,
+            emissive: [0.0, 0.0, 0.0],
+            opacity: 1.0,
+            metallic: 0.0,
+            diffuse_texture: None,
+        }
+    }
+}
+
+impl WavefrontMaterial {
+    /// The `Material` closest to the material: emissive, transparent, metallic, glossy or
+    /// matte.
+    pub fn to_material(&self) -> Material {
+        let max = |c: [f32; 3]| c[0].max(c[1]).max(c[2]);
+        let (role, value) = if max(self.emissive) > 0.0 {
+            (MaterialRole::Emissive, max(self.emissive))
+        } else if self.opacity < 1.0 {
+            (MaterialRole::Transparent, 1.0 - self.opacity)
+        } else if self.metallic > 0.0 {
+            (MaterialRole::Metallic, self.metallic)
+        } else if max(self.specular) > 0.0 && self.shininess > 0.0 {
+            (
+                MaterialRole::Glossy,
+                (self.shininess / 1000.0).clamp(0.0, 1.0),
+            )
+        } else {
+            (MaterialRole::Matte, 1.0)
+        };
+        Material::new(role, MaterialModifier::None, value, 0.0)
+    }
+
+    /// The diffuse color as a pixel source.
+    pub fn color(&self) -> PixelSource {
+        let [r, g, b] = self.diffuse;
+        PixelSource::Color(TheColor::from(Vec4::new(r, g, b, self.opacity)))
+    }
 }
 
 impl Wavefront {
@@ -21,70 +108,262 @@ impl Wavefront {
             indices,
             normals,
             texture_coords,
+            objects: vec![],
+            materials: vec![],
+            material_libs: vec![],
         }
     }
 
-    /// Parse an OBJ file from a given file path.
+    /// Parse an OBJ file from a given file path, the MTL files it references are loaded
+    /// from its directory and the texture paths are resolved against it.
     pub fn parse_file(file: String) -> Self {
-        let contents = std::fs::read_to_string(file).expect("Failed to read the file");
-        Wavefront::parse_string(contents)
+        let contents = std::fs::read_to_string(&file).expect("Failed to read the file");
+        let mut wavefront = Wavefront::parse_string(contents);
+
+        let dir = Path::new(&file).parent().unwrap_or(Path::new(""));
+        for lib in wavefront.material_libs.clone() {
+            let path = dir.join(&lib);
+            if let Ok(contents) = std::fs::read_to_string(&path) {
+                let first = wavefront.materials.len();
+                wavefront.parse_mtl(&contents);
+                let mtl_dir = path.parent().unwrap_or(dir);
+                for material in &mut wavefront.materials[first..] {
+                    if let Some(texture) = &mut material.diffuse_texture {
+                        *texture = mtl_dir.join(&*texture).to_string_lossy().into_owned();
+                    }
+                }
+            }
+        }
+        wavefront
     }
 
-    /// Parse an OBJ file from a given string.
+    /// Parse an OBJ file from a given string. Faces with more than three vertices are
+    /// triangulated and negative (relative) indices are supported. The MTL files are not
+    /// loaded, see `parse_mtl`.
     pub fn parse_string(contents: String) -> Self {
-        let lines = contents.lines();
-        let mut vertices = Vec::new();
-        let mut normals = Vec::new();
-        let mut texture_coords = Vec::new();
-        let mut indices = Vec::new();
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut texture_coords: Vec<[f32; 2]> = Vec::new();
+
+        let mut material_libs = Vec::new();
+        let mut objects: Vec<WavefrontObject> = Vec::new();
+        let mut current = WavefrontObject::default();
+        // The vertex of the current object for each position / uv / normal combination
+        let mut lookup: FxHashMap<(usize, Option<usize>, Option<usize>), usize> =
+            FxHashMap::default();
 
-        for line in lines {
+        for line in contents.lines() {
             let trimmed = line.trim();
 
             if trimmed.starts_with('#') || trimmed.is_empty() {
                 continue; // Skip comments and empty lines
             }
 
-            if trimmed.starts_with("v ") {
-                let mut items = trimmed.split_ascii_whitespace();
-                items.next().unwrap(); // Skip "v"
-                let x: f32 = items.next().unwrap().parse().unwrap();
-                let y: f32 = items.next().unwrap().parse().unwrap();
-                let z: f32 = items.next().unwrap().parse().unwrap();
-                // Convert to `[f32; 4]` by appending 1.0 as the w component
-                vertices.push([x, y, z, 1.0]);
-            } else if trimmed.starts_with("vn ") {
-                let mut items = trimmed.split_ascii_whitespace();
-                items.next().unwrap(); // Skip "vn"
-                let x: f32 = items.next().unwrap().parse().unwrap();
-                let y: f32 = items.next().unwrap().parse().unwrap();
-                let z: f32 = items.next().unwrap().parse().unwrap();
-                normals.push([x, y, z]);
-            } else if trimmed.starts_with("vt ") {
-                let mut items = trimmed.split_ascii_whitespace();
-                items.next().unwrap(); // Skip "vt"
-                let u: f32 = items.next().unwrap().parse().unwrap();
-                let v: f32 = items.next().unwrap().parse().unwrap();
-                texture_coords.push([u, v]);
-            } else if trimmed.starts_with("f ") {
-                let mut items = trimmed.split_ascii_whitespace();
-                items.next().unwrap(); // Skip "f"
-
-                // Parse three vertices for a triangle
-                let parse_face = |face_str: &str| -> usize {
-                    let mut parts = face_str.split('/');
-                    parts.next().unwrap().parse::<usize>().unwrap() - 1
-                };
-
-                let v0 = parse_face(items.next().unwrap());
-                let v1 = parse_face(items.next().unwrap());
-                let v2 = parse_face(items.next().unwrap());
-
-                indices.push((v0, v1, v2));
+            let mut items = trimmed.split_ascii_whitespace();
+            let keyword = items.next().unwrap_or_default();
+            let floats = || -> Vec<f32> {
+                items
+                    .clone()
+                    .map_while(|item| item.parse::<f32>().ok())
+                    .collect()
+            };
+
+            match keyword {
+                "v" => {
+                    if let [x, y, z, ..] = floats()[..] {
+                        positions.push([x, y, z]);
+                    }
+                }
+                "vn" => {
+                    if let [x, y, z, ..] = floats()[..] {
+                        normals.push([x, y, z]);
+                    }
+                }
+                "vt" => {
+                    if let [u, v, ..] = floats()[..] {
+                        texture_coords.push([u, v]);
+                    }
+                }
+                "o" | "g" | "usemtl" => {
+                    let name = trimmed[keyword.len()..].trim().to_string();
+                    let mut next = WavefrontObject {
+                        name: current.name.clone(),
+                        material: current.material.clone(),
+                        ..Default::default()
+                    };
+                    if keyword == "usemtl" {
+                        next.material = Some(name);
+                    } else {
+                        next.name = name;
+                    }
+                    let finished = std::mem::replace(&mut current, next);
+                    if !finished.indices.is_empty() {
+                        objects.push(finished);
+                    }
+                    lookup.clear();
+                }
+                "mtllib" => {
+                    material_libs.push(trimmed[keyword.len()..].trim().to_string());
+                }
+                "f" => {
+                    let mut face = Vec::new();
+                    for item in items {
+                        let mut parts = item.split('/');
+                        let Some(position) = parts
+                            .next()
+                            .and_then(|index| resolve_index(index, positions.len()))
+                        else {
+                            face.clear();
+                            break;
+                        };
+                        let uv = parts
+                            .next()
+                            .and_then(|index| resolve_index(index, texture_coords.len()));
+                        let normal = parts
+                            .next()
+                            .and_then(|index| resolve_index(index, normals.len()));
+
+                        let vertex = *lookup.entry((position, uv, normal)).or_insert_with(|| {
+                            let [x, y, z] = positions[position];
+                            current.vertices.push([x, y, z, 1.0]);
+                            current
+                                .texture_coords
+                                .push(uv.map_or([x, y], |uv| texture_coords[uv]));
+                            current
+                                .normals
+                                .push(normal.map_or([0.0, 0.0, 0.0], |n| normals[n]));
+                            current.vertices.len() - 1
+                        });
+                        face.push(vertex);
+                    }
+                    current
+                        .indices
+                        .extend(triangulate(&face, &current.vertices));
+                }
+                _ => {}
             }
         }
+        if !current.indices.is_empty() {
+            objects.push(current);
+        }
 
-        Wavefront::new(vertices, indices, normals, texture_coords)
+        // Without normals in the file the normals are left empty
+        let has_normals = !normals.is_empty();
+        let mut wavefront = Wavefront::new(vec![], vec![], vec![], vec![]);
+        for object in &mut objects {
+            if !has_normals {
+                object.normals.clear();
+            }
+            let base = wavefront.vertices.len();
+            wavefront.vertices.extend_from_slice(&object.vertices);
+            wavefront
+                .texture_coords
+                .extend_from_slice(&object.texture_coords);
+            wavefront.normals.extend_from_slice(&object.normals);
+            wavefront.indices.extend(
+                object
+                    .indices
+                    .iter()
+                    .map(|(a, b, c)| (a + base, b + base, c + base)),
+            );
+        }
+        wavefront.objects = objects;
+        wavefront.material_libs = material_libs;
+        wavefront
+    }
+
+    /// Parse the materials of an MTL file and add them to the materials.
+    pub fn parse_mtl(&mut self, contents: &str) {
+        let mut current: Option<WavefrontMaterial> = None;
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('#') || trimmed.is_empty() {
+                continue;
+            }
+            let mut items = trimmed.split_ascii_whitespace();
+            let keyword = items.next().unwrap_or_default();
+            let rest = trimmed[keyword.len()..].trim();
+            let floats: Vec<f32> = items.map_while(|item| item.parse().ok()).collect();
+            let color = || match floats[..] {
+                [r, g, b, ..] => Some([r, g, b]),
+                [v] => Some([v, v, v]),
+                _ => None,
+            };
+
+            if keyword == "newmtl" {
+                if let Some(material) = current.take() {
+                    self.materials.push(material);
+                }
+                current = Some(WavefrontMaterial {
+                    name: rest.to_string(),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let Some(material) = &mut current else {
+                continue;
+            };
+            match keyword {
+                "Kd" => material.diffuse = color().unwrap_or(material.diffuse),
+                "Ks" => material.specular = color().unwrap_or(material.specular),
+                "Ke" => material.emissive = color().unwrap_or(material.emissive),
+                "Ns" => material.shininess = floats.first().copied().unwrap_or(0.0),
+                "d" => material.opacity = floats.first().copied().unwrap_or(1.0),
+                "Tr" => material.opacity = 1.0 - floats.first().copied().unwrap_or(0.0),
+                "Pm" => material.metallic = floats.first().copied().unwrap_or(0.0),
+                // Options like `-s 1 1 1` precede the file name
+                "map_Kd" => {
+                    material.diffuse_texture = rest.split_ascii_whitespace().last().map(Into::into)
+                }
+                _ => {}
+            }
+        }
+        if let Some(material) = current {
+            self.materials.push(material);
+        }
+    }
+
+    /// The material of the object, if it is defined in the loaded MTL files.
+    pub fn material(&self, object: &WavefrontObject) -> Option<&WavefrontMaterial> {
+        let name = object.material.as_ref()?;
+        self.materials
+            .iter()
+            .find(|material| &material.name == name)
+    }
+
+    /// The diffuse textures of the materials, the texture index of a material is the index of
+    /// its texture in this list.
+    pub fn texture_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        for path in self
+            .materials
+            .iter()
+            .filter_map(|m| m.diffuse_texture.as_ref())
+        {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        paths
+    }
+
+    /// Load the diffuse textures of the materials in the order of `texture_paths`, textures
+    /// which cannot be loaded are replaced by their diffuse color.
+    pub fn load_textures(&self) -> Vec<Texture> {
+        self.texture_paths()
+            .iter()
+            .map(|path| {
+                Texture::from_image_safe(Path::new(path)).unwrap_or_else(|| {
+                    let color = self
+                        .materials
+                        .iter()
+                        .find(|m| m.diffuse_texture.as_ref() == Some(path))
+                        .map_or([0.8, 0.8, 0.8], |m| m.diffuse);
+                    let pixel = crate::vec4_to_pixel(&Vec4::new(color[0], color[1], color[2], 1.0));
+                    Texture::new(pixel.to_vec(), 1, 1)
+                })
+            })
+            .collect()
     }
 
     /// Convert the Wavefront object into a Batch for rendering.
@@ -99,4 +378,133 @@ impl Wavefront {
 
         Batch3D::new(self.vertices, self.indices, uvs)
     }
+
+    /// Convert every object into its own Batch with the material of the object. Textured
+    /// materials use the static texture at `texture_offset` plus their texture index (see
+    /// `texture_paths`), the others their diffuse color.
+    pub fn to_batches(&self, texture_offset: u16) -> Vec<Batch3D> {
+        let textures = self.texture_paths();
+        self.objects
+            .iter()
+            .map(|object| {
+                let mut batch = Batch3D::new(
+                    object.vertices.clone(),
+                    object.indices.clone(),
+                    object.texture_coords.clone(),
+                );
+                if !object.normals.is_empty() {
+                    batch.normals = object.normals.iter().map(|n| Vec3::from(*n)).collect();
+                }
+                if let Some(material) = self.material(object) {
+                    let texture = material
+                        .diffuse_texture
+                        .as_ref()
+                        .and_then(|path| textures.iter().position(|p| p == path));
+                    batch = match texture {
+                        Some(index) => batch
+                            .source(PixelSource::StaticTileIndex(texture_offset + index as u16)),
+                        None => batch.source(material.color()),
+                    }
+                    .material(material.to_material());
+                }
+                batch
+            })
+            .collect()
+    }
+}
+
+/// Resolve a 1-based OBJ index, negative indices count back from the last element.
+fn resolve_index(index: &str, count: usize) -> Option<usize> {
+    let index: i64 = index.parse().ok()?;
+    let resolved = if index > 0 {
+        index as usize - 1
+    } else {
+        count.checked_sub(index.unsigned_abs() as usize)?
+    };
+    (resolved < count).then_some(resolved)
+}
+
+/// Triangulate a face: triangles and convex quads as a fan, larger or concave polygons with
+/// earcut on the plane of the face.
+fn triangulate(face: &[usize], vertices: &[[f32; 4]]) -> Vec<(usize, usize, usize)> {
+    let fan = || (1..face.len().saturating_sub(1)).map(|i| (face[0], face[i], face[i + 1]));
+    if face.len() <= 3 {
+        return fan().collect();
+    }
+
+    let points: Vec<Vec3<f32>> = face
+        .iter()
+        .map(|i| Vec3::new(vertices[*i][0], vertices[*i][1], vertices[*i][2]))
+        .collect();
+    // Newell's method for the normal of the polygon
+    let mut normal = Vec3::zero();
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        normal += Vec3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        );
+    }
+    // Project onto the axis plane the polygon faces the most
+    let n = normal.map(f32::abs);
+    let project = |p: &Vec3<f32>| {
+        if n.x >= n.y && n.x >= n.z {
+            Vec2::new(p.y, p.z)
+        } else if n.y >= n.z {
+            Vec2::new(p.z, p.x)
+        } else {
+            Vec2::new(p.x, p.y)
+        }
+    };
+    let flat: Vec<f32> = points
+        .iter()
+        .flat_map(|p| {
+            let p = project(p);
+            [p.x, p.y]
+        })
+        .collect();
+
+    match earcutr::earcut(&flat, &[], 2) {
+        Ok(indices) if indices.len() == (face.len() - 2) * 3 => indices
+            .chunks_exact(3)
+            .map(|t| (face[t[0]], face[t[1]], face[t[2]]))
+            .collect(),
+        _ => fan().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_objects_materials_and_polygons() {
+        let obj = "mtllib scene.mtl\n\
+                   v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                   vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                   o quad\nusemtl red\nf 1/1 2/2 3/3 4/4\n\
+                   o tri\nusemtl wood\nf -4/1 -3/2 -2/3\n";
+        let mut wavefront = Wavefront::parse_string(obj.to_string());
+        wavefront.parse_mtl(
+            "newmtl red\nKd 1 0 0\nd 0.5\n\nnewmtl wood\nKd 0.5 0.4 0.3\nmap_Kd -s 2 2 2 wood.png\n",
+        );
+
+        assert_eq!(wavefront.material_libs, vec!["scene.mtl".to_string()]);
+        assert_eq!(wavefront.objects.len(), 2);
+        assert_eq!(wavefront.objects[0].name, "quad");
+        assert_eq!(wavefront.objects[0].indices.len(), 2);
+        assert_eq!(wavefront.objects[1].indices, vec![(0, 1, 2)]);
+        assert_eq!(wavefront.indices.len(), 3);
+        assert_eq!(wavefront.vertices.len(), wavefront.texture_coords.len());
+
+        let red = wavefront.material(&wavefront.objects[0]).unwrap();
+        assert_eq!(red.diffuse, [1.0, 0.0, 0.0]);
+        assert_eq!(red.to_material().role, MaterialRole::Transparent);
+        assert_eq!(wavefront.texture_paths(), vec!["wood.png".to_string()]);
+
+        let batches = wavefront.to_batches(5);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].source, PixelSource::StaticTileIndex(5));
+    }
 }